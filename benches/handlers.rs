@@ -0,0 +1,87 @@
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion};
+use cubist_wallet_provisioner::kv;
+use cubist_wallet_provisioner::testing::{
+    admin, admins, chain, provision_request_at, pubkey, set_chain_request, wallet, MockKeyCreator, MockKvStore,
+};
+use cubist_wallet_provisioner::{ChainId, ProvisionBatchRequest, Provisioner};
+
+/// Chains per request, up to the default `max_chains` quota
+const CHAIN_COUNTS: [usize; 3] = [1, 10, 100];
+
+/// Users per batch, up to `MAX_BATCH_SIZE`
+const BATCH_SIZES: [usize; 3] = [1, 10, 100];
+
+/// What the provisioners' clock reads, so a request signed once outlives a
+/// long benchmark
+const NOW: u64 = 1_700_000_000;
+
+fn chains(count: usize) -> Vec<u64> {
+    (1..=count as u64).collect()
+}
+
+/// Provisioner over an empty mock store, with every benchmarked chain registered
+fn provisioner() -> Provisioner<MockKvStore, MockKeyCreator> {
+    let provisioner = Provisioner::new(MockKvStore::new(), MockKeyCreator::new()).with_admins(admins()).with_clock(|| NOW);
+    for evm_chain_id in chains(CHAIN_COUNTS[CHAIN_COUNTS.len() - 1]) {
+        let name = format!("Chain {}", evm_chain_id);
+        provisioner.handle_set_chain(&admin(), set_chain_request(&chain(evm_chain_id), true, Some(&name))).unwrap();
+    }
+    provisioner
+}
+
+/// First provision of a user: the store flow with key creation, on a fresh
+/// store each iteration
+fn store(c: &mut Criterion) {
+    let mut group = c.benchmark_group("store");
+    for count in CHAIN_COUNTS {
+        let req = provision_request_at(&wallet(1), chains(count), NOW);
+        group.bench_with_input(BenchmarkId::from_parameter(count), &req, |b, req| {
+            b.iter_batched(provisioner, |provisioner| provisioner.handle(req.clone()).unwrap(), BatchSize::SmallInput)
+        });
+    }
+    group.finish();
+}
+
+/// Batch provisioning of distinct users on 10 chains each
+fn batch(c: &mut Criterion) {
+    let mut group = c.benchmark_group("store_batch");
+    for size in BATCH_SIZES {
+        let req = ProvisionBatchRequest {
+            requests: (1..=size as u8).map(|user| provision_request_at(&wallet(user), chains(10), NOW)).collect(),
+            request_id: None,
+        };
+        group.bench_with_input(BenchmarkId::from_parameter(size), &req, |b, req| {
+            b.iter_batched(provisioner, |provisioner| provisioner.handle_batch(req.clone()).unwrap(), BatchSize::SmallInput)
+        });
+    }
+    group.finish();
+}
+
+/// `Get` of every chain of a provisioned user, reading the chain index or,
+/// with the index emptied as for users stored before it existed, probing
+/// each requested chain
+fn get(c: &mut Criterion) {
+    let mut group = c.benchmark_group("get");
+    for count in CHAIN_COUNTS {
+        let alice = wallet(1);
+        let solana_pubkey = pubkey(&alice);
+        let chain_ids: Vec<ChainId> = chains(count).into_iter().map(chain).collect();
+        let indexed = provisioner();
+        indexed.handle(provision_request_at(&alice, chains(count), NOW)).unwrap();
+        let probed = provisioner();
+        probed.handle(provision_request_at(&alice, chains(count), NOW)).unwrap();
+        for chain_id in &chain_ids {
+            kv::remove_from_chain_index(probed.kv(), &solana_pubkey, chain_id).unwrap();
+        }
+
+        for (approach, provisioner) in [("index", &indexed), ("probing", &probed)] {
+            group.bench_with_input(BenchmarkId::new(approach, count), &chain_ids, |b, chain_ids| {
+                b.iter(|| provisioner.handle_get(&solana_pubkey, chain_ids).unwrap())
+            });
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, store, batch, get);
+criterion_main!(benches);
@@ -0,0 +1,26 @@
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+
+    // Commit reported by `health::version`
+    println!("cargo:rerun-if-env-changed=CUBIST_GIT_SHA");
+    if std::env::var_os("CUBIST_GIT_SHA").is_none() {
+        if std::path::Path::new(".git/HEAD").exists() {
+            println!("cargo:rerun-if-changed=.git/HEAD");
+        }
+        let sha = std::process::Command::new("git")
+            .args(["rev-parse", "HEAD"])
+            .output()
+            .ok()
+            .filter(|output| output.status.success())
+            .and_then(|output| String::from_utf8(output.stdout).ok());
+        if let Some(sha) = sha {
+            println!("cargo:rustc-env=CUBIST_GIT_SHA={}", sha.trim());
+        }
+    }
+
+    #[cfg(feature = "grpc")]
+    {
+        println!("cargo:rerun-if-changed=proto/provisioner.proto");
+        tonic_prost_build::compile_protos("proto/provisioner.proto").expect("failed to compile proto/provisioner.proto");
+    }
+}
@@ -0,0 +1,178 @@
+//! Sequences of requests through `server::route` over the mock backend: the
+//! provisioning handlers the policy dispatches to, behind a router that needs
+//! no C2F runtime. Bodies are JSON built from the requests' own field names
+//! and from well-formed addresses and chain ids, so most reach a handler
+//! rather than stopping at the parser. No request may panic, and every
+//! failure is a structured error (`{"code","message","retryable"}`).
+
+#![no_main]
+
+use arbitrary::Arbitrary;
+use cubist_wallet_provisioner::server;
+use cubist_wallet_provisioner::testing::{provision_request, pubkey, wallet, TestContext};
+use libfuzzer_sys::fuzz_target;
+use serde_json::{Map, Number, Value};
+
+/// Most requests per input
+const MAX_CALLS: usize = 16;
+
+/// Deepest JSON nesting generated
+const MAX_DEPTH: usize = 4;
+
+#[derive(Arbitrary, Debug)]
+enum Call {
+    /// A provision the harness signs, so the fuzzer gets past the ownership
+    /// check and the other requests find users to work on
+    SignedProvision { user: u8, chains: Vec<u64> },
+    Request { method: Method, route: Route, body: Json },
+}
+
+#[derive(Arbitrary, Debug)]
+enum Method {
+    Get,
+    Post,
+    Other(String),
+}
+
+#[derive(Arbitrary, Debug)]
+enum Route {
+    Provision,
+    Update,
+    Attest,
+    Certificate,
+    Mappings { user: Text, query: String },
+    Other(String),
+}
+
+/// A JSON value, with object keys biased toward the requests' field names
+#[derive(Arbitrary, Debug)]
+enum Json {
+    Null,
+    Bool(bool),
+    Number(u64),
+    Text(Text),
+    Array(Vec<Json>),
+    Object(Vec<(Field, Json)>),
+}
+
+#[derive(Arbitrary, Debug)]
+enum Field {
+    SolanaPubkey,
+    ChainId,
+    ChainIds,
+    Message,
+    Signature,
+    Label,
+    KeyType,
+    KeyClass,
+    TtlSecs,
+    Ttl,
+    IdempotencyKey,
+    ExpectedVersion,
+    Actor,
+    RequestId,
+    Other(String),
+}
+
+/// A string, likely one that parses as what the field wants
+#[derive(Arbitrary, Debug)]
+enum Text {
+    User(u8),
+    EvmAddress(u8),
+    Chain(u64),
+    Raw(String),
+}
+
+impl Method {
+    fn as_str(&self) -> &str {
+        match self {
+            Method::Get => "GET",
+            Method::Post => "POST",
+            Method::Other(method) => method,
+        }
+    }
+}
+
+impl Route {
+    fn target(&self) -> String {
+        match self {
+            Route::Provision => "/provision".to_string(),
+            Route::Update => "/update".to_string(),
+            Route::Attest => "/attest".to_string(),
+            Route::Certificate => "/certificate".to_string(),
+            Route::Mappings { user, query } => format!("/mappings/{}?{}", user.to_string(), query),
+            Route::Other(target) => target.clone(),
+        }
+    }
+}
+
+impl Json {
+    fn to_value(&self, depth: usize) -> Value {
+        if depth > MAX_DEPTH {
+            return Value::Null;
+        }
+        match self {
+            Json::Null => Value::Null,
+            Json::Bool(b) => Value::Bool(*b),
+            Json::Number(n) => Value::Number(Number::from(*n)),
+            Json::Text(text) => Value::String(text.to_string()),
+            Json::Array(items) => Value::Array(items.iter().map(|item| item.to_value(depth + 1)).collect()),
+            Json::Object(fields) => Value::Object(
+                fields.iter().map(|(field, value)| (field.name(), value.to_value(depth + 1))).collect::<Map<_, _>>(),
+            ),
+        }
+    }
+}
+
+impl Field {
+    fn name(&self) -> String {
+        match self {
+            Field::SolanaPubkey => "solana_pubkey",
+            Field::ChainId => "chain_id",
+            Field::ChainIds => "chain_ids",
+            Field::Message => "message",
+            Field::Signature => "signature",
+            Field::Label => "label",
+            Field::KeyType => "key_type",
+            Field::KeyClass => "key_class",
+            Field::TtlSecs => "ttl_secs",
+            Field::Ttl => "ttl",
+            Field::IdempotencyKey => "idempotency_key",
+            Field::ExpectedVersion => "expected_version",
+            Field::Actor => "actor",
+            Field::RequestId => "request_id",
+            Field::Other(name) => name,
+        }
+        .to_string()
+    }
+}
+
+impl Text {
+    fn to_string(&self) -> String {
+        match self {
+            Text::User(user) => pubkey(&wallet(*user)).to_string(),
+            Text::EvmAddress(n) => format!("0x{:040x}", n),
+            Text::Chain(n) => format!("eip155:{}", n),
+            Text::Raw(raw) => raw.clone(),
+        }
+    }
+}
+
+fuzz_target!(|calls: Vec<Call>| {
+    let ctx = TestContext::new();
+    for call in calls.iter().take(MAX_CALLS) {
+        let response = match call {
+            Call::SignedProvision { user, chains } => {
+                let body = serde_json::to_string(&provision_request(&wallet(*user), chains.clone())).unwrap();
+                server::route(&ctx.provisioner, "POST", "/provision", &body)
+            }
+            Call::Request { method, route, body } => {
+                server::route(&ctx.provisioner, method.as_str(), &route.target(), &body.to_value(0).to_string())
+            }
+        };
+        let body: Value = serde_json::from_str(&response.body).expect("responses are JSON");
+        if response.status != 200 {
+            assert!(body["code"].is_string() && body["message"].is_string() && body["retryable"].is_boolean(), "{}", response.body);
+        }
+    }
+});
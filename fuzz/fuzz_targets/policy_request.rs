@@ -0,0 +1,33 @@
+//! Bodies the C2F policy is invoked with, through everything the policy does
+//! with a body before dispatching it: the request HMAC check, then
+//! `PolicyRequest::parse`. None of it may panic (a panicking policy denies
+//! every operation), and a body that does not parse is `INVALID_REQUEST`.
+
+#![no_main]
+
+use cubist_wallet_provisioner::policy_api::PolicyRequest;
+use cubist_wallet_provisioner::{authz, request_auth};
+use libfuzzer_sys::fuzz_target;
+
+const SECRET: &str = "fuzz-request-auth-secret-32-bytes!";
+
+fuzz_target!(|data: &[u8]| {
+    // The policy is handed the body as a string
+    let body = String::from_utf8_lossy(data);
+    if let Err(e) = request_auth::verify_request(&body, SECRET) {
+        assert_eq!(e.code(), "REQUEST_AUTH_FAILED");
+    }
+
+    match PolicyRequest::parse(Some(&body)) {
+        Ok(req) => {
+            // Every action the parser accepts is one authorization knows
+            assert!(authz::MATRIX.iter().any(|(action, _)| *action == req.action()), "{} is not in the matrix", req.action());
+            let _ = req.solana_pubkey();
+        }
+        Err(e) => {
+            assert_eq!(e.code(), "INVALID_REQUEST");
+            let error = serde_json::to_value(&e).unwrap();
+            assert!(error["message"].is_string());
+        }
+    }
+});
@@ -0,0 +1,26 @@
+#!/bin/sh
+# Fail when the release policy WASM grows more than SIZE_SLACK_PERCENT
+# (default 2) past the size recorded in SIZE_FILE (default wasm-size.txt, the
+# default build). With --update, or when nothing is recorded yet, record the
+# current size instead. Extra cargo flags (features) go in CARGO_FLAGS, with
+# their own SIZE_FILE.
+set -eu
+cd "$(dirname "$0")"
+
+cargo build --release --target wasm32-wasip2 ${CARGO_FLAGS:-}
+size=$(wc -c < target/wasm32-wasip2/release/skate_provisioner.wasm)
+size_file=${SIZE_FILE:-wasm-size.txt}
+
+if [ "${1:-}" = "--update" ] || [ ! -f "$size_file" ]; then
+    echo "$size" > "$size_file"
+    echo "recorded $size bytes in $size_file"
+    exit 0
+fi
+
+recorded=$(cat "$size_file")
+limit=$((recorded + recorded * ${SIZE_SLACK_PERCENT:-2} / 100))
+echo "policy WASM: $size bytes (recorded $recorded, limit $limit)"
+if [ "$size" -gt "$limit" ]; then
+    echo "the policy WASM grew past its limit; shrink it, or run $0 --update if the growth is intended" >&2
+    exit 1
+fi
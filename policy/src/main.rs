@@ -18,153 +18,717 @@
 //!    │◄── 4. success ──────────────────┤
 //! ```
 //!
+//! Validation, key format and the store/get/update flows come from the
+//! `cubist-wallet-provisioner` library (`mapping`, `kv`, `approval`, …); this
+//! crate adapts the SDK bucket to its `KvStore` trait and dispatches actions.
+//!
 //! ## Build
 //! ```bash
 //! cd policy && cargo build --release
 //! cs policy update --name "skate_wallet_provisioner" \
 //!   target/wasm32-wasip2/release/skate_provisioner.wasm
 //! ```
+//!
+//! With `--features signing-gate` the same crate builds the signing gate
+//! instead: a policy for signing requests that allows a signature only when
+//! the key's EVM address is mapped to the user the requester signs for
+//! (`signing_gate`).
+//!
+//! With `--features read-only` it builds a policy serving only `get`, `list`
+//! and `reverse_get`, which cannot write to any bucket: one to attach to
+//! broadly-accessible roles, next to the full policy on restricted ones.
+
+#![cfg_attr(any(feature = "signing-gate", feature = "read-only"), allow(dead_code, unused_imports))]
+
+#[cfg(all(feature = "signing-gate", feature = "read-only"))]
+compile_error!("the signing gate and the read-only policy are separate builds");
 
 use cubist_policy_sdk::{
     error::Result,
     keyvalue::{self, IfExists, Value, OperationError},
     policy,
+    secrets,
     AccessDecision,
     AccessRequest,
 };
+use cubist_wallet_provisioner::{
+    address_sanity,
+    admin::{self, Requester, ADMINS_BUCKET},
+    anonymize,
+    approval::{self, PendingStatus, PendingUpdate},
+    audit::{self, AuditEvent, AuditQuery},
+    auth,
+    authz::{self, Role},
+    blocklist::{self, BlockEntry, BlockTarget, BLOCKLIST_BUCKET},
+    chains::{self, ChainInfo},
+    config::{self, Config, ConfigUpdate, CONFIG_BUCKET},
+    destinations::{self, AllowedDestinations},
+    dry_run::{self, DryRunResponse},
+    environment::{self, EnvPrefixed, Environment},
+    error::{ProvisionError, Result as ProvisionResult},
+    events,
+    evm_to_solana::{self, SolanaMappingValue, EVM_TO_SOLANA_BUCKET},
+    expiry,
+    export,
+    freeze::{self, FreezeEntry},
+    health,
+    idempotency::{self, IDEMPOTENCY_BUCKET},
+    import::{self, ImportRequest},
+    jobs,
+    kv::{self, BUCKET_NAME},
+    labels,
+    logging::{self, Logger, StderrLogger},
+    mapping,
+    merkle,
+    metrics::{self, METRICS_BUCKET},
+    migrate,
+    policy_api::{PolicyRequest, StoreBatchEntry, UpdateBatchEntry},
+    quota,
+    rate_limit::{self, RATE_LIMIT_BUCKET},
+    read_cache::{Cached, ReadCache},
+    reconcile::{self, ReconcileRequest},
+    repair::{self, RepairRequest},
+    request_auth,
+    response_signing::{self, ResponseSignature},
+    retirement::{self, RetirementRecord},
+    shadow::{self, ShadowWrites, Shadowed},
+    signing_gate::{self, SigningRequest},
+    spend_limits::{self, SpendLimit},
+    network::{self, Network, Networked},
+    privacy::{self, HashedKeys, Pepper},
+    tenant::{self, Namespaced, TenantId},
+    usage::{self, USAGE_BUCKET},
+    verify,
+    ChainId, EvmAddress, EvmToSolanaProvisionRequest, EvmToSolanaProvisionResponse, KvStore, LinkExternalRequest,
+    LinkExternalResponse, ListedKey, MappingRecord,
+    ProvisionBatchResponse, ProvisionRequest, ProvisionResponse, SolanaPubkey, UpdateBatchResponse,
+};
+#[cfg(feature = "encryption")]
+use cubist_wallet_provisioner::encryption::{self, DataKey, Encrypted, SecretWrapper};
+#[cfg(feature = "read-only")]
+use cubist_wallet_provisioner::kv::ReadOnly;
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use serde_json::value::RawValue;
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::time::Instant;
+
+/// Version of the response envelope (`Envelope::envelope`)
+const ENVELOPE_VERSION: u32 = 1;
 
-/// Bucket name for Solana to EVM mappings
-const BUCKET_NAME: &str = "solana_to_evm";
+/// Action logged for requests that could not be read
+const INVALID_ACTION: &str = "invalid";
+
+/// Environment whose keys this build reads and writes (see `environment`),
+/// from `CUBIST_ENVIRONMENT` at build time; unset keeps the unprefixed layout
+const ENVIRONMENT: Option<Environment> = Environment::from_build(option_env!("CUBIST_ENVIRONMENT"));
+
+/// Policy secret holding the org's key for signing enveloped replies (see
+/// `response_signing`); unset leaves replies unsigned
+const RESPONSE_SIGNING_KEY_SECRET: &str = "response_signing_key";
+
+/// Policy secret holding the key-encryption key of the data key (see
+/// `unwrap_data_key`)
+#[cfg(feature = "encryption")]
+const DATA_KEY_KEK_SECRET: &str = "data_key_kek";
+
+/// Policy secret holding the data key as `encryption::wrap_data_key` wraps it
+#[cfg(feature = "encryption")]
+const WRAPPED_DATA_KEY_SECRET: &str = "wrapped_data_key";
+
+/// Actions a `read-only` build serves
+const READ_ONLY_ACTIONS: [&str; 3] = ["get", "list", "reverse_get"];
+
+/// Every bucket the policy uses, all kept under `ENVIRONMENT`'s prefix
+const BUCKETS: [&str; 9] = [
+    BUCKET_NAME,
+    ADMINS_BUCKET,
+    BLOCKLIST_BUCKET,
+    CONFIG_BUCKET,
+    EVM_TO_SOLANA_BUCKET,
+    IDEMPOTENCY_BUCKET,
+    RATE_LIMIT_BUCKET,
+    METRICS_BUCKET,
+    USAGE_BUCKET,
+];
 
 // =============================================================================
 // REQUEST/RESPONSE TYPES
 // =============================================================================
 
+/// Fields any request may carry next to `action`
 #[derive(Deserialize)]
-#[serde(tag = "action")]
-enum PolicyRequest {
-    /// Store mappings for a Solana address (called after backend creates key)
-    #[serde(rename = "store")]
-    Store {
-        solana_pubkey: String,
-        chain_ids: Vec<u64>,
-        evm_address: String,
-    },
-    
-    /// Get existing mappings for a Solana address
-    #[serde(rename = "get")]
-    Get {
-        solana_pubkey: String,
-        chain_ids: Vec<u64>,
-    },
-    
-    /// Update mapping for a specific chain (admin only, after backend creates new key)
-    #[serde(rename = "update")]
-    Update {
-        solana_pubkey: String,
-        chain_id: u64,
-        new_evm_address: String,
-    },
+struct RequestOptions {
+    /// Reply in the envelope (`Envelope`) rather than the flat
+    /// `success`/`error` body; the default
+    #[serde(default = "enabled")]
+    envelope: bool,
+    /// Answer success with `Allow` and no payload, for callers that only need
+    /// the outcome. Off by default: the SDK's `Allow` carries no data, so
+    /// reads would come back empty (see `main`)
+    #[serde(default)]
+    allow_on_success: bool,
+    /// Compatibility with callers from before the envelope: every reply is a
+    /// `Deny` carrying the flat body, whatever the other two options say
+    #[serde(default)]
+    legacy_response: bool,
+    /// The caller's correlation id, logged with the request (see `logging`)
+    #[serde(default)]
+    request_id: Option<String>,
+}
+
+impl Default for RequestOptions {
+    fn default() -> Self {
+        Self { envelope: true, allow_on_success: false, legacy_response: false, request_id: None }
+    }
+}
+
+impl RequestOptions {
+    /// Whether the reply is enveloped
+    fn envelope(&self) -> bool {
+        self.envelope && !self.legacy_response
+    }
+
+    /// Whether a success is answered with a bare `Allow`
+    fn allow_on_success(&self) -> bool {
+        self.allow_on_success && !self.legacy_response
+    }
+}
+
+fn enabled() -> bool {
+    true
 }
 
+/// Where a request's data lives (see `enter_tenant`). Read apart from
+/// `RequestOptions`, so a malformed option never drops the tenant.
+#[derive(Deserialize, Default)]
+struct RequestScope {
+    #[serde(default)]
+    tenant: Option<Box<RawValue>>,
+    #[serde(default)]
+    network: Option<Box<RawValue>>,
+}
+
+/// Enveloped response: whether the request succeeded, apart from its payload
 #[derive(Serialize)]
-struct StoreResponse {
-    success: bool,
-    evm_address: String,
-    chain_mappings: HashMap<u64, String>,
+struct Envelope {
+    /// Envelope format version
+    envelope: u32,
+    /// `"success"` or `"error"`
+    outcome: &'static str,
+    /// The action's result, on success
+    #[serde(skip_serializing_if = "Option::is_none")]
+    payload: Option<Box<RawValue>>,
+    /// `code`, `message`, `retryable` (and `current`), on error
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<ProvisionError>,
+    /// Over the rest of the envelope, for builds with a response signing key
+    #[serde(skip_serializing_if = "Option::is_none")]
+    signature: Option<ResponseSignature>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct UpdateResponse {
+    new_evm_address: EvmAddress,
+    new_key_id: Option<String>,
+    chain_id: ChainId,
+    /// Revision of the chain mapping after the update
+    version: u64,
 }
 
 #[derive(Serialize)]
-struct GetResponse {
-    success: bool,
-    default_address: Option<String>,
-    chain_mappings: HashMap<u64, String>,
+struct EvmToSolanaResponse {
+    evm_address: EvmAddress,
+    solana_pubkey: Option<SolanaPubkey>,
+    key_id: Option<String>,
 }
 
 #[derive(Serialize)]
-struct UpdateResponse {
-    success: bool,
-    new_evm_address: String,
-    chain_id: u64,
+struct ReverseGetResponse {
+    evm_address: EvmAddress,
+    solana_pubkey: Option<SolanaPubkey>,
+}
+
+#[derive(Serialize)]
+struct RetirementResponse {
+    evm_address: EvmAddress,
+    retirement: Option<RetirementRecord>,
+}
+
+#[derive(Serialize)]
+struct TenantMembersResponse {
+    tenant_id: TenantId,
+    members: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct SignerResponse {
+    identity: String,
+    solana_pubkey: Option<SolanaPubkey>,
+}
+
+#[derive(Serialize)]
+struct PendingResponse {
+    pending: Option<PendingUpdate>,
+}
+
+/// Outcome of an `update_batch` entry: the proposal it made, or the update
+/// its approval applied
+#[derive(Serialize)]
+#[serde(untagged)]
+enum UpdateBatchResult {
+    Proposed(PendingResponse),
+    Applied(UpdateResponse),
+}
+
+#[derive(Serialize)]
+struct AdminResponse {
+    identity: String,
+    active: bool,
+}
+
+#[derive(Serialize)]
+struct ChainResponse {
+    chain: ChainInfo,
 }
 
+#[derive(Serialize)]
+struct FreezeResponse {
+    evm_address: EvmAddress,
+    freeze: FreezeEntry,
+}
+
+#[derive(Serialize)]
+struct BlockResponse {
+    #[serde(flatten)]
+    target: BlockTarget,
+    block: BlockEntry,
+}
+
+#[derive(Serialize)]
+struct ListChainsResponse {
+    chains: Vec<ChainInfo>,
+}
+
+/// `code` and `retryable` are for programs, `error` is for humans
 #[derive(Serialize)]
 struct ErrorResponse {
     success: bool,
+    code: &'static str,
     error: String,
+    retryable: bool,
+    /// The stored record, for `VERSION_CONFLICT`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    current: Option<MappingRecord>,
+}
+
+/// Response JSON for a failed request
+fn error_response(e: &ProvisionError) -> String {
+    serde_json::to_string(&ErrorResponse {
+        success: false,
+        code: e.code(),
+        error: e.to_string(),
+        retryable: e.is_retryable(),
+        current: match e {
+            ProvisionError::VersionConflict { current, .. } => current.as_deref().cloned(),
+            _ => None,
+        },
+    }).unwrap()
+}
+
+/// A handler result, its payload (a JSON object) serialized once, straight
+/// from the handler's response type
+type Reply = ProvisionResult<Box<RawValue>>;
+
+fn respond<T: Serialize>(result: ProvisionResult<T>) -> Reply {
+    result.map(|result| serde_json::value::to_raw_value(&result).unwrap())
+}
+
+/// Successful response: `success: true` next to the fields of `result`
+fn success_response(result: &RawValue) -> String {
+    let fields = result.get().strip_prefix('{').expect("responses are JSON objects");
+    if fields == "}" {
+        r#"{"success":true}"#.to_string()
+    } else {
+        format!(r#"{{"success":true,{}"#, fields)
+    }
+}
+
+/// The org's key for signing the reply, when the caller asked for the
+/// envelope (`None`: no key, the reply goes unsigned). Read before the
+/// request is handled, so a malformed key fails it before it writes anything.
+fn response_signing_key(options: &RequestOptions) -> ProvisionResult<Option<response_signing::SigningKey>> {
+    if !options.envelope() {
+        return Ok(None);
+    }
+    secret(RESPONSE_SIGNING_KEY_SECRET)?.map(|key| response_signing::signing_key_from_base64(&key)).transpose()
+}
+
+/// Response JSON for `reply` to the request `body`, in the format the caller
+/// asked for, the envelope signed with `signing_key`
+fn encode(reply: Reply, body: Option<&str>, options: &RequestOptions, signing_key: Option<&response_signing::SigningKey>) -> String {
+    match (reply, options.envelope()) {
+        (Ok(result), false) => success_response(&result),
+        (Err(e), false) => error_response(&e),
+        (reply, true) => {
+            let (outcome, payload, error) = match reply {
+                Ok(payload) => ("success", Some(payload), None),
+                Err(e) => ("error", None, Some(e)),
+            };
+            let mut envelope = Envelope { envelope: ENVELOPE_VERSION, outcome, payload, error, signature: None };
+            envelope.signature = signing_key
+                .map(|key| response_signing::sign(&envelope, &response_signing::request_sha256(body.unwrap_or_default()), key, now_secs()));
+            serde_json::to_string(&envelope).unwrap()
+        }
+    }
 }
 
 // =============================================================================
-// KV STORE OPERATIONS
+// KV STORE
 // =============================================================================
 
-fn get_existing_mapping(solana_pubkey: &str, chain_id: u64) -> std::result::Result<Option<String>, String> {
-    let bucket = keyvalue::open(BUCKET_NAME)
-        .map_err(|e| format!("Failed to open bucket: {:?}", e))?;
-    
-    let key = format!("{}:{}", solana_pubkey, chain_id);
-    
-    match bucket.get(&key) {
-        Ok(Some(Value::Str(addr))) => Ok(Some(addr)),
-        Ok(Some(_)) => Err("Unexpected value type".into()),
-        Ok(None) => Ok(None),
-        Err(e) => Err(format!("KV read error: {:?}", e)),
+/// A C2F bucket as the library's `KvStore`
+struct KvBucket(&'static str);
+
+impl KvBucket {
+    /// The SDK bucket, opened on first use in the invocation and reused by
+    /// every later operation on it
+    fn open(&self) -> ProvisionResult<Rc<keyvalue::Bucket>> {
+        let open = OPEN_BUCKETS.with_borrow(|open| open.iter().find(|(name, _)| *name == self.0).map(|(_, bucket)| Rc::clone(bucket)));
+        if let Some(bucket) = open {
+            return Ok(bucket);
+        }
+        let bucket = Rc::new(keyvalue::open(self.0).map_err(|e| ProvisionError::Kv(format!("Failed to open bucket: {:?}", e)))?);
+        OPEN_BUCKETS.with_borrow_mut(|open| open.push((self.0, Rc::clone(&bucket))));
+        Ok(bucket)
     }
 }
 
-fn get_default_evm_address(solana_pubkey: &str) -> std::result::Result<Option<String>, String> {
-    let bucket = keyvalue::open(BUCKET_NAME)
-        .map_err(|e| format!("Failed to open bucket: {:?}", e))?;
-    
-    let key = format!("default:{}", solana_pubkey);
-    
-    match bucket.get(&key) {
-        Ok(Some(Value::Str(addr))) => Ok(Some(addr)),
-        Ok(Some(_)) => Err("Unexpected value type".into()),
+/// The string at `key` in an opened bucket
+fn read(bucket: &keyvalue::Bucket, key: &str) -> ProvisionResult<Option<String>> {
+    match bucket.get(key) {
+        Ok(Some(Value::Str(raw))) => Ok(Some(raw)),
+        Ok(Some(_)) => Err(ProvisionError::corrupt(format!("value at {}", key), "unexpected value type")),
         Ok(None) => Ok(None),
-        Err(e) => Err(format!("KV read error: {:?}", e)),
+        Err(e) => Err(ProvisionError::Kv(format!("KV read error: {:?}", e))),
     }
 }
 
-fn store_mapping_once(solana_pubkey: &str, chain_id: u64, evm_address: &str) -> std::result::Result<(), String> {
-    let bucket = keyvalue::open(BUCKET_NAME)
-        .map_err(|e| format!("Failed to open bucket: {:?}", e))?;
-    
-    let key = format!("{}:{}", solana_pubkey, chain_id);
-    let value = Value::Str(evm_address.to_string());
-    
-    match bucket.set(&key, &value, IfExists::Deny) {
-        Ok(()) => Ok(()),
-        Err(OperationError::ConditionFailed(_)) => Ok(()), // Already exists - fine
-        Err(e) => Err(format!("KV write error: {:?}", e)),
+/// The policy secret `name`, if the org has set it
+fn secret(name: &str) -> ProvisionResult<Option<String>> {
+    secrets::get(name).map_err(|e| ProvisionError::Kv(format!("Secret read error: {:?}", e)))
+}
+
+/// Write `value` at `key` in an opened bucket; `false` if `if_exists` is
+/// `Deny` and the key exists
+fn write(bucket: &keyvalue::Bucket, key: &str, value: &str, if_exists: IfExists) -> ProvisionResult<bool> {
+    match bucket.set(key, &Value::Str(value.to_string()), if_exists) {
+        Ok(()) => Ok(true),
+        Err(OperationError::ConditionFailed(_)) => Ok(false),
+        Err(e) => Err(ProvisionError::Kv(format!("KV write error: {:?}", e))),
     }
 }
 
-fn store_default_evm_address(solana_pubkey: &str, evm_address: &str) -> std::result::Result<(), String> {
-    let bucket = keyvalue::open(BUCKET_NAME)
-        .map_err(|e| format!("Failed to open bucket: {:?}", e))?;
-    
-    let key = format!("default:{}", solana_pubkey);
-    let value = Value::Str(evm_address.to_string());
-    
-    match bucket.set(&key, &value, IfExists::Deny) {
-        Ok(()) => Ok(()),
-        Err(OperationError::ConditionFailed(_)) => Ok(()), // Already exists - fine
-        Err(e) => Err(format!("KV write error: {:?}", e)),
+impl KvStore for KvBucket {
+    fn get(&self, key: &str) -> ProvisionResult<Option<String>> {
+        let bucket = self.open()?;
+        read(&bucket, key)
+    }
+
+    fn set_if_absent(&self, key: &str, value: &str) -> ProvisionResult<bool> {
+        let bucket = self.open()?;
+        write(&bucket, key, value, IfExists::Deny)
+    }
+
+    fn set(&self, key: &str, value: &str) -> ProvisionResult<()> {
+        let bucket = self.open()?;
+        write(&bucket, key, value, IfExists::Overwrite).map(|_| ())
+    }
+
+    fn get_many(&self, keys: &[String]) -> ProvisionResult<Vec<Option<String>>> {
+        let bucket = self.open()?;
+        keys.iter().map(|key| read(&bucket, key)).collect()
+    }
+
+    fn list_keys(&self, after: Option<&str>, limit: usize) -> ProvisionResult<Vec<String>> {
+        self.open()?
+            .list_keys(after, limit as u32)
+            .map_err(|e| ProvisionError::Kv(format!("KV list error: {:?}", e)))
     }
 }
 
-fn update_mapping(solana_pubkey: &str, chain_id: u64, evm_address: &str) -> std::result::Result<(), String> {
-    let bucket = keyvalue::open(BUCKET_NAME)
-        .map_err(|e| format!("Failed to open bucket: {:?}", e))?;
-    
-    let key = format!("{}:{}", solana_pubkey, chain_id);
-    let value = Value::Str(evm_address.to_string());
-    
-    bucket.set(&key, &value, IfExists::Overwrite)
-        .map_err(|e| format!("KV write error: {:?}", e))
+thread_local! {
+    /// Tenant of the request being handled, set by `enter_tenant` before
+    /// anything touches a bucket
+    static TENANT: RefCell<Option<TenantId>> = const { RefCell::new(None) };
+
+    /// Network of the request being handled, set by `enter_tenant` with the tenant
+    static NETWORK: RefCell<Network> = const { RefCell::new(Network::Mainnet) };
+
+    /// Data key of the request, set by `enter_tenant` (see `unwrap_data_key`)
+    #[cfg(feature = "encryption")]
+    static DATA_KEY: RefCell<Option<DataKey>> = const { RefCell::new(None) };
+
+    /// Pepper hashing the Solana pubkeys in every key, set by `enter_tenant`
+    /// (see `load_pepper`)
+    static PEPPER: RefCell<Option<Pepper>> = const { RefCell::new(None) };
+
+    /// Configuration of the request's tenant, read at most once per request
+    static CONFIG: RefCell<Option<Config>> = const { RefCell::new(None) };
+
+    /// Writes of the request when this build runs in shadow mode, set by
+    /// `enter_shadow` (see `shadow`)
+    static SHADOW: RefCell<Option<ShadowWrites>> = const { RefCell::new(None) };
+
+    /// Values the request has read (see `read_cache`)
+    static READ_CACHE: RefCell<ReadCache> = RefCell::new(ReadCache::new());
+
+    /// SDK buckets the request has opened (see `KvBucket::open`)
+    static OPEN_BUCKETS: RefCell<Vec<(&'static str, Rc<keyvalue::Bucket>)>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Make the request's `tenant` (next to `action`; absent: the default
+/// namespace) the namespace of every bucket but the shared blocklist, and its
+/// `network` (absent: mainnet) the network of its mappings (see `network`).
+/// Only the tenant's members may enter it (see `tenant`).
+fn enter_tenant(request: &AccessRequest) -> ProvisionResult<()> {
+    // Nothing of a previous request's tenant, network, bucket handles or
+    // reads survives a bad one
+    TENANT.set(None);
+    NETWORK.set(Network::Mainnet);
+    PEPPER.set(None);
+    #[cfg(feature = "encryption")]
+    DATA_KEY.set(None);
+    OPEN_BUCKETS.set(Vec::new());
+    READ_CACHE.set(ReadCache::new());
+    let body = request.request.as_deref();
+    let scope: RequestScope = body.and_then(|body| serde_json::from_str(body).ok()).unwrap_or_default();
+    let tenant = match scope.tenant {
+        None => None,
+        Some(tenant) => {
+            let id: String = serde_json::from_str(tenant.get())
+                .map_err(|_| ProvisionError::InvalidRequest("tenant must be a string".to_string()))?;
+            Some(TenantId::parse(&id)?)
+        }
+    };
+    let network = match scope.network {
+        None => Network::Mainnet,
+        Some(network) => serde_json::from_str(network.get())
+            .map_err(|_| ProvisionError::InvalidRequest("network must be \"mainnet\" or \"testnet\"".to_string()))?,
+    };
+    // Configuration is per tenant: read it again for this one
+    CONFIG.set(None);
+    SHADOW.set(None);
+    #[cfg(feature = "encryption")]
+    DATA_KEY.set(Some(unwrap_data_key()?));
+    PEPPER.set(load_pepper()?);
+    if let Some(tenant) = &tenant {
+        // Member lists live in the default namespace, out of tenants' reach
+        tenant::require_member(&env_bucket(CONFIG_BUCKET), tenant, request.identity.as_deref().unwrap_or_default())?;
+    }
+    TENANT.set(tenant);
+    NETWORK.set(network);
+    Ok(())
+}
+
+/// The org's data key, unwrapped with the key-encryption key. Both come from
+/// the policy's secrets, which CubeSigner keeps: neither is in the WASM nor
+/// in any request, so reading the build or the traffic gives no key away.
+#[cfg(feature = "encryption")]
+fn unwrap_data_key() -> ProvisionResult<DataKey> {
+    let kek = secret(DATA_KEY_KEK_SECRET)?.ok_or(ProvisionError::NotConfigured("the data_key_kek policy secret"))?;
+    let wrapped = secret(WRAPPED_DATA_KEY_SECRET)?.ok_or(ProvisionError::NotConfigured("the wrapped_data_key policy secret"))?;
+    let data_key = encryption::unwrap_data_key(&wrapped, &SecretWrapper(&kek))?;
+    DataKey::from_base64(&data_key)
+}
+
+/// The deployment's pepper: `kv_pepper` of the default namespace's
+/// configuration, which holds for every tenant since the blocklist is shared
+fn load_pepper() -> ProvisionResult<Option<Pepper>> {
+    // The configuration key names no user, so it reads the same unhashed
+    let config = config::get_config(&EnvPrefixed::new(stored_with(CONFIG_BUCKET, None), ENVIRONMENT))?;
+    config.kv_pepper.map(|pepper| Pepper::new(pepper.as_bytes())).transpose()
+}
+
+/// A bucket as this build stores it: keys hashed with the pepper (see
+/// `privacy`), values encrypted with its data key under the stored key (see
+/// `encryption`)
+type Stored = HashedKeys<Sealed>;
+#[cfg(feature = "encryption")]
+type Sealed = Encrypted<Shadowed<Cached<Bare>>>;
+#[cfg(not(feature = "encryption"))]
+type Sealed = Shadowed<Cached<Bare>>;
+
+/// The SDK bucket; `read-only` builds refuse to write to it
+#[cfg(feature = "read-only")]
+type Bare = ReadOnly<KvBucket>;
+#[cfg(not(feature = "read-only"))]
+type Bare = KvBucket;
+
+/// A bucket as stored, its reads kept for the rest of the request and its
+/// writes kept in memory in shadow mode
+fn bare(name: &'static str) -> Shadowed<Cached<Bare>> {
+    let bucket = KvBucket(name);
+    #[cfg(feature = "read-only")]
+    let bucket = ReadOnly(bucket);
+    let bucket = Cached::new(bucket, name, READ_CACHE.with_borrow(Clone::clone));
+    Shadowed::new(bucket, name, SHADOW.with_borrow(Clone::clone))
+}
+
+fn stored(name: &'static str) -> Stored {
+    stored_with(name, pepper())
+}
+
+fn stored_with(name: &'static str, pepper: Option<Pepper>) -> Stored {
+    HashedKeys::new(sealed(name), pepper)
+}
+
+/// A bucket as stored, its values opened and sealed with the data key.
+/// Migrations copy through it, so copies are sealed under their new keys.
+fn sealed(name: &'static str) -> Sealed {
+    let bucket = bare(name);
+    #[cfg(feature = "encryption")]
+    let bucket = Encrypted::new(bucket, data_key());
+    bucket
+}
+
+/// A bucket, in the build's environment (see `environment`)
+fn env_bucket(name: &'static str) -> EnvPrefixed<Stored> {
+    EnvPrefixed::new(stored(name), ENVIRONMENT)
+}
+
+/// The deployment's pepper, if it has one
+fn pepper() -> Option<Pepper> {
+    PEPPER.with_borrow(Clone::clone)
+}
+
+/// The request's data key
+#[cfg(feature = "encryption")]
+fn data_key() -> DataKey {
+    DATA_KEY.with_borrow(Clone::clone).expect("set by enter_tenant before any bucket is opened")
+}
+
+/// A bucket, in the build's environment and the request's tenant namespace
+/// (see `tenant`)
+fn bucket(name: &'static str) -> Namespaced<EnvPrefixed<Stored>> {
+    TENANT.with_borrow(|tenant| Namespaced::new(env_bucket(name), tenant.as_ref()))
+}
+
+/// A bucket, in the build's environment, the request's tenant namespace and
+/// the request's network (see `network`)
+fn networked(name: &'static str) -> Networked<Namespaced<EnvPrefixed<Stored>>> {
+    Networked::new(bucket(name), network())
+}
+
+/// Network of the request
+fn network() -> Network {
+    NETWORK.with_borrow(|network| *network)
+}
+
+/// Runtime configuration of the request's tenant (see `config`)
+fn config() -> ProvisionResult<Config> {
+    if let Some(config) = CONFIG.with_borrow(Clone::clone) {
+        return Ok(config);
+    }
+    let config = config::get_config(&bucket(CONFIG_BUCKET))?;
+    CONFIG.set(Some(config.clone()));
+    Ok(config)
+}
+
+/// The `solana_to_evm` bucket (mappings, indexes, registry, audit log) of
+/// the request's network
+fn mappings() -> Networked<Namespaced<EnvPrefixed<Stored>>> {
+    networked(BUCKET_NAME)
+}
+
+/// Current Unix time in seconds
+fn now_secs() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// Record the outcome of a mutating action; fails the action if the record cannot be written
+fn audited<T>(action: &str, actor: &str, subject: &str, result: ProvisionResult<T>) -> ProvisionResult<T> {
+    let event = AuditEvent {
+        action: action.to_string(),
+        actor: actor.to_string(),
+        subject: Some(subject.to_string()),
+        outcome: result.as_ref().map(|_| ()).map_err(|e| e.to_string()),
+    };
+    audit::append(&mappings(), event, now_secs())?;
+    // Best effort: a lost count must not fail the action
+    let _ = metrics::record_outcome(&bucket(METRICS_BUCKET), action, result.as_ref().map(|_| ()));
+    if result.is_ok() {
+        let _ = usage::record_operation(&bucket(USAGE_BUCKET), action, now_secs());
+    }
+    result
+}
+
+/// Count a request against `solana_pubkey`'s rate limit. Refused requests are
+/// not audited, so a retry loop does not flood the audit log either.
+fn rate_limited(solana_pubkey: &SolanaPubkey) -> ProvisionResult<()> {
+    let result = config().and_then(|config| rate_limit::check(&bucket(RATE_LIMIT_BUCKET), &config.rate_limit, solana_pubkey, now_secs()));
+    if let Err(e) = &result {
+        let _ = metrics::record_error(&bucket(METRICS_BUCKET), e);
+    }
+    result
+}
+
+/// Count a request against `solana_pubkey`'s rate limit once `proof`, its
+/// ownership proof checked without burning the nonce (see
+/// `mapping::verify_store`), holds. A failed proof is audited under `action`
+/// and counts nothing, so no one can use up another address's budget.
+fn rate_limited_if_proven<T>(action: &str, solana_pubkey: &SolanaPubkey, proof: ProvisionResult<T>) -> ProvisionResult<()> {
+    if let Err(e) = proof {
+        let actor = solana_pubkey.to_string();
+        return audited(action, &actor, &actor, Err(e));
+    }
+    rate_limited(solana_pubkey)
+}
+
+/// Run `f` once per idempotency key (see `idempotency`); without a key, just run it.
+/// `request_hash` covers every field that affects the result.
+fn idempotent<T: Serialize + DeserializeOwned>(
+    action: &str,
+    idempotency_key: Option<&str>,
+    request_hash: &str,
+    f: impl FnOnce() -> ProvisionResult<T>,
+) -> ProvisionResult<T> {
+    match idempotency_key {
+        Some(key) => idempotency::run(&networked(IDEMPOTENCY_BUCKET), action, key, request_hash, now_secs(), f),
+        None => f(),
+    }
+}
+
+// =============================================================================
+// REQUESTER
+// =============================================================================
+
+/// The only place that reads the caller's identity off the `AccessRequest`
+fn requester(request: &AccessRequest) -> Requester {
+    Requester::new(request.identity.as_deref(), request.org_role.as_deref())
+}
+
+/// Fail unless the requester's role allows `action` (see `authz`). Refused
+/// mutating actions are audited; refused reads are not, like all reads.
+fn authorize(requester: &Requester, action: &str) -> ProvisionResult<()> {
+    let result = authz::authorize(&bucket(ADMINS_BUCKET), requester, action);
+    if result.is_err() && authz::required_role(action) > Role::Reader {
+        audited(action, requester.name(), "", result)
+    } else {
+        result
+    }
+}
+
+/// Fail unless the requester is an active admin
+fn require_admin(requester: &Requester) -> ProvisionResult<()> {
+    if requester.identity.is_empty() {
+        return Err(ProvisionError::NotAdmin(admin::UNKNOWN_REQUESTER.to_string()));
+    }
+    admin::require_admin(&bucket(ADMINS_BUCKET), &requester.identity)
 }
 
 // =============================================================================
@@ -173,142 +737,1088 @@ fn update_mapping(solana_pubkey: &str, chain_id: u64, evm_address: &str) -> std:
 
 /// Store mappings for a Solana address across multiple chains
 /// Called by backend AFTER it creates the EVM key via CubeSigner API
-fn handle_store(solana_pubkey: String, chain_ids: Vec<u64>, evm_address: String) -> std::result::Result<StoreResponse, String> {
-    if chain_ids.is_empty() {
-        return Err("chain_ids cannot be empty".into());
+fn handle_store(
+    req: ProvisionRequest,
+    evm_address: EvmAddress,
+    key_id: Option<String>,
+) -> ProvisionResult<ProvisionResponse> {
+    let counted = match labels::parse_label(req.label.as_deref())? {
+        None => Some(metrics::unmapped_chains(&mappings(), &req.solana_pubkey, &req.chain_ids)?),
+        Some(_) => None,
+    };
+    let (response, new_wallet) = store_mappings(&mappings(), &req, evm_address, key_id)?;
+
+    if let Some(new_chains) = counted {
+        let _ = metrics::record_provision(&bucket(METRICS_BUCKET), new_wallet, &new_chains);
     }
-    
-    // Validate EVM address format
-    if !evm_address.starts_with("0x") || evm_address.len() != 42 {
-        return Err(format!("Invalid EVM address format: {}", evm_address));
+    if new_wallet {
+        let _ = usage::record_key_creation(&bucket(USAGE_BUCKET), now_secs());
     }
+    Ok(response)
+}
 
-    // Store default address (first-writer-wins)
-    store_default_evm_address(&solana_pubkey, &evm_address)?;
+/// The store flow over `kv`; returns whether the user (or label) got its
+/// first key, `evm_address`
+fn store_mappings(
+    kv: &impl KvStore,
+    req: &ProvisionRequest,
+    evm_address: EvmAddress,
+    key_id: Option<String>,
+) -> ProvisionResult<(ProvisionResponse, bool)> {
+    let now = now_secs();
+    let config = config()?;
+    address_sanity::check_solana_pubkey(&req.solana_pubkey, config.allow_program_pubkeys)?;
+    address_sanity::check(&evm_address, &config.denied_addresses)?;
+    blocklist::screen(&env_bucket(BLOCKLIST_BUCKET), &req.solana_pubkey, &[&evm_address])?;
+    let label = labels::parse_label(req.label.as_deref())?;
+    network::require_chains(kv, network(), &req.chain_ids)?;
+    quota::check(kv, &config.mapping_quota, &req.solana_pubkey, label, &req.chain_ids)?;
+    let mut new_wallet = false;
 
-    // Store chain-specific mappings
-    let mut chain_mappings = HashMap::new();
-    
-    for chain_id in chain_ids {
-        match get_existing_mapping(&solana_pubkey, chain_id)? {
-            Some(existing) => {
-                // Already exists, use existing value
-                chain_mappings.insert(chain_id, existing);
-            }
-            None => {
-                store_mapping_once(&solana_pubkey, chain_id, &evm_address)?;
-                chain_mappings.insert(chain_id, evm_address.clone());
-            }
-        }
+    let response = mapping::store(kv, req, now, || {
+        new_wallet = true;
+        Ok(MappingRecord::new(&evm_address, key_id.as_deref(), req.solana_pubkey.as_str(), now))
+    })?;
+    Ok((response, new_wallet))
+}
+
+/// Store mappings for many Solana addresses
+/// Each entry is handled (and audited) independently
+fn handle_store_batch(requests: Vec<StoreBatchEntry>) -> ProvisionResult<ProvisionBatchResponse> {
+    mapping::batch(requests, |entry| entry.solana_pubkey.clone(), |entry| {
+        let actor = entry.solana_pubkey.to_string();
+        let (req, evm_address, key_id) = entry.into_request();
+        rate_limited_if_proven("store", &req.solana_pubkey, store_proof(&req))?;
+        let req = authorized(&mappings(), req)?;
+        audited("store", &actor, &actor, handle_store(req, evm_address, key_id))
+    })
+}
+
+/// Dry run of `handle_store_batch`; later entries see what earlier ones would
+/// have written
+fn dry_run_store_batch(requests: Vec<StoreBatchEntry>) -> ProvisionResult<DryRunResponse<ProvisionBatchResponse>> {
+    dry_run::run(&mappings(), |kv| {
+        mapping::batch(requests, |entry| entry.solana_pubkey.clone(), |entry| {
+            let (req, evm_address, key_id) = entry.into_request();
+            Ok(store_mappings(kv, &authorized(kv, req)?, evm_address, key_id)?.0)
+        })
+    })
+}
+
+/// Check `req`'s address and ownership proof without burning its nonce (see
+/// `mapping::verify_store`)
+fn store_proof(req: &ProvisionRequest) -> ProvisionResult<u64> {
+    address_sanity::check_solana_pubkey(&req.solana_pubkey, config()?.allow_program_pubkeys)?;
+    mapping::verify_store(req, now_secs())
+}
+
+/// `req` once its address is usable and its ownership proof checks out (see
+/// `mapping::authorize_store`), with the configured default chains if it names none
+fn authorized(kv: &impl KvStore, mut req: ProvisionRequest) -> ProvisionResult<ProvisionRequest> {
+    address_sanity::check_solana_pubkey(&req.solana_pubkey, config()?.allow_program_pubkeys)?;
+    mapping::authorize_store(kv, &req, now_secs())?;
+    if req.chain_ids.is_empty() {
+        req.chain_ids = config()?.default_chain_ids;
     }
+    Ok(req)
+}
 
-    Ok(StoreResponse { 
-        success: true,
+/// Store the Solana wallet of an EVM address (EVM → Solana provisioning)
+/// Called by backend AFTER it creates the Ed25519 key via CubeSigner API
+fn handle_store_evm_to_solana(
+    req: EvmToSolanaProvisionRequest,
+    solana_pubkey: SolanaPubkey,
+    key_id: String,
+) -> ProvisionResult<EvmToSolanaProvisionResponse> {
+    mapping::store_evm_to_solana(&networked(EVM_TO_SOLANA_BUCKET), &req, now_secs(), || {
+        Ok(SolanaMappingValue { address: solana_pubkey, key_id })
+    })
+}
+
+/// Get the Solana wallet of an EVM address
+fn handle_get_evm_to_solana(evm_address: EvmAddress) -> ProvisionResult<EvmToSolanaResponse> {
+    let stored = evm_to_solana::get_mapping(&networked(EVM_TO_SOLANA_BUCKET), &evm_address)?;
+
+    Ok(EvmToSolanaResponse {
         evm_address,
-        chain_mappings,
+        solana_pubkey: stored.as_ref().map(|v| v.address.clone()),
+        key_id: stored.map(|v| v.key_id),
     })
 }
 
-/// Get existing mappings for a Solana address
-fn handle_get(solana_pubkey: String, chain_ids: Vec<u64>) -> std::result::Result<GetResponse, String> {
-    let default_address = get_default_evm_address(&solana_pubkey)?;
-    
-    let mut chain_mappings = HashMap::new();
-    for chain_id in chain_ids {
-        if let Some(addr) = get_existing_mapping(&solana_pubkey, chain_id)? {
-            chain_mappings.insert(chain_id, addr);
+/// Propose a new mapping for a specific chain (admin only)
+/// Called by backend AFTER it creates a new EVM key
+fn handle_propose_update(
+    kv: &impl KvStore,
+    requester: &Requester,
+    solana_pubkey: SolanaPubkey,
+    chain_id: ChainId,
+    new_evm_address: EvmAddress,
+    new_key_id: Option<String>,
+    allow_shared_address: bool,
+) -> ProvisionResult<PendingResponse> {
+    require_admin(requester)?;
+
+    mapping::require_provisioned(kv, &solana_pubkey)?;
+    network::require_chains(kv, network(), std::slice::from_ref(&chain_id))?;
+    address_sanity::check(&new_evm_address, &config()?.denied_addresses)?;
+    if !allow_shared_address {
+        mapping::require_address_free(kv, &new_evm_address, &solana_pubkey)?;
+    }
+    let pending = approval::propose(
+        kv,
+        &solana_pubkey,
+        &chain_id,
+        Some(&new_evm_address),
+        new_key_id.as_deref(),
+        allow_shared_address,
+        &requester.identity,
+        now_secs(),
+    )?;
+
+    Ok(PendingResponse { pending: Some(pending) })
+}
+
+/// Approve a pending update: a second admin signs off, then the mapping is overwritten
+fn handle_approve_update(
+    kv: &impl KvStore,
+    requester: &Requester,
+    solana_pubkey: SolanaPubkey,
+    chain_id: ChainId,
+    proposal_id: u64,
+) -> ProvisionResult<UpdateResponse> {
+    require_admin(requester)?;
+
+    let pending = approval::resolve(
+        kv,
+        &solana_pubkey,
+        &chain_id,
+        proposal_id,
+        &requester.identity,
+        PendingStatus::Approved,
+        now_secs(),
+    )?;
+    let new_evm_address = pending
+        .new_evm_address
+        .ok_or_else(|| ProvisionError::InvalidRequest(format!("update {} does not name a new EVM address", proposal_id)))?;
+
+    let allow_shared_address = pending.allow_shared_address;
+    apply_update(kv, &solana_pubkey, &chain_id, new_evm_address, pending.new_key_id, allow_shared_address, &requester.identity)
+}
+
+/// Propose or approve many chain updates (admin only). With `all_or_nothing`
+/// the batch is dry-run first and nothing is written unless every entry would
+/// succeed.
+fn handle_update_batch(
+    requester: &Requester,
+    updates: Vec<UpdateBatchEntry>,
+    all_or_nothing: bool,
+) -> ProvisionResult<UpdateBatchResponse<UpdateBatchResult>> {
+    require_admin(requester)?;
+
+    let target = |entry: &UpdateBatchEntry| (entry.solana_pubkey.clone(), entry.chain_id.clone());
+    if all_or_nothing {
+        let preview = dry_run::run(&mappings(), |kv| {
+            mapping::update_batch(updates.clone(), target, |entry| update_batch_entry(kv, requester, entry))
+        })?;
+        if preview.result.failed > 0 {
+            return Ok(UpdateBatchResponse { applied: false, ..preview.result });
         }
     }
 
-    Ok(GetResponse {
-        success: true,
-        default_address,
-        chain_mappings,
+    let actor = requester.name();
+    mapping::update_batch(updates, target, |entry| {
+        let action = if entry.proposal_id.is_some() { "approve_update" } else { "propose_update" };
+        let subject = entry.solana_pubkey.to_string();
+        audited(action, actor, &subject, update_batch_entry(&mappings(), requester, entry))
     })
 }
 
-/// Update mapping for a specific chain (admin only)
-/// Called by backend AFTER it creates a new EVM key
-fn handle_update(solana_pubkey: String, chain_id: u64, new_evm_address: String) -> std::result::Result<UpdateResponse, String> {
-    // Validate EVM address format
-    if !new_evm_address.starts_with("0x") || new_evm_address.len() != 42 {
-        return Err(format!("Invalid EVM address format: {}", new_evm_address));
+/// Propose one batch entry's update, or approve the proposal it names
+fn update_batch_entry(kv: &impl KvStore, requester: &Requester, entry: UpdateBatchEntry) -> ProvisionResult<UpdateBatchResult> {
+    let UpdateBatchEntry { solana_pubkey, chain_id, new_evm_address, new_key_id, proposal_id, allow_shared_address } = entry;
+    let Some(proposal_id) = proposal_id else {
+        return handle_propose_update(kv, requester, solana_pubkey, chain_id, new_evm_address, new_key_id, allow_shared_address)
+            .map(UpdateBatchResult::Proposed);
+    };
+
+    // Approving must not apply an address other than the one the entry names
+    let pending = approval::get_pending(kv, &solana_pubkey, &chain_id)?;
+    if pending.as_ref().is_some_and(|p| p.id == proposal_id && p.new_evm_address.as_ref() != Some(&new_evm_address)) {
+        return Err(ProvisionError::InvalidRequest(format!(
+            "update {} is not for {}",
+            proposal_id, new_evm_address
+        )));
+    }
+    handle_approve_update(kv, requester, solana_pubkey, chain_id, proposal_id).map(UpdateBatchResult::Applied)
+}
+
+/// Reject (or, for the proposer, withdraw) a pending update
+fn handle_reject_update(
+    requester: &Requester,
+    solana_pubkey: SolanaPubkey,
+    chain_id: ChainId,
+    proposal_id: u64,
+) -> ProvisionResult<PendingResponse> {
+    require_admin(requester)?;
+
+    let pending = approval::resolve(
+        &mappings(),
+        &solana_pubkey,
+        &chain_id,
+        proposal_id,
+        &requester.identity,
+        PendingStatus::Rejected,
+        now_secs(),
+    )?;
+
+    Ok(PendingResponse { pending: Some(pending) })
+}
+
+/// Add (`active: true`) or remove an admin (org owners only)
+fn handle_set_admin(requester: &Requester, identity: String, active: bool) -> ProvisionResult<AdminResponse> {
+    admin::set_admin(&bucket(ADMINS_BUCKET), requester, &identity, active, now_secs())?;
+    Ok(AdminResponse { identity, active })
+}
+
+/// Self-service update: the owner of the Solana address signs the new key
+/// (its address and id), whose address must be the one `new_key_id` names
+fn handle_update_self(
+    solana_pubkey: SolanaPubkey,
+    chain_id: ChainId,
+    new_evm_address: EvmAddress,
+    new_key_id: String,
+    nonce: String,
+    expires_at: u64,
+    signature: String,
+) -> ProvisionResult<UpdateResponse> {
+    let now = now_secs();
+    config()?.check_authorization_ttl(expires_at, now)?;
+    // The mapping must name the key the user signed for, not any address
+    if signing_gate::key_address(&new_key_id)? != new_evm_address {
+        return Err(ProvisionError::InvalidRequest(format!("{} is not the address of {}", new_evm_address, new_key_id)));
     }
+    let message = auth::update_self_address_message(&solana_pubkey, &chain_id, &new_evm_address, &new_key_id, &nonce, expires_at);
+    mapping::authorize_update_self(&mappings(), &solana_pubkey, &message, &nonce, expires_at, &signature, now)?;
+
+    let actor = solana_pubkey.to_string();
+    apply_update(&mappings(), &solana_pubkey, &chain_id, new_evm_address, Some(new_key_id), false, &actor)
+}
+
+/// Link an external EVM address: both wallets signed `auth::link_external_message`
+fn handle_link_external(req: LinkExternalRequest) -> ProvisionResult<LinkExternalResponse> {
+    let now = now_secs();
+    config()?.check_authorization_ttl(req.expires_at, now)?;
+    address_sanity::check(&req.evm_address, &config()?.denied_addresses)?;
+    blocklist::screen(&env_bucket(BLOCKLIST_BUCKET), &req.solana_pubkey, &[&req.evm_address])?;
+    network::require_chains(&mappings(), network(), &req.chain_ids)?;
+    quota::check(&mappings(), &config()?.mapping_quota, &req.solana_pubkey, None, &req.chain_ids)?;
+    let linked = metrics::custodial_chains(&mappings(), &req.solana_pubkey, &req.chain_ids)?;
+    let response = mapping::link_external(&mappings(), &req, now)?;
 
-    // Verify Solana address has been provisioned
-    get_default_evm_address(&solana_pubkey)?
-        .ok_or_else(|| format!("Solana address {} not provisioned", solana_pubkey))?;
+    let _ = metrics::record_external_links(&bucket(METRICS_BUCKET), &linked);
+    Ok(response)
+}
+
+/// Overwrite a chain mapping, keeping the replaced value in the chain's history.
+/// Fails with `AddressOwned` if the address belongs to another user, unless
+/// an admin allowed sharing it.
+fn apply_update(
+    kv: &impl KvStore,
+    solana_pubkey: &SolanaPubkey,
+    chain_id: &ChainId,
+    new_evm_address: EvmAddress,
+    new_key_id: Option<String>,
+    allow_shared_address: bool,
+    actor: &str,
+) -> ProvisionResult<UpdateResponse> {
+    let now = now_secs();
+    mapping::require_provisioned(kv, solana_pubkey)?;
+    address_sanity::check(&new_evm_address, &config()?.denied_addresses)?;
+    blocklist::screen(&env_bucket(BLOCKLIST_BUCKET), solana_pubkey, &[&new_evm_address])?;
+    network::require_chains(kv, network(), std::slice::from_ref(chain_id))?;
+    quota::check(kv, &config()?.mapping_quota, solana_pubkey, None, std::slice::from_ref(chain_id))?;
+    if !allow_shared_address {
+        mapping::claim_address(kv, &new_evm_address, solana_pubkey)?;
+    }
 
-    // Update the mapping (allows overwrite)
-    update_mapping(&solana_pubkey, chain_id, &new_evm_address)?;
+    let record = MappingRecord::new(&new_evm_address, new_key_id.as_deref(), actor, now);
+    let stored = mapping::apply_update(kv, solana_pubkey, chain_id, &record, None, None, actor, now)?;
 
     Ok(UpdateResponse {
-        success: true,
         new_evm_address,
-        chain_id,
+        new_key_id,
+        chain_id: chain_id.clone(),
+        version: stored.revision,
     })
 }
 
+/// Enable or disable a chain, or register a new one (admin only)
+fn handle_set_chain(
+    requester: &Requester,
+    chain_id: ChainId,
+    enabled: bool,
+    name: Option<String>,
+    testnet: Option<bool>,
+) -> ProvisionResult<ChainResponse> {
+    require_admin(requester)?;
+
+    let chain = chains::set_chain(&mappings(), &chain_id, enabled, name.as_deref(), testnet, &requester.identity, now_secs())?;
+    Ok(ChainResponse { chain })
+}
+
+/// Freeze or unfreeze an EVM address (admin only)
+fn handle_set_frozen(
+    requester: &Requester,
+    evm_address: EvmAddress,
+    frozen: bool,
+    reason: Option<String>,
+) -> ProvisionResult<FreezeResponse> {
+    require_admin(requester)?;
+
+    let freeze = freeze::set_frozen(&mappings(), &evm_address, frozen, reason.as_deref(), &requester.identity, now_secs())?;
+    Ok(FreezeResponse { evm_address, freeze })
+}
+
+/// Set or clear a user's spending limit (admin only)
+fn handle_set_spend_limit(
+    requester: &Requester,
+    solana_pubkey: SolanaPubkey,
+    chain_id: Option<ChainId>,
+    spend_limit: Option<SpendLimit>,
+) -> ProvisionResult<MappingRecord> {
+    require_admin(requester)?;
+
+    spend_limits::set_spend_limit(&mappings(), &solana_pubkey, chain_id.as_ref(), spend_limit)
+}
+
+/// Bind an identity to the user it signs for, or unbind it (admin only)
+fn handle_set_signer(requester: &Requester, identity: String, solana_pubkey: Option<SolanaPubkey>) -> ProvisionResult<SignerResponse> {
+    require_admin(requester)?;
+
+    signing_gate::set_signer(&mappings(), &identity, solana_pubkey.as_ref())?;
+    Ok(SignerResponse { identity, solana_pubkey })
+}
+
+/// Add or remove an allowed destination (admin only)
+fn handle_set_allowed_destination(
+    requester: &Requester,
+    solana_pubkey: SolanaPubkey,
+    chain_id: ChainId,
+    destination: EvmAddress,
+    allowed: bool,
+) -> ProvisionResult<AllowedDestinations> {
+    require_admin(requester)?;
+
+    if allowed {
+        destinations::add_allowed(&mappings(), &solana_pubkey, &chain_id, &destination)
+    } else {
+        destinations::remove_allowed(&mappings(), &solana_pubkey, &chain_id, &destination)
+    }
+}
+
+/// Block or unblock an address (org owners only: the blocklist is shared by
+/// every tenant, whose admins are their own)
+fn handle_set_blocked(
+    requester: &Requester,
+    target: BlockTarget,
+    blocked: bool,
+    reason: Option<String>,
+) -> ProvisionResult<BlockResponse> {
+    if !requester.is_org_owner {
+        return Err(ProvisionError::NotOrgOwner);
+    }
+
+    let block = blocklist::set_blocked(&env_bucket(BLOCKLIST_BUCKET), &target, blocked, reason.as_deref(), &requester.identity, now_secs())?;
+    Ok(BlockResponse { target, block })
+}
+
+/// Migrate one batch of keys after `cursor` (admin only)
+fn handle_migrate(
+    requester: &Requester,
+    cursor: Option<String>,
+    limit: Option<usize>,
+) -> ProvisionResult<migrate::MigrationReport> {
+    require_admin(requester)?;
+    migrate::migrate_batch(&mappings(), cursor.as_deref(), limit)
+}
+
+/// Remove the expired temporary mappings among one batch of keys (admin only)
+fn handle_sweep(
+    requester: &Requester,
+    cursor: Option<String>,
+    limit: Option<usize>,
+) -> ProvisionResult<expiry::SweepReport> {
+    require_admin(requester)?;
+    expiry::sweep_batch(&mappings(), cursor.as_deref(), limit, now_secs())
+}
+
+fn handle_anonymize(requester: &Requester, solana_pubkey: SolanaPubkey, salt: String) -> ProvisionResult<anonymize::DeletionReceipt> {
+    require_admin(requester)?;
+    anonymize::anonymize(&mappings(), &solana_pubkey, &salt, requester.name(), now_secs())
+}
+
+/// Apply a configuration change (admin only); the rest of the request sees
+/// it, except a new `kv_pepper` (org owners only, default namespace only,
+/// `encryption` builds only), which applies from the next request on
+fn handle_set_config(requester: &Requester, update: ConfigUpdate) -> ProvisionResult<Config> {
+    require_admin(requester)?;
+    // Values name users too: hashing keys hides nothing unless they are sealed
+    #[cfg(not(feature = "encryption"))]
+    if update.kv_pepper.is_some() {
+        return Err(ProvisionError::InvalidRequest("kv_pepper needs a build with the encryption feature".to_string()));
+    }
+    if update.kv_pepper.is_some() {
+        if !requester.is_org_owner {
+            return Err(ProvisionError::NotOrgOwner);
+        }
+        if TENANT.with_borrow(Option::is_some) {
+            return Err(ProvisionError::InvalidRequest("kv_pepper is set in the default namespace only".to_string()));
+        }
+    }
+    let config = config::set_config(&bucket(CONFIG_BUCKET), update)?;
+    CONFIG.set(Some(config.clone()));
+    Ok(config.redacted())
+}
+
+/// The default namespace's `config` bucket, holding the tenants' member
+/// lists; requests in a tenant cannot manage them
+fn tenant_members_bucket(requester: &Requester, tenant_id: &str) -> ProvisionResult<(EnvPrefixed<Stored>, TenantId)> {
+    if !requester.is_org_owner {
+        return Err(ProvisionError::NotOrgOwner);
+    }
+    if TENANT.with_borrow(Option::is_some) {
+        return Err(ProvisionError::InvalidRequest("tenant members are managed in the default namespace".to_string()));
+    }
+    Ok((env_bucket(CONFIG_BUCKET), TenantId::parse(tenant_id)?))
+}
+
+fn handle_get_tenant_members(requester: &Requester, tenant_id: &str) -> ProvisionResult<TenantMembersResponse> {
+    let (bucket, tenant) = tenant_members_bucket(requester, tenant_id)?;
+    let members = tenant::get_members(&bucket, &tenant)?;
+    Ok(TenantMembersResponse { tenant_id: tenant, members })
+}
+
+/// Replace a tenant's member list (org owners only)
+fn handle_set_tenant_members(requester: &Requester, tenant_id: &str, members: Vec<String>) -> ProvisionResult<TenantMembersResponse> {
+    let (bucket, tenant) = tenant_members_bucket(requester, tenant_id)?;
+    let members = tenant::set_members(&bucket, &tenant, members)?;
+    Ok(TenantMembersResponse { tenant_id: tenant, members })
+}
+
+/// Copy one batch of `bucket`'s legacy keys after `cursor` under the build's
+/// environment prefix (org owners only). Not audited: the audit log is among
+/// the keys being copied.
+fn handle_migrate_environment(
+    requester: &Requester,
+    bucket: String,
+    cursor: Option<String>,
+    limit: Option<usize>,
+) -> ProvisionResult<environment::LegacyMigrationReport> {
+    if !requester.is_org_owner {
+        return Err(ProvisionError::NotOrgOwner);
+    }
+    let env = ENVIRONMENT.ok_or_else(|| ProvisionError::InvalidRequest("this build has no environment".to_string()))?;
+    let name = BUCKETS
+        .into_iter()
+        .find(|name| *name == bucket)
+        .ok_or_else(|| ProvisionError::InvalidRequest(format!("unknown bucket {:?}", bucket)))?;
+    // Unprefixed: legacy keys are outside every environment and tenant
+    environment::migrate_legacy_batch(&sealed(name), env, cursor.as_deref(), limit)
+}
+
+/// Copy one batch of `bucket`'s keys after `cursor` that name a Solana pubkey
+/// in the clear under their hashed form (org owners only). Not audited, like
+/// `migrate_environment`.
+fn handle_migrate_hashed_keys(
+    requester: &Requester,
+    bucket: String,
+    cursor: Option<String>,
+    limit: Option<usize>,
+) -> ProvisionResult<privacy::KeyHashingReport> {
+    if !requester.is_org_owner {
+        return Err(ProvisionError::NotOrgOwner);
+    }
+    let pepper = pepper().ok_or_else(|| ProvisionError::InvalidRequest("no kv_pepper is configured".to_string()))?;
+    let name = BUCKETS
+        .into_iter()
+        .find(|name| *name == bucket)
+        .ok_or_else(|| ProvisionError::InvalidRequest(format!("unknown bucket {:?}", bucket)))?;
+    // Unprefixed: hashing applies under every environment and tenant prefix
+    privacy::migrate_plaintext_batch(&sealed(name), &pepper, cursor.as_deref(), limit)
+}
+
+/// Encrypt the plaintext values of one batch of `bucket`'s keys after
+/// `cursor` in place (org owners only). Not audited, like
+/// `migrate_environment`.
+#[cfg(feature = "encryption")]
+fn handle_encrypt_values(
+    requester: &Requester,
+    bucket: String,
+    cursor: Option<String>,
+    limit: Option<usize>,
+) -> ProvisionResult<encryption::EncryptionReport> {
+    if !requester.is_org_owner {
+        return Err(ProvisionError::NotOrgOwner);
+    }
+    let name = BUCKETS
+        .into_iter()
+        .find(|name| *name == bucket)
+        .ok_or_else(|| ProvisionError::InvalidRequest(format!("unknown bucket {:?}", bucket)))?;
+    // The bare bucket: values are encrypted under every environment and tenant prefix
+    encryption::encrypt_plaintext_batch(&bare(name), &data_key(), cursor.as_deref(), limit)
+}
+
+/// Builds without the `encryption` feature store values as they are
+#[cfg(not(feature = "encryption"))]
+fn handle_encrypt_values(
+    requester: &Requester,
+    _bucket: String,
+    _cursor: Option<String>,
+    _limit: Option<usize>,
+) -> ProvisionResult<()> {
+    if !requester.is_org_owner {
+        return Err(ProvisionError::NotOrgOwner);
+    }
+    Err(ProvisionError::InvalidRequest("this build does not encrypt values".to_string()))
+}
+
+/// Export one page of the mappings bucket after `cursor` (admin only)
+fn handle_export(
+    requester: &Requester,
+    cursor: Option<String>,
+    limit: Option<usize>,
+) -> ProvisionResult<export::ExportPage> {
+    require_admin(requester)?;
+    export::export_page(&mappings(), cursor.as_deref(), limit)
+}
+
+/// Check one batch of the mappings bucket for inconsistent records (admin only)
+fn handle_verify(
+    requester: &Requester,
+    cursor: Option<String>,
+    limit: Option<usize>,
+) -> ProvisionResult<verify::VerifyReport> {
+    require_admin(requester)?;
+    verify::verify_batch(&mappings(), cursor.as_deref(), limit)
+}
+
+/// Import one batch of exported entries (admin only)
+fn handle_import(requester: &Requester, req: &ImportRequest) -> ProvisionResult<import::ImportReport> {
+    require_admin(requester)?;
+    import::import_batch(&mappings(), req)
+}
+
+/// Fix violations found by `verify`, by id (admin only)
+fn handle_repair(requester: &Requester, req: &RepairRequest) -> ProvisionResult<repair::RepairReport> {
+    require_admin(requester)?;
+    repair::repair_batch(&mappings(), req)
+}
+
+fn handle_reconcile(
+    requester: &Requester,
+    keys: &[ListedKey],
+    req: &ReconcileRequest,
+) -> ProvisionResult<reconcile::ReconcileReport> {
+    require_admin(requester)?;
+    reconcile::reconcile_batch(&mappings(), keys, req, requester.name(), now_secs())
+}
+
+/// Look up which Solana address owns an EVM address
+fn handle_reverse_get(evm_address: EvmAddress) -> ProvisionResult<ReverseGetResponse> {
+    let solana_pubkey = kv::get_reverse_mapping(&mappings(), &evm_address)?;
+    Ok(ReverseGetResponse { evm_address, solana_pubkey })
+}
+
+fn handle_get_retirement(evm_address: EvmAddress) -> ProvisionResult<RetirementResponse> {
+    let retirement = retirement::get_retirement(&mappings(), &evm_address)?;
+    Ok(RetirementResponse { evm_address, retirement })
+}
+
 // =============================================================================
 // POLICY ENTRY POINT
 // =============================================================================
 
+/// Every reply is a `Deny` decision carrying the envelope, the only decision
+/// the SDK lets carry data: a success's payload or the error. Callers that
+/// only need the outcome send `"allow_on_success": true` and get a bare
+/// `Allow` for a success; `"legacy_response": true` answers every request
+/// the way callers from before the envelope expect (see `RequestOptions`).
+#[cfg(not(feature = "signing-gate"))]
 #[policy]
 async fn main(request: AccessRequest) -> Result<AccessDecision> {
-    let body = match &request.request {
-        Some(body) => body,
-        None => {
-            let resp = serde_json::to_string(&ErrorResponse {
-                success: false,
-                error: "Missing request body".into(),
-            }).unwrap();
-            return Ok(AccessDecision::Deny(resp));
-        }
-    };
-    
-    let policy_req: PolicyRequest = match serde_json::from_str(body) {
-        Ok(req) => req,
-        Err(e) => {
-            let resp = serde_json::to_string(&ErrorResponse {
-                success: false,
-                error: format!("Invalid request: {}", e),
-            }).unwrap();
-            return Ok(AccessDecision::Deny(resp));
-        }
-    };
+    let started = Instant::now();
+    let body = request.request.as_deref();
+    // Unreadable bodies are answered in the default format
+    let options: RequestOptions = body.and_then(|body| serde_json::from_str(body).ok()).unwrap_or_default();
+
+    let policy_req = PolicyRequest::parse(body);
+    let action = policy_req.as_ref().map_or(INVALID_ACTION, PolicyRequest::action);
+    let solana_pubkey = policy_req.as_ref().ok().and_then(PolicyRequest::solana_pubkey).map(SolanaPubkey::to_string);
+
+    let signing_key = response_signing_key(&options);
+    let reply = signing_key
+        .as_ref()
+        .map(|_| ())
+        .map_err(ProvisionError::clone)
+        .and_then(|()| enter_tenant(&request))
+        .and_then(|()| authenticate(body))
+        .and_then(|()| enter_shadow())
+        .and_then(|()| policy_req)
+        .and_then(|policy_req| dispatch(&request, policy_req));
+    let mut event = logging::event(options.request_id.as_deref(), action, solana_pubkey.as_deref(), started, &reply);
+    if !cfg!(feature = "read-only") {
+        event.shadow_writes = SHADOW.with_borrow(|writes| writes.as_ref().map(ShadowWrites::logged_keys));
+    }
+    StderrLogger.log(&event);
+    Ok(decide(reply, body, &options, signing_key.ok().flatten().as_ref()))
+}
+
+/// The decision answering `reply`: a bare `Allow` only for a success whose
+/// caller asked for one, otherwise a `Deny` carrying the encoded reply
+fn decide(reply: Reply, body: Option<&str>, options: &RequestOptions, signing_key: Option<&response_signing::SigningKey>) -> AccessDecision {
+    if reply.is_ok() && options.allow_on_success() {
+        return AccessDecision::Allow;
+    }
+    AccessDecision::Deny(encode(reply, body, options, signing_key))
+}
+
+/// Check the request's `auth` when the tenant's configuration has a shared
+/// secret (see `request_auth`)
+fn authenticate(body: Option<&str>) -> ProvisionResult<()> {
+    match (config()?.request_auth_secret, body) {
+        (Some(secret), Some(body)) => request_auth::verify_request(body, &secret),
+        _ => Ok(()),
+    }
+}
+
+/// Keep the request's writes in memory when the tenant's configuration names
+/// this build as the shadow build (see `shadow`). `read-only` builds always
+/// do: reads that complete a half-written store (`txn`) or materialize
+/// inherited mappings then work on the completed view without writing it.
+fn enter_shadow() -> ProvisionResult<()> {
+    let shadow = cfg!(feature = "read-only")
+        || shadow::is_shadow_build(config()?.shadow_build.as_deref(), health::version().git_sha.as_deref());
+    SHADOW.set(shadow.then(ShadowWrites::new));
+    Ok(())
+}
+
+/// Run a data action
+fn dispatch(request: &AccessRequest, policy_req: PolicyRequest) -> Reply {
+    let requester = requester(request);
+    if cfg!(feature = "read-only") && !READ_ONLY_ACTIONS.contains(&policy_req.action()) {
+        return Err(ProvisionError::InvalidRequest(format!("this build only serves {}", READ_ONLY_ACTIONS.join(", "))));
+    }
+    authorize(&requester, policy_req.action())?;
     
-    let response_json = match policy_req {
-        PolicyRequest::Store { solana_pubkey, chain_ids, evm_address } => {
-            match handle_store(solana_pubkey, chain_ids, evm_address) {
-                Ok(res) => serde_json::to_string(&res).unwrap(),
-                Err(e) => serde_json::to_string(&ErrorResponse {
-                    success: false,
-                    error: e,
-                }).unwrap(),
+    match policy_req {
+        PolicyRequest::Store { solana_pubkey, chain_ids, evm_address, key_id, message, signature, label, key_type, key_class, ttl_secs, idempotency_key, dry_run } => {
+            let actor = solana_pubkey.to_string();
+            let req = ProvisionRequest {
+                solana_pubkey,
+                chain_ids,
+                message,
+                signature,
+                label,
+                key_type,
+                key_class,
+                ttl_secs,
+                idempotency_key: None,
+                request_id: None,
+            };
+            if dry_run {
+                return respond(dry_run::run(&mappings(), |kv| {
+                    Ok(store_mappings(kv, &authorized(kv, req)?, evm_address, key_id)?.0)
+                }));
             }
+            // Hashed as sent, so a retry replays even if the default chains changed since
+            let hash = idempotency::request_hash(&(&req, &evm_address, &key_id));
+            let result = idempotent("store", idempotency_key.as_deref(), &hash, || {
+                rate_limited_if_proven("store", &req.solana_pubkey, store_proof(&req))?;
+                let req = authorized(&mappings(), req)?;
+                audited("store", &actor, &actor, handle_store(req, evm_address, key_id))
+            });
+            // A replayed response may hold an address frozen since it was recorded
+            respond(result.and_then(|response| mapping::require_not_frozen(&mappings(), &response).map(|()| response)))
         }
         
+        PolicyRequest::ProvisionAsync { solana_pubkey, chain_ids, message, signature, label, key_type, key_class } => {
+            let req = ProvisionRequest {
+                solana_pubkey,
+                chain_ids,
+                message,
+                signature,
+                label,
+                key_type,
+                key_class,
+                ttl_secs: None,
+                idempotency_key: None,
+                request_id: None,
+            };
+            let proof = store_proof(&req);
+            respond(rate_limited_if_proven("provision_async", &req.solana_pubkey, proof).and_then(|()| {
+                let req = authorized(&mappings(), req)?;
+                network::require_chains(&mappings(), network(), &req.chain_ids)?;
+                jobs::submit(&mappings(), req, now_secs())
+            }))
+        }
+
+        PolicyRequest::JobStatus { solana_pubkey, job_id } => {
+            respond(jobs::require(&mappings(), &solana_pubkey, job_id))
+        }
+
         PolicyRequest::Get { solana_pubkey, chain_ids } => {
-            match handle_get(solana_pubkey, chain_ids) {
-                Ok(res) => serde_json::to_string(&res).unwrap(),
-                Err(e) => serde_json::to_string(&ErrorResponse {
-                    success: false,
-                    error: e,
-                }).unwrap(),
+            respond(config().and_then(|config| {
+                if config.materialize_inherited {
+                    mapping::get_materialized(&mappings(), &solana_pubkey, &chain_ids, now_secs())
+                } else {
+                    mapping::get(&mappings(), &solana_pubkey, &chain_ids, now_secs())
+                }
+            }))
+        }
+        
+        PolicyRequest::ProposeUpdate { solana_pubkey, chain_id, new_evm_address, new_key_id, allow_shared_address } => {
+            let subject = solana_pubkey.to_string();
+            let result = handle_propose_update(&mappings(), &requester, solana_pubkey, chain_id, new_evm_address, new_key_id, allow_shared_address);
+            respond(audited("propose_update", requester.name(), &subject, result))
+        }
+        
+        PolicyRequest::ApproveUpdate { solana_pubkey, chain_id, proposal_id, dry_run: true, .. } => {
+            respond(dry_run::run(&mappings(), |kv| handle_approve_update(kv, &requester, solana_pubkey, chain_id, proposal_id)))
+        }
+
+        PolicyRequest::ApproveUpdate { solana_pubkey, chain_id, proposal_id, idempotency_key, dry_run: false } => {
+            let subject = solana_pubkey.to_string();
+            let hash = idempotency::request_hash(&(&requester.identity, &solana_pubkey, &chain_id, proposal_id));
+            respond(idempotent("approve_update", idempotency_key.as_deref(), &hash, || {
+                let result = handle_approve_update(&mappings(), &requester, solana_pubkey, chain_id, proposal_id);
+                audited("approve_update", requester.name(), &subject, result)
+            }))
+        }
+        
+        PolicyRequest::UpdateBatch { updates, all_or_nothing } => {
+            respond(handle_update_batch(&requester, updates, all_or_nothing))
+        }
+
+        PolicyRequest::RejectUpdate { solana_pubkey, chain_id, proposal_id } => {
+            let subject = solana_pubkey.to_string();
+            let result = handle_reject_update(&requester, solana_pubkey, chain_id, proposal_id);
+            respond(audited("reject_update", requester.name(), &subject, result))
+        }
+        
+        PolicyRequest::GetPending { solana_pubkey, chain_id } => {
+            respond(approval::get_pending(&mappings(), &solana_pubkey, &chain_id).map(|pending| PendingResponse { pending }))
+        }
+        
+        PolicyRequest::AddAdmin { identity } => {
+            let subject = identity.clone();
+            let result = handle_set_admin(&requester, identity, true);
+            respond(audited("add_admin", requester.name(), &subject, result))
+        }
+        
+        PolicyRequest::RemoveAdmin { identity } => {
+            let subject = identity.clone();
+            let result = handle_set_admin(&requester, identity, false);
+            respond(audited("remove_admin", requester.name(), &subject, result))
+        }
+        
+        PolicyRequest::UpdateSelf { solana_pubkey, chain_id, new_evm_address, new_key_id, nonce, expires_at, signature, idempotency_key } => {
+            let actor = solana_pubkey.to_string();
+            let hash = idempotency::request_hash(&(&solana_pubkey, &chain_id, &new_evm_address, &new_key_id, &nonce, expires_at, &signature));
+            respond(idempotent("update_self", idempotency_key.as_deref(), &hash, || {
+                let message = auth::update_self_address_message(&solana_pubkey, &chain_id, &new_evm_address, &new_key_id, &nonce, expires_at);
+                let proof = mapping::verify_update_self(&solana_pubkey, &message, &nonce, expires_at, &signature, now_secs());
+                rate_limited_if_proven("update_self", &solana_pubkey, proof)?;
+                let result = handle_update_self(solana_pubkey, chain_id, new_evm_address, new_key_id, nonce, expires_at, signature);
+                audited("update_self", &actor, &actor, result)
+            }))
+        }
+        
+        PolicyRequest::LinkExternal { request } => {
+            let actor = request.solana_pubkey.to_string();
+            let proof = config().and_then(|config| address_sanity::check(&request.evm_address, &config.denied_addresses)).and_then(|()| mapping::verify_link_external(&request, now_secs()));
+            respond(
+                rate_limited_if_proven("link_external", &request.solana_pubkey, proof)
+                    .and_then(|()| audited("link_external", &actor, &actor, handle_link_external(request))),
+            )
+        }
+
+        PolicyRequest::StoreBatch { requests, dry_run: true } => respond(dry_run_store_batch(requests)),
+
+        PolicyRequest::StoreBatch { requests, dry_run: false } => {
+            respond(handle_store_batch(requests))
+        }
+        
+        PolicyRequest::History { solana_pubkey, chain_id } => {
+            respond(mapping::history(&mappings(), &solana_pubkey, &chain_id))
+        }
+        
+        PolicyRequest::List { solana_pubkey } => {
+            respond(mapping::list(&mappings(), &solana_pubkey))
+        }
+        
+        PolicyRequest::StoreEvmToSolana { evm_address, solana_pubkey, key_id, message, signature } => {
+            let actor = evm_address.to_string();
+            let req = EvmToSolanaProvisionRequest { evm_address, message, signature, request_id: None };
+            respond(audited("store_evm_to_solana", &actor, &actor, handle_store_evm_to_solana(req, solana_pubkey, key_id)))
+        }
+        
+        PolicyRequest::GetEvmToSolana { evm_address } => {
+            respond(handle_get_evm_to_solana(evm_address))
+        }
+        
+        PolicyRequest::ReverseGet { evm_address } => {
+            respond(handle_reverse_get(evm_address))
+        }
+
+        PolicyRequest::GetRetirement { evm_address } => {
+            respond(handle_get_retirement(evm_address))
+        }
+        
+        PolicyRequest::SetChain { chain_id, enabled, name, testnet } => {
+            let subject = chain_id.to_string();
+            let action = if enabled { "enable_chain" } else { "disable_chain" };
+            let result = handle_set_chain(&requester, chain_id, enabled, name, testnet);
+            respond(audited(action, requester.name(), &subject, result))
+        }
+        
+        PolicyRequest::ListChains => {
+            respond(chains::list_chains(&mappings()).map(|chains| ListChainsResponse { chains }))
+        }
+
+        PolicyRequest::Stats => {
+            respond(metrics::get_stats(&bucket(METRICS_BUCKET)))
+        }
+
+        PolicyRequest::UsageReport { month } => {
+            respond(usage::get_usage(&bucket(USAGE_BUCKET), &month))
+        }
+
+        PolicyRequest::Ping => respond(health::ping(&bucket(METRICS_BUCKET), now_secs())),
+
+        PolicyRequest::Version => respond(Ok(health::version())),
+
+        PolicyRequest::GetConfig => respond(require_admin(&requester).and_then(|()| config()).map(Config::redacted)),
+
+        PolicyRequest::SetConfig { config } => {
+            let result = handle_set_config(&requester, config);
+            respond(audited("set_config", requester.name(), "", result))
+        }
+
+        PolicyRequest::GetTenantMembers { tenant_id } => {
+            respond(handle_get_tenant_members(&requester, &tenant_id))
+        }
+
+        PolicyRequest::SetTenantMembers { tenant_id, members } => {
+            let result = handle_set_tenant_members(&requester, &tenant_id, members);
+            respond(audited("set_tenant_members", requester.name(), &tenant_id, result))
+        }
+        
+        PolicyRequest::Migrate { cursor, limit } => {
+            let subject = cursor.clone().unwrap_or_default();
+            let result = handle_migrate(&requester, cursor, limit);
+            respond(audited("migrate", requester.name(), &subject, result))
+        }
+
+        PolicyRequest::Sweep { cursor, limit } => {
+            let subject = cursor.clone().unwrap_or_default();
+            let result = handle_sweep(&requester, cursor, limit);
+            respond(audited("sweep", requester.name(), &subject, result))
+        }
+
+        PolicyRequest::Anonymize { solana_pubkey, salt } => {
+            // Audited under the pseudonym, so the log does not name the erased user
+            let subject = anonymize::pseudonym(&solana_pubkey, &salt);
+            let result = handle_anonymize(&requester, solana_pubkey, salt);
+            respond(audited("anonymize", requester.name(), &subject, result))
+        }
+
+        PolicyRequest::Export { cursor, limit } => respond(handle_export(&requester, cursor, limit)),
+
+        PolicyRequest::Verify { cursor, limit } => respond(handle_verify(&requester, cursor, limit)),
+
+        PolicyRequest::MigrateEnvironment { bucket, cursor, limit } => {
+            respond(handle_migrate_environment(&requester, bucket, cursor, limit))
+        }
+
+        PolicyRequest::MigrateHashedKeys { bucket, cursor, limit } => {
+            respond(handle_migrate_hashed_keys(&requester, bucket, cursor, limit))
+        }
+
+        PolicyRequest::EncryptValues { bucket, cursor, limit } => {
+            respond(handle_encrypt_values(&requester, bucket, cursor, limit))
+        }
+
+        PolicyRequest::Import { entries, strategy, dry_run } => {
+            let subject = entries.first().map(|entry| entry.key.clone()).unwrap_or_default();
+            let req = ImportRequest { entries, strategy, dry_run, request_id: None };
+            let result = handle_import(&requester, &req);
+            // Dry runs write nothing, so they are not audited
+            if dry_run {
+                respond(result)
+            } else {
+                respond(audited("import", requester.name(), &subject, result))
             }
         }
         
-        PolicyRequest::Update { solana_pubkey, chain_id, new_evm_address } => {
-            match handle_update(solana_pubkey, chain_id, new_evm_address) {
-                Ok(res) => serde_json::to_string(&res).unwrap(),
-                Err(e) => serde_json::to_string(&ErrorResponse {
-                    success: false,
-                    error: e,
-                }).unwrap(),
+        PolicyRequest::Repair { ids, dry_run } => {
+            let subject = ids.first().cloned().unwrap_or_default();
+            let req = RepairRequest { ids, dry_run, request_id: None };
+            let result = handle_repair(&requester, &req);
+            if dry_run {
+                respond(result)
+            } else {
+                respond(audited("repair", requester.name(), &subject, result))
             }
         }
-    };
-    
-    // Return response in Deny reason (this is a data policy, not signing)
-    Ok(AccessDecision::Deny(response_json))
+
+        PolicyRequest::Reconcile { keys, cursor, limit, repair } => {
+            let subject = cursor.clone().unwrap_or_default();
+            let req = ReconcileRequest { cursor, limit, repair, request_id: None };
+            let result = handle_reconcile(&requester, &keys, &req);
+            respond(audited("reconcile", requester.name(), &subject, result))
+        }
+        
+        PolicyRequest::Freeze { evm_address, reason } => {
+            let subject = evm_address.to_string();
+            let result = handle_set_frozen(&requester, evm_address, true, reason);
+            respond(audited("freeze", requester.name(), &subject, result))
+        }
+        
+        PolicyRequest::Unfreeze { evm_address } => {
+            let subject = evm_address.to_string();
+            let result = handle_set_frozen(&requester, evm_address, false, None);
+            respond(audited("unfreeze", requester.name(), &subject, result))
+        }
+        
+        PolicyRequest::SetSpendLimit { solana_pubkey, chain_id, spend_limit } => {
+            let subject = solana_pubkey.to_string();
+            let result = handle_set_spend_limit(&requester, solana_pubkey, chain_id, spend_limit);
+            respond(audited("set_spend_limit", requester.name(), &subject, result))
+        }
+
+        PolicyRequest::SetSigner { identity, solana_pubkey } => {
+            let subject = identity.clone();
+            let result = handle_set_signer(&requester, identity, solana_pubkey);
+            respond(audited("set_signer", requester.name(), &subject, result))
+        }
+
+        PolicyRequest::GetSpendLimit { solana_pubkey, chain_id } => {
+            respond(spend_limits::status(&mappings(), &solana_pubkey, chain_id.as_ref(), now_secs()))
+        }
+
+        PolicyRequest::AddAllowedDestination { solana_pubkey, chain_id, destination } => {
+            let subject = solana_pubkey.to_string();
+            let result = handle_set_allowed_destination(&requester, solana_pubkey, chain_id, destination, true);
+            respond(audited("add_allowed_destination", requester.name(), &subject, result))
+        }
+
+        PolicyRequest::RemoveAllowedDestination { solana_pubkey, chain_id, destination } => {
+            let subject = solana_pubkey.to_string();
+            let result = handle_set_allowed_destination(&requester, solana_pubkey, chain_id, destination, false);
+            respond(audited("remove_allowed_destination", requester.name(), &subject, result))
+        }
+
+        PolicyRequest::Block { target, reason } => {
+            let subject = target.key();
+            let result = handle_set_blocked(&requester, target, true, reason);
+            respond(audited("block", requester.name(), &subject, result))
+        }
+        
+        PolicyRequest::Unblock { target } => {
+            let subject = target.key();
+            let result = handle_set_blocked(&requester, target, false, None);
+            respond(audited("unblock", requester.name(), &subject, result))
+        }
+        
+        PolicyRequest::AuditQuery { from, to, after_seq, limit } => {
+            respond(audit::query(&mappings(), &AuditQuery { from, to, after_seq, limit }))
+        }
+
+        PolicyRequest::PollEvents { after_seq, limit } => respond(events::poll(&mappings(), after_seq, limit)),
+
+        PolicyRequest::MerkleRoot => respond(merkle::root(&mappings())),
+
+        PolicyRequest::MerkleProof { solana_pubkey, chain_id } => {
+            respond(merkle::proof(&mappings(), &solana_pubkey, &chain_id))
+        }
+    }
+}
+
+// =============================================================================
+// SIGNING GATE (`signing-gate` feature)
+// =============================================================================
+
+/// What the signing request asks for, read only from what CubeSigner
+/// authenticates: the requester's identity, the key it signs with and the
+/// request it signs (see `signing_gate::signing_request`). Nothing the
+/// requester merely claims decides the answer.
+#[cfg(feature = "signing-gate")]
+fn signing_request(request: &AccessRequest) -> ProvisionResult<SigningRequest> {
+    let key_id = request
+        .key_id
+        .as_deref()
+        .ok_or_else(|| ProvisionError::InvalidRequest("not a signing request: no key".to_string()))?;
+    signing_gate::signing_request(&mappings(), request.identity.as_deref().unwrap_or_default(), key_id, request.request.as_deref())
+}
+
+#[cfg(feature = "signing-gate")]
+#[policy]
+async fn main(request: AccessRequest) -> Result<AccessDecision> {
+    let decision = enter_tenant(&request)
+        .and_then(|()| signing_request(&request))
+        .and_then(|req| signing_gate::authorize(&mappings(), &req, now_secs()));
+    Ok(match decision {
+        Ok(()) => AccessDecision::Allow,
+        Err(e) => AccessDecision::Deny(error_response(&e)),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAPPING: &str = r#"{"default_address":"0x1111111111111111111111111111111111111111","chain_mappings":{"1":"0x2222222222222222222222222222222222222222"}}"#;
+
+    /// The decision `main` takes for a successful `body` answered with `MAPPING`
+    fn decide_success(body: &str) -> AccessDecision {
+        let options: RequestOptions = serde_json::from_str(body).unwrap();
+        let payload = RawValue::from_string(MAPPING.to_string()).unwrap();
+        decide(Ok(payload), Some(body), &options, None)
+    }
+
+    /// The reply a `Deny` carries, or a panic for a payload-free `Allow`
+    fn reply(decision: AccessDecision) -> serde_json::Value {
+        match decision {
+            AccessDecision::Deny(reply) => serde_json::from_str(&reply).unwrap(),
+            _ => panic!("the decision carries no reply"),
+        }
+    }
+
+    #[test]
+    fn test_default_get_returns_its_mapping() {
+        let body = r#"{"action":"get","solana_pubkey":"11111111111111111111111111111111","chain_ids":[1]}"#;
+        let reply = reply(decide_success(body));
+        assert_eq!(reply["outcome"], "success");
+        assert_eq!(reply["payload"], serde_json::from_str::<serde_json::Value>(MAPPING).unwrap());
+    }
+
+    #[test]
+    fn test_read_only_actions_return_their_payload_by_default() {
+        // Everything a `read-only` build serves is a read, so none of it may
+        // come back as a bare `Allow`
+        for action in READ_ONLY_ACTIONS {
+            let reply = reply(decide_success(&format!(r#"{{"action":"{}"}}"#, action)));
+            assert_eq!(reply["payload"]["chain_mappings"]["1"], "0x2222222222222222222222222222222222222222", "{}", action);
+        }
+    }
+
+    #[test]
+    fn test_allow_on_success_is_opt_in() {
+        assert!(matches!(decide_success(r#"{"action":"get","allow_on_success":true}"#), AccessDecision::Allow));
+        // Legacy callers always get the flat body
+        let reply = reply(decide_success(r#"{"action":"get","allow_on_success":true,"legacy_response":true}"#));
+        assert_eq!(reply["success"], true);
+        assert_eq!(reply["default_address"], "0x1111111111111111111111111111111111111111");
+    }
 }
@@ -0,0 +1,193 @@
+//! Address Validation
+//!
+//! Both address kinds end up inside KV keys, so they are validated before
+//! anything is read or written.
+//!
+//! - Solana: base58, exactly 32 bytes (this also rules out `:` and other
+//!   characters that would corrupt the `{solana_pubkey}:{chain_id}` key format)
+//! - EVM: `0x` + 40 hex digits, either all-lowercase/all-uppercase (no
+//!   checksum) or mixed-case with a valid EIP-55 checksum. Stored lowercase
+//!   so mixed-case inputs for the same address map to the same KV entries,
+//!   returned in EIP-55 checksummed form.
+//!
+//! `SolanaPubkey` and `EvmAddress` carry these guarantees in the type, so
+//! KV helpers and request/response types cannot be handed unvalidated strings.
+
+use crate::error::{ProvisionError, Result};
+use serde::{Deserialize, Serialize};
+use sha3::{Digest, Keccak256};
+use std::fmt;
+use std::str::FromStr;
+
+/// Decode a base58 Solana public key, requiring exactly 32 bytes
+pub fn decode_solana_pubkey(solana_pubkey: &str) -> Result<[u8; 32]> {
+    bs58::decode(solana_pubkey)
+        .into_vec()
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or_else(|| ProvisionError::InvalidSolanaPubkey(solana_pubkey.to_string()))
+}
+
+/// Validate an EVM address and return its normalized (lowercase) form
+pub fn normalize_evm_address(address: &str) -> Result<String> {
+    let hex = address
+        .strip_prefix("0x")
+        .filter(|hex| hex.len() == 40 && hex.bytes().all(|b| b.is_ascii_hexdigit()))
+        .ok_or_else(|| ProvisionError::InvalidEvmAddress(address.to_string()))?;
+
+    let has_lower = hex.bytes().any(|b| b.is_ascii_lowercase());
+    let has_upper = hex.bytes().any(|b| b.is_ascii_uppercase());
+    if has_lower && has_upper && to_checksum_address(address) != address {
+        return Err(ProvisionError::InvalidChecksum(address.to_string()));
+    }
+
+    Ok(format!("0x{}", hex.to_ascii_lowercase()))
+}
+
+/// EIP-55 checksummed form of a (well-formed) address
+pub fn to_checksum_address(address: &str) -> String {
+    let hex = address.trim_start_matches("0x").to_ascii_lowercase();
+    let hash = Keccak256::digest(hex.as_bytes());
+
+    let checksummed: String = hex
+        .chars()
+        .enumerate()
+        .map(|(i, c)| {
+            let nibble = (hash[i / 2] >> if i % 2 == 0 { 4 } else { 0 }) & 0x0f;
+            if nibble >= 8 {
+                c.to_ascii_uppercase()
+            } else {
+                c
+            }
+        })
+        .collect();
+
+    format!("0x{}", checksummed)
+}
+
+// =============================================================================
+// TYPES
+// =============================================================================
+
+/// A validated Solana public key (base58, 32 bytes)
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct SolanaPubkey(String);
+
+impl SolanaPubkey {
+    pub fn parse(solana_pubkey: &str) -> Result<Self> {
+        decode_solana_pubkey(solana_pubkey)?;
+        Ok(Self(solana_pubkey.to_string()))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    pub fn to_bytes(&self) -> [u8; 32] {
+        decode_solana_pubkey(&self.0).expect("SolanaPubkey is validated on construction")
+    }
+}
+
+/// A validated EVM address.
+///
+/// Held lowercase (`as_str`, used for KV keys and values); `Display` and
+/// `Serialize` produce the EIP-55 checksummed form.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct EvmAddress(String);
+
+impl EvmAddress {
+    pub fn parse(address: &str) -> Result<Self> {
+        normalize_evm_address(address).map(Self)
+    }
+
+    /// Lowercase form (storage format)
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// EIP-55 checksummed form (response format)
+    pub fn to_checksum(&self) -> String {
+        to_checksum_address(&self.0)
+    }
+}
+
+macro_rules! string_newtype_impls {
+    ($ty:ident, $display:expr) => {
+        impl fmt::Display for $ty {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str(&$display(self))
+            }
+        }
+
+        impl FromStr for $ty {
+            type Err = ProvisionError;
+
+            fn from_str(s: &str) -> Result<Self> {
+                Self::parse(s)
+            }
+        }
+
+        impl TryFrom<String> for $ty {
+            type Error = ProvisionError;
+
+            fn try_from(s: String) -> Result<Self> {
+                Self::parse(&s)
+            }
+        }
+
+        impl From<$ty> for String {
+            fn from(value: $ty) -> String {
+                value.to_string()
+            }
+        }
+    };
+}
+
+string_newtype_impls!(SolanaPubkey, |pk: &SolanaPubkey| pk.0.clone());
+string_newtype_impls!(EvmAddress, |addr: &EvmAddress| addr.to_checksum());
+
+#[cfg(feature = "openapi")]
+impl schemars::JsonSchema for SolanaPubkey {
+    fn schema_name() -> std::borrow::Cow<'static, str> {
+        "SolanaPubkey".into()
+    }
+
+    fn json_schema(_: &mut schemars::SchemaGenerator) -> schemars::Schema {
+        schemars::json_schema!({
+            "type": "string",
+            "description": "Base58-encoded 32-byte Solana public key",
+        })
+    }
+}
+
+#[cfg(feature = "openapi")]
+impl schemars::JsonSchema for EvmAddress {
+    fn schema_name() -> std::borrow::Cow<'static, str> {
+        "EvmAddress".into()
+    }
+
+    fn json_schema(_: &mut schemars::SchemaGenerator) -> schemars::Schema {
+        schemars::json_schema!({
+            "type": "string",
+            "pattern": "^0x[0-9a-fA-F]{40}$",
+            "description": "EVM address; any case is accepted, responses use the EIP-55 checksummed form",
+        })
+    }
+}
+
+/// Serde adapter writing an `EvmAddress` in its lowercase storage form
+/// (`#[serde(with = "crate::address::lowercase")]`)
+pub mod lowercase {
+    use super::EvmAddress;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(address: &EvmAddress, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(address.as_str())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<EvmAddress, D::Error> {
+        EvmAddress::deserialize(deserializer)
+    }
+}
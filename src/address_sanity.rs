@@ -0,0 +1,90 @@
+//! Address Sanity
+//!
+//! Some addresses are valid but can never be a user's wallet: nobody holds a
+//! key for them, so whatever is sent there is lost. Buggy backend scripts
+//! have submitted them, so stores, updates and external links refuse EVM
+//! addresses that are:
+//!
+//! - the zero address
+//! - precompiles: `0x01`-`0x11` on Ethereum, and `0x100`-`0x1ff`, the range
+//!   EIP-7587 reserves for L2 precompiles (e.g. P-256 verification at `0x100`)
+//! - common burn addresses (`BURN_ADDRESSES`)
+//! - on an extra deny list configured by admins (`denied_addresses` in the
+//!   config, `Provisioner::with_denied_addresses` in the library)
+//!
+//! Likewise, stores and provisioning jobs refuse a Solana address that is a
+//! program rather than a user: a well-known program id (`PROGRAM_IDS`) or an
+//! address off the ed25519 curve, as program derived addresses are. Admins
+//! can lift this check (`allow_program_pubkeys`); the ownership proof still
+//! has to verify, which an off-curve address never does.
+//!
+//! Mappings written before an address was denied stay as they are; `verify`
+//! does not look for them.
+
+use crate::address::{EvmAddress, SolanaPubkey};
+use crate::error::{ProvisionError, Result};
+use ed25519_dalek::VerifyingKey;
+
+/// Highest address treated as a precompile (the end of the EIP-7587 range)
+const MAX_PRECOMPILE: u64 = 0x1ff;
+
+/// Burn addresses in common use, lowercase
+pub const BURN_ADDRESSES: [&str; 2] = [
+    "0x000000000000000000000000000000000000dead",
+    "0xdead000000000000000042069420694206942069",
+];
+
+/// Programs every cluster has, by id
+pub const PROGRAM_IDS: [&str; 7] = [
+    "11111111111111111111111111111111",
+    "TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA",
+    "ATokenGPvbdGVxr1b2hvZbsiqW5xWH25efTNsLJA8knL",
+    "BPFLoaderUpgradeab1e11111111111111111111111",
+    "ComputeBudget111111111111111111111111111111",
+    "Stake11111111111111111111111111111111111111",
+    "Vote111111111111111111111111111111111111111",
+];
+
+/// Fail with `UnusableAddress` if `evm_address` is the zero address, a
+/// precompile, a burn address or in `denied`
+pub fn check(evm_address: &EvmAddress, denied: &[EvmAddress]) -> Result<()> {
+    let unusable = |reason| Err(ProvisionError::UnusableAddress { evm_address: evm_address.to_string(), reason });
+    match low_value(evm_address) {
+        Some(0) => return unusable("the zero address"),
+        Some(1..=MAX_PRECOMPILE) => return unusable("a precompile"),
+        _ => {}
+    }
+    if BURN_ADDRESSES.contains(&evm_address.as_str()) {
+        return unusable("a burn address");
+    }
+    if denied.contains(evm_address) {
+        return unusable("on the deny list");
+    }
+    Ok(())
+}
+
+/// Value of the address if it fits in its last 8 bytes
+fn low_value(evm_address: &EvmAddress) -> Option<u64> {
+    let hex = evm_address.as_str().trim_start_matches("0x");
+    let (high, low) = hex.split_at(hex.len() - 16);
+    if high.bytes().any(|b| b != b'0') {
+        return None;
+    }
+    u64::from_str_radix(low, 16).ok()
+}
+
+/// Fail with `UnusablePubkey` if `solana_pubkey` is a well-known program or
+/// off the ed25519 curve, unless `allow_programs`
+pub fn check_solana_pubkey(solana_pubkey: &SolanaPubkey, allow_programs: bool) -> Result<()> {
+    if allow_programs {
+        return Ok(());
+    }
+    let unusable = |reason| Err(ProvisionError::UnusablePubkey { solana_pubkey: solana_pubkey.to_string(), reason });
+    if PROGRAM_IDS.contains(&solana_pubkey.as_str()) {
+        return unusable("a program");
+    }
+    if VerifyingKey::from_bytes(&solana_pubkey.to_bytes()).is_err() {
+        return unusable("off the ed25519 curve (a program derived address)");
+    }
+    Ok(())
+}
@@ -0,0 +1,100 @@
+//! Admin Allowlist
+//!
+//! Admin-only actions (per-chain updates, ...) are only executed for
+//! identities listed in the `admins` bucket. The allowlist itself is managed
+//! by org owners.
+//!
+//! ## Key Schema (`admins` bucket)
+//! ```text
+//! {identity} → AdminEntry   # Never deleted; removal sets `active: false`
+//! ```
+
+use crate::kv::KvStore;
+use crate::error::{ProvisionError, Result};
+use serde::{Deserialize, Serialize};
+
+/// Bucket holding the admin allowlist
+pub const ADMINS_BUCKET: &str = "admins";
+
+/// Org role allowed to manage the admin allowlist
+pub const ORG_OWNER_ROLE: &str = "Owner";
+
+/// Identity prefix of role sessions, which is how service accounts such as
+/// the backend authenticate (people authenticate as `User#…`)
+pub const SERVICE_ACCOUNT_PREFIX: &str = "Role#";
+
+/// Identity recorded when a requester has none
+pub const UNKNOWN_REQUESTER: &str = "unknown";
+
+/// Who is invoking an action, as authenticated by CubeSigner
+#[derive(Debug, Clone, PartialEq)]
+pub struct Requester {
+    /// CubeSigner identity of the session (e.g. user id or email)
+    pub identity: String,
+    /// Whether the session belongs to an org owner
+    pub is_org_owner: bool,
+    /// Whether the session is a service account (e.g. the backend) rather than a person
+    pub is_service_account: bool,
+}
+
+impl Requester {
+    /// The requester behind a session CubeSigner (or a proxy that
+    /// authenticated it) reports with `identity` and `org_role`
+    pub fn new(identity: Option<&str>, org_role: Option<&str>) -> Self {
+        Self {
+            identity: identity.unwrap_or_default().to_string(),
+            is_org_owner: org_role == Some(ORG_OWNER_ROLE),
+            is_service_account: identity.is_some_and(|identity| identity.starts_with(SERVICE_ACCOUNT_PREFIX)),
+        }
+    }
+
+    /// Identity for error messages and the audit log
+    pub fn name(&self) -> &str {
+        if self.identity.is_empty() { UNKNOWN_REQUESTER } else { &self.identity }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct AdminEntry {
+    pub active: bool,
+    /// Owner who last added/removed this admin
+    pub updated_by: String,
+    /// Unix timestamp (seconds)
+    pub updated_at: u64,
+}
+
+pub fn get_admin(kv: &impl KvStore, identity: &str) -> Result<Option<AdminEntry>> {
+    kv.get(identity)?
+        .map(|raw| serde_json::from_str(&raw).map_err(|e| ProvisionError::corrupt("admin entry", e)))
+        .transpose()
+}
+
+pub fn is_admin(kv: &impl KvStore, identity: &str) -> Result<bool> {
+    Ok(get_admin(kv, identity)?.is_some_and(|entry| entry.active))
+}
+
+/// Fail unless `identity` is an active admin
+pub fn require_admin(kv: &impl KvStore, identity: &str) -> Result<()> {
+    if !is_admin(kv, identity)? {
+        return Err(ProvisionError::NotAdmin(identity.to_string()));
+    }
+    Ok(())
+}
+
+/// Add or remove an admin. Only org owners may change the allowlist.
+pub fn set_admin(kv: &impl KvStore, requester: &Requester, identity: &str, active: bool, now: u64) -> Result<()> {
+    if !requester.is_org_owner {
+        return Err(ProvisionError::NotOrgOwner);
+    }
+    if identity.is_empty() {
+        return Err(ProvisionError::InvalidRequest("identity cannot be empty".to_string()));
+    }
+
+    let entry = AdminEntry {
+        active,
+        updated_by: requester.identity.clone(),
+        updated_at: now,
+    };
+    let raw = serde_json::to_string(&entry).expect("admin entry serialization cannot fail");
+    kv.set(identity, &raw)
+}
@@ -0,0 +1,233 @@
+//! Right to Erasure
+//!
+//! A user may ask for their data to be erased, but the mappings bucket is
+//! append-mostly by design: other users rely on an EVM address never being
+//! handed to someone else, and the C2F bucket cannot delete keys. So instead
+//! of deleting, `anonymize` overwrites what links the user's Solana address to
+//! their EVM addresses:
+//!
+//! - default, chain and labeled mapping records become tombstones naming only
+//!   a pseudonym, `anon:{sha256(salt:solana_pubkey)}`. Reading one fails with
+//!   `Anonymized`, so the address can never be provisioned again
+//! - the reverse entries of every address the user held (current, rotated
+//!   away from, labeled) keep the address reserved, under the pseudonym
+//! - chain histories and write journals are emptied, and the user's
+//!   retirement records become tombstones
+//!
+//! The salt comes with the request and is never stored: whoever keeps it can
+//! show that a pseudonym was the user's, nobody else can link it back. The
+//! audit log and event feed stay as they are, the record of what happened.
+//! Keys still contain the Solana address; only their values are replaced.
+//!
+//! ## Key Schema
+//! ```text
+//! anonymized:{pseudonym} → DeletionReceipt   # Overwritten by a retry
+//! ```
+
+use crate::address::{EvmAddress, SolanaPubkey};
+use crate::error::{ProvisionError, Result};
+use crate::kv::{self, KvStore, MappingRecord};
+use crate::labels;
+use crate::retirement;
+use crate::txn;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeSet;
+
+/// Prefix of every pseudonym
+pub const PSEUDONYM_PREFIX: &str = "anon:";
+
+/// Shortest accepted salt
+pub const MIN_SALT_LEN: usize = 16;
+
+/// Longest accepted salt
+pub const MAX_SALT_LEN: usize = 256;
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct AnonymizeRequest {
+    pub solana_pubkey: SolanaPubkey,
+    /// Secret mixed into the pseudonym; keep it to prove the erasure later
+    pub salt: String,
+    /// Correlation id carried into log events (see `logging`)
+    #[serde(default)]
+    pub request_id: Option<String>,
+}
+
+/// Proof of an erasure, returned to the caller and kept under the pseudonym
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct DeletionReceipt {
+    pub pseudonym: String,
+    /// Unix timestamp (seconds)
+    pub anonymized_at: u64,
+    pub anonymized_by: String,
+    /// Mapping, history, journal and retirement records overwritten
+    pub records: usize,
+    /// EVM addresses kept reserved in the reverse index under the pseudonym
+    pub reserved_addresses: usize,
+}
+
+/// What is left of an erased record
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Tombstone {
+    anonymized: String,
+}
+
+/// Key of the receipt of an erasure: `anonymized:{pseudonym}`
+pub fn receipt_key(pseudonym: &str) -> String {
+    format!("anonymized:{}", pseudonym)
+}
+
+/// Pseudonym of `solana_pubkey` under `salt`
+pub fn pseudonym(solana_pubkey: &SolanaPubkey, salt: &str) -> String {
+    let digest = Sha256::digest(format!("{}:{}", salt, solana_pubkey.as_str()).as_bytes());
+    let hex: String = digest.iter().map(|b| format!("{:02x}", b)).collect();
+    format!("{}{}", PSEUDONYM_PREFIX, hex)
+}
+
+/// Whether a reverse entry's owner is a pseudonym rather than a Solana address
+pub fn is_pseudonym(owner: &str) -> bool {
+    owner.starts_with(PSEUDONYM_PREFIX)
+}
+
+/// Pseudonym named by a tombstone, `None` if `raw` is not one
+pub fn tombstone_pseudonym(raw: &str) -> Option<String> {
+    serde_json::from_str::<Tombstone>(raw).ok().map(|tombstone| tombstone.anonymized)
+}
+
+/// Whether `raw` is a tombstone left by `anonymize`
+pub fn is_tombstone(raw: &str) -> bool {
+    tombstone_pseudonym(raw).is_some()
+}
+
+/// `Anonymized` for a tombstone; `None` if `raw` is not one
+pub fn refuse_tombstone(raw: &str) -> Option<ProvisionError> {
+    tombstone_pseudonym(raw).map(|pseudonym| ProvisionError::Anonymized { pseudonym })
+}
+
+/// The receipt of an earlier erasure
+pub fn get_receipt(kv: &impl KvStore, pseudonym: &str) -> Result<Option<DeletionReceipt>> {
+    kv.get(&receipt_key(pseudonym))?
+        .map(|raw| serde_json::from_str(&raw).map_err(|e| ProvisionError::corrupt("deletion receipt", e)))
+        .transpose()
+}
+
+/// Erase the mappings of `solana_pubkey`. A retry (with any salt) returns the
+/// receipt of the first erasure; a run cut short is completed by the next one.
+pub fn anonymize(kv: &impl KvStore, solana_pubkey: &SolanaPubkey, salt: &str, actor: &str, now: u64) -> Result<DeletionReceipt> {
+    if !(MIN_SALT_LEN..=MAX_SALT_LEN).contains(&salt.len()) {
+        return Err(ProvisionError::InvalidRequest(format!(
+            "salt must be between {} and {} characters",
+            MIN_SALT_LEN, MAX_SALT_LEN
+        )));
+    }
+    let default_key = kv::default_key(solana_pubkey);
+    let Some(raw_default) = kv.get(&default_key)? else {
+        return Err(ProvisionError::NotProvisioned(solana_pubkey.to_string()));
+    };
+    if let Some(pseudonym) = tombstone_pseudonym(&raw_default) {
+        return get_receipt(kv, &pseudonym)?.ok_or(ProvisionError::Anonymized { pseudonym });
+    }
+
+    // Finish a store still in flight, so replaying its journal cannot bring a mapping back
+    txn::recover(kv, solana_pubkey)?;
+    let pseudonym = pseudonym(solana_pubkey, salt);
+    let tombstone = serde_json::to_string(&Tombstone { anonymized: pseudonym.clone() }).expect("tombstone serialization cannot fail");
+
+    let chain_ids = kv::get_chain_index(kv, solana_pubkey)?;
+    let mut mapping_keys = vec![default_key.clone()];
+    mapping_keys.extend(chain_ids.iter().map(|chain_id| kv::chain_key(solana_pubkey, chain_id)));
+    let mut history_keys: Vec<String> = chain_ids.iter().map(|chain_id| kv::history_key(solana_pubkey, chain_id)).collect();
+    for (label, label_chains) in labels::get_label_index(kv, solana_pubkey)? {
+        mapping_keys.push(labels::label_key(solana_pubkey, &label));
+        mapping_keys.extend(label_chains.iter().map(|chain_id| labels::labeled_key(solana_pubkey, &label, chain_id)));
+        history_keys.extend(label_chains.iter().map(|chain_id| labels::labeled_history_key(solana_pubkey, &label, chain_id)));
+    }
+
+    // Every address the user held: current mappings and the chains' and labels' histories
+    let mut addresses = BTreeSet::new();
+    let mut erased = Vec::new();
+    for (key, raw) in mapping_keys.iter().zip(kv.get_many(&mapping_keys)?) {
+        let Some(raw) = raw else {
+            continue;
+        };
+        if !is_tombstone(&raw) {
+            addresses.insert(MappingRecord::decode(&raw)?.address);
+        }
+        erased.push(key.clone());
+    }
+    let mut histories = Vec::new();
+    for key in history_keys {
+        let history = kv::get_history_at(kv, &key)?;
+        if !history.is_empty() {
+            addresses.extend(history.into_iter().map(|entry| entry.address));
+            histories.push(key);
+        }
+    }
+
+    // Addresses first: a retry only finds them while the records above are intact
+    let mut records = erased.len() + histories.len();
+    let mut reserved_addresses = 0;
+    for address in &addresses {
+        reserved_addresses += reserve(kv, address, solana_pubkey, &pseudonym)? as usize;
+        match retirement::get_retirement(kv, address) {
+            Ok(Some(record)) if record.solana_pubkey == *solana_pubkey => {
+                kv.set(&retirement::retirement_key(address), &tombstone)?;
+                records += 1;
+            }
+            Ok(_) | Err(ProvisionError::Anonymized { .. }) => {}
+            Err(e) => return Err(e),
+        }
+    }
+    for key in &histories {
+        kv.set(key, "[]")?;
+    }
+    records += clear_journals(kv, solana_pubkey)?;
+
+    // The default goes last: once it is a tombstone, retries return the receipt
+    for key in erased.iter().filter(|key| **key != default_key) {
+        kv.set(key, &tombstone)?;
+    }
+    let receipt = DeletionReceipt {
+        pseudonym,
+        anonymized_at: now,
+        anonymized_by: actor.to_string(),
+        records,
+        reserved_addresses,
+    };
+    kv.set(&receipt_key(&receipt.pseudonym), &serde_json::to_string(&receipt).expect("receipt serialization cannot fail"))?;
+    kv.set(&default_key, &tombstone)?;
+    Ok(receipt)
+}
+
+/// Give `address` to the pseudonym in the reverse index unless it belongs to
+/// someone else.
+/// Returns whether it is now reserved under the pseudonym.
+fn reserve(kv: &impl KvStore, address: &EvmAddress, solana_pubkey: &SolanaPubkey, pseudonym: &str) -> Result<bool> {
+    let reverse_key = kv::reverse_key(address);
+    match kv.get(&reverse_key)? {
+        Some(owner) if owner == solana_pubkey.as_str() => {
+            kv.set(&reverse_key, pseudonym)?;
+            Ok(true)
+        }
+        Some(owner) => Ok(owner == pseudonym),
+        // Mapped before the reverse index existed
+        None => kv.set_if_absent(&reverse_key, pseudonym),
+    }
+}
+
+/// Drop the writes (and so the mapping records) kept in the user's journals.
+/// Returns how many journals were emptied.
+fn clear_journals(kv: &impl KvStore, solana_pubkey: &SolanaPubkey) -> Result<usize> {
+    let mut cleared = 0;
+    let mut id = 1;
+    while let Some(mut journal) = txn::get(kv, solana_pubkey, id)? {
+        if !journal.writes.is_empty() {
+            journal.writes.clear();
+            kv.set(&txn::journal_key(solana_pubkey, id), &serde_json::to_string(&journal).expect("journal serialization cannot fail"))?;
+            cleared += 1;
+        }
+        id += 1;
+    }
+    Ok(cleared)
+}
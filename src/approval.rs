@@ -0,0 +1,207 @@
+//! Two-Phase Approval
+//!
+//! Admin updates overwrite a user's receiving address, so they need two
+//! distinct admins: one proposes the update, another approves it before the
+//! chain mapping is touched. A proposal either names the new address (the
+//! policy, where the backend created the key) or leaves it to be created on
+//! approval (`Provisioner`). Either admin (or the proposer withdrawing) can
+//! reject it instead; an open proposal expires after `PENDING_UPDATE_TTL`.
+//!
+//! ## Key Schema
+//! ```text
+//! pending:{solana_pubkey}:{chain_id}       → PendingUpdate # Latest proposal for the chain
+//! pending:{solana_pubkey}:{chain_id}:{id}  → {proposer}    # Claimed with IfExists::Deny on propose
+//! resolved:{solana_pubkey}:{chain_id}:{id} → {status}      # Claimed with IfExists::Deny on approve/reject
+//! ```
+//!
+//! The claim keys make concurrent proposals and double approvals lose
+//! cleanly instead of both going through.
+
+use crate::address::{EvmAddress, SolanaPubkey};
+use crate::chain_id::ChainId;
+use crate::kv::KvStore;
+use crate::error::{ProvisionError, Result};
+use serde::{Deserialize, Serialize};
+
+/// Seconds a proposal stays open
+pub const PENDING_UPDATE_TTL: u64 = 24 * 60 * 60;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum PendingStatus {
+    Pending,
+    Approved,
+    Rejected,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PendingUpdate {
+    /// Per-chain proposal number, starting at 1
+    pub id: u64,
+    pub solana_pubkey: SolanaPubkey,
+    pub chain_id: ChainId,
+    /// Address to switch the chain to, when it exists before approval
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub new_evm_address: Option<EvmAddress>,
+    /// CubeSigner key id of `new_evm_address`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub new_key_id: Option<String>,
+    /// Admin override: `new_evm_address` may already belong to another user
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub allow_shared_address: bool,
+    pub proposed_by: String,
+    /// Unix timestamp (seconds)
+    pub proposed_at: u64,
+    /// Not approvable after this time (seconds)
+    pub expires_at: u64,
+    pub status: PendingStatus,
+    #[serde(default)]
+    pub resolved_by: Option<String>,
+    #[serde(default)]
+    pub resolved_at: Option<u64>,
+}
+
+impl PendingUpdate {
+    /// Still waiting for approval at `now`
+    pub fn is_open(&self, now: u64) -> bool {
+        self.status == PendingStatus::Pending && now <= self.expires_at
+    }
+}
+
+fn is_false(flag: &bool) -> bool {
+    !flag
+}
+
+/// Key of the latest proposal for a chain: `pending:{solana_pubkey}:{chain_id}`
+pub fn pending_key(solana_pubkey: &SolanaPubkey, chain_id: &ChainId) -> String {
+    format!("pending:{}:{}", solana_pubkey.as_str(), chain_id.key_segment())
+}
+
+fn proposal_claim_key(solana_pubkey: &SolanaPubkey, chain_id: &ChainId, id: u64) -> String {
+    format!("pending:{}:{}:{}", solana_pubkey.as_str(), chain_id.key_segment(), id)
+}
+
+fn resolution_claim_key(solana_pubkey: &SolanaPubkey, chain_id: &ChainId, id: u64) -> String {
+    format!("resolved:{}:{}:{}", solana_pubkey.as_str(), chain_id.key_segment(), id)
+}
+
+pub fn get_pending(kv: &impl KvStore, solana_pubkey: &SolanaPubkey, chain_id: &ChainId) -> Result<Option<PendingUpdate>> {
+    kv.get(&pending_key(solana_pubkey, chain_id))?
+        .map(|raw| serde_json::from_str(&raw).map_err(|e| ProvisionError::corrupt("pending update", e)))
+        .transpose()
+}
+
+/// Open a proposal for a chain. Fails while another proposal is still open.
+#[allow(clippy::too_many_arguments)]
+pub fn propose(
+    kv: &impl KvStore,
+    solana_pubkey: &SolanaPubkey,
+    chain_id: &ChainId,
+    new_evm_address: Option<&EvmAddress>,
+    new_key_id: Option<&str>,
+    allow_shared_address: bool,
+    proposer: &str,
+    now: u64,
+) -> Result<PendingUpdate> {
+    let previous = get_pending(kv, solana_pubkey, chain_id)?;
+    if let Some(open) = previous.as_ref().filter(|p| p.is_open(now)) {
+        return Err(ProvisionError::UpdatePending {
+            id: open.id,
+            solana_pubkey: solana_pubkey.to_string(),
+            chain_id: chain_id.to_string(),
+        });
+    }
+
+    let id = previous.map_or(1, |p| p.id + 1);
+    if !kv.set_if_absent(&proposal_claim_key(solana_pubkey, chain_id, id), proposer)? {
+        return Err(ProvisionError::KvConflict(format!(
+            "Another update for {} on chain {} was proposed concurrently",
+            solana_pubkey, chain_id
+        )));
+    }
+
+    let pending = PendingUpdate {
+        id,
+        solana_pubkey: solana_pubkey.clone(),
+        chain_id: chain_id.clone(),
+        new_evm_address: new_evm_address.cloned(),
+        new_key_id: new_key_id.map(str::to_string),
+        allow_shared_address,
+        proposed_by: proposer.to_string(),
+        proposed_at: now,
+        expires_at: now + PENDING_UPDATE_TTL,
+        status: PendingStatus::Pending,
+        resolved_by: None,
+        resolved_at: None,
+    };
+    store(kv, &pending)?;
+    Ok(pending)
+}
+
+/// Approve or reject proposal `id`. Approval must come from someone other
+/// than the proposer; the proposer may reject (withdraw) their own proposal.
+pub fn resolve(
+    kv: &impl KvStore,
+    solana_pubkey: &SolanaPubkey,
+    chain_id: &ChainId,
+    id: u64,
+    resolver: &str,
+    status: PendingStatus,
+    now: u64,
+) -> Result<PendingUpdate> {
+    if status == PendingStatus::Pending {
+        return Err(ProvisionError::InvalidRequest(
+            "a proposal can only be resolved as approved or rejected".to_string(),
+        ));
+    }
+
+    let mut pending = get_pending(kv, solana_pubkey, chain_id)?
+        .filter(|p| p.id == id)
+        .ok_or_else(|| ProvisionError::ProposalNotFound {
+            id,
+            solana_pubkey: solana_pubkey.to_string(),
+            chain_id: chain_id.to_string(),
+        })?;
+
+    if pending.status != PendingStatus::Pending {
+        return Err(ProvisionError::ProposalResolved {
+            id,
+            status: status_name(pending.status),
+        });
+    }
+    if now > pending.expires_at {
+        return Err(ProvisionError::ProposalExpired {
+            id,
+            expires_at: pending.expires_at,
+        });
+    }
+    if status == PendingStatus::Approved && pending.proposed_by == resolver {
+        return Err(ProvisionError::SelfApproval {
+            id,
+            admin: resolver.to_string(),
+        });
+    }
+
+    if !kv.set_if_absent(&resolution_claim_key(solana_pubkey, chain_id, id), status_name(status))? {
+        return Err(ProvisionError::KvConflict(format!("Update {} was resolved concurrently", id)));
+    }
+
+    pending.status = status;
+    pending.resolved_by = Some(resolver.to_string());
+    pending.resolved_at = Some(now);
+    store(kv, &pending)?;
+    Ok(pending)
+}
+
+fn status_name(status: PendingStatus) -> &'static str {
+    match status {
+        PendingStatus::Pending => "pending",
+        PendingStatus::Approved => "approved",
+        PendingStatus::Rejected => "rejected",
+    }
+}
+
+fn store(kv: &impl KvStore, pending: &PendingUpdate) -> Result<()> {
+    let raw = serde_json::to_string(pending).expect("pending update serialization cannot fail");
+    kv.set(&pending_key(&pending.solana_pubkey, &pending.chain_id), &raw)
+}
@@ -0,0 +1,161 @@
+//! Async API (`async` feature)
+//!
+//! The flows stay synchronous: they are shared with the WASM policy, whose
+//! host calls block. `AsyncProvisioner` wraps a `Provisioner` for async
+//! callers instead: each call runs the synchronous handler on a blocking
+//! executor and returns a future that completes with its result, so a KV
+//! round trip or CubeSigner call never holds up the caller's worker thread.
+//!
+//! The executor is pluggable (`Blocking`) so the library depends on no async
+//! runtime. The default, `ThreadPerCall`, starts one OS thread per call; a
+//! tokio backend passes its blocking pool:
+//!
+//! ```ignore
+//! struct Tokio;
+//! impl Blocking for Tokio {
+//!     fn spawn_blocking(&self, task: Box<dyn FnOnce() + Send>) {
+//!         tokio::task::spawn_blocking(task);
+//!     }
+//! }
+//! let provisioner = AsyncProvisioner::new(provisioner).with_blocking(Tokio);
+//! ```
+//!
+//! Dropping a future does not cancel its call: the handler runs to completion
+//! and its result is discarded. A handler that panics panics the future.
+
+use crate::admin::Requester;
+use crate::chain_id::ChainId;
+use crate::error::Result;
+use crate::keys::KeyCreator;
+use crate::kv::KvStore;
+use crate::{
+    GetMappingsResponse, ProvisionBatchRequest, ProvisionBatchResponse, ProvisionRequest, ProvisionResponse, Provisioner,
+    SolanaPubkey, UpdateMappingRequest, UpdateMappingResponse,
+};
+use std::future::Future;
+use std::panic::{self, AssertUnwindSafe};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+use std::thread;
+
+/// Runs tasks that block, off the caller's async executor
+pub trait Blocking: Send + Sync {
+    fn spawn_blocking(&self, task: Box<dyn FnOnce() + Send>);
+}
+
+/// `Blocking` starting one OS thread per task
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ThreadPerCall;
+
+impl Blocking for ThreadPerCall {
+    fn spawn_blocking(&self, task: Box<dyn FnOnce() + Send>) {
+        thread::spawn(task);
+    }
+}
+
+/// A `Provisioner` whose handlers are awaited instead of blocking
+pub struct AsyncProvisioner<S, K> {
+    inner: Arc<Provisioner<S, K>>,
+    blocking: Arc<dyn Blocking>,
+}
+
+impl<S, K> Clone for AsyncProvisioner<S, K> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: Arc::clone(&self.inner),
+            blocking: Arc::clone(&self.blocking),
+        }
+    }
+}
+
+impl<S, K> AsyncProvisioner<S, K>
+where
+    S: KvStore + Send + Sync + 'static,
+    K: KeyCreator + Send + Sync + 'static,
+{
+    pub fn new(provisioner: Provisioner<S, K>) -> Self {
+        Self {
+            inner: Arc::new(provisioner),
+            blocking: Arc::new(ThreadPerCall),
+        }
+    }
+
+    /// Run handlers on `blocking` instead of one thread per call
+    pub fn with_blocking(mut self, blocking: impl Blocking + 'static) -> Self {
+        self.blocking = Arc::new(blocking);
+        self
+    }
+
+    /// The wrapped provisioner, for calls made from blocking code
+    pub fn provisioner(&self) -> &Provisioner<S, K> {
+        &self.inner
+    }
+
+    /// `Provisioner::handle`
+    pub async fn handle(&self, req: ProvisionRequest) -> Result<ProvisionResponse> {
+        self.run(move |provisioner| provisioner.handle(req)).await
+    }
+
+    /// `Provisioner::handle_batch`
+    pub async fn handle_batch(&self, req: ProvisionBatchRequest) -> Result<ProvisionBatchResponse> {
+        self.run(move |provisioner| provisioner.handle_batch(req)).await
+    }
+
+    /// `Provisioner::handle_update_mapping`
+    pub async fn handle_update_mapping(&self, requester: Requester, req: UpdateMappingRequest) -> Result<UpdateMappingResponse> {
+        self.run(move |provisioner| provisioner.handle_update_mapping(&requester, req)).await
+    }
+
+    /// `Provisioner::handle_get`
+    pub async fn handle_get(&self, solana_pubkey: SolanaPubkey, chain_ids: Vec<ChainId>) -> Result<GetMappingsResponse> {
+        self.run(move |provisioner| provisioner.handle_get(&solana_pubkey, &chain_ids)).await
+    }
+
+    /// Run any other handler: `f` gets the wrapped provisioner, on the
+    /// blocking executor
+    pub fn run<T, F>(&self, f: F) -> Completion<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&Provisioner<S, K>) -> Result<T> + Send + 'static,
+    {
+        let state = Arc::new(Mutex::new(State { result: None, waker: None }));
+        let completion = Completion { state: Arc::clone(&state) };
+        let inner = Arc::clone(&self.inner);
+        self.blocking.spawn_blocking(Box::new(move || {
+            let result = panic::catch_unwind(AssertUnwindSafe(|| f(&inner)));
+            let mut state = state.lock().unwrap_or_else(|e| e.into_inner());
+            state.result = Some(result);
+            if let Some(waker) = state.waker.take() {
+                waker.wake();
+            }
+        }));
+        completion
+    }
+}
+
+struct State<T> {
+    result: Option<thread::Result<Result<T>>>,
+    waker: Option<Waker>,
+}
+
+/// Future of a handler running on the blocking executor
+pub struct Completion<T> {
+    state: Arc<Mutex<State<T>>>,
+}
+
+impl<T> Future for Completion<T> {
+    type Output = Result<T>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        match state.result.take() {
+            Some(Ok(result)) => Poll::Ready(result),
+            Some(Err(payload)) => panic::resume_unwind(payload),
+            None => {
+                state.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
@@ -0,0 +1,222 @@
+//! Mapping Attestations
+//!
+//! EIP-712 signed statements that a Solana address maps to an EVM address on
+//! a chain, so partners can check a mapping offline (or in a contract with
+//! `ecrecover`) instead of trusting an API response. Attestations are signed
+//! by the org's attestation key (`AttestationSigner`, `CubeSignerAttester`
+//! for a CubeSigner EVM key); partners pin its address.
+//!
+//! The typed data:
+//! ```text
+//! EIP712Domain(string name,string version,uint256 chainId)
+//!   name = "SolanaEvmMapping", version = "1", chainId = the mapping's EVM chain
+//! MappingAttestation(bytes32 solanaPubkey,address evmAddress,string chain,uint64 version,uint64 issuedAt)
+//!   chain = CAIP-2 chain id, version = revision of the chain mapping
+//! ```
+//!
+//! An attestation states the mapping at `issued_at`; it does not expire by
+//! itself. Verifiers that care about rotations compare `version` with what
+//! they last saw, or only accept recent `issued_at`.
+//!
+//! Attesting needs a signature from CubeSigner, so it is a `Provisioner`
+//! handler (and REST route), not a policy action.
+
+use crate::address::{EvmAddress, SolanaPubkey};
+use crate::auth;
+use crate::chain_id::ChainId;
+use crate::cubesigner_client::{CubeSignerClient, HttpTransport};
+use crate::error::{ProvisionError, Result};
+use crate::kv::KvStore;
+use crate::mapping;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sha3::{Digest, Keccak256};
+
+/// EIP-712 domain name
+pub const DOMAIN_NAME: &str = "SolanaEvmMapping";
+
+/// EIP-712 domain version
+pub const DOMAIN_VERSION: &str = "1";
+
+const DOMAIN_TYPE: &str = "EIP712Domain(string name,string version,uint256 chainId)";
+const ATTESTATION_TYPE: &str =
+    "MappingAttestation(bytes32 solanaPubkey,address evmAddress,string chain,uint64 version,uint64 issuedAt)";
+
+/// Request body of `POST /attest`
+#[derive(Deserialize, Debug, Clone)]
+pub struct AttestRequest {
+    pub solana_pubkey: SolanaPubkey,
+    pub chain_id: ChainId,
+}
+
+/// What an attestation states
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct MappingAttestation {
+    pub solana_pubkey: SolanaPubkey,
+    /// An `eip155` chain; its EVM chain id is the domain's `chainId`
+    pub chain_id: ChainId,
+    pub evm_address: EvmAddress,
+    /// Revision of the chain mapping (0 for an address inherited from the default)
+    pub version: u64,
+    /// Unix timestamp (seconds) the mapping was read at
+    pub issued_at: u64,
+}
+
+fn evm_chain_id(chain_id: &ChainId) -> Result<u64> {
+    chain_id
+        .evm_chain_id()
+        .ok_or_else(|| ProvisionError::InvalidRequest(format!("{} is not an EVM chain", chain_id)))
+}
+
+impl MappingAttestation {
+    /// The typed data as `eth_signTypedData_v4` takes it
+    pub fn typed_data(&self) -> Result<serde_json::Value> {
+        Ok(json!({
+            "types": {
+                "EIP712Domain": [
+                    { "name": "name", "type": "string" },
+                    { "name": "version", "type": "string" },
+                    { "name": "chainId", "type": "uint256" },
+                ],
+                "MappingAttestation": [
+                    { "name": "solanaPubkey", "type": "bytes32" },
+                    { "name": "evmAddress", "type": "address" },
+                    { "name": "chain", "type": "string" },
+                    { "name": "version", "type": "uint64" },
+                    { "name": "issuedAt", "type": "uint64" },
+                ],
+            },
+            "primaryType": "MappingAttestation",
+            "domain": { "name": DOMAIN_NAME, "version": DOMAIN_VERSION, "chainId": evm_chain_id(&self.chain_id)? },
+            "message": {
+                "solanaPubkey": to_hex(&self.solana_pubkey.to_bytes()),
+                "evmAddress": self.evm_address.as_str(),
+                "chain": self.chain_id.to_string(),
+                "version": self.version,
+                "issuedAt": self.issued_at,
+            },
+        }))
+    }
+
+    /// EIP-712 digest: `keccak256(0x1901 ‖ domainSeparator ‖ hashStruct(message))`
+    pub fn digest(&self) -> Result<[u8; 32]> {
+        let mut domain = Keccak256::new();
+        domain.update(Keccak256::digest(DOMAIN_TYPE));
+        domain.update(Keccak256::digest(DOMAIN_NAME));
+        domain.update(Keccak256::digest(DOMAIN_VERSION));
+        domain.update(uint256(evm_chain_id(&self.chain_id)?));
+
+        let mut message = Keccak256::new();
+        message.update(Keccak256::digest(ATTESTATION_TYPE));
+        message.update(self.solana_pubkey.to_bytes());
+        message.update(address_word(&self.evm_address));
+        message.update(Keccak256::digest(self.chain_id.to_string()));
+        message.update(uint256(self.version));
+        message.update(uint256(self.issued_at));
+
+        let mut digest = Keccak256::new();
+        digest.update([0x19, 0x01]);
+        digest.update(domain.finalize());
+        digest.update(message.finalize());
+        Ok(digest.finalize().into())
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SignedAttestation {
+    #[serde(flatten)]
+    pub attestation: MappingAttestation,
+    /// Address of the attestation key
+    pub attester: EvmAddress,
+    /// 65-byte signature over the EIP-712 digest, 0x-prefixed hex
+    pub signature: String,
+}
+
+/// Signs attestations with the org's attestation key
+pub trait AttestationSigner {
+    /// Address of the attestation key, which partners pin
+    fn attester(&self) -> &EvmAddress;
+
+    /// 65-byte signature over `attestation.digest()`, 0x-prefixed hex
+    fn sign(&self, attestation: &MappingAttestation) -> Result<String>;
+}
+
+/// `AttestationSigner` over a CubeSigner EVM key
+pub struct CubeSignerAttester<T> {
+    client: CubeSignerClient<T>,
+    attester: EvmAddress,
+}
+
+impl<T: HttpTransport> CubeSignerAttester<T> {
+    /// Sign with the CubeSigner key at `attester`; the client's session needs
+    /// the `sign:evm:eip712` scope
+    pub fn new(client: CubeSignerClient<T>, attester: EvmAddress) -> Self {
+        Self { client, attester }
+    }
+}
+
+impl<T: HttpTransport> AttestationSigner for CubeSignerAttester<T> {
+    fn attester(&self) -> &EvmAddress {
+        &self.attester
+    }
+
+    fn sign(&self, attestation: &MappingAttestation) -> Result<String> {
+        self.client
+            .eip712_sign(self.attester.as_str(), evm_chain_id(&attestation.chain_id)?, &attestation.typed_data()?)
+            .map_err(|e| ProvisionError::SigningFailed { message: e.to_string(), retryable: e.is_retryable() })
+    }
+}
+
+/// Attest the current mapping of `solana_pubkey` on `chain_id` (including an
+/// address inherited from the default)
+pub fn attest(
+    kv: &impl KvStore,
+    signer: &(impl AttestationSigner + ?Sized),
+    solana_pubkey: &SolanaPubkey,
+    chain_id: &ChainId,
+    now: u64,
+) -> Result<SignedAttestation> {
+    // Non-EVM chains have no domain `chainId`, and never inherit the default
+    evm_chain_id(chain_id)?;
+    let current = mapping::get(kv, solana_pubkey, std::slice::from_ref(chain_id), now)?;
+    let evm_address = current
+        .chain_mappings
+        .get(chain_id)
+        .cloned()
+        .ok_or_else(|| ProvisionError::NotProvisioned(solana_pubkey.to_string()))?;
+
+    let attestation = MappingAttestation {
+        solana_pubkey: solana_pubkey.clone(),
+        chain_id: chain_id.clone(),
+        evm_address,
+        version: current.chain_versions.get(chain_id).copied().unwrap_or(0),
+        issued_at: now,
+    };
+    let signature = signer.sign(&attestation)?;
+    Ok(SignedAttestation { attestation, attester: signer.attester().clone(), signature })
+}
+
+/// Verify that `signed` was signed by `attester` (the pinned attestation
+/// key, not the `attester` field of the attestation itself)
+pub fn verify(signed: &SignedAttestation, attester: &EvmAddress) -> Result<()> {
+    auth::verify_evm_prehash_signature(attester, &signed.attestation.digest()?, &signed.signature)
+}
+
+fn uint256(value: u64) -> [u8; 32] {
+    let mut word = [0u8; 32];
+    word[24..].copy_from_slice(&value.to_be_bytes());
+    word
+}
+
+fn address_word(evm_address: &EvmAddress) -> [u8; 32] {
+    let hex = &evm_address.as_str()[2..];
+    let mut word = [0u8; 32];
+    for (i, byte) in word[12..].iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hex[2 * i..2 * i + 2], 16).expect("EvmAddress is validated");
+    }
+    word
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    format!("0x{}", bytes.iter().map(|b| format!("{:02x}", b)).collect::<String>())
+}
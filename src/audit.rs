@@ -0,0 +1,202 @@
+//! Audit Log
+//!
+//! Append-only, hash-chained record of every mutating operation
+//! (provision, store, update, ...). Each record carries the hash of its
+//! predecessor, so rewriting or removing a record breaks every later hash.
+//!
+//! ## Key Schema
+//! ```text
+//! audit:{seq}  → AuditRecord   # seq starts at 1, written with IfExists::Deny
+//! audit:head   → {seq}         # Hint for the latest seq (may lag behind)
+//! ```
+
+use crate::kv::KvStore;
+use crate::error::{ProvisionError, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// `prev_hash` of the first record
+pub const GENESIS_HASH: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+/// Maximum number of records returned by one query
+pub const MAX_QUERY_LIMIT: usize = 500;
+
+/// Attempts to claim a seq before giving up under contention
+const MAX_APPEND_ATTEMPTS: usize = 16;
+
+const HEAD_KEY: &str = "audit:head";
+
+pub fn audit_key(seq: u64) -> String {
+    format!("audit:{}", seq)
+}
+
+/// What happened, before it is sequenced and hashed
+#[derive(Debug, Clone)]
+pub struct AuditEvent {
+    /// e.g. `provision`, `update`
+    pub action: String,
+    /// Who performed it
+    pub actor: String,
+    /// Solana address the action applied to, if any
+    pub subject: Option<String>,
+    /// `Err(message)` if the action failed
+    pub outcome: std::result::Result<(), String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct AuditRecord {
+    pub seq: u64,
+    /// Unix timestamp (seconds)
+    pub timestamp: u64,
+    pub action: String,
+    pub actor: String,
+    pub subject: Option<String>,
+    pub success: bool,
+    pub error: Option<String>,
+    /// `hash` of record `seq - 1` (`GENESIS_HASH` for the first record)
+    pub prev_hash: String,
+    /// SHA-256 over all fields above, hex
+    pub hash: String,
+}
+
+impl AuditRecord {
+    fn compute_hash(&self) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(self.seq.to_be_bytes());
+        hasher.update(self.timestamp.to_be_bytes());
+        for field in [&self.action, &self.actor, &self.prev_hash] {
+            hasher.update((field.len() as u64).to_be_bytes());
+            hasher.update(field.as_bytes());
+        }
+        for field in [&self.subject, &self.error] {
+            let field = field.as_deref().unwrap_or("");
+            hasher.update((field.len() as u64).to_be_bytes());
+            hasher.update(field.as_bytes());
+        }
+        hasher.update([self.success as u8]);
+        hex(&hasher.finalize())
+    }
+}
+
+/// Time-range query over the log, paged by seq
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct AuditQuery {
+    /// Only records with `timestamp >= from`
+    #[serde(default)]
+    pub from: Option<u64>,
+    /// Only records with `timestamp <= to`
+    #[serde(default)]
+    pub to: Option<u64>,
+    /// Resume after this seq (`next_seq` of the previous page)
+    #[serde(default)]
+    pub after_seq: Option<u64>,
+    #[serde(default)]
+    pub limit: Option<usize>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct AuditQueryResponse {
+    pub records: Vec<AuditRecord>,
+    /// Pass as `after_seq` to continue; `None` when the end of the log was reached
+    pub next_seq: Option<u64>,
+}
+
+/// Append an event to the log, returning the sequenced record
+pub fn append(kv: &impl KvStore, event: AuditEvent, timestamp: u64) -> Result<AuditRecord> {
+    for _ in 0..MAX_APPEND_ATTEMPTS {
+        let (last_seq, prev_hash) = match find_last(kv)? {
+            Some(last) => (last.seq, last.hash),
+            None => (0, GENESIS_HASH.to_string()),
+        };
+
+        let mut record = AuditRecord {
+            seq: last_seq + 1,
+            timestamp,
+            action: event.action.clone(),
+            actor: event.actor.clone(),
+            subject: event.subject.clone(),
+            success: event.outcome.is_ok(),
+            error: event.outcome.clone().err(),
+            prev_hash,
+            hash: String::new(),
+        };
+        record.hash = record.compute_hash();
+
+        let raw = serde_json::to_string(&record).expect("audit record serialization cannot fail");
+        if kv.set_if_absent(&audit_key(record.seq), &raw)? {
+            kv.set(HEAD_KEY, &record.seq.to_string())?;
+            return Ok(record);
+        }
+        // Another writer claimed this seq - re-read the tail and retry
+    }
+
+    Err(ProvisionError::KvConflict(format!("Could not append audit record after {} attempts", MAX_APPEND_ATTEMPTS)))
+}
+
+pub fn get(kv: &impl KvStore, seq: u64) -> Result<Option<AuditRecord>> {
+    kv.get(&audit_key(seq))?
+        .map(|raw| serde_json::from_str(&raw).map_err(|e| ProvisionError::corrupt(format!("audit record {}", seq), e)))
+        .transpose()
+}
+
+/// Scan the log in seq order, filtering by time range
+pub fn query(kv: &impl KvStore, query: &AuditQuery) -> Result<AuditQueryResponse> {
+    let limit = query.limit.unwrap_or(MAX_QUERY_LIMIT).clamp(1, MAX_QUERY_LIMIT);
+    let from = query.from.unwrap_or(0);
+    let to = query.to.unwrap_or(u64::MAX);
+
+    let mut records = Vec::new();
+    let mut seq = query.after_seq.unwrap_or(0);
+
+    loop {
+        let Some(record) = get(kv, seq + 1)? else {
+            return Ok(AuditQueryResponse { records, next_seq: None });
+        };
+        seq = record.seq;
+
+        if record.timestamp >= from && record.timestamp <= to {
+            records.push(record);
+            if records.len() == limit {
+                return Ok(AuditQueryResponse { records, next_seq: Some(seq) });
+            }
+        }
+    }
+}
+
+/// Check that `records` (consecutive, ascending seq) are internally consistent
+/// and correctly chained. Pass the record preceding the first one, if any.
+pub fn verify_chain(previous: Option<&AuditRecord>, records: &[AuditRecord]) -> Result<()> {
+    let mut expected_prev = previous.map_or(GENESIS_HASH.to_string(), |r| r.hash.clone());
+    let first_seq = previous.map_or(1, |r| r.seq + 1);
+
+    for (expected_seq, record) in (first_seq..).zip(records) {
+        if record.seq != expected_seq {
+            return Err(ProvisionError::AuditChainBroken(format!("Audit gap: expected seq {}, found {}", expected_seq, record.seq)));
+        }
+        if record.prev_hash != expected_prev {
+            return Err(ProvisionError::AuditChainBroken(format!("Audit chain broken at seq {}", record.seq)));
+        }
+        if record.hash != record.compute_hash() {
+            return Err(ProvisionError::AuditChainBroken(format!("Audit record {} has been modified", record.seq)));
+        }
+        expected_prev = record.hash.clone();
+    }
+    Ok(())
+}
+
+/// Latest record, starting from the head hint and probing forward
+fn find_last(kv: &impl KvStore) -> Result<Option<AuditRecord>> {
+    let hint = kv.get(HEAD_KEY)?.and_then(|raw| raw.parse::<u64>().ok()).unwrap_or(0);
+
+    let mut last = if hint > 0 { get(kv, hint)? } else { None };
+    let mut seq = hint;
+    while let Some(next) = get(kv, seq + 1)? {
+        seq = next.seq;
+        last = Some(next);
+    }
+    Ok(last)
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
@@ -0,0 +1,200 @@
+//! Ownership Proofs
+//!
+//! Provisioning requires proof that the caller controls the Solana address:
+//! an ed25519 signature by `solana_pubkey` over `message`, produced by the
+//! user's wallet (e.g. `signMessage`). `message` must be the one
+//! `store_message` builds for the request: it binds the action, the chains,
+//! the label, lifetime and key of what is created, a nonce and an expiry, so
+//! neither a signature over some other message (a login on another dapp) nor
+//! an old store request can be replayed, and no term can be swapped under it.
+//!
+//! - `solana_pubkey`: base58, 32 bytes
+//! - `signature`: base64, 64 bytes (same encoding as `backend/solana-auth.ts`)
+//!
+//! Self-service updates are authorized the same way, over a message built by
+//! `update_self_message` that binds the chain, a nonce and an expiry. Nonces
+//! are decimal integers that must grow with every accepted request of a
+//! Solana address, and the expiry may be at most `MAX_AUTHORIZATION_TTL_SECS`
+//! away, so a signed request can neither be replayed nor kept for later.
+//! When the backend has already created the new key (the policy), the message
+//! also binds its address and key id (`update_self_address_message`).
+//!
+//! EVM → Solana provisioning is the mirror image: an EIP-191 `personal_sign`
+//! signature by `evm_address` (e.g. from MetaMask) over the message
+//! `evm_to_solana_message` builds, binding the address, a single-use nonce and
+//! an expiry. Linking an external EVM address takes both signatures over
+//! `link_external_message`.
+//!
+//! - `signature`: `0x` + 130 hex digits (`r || s || v`, `v` = 27/28 or 0/1)
+
+use crate::address::{EvmAddress, SolanaPubkey};
+use crate::chain_id::ChainId;
+use crate::error::{ProvisionError, Result};
+use crate::keys::KeyClass;
+use crate::ProvisionRequest;
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use ed25519_dalek::{Signature, VerifyingKey};
+use k256::ecdsa::{RecoveryId, Signature as EcdsaSignature, VerifyingKey as EcdsaVerifyingKey};
+use sha3::{Digest, Keccak256};
+
+/// Verify that `signature` over `message` was produced by `solana_pubkey`
+pub fn verify_solana_signature(solana_pubkey: &SolanaPubkey, message: &str, signature: &str) -> Result<()> {
+    let pubkey_bytes = solana_pubkey.to_bytes();
+
+    let verifying_key = VerifyingKey::from_bytes(&pubkey_bytes)
+        .map_err(|_| ProvisionError::InvalidSolanaPubkey(solana_pubkey.to_string()))?;
+
+    let signature_bytes: [u8; 64] = BASE64
+        .decode(signature)
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or(ProvisionError::InvalidSignatureEncoding { expected: "base64, 64 bytes" })?;
+
+    verifying_key
+        .verify_strict(message.as_bytes(), &Signature::from_bytes(&signature_bytes))
+        .map_err(|_| ProvisionError::SignatureMismatch(solana_pubkey.to_string()))
+}
+
+/// Verify that `signature` is an EIP-191 `personal_sign` signature over
+/// `message` by `evm_address`
+pub fn verify_evm_signature(evm_address: &EvmAddress, message: &str, signature: &str) -> Result<()> {
+    let prefixed = format!("\x19Ethereum Signed Message:\n{}{}", message.len(), message);
+    verify_evm_prehash_signature(evm_address, &Keccak256::digest(prefixed.as_bytes()), signature)
+}
+
+/// Verify that `signature` (0x-prefixed hex, 65 bytes) over the 32-byte
+/// `digest` was produced by `evm_address`
+pub fn verify_evm_prehash_signature(evm_address: &EvmAddress, digest: &[u8], signature: &str) -> Result<()> {
+    let bytes: [u8; 65] = signature
+        .strip_prefix("0x")
+        .and_then(decode_hex)
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or(ProvisionError::InvalidSignatureEncoding { expected: "0x-prefixed hex, 65 bytes" })?;
+
+    let recovery_id = match bytes[64] {
+        0 | 27 => RecoveryId::new(false, false),
+        1 | 28 => RecoveryId::new(true, false),
+        v => return Err(ProvisionError::InvalidRecoveryId(v)),
+    };
+    let signature = EcdsaSignature::from_slice(&bytes[..64])
+        .map_err(|_| ProvisionError::SignatureMismatch(evm_address.to_string()))?;
+
+    let recovered = EcdsaVerifyingKey::recover_from_prehash(digest, &signature, recovery_id)
+        .map_err(|_| ProvisionError::SignatureMismatch(evm_address.to_string()))?;
+
+    if evm_address_of(&recovered) != evm_address.as_str() {
+        return Err(ProvisionError::SignatureMismatch(evm_address.to_string()));
+    }
+    Ok(())
+}
+
+/// Lowercase `0x…` address of a secp256k1 public key
+pub fn evm_address_of(key: &EcdsaVerifyingKey) -> String {
+    let point = key.to_encoded_point(false);
+    let hash = Keccak256::digest(&point.as_bytes()[1..]);
+    format!("0x{}", hash[12..].iter().map(|b| format!("{:02x}", b)).collect::<String>())
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+/// Furthest into the future a signed request may expire (seconds)
+pub const MAX_AUTHORIZATION_TTL_SECS: u64 = 300;
+
+/// Message the user signs to store `req`: its chains as the request names
+/// them (CAIP-2 ids, `*` for the wildcard; empty for the default chains) and
+/// every term that shapes what is created (label, expiry, key type and class;
+/// empty when absent), so none can be changed under the signature
+pub fn store_message(req: &ProvisionRequest, nonce: &str, expires_at: u64) -> String {
+    let chain_ids: Vec<&str> = req.chain_ids.iter().map(ChainId::as_str).collect();
+    let key_class = match req.key_class {
+        KeyClass::Standard => "standard".to_string(),
+        KeyClass::Mpc { threshold, participants } => format!("mpc {} of {}", threshold, participants),
+    };
+    format!(
+        "Store EVM wallet\nsolana_pubkey: {}\nchain_ids: {}\nlabel: {}\nttl_secs: {}\nkey_type: {}\nkey_class: {}\nnonce: {}\nexpires_at: {}",
+        req.solana_pubkey,
+        chain_ids.join(","),
+        req.label.as_deref().unwrap_or(""),
+        req.ttl_secs.map(|ttl| ttl.to_string()).unwrap_or_default(),
+        req.key_type.as_str(),
+        key_class,
+        nonce,
+        expires_at
+    )
+}
+
+/// The nonce and expiry named by a message in the format of `store_message`
+/// (or `evm_to_solana_message`); `None` if it names none
+pub fn store_message_terms(message: &str) -> Option<(&str, u64)> {
+    let field = |name: &str| message.lines().find_map(|line| line.strip_prefix(name)?.strip_prefix(": "));
+    Some((field("nonce")?, field("expires_at")?.parse().ok()?))
+}
+
+/// Message the EVM wallet signs to provision a Solana wallet for `evm_address`
+pub fn evm_to_solana_message(evm_address: &EvmAddress, nonce: &str, expires_at: u64) -> String {
+    format!(
+        "Provision Solana wallet\nevm_address: {}\nnonce: {}\nexpires_at: {}",
+        evm_address, nonce, expires_at
+    )
+}
+
+/// Message the user signs to rotate the EVM key of one chain
+pub fn update_self_message(solana_pubkey: &SolanaPubkey, chain_id: &ChainId, nonce: &str, expires_at: u64) -> String {
+    format!(
+        "Rotate EVM wallet\nsolana_pubkey: {}\nchain_id: {}\nnonce: {}\nexpires_at: {}",
+        solana_pubkey, chain_id, nonce, expires_at
+    )
+}
+
+/// Message the user signs to switch one chain to a key the backend created,
+/// named by both its address and its CubeSigner key id
+pub fn update_self_address_message(
+    solana_pubkey: &SolanaPubkey,
+    chain_id: &ChainId,
+    new_evm_address: &EvmAddress,
+    new_key_id: &str,
+    nonce: &str,
+    expires_at: u64,
+) -> String {
+    format!(
+        "Update EVM wallet\nsolana_pubkey: {}\nchain_id: {}\nnew_evm_address: {}\nnew_key_id: {}\nnonce: {}\nexpires_at: {}",
+        solana_pubkey, chain_id, new_evm_address, new_key_id, nonce, expires_at
+    )
+}
+
+/// Message both wallets sign to link an externally owned EVM address to a
+/// Solana address on `chain_ids`: by `solana_pubkey` (ed25519) and by
+/// `evm_address` (EIP-191 `personal_sign`)
+pub fn link_external_message(
+    solana_pubkey: &SolanaPubkey,
+    evm_address: &EvmAddress,
+    chain_ids: &[ChainId],
+    nonce: &str,
+    expires_at: u64,
+) -> String {
+    let chain_ids: Vec<&str> = chain_ids.iter().map(ChainId::as_str).collect();
+    format!(
+        "Link EVM wallet\nsolana_pubkey: {}\nevm_address: {}\nchain_ids: {}\nnonce: {}\nexpires_at: {}",
+        solana_pubkey,
+        evm_address,
+        chain_ids.join(","),
+        nonce,
+        expires_at
+    )
+}
+
+/// Nonces are decimal `u64`s (e.g. a counter or a millisecond timestamp)
+pub fn parse_nonce(nonce: &str) -> Result<u64> {
+    if nonce.is_empty() || !nonce.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(ProvisionError::InvalidNonce);
+    }
+    nonce.parse().map_err(|_| ProvisionError::InvalidNonce)
+}
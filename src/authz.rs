@@ -0,0 +1,128 @@
+//! Authorization Matrix
+//!
+//! Which requesters may invoke which policy action. The library's
+//! `Provisioner` checks its admin and owner handlers against the same matrix.
+//! Every action requires one of four roles:
+//!
+//! | Role      | Held by                                   | Actions                                   |
+//! |-----------|-------------------------------------------|-------------------------------------------|
+//! | `Reader`  | any authenticated identity                | get, list, history, reverse_get, …        |
+//! | `Service` | service accounts, admins and org owners   | store, store_batch, update_self, …        |
+//! | `Admin`   | identities in the admin allowlist         | propose/approve/reject updates, freeze, … |
+//! | `Owner`   | org owners                                | add_admin, remove_admin, block, …         |
+//!
+//! `Admin` and `Owner` are deliberately separate: an org owner manages the
+//! allowlist but only acts as an admin when listed in it. Unknown actions
+//! require `Owner`, so a new action is never open by accident.
+
+use crate::admin::{self, Requester};
+use crate::error::{ProvisionError, Result};
+use crate::kv::KvStore;
+
+/// Identity reported for requesters without one
+const ANONYMOUS: &str = "anonymous";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Role {
+    Reader,
+    Service,
+    Admin,
+    Owner,
+}
+
+/// Role each policy action requires, by the action's `"action"` name
+pub const MATRIX: &[(&str, Role)] = &[
+    ("get", Role::Reader),
+    ("list", Role::Reader),
+    ("history", Role::Reader),
+    ("get_pending", Role::Reader),
+    ("reverse_get", Role::Reader),
+    ("get_retirement", Role::Reader),
+    ("get_evm_to_solana", Role::Reader),
+    ("list_chains", Role::Reader),
+    ("stats", Role::Reader),
+    ("usage_report", Role::Reader),
+    ("ping", Role::Reader),
+    ("version", Role::Reader),
+    ("merkle_proof", Role::Reader),
+    ("get_spend_limit", Role::Reader),
+    ("job_status", Role::Reader),
+    ("store", Role::Service),
+    ("store_batch", Role::Service),
+    ("provision_async", Role::Service),
+    ("store_evm_to_solana", Role::Service),
+    ("update_self", Role::Service),
+    ("link_external", Role::Service),
+    ("poll_events", Role::Service),
+    ("propose_update", Role::Admin),
+    ("approve_update", Role::Admin),
+    ("reject_update", Role::Admin),
+    ("update_batch", Role::Admin),
+    ("set_chain", Role::Admin),
+    ("migrate", Role::Admin),
+    ("sweep", Role::Admin),
+    ("anonymize", Role::Admin),
+    ("export", Role::Admin),
+    ("verify", Role::Admin),
+    ("repair", Role::Admin),
+    ("import", Role::Admin),
+    ("reconcile", Role::Admin),
+    ("freeze", Role::Admin),
+    ("unfreeze", Role::Admin),
+    ("set_spend_limit", Role::Admin),
+    ("set_signer", Role::Admin),
+    ("add_allowed_destination", Role::Admin),
+    ("remove_allowed_destination", Role::Admin),
+    ("audit_query", Role::Admin),
+    ("merkle_root", Role::Admin),
+    ("get_config", Role::Admin),
+    ("set_config", Role::Admin),
+    // Library only: single-step updates, which create the new key themselves
+    ("update", Role::Admin),
+    ("rotate", Role::Admin),
+    ("add_admin", Role::Owner),
+    ("remove_admin", Role::Owner),
+    ("migrate_environment", Role::Owner),
+    ("migrate_hashed_keys", Role::Owner),
+    ("encrypt_values", Role::Owner),
+    ("get_tenant_members", Role::Owner),
+    ("set_tenant_members", Role::Owner),
+    // The blocklist is shared by every tenant, so no tenant's admins write it
+    ("block", Role::Owner),
+    ("unblock", Role::Owner),
+];
+
+/// Role `action` requires (`Owner` for actions not in the matrix)
+pub fn required_role(action: &str) -> Role {
+    MATRIX
+        .iter()
+        .find(|(name, _)| *name == action)
+        .map_or(Role::Owner, |(_, role)| *role)
+}
+
+/// Fail unless `requester` holds the role `action` requires. `admins` is the
+/// admin allowlist; it is only read when the answer depends on it.
+/// Requesters without an identity may do nothing.
+pub fn authorize(admins: &impl KvStore, requester: &Requester, action: &str) -> Result<()> {
+    let forbidden = |identity: &str| ProvisionError::Forbidden {
+        identity: identity.to_string(),
+        action: action.to_string(),
+    };
+    if requester.identity.is_empty() {
+        return Err(forbidden(ANONYMOUS));
+    }
+
+    match required_role(action) {
+        Role::Reader => Ok(()),
+        Role::Service => {
+            if requester.is_service_account || requester.is_org_owner || admin::is_admin(admins, &requester.identity)? {
+                Ok(())
+            } else {
+                Err(forbidden(&requester.identity))
+            }
+        }
+        Role::Admin => admin::require_admin(admins, &requester.identity),
+        Role::Owner if requester.is_org_owner => Ok(()),
+        Role::Owner => Err(ProvisionError::NotOrgOwner),
+    }
+}
@@ -0,0 +1,11 @@
+//! Print the OpenAPI document (`openapi` feature)
+//!
+//! ```text
+//! cargo run --features openapi --bin openapi > openapi.json
+//! ```
+
+use cubist_wallet_provisioner::openapi;
+
+fn main() {
+    println!("{}", serde_json::to_string_pretty(&openapi::document()).expect("document serialization cannot fail"));
+}
@@ -0,0 +1,83 @@
+//! Operator CLI (`cli` feature)
+//!
+//! Runs one operation against a provisioning server or a local KV file (see
+//! `cli`) and prints the response as JSON. Failures print the error object
+//! to stderr and exit with 1; malformed arguments print the usage and exit
+//! with 2.
+//!
+//! ```text
+//! cargo run --features cli --bin provisioner-cli -- \
+//!     --kv-file mappings.json get --solana-pubkey 7xKX… --chain eip155:137
+//! ```
+//!
+//! Outbound HTTP goes through `curl`, which must be on the `PATH`.
+
+use cubist_wallet_provisioner::cli::{self, Backend, Command, Outcome};
+use cubist_wallet_provisioner::cubesigner_client::CubeSignerClient;
+use cubist_wallet_provisioner::curl::CurlTransport;
+use std::process::ExitCode;
+
+fn env(name: &str) -> Result<String, String> {
+    std::env::var(name).map_err(|_| format!("{} is not set", name))
+}
+
+/// CubeSigner client from the environment. Commands that never reach
+/// CubeSigner get one with nothing set, which they do not call.
+fn cubesigner(command: &Command) -> Result<CubeSignerClient<CurlTransport>, String> {
+    if !command.uses_cubesigner() {
+        return Ok(CubeSignerClient::new(CurlTransport, "", "", ""));
+    }
+    Ok(CubeSignerClient::new(
+        CurlTransport,
+        &env("CUBESIGNER_API_URL")?,
+        &env("CUBESIGNER_ORG_ID")?,
+        &env("CUBESIGNER_SESSION_TOKEN")?,
+    ))
+}
+
+fn run(backend: Backend, command: Command) -> Result<Outcome, String> {
+    match backend {
+        Backend::Server(url) => Ok(cli::run_on_server(&CurlTransport, &url, &command)),
+        Backend::KvFile(path) => {
+            let keys = cubesigner(&command)?;
+            let kv = cli::load_kv(&path).map_err(|e| e.to_string())?;
+            let writes = command.writes();
+            let operator = std::env::var(cli::OPERATOR_ENV).unwrap_or_else(|_| cli::DEFAULT_OPERATOR.to_string());
+            let outcome = cli::run_on_kv(kv.clone(), keys, &operator, command).map_err(|e| cli::error_object(&e));
+            // Failed commands can have written too (audit records, journals)
+            if writes {
+                cli::save_kv(&path, &kv).map_err(|e| e.to_string())?;
+            }
+            Ok(outcome)
+        }
+    }
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.iter().any(|arg| arg == "--help" || arg == "-h") {
+        println!("{}", cli::USAGE);
+        return ExitCode::SUCCESS;
+    }
+    let (backend, command) = match cli::parse(&args) {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, cli::USAGE);
+            return ExitCode::from(2);
+        }
+    };
+    match run(backend, command) {
+        Ok(Ok(response)) => {
+            println!("{}", serde_json::to_string_pretty(&response).expect("JSON values always serialize"));
+            ExitCode::SUCCESS
+        }
+        Ok(Err(error)) => {
+            eprintln!("{}", serde_json::to_string_pretty(&error).expect("JSON values always serialize"));
+            ExitCode::FAILURE
+        }
+        Err(message) => {
+            eprintln!("{}", message);
+            ExitCode::FAILURE
+        }
+    }
+}
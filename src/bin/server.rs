@@ -0,0 +1,78 @@
+//! Local provisioning server (`server` and `mock-kv` features)
+//!
+//! Serves `server::route` with mappings kept in memory and keys created in
+//! CubeSigner. Mappings are lost on exit: this is for trying the API and for
+//! local integration work, not a deployment.
+//!
+//! ```text
+//! CUBESIGNER_API_URL=https://gamma.signer.cubist.dev \
+//! CUBESIGNER_ORG_ID=Org#... CUBESIGNER_SESSION_TOKEN=... \
+//! PROVISIONER_ADDR=127.0.0.1:8080 PROVISIONER_ADMINS=User#alice,User#bob \
+//!     cargo run --features server,mock-kv --bin server
+//! ```
+//!
+//! `PROVISIONER_ADMINS` lists the identities `/update` accepts in
+//! `X-Requester-Identity`, one step each; without it every update is refused.
+//!
+//! Outbound HTTP goes through `curl`, which must be on the `PATH`.
+//!
+//! With the `dev-keys` feature, keys are derived locally (`dev_keys`) from
+//! `PROVISIONER_DEV_SEED`, or the default seed, and CubeSigner is not needed.
+
+use cubist_wallet_provisioner::admin::{self, Requester};
+#[cfg(not(feature = "dev-keys"))]
+use cubist_wallet_provisioner::cubesigner_client::CubeSignerClient;
+#[cfg(not(feature = "dev-keys"))]
+use cubist_wallet_provisioner::curl::CurlTransport;
+#[cfg(feature = "dev-keys")]
+use cubist_wallet_provisioner::dev_keys::DevKeys;
+use cubist_wallet_provisioner::memory_kv::MemoryKvStore;
+use cubist_wallet_provisioner::{server, Provisioner};
+use std::net::TcpListener;
+use std::sync::Arc;
+
+const DEFAULT_ADDR: &str = "127.0.0.1:8080";
+
+#[cfg(not(feature = "dev-keys"))]
+fn env(name: &str) -> String {
+    std::env::var(name).unwrap_or_else(|_| {
+        eprintln!("{} is not set", name);
+        std::process::exit(2);
+    })
+}
+
+#[cfg(not(feature = "dev-keys"))]
+fn keys() -> CubeSignerClient<CurlTransport> {
+    CubeSignerClient::new(
+        CurlTransport,
+        &env("CUBESIGNER_API_URL"),
+        &env("CUBESIGNER_ORG_ID"),
+        &env("CUBESIGNER_SESSION_TOKEN"),
+    )
+}
+
+#[cfg(feature = "dev-keys")]
+fn keys() -> DevKeys {
+    std::env::var("PROVISIONER_DEV_SEED").map(|seed| DevKeys::new(seed.as_bytes())).unwrap_or_default()
+}
+
+/// Allowlist of the comma-separated identities in `PROVISIONER_ADMINS`
+fn admins() -> MemoryKvStore {
+    let admins = MemoryKvStore::new();
+    let operator = Requester::new(Some("server"), Some(admin::ORG_OWNER_ROLE));
+    let listed = std::env::var("PROVISIONER_ADMINS").unwrap_or_default();
+    for identity in listed.split(',').map(str::trim).filter(|identity| !identity.is_empty()) {
+        admin::set_admin(&admins, &operator, identity, true, 0).expect("the in-memory store cannot fail");
+    }
+    admins
+}
+
+fn main() -> std::io::Result<()> {
+    let provisioner = Provisioner::new(MemoryKvStore::new(), keys()).with_admins(admins()).with_single_step_updates();
+    let provisioner = Arc::new(provisioner);
+
+    let addr = std::env::var("PROVISIONER_ADDR").unwrap_or_else(|_| DEFAULT_ADDR.to_string());
+    let listener = TcpListener::bind(&addr)?;
+    eprintln!("listening on http://{}", listener.local_addr()?);
+    server::serve(listener, provisioner)
+}
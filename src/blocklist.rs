@@ -0,0 +1,105 @@
+//! Blocklist Screening
+//!
+//! Compliance requires that a sanctioned counterparty is never mapped. Admins
+//! block Solana addresses and EVM addresses in the `blocklist` bucket; stores
+//! and updates screen the Solana address they act for and every EVM address
+//! they are about to map, and fail with `Blocked` on a hit.
+//!
+//! ## Key Schema (`blocklist` bucket)
+//! ```text
+//! solana:{solana_pubkey} → BlockEntry   # Never deleted; unblocking sets `blocked: false`
+//! evm:{evm_address}      → BlockEntry
+//! ```
+//!
+//! Mappings created before a block stay as they are; freeze the address
+//! (`freeze`) to stop handing it out.
+
+use crate::address::{EvmAddress, SolanaPubkey};
+use crate::error::{ProvisionError, Result};
+use crate::kv::KvStore;
+use serde::{Deserialize, Serialize};
+
+/// Bucket holding blocked addresses
+pub const BLOCKLIST_BUCKET: &str = "blocklist";
+
+/// An address that can be blocked
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum BlockTarget {
+    SolanaPubkey(SolanaPubkey),
+    EvmAddress(EvmAddress),
+}
+
+impl BlockTarget {
+    /// Key in the `blocklist` bucket: `solana:{solana_pubkey}` or `evm:{evm_address}`
+    pub fn key(&self) -> String {
+        match self {
+            Self::SolanaPubkey(pubkey) => format!("solana:{}", pubkey.as_str()),
+            Self::EvmAddress(address) => format!("evm:{}", address.as_str()),
+        }
+    }
+
+    fn address(&self) -> &str {
+        match self {
+            Self::SolanaPubkey(pubkey) => pubkey.as_str(),
+            Self::EvmAddress(address) => address.as_str(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct BlockEntry {
+    pub blocked: bool,
+    /// Why the address is blocked (e.g. the sanctions list it appears on)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    /// Admin who last blocked/unblocked the address
+    pub updated_by: String,
+    /// Unix timestamp (seconds)
+    pub updated_at: u64,
+}
+
+pub fn get_block(kv: &impl KvStore, target: &BlockTarget) -> Result<Option<BlockEntry>> {
+    kv.get(&target.key())?.map(|raw| decode(&raw)).transpose()
+}
+
+/// Fail with `Blocked` if `solana_pubkey` or any of `evm_addresses` is
+/// blocked. All of them are read with one `get_many`.
+pub fn screen(kv: &impl KvStore, solana_pubkey: &SolanaPubkey, evm_addresses: &[&EvmAddress]) -> Result<()> {
+    let targets: Vec<BlockTarget> = std::iter::once(BlockTarget::SolanaPubkey(solana_pubkey.clone()))
+        .chain(evm_addresses.iter().map(|address| BlockTarget::EvmAddress((*address).clone())))
+        .collect();
+    let keys: Vec<String> = targets.iter().map(BlockTarget::key).collect();
+
+    for (target, raw) in targets.iter().zip(kv.get_many(&keys)?) {
+        if raw.map(|raw| decode(&raw)).transpose()?.is_some_and(|entry| entry.blocked) {
+            return Err(ProvisionError::Blocked(target.address().to_string()));
+        }
+    }
+    Ok(())
+}
+
+/// Block or unblock an address
+pub fn set_blocked(
+    kv: &impl KvStore,
+    target: &BlockTarget,
+    blocked: bool,
+    reason: Option<&str>,
+    actor: &str,
+    now: u64,
+) -> Result<BlockEntry> {
+    let entry = BlockEntry {
+        blocked,
+        reason: reason.map(str::to_string),
+        updated_by: actor.to_string(),
+        updated_at: now,
+    };
+    let raw = serde_json::to_string(&entry).expect("block entry serialization cannot fail");
+    kv.set(&target.key(), &raw)?;
+    Ok(entry)
+}
+
+fn decode(raw: &str) -> Result<BlockEntry> {
+    serde_json::from_str(raw).map_err(|e| ProvisionError::corrupt("blocklist entry", e))
+}
@@ -0,0 +1,148 @@
+//! Mapping Certificates
+//!
+//! Short-lived JWTs asserting a mapping, for web backends that want to cache
+//! a verified mapping client-side: the backend issues one (`issue`), hands it
+//! to the client, and whoever receives it checks it with `verify_mapping_jwt`
+//! and the org's certificate public key, without calling the API.
+//!
+//! Certificates are signed with an ed25519 key held by the backend
+//! (`alg: EdDSA`, RFC 8037). Unlike attestations (`attestation`), they expire
+//! (`exp`), so a consumer never acts on a mapping older than the TTL.
+//!
+//! ```text
+//! header  {"alg":"EdDSA","typ":"JWT"}
+//! claims  {"solana_pubkey","chain_id","evm_address","iat","exp"}
+//! ```
+
+use crate::address::{EvmAddress, SolanaPubkey};
+use crate::chain_id::ChainId;
+use crate::error::{ProvisionError, Result};
+use crate::kv::KvStore;
+use crate::mapping;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD as BASE64URL, Engine};
+use ed25519_dalek::{Signature, Signer, SigningKey, VerifyingKey};
+use serde::{Deserialize, Serialize};
+
+/// Lifetime of a certificate unless the request says otherwise (seconds)
+pub const DEFAULT_CERTIFICATE_TTL_SECS: u64 = 300;
+
+/// Longest lifetime a certificate may be issued with (seconds)
+pub const MAX_CERTIFICATE_TTL_SECS: u64 = 3600;
+
+const HEADER: &str = r#"{"alg":"EdDSA","typ":"JWT"}"#;
+
+#[derive(Deserialize)]
+struct Header {
+    alg: String,
+}
+
+/// Request body of `POST /certificate`
+#[derive(Deserialize, Debug, Clone)]
+pub struct CertificateRequest {
+    pub solana_pubkey: SolanaPubkey,
+    pub chain_id: ChainId,
+    /// Lifetime in seconds; `DEFAULT_CERTIFICATE_TTL_SECS` if absent
+    #[serde(default)]
+    pub ttl: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct MappingClaims {
+    pub solana_pubkey: SolanaPubkey,
+    pub chain_id: ChainId,
+    pub evm_address: EvmAddress,
+    /// Unix timestamp (seconds) the certificate was issued at
+    pub iat: u64,
+    /// Unix timestamp (seconds) after which the certificate is invalid
+    pub exp: u64,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct MappingCertificate {
+    /// The JWT
+    pub token: String,
+    pub expires_at: u64,
+}
+
+/// Certificate for the current mapping of `solana_pubkey` on `chain_id`
+/// (including an address inherited from the default), valid for `ttl`
+/// seconds (default `DEFAULT_CERTIFICATE_TTL_SECS`, at most
+/// `MAX_CERTIFICATE_TTL_SECS`)
+pub fn issue(
+    kv: &impl KvStore,
+    key: &SigningKey,
+    solana_pubkey: &SolanaPubkey,
+    chain_id: &ChainId,
+    ttl: Option<u64>,
+    now: u64,
+) -> Result<MappingCertificate> {
+    let ttl = ttl.unwrap_or(DEFAULT_CERTIFICATE_TTL_SECS);
+    if ttl == 0 || ttl > MAX_CERTIFICATE_TTL_SECS {
+        return Err(ProvisionError::InvalidRequest(format!(
+            "Certificate TTL must be between 1 and {} seconds",
+            MAX_CERTIFICATE_TTL_SECS
+        )));
+    }
+
+    let current = mapping::get(kv, solana_pubkey, std::slice::from_ref(chain_id), now)?;
+    let evm_address = current
+        .chain_mappings
+        .get(chain_id)
+        .cloned()
+        .ok_or_else(|| ProvisionError::NotProvisioned(solana_pubkey.to_string()))?;
+
+    let claims = MappingClaims {
+        solana_pubkey: solana_pubkey.clone(),
+        chain_id: chain_id.clone(),
+        evm_address,
+        iat: now,
+        exp: now + ttl,
+    };
+    let claims_json = serde_json::to_string(&claims).expect("claims serialization cannot fail");
+    let signing_input = format!("{}.{}", BASE64URL.encode(HEADER), BASE64URL.encode(claims_json));
+    let signature = key.sign(signing_input.as_bytes());
+    Ok(MappingCertificate {
+        token: format!("{}.{}", signing_input, BASE64URL.encode(signature.to_bytes())),
+        expires_at: claims.exp,
+    })
+}
+
+/// Claims of `token` if it is a mapping certificate signed by `key` that has
+/// not expired at `now`
+pub fn verify_mapping_jwt(token: &str, key: &VerifyingKey, now: u64) -> Result<MappingClaims> {
+    let invalid = |reason: &str| ProvisionError::InvalidCertificate(reason.to_string());
+
+    let mut parts = token.split('.');
+    let (Some(header), Some(claims), Some(signature), None) = (parts.next(), parts.next(), parts.next(), parts.next()) else {
+        return Err(invalid("not a JWT"));
+    };
+
+    let header: Header = BASE64URL
+        .decode(header)
+        .ok()
+        .and_then(|raw| serde_json::from_slice(&raw).ok())
+        .ok_or_else(|| invalid("malformed header"))?;
+    // Only the algorithm certificates are issued with; never `none`
+    if header.alg != "EdDSA" {
+        return Err(invalid(&format!("unexpected algorithm {}", header.alg)));
+    }
+
+    let signature: [u8; 64] = BASE64URL
+        .decode(signature)
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or_else(|| invalid("malformed signature"))?;
+    let signing_input = &token[..token.rfind('.').expect("token has three parts")];
+    key.verify_strict(signing_input.as_bytes(), &Signature::from_bytes(&signature))
+        .map_err(|_| invalid("signature does not match the certificate key"))?;
+
+    let claims: MappingClaims = BASE64URL
+        .decode(claims)
+        .ok()
+        .and_then(|raw| serde_json::from_slice(&raw).ok())
+        .ok_or_else(|| invalid("malformed claims"))?;
+    if claims.exp <= now {
+        return Err(invalid(&format!("expired at {}", claims.exp)));
+    }
+    Ok(claims)
+}
@@ -0,0 +1,177 @@
+//! Chain Identifiers
+//!
+//! Chains are identified by CAIP-2 ids (`namespace:reference`, e.g.
+//! `eip155:137`, `solana:5eykt4UsFv8P8NJdTREpY1vzqKqZKvdp`), so non-EVM chains
+//! and rollups without a numeric EVM chain id can be mapped too.
+//!
+//! Compatibility with the original `u64` chain ids:
+//! - Requests may still send a bare number (or numeric string); it is read as
+//!   `eip155:{n}`
+//! - Requests may also name a built-in chain (`polygon`, `arbitrum`, see
+//!   `chains::by_name`); responses carry the CAIP-2 id and the registry name
+//! - KV keys use the bare number for `eip155` chains (`{solana_pubkey}:137`),
+//!   so existing mappings keep their keys. Other chains use the full CAIP-2 id
+//!   (`{solana_pubkey}:solana:5eykt…`), which cannot collide: Solana pubkeys
+//!   contain no `:` and namespaces must start with a letter.
+
+use crate::chains;
+use crate::error::{ProvisionError, Result};
+use crate::wildcard;
+use serde::{Deserialize, Deserializer, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+/// CAIP-2 namespace of EVM chains
+pub const EIP155: &str = "eip155";
+
+/// A validated CAIP-2 chain id
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize)]
+#[serde(into = "String")]
+pub struct ChainId(String);
+
+impl ChainId {
+    /// Parse a CAIP-2 id, or a bare decimal EVM chain id (`137` → `eip155:137`)
+    pub fn parse(chain_id: &str) -> Result<Self> {
+        if let Ok(evm_chain_id) = chain_id.parse::<u64>() {
+            return Ok(Self::eip155(evm_chain_id));
+        }
+
+        let invalid = || ProvisionError::InvalidChainId(chain_id.to_string());
+        let (namespace, reference) = chain_id.split_once(':').ok_or_else(invalid)?;
+
+        let namespace_valid = (3..=8).contains(&namespace.len())
+            && namespace.starts_with(|c: char| c.is_ascii_lowercase())
+            && namespace.bytes().all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'-');
+        let reference_valid = (1..=32).contains(&reference.len())
+            && reference.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_');
+        if !namespace_valid || !reference_valid {
+            return Err(invalid());
+        }
+
+        if namespace == EIP155 {
+            // Canonical decimal form, so `eip155:0137` and `eip155:137` are one chain
+            let evm_chain_id = reference.parse::<u64>().map_err(|_| invalid())?;
+            return Ok(Self::eip155(evm_chain_id));
+        }
+
+        Ok(Self(chain_id.to_string()))
+    }
+
+    /// `parse`, also accepting the name of a built-in chain (`polygon`), as
+    /// requests send chain ids. Ids read back from keys use `parse`.
+    pub fn resolve(chain_id: &str) -> Result<Self> {
+        match chains::by_name(chain_id) {
+            Some(chain_id) => Ok(chain_id),
+            None => Self::parse(chain_id),
+        }
+    }
+
+    /// The wildcard `*` of store requests: every EVM chain (see `wildcard`).
+    /// Not a chain id `parse` or `resolve` accept.
+    pub fn wildcard() -> Self {
+        Self(wildcard::WILDCARD.to_string())
+    }
+
+    pub fn is_wildcard(&self) -> bool {
+        self.0 == wildcard::WILDCARD
+    }
+
+    /// `eip155:{chain_id}`
+    pub fn eip155(evm_chain_id: u64) -> Self {
+        Self(format!("{}:{}", EIP155, evm_chain_id))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    pub fn namespace(&self) -> &str {
+        self.0.split_once(':').map_or("", |(namespace, _)| namespace)
+    }
+
+    pub fn reference(&self) -> &str {
+        self.0.split_once(':').map_or("", |(_, reference)| reference)
+    }
+
+    /// The numeric chain id of an `eip155` chain
+    pub fn evm_chain_id(&self) -> Option<u64> {
+        if self.namespace() != EIP155 {
+            return None;
+        }
+        self.reference().parse().ok()
+    }
+
+    /// Form used inside KV keys and key names: the bare number for `eip155`
+    /// chains, the CAIP-2 id otherwise
+    pub fn key_segment(&self) -> &str {
+        if self.namespace() == EIP155 {
+            self.reference()
+        } else {
+            &self.0
+        }
+    }
+}
+
+impl fmt::Display for ChainId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl FromStr for ChainId {
+    type Err = ProvisionError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Self::parse(s)
+    }
+}
+
+impl From<u64> for ChainId {
+    fn from(evm_chain_id: u64) -> Self {
+        Self::eip155(evm_chain_id)
+    }
+}
+
+impl From<ChainId> for String {
+    fn from(chain_id: ChainId) -> String {
+        chain_id.0
+    }
+}
+
+#[cfg(feature = "openapi")]
+impl schemars::JsonSchema for ChainId {
+    fn schema_name() -> std::borrow::Cow<'static, str> {
+        "ChainId".into()
+    }
+
+    fn json_schema(_: &mut schemars::SchemaGenerator) -> schemars::Schema {
+        schemars::json_schema!({
+            "type": ["string", "integer"],
+            "description": "CAIP-2 chain id (`eip155:137`); a bare number is read as an eip155 chain id, and a built-in chain's name (`polygon`) as its id. Responses always use the CAIP-2 form.",
+        })
+    }
+}
+
+/// A chain id as requests send it, before it is resolved
+#[derive(Deserialize)]
+#[serde(untagged)]
+pub(crate) enum RawChainId {
+    Number(u64),
+    Str(String),
+}
+
+impl RawChainId {
+    pub(crate) fn resolve(self) -> Result<ChainId> {
+        match self {
+            Self::Number(evm_chain_id) => Ok(ChainId::eip155(evm_chain_id)),
+            Self::Str(chain_id) => ChainId::resolve(&chain_id),
+        }
+    }
+}
+
+/// Accepts `"eip155:137"` as well as the legacy `137` and names (`"polygon"`)
+impl<'de> Deserialize<'de> for ChainId {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        RawChainId::deserialize(deserializer)?.resolve().map_err(serde::de::Error::custom)
+    }
+}
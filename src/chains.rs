@@ -0,0 +1,227 @@
+//! Chain Registry
+//!
+//! Mappings are only created for chains in the registry, so a typo such as
+//! `1370` fails instead of silently creating a junk mapping. The registry is
+//! the built-in `KNOWN_CHAINS` list plus admin overrides stored in the main
+//! bucket, which can disable a known chain or register a new one at runtime.
+//!
+//! ## Key Schema
+//! ```text
+//! registry:{chain_id} → ChainEntry     # Admin override of / addition to KNOWN_CHAINS
+//! registry:index      → [chain_id, …]  # Chains with an override (sorted)
+//! ```
+//!
+//! `{chain_id}` uses `ChainId::key_segment`, like the mapping keys.
+
+use crate::chain_id::ChainId;
+use crate::kv::KvStore;
+use crate::error::{ProvisionError, Result};
+use serde::{Deserialize, Serialize};
+
+/// Built-in EVM chains: (chain id, name, testnet)
+pub const KNOWN_CHAINS: &[(u64, &str, bool)] = &[
+    (1, "Ethereum", false),
+    (10, "OP Mainnet", false),
+    (56, "BNB Smart Chain", false),
+    (137, "Polygon", false),
+    (8453, "Base", false),
+    (42161, "Arbitrum One", false),
+    (43114, "Avalanche C-Chain", false),
+    (80002, "Polygon Amoy", true),
+    (84532, "Base Sepolia", true),
+    (421614, "Arbitrum Sepolia", true),
+    (11155111, "Sepolia", true),
+    (11155420, "OP Sepolia", true),
+];
+
+/// Short names requests may use besides the built-in chains' own names
+pub const CHAIN_ALIASES: &[(&str, u64)] = &[
+    ("optimism", 10),
+    ("bsc", 56),
+    ("bnb", 56),
+    ("arbitrum", 42161),
+    ("avalanche", 43114),
+    ("avax", 43114),
+];
+
+/// The built-in chain called `name`: its name in `KNOWN_CHAINS` (`polygon`,
+/// `Arbitrum One`, `arbitrum-one`) or one of `CHAIN_ALIASES` (`arbitrum`),
+/// ignoring case and with `-`, `_` and spaces alike
+pub fn by_name(name: &str) -> Option<ChainId> {
+    let slug = |name: &str| name.trim().to_ascii_lowercase().replace([' ', '_'], "-");
+    let wanted = slug(name);
+    KNOWN_CHAINS
+        .iter()
+        .map(|&(id, name, _)| (id, name))
+        .chain(CHAIN_ALIASES.iter().map(|&(name, id)| (id, name)))
+        .find(|(_, name)| slug(name) == wanted)
+        .map(|(id, _)| ChainId::eip155(id))
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ChainInfo {
+    pub chain_id: ChainId,
+    pub name: String,
+    pub testnet: bool,
+    pub enabled: bool,
+}
+
+/// Stored admin override of a chain
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ChainEntry {
+    pub name: String,
+    pub testnet: bool,
+    pub enabled: bool,
+    /// Admin who last changed this chain
+    pub updated_by: String,
+    /// Unix timestamp (seconds)
+    pub updated_at: u64,
+}
+
+/// Key of a chain's override: `registry:{chain_id}`
+pub fn registry_key(chain_id: &ChainId) -> String {
+    format!("registry:{}", chain_id.key_segment())
+}
+
+/// Key of the list of overridden chains
+pub const REGISTRY_INDEX_KEY: &str = "registry:index";
+
+fn builtin(chain_id: &ChainId) -> Option<ChainInfo> {
+    let evm_chain_id = chain_id.evm_chain_id()?;
+    KNOWN_CHAINS
+        .iter()
+        .find(|(id, _, _)| *id == evm_chain_id)
+        .map(|&(_, name, testnet)| ChainInfo {
+            chain_id: chain_id.clone(),
+            name: name.to_string(),
+            testnet,
+            enabled: true,
+        })
+}
+
+fn get_entry(kv: &impl KvStore, chain_id: &ChainId) -> Result<Option<ChainEntry>> {
+    kv.get(&registry_key(chain_id))?
+        .map(|raw| serde_json::from_str(&raw).map_err(|e| ProvisionError::corrupt("chain entry", e)))
+        .transpose()
+}
+
+/// Registry entry of a chain, `None` if the chain is unknown
+pub fn get_chain(kv: &impl KvStore, chain_id: &ChainId) -> Result<Option<ChainInfo>> {
+    Ok(resolve(chain_id, get_entry(kv, chain_id)?))
+}
+
+/// Registry entries of several chains, read with one `get_many`
+pub fn get_chains(kv: &impl KvStore, chain_ids: &[ChainId]) -> Result<Vec<Option<ChainInfo>>> {
+    let keys: Vec<String> = chain_ids.iter().map(registry_key).collect();
+    chain_ids
+        .iter()
+        .zip(kv.get_many(&keys)?)
+        .map(|(chain_id, raw)| {
+            let entry = raw
+                .map(|raw| serde_json::from_str(&raw).map_err(|e| ProvisionError::corrupt("chain entry", e)))
+                .transpose()?;
+            Ok(resolve(chain_id, entry))
+        })
+        .collect()
+}
+
+/// Stored override if there is one, else the built-in entry
+fn resolve(chain_id: &ChainId, entry: Option<ChainEntry>) -> Option<ChainInfo> {
+    match entry {
+        Some(entry) => Some(ChainInfo {
+            chain_id: chain_id.clone(),
+            name: entry.name,
+            testnet: entry.testnet,
+            enabled: entry.enabled,
+        }),
+        None => builtin(chain_id),
+    }
+}
+
+/// Every known chain: built-in chains first, then registered ones
+pub fn list_chains(kv: &impl KvStore) -> Result<Vec<ChainInfo>> {
+    let mut chains = Vec::new();
+    for &(evm_chain_id, _, _) in KNOWN_CHAINS {
+        chains.extend(get_chain(kv, &ChainId::eip155(evm_chain_id))?);
+    }
+    for chain_id in get_index(kv)? {
+        if builtin(&chain_id).is_none() {
+            chains.extend(get_chain(kv, &chain_id)?);
+        }
+    }
+    Ok(chains)
+}
+
+/// Fail unless every chain is known and enabled; their registry entries otherwise
+pub fn require_enabled(kv: &impl KvStore, chain_ids: &[ChainId]) -> Result<Vec<ChainInfo>> {
+    let mut chains = Vec::with_capacity(chain_ids.len());
+    for chain_id in chain_ids {
+        match get_chain(kv, chain_id)? {
+            Some(chain) if chain.enabled => chains.push(chain),
+            Some(chain) => {
+                return Err(ProvisionError::ChainDisabled {
+                    chain_id: chain_id.to_string(),
+                    name: chain.name,
+                })
+            }
+            None => return Err(ProvisionError::UnknownChain(chain_id.to_string())),
+        }
+    }
+    Ok(chains)
+}
+
+/// Enable or disable a chain. Registering a chain that is not built in
+/// requires a `name`; `name`/`testnet` otherwise keep their current values.
+pub fn set_chain(
+    kv: &impl KvStore,
+    chain_id: &ChainId,
+    enabled: bool,
+    name: Option<&str>,
+    testnet: Option<bool>,
+    actor: &str,
+    now: u64,
+) -> Result<ChainInfo> {
+    let current = get_chain(kv, chain_id)?;
+    let name = match (name, &current) {
+        (Some(name), _) if !name.is_empty() => name.to_string(),
+        (_, Some(current)) => current.name.clone(),
+        _ => return Err(ProvisionError::ChainNameRequired(chain_id.to_string())),
+    };
+
+    let entry = ChainEntry {
+        name,
+        testnet: testnet.or(current.map(|c| c.testnet)).unwrap_or(false),
+        enabled,
+        updated_by: actor.to_string(),
+        updated_at: now,
+    };
+    let raw = serde_json::to_string(&entry).expect("chain entry serialization cannot fail");
+    kv.set(&registry_key(chain_id), &raw)?;
+    add_to_index(kv, chain_id)?;
+
+    Ok(ChainInfo {
+        chain_id: chain_id.clone(),
+        name: entry.name,
+        testnet: entry.testnet,
+        enabled,
+    })
+}
+
+fn get_index(kv: &impl KvStore) -> Result<Vec<ChainId>> {
+    match kv.get(REGISTRY_INDEX_KEY)? {
+        Some(raw) => serde_json::from_str(&raw).map_err(|e| ProvisionError::corrupt("chain registry index", e)),
+        None => Ok(Vec::new()),
+    }
+}
+
+fn add_to_index(kv: &impl KvStore, chain_id: &ChainId) -> Result<()> {
+    let mut index = get_index(kv)?;
+    if index.contains(chain_id) {
+        return Ok(());
+    }
+    index.push(chain_id.clone());
+    index.sort_unstable();
+
+    let raw = serde_json::to_string(&index).expect("chain registry index serialization cannot fail");
+    kv.set(REGISTRY_INDEX_KEY, &raw)
+}
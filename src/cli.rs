@@ -0,0 +1,309 @@
+//! Operator CLI (`cli` feature)
+//!
+//! What the `provisioner-cli` binary runs: one subcommand per operation,
+//! with flags instead of hand-written JSON. A command goes either to a
+//! provisioning server (`server`) over HTTP, or straight to the library
+//! handlers over a local KV file, a `MemoryKvStore` saved as one JSON object
+//! between runs. The C2F bucket is only reachable from the policy; against a
+//! deployment, use the server or invoke the policy.
+//!
+//! ```text
+//! provisioner-cli (--server URL | --kv-file PATH) <command> [flags]
+//!
+//! provision  --solana-pubkey P --message M --signature S [--chain ID]... [--label L]
+//! get        --solana-pubkey P [--chain ID]...
+//! update     --solana-pubkey P --chain ID [--expected-version N] [--label L]
+//! export     [--cursor C] [--limit N]
+//! verify     [--cursor C] [--limit N]
+//! reconcile  [--cursor C] [--limit N] [--repair]
+//! ```
+//!
+//! The server serves `provision`, `get` and `update`; the others need a KV
+//! file. Whoever holds a KV file can rewrite it anyway, so over one the
+//! operator (`OPERATOR_ENV`) is its admin; the server's `update` checks the
+//! requester its proxy authenticated instead. Flags are checked by the request types themselves: they are
+//! collected into the JSON body the command's request deserializes from.
+
+use crate::address::SolanaPubkey;
+use crate::admin::{self, Requester};
+use crate::chain_id::ChainId;
+use crate::cubesigner_client::{HttpMethod, HttpRequest, HttpTransport, DEFAULT_TIMEOUT};
+use crate::error::{ProvisionError, Result};
+use crate::export::ExportRequest;
+use crate::keys::{KeyCreator, KeyLister};
+use crate::kv::KvStore;
+use crate::memory_kv::MemoryKvStore;
+use crate::reconcile::ReconcileRequest;
+use crate::verify::VerifyRequest;
+use crate::{ProvisionRequest, Provisioner, UpdateMappingRequest};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// Usage text printed on `--help` and on malformed arguments
+pub const USAGE: &str = "usage: provisioner-cli (--server URL | --kv-file PATH) <command> [flags]
+
+commands:
+  provision  --solana-pubkey P --message M --signature S [--chain ID]... [--label L]
+  get        --solana-pubkey P [--chain ID]...
+  update     --solana-pubkey P --chain ID [--expected-version N] [--label L]
+  export     [--cursor C] [--limit N]
+  verify     [--cursor C] [--limit N]
+  reconcile  [--cursor C] [--limit N] [--repair]
+
+provision, update and reconcile with --kv-file create or list keys in CubeSigner:
+set CUBESIGNER_API_URL, CUBESIGNER_ORG_ID and CUBESIGNER_SESSION_TOKEN.
+With --kv-file, PROVISIONER_OPERATOR names you in the audit log (default: provisioner-cli).";
+
+/// Environment variable naming the operator of a KV file
+pub const OPERATOR_ENV: &str = "PROVISIONER_OPERATOR";
+
+/// Operator recorded when `OPERATOR_ENV` is unset
+pub const DEFAULT_OPERATOR: &str = "provisioner-cli";
+
+/// Where commands go
+#[derive(Debug, Clone, PartialEq)]
+pub enum Backend {
+    /// Base URL of a provisioning server
+    Server(String),
+    /// JSON file holding the KV store (created on first write)
+    KvFile(PathBuf),
+}
+
+#[derive(Clone)]
+pub enum Command {
+    Provision(ProvisionRequest),
+    Get { solana_pubkey: SolanaPubkey, chain_ids: Vec<ChainId> },
+    Update(UpdateMappingRequest),
+    Export(ExportRequest),
+    Verify(VerifyRequest),
+    Reconcile(ReconcileRequest),
+}
+
+impl Command {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Provision(_) => "provision",
+            Self::Get { .. } => "get",
+            Self::Update(_) => "update",
+            Self::Export(_) => "export",
+            Self::Verify(_) => "verify",
+            Self::Reconcile(_) => "reconcile",
+        }
+    }
+
+    /// Whether the command creates or lists CubeSigner keys when run over a KV file
+    pub fn uses_cubesigner(&self) -> bool {
+        matches!(self, Self::Provision(_) | Self::Update(_) | Self::Reconcile(_))
+    }
+
+    /// Whether the command may write to the KV file
+    pub fn writes(&self) -> bool {
+        !matches!(self, Self::Export(_) | Self::Verify(_))
+    }
+}
+
+/// How a flag's values go into the request body
+#[derive(Clone, Copy)]
+enum Flag {
+    /// One string
+    One(&'static str),
+    /// Repeatable, collected into an array
+    Many(&'static str),
+    /// One non-negative integer
+    Number(&'static str),
+    /// No value: `true` when given
+    Switch(&'static str),
+}
+
+/// Flags of each command, by name
+fn flags(command: &str) -> Option<&'static [(&'static str, Flag)]> {
+    use Flag::*;
+    Some(match command {
+        "provision" => &[
+            ("--solana-pubkey", One("solana_pubkey")),
+            ("--message", One("message")),
+            ("--signature", One("signature")),
+            ("--chain", Many("chain_ids")),
+            ("--label", One("label")),
+        ],
+        "get" => &[("--solana-pubkey", One("solana_pubkey")), ("--chain", Many("chain_ids"))],
+        "update" => &[
+            ("--solana-pubkey", One("solana_pubkey")),
+            ("--chain", One("chain_id")),
+            ("--expected-version", Number("expected_version")),
+            ("--label", One("label")),
+        ],
+        "export" | "verify" => &[("--cursor", One("cursor")), ("--limit", Number("limit"))],
+        "reconcile" => &[("--cursor", One("cursor")), ("--limit", Number("limit")), ("--repair", Switch("repair"))],
+        _ => return None,
+    })
+}
+
+/// Flags of `get`, which has no request type of its own
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct GetArgs {
+    solana_pubkey: SolanaPubkey,
+    #[serde(default)]
+    chain_ids: Vec<ChainId>,
+}
+
+fn usage_error(message: String) -> ProvisionError {
+    ProvisionError::InvalidRequest(message)
+}
+
+fn request<T: DeserializeOwned>(body: Map<String, Value>) -> Result<T> {
+    serde_json::from_value(Value::Object(body)).map_err(|e| usage_error(e.to_string()))
+}
+
+/// Backend and command of the arguments after the program name
+pub fn parse(args: &[String]) -> Result<(Backend, Command)> {
+    let mut args = args.iter();
+    let backend = match (args.next().map(String::as_str), args.next()) {
+        (Some("--server"), Some(url)) => Backend::Server(url.trim_end_matches('/').to_string()),
+        (Some("--kv-file"), Some(path)) => Backend::KvFile(PathBuf::from(path)),
+        _ => return Err(usage_error("expected --server URL or --kv-file PATH first".to_string())),
+    };
+    let name = args.next().ok_or_else(|| usage_error("missing command".to_string()))?;
+    let flags = flags(name).ok_or_else(|| usage_error(format!("unknown command {}", name)))?;
+
+    let mut body = Map::new();
+    while let Some(arg) = args.next() {
+        let (_, flag) = flags
+            .iter()
+            .find(|(name, _)| name == arg)
+            .ok_or_else(|| usage_error(format!("unknown flag {} for {}", arg, name)))?;
+        let mut value = || args.next().ok_or_else(|| usage_error(format!("{} needs a value", arg)));
+        match *flag {
+            Flag::One(field) => {
+                if body.insert(field.to_string(), Value::from(value()?.as_str())).is_some() {
+                    return Err(usage_error(format!("{} given twice", arg)));
+                }
+            }
+            Flag::Many(field) => {
+                let values = body.entry(field).or_insert_with(|| Value::Array(Vec::new()));
+                values.as_array_mut().expect("only Many flags insert arrays").push(Value::from(value()?.as_str()));
+            }
+            Flag::Number(field) => {
+                let number: u64 = value()?.parse().map_err(|_| usage_error(format!("{} needs a number", arg)))?;
+                body.insert(field.to_string(), Value::from(number));
+            }
+            Flag::Switch(field) => {
+                body.insert(field.to_string(), Value::Bool(true));
+            }
+        }
+    }
+
+    let command = match name.as_str() {
+        "provision" => Command::Provision(request(body)?),
+        "get" => {
+            let GetArgs { solana_pubkey, chain_ids } = request(body)?;
+            Command::Get { solana_pubkey, chain_ids }
+        }
+        "update" => Command::Update(request(body)?),
+        "export" => Command::Export(request(body)?),
+        "verify" => Command::Verify(request(body)?),
+        _ => Command::Reconcile(request(body)?),
+    };
+    Ok((backend, command))
+}
+
+// =============================================================================
+// SERVER BACKEND
+// =============================================================================
+
+/// Outcome of a command: its response, or an error object
+/// (`{"code","message","retryable"}`, as the server sends them)
+pub type Outcome = std::result::Result<Value, Value>;
+
+/// Error object of `e`
+pub fn error_object(e: &ProvisionError) -> Value {
+    serde_json::to_value(e).expect("error serialization cannot fail")
+}
+
+fn json(req: &impl Serialize) -> String {
+    serde_json::to_string(req).expect("request serialization cannot fail")
+}
+
+/// Send `command` to the server at `url`
+pub fn run_on_server(transport: &impl HttpTransport, url: &str, command: &Command) -> Outcome {
+    let (method, path, body) = match command {
+        Command::Provision(req) => (HttpMethod::Post, "/provision".to_string(), Some(json(req))),
+        Command::Update(req) => (HttpMethod::Post, "/update".to_string(), Some(json(req))),
+        Command::Get { solana_pubkey, chain_ids } => {
+            let chain_ids: Vec<String> = chain_ids.iter().map(ToString::to_string).collect();
+            (HttpMethod::Get, format!("/mappings/{}?chain_ids={}", solana_pubkey, chain_ids.join(",")), None)
+        }
+        _ => {
+            let e = usage_error(format!("the server does not serve {}; use --kv-file", command.name()));
+            return Err(error_object(&e));
+        }
+    };
+    let unreachable = |message: String| serde_json::json!({ "code": "SERVER_UNREACHABLE", "message": message, "retryable": true });
+    let response = transport
+        .send(HttpRequest {
+            method,
+            url: format!("{}{}", url, path),
+            headers: vec![("Content-Type".to_string(), "application/json".to_string())],
+            body,
+            timeout: DEFAULT_TIMEOUT,
+        })
+        .map_err(unreachable)?;
+    let body: Value = serde_json::from_str(&response.body)
+        .map_err(|_| unreachable(format!("the server answered {} with a body that is not JSON", response.status)))?;
+    if response.status == 200 { Ok(body) } else { Err(body) }
+}
+
+// =============================================================================
+// KV FILE BACKEND
+// =============================================================================
+
+/// The KV store saved at `path`; empty if there is no file yet
+pub fn load_kv(path: &Path) -> Result<MemoryKvStore> {
+    let kv = MemoryKvStore::new();
+    let saved = match std::fs::read_to_string(path) {
+        Ok(saved) => saved,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(kv),
+        Err(e) => return Err(ProvisionError::Kv(format!("cannot read {}: {}", path.display(), e))),
+    };
+    let entries: BTreeMap<String, String> =
+        serde_json::from_str(&saved).map_err(|e| ProvisionError::corrupt(format!("KV file {}", path.display()), e))?;
+    for (key, value) in entries {
+        kv.set(&key, &value)?;
+    }
+    Ok(kv)
+}
+
+/// Save `kv` at `path`, replacing the file
+pub fn save_kv(path: &Path, kv: &MemoryKvStore) -> Result<()> {
+    let saved = serde_json::to_string_pretty(&kv.snapshot()).expect("KV snapshot serialization cannot fail");
+    // Write beside the file and rename, so an interrupted save keeps the old one
+    let partial = path.with_extension("partial");
+    std::fs::write(&partial, saved)
+        .and_then(|()| std::fs::rename(&partial, path))
+        .map_err(|e| ProvisionError::Kv(format!("cannot write {}: {}", path.display(), e)))
+}
+
+/// Run `command` with the library handlers over `kv`, as `operator`: the
+/// one admin of an in-memory allowlist, with single-step updates
+pub fn run_on_kv<S: KvStore, K: KeyCreator + KeyLister>(kv: S, keys: K, operator: &str, command: Command) -> Result<Value> {
+    let requester = Requester::new(Some(operator), Some(admin::ORG_OWNER_ROLE));
+    let admins = MemoryKvStore::new();
+    admin::set_admin(&admins, &requester, operator, true, 0)?;
+    let provisioner = Provisioner::new(kv, keys).with_admins(admins).with_single_step_updates();
+    Ok(match command {
+        Command::Provision(req) => to_value(provisioner.handle(req)?),
+        Command::Get { solana_pubkey, chain_ids } => to_value(provisioner.handle_get(&solana_pubkey, &chain_ids)?),
+        Command::Update(req) => to_value(provisioner.handle_update_mapping(&requester, req)?),
+        Command::Export(req) => to_value(provisioner.handle_export(&requester, req)?),
+        Command::Verify(req) => to_value(provisioner.handle_verify(&requester, req)?),
+        Command::Reconcile(req) => to_value(provisioner.handle_reconcile(&requester, req)?),
+    })
+}
+
+fn to_value(response: impl Serialize) -> Value {
+    serde_json::to_value(response).expect("response serialization cannot fail")
+}
@@ -0,0 +1,239 @@
+//! Runtime Configuration
+//!
+//! Parameters an admin can tune without rebuilding the policy: the default
+//! chain set, the rate limit, the mapping quota, how long self-service authorizations may be
+//! valid, addresses never to map or provision, the secret backends
+//! authenticate requests with (see `request_auth`), the pepper hashing keys
+//! (see `privacy`), the build to run in shadow mode (see `shadow`), and
+//! feature toggles. They live in one document of their own bucket;
+//! fields never set keep their defaults, which are the values the policy was
+//! built with before this bucket existed.
+//!
+//! Settings can tighten the built-in validation but not loosen it:
+//! `max_authorization_ttl_secs` is capped at `auth::MAX_AUTHORIZATION_TTL_SECS`,
+//! and `rate_limit` at `rate_limit::MAX_RATE_LIMIT_REQUESTS` per
+//! `rate_limit::MAX_RATE_LIMIT_WINDOW_SECS`.
+//!
+//! ## Key Schema (`config` bucket)
+//! ```text
+//! config → Config
+//! ```
+
+use crate::address::EvmAddress;
+use crate::auth::MAX_AUTHORIZATION_TTL_SECS;
+use crate::chain_id::ChainId;
+use crate::error::{ProvisionError, Result};
+use crate::kv::KvStore;
+use crate::privacy::MIN_PEPPER_LEN;
+use crate::quota::MappingQuota;
+use crate::rate_limit::{RateLimit, MAX_RATE_LIMIT_REQUESTS, MAX_RATE_LIMIT_WINDOW_SECS};
+use crate::request_auth::MIN_SECRET_LEN;
+use crate::shadow::MIN_SHADOW_BUILD_LEN;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+/// Bucket holding the configuration
+pub const CONFIG_BUCKET: &str = "config";
+
+/// Key of the configuration document
+pub const CONFIG_KEY: &str = "config";
+
+/// What replies show in place of `request_auth_secret` and `kv_pepper`
+pub const REDACTED: &str = "REDACTED";
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct Config {
+    /// Chains mapped when a store does not name any
+    pub default_chain_ids: Vec<ChainId>,
+    /// Stores and self-service updates allowed per Solana address
+    pub rate_limit: RateLimit,
+    /// Chains and labels allowed per Solana address (see `quota`)
+    pub mapping_quota: MappingQuota,
+    /// Longest an `update_self`/`link_external` authorization may be valid
+    pub max_authorization_ttl_secs: u64,
+    /// Whether `get` writes mappings for chains that inherit the default
+    /// address (see `mapping::get_materialized`)
+    pub materialize_inherited: bool,
+    /// Addresses refused on top of the built-in ones (see `address_sanity`)
+    pub denied_addresses: Vec<EvmAddress>,
+    /// Whether program ids and off-curve Solana addresses may be provisioned
+    /// (see `address_sanity`)
+    pub allow_program_pubkeys: bool,
+    /// Secret every request must carry an HMAC under (see `request_auth`);
+    /// replies show it as `REDACTED`
+    pub request_auth_secret: Option<String>,
+    /// Pepper hashing the Solana pubkeys in keys (see `privacy`); the policy
+    /// reads it from the default namespace only. Set once, as changing it
+    /// would orphan every key; replies show it as `REDACTED`
+    pub kv_pepper: Option<String>,
+    /// Git sha (or a prefix of it) of the build that runs in shadow mode
+    /// (see `shadow`)
+    pub shadow_build: Option<String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            default_chain_ids: vec![ChainId::eip155(1), ChainId::eip155(137), ChainId::eip155(42161)],
+            rate_limit: RateLimit::default(),
+            mapping_quota: MappingQuota::default(),
+            max_authorization_ttl_secs: MAX_AUTHORIZATION_TTL_SECS,
+            materialize_inherited: false,
+            denied_addresses: Vec::new(),
+            allow_program_pubkeys: false,
+            request_auth_secret: None,
+            kv_pepper: None,
+            shadow_build: None,
+        }
+    }
+}
+
+/// Changes to apply to the configuration; absent fields are left as they are
+#[derive(Deserialize, Debug, Clone, Default)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+#[serde(deny_unknown_fields)]
+pub struct ConfigUpdate {
+    #[serde(default)]
+    pub default_chain_ids: Option<Vec<ChainId>>,
+    #[serde(default)]
+    pub rate_limit: Option<RateLimit>,
+    #[serde(default)]
+    pub mapping_quota: Option<MappingQuota>,
+    #[serde(default)]
+    pub max_authorization_ttl_secs: Option<u64>,
+    #[serde(default)]
+    pub materialize_inherited: Option<bool>,
+    /// Replaces the whole list
+    #[serde(default)]
+    pub denied_addresses: Option<Vec<EvmAddress>>,
+    #[serde(default)]
+    pub allow_program_pubkeys: Option<bool>,
+    /// `""` turns request authentication off
+    #[serde(default)]
+    pub request_auth_secret: Option<String>,
+    /// Refused once a pepper is set
+    #[serde(default)]
+    pub kv_pepper: Option<String>,
+    /// `""` turns shadow mode off
+    #[serde(default)]
+    pub shadow_build: Option<String>,
+}
+
+impl Config {
+    fn apply(&mut self, update: ConfigUpdate) {
+        if let Some(default_chain_ids) = update.default_chain_ids {
+            self.default_chain_ids = default_chain_ids;
+        }
+        if let Some(rate_limit) = update.rate_limit {
+            self.rate_limit = rate_limit;
+        }
+        if let Some(mapping_quota) = update.mapping_quota {
+            self.mapping_quota = mapping_quota;
+        }
+        if let Some(max_authorization_ttl_secs) = update.max_authorization_ttl_secs {
+            self.max_authorization_ttl_secs = max_authorization_ttl_secs;
+        }
+        if let Some(materialize_inherited) = update.materialize_inherited {
+            self.materialize_inherited = materialize_inherited;
+        }
+        if let Some(denied_addresses) = update.denied_addresses {
+            self.denied_addresses = denied_addresses;
+        }
+        if let Some(allow_program_pubkeys) = update.allow_program_pubkeys {
+            self.allow_program_pubkeys = allow_program_pubkeys;
+        }
+        if let Some(secret) = update.request_auth_secret {
+            self.request_auth_secret = Some(secret).filter(|secret| !secret.is_empty());
+        }
+        if let Some(pepper) = update.kv_pepper {
+            self.kv_pepper = Some(pepper);
+        }
+        if let Some(shadow_build) = update.shadow_build {
+            self.shadow_build = Some(shadow_build.to_ascii_lowercase()).filter(|sha| !sha.is_empty());
+        }
+    }
+
+    fn validate(&self) -> Result<()> {
+        if self.default_chain_ids.is_empty() {
+            return Err(ProvisionError::InvalidRequest("default_chain_ids cannot be empty".to_string()));
+        }
+        let mut seen = HashSet::new();
+        if let Some(duplicate) = self.default_chain_ids.iter().find(|chain_id| !seen.insert(*chain_id)) {
+            return Err(ProvisionError::InvalidRequest(format!("duplicate default chain {}", duplicate)));
+        }
+        if !(1..=MAX_RATE_LIMIT_REQUESTS).contains(&self.rate_limit.max_requests) {
+            return Err(ProvisionError::InvalidRequest(format!("rate_limit.max_requests must be between 1 and {}", MAX_RATE_LIMIT_REQUESTS)));
+        }
+        if !(1..=MAX_RATE_LIMIT_WINDOW_SECS).contains(&self.rate_limit.window_secs) {
+            return Err(ProvisionError::InvalidRequest(format!("rate_limit.window_secs must be between 1 and {}", MAX_RATE_LIMIT_WINDOW_SECS)));
+        }
+        if self.mapping_quota.max_chains == 0 {
+            return Err(ProvisionError::InvalidRequest("mapping_quota.max_chains must be positive".to_string()));
+        }
+        if !(1..=MAX_AUTHORIZATION_TTL_SECS).contains(&self.max_authorization_ttl_secs) {
+            return Err(ProvisionError::InvalidRequest(format!(
+                "max_authorization_ttl_secs must be between 1 and {}",
+                MAX_AUTHORIZATION_TTL_SECS
+            )));
+        }
+        if self.request_auth_secret.as_ref().is_some_and(|secret| secret.len() < MIN_SECRET_LEN) {
+            return Err(ProvisionError::InvalidRequest(format!("request_auth_secret must be at least {} characters", MIN_SECRET_LEN)));
+        }
+        if self.kv_pepper.as_ref().is_some_and(|pepper| pepper.len() < MIN_PEPPER_LEN) {
+            return Err(ProvisionError::InvalidRequest(format!("kv_pepper must be at least {} characters", MIN_PEPPER_LEN)));
+        }
+        if let Some(sha) = &self.shadow_build {
+            if sha.len() < MIN_SHADOW_BUILD_LEN || !sha.chars().all(|c| c.is_ascii_hexdigit()) {
+                return Err(ProvisionError::InvalidRequest(format!("shadow_build must be a git sha of at least {} hex digits", MIN_SHADOW_BUILD_LEN)));
+            }
+        }
+        Ok(())
+    }
+
+    /// The configuration as shown to admins, without the request secret or
+    /// the pepper
+    pub fn redacted(mut self) -> Self {
+        if self.request_auth_secret.is_some() {
+            self.request_auth_secret = Some(REDACTED.to_string());
+        }
+        if self.kv_pepper.is_some() {
+            self.kv_pepper = Some(REDACTED.to_string());
+        }
+        self
+    }
+
+    /// Fail unless an authorization expiring at `expires_at` is valid for at
+    /// most `max_authorization_ttl_secs`
+    pub fn check_authorization_ttl(&self, expires_at: u64, now: u64) -> Result<()> {
+        if expires_at > now.saturating_add(self.max_authorization_ttl_secs) {
+            return Err(ProvisionError::InvalidRequest(format!(
+                "expires_at must be at most {}s in the future",
+                self.max_authorization_ttl_secs
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Current configuration (the defaults until an admin sets something)
+pub fn get_config(kv: &impl KvStore) -> Result<Config> {
+    match kv.get(CONFIG_KEY)? {
+        Some(raw) => serde_json::from_str(&raw).map_err(|e| ProvisionError::corrupt("configuration", e)),
+        None => Ok(Config::default()),
+    }
+}
+
+/// Apply `update` and store the result. Concurrent updates are not merged:
+/// the last one written wins.
+pub fn set_config(kv: &impl KvStore, update: ConfigUpdate) -> Result<Config> {
+    let mut config = get_config(kv)?;
+    if config.kv_pepper.is_some() && update.kv_pepper.is_some() {
+        return Err(ProvisionError::InvalidRequest("kv_pepper cannot be changed once set".to_string()));
+    }
+    config.apply(update);
+    config.validate()?;
+    let raw = serde_json::to_string(&config).expect("config serialization cannot fail");
+    kv.set(CONFIG_KEY, &raw)?;
+    Ok(config)
+}
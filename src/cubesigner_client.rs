@@ -0,0 +1,634 @@
+//! CubeSigner Management API Client
+//!
+//! Talks to the CubeSigner REST API directly (key create, key get, key list,
+//! EVM transaction and Solana message signing) instead of shelling out to the `cs` CLI. The HTTP layer is behind the
+//! `HttpTransport` trait so the client works both natively and inside WASM,
+//! where the host provides outbound HTTP.
+//!
+//! Every call has a timeout, and transient failures (transport errors, 429,
+//! 5xx) are retried with jittered exponential backoff (`RetryPolicy`).
+//! Permanent failures (401/403, 404, 409, other 4xx) are returned at once.
+//!
+//! Key names are deterministic (`keys`), so a key creation rejected because
+//! the name is taken (409) means an earlier attempt created the key, e.g. a
+//! retry after a lost response. `create_key` then returns that key instead.
+//!
+//! EVM keys created for mappings (`KeyCreator`) are restricted right after
+//! creation when the client has `KeyPolicies`: the policies are attached and
+//! the key is added to a role. Both steps are idempotent and also run for a key
+//! returned for a taken name, so a retry completes an interrupted attempt.
+//!
+//! ## Endpoints
+//! ```text
+//! POST /v0/org/{org_id}/keys             → create key(s)
+//! GET  /v0/org/{org_id}/keys/{key_id}    → get key
+//! GET  /v0/org/{org_id}/keys?page.start= → list keys (paginated)
+//! PATCH /v0/org/{org_id}/keys/{key_id}   → set a key's policies
+//! PUT  /v0/org/{org_id}/roles/{role_id}/add_keys → add keys to a role
+//! POST /v1/org/{org_id}/eth1/sign/{address} → sign an EVM transaction
+//! POST /v0/org/{org_id}/solana/sign/{address} → sign a Solana message
+//! ```
+
+use crate::auth;
+use crate::chain_id::ChainId;
+use crate::error::ProvisionError;
+use crate::keys::{self, CreatedKey, KeyClass, KeyCreator, KeyLister, KeyType, ListedKey, SolanaKeyCreator};
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::RandomState;
+use std::fmt;
+use std::hash::{BuildHasher, Hasher};
+use std::time::Duration;
+
+/// CubeSigner key type for Ethereum-style secp256k1 keys
+pub const KEY_TYPE_EVM: &str = "SecpEthAddr";
+
+/// CubeSigner key type for Solana ed25519 keys
+pub const KEY_TYPE_SOLANA: &str = "Ed25519SolanaAddr";
+
+/// Timeout of a single request unless set with `with_timeout`
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+// =============================================================================
+// HTTP TRANSPORT
+// =============================================================================
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HttpMethod {
+    Get,
+    Post,
+    Put,
+    Patch,
+}
+
+#[derive(Debug, Clone)]
+pub struct HttpRequest {
+    pub method: HttpMethod,
+    /// Full URL including query string
+    pub url: String,
+    pub headers: Vec<(String, String)>,
+    /// JSON body
+    pub body: Option<String>,
+    /// Give up after this long and return an error (retried like any transport error)
+    pub timeout: Duration,
+}
+
+#[derive(Debug, Clone)]
+pub struct HttpResponse {
+    pub status: u16,
+    pub body: String,
+}
+
+/// Sends HTTP requests on behalf of the client
+pub trait HttpTransport {
+    /// Perform the request. Only connection-level failures are errors;
+    /// non-2xx responses are returned as-is.
+    fn send(&self, request: HttpRequest) -> Result<HttpResponse, String>;
+}
+
+// =============================================================================
+// RETRIES
+// =============================================================================
+
+/// Waits between retries
+pub type Sleep = Box<dyn Fn(Duration) + Send + Sync>;
+
+/// How often a transiently failing call is attempted, and how long to wait in between
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Attempts in total, including the first (1 = no retries)
+    pub max_attempts: u32,
+    /// Backoff before the first retry; doubles with each further retry
+    pub base_delay: Duration,
+    /// Upper bound of a single backoff
+    pub max_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            base_delay: Duration::from_millis(200),
+            max_delay: Duration::from_secs(5),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Attempt every call once
+    pub fn none() -> Self {
+        Self { max_attempts: 1, ..Self::default() }
+    }
+
+    /// Wait before retry number `retry` (from 1): the exponential backoff,
+    /// of which the upper half is random so that clients retrying together
+    /// spread out
+    pub fn backoff(&self, retry: u32) -> Duration {
+        let exponential = self.base_delay.saturating_mul(1 << retry.saturating_sub(1).min(16)).min(self.max_delay);
+        let half = exponential / 2;
+        let random = RandomState::new().build_hasher().finish() as f64 / u64::MAX as f64;
+        half + half.mul_f64(random)
+    }
+}
+
+// =============================================================================
+// TYPES
+// =============================================================================
+
+/// Key as returned by the management API
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct KeyInfo {
+    /// e.g. `Key#0xcb373e47d769b06dee02f05c86dd8790e0358aee`
+    pub key_id: String,
+    /// e.g. `SecpEthAddr`
+    pub key_type: String,
+    /// For EVM and Solana keys this is the address
+    pub material_id: String,
+    /// Public key, 0x-prefixed hex (uncompressed SEC1 for secp256k1 keys)
+    #[serde(default)]
+    pub public_key: Option<String>,
+    #[serde(default)]
+    pub purpose: Option<String>,
+    #[serde(default)]
+    pub metadata: Option<KeyMetadata>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct KeyMetadata {
+    pub name: String,
+}
+
+/// One page of `list_keys`
+#[derive(Deserialize, Debug, Clone)]
+pub struct KeyPage {
+    pub keys: Vec<KeyInfo>,
+    /// Cursor for the next page, `None` on the last page
+    #[serde(default)]
+    pub last_evaluated_key: Option<String>,
+}
+
+#[derive(Serialize)]
+struct CreateKeyRequest<'a> {
+    count: u32,
+    key_type: &'a str,
+    metadata: KeyMetadata,
+    /// Quorum of an MPC key; absent for standard keys
+    #[serde(skip_serializing_if = "Option::is_none")]
+    mpc: Option<MpcQuorum>,
+}
+
+#[derive(Serialize)]
+struct MpcQuorum {
+    threshold: u8,
+    participants: u8,
+}
+
+#[derive(Deserialize)]
+struct CreateKeyResponse {
+    keys: Vec<KeyInfo>,
+}
+
+/// Restrictions put on every EVM key the client creates for a mapping
+/// (`with_key_policies`)
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct KeyPolicies {
+    /// Named signing policies to attach, by id (e.g. a transaction receiver
+    /// allowlist, a value limit)
+    pub policy_ids: Vec<String>,
+    /// Role to add the key to, so the role's sessions can sign with it
+    pub role_id: Option<String>,
+}
+
+#[derive(Serialize)]
+struct UpdateKeyRequest<'a> {
+    policy: &'a [String],
+}
+
+#[derive(Serialize)]
+struct AddKeysRequest<'a> {
+    key_ids: [&'a str; 1],
+}
+
+#[derive(Serialize)]
+struct Eth1SignRequest {
+    chain_id: u64,
+    tx: serde_json::Value,
+}
+
+#[derive(Deserialize)]
+struct Eth1SignResponse {
+    rlp_signed_tx: String,
+}
+
+#[derive(Serialize)]
+struct SolanaSignRequest<'a> {
+    message_base64: &'a str,
+}
+
+#[derive(Deserialize)]
+struct SolanaSignResponse {
+    signature: String,
+}
+
+#[derive(Serialize)]
+struct Eip712SignRequest<'a> {
+    chain_id: u64,
+    typed_data: &'a serde_json::Value,
+}
+
+#[derive(Deserialize)]
+struct Eip712SignResponse {
+    signature: String,
+}
+
+#[derive(Deserialize)]
+struct ApiErrorBody {
+    #[serde(default)]
+    message: Option<String>,
+}
+
+// =============================================================================
+// ERRORS
+// =============================================================================
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CubeSignerError {
+    /// The request never got a response (DNS, TLS, connection reset, ...)
+    Transport(String),
+    /// 401/403 - session expired or missing scope
+    Unauthorized(String),
+    /// 404
+    NotFound(String),
+    /// 409 - e.g. a key with the same metadata already exists
+    Conflict(String),
+    /// 429
+    RateLimited(String),
+    /// Any other non-2xx status
+    Api { status: u16, message: String },
+    /// 2xx with a body we could not decode
+    InvalidResponse(String),
+}
+
+impl fmt::Display for CubeSignerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Transport(msg) => write!(f, "CubeSigner transport error: {}", msg),
+            Self::Unauthorized(msg) => write!(f, "CubeSigner unauthorized: {}", msg),
+            Self::NotFound(msg) => write!(f, "CubeSigner not found: {}", msg),
+            Self::Conflict(msg) => write!(f, "CubeSigner conflict: {}", msg),
+            Self::RateLimited(msg) => write!(f, "CubeSigner rate limited: {}", msg),
+            Self::Api { status, message } => write!(f, "CubeSigner API error ({}): {}", status, message),
+            Self::InvalidResponse(msg) => write!(f, "Invalid CubeSigner response: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for CubeSignerError {}
+
+impl CubeSignerError {
+    /// Whether the same call may succeed later: transport errors, 429 and 5xx
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            Self::Transport(_) | Self::RateLimited(_) => true,
+            Self::Api { status, .. } => *status >= 500,
+            _ => false,
+        }
+    }
+
+    fn from_response(response: &HttpResponse) -> Self {
+        let message = serde_json::from_str::<ApiErrorBody>(&response.body)
+            .ok()
+            .and_then(|body| body.message)
+            .unwrap_or_else(|| response.body.clone());
+
+        match response.status {
+            401 | 403 => Self::Unauthorized(message),
+            404 => Self::NotFound(message),
+            409 => Self::Conflict(message),
+            429 => Self::RateLimited(message),
+            status => Self::Api { status, message },
+        }
+    }
+}
+
+// =============================================================================
+// CLIENT
+// =============================================================================
+
+pub struct CubeSignerClient<T> {
+    transport: T,
+    /// e.g. `https://gamma.signer.cubist.dev`
+    base_url: String,
+    org_id: String,
+    session_token: String,
+    timeout: Duration,
+    retry: RetryPolicy,
+    sleep: Sleep,
+    key_policies: KeyPolicies,
+}
+
+impl<T: HttpTransport> CubeSignerClient<T> {
+    pub fn new(transport: T, base_url: &str, org_id: &str, session_token: &str) -> Self {
+        Self {
+            transport,
+            base_url: base_url.trim_end_matches('/').to_string(),
+            org_id: org_id.to_string(),
+            session_token: session_token.to_string(),
+            timeout: DEFAULT_TIMEOUT,
+            retry: RetryPolicy::default(),
+            sleep: Box::new(std::thread::sleep),
+            key_policies: KeyPolicies::default(),
+        }
+    }
+
+    /// Timeout of each request (`DEFAULT_TIMEOUT` by default)
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Retry transient failures according to `retry` (`RetryPolicy::default()` by default)
+    pub fn with_retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Replace `std::thread::sleep` between retries (tests, hosts with their own timers)
+    pub fn with_sleep(mut self, sleep: impl Fn(Duration) + Send + Sync + 'static) -> Self {
+        self.sleep = Box::new(sleep);
+        self
+    }
+
+    /// Attach `policies` to every EVM key created for a mapping (none by default)
+    pub fn with_key_policies(mut self, policies: KeyPolicies) -> Self {
+        self.key_policies = policies;
+        self
+    }
+
+    /// Create one key of `key_type` tagged with metadata `name`, or return the
+    /// existing one if CubeSigner reports the name as taken
+    pub fn create_key(&self, key_type: &str, name: &str) -> Result<KeyInfo, CubeSignerError> {
+        self.create_key_of_class(key_type, name, KeyClass::Standard)
+    }
+
+    /// `create_key`, as an MPC key if `key_class` says so. An existing key
+    /// with the name is returned whatever its class.
+    pub fn create_key_of_class(&self, key_type: &str, name: &str, key_class: KeyClass) -> Result<KeyInfo, CubeSignerError> {
+        match self.create_new_key(key_type, name, key_class) {
+            Err(CubeSignerError::Conflict(message)) => match self.find_key(key_type, name)? {
+                Some(existing) => Ok(existing),
+                None => Err(CubeSignerError::Conflict(message)),
+            },
+            result => result,
+        }
+    }
+
+    /// Key of `key_type` with metadata `name`, searched page by page
+    pub fn find_key(&self, key_type: &str, name: &str) -> Result<Option<KeyInfo>, CubeSignerError> {
+        let mut page_start = None;
+        loop {
+            let page = self.list_keys(page_start.as_deref())?;
+            let found = page
+                .keys
+                .into_iter()
+                .find(|key| key.key_type == key_type && key.metadata.as_ref().is_some_and(|m| m.name == name));
+            if found.is_some() {
+                return Ok(found);
+            }
+            match page.last_evaluated_key {
+                Some(next) => page_start = Some(next),
+                None => return Ok(None),
+            }
+        }
+    }
+
+    fn create_new_key(&self, key_type: &str, name: &str, key_class: KeyClass) -> Result<KeyInfo, CubeSignerError> {
+        let mpc = match key_class {
+            KeyClass::Standard => None,
+            KeyClass::Mpc { threshold, participants } => Some(MpcQuorum { threshold, participants }),
+        };
+        let body = CreateKeyRequest {
+            count: 1,
+            key_type,
+            metadata: KeyMetadata { name: name.to_string() },
+            mpc,
+        };
+        let body = serde_json::to_string(&body).map_err(|e| CubeSignerError::InvalidResponse(e.to_string()))?;
+
+        let response: CreateKeyResponse = self.call(HttpMethod::Post, &self.org_url("keys"), Some(body))?;
+
+        response
+            .keys
+            .into_iter()
+            .next()
+            .ok_or_else(|| CubeSignerError::InvalidResponse("key create returned no keys".into()))
+    }
+
+    /// Replace the policies of `key_id` with the named policies `policy_ids`
+    pub fn set_key_policies(&self, key_id: &str, policy_ids: &[String]) -> Result<KeyInfo, CubeSignerError> {
+        let body = serde_json::to_string(&UpdateKeyRequest { policy: policy_ids }).map_err(|e| CubeSignerError::InvalidResponse(e.to_string()))?;
+        self.call(HttpMethod::Patch, &self.org_url(&format!("keys/{}", key_id)), Some(body))
+    }
+
+    /// Add `key_id` to role `role_id`; a key already in the role is left as is
+    pub fn add_key_to_role(&self, role_id: &str, key_id: &str) -> Result<(), CubeSignerError> {
+        let body = serde_json::to_string(&AddKeysRequest { key_ids: [key_id] }).map_err(|e| CubeSignerError::InvalidResponse(e.to_string()))?;
+        match self.call::<serde::de::IgnoredAny>(HttpMethod::Put, &self.org_url(&format!("roles/{}/add_keys", role_id)), Some(body)) {
+            Ok(_) | Err(CubeSignerError::Conflict(_)) => Ok(()),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Apply the client's `KeyPolicies` to a key created for a mapping
+    fn restrict(&self, mut key: CreatedKey) -> Result<CreatedKey, CubeSignerError> {
+        if !self.key_policies.policy_ids.is_empty() {
+            self.set_key_policies(&key.key_id, &self.key_policies.policy_ids)?;
+            key.policies = self.key_policies.policy_ids.clone();
+        }
+        if let Some(role_id) = &self.key_policies.role_id {
+            self.add_key_to_role(role_id, &key.key_id)?;
+        }
+        Ok(key)
+    }
+
+    pub fn get_key(&self, key_id: &str) -> Result<KeyInfo, CubeSignerError> {
+        self.call(HttpMethod::Get, &self.org_url(&format!("keys/{}", key_id)), None)
+    }
+
+    /// List one page of keys, starting after `page_start` (from a previous `KeyPage`)
+    pub fn list_keys(&self, page_start: Option<&str>) -> Result<KeyPage, CubeSignerError> {
+        let mut url = self.org_url("keys");
+        if let Some(start) = page_start {
+            url.push_str(&format!("?page.start={}", start));
+        }
+        self.call(HttpMethod::Get, &url, None)
+    }
+
+    /// Sign EVM transaction `tx` (JSON-RPC field names, hex quantities) for
+    /// `chain_id` with the EVM key at `address`. Returns the RLP-encoded
+    /// signed transaction, ready for `eth_sendRawTransaction`.
+    pub fn eth1_sign(&self, address: &str, chain_id: u64, tx: serde_json::Value) -> Result<String, CubeSignerError> {
+        let body = serde_json::to_string(&Eth1SignRequest { chain_id, tx }).map_err(|e| CubeSignerError::InvalidResponse(e.to_string()))?;
+        let url = format!("{}/v1/org/{}/eth1/sign/{}", self.base_url, self.org_id, address);
+        let response: Eth1SignResponse = self.call(HttpMethod::Post, &url, Some(body))?;
+        Ok(response.rlp_signed_tx)
+    }
+
+    /// Sign a serialized Solana transaction message (base64) with the Solana
+    /// key at `address`. Returns the ed25519 signature, 0x-prefixed hex.
+    pub fn solana_sign(&self, address: &str, message_base64: &str) -> Result<String, CubeSignerError> {
+        let body = serde_json::to_string(&SolanaSignRequest { message_base64 }).map_err(|e| CubeSignerError::InvalidResponse(e.to_string()))?;
+        let response: SolanaSignResponse = self.call(HttpMethod::Post, &self.org_url(&format!("solana/sign/{}", address)), Some(body))?;
+        Ok(response.signature)
+    }
+
+    /// Sign EIP-712 `typed_data` (the `eth_signTypedData_v4` JSON) whose
+    /// domain is on `chain_id` with the EVM key at `address`. Returns the
+    /// 65-byte signature, 0x-prefixed hex.
+    pub fn eip712_sign(&self, address: &str, chain_id: u64, typed_data: &serde_json::Value) -> Result<String, CubeSignerError> {
+        let body = serde_json::to_string(&Eip712SignRequest { chain_id, typed_data }).map_err(|e| CubeSignerError::InvalidResponse(e.to_string()))?;
+        let url = format!("{}/v1/org/{}/evm/eip712/sign/{}", self.base_url, self.org_id, address);
+        let response: Eip712SignResponse = self.call(HttpMethod::Post, &url, Some(body))?;
+        Ok(response.signature)
+    }
+
+    fn org_url(&self, path: &str) -> String {
+        format!("{}/v0/org/{}/{}", self.base_url, self.org_id, path)
+    }
+
+    /// `call_once`, retrying transient failures per the retry policy
+    fn call<R: for<'de> Deserialize<'de>>(
+        &self,
+        method: HttpMethod,
+        url: &str,
+        body: Option<String>,
+    ) -> Result<R, CubeSignerError> {
+        let mut attempt = 1;
+        loop {
+            match self.call_once(method, url, body.clone()) {
+                Err(e) if e.is_retryable() && attempt < self.retry.max_attempts => {
+                    (self.sleep)(self.retry.backoff(attempt));
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    fn call_once<R: for<'de> Deserialize<'de>>(
+        &self,
+        method: HttpMethod,
+        url: &str,
+        body: Option<String>,
+    ) -> Result<R, CubeSignerError> {
+        let request = HttpRequest {
+            method,
+            url: url.to_string(),
+            headers: vec![
+                ("Authorization".into(), self.session_token.clone()),
+                ("Content-Type".into(), "application/json".into()),
+            ],
+            body,
+            timeout: self.timeout,
+        };
+
+        let response = self.transport.send(request).map_err(CubeSignerError::Transport)?;
+
+        if !(200..300).contains(&response.status) {
+            return Err(CubeSignerError::from_response(&response));
+        }
+
+        serde_json::from_str(&response.body).map_err(|e| CubeSignerError::InvalidResponse(e.to_string()))
+    }
+}
+
+impl From<KeyInfo> for CreatedKey {
+    fn from(key: KeyInfo) -> Self {
+        Self {
+            address: key.material_id,
+            key_id: key.key_id,
+            policies: Vec::new(),
+        }
+    }
+}
+
+impl From<KeyInfo> for ListedKey {
+    fn from(key: KeyInfo) -> Self {
+        Self {
+            name: key.metadata.map(|metadata| metadata.name).unwrap_or_default(),
+            key_id: key.key_id,
+            address: key.material_id,
+        }
+    }
+}
+
+impl<T: HttpTransport> KeyCreator for CubeSignerClient<T> {
+    fn create_evm_key(&self, solana_pubkey: &str) -> crate::error::Result<CreatedKey> {
+        let key = self.create_key(KEY_TYPE_EVM, &keys::default_key_name(solana_pubkey))?;
+        Ok(self.restrict(key.into())?)
+    }
+
+    fn create_evm_key_for_chain(&self, solana_pubkey: &str, chain_id: &ChainId) -> crate::error::Result<CreatedKey> {
+        let key = self.create_key(KEY_TYPE_EVM, &keys::chain_key_name(solana_pubkey, chain_id))?;
+        Ok(self.restrict(key.into())?)
+    }
+
+    fn create_labeled_evm_key(&self, solana_pubkey: &str, label: &str, chain_id: Option<&ChainId>) -> crate::error::Result<CreatedKey> {
+        let key = self.create_key(KEY_TYPE_EVM, &keys::labeled_key_name(solana_pubkey, label, chain_id))?;
+        Ok(self.restrict(key.into())?)
+    }
+
+    /// Non-EVM secp256k1 keys are addressed by the EVM address of their public key
+    fn create_typed_key(&self, solana_pubkey: &str, key_type: KeyType, key_class: KeyClass) -> crate::error::Result<CreatedKey> {
+        if !key_type.has_evm_address() {
+            return Err(ProvisionError::InvalidRequest(format!("{} keys have no EVM address", key_type.as_str())));
+        }
+        let key = self.create_key_of_class(key_type.as_str(), &keys::typed_key_name(solana_pubkey, key_type), key_class)?;
+        if key_type == KeyType::SecpEthAddr {
+            return Ok(self.restrict(key.into())?);
+        }
+        let address = key
+            .public_key
+            .as_deref()
+            .and_then(evm_address_of_public_key)
+            .ok_or_else(|| CubeSignerError::InvalidResponse(format!("Key {} has no secp256k1 public key", key.key_id)))?;
+        Ok(self.restrict(CreatedKey { address, key_id: key.key_id, policies: Vec::new() })?)
+    }
+}
+
+/// EVM address of an SEC1-encoded secp256k1 public key (0x-prefixed hex)
+fn evm_address_of_public_key(public_key: &str) -> Option<String> {
+    let hex = public_key.strip_prefix("0x").unwrap_or(public_key);
+    let bytes: Vec<u8> = (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect::<Option<_>>()?;
+    let key = k256::ecdsa::VerifyingKey::from_sec1_bytes(&bytes).ok()?;
+    Some(auth::evm_address_of(&key))
+}
+
+impl<T: HttpTransport> SolanaKeyCreator for CubeSignerClient<T> {
+    fn create_solana_key(&self, evm_address: &str) -> crate::error::Result<CreatedKey> {
+        let key = self.create_key(KEY_TYPE_SOLANA, &keys::solana_key_name(evm_address))?;
+        Ok(key.into())
+    }
+}
+
+impl<T: HttpTransport> KeyLister for CubeSignerClient<T> {
+    fn list_evm_keys(&self) -> crate::error::Result<Vec<ListedKey>> {
+        let mut listed = Vec::new();
+        let mut page_start = None;
+        loop {
+            let page = self.list_keys(page_start.as_deref())?;
+            listed.extend(page.keys.into_iter().filter(|key| key.key_type == KEY_TYPE_EVM).map(ListedKey::from));
+            match page.last_evaluated_key {
+                Some(next) => page_start = Some(next),
+                None => return Ok(listed),
+            }
+        }
+    }
+
+    /// Stops at the page holding the key
+    fn find_evm_key(&self, name: &str) -> crate::error::Result<Option<ListedKey>> {
+        Ok(self.find_key(KEY_TYPE_EVM, name)?.map(ListedKey::from))
+    }
+}
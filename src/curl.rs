@@ -0,0 +1,82 @@
+//! `curl` Transport (`server` and `cli` features)
+//!
+//! An `HttpTransport` for the binaries, so they need no HTTP client crate:
+//! each request runs `curl`, which must be on the `PATH`.
+//!
+//! Headers carry the session token, so nothing of the request goes on curl's
+//! command line, which other users can read (`ps`, `/proc`): the URL, headers
+//! and body go to curl's stdin as a config file (`--config -`).
+
+use crate::cubesigner_client::{HttpMethod, HttpRequest, HttpResponse, HttpTransport};
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+/// `HttpTransport` running `curl`
+pub struct CurlTransport;
+
+impl HttpTransport for CurlTransport {
+    fn send(&self, request: HttpRequest) -> Result<HttpResponse, String> {
+        let mut command = Command::new("curl");
+        command
+            .args(["--silent", "--show-error", "--max-time"])
+            .arg(request.timeout.as_secs_f64().to_string())
+            .args(["--request", match request.method {
+                HttpMethod::Get => "GET",
+                HttpMethod::Post => "POST",
+                HttpMethod::Put => "PUT",
+                HttpMethod::Patch => "PATCH",
+            }])
+            // Status on a line of its own after the body
+            .args(["--write-out", "\n%{http_code}"])
+            .args(["--config", "-"]);
+        let mut child = command
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| format!("failed to run curl: {}", e))?;
+        let mut stdin = child.stdin.take().expect("stdin is piped");
+        stdin.write_all(config(&request).as_bytes()).map_err(|e| format!("failed to send request to curl: {}", e))?;
+        drop(stdin);
+
+        let output = child.wait_with_output().map_err(|e| format!("curl failed: {}", e))?;
+        if !output.status.success() {
+            return Err(String::from_utf8_lossy(&output.stderr).trim().to_string());
+        }
+        let stdout = String::from_utf8(output.stdout).map_err(|_| "response is not UTF-8".to_string())?;
+        let (body, status) = stdout.rsplit_once('\n').ok_or("curl printed no status")?;
+        let status = status.trim().parse().map_err(|_| format!("invalid status {:?}", status))?;
+        Ok(HttpResponse { status, body: body.to_string() })
+    }
+}
+
+/// curl config sending `request`'s URL, headers and body. `data-raw` sends
+/// the body as it is, even one starting with `@`.
+fn config(request: &HttpRequest) -> String {
+    let mut config = format!("url = {}\n", quoted(&request.url));
+    for (name, value) in &request.headers {
+        config.push_str(&format!("header = {}\n", quoted(&format!("{}: {}", name, value))));
+    }
+    if let Some(body) = &request.body {
+        config.push_str(&format!("data-raw = {}\n", quoted(body)));
+    }
+    config
+}
+
+/// `value` as a double-quoted curl config string
+fn quoted(value: &str) -> String {
+    let mut quoted = String::with_capacity(value.len() + 2);
+    quoted.push('"');
+    for c in value.chars() {
+        match c {
+            '\\' => quoted.push_str("\\\\"),
+            '"' => quoted.push_str("\\\""),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
@@ -0,0 +1,100 @@
+//! Destination Allowlists
+//!
+//! Lock a user's wallet on a chain to a handful of contracts or EOAs. Admins
+//! add and remove allowed destinations per user and chain; once a chain has
+//! any, the signing gate (`signing_gate`, the policy's `signing-gate` build)
+//! refuses transactions to every other address on it, and contract
+//! deployments (transactions without a `to`). It checks the `to` of the
+//! transaction being signed, whatever its value.
+//! Chains without an allowlist are not restricted.
+//!
+//! Contract addresses differ between chains, so allowlists are per chain, and
+//! cover whichever key the chain maps to: a rotated key is as restricted as
+//! the one it replaced.
+//!
+//! ## Key Schema
+//! ```text
+//! destinations:{solana_pubkey}:{chain_id} → JSON array of EVM addresses (sorted)
+//! ```
+
+use crate::address::{EvmAddress, SolanaPubkey};
+use crate::chain_id::ChainId;
+use crate::error::{ProvisionError, Result};
+use crate::kv::{self, KvStore};
+use serde::Serialize;
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct AllowedDestinations {
+    pub solana_pubkey: SolanaPubkey,
+    pub chain_id: ChainId,
+    /// Addresses the user's wallet may send transactions to (sorted; empty
+    /// when the chain is not restricted)
+    pub destinations: Vec<EvmAddress>,
+}
+
+/// Key of a user's allowlist on a chain: `destinations:{solana_pubkey}:{chain_id}`
+pub fn destinations_key(solana_pubkey: &SolanaPubkey, chain_id: &ChainId) -> String {
+    format!("destinations:{}:{}", solana_pubkey.as_str(), chain_id)
+}
+
+/// Addresses the user may send transactions to on `chain_id` (empty if unrestricted)
+pub fn get_allowed(kv: &impl KvStore, solana_pubkey: &SolanaPubkey, chain_id: &ChainId) -> Result<Vec<EvmAddress>> {
+    match kv.get(&destinations_key(solana_pubkey, chain_id))? {
+        Some(raw) => serde_json::from_str(&raw).map_err(|e| ProvisionError::corrupt("destination allowlist", e)),
+        None => Ok(Vec::new()),
+    }
+}
+
+/// Add `destination` to the user's allowlist on `chain_id`, restricting the
+/// chain if it was not yet. Fails with `NotProvisioned` for unknown users.
+pub fn add_allowed(
+    kv: &impl KvStore,
+    solana_pubkey: &SolanaPubkey,
+    chain_id: &ChainId,
+    destination: &EvmAddress,
+) -> Result<AllowedDestinations> {
+    if kv::get_default_mapping(kv, solana_pubkey)?.is_none() {
+        return Err(ProvisionError::NotProvisioned(solana_pubkey.to_string()));
+    }
+    let mut destinations = get_allowed(kv, solana_pubkey, chain_id)?;
+    if let Err(position) = destinations.binary_search(destination) {
+        destinations.insert(position, destination.clone());
+        write(kv, solana_pubkey, chain_id, &destinations)?;
+    }
+    Ok(AllowedDestinations { solana_pubkey: solana_pubkey.clone(), chain_id: chain_id.clone(), destinations })
+}
+
+/// Take `destination` off the user's allowlist on `chain_id`. Removing the
+/// last one lifts the restriction.
+pub fn remove_allowed(
+    kv: &impl KvStore,
+    solana_pubkey: &SolanaPubkey,
+    chain_id: &ChainId,
+    destination: &EvmAddress,
+) -> Result<AllowedDestinations> {
+    let mut destinations = get_allowed(kv, solana_pubkey, chain_id)?;
+    if let Ok(position) = destinations.binary_search(destination) {
+        destinations.remove(position);
+        write(kv, solana_pubkey, chain_id, &destinations)?;
+    }
+    Ok(AllowedDestinations { solana_pubkey: solana_pubkey.clone(), chain_id: chain_id.clone(), destinations })
+}
+
+/// Fail with `DestinationNotAllowed` if the user's wallet on `chain_id` is
+/// restricted and `to` is not on its allowlist (`None`: a contract deployment)
+pub fn check(kv: &impl KvStore, solana_pubkey: &SolanaPubkey, chain_id: &ChainId, to: Option<&EvmAddress>) -> Result<()> {
+    let destinations = get_allowed(kv, solana_pubkey, chain_id)?;
+    if destinations.is_empty() || to.is_some_and(|to| destinations.binary_search(to).is_ok()) {
+        return Ok(());
+    }
+    Err(ProvisionError::DestinationNotAllowed {
+        to: to.map(|to| to.to_string()),
+        chain_id: chain_id.to_string(),
+    })
+}
+
+fn write(kv: &impl KvStore, solana_pubkey: &SolanaPubkey, chain_id: &ChainId, destinations: &[EvmAddress]) -> Result<()> {
+    let raw = serde_json::to_string(destinations).expect("destination allowlist serialization cannot fail");
+    kv.set(&destinations_key(solana_pubkey, chain_id), &raw)
+}
@@ -0,0 +1,113 @@
+//! Development Keys (`dev-keys` feature)
+//!
+//! `DevKeys` stands in for CubeSigner in tests and local stacks. Each key is
+//! derived from a seed and its metadata name (`keys::default_key_name` and
+//! friends), so the same Solana address always gets the same EVM address,
+//! and the address is that of a real secp256k1 key:
+//!
+//! ```text
+//! secret  = HKDF-SHA256(salt = DEV_KEYS_SALT, ikm = seed, info = name ‖ counter (u32, big-endian))
+//! address = keccak256(uncompressed public key)[12..]
+//! ```
+//!
+//! `counter` starts at 0 and only moves on in the (negligible) case the
+//! output is not a valid secp256k1 scalar. Solana keys use the same output
+//! as an ed25519 seed.
+//!
+//! Anyone with the seed can sign for every key: never use these outside
+//! development.
+
+use crate::auth::evm_address_of;
+use crate::chain_id::ChainId;
+use crate::error::{ProvisionError, Result};
+use crate::keys::{self, CreatedKey, KeyClass, KeyCreator, KeyType, SolanaKeyCreator};
+use hkdf::Hkdf;
+use k256::ecdsa::SigningKey as EvmSigningKey;
+use sha2::Sha256;
+
+/// HKDF salt, versioning the derivation
+pub const DEV_KEYS_SALT: &[u8] = b"cubist-wallet-provisioner/dev-keys/v1";
+
+/// Seed of `DevKeys::default()`
+pub const DEFAULT_DEV_SEED: &[u8] = b"cubist-wallet-provisioner dev seed";
+
+/// Key creator deriving every key from a seed
+#[derive(Clone)]
+pub struct DevKeys {
+    seed: Vec<u8>,
+}
+
+impl Default for DevKeys {
+    fn default() -> Self {
+        Self::new(DEFAULT_DEV_SEED)
+    }
+}
+
+impl DevKeys {
+    pub fn new(seed: &[u8]) -> Self {
+        Self { seed: seed.to_vec() }
+    }
+
+    /// 32 bytes of key material for `name`, the first candidate `accept` takes
+    fn derive<T>(&self, name: &str, accept: impl Fn([u8; 32]) -> Option<T>) -> T {
+        let hkdf = Hkdf::<Sha256>::new(Some(DEV_KEYS_SALT), &self.seed);
+        (0u32..)
+            .find_map(|counter| {
+                let mut okm = [0u8; 32];
+                hkdf.expand_multi_info(&[name.as_bytes(), &counter.to_be_bytes()], &mut okm)
+                    .expect("32 bytes is a valid HKDF-SHA256 output length");
+                accept(okm)
+            })
+            .expect("some counter gives a valid key")
+    }
+
+    /// secp256k1 key with metadata `name`, so tests can sign as it
+    pub fn evm_signing_key(&self, name: &str) -> EvmSigningKey {
+        self.derive(name, |okm| EvmSigningKey::from_bytes(&okm.into()).ok())
+    }
+
+    /// ed25519 key with metadata `name`
+    pub fn solana_signing_key(&self, name: &str) -> ed25519_dalek::SigningKey {
+        self.derive(name, |okm| Some(ed25519_dalek::SigningKey::from_bytes(&okm)))
+    }
+
+    /// The key CubeSigner would have created under `name`, addressed by its
+    /// EVM address
+    fn evm_key(&self, name: &str) -> CreatedKey {
+        let address = evm_address_of(self.evm_signing_key(name).verifying_key());
+        CreatedKey { key_id: format!("Key#{}", address), address, policies: Vec::new() }
+    }
+}
+
+impl KeyCreator for DevKeys {
+    fn create_evm_key(&self, solana_pubkey: &str) -> Result<CreatedKey> {
+        Ok(self.evm_key(&keys::default_key_name(solana_pubkey)))
+    }
+
+    fn create_evm_key_for_chain(&self, solana_pubkey: &str, chain_id: &ChainId) -> Result<CreatedKey> {
+        Ok(self.evm_key(&keys::chain_key_name(solana_pubkey, chain_id)))
+    }
+
+    fn create_labeled_evm_key(&self, solana_pubkey: &str, label: &str, chain_id: Option<&ChainId>) -> Result<CreatedKey> {
+        Ok(self.evm_key(&keys::labeled_key_name(solana_pubkey, label, chain_id)))
+    }
+
+    /// Any secp256k1 key type, as one standard key (there are no MPC dev keys)
+    fn create_typed_key(&self, solana_pubkey: &str, key_type: KeyType, key_class: KeyClass) -> Result<CreatedKey> {
+        if !key_type.has_evm_address() {
+            return Err(ProvisionError::InvalidRequest(format!("{} keys have no EVM address", key_type.as_str())));
+        }
+        if !key_class.is_default() {
+            return Err(ProvisionError::InvalidRequest("MPC keys are not supported by this key creator".to_string()));
+        }
+        Ok(self.evm_key(&keys::typed_key_name(solana_pubkey, key_type)))
+    }
+}
+
+impl SolanaKeyCreator for DevKeys {
+    fn create_solana_key(&self, evm_address: &str) -> Result<CreatedKey> {
+        let key = self.solana_signing_key(&keys::solana_key_name(evm_address));
+        let address = bs58::encode(key.verifying_key().to_bytes()).into_string();
+        Ok(CreatedKey { key_id: format!("Key#{}", address), address, policies: Vec::new() })
+    }
+}
@@ -0,0 +1,152 @@
+//! Dry Runs
+//!
+//! A dry run performs every check of a mutating request and returns the
+//! response it would produce, without writing to the bucket or creating
+//! CubeSigner keys. The request's flow runs unchanged over a `DryRunKv`:
+//! reads go to the bucket, writes stay in memory, and the keys that would
+//! have been written are listed in the response.
+//!
+//! Where the request would create a CubeSigner key, the key's address is not
+//! known yet: `PlaceholderKeys` hands out `PLACEHOLDER_ADDRESS` instead and
+//! the response says `creates_key`. The policy never creates keys (the backend
+//! passes their addresses in), so its dry runs show the real addresses.
+//!
+//! Dry runs are reads: they are not audited, rate limited, recorded for
+//! idempotency or counted in the metrics.
+
+use crate::chain_id::ChainId;
+use crate::error::Result;
+use crate::keys::{CreatedKey, KeyCreator};
+use crate::kv::KvStore;
+use serde::Serialize;
+use std::cell::{Cell, RefCell};
+use std::collections::BTreeMap;
+
+/// Address shown for keys a dry run would create
+pub const PLACEHOLDER_ADDRESS: &str = "0x0000000000000000000000000000000000000000";
+
+/// Key id shown for keys a dry run would create
+pub const PLACEHOLDER_KEY_ID: &str = "Key#0x0000000000000000000000000000000000000000";
+
+/// Response of a dry run: the response the request would return, plus what
+/// it would have changed
+#[derive(Serialize, Debug)]
+pub struct DryRunResponse<T> {
+    /// Always `true`, so a dry run's response cannot pass for a real one
+    pub dry_run: bool,
+    /// Whether the request would create a CubeSigner key
+    pub creates_key: bool,
+    /// Keys the request would write, in the order of their first write
+    pub writes: Vec<String>,
+    #[serde(flatten)]
+    pub result: T,
+}
+
+/// A `KvStore` over another that keeps writes in memory
+pub struct DryRunKv<'a, S: ?Sized> {
+    inner: &'a S,
+    values: RefCell<BTreeMap<String, String>>,
+    order: RefCell<Vec<String>>,
+}
+
+impl<'a, S: KvStore + ?Sized> DryRunKv<'a, S> {
+    pub fn new(inner: &'a S) -> Self {
+        Self {
+            inner,
+            values: RefCell::new(BTreeMap::new()),
+            order: RefCell::new(Vec::new()),
+        }
+    }
+
+    /// Keys written so far, in the order of their first write
+    pub fn writes(&self) -> Vec<String> {
+        self.order.borrow().clone()
+    }
+
+    fn write(&self, key: &str, value: &str) {
+        if self.values.borrow_mut().insert(key.to_string(), value.to_string()).is_none() {
+            self.order.borrow_mut().push(key.to_string());
+        }
+    }
+}
+
+impl<S: KvStore + ?Sized> KvStore for DryRunKv<'_, S> {
+    fn get(&self, key: &str) -> Result<Option<String>> {
+        match self.values.borrow().get(key) {
+            Some(value) => Ok(Some(value.clone())),
+            None => self.inner.get(key),
+        }
+    }
+
+    fn set_if_absent(&self, key: &str, value: &str) -> Result<bool> {
+        if self.get(key)?.is_some() {
+            return Ok(false);
+        }
+        self.write(key, value);
+        Ok(true)
+    }
+
+    fn set(&self, key: &str, value: &str) -> Result<()> {
+        self.write(key, value);
+        Ok(())
+    }
+
+    fn list_keys(&self, after: Option<&str>, limit: usize) -> Result<Vec<String>> {
+        // The first `limit` keys of the union are among the first `limit` of each side
+        let mut keys = self.inner.list_keys(after, limit)?;
+        let values = self.values.borrow();
+        keys.extend(values.keys().filter(|key| after.is_none_or(|after| key.as_str() > after)).take(limit).cloned());
+        keys.sort();
+        keys.dedup();
+        keys.truncate(limit);
+        Ok(keys)
+    }
+}
+
+/// Run `f` over a `DryRunKv` of `kv`
+pub fn run<S: KvStore + ?Sized, T>(kv: &S, f: impl FnOnce(&DryRunKv<'_, S>) -> Result<T>) -> Result<DryRunResponse<T>> {
+    let overlay = DryRunKv::new(kv);
+    let result = f(&overlay)?;
+    Ok(DryRunResponse {
+        dry_run: true,
+        creates_key: false,
+        writes: overlay.writes(),
+        result,
+    })
+}
+
+/// `KeyCreator` that hands out `PLACEHOLDER_ADDRESS` instead of creating keys
+#[derive(Default)]
+pub struct PlaceholderKeys {
+    used: Cell<bool>,
+}
+
+impl PlaceholderKeys {
+    /// Whether a key was asked for
+    pub fn used(&self) -> bool {
+        self.used.get()
+    }
+
+    fn placeholder(&self) -> Result<CreatedKey> {
+        self.used.set(true);
+        Ok(CreatedKey {
+            address: PLACEHOLDER_ADDRESS.to_string(),
+            key_id: PLACEHOLDER_KEY_ID.to_string(),
+            policies: Vec::new(),
+        })
+    }
+}
+
+impl KeyCreator for PlaceholderKeys {
+    fn create_evm_key(&self, _solana_pubkey: &str) -> Result<CreatedKey> {
+        self.placeholder()
+    }
+
+    fn create_evm_key_for_chain(&self, _solana_pubkey: &str, _chain_id: &ChainId) -> Result<CreatedKey> {
+        self.placeholder()
+    }
+
+    fn create_labeled_evm_key(&self, _solana_pubkey: &str, _label: &str, _chain_id: Option<&ChainId>) -> Result<CreatedKey> {
+        self.placeholder()
+    }
+}
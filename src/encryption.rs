@@ -0,0 +1,245 @@
+//! Value Encryption at Rest (`encryption` feature)
+//!
+//! The KV store is shared with the rest of the org, so anyone who can read a
+//! bucket can read every address mapping in it. `Encrypted` seals each value
+//! with AES-256-GCM under the org's data key before it is written, and opens
+//! it on read. The flows keep passing plaintext values; only the stored form
+//! changes.
+//!
+//! The data key is kept wrapped: sealed under a key-encryption key (KEK)
+//! that CubeSigner holds as a policy secret (`KeyWrapper`, `SecretWrapper`).
+//! The policy reads the KEK and the wrapped key from its secrets for each
+//! request, so neither is compiled into the WASM or sent in a request.
+//!
+//! Each value is sealed with its key as associated data, so a ciphertext
+//! moved or copied under another key fails to open. Copying migrations go
+//! through an `Encrypted` view, which opens each value under its old key and
+//! seals it again under the new one.
+//!
+//! Nonces are derived from the key and the value (an HMAC under a key of
+//! their own), not drawn at random: the policy has no random source, and a
+//! nonce only repeats for the same value under the same key, whose
+//! ciphertext is then the same. So a rewritten value stays recognizable as
+//! unchanged.
+//!
+//! Values written before encryption was turned on are read as they are;
+//! `encrypt_plaintext_batch` rewrites them in bounded batches.
+//!
+//! ## Stored Form
+//! ```text
+//! enc:v1:{base64(nonce ‖ ciphertext ‖ tag)}
+//! ```
+
+use crate::error::{ProvisionError, Result};
+use crate::kv::KvStore;
+use crate::migrate::{DEFAULT_MIGRATION_BATCH, MAX_MIGRATION_BATCH};
+use aes_gcm::aead::{Aead, KeyInit, Payload};
+use aes_gcm::{Aes256Gcm, Nonce};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use hmac::{Hmac, Mac};
+use serde::Serialize;
+use sha2::Sha256;
+
+/// Prefix of an encrypted value
+pub const ENCRYPTED_PREFIX: &str = "enc:v1:";
+
+/// Length of the data key (AES-256)
+pub const DATA_KEY_LEN: usize = 32;
+
+/// Length of an AES-GCM nonce
+const NONCE_LEN: usize = 12;
+
+/// Associated data of the wrapped data key
+const WRAPPED_DATA_KEY_AAD: &str = "data_key";
+
+/// The org's data key, unwrapped
+#[derive(Clone)]
+pub struct DataKey {
+    cipher: Aes256Gcm,
+    nonces: Hmac<Sha256>,
+}
+
+impl DataKey {
+    pub fn new(secret: &[u8]) -> Result<Self> {
+        if secret.len() != DATA_KEY_LEN {
+            return Err(ProvisionError::InvalidRequest(format!("the data key must be {} bytes", DATA_KEY_LEN)));
+        }
+        // Separate subkeys for sealing and for deriving nonces
+        let subkey = |purpose: &str| {
+            let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(secret).expect("HMAC accepts keys of any length");
+            mac.update(purpose.as_bytes());
+            mac.finalize().into_bytes()
+        };
+        Ok(Self {
+            cipher: Aes256Gcm::new(&subkey("encrypt")),
+            nonces: <Hmac<Sha256> as Mac>::new_from_slice(&subkey("nonce")).expect("HMAC accepts keys of any length"),
+        })
+    }
+
+    /// Data key from its base64 encoding (what `unwrap_data_key` returns)
+    pub fn from_base64(encoded: &str) -> Result<Self> {
+        let secret = BASE64
+            .decode(encoded)
+            .map_err(|_| ProvisionError::InvalidRequest("the data key must be base64".to_string()))?;
+        Self::new(&secret)
+    }
+
+    /// Stored form of `value` under `key`
+    pub fn encrypt(&self, key: &str, value: &str) -> String {
+        // The key's length keeps `(key, value)` pairs from running together
+        let mut mac = self.nonces.clone();
+        mac.update(&(key.len() as u64).to_be_bytes());
+        mac.update(key.as_bytes());
+        mac.update(value.as_bytes());
+        let nonce = mac.finalize().into_bytes();
+        let nonce = Nonce::from_slice(&nonce[..NONCE_LEN]);
+        let sealed = self
+            .cipher
+            .encrypt(nonce, Payload { msg: value.as_bytes(), aad: key.as_bytes() })
+            .expect("AES-GCM seals values of any length");
+        let mut stored = nonce.to_vec();
+        stored.extend(sealed);
+        format!("{}{}", ENCRYPTED_PREFIX, BASE64.encode(stored))
+    }
+
+    /// Value of a form stored under `key`; values stored before encryption
+    /// are returned as they are
+    pub fn decrypt(&self, key: &str, stored: &str) -> Result<String> {
+        let Some(encoded) = stored.strip_prefix(ENCRYPTED_PREFIX) else {
+            return Ok(stored.to_string());
+        };
+        let sealed = BASE64.decode(encoded).map_err(|e| ProvisionError::corrupt("encrypted value", e))?;
+        if sealed.len() < NONCE_LEN {
+            return Err(ProvisionError::corrupt("encrypted value", "shorter than its nonce"));
+        }
+        let (nonce, sealed) = sealed.split_at(NONCE_LEN);
+        let value = self
+            .cipher
+            .decrypt(Nonce::from_slice(nonce), Payload { msg: sealed, aad: key.as_bytes() })
+            .map_err(|_| ProvisionError::corrupt("encrypted value", "not sealed with this data key under this key"))?;
+        String::from_utf8(value).map_err(|e| ProvisionError::corrupt("encrypted value", e))
+    }
+}
+
+/// Whether `stored` is an encrypted value
+pub fn is_encrypted(stored: &str) -> bool {
+    stored.starts_with(ENCRYPTED_PREFIX)
+}
+
+// =============================================================================
+// KEY WRAPPING
+// =============================================================================
+
+/// Source of the key-encryption key the data key is wrapped with
+pub trait KeyWrapper {
+    fn wrapping_key(&self) -> Result<[u8; DATA_KEY_LEN]>;
+}
+
+/// Key-encryption key kept as a secret (base64 of 32 bytes): the policy's
+/// `data_key_kek` secret, or wherever a backend keeps its secrets. It never
+/// leaves the secret store, so only CubeSigner and the org's secret holders
+/// can wrap or unwrap.
+pub struct SecretWrapper<'a>(pub &'a str);
+
+impl KeyWrapper for SecretWrapper<'_> {
+    fn wrapping_key(&self) -> Result<[u8; DATA_KEY_LEN]> {
+        BASE64
+            .decode(self.0.trim())
+            .ok()
+            .and_then(|key| <[u8; DATA_KEY_LEN]>::try_from(key).ok())
+            .ok_or(ProvisionError::NotConfigured("a 32-byte base64 key-encryption key"))
+    }
+}
+
+/// `data_key` sealed under the wrapper's key, to be kept with the deployment
+/// (the `wrapped_data_key` secret for the policy)
+pub fn wrap_data_key(data_key: &[u8], wrapper: &impl KeyWrapper) -> Result<String> {
+    DataKey::new(data_key)?;
+    Ok(DataKey::new(&wrapper.wrapping_key()?)?.encrypt(WRAPPED_DATA_KEY_AAD, &BASE64.encode(data_key)))
+}
+
+/// Base64 of the data key sealed in `wrapped`
+pub fn unwrap_data_key(wrapped: &str, wrapper: &impl KeyWrapper) -> Result<String> {
+    if !is_encrypted(wrapped) {
+        return Err(ProvisionError::InvalidRequest("not a wrapped data key".to_string()));
+    }
+    let data_key = DataKey::new(&wrapper.wrapping_key()?)?.decrypt(WRAPPED_DATA_KEY_AAD, wrapped)?;
+    DataKey::from_base64(&data_key)?;
+    Ok(data_key)
+}
+
+// =============================================================================
+// ENCRYPTED STORE
+// =============================================================================
+
+/// A `KvStore` storing values encrypted under the data key
+pub struct Encrypted<S> {
+    inner: S,
+    data_key: DataKey,
+}
+
+impl<S: KvStore> Encrypted<S> {
+    pub fn new(inner: S, data_key: DataKey) -> Self {
+        Self { inner, data_key }
+    }
+}
+
+impl<S: KvStore> KvStore for Encrypted<S> {
+    fn get(&self, key: &str) -> Result<Option<String>> {
+        self.inner.get(key)?.map(|stored| self.data_key.decrypt(key, &stored)).transpose()
+    }
+
+    fn set_if_absent(&self, key: &str, value: &str) -> Result<bool> {
+        self.inner.set_if_absent(key, &self.data_key.encrypt(key, value))
+    }
+
+    fn set(&self, key: &str, value: &str) -> Result<()> {
+        self.inner.set(key, &self.data_key.encrypt(key, value))
+    }
+
+    fn get_many(&self, keys: &[String]) -> Result<Vec<Option<String>>> {
+        self.inner
+            .get_many(keys)?
+            .into_iter()
+            .zip(keys)
+            .map(|(stored, key)| stored.map(|stored| self.data_key.decrypt(key, &stored)).transpose())
+            .collect()
+    }
+
+    fn delete(&self, key: &str) -> Result<()> {
+        self.inner.delete(key)
+    }
+
+    fn list_keys(&self, after: Option<&str>, limit: usize) -> Result<Vec<String>> {
+        self.inner.list_keys(after, limit)
+    }
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct EncryptionReport {
+    /// Keys looked at in this batch, encrypted ones included
+    pub scanned: usize,
+    /// Plaintext values rewritten encrypted
+    pub encrypted: usize,
+    /// Pass as `cursor` to continue; null once every key has been scanned
+    pub next_cursor: Option<String>,
+}
+
+/// Encrypt the plaintext values of one batch of keys after `cursor`, in
+/// place. `kv` is the bare bucket, not an `Encrypted` view of it. A write
+/// landing between a value's read and its rewrite is lost, so run it while
+/// the bucket is quiet.
+pub fn encrypt_plaintext_batch(kv: &impl KvStore, data_key: &DataKey, cursor: Option<&str>, limit: Option<usize>) -> Result<EncryptionReport> {
+    let limit = limit.unwrap_or(DEFAULT_MIGRATION_BATCH).clamp(1, MAX_MIGRATION_BATCH);
+    let keys = kv.list_keys(cursor, limit)?;
+    let next_cursor = if keys.len() < limit { None } else { keys.last().cloned() };
+
+    let mut report = EncryptionReport { scanned: keys.len(), encrypted: 0, next_cursor };
+    for (key, stored) in keys.iter().zip(kv.get_many(&keys)?) {
+        // Deleted since it was listed, or already encrypted
+        let Some(value) = stored.filter(|stored| !is_encrypted(stored)) else { continue };
+        kv.set(key, &data_key.encrypt(key, &value))?;
+        report.encrypted += 1;
+    }
+    Ok(report)
+}
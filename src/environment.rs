@@ -0,0 +1,185 @@
+//! Environment Prefixes
+//!
+//! Production and staging keep the same key shapes, so a staging build
+//! deployed against production buckets (or the reverse) would read and write
+//! the other environment's mappings as its own. A build configured for an
+//! environment stores every key under `{environment}:` instead, in every
+//! bucket, so such a mis-deploy finds nothing of the other's and writes
+//! beside it rather than over it.
+//!
+//! Builds without an environment keep the unprefixed layout of existing
+//! deployments. Moving one to an environment means copying its keys under the
+//! prefix first: `migrate_legacy_batch` does that in bounded batches, like
+//! `migrate`. Legacy keys are copied, not moved, so the previous build keeps
+//! working until the new one is deployed; they can be dropped afterwards.
+//!
+//! ## Key Schema
+//! ```text
+//! {environment}:{key}   # `key` as without an environment (tenant keys included)
+//! ```
+
+use crate::error::{ProvisionError, Result};
+use crate::kv::KvStore;
+use crate::migrate::{DEFAULT_MIGRATION_BATCH, MAX_MIGRATION_BATCH};
+use serde::Serialize;
+use std::fmt;
+
+/// Deployment environment a build is configured for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Environment {
+    Prod,
+    Staging,
+    Dev,
+}
+
+impl Environment {
+    pub const ALL: [Self; 3] = [Self::Prod, Self::Staging, Self::Dev];
+
+    /// Environment named by a build setting (`None`: no environment). Invalid
+    /// names panic, which is a compile error when evaluated in a `const`.
+    pub const fn from_build(name: Option<&str>) -> Option<Self> {
+        match name {
+            None => None,
+            Some(name) => match name.as_bytes() {
+                b"prod" => Some(Self::Prod),
+                b"staging" => Some(Self::Staging),
+                b"dev" => Some(Self::Dev),
+                _ => panic!("unknown environment (expected prod, staging or dev)"),
+            },
+        }
+    }
+
+    pub fn parse(name: &str) -> Result<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|env| env.as_str() == name)
+            .ok_or_else(|| ProvisionError::InvalidRequest(format!("unknown environment {:?} (expected prod, staging or dev)", name)))
+    }
+
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Prod => "prod",
+            Self::Staging => "staging",
+            Self::Dev => "dev",
+        }
+    }
+
+    /// Prefix of the environment's keys
+    pub const fn prefix(self) -> &'static str {
+        match self {
+            Self::Prod => "prod:",
+            Self::Staging => "staging:",
+            Self::Dev => "dev:",
+        }
+    }
+}
+
+impl fmt::Display for Environment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Whether `key` belongs to an environment. Legacy keys never do: their first
+/// segment is a key family (`default`, `reverse`, …) or a Solana pubkey.
+pub fn is_environment_key(key: &str) -> bool {
+    Environment::ALL.iter().any(|env| key.starts_with(env.prefix()))
+}
+
+/// A `KvStore` restricted to one environment's keys (`None`: the unprefixed
+/// legacy layout)
+pub struct EnvPrefixed<S> {
+    inner: S,
+    environment: Option<Environment>,
+}
+
+impl<S: KvStore> EnvPrefixed<S> {
+    pub fn new(inner: S, environment: Option<Environment>) -> Self {
+        Self { inner, environment }
+    }
+
+    /// The key `key` is stored under
+    fn key(&self, key: &str) -> String {
+        match self.environment {
+            Some(env) => format!("{}{}", env.prefix(), key),
+            None => key.to_string(),
+        }
+    }
+}
+
+impl<S: KvStore> KvStore for EnvPrefixed<S> {
+    fn get(&self, key: &str) -> Result<Option<String>> {
+        self.inner.get(&self.key(key))
+    }
+
+    fn set_if_absent(&self, key: &str, value: &str) -> Result<bool> {
+        self.inner.set_if_absent(&self.key(key), value)
+    }
+
+    fn set(&self, key: &str, value: &str) -> Result<()> {
+        self.inner.set(&self.key(key), value)
+    }
+
+    fn get_many(&self, keys: &[String]) -> Result<Vec<Option<String>>> {
+        let keys: Vec<String> = keys.iter().map(|key| self.key(key)).collect();
+        self.inner.get_many(&keys)
+    }
+
+    fn delete(&self, key: &str) -> Result<()> {
+        self.inner.delete(&self.key(key))
+    }
+
+    fn list_keys(&self, after: Option<&str>, limit: usize) -> Result<Vec<String>> {
+        let Some(env) = self.environment else {
+            return self.inner.list_keys(after, limit);
+        };
+        // The environment's keys sort together, right after the bare prefix
+        let start = format!("{}{}", env.prefix(), after.unwrap_or_default());
+        let keys = self.inner.list_keys(Some(&start), limit)?;
+        Ok(keys.into_iter().map_while(|key| key.strip_prefix(env.prefix()).map(str::to_string)).collect())
+    }
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct LegacyMigrationReport {
+    /// Keys looked at in this batch, environment keys included
+    pub scanned: usize,
+    /// Legacy keys copied under the environment's prefix
+    pub copied: usize,
+    /// Legacy keys whose copy already held the same value
+    pub unchanged: usize,
+    /// Legacy keys whose copy holds a different value (left as it is)
+    pub conflicts: Vec<String>,
+    /// Pass as `cursor` to continue; null once every key has been scanned
+    pub next_cursor: Option<String>,
+}
+
+/// Copy one batch of legacy (unprefixed) keys after `cursor` under `env`'s
+/// prefix. `kv` is the bare bucket, not an `EnvPrefixed` view of it.
+pub fn migrate_legacy_batch(
+    kv: &impl KvStore,
+    env: Environment,
+    cursor: Option<&str>,
+    limit: Option<usize>,
+) -> Result<LegacyMigrationReport> {
+    let limit = limit.unwrap_or(DEFAULT_MIGRATION_BATCH).clamp(1, MAX_MIGRATION_BATCH);
+    let keys = kv.list_keys(cursor, limit)?;
+    let next_cursor = if keys.len() < limit { None } else { keys.last().cloned() };
+
+    let legacy: Vec<String> = keys.iter().filter(|key| !is_environment_key(key)).cloned().collect();
+    let values = kv.get_many(&legacy)?;
+    let mut report = LegacyMigrationReport { scanned: keys.len(), copied: 0, unchanged: 0, conflicts: Vec::new(), next_cursor };
+    for (key, value) in legacy.into_iter().zip(values) {
+        // Deleted since it was listed
+        let Some(value) = value else { continue };
+        let target = format!("{}{}", env.prefix(), key);
+        if kv.set_if_absent(&target, &value)? {
+            report.copied += 1;
+        } else if kv.get(&target)?.as_deref() == Some(value.as_str()) {
+            report.unchanged += 1;
+        } else {
+            report.conflicts.push(key);
+        }
+    }
+    Ok(report)
+}
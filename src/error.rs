@@ -0,0 +1,348 @@
+//! Errors
+//!
+//! Every operation fails with a `ProvisionError`. Callers should branch on
+//! `code()` (stable, `SCREAMING_SNAKE_CASE`) and `is_retryable()`, not on the
+//! English message, which is meant for humans and may change.
+//!
+//! Serialized as `{"code": "NOT_PROVISIONED", "message": "…", "retryable": false}`,
+//! plus `current` (the stored record) for `VERSION_CONFLICT`.
+
+use crate::cubesigner_client::CubeSignerError;
+use crate::kv::MappingRecord;
+use serde::ser::SerializeStruct;
+use serde::{Serialize, Serializer};
+use std::fmt;
+
+pub type Result<T> = std::result::Result<T, ProvisionError>;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ProvisionError {
+    // -- Invalid input --
+    InvalidSolanaPubkey(String),
+    InvalidEvmAddress(String),
+    /// Mixed-case EVM address with a wrong EIP-55 checksum
+    InvalidChecksum(String),
+    InvalidChainId(String),
+    /// Signature is not well-formed (`expected` describes the encoding)
+    InvalidSignatureEncoding { expected: &'static str },
+    InvalidRecoveryId(u8),
+    /// Well-formed signature that was not produced by this address
+    SignatureMismatch(String),
+    /// A mapping certificate is malformed, not signed by the expected key, or expired (see `certificates`)
+    InvalidCertificate(String),
+    /// A policy reply is unsigned, altered, signed by another key, or too old (see `response_signing`)
+    InvalidResponseSignature(String),
+    InvalidNonce,
+    InvalidIdempotencyKey { max_len: usize },
+    /// Malformed or incomplete request
+    InvalidRequest(String),
+    BatchTooLarge { size: usize, max: usize },
+
+    // -- Rejected by current state --
+    NotProvisioned(String),
+    UnknownChain(String),
+    ChainDisabled { chain_id: String, name: String },
+    /// The chain belongs to the other network than the request's (see `network`)
+    WrongNetwork { chain_id: String, network: &'static str },
+    ChainNameRequired(String),
+    NonceUsed(String),
+    /// The nonce is not above the last one the Solana address used
+    NonceTooLow { nonce: u64, last: u64 },
+    /// An admin froze the EVM address (see `freeze`)
+    AddressFrozen(String),
+    /// The Solana or EVM address is on the blocklist (see `blocklist`)
+    Blocked(String),
+    /// The EVM address can never be a wallet, e.g. the zero address (see `address_sanity`)
+    UnusableAddress { evm_address: String, reason: &'static str },
+    /// The Solana address is a program, not a user (see `address_sanity`)
+    UnusablePubkey { solana_pubkey: String, reason: &'static str },
+    /// The record was erased at its owner's request; only a pseudonym is left (see `anonymize`)
+    Anonymized { pseudonym: String },
+    /// No admin bound the identity asking to sign to a user (see `signing_gate`)
+    UnknownSigner(String),
+    /// The signing key is not a current mapping of the user (see `signing_gate`)
+    AddressNotMapped { evm_address: String, solana_pubkey: String },
+    /// The address is externally owned; CubeSigner holds no key for it (see `mapping::link_external`)
+    ExternalAddress(String),
+    /// Signing would break the user's `limit` (see `spend_limits`); `allowed` is what it still permits, in wei
+    SpendLimitExceeded { limit: &'static str, value: String, allowed: String },
+    /// The user's wallet on the chain may only send to its allowlist (see `destinations`); `to` is `None` for a deployment
+    DestinationNotAllowed { to: Option<String>, chain_id: String },
+    /// The EVM address is already mapped to another Solana address
+    AddressOwned { evm_address: String, owner: String },
+    /// The idempotency key already completed a different request
+    IdempotencyKeyReused(String),
+    AuthorizationExpired { expires_at: u64 },
+    /// An import would replace keys holding other values (see `import`)
+    ImportConflict { conflicts: usize, first: String },
+    /// The chain mapping is not at the expected revision (`current` is what is stored now)
+    VersionConflict {
+        solana_pubkey: String,
+        chain_id: String,
+        expected: u64,
+        current: Option<Box<MappingRecord>>,
+    },
+
+    // -- Authorization and approval --
+    NotAdmin(String),
+    NotOrgOwner,
+    /// The requester's role does not allow the action (see `authz`)
+    Forbidden { identity: String, action: String },
+    /// The requester is not listed for the request's tenant (see `tenant`)
+    NotTenantMember { identity: String, tenant: String },
+    /// The request's `auth` is missing or not its HMAC under the shared secret (see `request_auth`)
+    RequestAuthFailed(String),
+    /// Single-step updates are off while an admin allowlist is configured
+    ApprovalRequired,
+    UpdatePending { id: u64, solana_pubkey: String, chain_id: String },
+    ProposalNotFound { id: u64, solana_pubkey: String, chain_id: String },
+    ProposalResolved { id: u64, status: &'static str },
+    ProposalExpired { id: u64, expires_at: u64 },
+    /// No provisioning job `id` for the Solana address (see `jobs`)
+    JobNotFound { id: u64, solana_pubkey: String },
+    SelfApproval { id: u64, admin: String },
+
+    // -- Infrastructure --
+    /// Too many requests for the Solana address; retry after `retry_after` seconds
+    RateLimited { solana_pubkey: String, retry_after: u64 },
+    /// The Solana address is mapped on as many `what` (chains, labels) as it may be (see `quota`)
+    QuotaExceeded { solana_pubkey: String, what: &'static str, limit: usize },
+    /// A concurrent writer got there first; retrying usually succeeds
+    KvConflict(String),
+    /// The KV store failed (message from the store)
+    Kv(String),
+    /// The store does not implement an optional operation
+    Unsupported(&'static str),
+    /// A stored value could not be decoded
+    CorruptRecord { what: String, detail: String },
+    UnsupportedRecordVersion(u32),
+    AuditChainBroken(String),
+    KeyCreationFailed { message: String, retryable: bool },
+    /// An org key could not sign a registry transaction (`onchain`, `solana_sync`) or an attestation
+    SigningFailed { message: String, retryable: bool },
+    NotConfigured(&'static str),
+}
+
+impl ProvisionError {
+    /// Stable machine-readable code
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::InvalidSolanaPubkey(_) => "INVALID_SOLANA_PUBKEY",
+            Self::InvalidEvmAddress(_) => "INVALID_EVM_ADDRESS",
+            Self::InvalidChecksum(_) => "INVALID_EVM_CHECKSUM",
+            Self::InvalidChainId(_) => "INVALID_CHAIN_ID",
+            Self::InvalidSignatureEncoding { .. } | Self::InvalidRecoveryId(_) => "INVALID_SIGNATURE",
+            Self::SignatureMismatch(_) => "SIGNATURE_MISMATCH",
+            Self::InvalidCertificate(_) => "INVALID_CERTIFICATE",
+            Self::InvalidResponseSignature(_) => "INVALID_RESPONSE_SIGNATURE",
+            Self::InvalidNonce => "INVALID_NONCE",
+            Self::InvalidIdempotencyKey { .. } => "INVALID_IDEMPOTENCY_KEY",
+            Self::InvalidRequest(_) => "INVALID_REQUEST",
+            Self::BatchTooLarge { .. } => "BATCH_TOO_LARGE",
+            Self::NotProvisioned(_) => "NOT_PROVISIONED",
+            Self::UnknownChain(_) => "UNKNOWN_CHAIN",
+            Self::ChainDisabled { .. } => "CHAIN_DISABLED",
+            Self::WrongNetwork { .. } => "WRONG_NETWORK",
+            Self::ChainNameRequired(_) => "CHAIN_NAME_REQUIRED",
+            Self::NonceUsed(_) => "NONCE_USED",
+            Self::NonceTooLow { .. } => "NONCE_TOO_LOW",
+            Self::AddressFrozen(_) => "ADDRESS_FROZEN",
+            Self::Blocked(_) => "BLOCKED",
+            Self::UnusableAddress { .. } => "UNUSABLE_ADDRESS",
+            Self::UnusablePubkey { .. } => "UNUSABLE_SOLANA_PUBKEY",
+            Self::Anonymized { .. } => "ANONYMIZED",
+            Self::UnknownSigner(_) => "UNKNOWN_SIGNER",
+            Self::AddressNotMapped { .. } => "ADDRESS_NOT_MAPPED",
+            Self::ExternalAddress(_) => "EXTERNAL_ADDRESS",
+            Self::SpendLimitExceeded { .. } => "SPEND_LIMIT_EXCEEDED",
+            Self::DestinationNotAllowed { .. } => "DESTINATION_NOT_ALLOWED",
+            Self::AddressOwned { .. } => "ADDRESS_OWNED",
+            Self::IdempotencyKeyReused(_) => "IDEMPOTENCY_KEY_REUSED",
+            Self::AuthorizationExpired { .. } => "AUTHORIZATION_EXPIRED",
+            Self::ImportConflict { .. } => "IMPORT_CONFLICT",
+            Self::VersionConflict { .. } => "VERSION_CONFLICT",
+            Self::NotAdmin(_) => "NOT_ADMIN",
+            Self::NotOrgOwner => "NOT_ORG_OWNER",
+            Self::Forbidden { .. } => "FORBIDDEN",
+            Self::NotTenantMember { .. } => "NOT_TENANT_MEMBER",
+            Self::RequestAuthFailed(_) => "REQUEST_AUTH_FAILED",
+            Self::ApprovalRequired => "APPROVAL_REQUIRED",
+            Self::UpdatePending { .. } => "UPDATE_PENDING",
+            Self::ProposalNotFound { .. } => "PROPOSAL_NOT_FOUND",
+            Self::JobNotFound { .. } => "JOB_NOT_FOUND",
+            Self::ProposalResolved { .. } => "PROPOSAL_RESOLVED",
+            Self::ProposalExpired { .. } => "PROPOSAL_EXPIRED",
+            Self::SelfApproval { .. } => "SELF_APPROVAL",
+            Self::RateLimited { .. } => "RATE_LIMITED",
+            Self::QuotaExceeded { .. } => "QUOTA_EXCEEDED",
+            Self::KvConflict(_) => "KV_CONFLICT",
+            Self::Kv(_) => "KV_ERROR",
+            Self::Unsupported(_) => "UNSUPPORTED",
+            Self::CorruptRecord { .. } => "CORRUPT_RECORD",
+            Self::UnsupportedRecordVersion(_) => "UNSUPPORTED_RECORD_VERSION",
+            Self::AuditChainBroken(_) => "AUDIT_CHAIN_BROKEN",
+            Self::KeyCreationFailed { .. } => "KEY_CREATION_FAILED",
+            Self::SigningFailed { .. } => "SIGNING_FAILED",
+            Self::NotConfigured(_) => "NOT_CONFIGURED",
+        }
+    }
+
+    /// Whether repeating the same request may succeed
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            Self::KvConflict(_) | Self::Kv(_) | Self::RateLimited { .. } => true,
+            Self::KeyCreationFailed { retryable, .. } | Self::SigningFailed { retryable, .. } => *retryable,
+            _ => false,
+        }
+    }
+
+    /// Stored value that failed to decode
+    pub fn corrupt(what: impl Into<String>, detail: impl fmt::Display) -> Self {
+        Self::CorruptRecord {
+            what: what.into(),
+            detail: detail.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for ProvisionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidSolanaPubkey(pubkey) => write!(f, "Invalid Solana public key: {}", pubkey),
+            Self::InvalidEvmAddress(address) => write!(f, "Invalid EVM address format: {}", address),
+            Self::InvalidChecksum(address) => write!(f, "Invalid EIP-55 checksum: {}", address),
+            Self::InvalidChainId(chain_id) => write!(f, "Invalid chain id: {}", chain_id),
+            Self::InvalidSignatureEncoding { expected } => write!(f, "Invalid signature encoding (expected {})", expected),
+            Self::InvalidRecoveryId(v) => write!(f, "Invalid signature recovery id: {}", v),
+            Self::SignatureMismatch(signer) => write!(f, "Signature verification failed for {}", signer),
+            Self::InvalidCertificate(reason) => write!(f, "Invalid mapping certificate: {}", reason),
+            Self::InvalidResponseSignature(reason) => write!(f, "Invalid response signature: {}", reason),
+            Self::InvalidNonce => write!(f, "Invalid nonce (expected a decimal integer below 2^64)"),
+            Self::InvalidIdempotencyKey { max_len } => {
+                write!(f, "Invalid idempotency key (expected 1-{} chars of [A-Za-z0-9_-])", max_len)
+            }
+            Self::InvalidRequest(msg) => write!(f, "Invalid request: {}", msg),
+            Self::BatchTooLarge { size, max } => write!(f, "Batch too large: {} requests (max {})", size, max),
+            Self::NotProvisioned(pubkey) => write!(f, "Solana address {} has not been provisioned yet", pubkey),
+            Self::UnknownChain(chain_id) => write!(f, "Unknown chain id: {}", chain_id),
+            Self::ChainDisabled { chain_id, name } => write!(f, "Chain {} ({}) is disabled", chain_id, name),
+            Self::WrongNetwork { chain_id, network } => write!(f, "Chain {} is not a {} chain", chain_id, network),
+            Self::ChainNameRequired(chain_id) => write!(f, "Unknown chain id {}: a name is required to register it", chain_id),
+            Self::NonceUsed(nonce) => write!(f, "Nonce {} has already been used", nonce),
+            Self::NonceTooLow { nonce, last } => write!(f, "Nonce {} must be greater than the last used nonce {}", nonce, last),
+            Self::AddressFrozen(address) => write!(f, "EVM address {} is frozen", address),
+            Self::Blocked(address) => write!(f, "Address {} is blocked", address),
+            Self::UnusableAddress { evm_address, reason } => write!(f, "EVM address {} cannot be mapped: it is {}", evm_address, reason),
+            Self::Anonymized { pseudonym } => write!(f, "Erased at its owner's request (pseudonym {})", pseudonym),
+            Self::UnusablePubkey { solana_pubkey, reason } => {
+                write!(f, "Solana address {} cannot be provisioned: it is {}", solana_pubkey, reason)
+            }
+            Self::UnknownSigner(identity) => write!(f, "{:?} is not bound to a user it may sign for", identity),
+            Self::AddressNotMapped { evm_address, solana_pubkey } => {
+                write!(f, "EVM address {} is not mapped to {}", evm_address, solana_pubkey)
+            }
+            Self::ExternalAddress(address) => write!(f, "EVM address {} is externally owned; CubeSigner holds no key for it", address),
+            Self::SpendLimitExceeded { limit, value, allowed } => {
+                write!(f, "Transaction value {} wei exceeds the {} ({} wei allowed)", value, limit, allowed)
+            }
+            Self::DestinationNotAllowed { to: Some(to), chain_id } => {
+                write!(f, "Destination {} is not on the allowlist for chain {}", to, chain_id)
+            }
+            Self::DestinationNotAllowed { to: None, chain_id } => {
+                write!(f, "Contract deployment is not allowed by the allowlist for chain {}", chain_id)
+            }
+            Self::AddressOwned { evm_address, owner } => write!(f, "EVM address {} already belongs to {}", evm_address, owner),
+            Self::IdempotencyKeyReused(key) => write!(f, "Idempotency key {} was already used for a different request", key),
+            Self::AuthorizationExpired { expires_at } => write!(f, "Authorization expired at {}", expires_at),
+            Self::ImportConflict { conflicts, first } => {
+                write!(f, "Import conflicts with {} existing keys holding other values (first: {})", conflicts, first)
+            }
+            Self::VersionConflict { solana_pubkey, chain_id, expected, current } => write!(
+                f,
+                "Mapping of {} on chain {} is at version {}, expected {}",
+                solana_pubkey,
+                chain_id,
+                current.as_ref().map_or(0, |record| record.revision),
+                expected
+            ),
+            Self::NotAdmin(identity) => write!(f, "{} is not an admin", identity),
+            Self::NotOrgOwner => write!(f, "Only org owners can manage admins"),
+            Self::Forbidden { identity, action } => write!(f, "{} is not allowed to {}", identity, action),
+            Self::NotTenantMember { identity, tenant } => write!(f, "{:?} is not a member of tenant {}", identity, tenant),
+            Self::RequestAuthFailed(reason) => write!(f, "Request authentication failed: {}", reason),
+            Self::ApprovalRequired => write!(f, "Updates require approval by a second admin (propose_update/approve_update)"),
+            Self::UpdatePending { id, solana_pubkey, chain_id } => {
+                write!(f, "Update {} for {} on chain {} is already pending", id, solana_pubkey, chain_id)
+            }
+            Self::ProposalNotFound { id, solana_pubkey, chain_id } => {
+                write!(f, "No pending update {} for {} on chain {}", id, solana_pubkey, chain_id)
+            }
+            Self::ProposalResolved { id, status } => write!(f, "Update {} is already {}", id, status),
+            Self::ProposalExpired { id, expires_at } => write!(f, "Update {} expired at {}", id, expires_at),
+            Self::JobNotFound { id, solana_pubkey } => write!(f, "No provisioning job {} for {}", id, solana_pubkey),
+            Self::SelfApproval { id, admin } => write!(f, "Update {} must be approved by a different admin than {}", id, admin),
+            Self::RateLimited { solana_pubkey, retry_after } => {
+                write!(f, "Too many requests for {}; retry in {}s", solana_pubkey, retry_after)
+            }
+            Self::QuotaExceeded { solana_pubkey, what, limit } => {
+                write!(f, "{} is already mapped on the most {} allowed ({})", solana_pubkey, what, limit)
+            }
+            Self::KvConflict(msg) | Self::Kv(msg) | Self::AuditChainBroken(msg) => f.write_str(msg),
+            Self::Unsupported(what) => write!(f, "{} is not supported by this KV store", what),
+            Self::CorruptRecord { what, detail } => write!(f, "Malformed {}: {}", what, detail),
+            Self::UnsupportedRecordVersion(version) => write!(f, "Unsupported mapping record version {}", version),
+            Self::KeyCreationFailed { message, .. } => write!(f, "Key creation failed: {}", message),
+            Self::SigningFailed { message, .. } => write!(f, "Signing failed: {}", message),
+            Self::NotConfigured(what) => write!(f, "{} is not configured", what),
+        }
+    }
+}
+
+impl std::error::Error for ProvisionError {}
+
+impl Serialize for ProvisionError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        let current = match self {
+            Self::VersionConflict { current, .. } => Some(current),
+            _ => None,
+        };
+        let mut state = serializer.serialize_struct("ProvisionError", 3 + current.is_some() as usize)?;
+        state.serialize_field("code", self.code())?;
+        state.serialize_field("message", &self.to_string())?;
+        state.serialize_field("retryable", &self.is_retryable())?;
+        if let Some(current) = current {
+            state.serialize_field("current", current)?;
+        }
+        state.end()
+    }
+}
+
+#[cfg(feature = "openapi")]
+impl schemars::JsonSchema for ProvisionError {
+    fn schema_name() -> std::borrow::Cow<'static, str> {
+        "ProvisionError".into()
+    }
+
+    fn json_schema(_: &mut schemars::SchemaGenerator) -> schemars::Schema {
+        schemars::json_schema!({
+            "type": "object",
+            "properties": {
+                "code": { "type": "string", "description": "Stable machine-readable code (`NOT_PROVISIONED`, …)" },
+                "message": { "type": "string", "description": "For humans; may change" },
+                "retryable": { "type": "boolean" },
+                "current": { "type": ["object", "null"], "description": "`VERSION_CONFLICT` only: the stored mapping record" },
+            },
+            "required": ["code", "message", "retryable"],
+        })
+    }
+}
+
+impl From<CubeSignerError> for ProvisionError {
+    fn from(error: CubeSignerError) -> Self {
+        let retryable = error.is_retryable();
+        Self::KeyCreationFailed {
+            message: error.to_string(),
+            retryable,
+        }
+    }
+}
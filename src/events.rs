@@ -0,0 +1,128 @@
+//! Mapping Change Events
+//!
+//! An ordered feed of mapping changes, so downstream consumers (indexers, the
+//! backend's webhooks) can follow changes instead of polling every user.
+//! Stores that add mappings and every update append an event; `poll` pages
+//! through the feed by seq.
+//!
+//! Events carry the resulting state (chain → address, and the revision for
+//! updates), not a delta, so applying one twice is harmless. That matters
+//! because delivery is at least once: a store appends its event through the
+//! write journal (`txn`), and a journal completed by recovery while the
+//! original writer is still running appends it again. A store that adds
+//! nothing new (a retry, an idempotent re-provision) appends nothing.
+//!
+//! The feed is never pruned, like the audit log.
+//!
+//! ## Key Schema
+//! ```text
+//! events:{seq}  → MappingEvent  # seq starts at 1, written with IfExists::Deny
+//! events:head   → {seq}         # Hint for the latest seq (may lag behind)
+//! ```
+
+use crate::address::{EvmAddress, SolanaPubkey};
+use crate::chain_id::ChainId;
+use crate::error::{ProvisionError, Result};
+use crate::kv::KvStore;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Events returned by one poll unless `limit` says otherwise
+pub const DEFAULT_POLL_LIMIT: usize = 100;
+
+/// Maximum number of events returned by one poll
+pub const MAX_POLL_LIMIT: usize = 500;
+
+/// Attempts to claim a seq before giving up under contention
+const MAX_APPEND_ATTEMPTS: usize = 16;
+
+const HEAD_KEY: &str = "events:head";
+
+pub fn event_key(seq: u64) -> String {
+    format!("events:{}", seq)
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum EventKind {
+    /// Chains were mapped for the first time (`store`)
+    Provisioned,
+    /// A chain mapping was overwritten (admin update, approval, self-service
+    /// update, external link)
+    Updated,
+}
+
+/// What changed, before it is sequenced
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct MappingChange {
+    pub kind: EventKind,
+    pub solana_pubkey: SolanaPubkey,
+    /// Label of the changed addresses; `None` for the primary address
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    /// Address of each changed chain after the change
+    pub chain_mappings: BTreeMap<ChainId, EvmAddress>,
+    /// Revision of the chain mapping after an update (`None` for provisions)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct MappingEvent {
+    pub seq: u64,
+    /// Unix timestamp (seconds) of the change
+    pub timestamp: u64,
+    #[serde(flatten)]
+    pub change: MappingChange,
+}
+
+#[derive(Serialize, Debug)]
+pub struct EventPage {
+    pub events: Vec<MappingEvent>,
+    /// Seq of the last event returned (`after_seq` if there were none): pass
+    /// as `after_seq` to continue
+    pub last_seq: u64,
+}
+
+/// Append a change to the feed, returning the sequenced event
+pub fn append(kv: &impl KvStore, change: MappingChange, timestamp: u64) -> Result<MappingEvent> {
+    for _ in 0..MAX_APPEND_ATTEMPTS {
+        let event = MappingEvent {
+            seq: find_last_seq(kv)? + 1,
+            timestamp,
+            change: change.clone(),
+        };
+        let raw = serde_json::to_string(&event).expect("event serialization cannot fail");
+        if kv.set_if_absent(&event_key(event.seq), &raw)? {
+            kv.set(HEAD_KEY, &event.seq.to_string())?;
+            return Ok(event);
+        }
+        // Another writer claimed this seq - re-read the tail and retry
+    }
+
+    Err(ProvisionError::KvConflict(format!("Could not append event after {} attempts", MAX_APPEND_ATTEMPTS)))
+}
+
+/// Events after `after_seq`, oldest first, read in one `get_many` round-trip
+pub fn poll(kv: &impl KvStore, after_seq: u64, limit: Option<usize>) -> Result<EventPage> {
+    let limit = limit.unwrap_or(DEFAULT_POLL_LIMIT).clamp(1, MAX_POLL_LIMIT) as u64;
+    let keys: Vec<String> = (after_seq + 1..=after_seq + limit).map(event_key).collect();
+
+    let mut events = Vec::new();
+    // Seqs are claimed in order, so the feed ends at the first missing one
+    for (seq, raw) in (after_seq + 1..).zip(kv.get_many(&keys)?) {
+        let Some(raw) = raw else { break };
+        events.push(serde_json::from_str(&raw).map_err(|e| ProvisionError::corrupt(format!("event {}", seq), e))?);
+    }
+    let last_seq = after_seq + events.len() as u64;
+    Ok(EventPage { events, last_seq })
+}
+
+/// Latest seq (0 for an empty feed), starting from the head hint and probing forward
+fn find_last_seq(kv: &impl KvStore) -> Result<u64> {
+    let mut seq = kv.get(HEAD_KEY)?.and_then(|raw| raw.parse::<u64>().ok()).unwrap_or(0);
+    while kv.get(&event_key(seq + 1))?.is_some() {
+        seq += 1;
+    }
+    Ok(seq)
+}
@@ -0,0 +1,90 @@
+//! EVM → Solana Mappings
+//!
+//! Mirror of the Solana → EVM flow for users onboarding from an EVM wallet:
+//! one CubeSigner Solana key per EVM address, stored first-writer-wins in its
+//! own bucket.
+//!
+//! ## Key Schema (`evm_to_solana` bucket)
+//! ```text
+//! default:{evm_address}   → SolanaMappingValue # Solana wallet of the EVM address
+//! reverse:{solana_pubkey} → {evm_address}      # Reverse index (Solana → EVM)
+//! store_nonce:{evm_address}:{nonce} → {used_at} # Consumed authorization nonces
+//! ```
+//!
+//! EVM addresses are stored lowercase, like in `solana_to_evm`.
+
+use crate::address::{EvmAddress, SolanaPubkey};
+use crate::kv::KvStore;
+use crate::error::{ProvisionError, Result};
+use serde::{Deserialize, Serialize};
+
+/// Bucket name for EVM to Solana mappings
+pub const EVM_TO_SOLANA_BUCKET: &str = "evm_to_solana";
+
+/// Key of the Solana wallet of an EVM address: `default:{evm_address}`
+pub fn default_key(evm_address: &EvmAddress) -> String {
+    format!("default:{}", evm_address.as_str())
+}
+
+/// Key of the reverse index entry: `reverse:{solana_pubkey}`
+pub fn reverse_key(solana_pubkey: &SolanaPubkey) -> String {
+    format!("reverse:{}", solana_pubkey.as_str())
+}
+
+/// Key of a consumed authorization nonce: `store_nonce:{evm_address}:{nonce}`
+pub fn store_nonce_key(evm_address: &EvmAddress, nonce: u64) -> String {
+    format!("store_nonce:{}:{}", evm_address.as_str(), nonce)
+}
+
+/// Value stored under `default:{evm_address}`.
+///
+/// Encoded as JSON: `{"address":"<base58>","key_id":"Key#…"}`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SolanaMappingValue {
+    pub address: SolanaPubkey,
+    pub key_id: String,
+}
+
+impl SolanaMappingValue {
+    pub fn encode(&self) -> String {
+        serde_json::to_string(self).expect("mapping value serialization cannot fail")
+    }
+
+    pub fn decode(raw: &str) -> Result<Self> {
+        serde_json::from_str(raw).map_err(|e| ProvisionError::corrupt("EVM to Solana mapping", e))
+    }
+}
+
+pub fn get_mapping(kv: &impl KvStore, evm_address: &EvmAddress) -> Result<Option<SolanaMappingValue>> {
+    kv.get(&default_key(evm_address))?.map(|raw| SolanaMappingValue::decode(&raw)).transpose()
+}
+
+/// Store the Solana wallet of an EVM address (first-writer-wins), returning
+/// the value that ended up stored
+pub fn store_mapping_once(kv: &impl KvStore, evm_address: &EvmAddress, value: &SolanaMappingValue) -> Result<SolanaMappingValue> {
+    let key = default_key(evm_address);
+    if kv.set_if_absent(&key, &value.encode())? {
+        return Ok(value.clone());
+    }
+    let stored = kv
+        .get(&key)?
+        .ok_or_else(|| ProvisionError::KvConflict(format!("Key {} reported as existing but could not be read", key)))?;
+    SolanaMappingValue::decode(&stored)
+}
+
+/// Look up which EVM address owns a (provisioned) Solana address
+pub fn get_reverse_mapping(kv: &impl KvStore, solana_pubkey: &SolanaPubkey) -> Result<Option<EvmAddress>> {
+    kv.get(&reverse_key(solana_pubkey))?.map(|raw| EvmAddress::parse(&raw)).transpose()
+}
+
+/// Record the owner of a Solana address (first-writer-wins)
+pub fn store_reverse_mapping(kv: &impl KvStore, solana_pubkey: &SolanaPubkey, evm_address: &EvmAddress) -> Result<()> {
+    kv.set_if_absent(&reverse_key(solana_pubkey), evm_address.as_str())?;
+    Ok(())
+}
+
+/// Mark an authorization's nonce as used (atomic). Returns `false` if it had
+/// already been used. Like Solana store nonces, they only need to be unused.
+pub fn consume_store_nonce(kv: &impl KvStore, evm_address: &EvmAddress, nonce: u64, used_at: u64) -> Result<bool> {
+    kv.set_if_absent(&store_nonce_key(evm_address, nonce), &used_at.to_string())
+}
@@ -0,0 +1,160 @@
+//! Temporary Mappings
+//!
+//! Hackathon and demo environments want wallets that clean up after
+//! themselves. A store or external link with `ttl_secs` writes its mappings
+//! with an `expires_at`; from then on:
+//!
+//! - `get` (and so the signing gate, attestations and certificates) treats an
+//!   expired mapping as absent. An expired chain mapping falls back to the
+//!   default address, as if it had never been written
+//! - chain mappings never outlive the default they were stored for: they
+//!   expire with it if it is temporary
+//! - storing for a user whose default expired provisions the user afresh
+//!   (`mapping::store`): the new default takes over the expired one through
+//!   a `set_if_absent` claim (`kv::renew_default_mapping`), and each expired
+//!   chain mapping the store names is replaced like an update, keeping its
+//!   history. No key is deleted, so this works on the C2F bucket
+//!
+//! `sweep_batch` garbage-collects expired default and chain mappings a page
+//! at a time, with the chain index, reverse index, history and revision
+//! claims that only served them. It needs `KvStore::delete`, which the C2F
+//! bucket does not have: there, expired mappings stay stored (and hidden)
+//! until a store replaces them.
+//!
+//! Only the primary address can be temporary; labeled stores refuse `ttl_secs`.
+
+use crate::address::{EvmAddress, SolanaPubkey};
+use crate::anonymize;
+use crate::chain_id::ChainId;
+use crate::error::{ProvisionError, Result};
+use crate::kv::{self, KvStore, MappingRecord};
+use crate::migrate::{self, DEFAULT_MIGRATION_BATCH, MAX_MIGRATION_BATCH};
+use serde::{Deserialize, Serialize};
+
+/// Longest lifetime of a temporary mapping (a year)
+pub const MAX_MAPPING_TTL_SECS: u64 = 365 * 24 * 60 * 60;
+
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct SweepRequest {
+    /// Resume after this key (`next_cursor` of the previous batch)
+    #[serde(default)]
+    pub cursor: Option<String>,
+    #[serde(default)]
+    pub limit: Option<usize>,
+    /// Correlation id carried into log events (see `logging`)
+    #[serde(default)]
+    pub request_id: Option<String>,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct SweepReport {
+    /// Keys looked at in this batch
+    pub scanned: usize,
+    /// Expired mapping keys removed in this batch
+    pub swept: Vec<String>,
+    /// Pass as `cursor` to continue; null once every key has been scanned
+    pub next_cursor: Option<String>,
+}
+
+/// Expiry of mappings written `now` with `ttl_secs` (`None`: permanent)
+pub fn expires_at(ttl_secs: Option<u64>, now: u64) -> Result<Option<u64>> {
+    match ttl_secs {
+        None => Ok(None),
+        Some(ttl) if ttl == 0 || ttl > MAX_MAPPING_TTL_SECS => Err(ProvisionError::InvalidRequest(format!(
+            "Mapping TTL must be between 1 and {} seconds",
+            MAX_MAPPING_TTL_SECS
+        ))),
+        Some(ttl) => Ok(Some(now + ttl)),
+    }
+}
+
+/// The earlier of two expiries, either of which may be permanent
+pub fn earliest(a: Option<u64>, b: Option<u64>) -> Option<u64> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        (a, b) => a.or(b),
+    }
+}
+
+/// Remove the expired mappings among one batch of keys after `cursor`
+pub fn sweep_batch(kv: &impl KvStore, cursor: Option<&str>, limit: Option<usize>, now: u64) -> Result<SweepReport> {
+    let limit = limit.unwrap_or(DEFAULT_MIGRATION_BATCH).clamp(1, MAX_MIGRATION_BATCH);
+    let keys = kv.list_keys(cursor, limit)?;
+
+    let mut report = SweepReport {
+        scanned: keys.len(),
+        swept: Vec::new(),
+        next_cursor: if keys.len() < limit { None } else { keys.last().cloned() },
+    };
+
+    for key in keys.iter().filter(|key| migrate::is_mapping_key(key)) {
+        if sweep_key(kv, key, now)? {
+            report.swept.push(key.clone());
+        }
+    }
+
+    Ok(report)
+}
+
+/// Remove the mapping under `key` if it expired; returns whether it did
+fn sweep_key(kv: &impl KvStore, key: &str, now: u64) -> Result<bool> {
+    let raw = kv.get(key)?.filter(|raw| !anonymize::is_tombstone(raw));
+    let Some(record) = raw.map(|raw| MappingRecord::decode(&raw)).transpose()? else {
+        return Ok(false);
+    };
+    if !record.is_expired(now) {
+        return Ok(false);
+    }
+
+    let (solana_pubkey, chain_id) = parse_mapping_key(key)?;
+    kv.delete(key)?;
+    if let Some(chain_id) = &chain_id {
+        kv::remove_from_chain_index(kv, &solana_pubkey, chain_id)?;
+        kv.delete(&kv::history_key(&solana_pubkey, chain_id))?;
+        for revision in 1..=record.revision {
+            kv.delete(&kv::revision_key(&solana_pubkey, chain_id, revision))?;
+            // Takeovers of stale claims, if any
+            for n in 2.. {
+                let key = kv::revision_claim_key(&solana_pubkey, chain_id, revision, n);
+                if kv.get(&key)?.is_none() {
+                    break;
+                }
+                kv.delete(&key)?;
+            }
+        }
+    }
+    release_address(kv, &solana_pubkey, &record.address, now)?;
+    Ok(true)
+}
+
+/// Drop the reverse entry giving `evm_address` to `solana_pubkey`, unless one
+/// of the user's live mappings still uses the address
+fn release_address(kv: &impl KvStore, solana_pubkey: &SolanaPubkey, evm_address: &EvmAddress, now: u64) -> Result<()> {
+    match kv::get_reverse_mapping(kv, evm_address) {
+        Ok(owner) if owner.as_ref() == Some(solana_pubkey) => {}
+        Ok(_) | Err(ProvisionError::Anonymized { .. }) => return Ok(()),
+        Err(e) => return Err(e),
+    }
+    let keys: Vec<String> = std::iter::once(kv::default_key(solana_pubkey))
+        .chain(kv::get_chain_index(kv, solana_pubkey)?.iter().map(|chain_id| kv::chain_key(solana_pubkey, chain_id)))
+        .collect();
+    let in_use = kv::get_mappings(kv, &keys)?
+        .into_iter()
+        .flatten()
+        .any(|record| record.address == *evm_address && !record.is_expired(now));
+    if in_use {
+        return Ok(());
+    }
+    kv.delete(&kv::reverse_key(evm_address))
+}
+
+/// User (and chain, for chain mappings) of a mapping key
+fn parse_mapping_key(key: &str) -> Result<(SolanaPubkey, Option<ChainId>)> {
+    if let Some(solana_pubkey) = key.strip_prefix("default:") {
+        return Ok((SolanaPubkey::parse(solana_pubkey)?, None));
+    }
+    let (solana_pubkey, chain_id) = key
+        .split_once(':')
+        .ok_or_else(|| ProvisionError::InvalidRequest(format!("{} is not a mapping key", key)))?;
+    Ok((SolanaPubkey::parse(solana_pubkey)?, Some(ChainId::parse(chain_id)?)))
+}
@@ -0,0 +1,58 @@
+//! Bucket Export
+//!
+//! Pages through the mappings bucket so the backend can back it up. Every key
+//! is exported with its stored value as is: mapping records, labels, external
+//! addresses, retirement records, indexes, history, the chain registry and the
+//! audit log, so writing the entries back restores the bucket byte for byte.
+//!
+//! Like `migrate`, pages follow `KvStore::list_keys` in ascending key order:
+//! each call returns at most `limit` entries after `cursor` and the cursor to
+//! resume from. Pages are stable, but an export is not a snapshot: keys
+//! written during the export show up if they sort after the cursor.
+
+use crate::error::Result;
+use crate::kv::KvStore;
+use crate::migrate::{DEFAULT_MIGRATION_BATCH, MAX_MIGRATION_BATCH};
+use serde::{Deserialize, Serialize};
+
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct ExportRequest {
+    /// Resume after this key (`next_cursor` of the previous page)
+    #[serde(default)]
+    pub cursor: Option<String>,
+    #[serde(default)]
+    pub limit: Option<usize>,
+    /// Correlation id carried into log events (see `logging`)
+    #[serde(default)]
+    pub request_id: Option<String>,
+}
+
+/// One stored key and its raw value
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct ExportEntry {
+    pub key: String,
+    pub value: String,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct ExportPage {
+    pub entries: Vec<ExportEntry>,
+    /// Pass as `cursor` to continue; null once every key has been exported
+    pub next_cursor: Option<String>,
+}
+
+/// Export one page of keys after `cursor`
+pub fn export_page(kv: &impl KvStore, cursor: Option<&str>, limit: Option<usize>) -> Result<ExportPage> {
+    let limit = limit.unwrap_or(DEFAULT_MIGRATION_BATCH).clamp(1, MAX_MIGRATION_BATCH);
+    let keys = kv.list_keys(cursor, limit)?;
+    let next_cursor = if keys.len() < limit { None } else { keys.last().cloned() };
+
+    let values = kv.get_many(&keys)?;
+    let entries = keys
+        .into_iter()
+        .zip(values)
+        .filter_map(|(key, value)| value.map(|value| ExportEntry { key, value }))
+        .collect();
+    Ok(ExportPage { entries, next_cursor })
+}
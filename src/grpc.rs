@@ -0,0 +1,326 @@
+//! gRPC API (`grpc` feature)
+//!
+//! The provisioning API for internal services, with types generated from
+//! `proto/provisioner.proto` on both ends instead of JSON shaped by hand.
+//! `pb` holds the generated messages, the tonic server trait
+//! (`pb::provisioner_server`) and client (`pb::provisioner_client::ProvisionerClient`).
+//!
+//! `GrpcProvisioner` implements the service over a `Provisioner`: each call is
+//! converted to the library request type (validating addresses and chain ids
+//! on the way), run on tokio's blocking pool through `AsyncProvisioner`, and
+//! its response converted back.
+//!
+//! ```ignore
+//! let service = GrpcProvisioner::new(Provisioner::new(kv, keys)).into_server();
+//! tonic::transport::Server::builder().add_service(service).serve(addr).await?;
+//! ```
+//!
+//! A failure is a `Status` whose code follows the error (see `code`); the
+//! error's stable code and retryability are in the `x-error-code` and
+//! `x-retryable` metadata, the same values the JSON API reports.
+//!
+//! Like the REST server, the service sits behind a proxy that authenticates
+//! callers and names them in the `x-requester-identity` and
+//! `x-requester-org-role` metadata; `update` checks that requester against
+//! the admin allowlist.
+
+use crate::address::{EvmAddress, SolanaPubkey};
+use crate::admin::Requester;
+use crate::async_api::{AsyncProvisioner, Blocking};
+use crate::chain_id::ChainId;
+use crate::wildcard;
+use crate::error::{ProvisionError, Result};
+use crate::keys::{KeyClass, KeyCreator, KeyType};
+use crate::kv::KvStore;
+use crate::{
+    GetMappingsResponse, ProvisionBatchItem, ProvisionBatchRequest, ProvisionBatchResponse, ProvisionRequest,
+    ProvisionResponse, Provisioner, UpdateMappingRequest, UpdateMappingResponse,
+};
+use std::collections::HashMap;
+use tonic::metadata::MetadataValue;
+use tonic::{Code, Request, Response, Status};
+
+/// Types generated from `proto/provisioner.proto`
+pub mod pb {
+    tonic::include_proto!("cubist.provisioner.v1");
+}
+
+/// Metadata key carrying `ProvisionError::code` on a failed call
+pub const ERROR_CODE_METADATA: &str = "x-error-code";
+
+/// Metadata key carrying `ProvisionError::is_retryable` on a failed call
+pub const RETRYABLE_METADATA: &str = "x-retryable";
+
+/// Metadata key the authenticating proxy puts the caller's identity in
+pub const REQUESTER_IDENTITY_METADATA: &str = "x-requester-identity";
+
+/// Metadata key the authenticating proxy puts the caller's org role in
+pub const REQUESTER_ORG_ROLE_METADATA: &str = "x-requester-org-role";
+
+// =============================================================================
+// STATUS
+// =============================================================================
+
+/// gRPC status code of a failed call
+pub fn code(e: &ProvisionError) -> Code {
+    use ProvisionError::*;
+    match e {
+        InvalidSolanaPubkey(_) | InvalidEvmAddress(_) | InvalidChecksum(_) | InvalidChainId(_)
+        | InvalidSignatureEncoding { .. } | InvalidRecoveryId(_) | InvalidNonce | InvalidIdempotencyKey { .. }
+        | InvalidRequest(_) | BatchTooLarge { .. } | UnknownChain(_) | ChainNameRequired(_) | UnusableAddress { .. }
+        | UnusablePubkey { .. } | WrongNetwork { .. } => Code::InvalidArgument,
+        AuthorizationExpired { .. } | ProposalResolved { .. } | ProposalExpired { .. } => Code::FailedPrecondition,
+        SignatureMismatch(_) | InvalidCertificate(_) | InvalidResponseSignature(_) | RequestAuthFailed(_) => Code::Unauthenticated,
+        NotAdmin(_) | NotOrgOwner | Forbidden { .. } | NotTenantMember { .. } | UnknownSigner(_) | ApprovalRequired | SelfApproval { .. } | AddressFrozen(_)
+        | Blocked(_) | ChainDisabled { .. } | ExternalAddress(_) | SpendLimitExceeded { .. }
+        | DestinationNotAllowed { .. } => Code::PermissionDenied,
+        NotProvisioned(_) | AddressNotMapped { .. } | ProposalNotFound { .. } | JobNotFound { .. } | Anonymized { .. } => {
+            Code::NotFound
+        }
+        NonceUsed(_) | NonceTooLow { .. } | AddressOwned { .. } | IdempotencyKeyReused(_) | ImportConflict { .. } => {
+            Code::AlreadyExists
+        }
+        VersionConflict { .. } | UpdatePending { .. } | KvConflict(_) => Code::Aborted,
+        RateLimited { .. } | QuotaExceeded { .. } => Code::ResourceExhausted,
+        CorruptRecord { .. } | UnsupportedRecordVersion(_) | AuditChainBroken(_) => Code::DataLoss,
+        Unsupported(_) | NotConfigured(_) => Code::Unimplemented,
+        KeyCreationFailed { .. } | SigningFailed { .. } | Kv(_) => Code::Unavailable,
+    }
+}
+
+/// `Status` reporting `e`
+pub fn status(e: &ProvisionError) -> Status {
+    let mut status = Status::new(code(e), e.to_string());
+    let metadata = status.metadata_mut();
+    metadata.insert(ERROR_CODE_METADATA, MetadataValue::from_static(e.code()));
+    metadata.insert(RETRYABLE_METADATA, MetadataValue::from_static(if e.is_retryable() { "true" } else { "false" }));
+    status
+}
+
+// =============================================================================
+// CONVERSIONS
+// =============================================================================
+
+fn chain_ids(chain_ids: &[String]) -> Result<Vec<ChainId>> {
+    chain_ids.iter().map(|chain_id| ChainId::resolve(chain_id)).collect()
+}
+
+/// `chain_ids` of a provision, which may also hold `*` (see `wildcard`)
+fn store_chain_ids(chain_ids: &[String]) -> Result<Vec<ChainId>> {
+    chain_ids
+        .iter()
+        .map(|chain_id| if chain_id == wildcard::WILDCARD { Ok(ChainId::wildcard()) } else { ChainId::resolve(chain_id) })
+        .collect()
+}
+
+fn by_chain<V>(map: HashMap<ChainId, V>, value: impl Fn(V) -> String) -> HashMap<String, String> {
+    map.into_iter().map(|(chain_id, v)| (chain_id.to_string(), value(v))).collect()
+}
+
+/// Out-of-range values saturate, which `KeyClass::validate` then rejects
+fn key_class(mpc: pb::MpcQuorum) -> KeyClass {
+    let saturate = |n: u32| u8::try_from(n).unwrap_or(u8::MAX);
+    KeyClass::Mpc { threshold: saturate(mpc.threshold), participants: saturate(mpc.participants) }
+}
+
+fn addresses(addresses: Vec<EvmAddress>) -> Vec<String> {
+    addresses.into_iter().map(|address| address.to_string()).collect()
+}
+
+impl TryFrom<pb::ProvisionRequest> for ProvisionRequest {
+    type Error = ProvisionError;
+
+    fn try_from(req: pb::ProvisionRequest) -> Result<Self> {
+        Ok(Self {
+            solana_pubkey: SolanaPubkey::parse(&req.solana_pubkey)?,
+            chain_ids: store_chain_ids(&req.chain_ids)?,
+            message: req.message,
+            signature: req.signature,
+            label: req.label,
+            key_type: req.key_type.as_deref().map(KeyType::parse).transpose()?.unwrap_or_default(),
+            key_class: req.mpc.map(key_class).unwrap_or_default(),
+            ttl_secs: req.ttl_secs,
+            idempotency_key: req.idempotency_key,
+            request_id: req.request_id,
+        })
+    }
+}
+
+impl From<ProvisionResponse> for pb::ProvisionResponse {
+    fn from(response: ProvisionResponse) -> Self {
+        Self {
+            evm_address: response.evm_address.to_string(),
+            key_id: response.key_id,
+            chain_mappings: by_chain(response.chain_mappings, |address| address.to_string()),
+            label: response.label,
+            chain_names: by_chain(response.chain_names, |name| name),
+            wildcard: response.wildcard,
+        }
+    }
+}
+
+impl From<GetMappingsResponse> for pb::GetMappingsResponse {
+    fn from(response: GetMappingsResponse) -> Self {
+        Self {
+            default_address: response.default_address.map(|address| address.to_string()),
+            default_key_id: response.default_key_id,
+            chain_mappings: by_chain(response.chain_mappings, |address| address.to_string()),
+            chain_key_ids: by_chain(response.chain_key_ids, |key_id| key_id),
+            chain_versions: response.chain_versions.into_iter().map(|(chain_id, v)| (chain_id.to_string(), v)).collect(),
+            chain_inherited: response.chain_inherited.into_iter().map(|(chain_id, v)| (chain_id.to_string(), v)).collect(),
+            labeled_mappings: response
+                .labeled_mappings
+                .into_iter()
+                .map(|(chain_id, labels)| {
+                    let addresses = labels.into_iter().map(|(label, address)| (label, address.to_string())).collect();
+                    (chain_id.to_string(), pb::LabeledAddresses { addresses })
+                })
+                .collect(),
+            frozen_addresses: addresses(response.frozen_addresses),
+            external_addresses: addresses(response.external_addresses),
+            chain_names: by_chain(response.chain_names, |name| name),
+            wildcard: response.wildcard,
+        }
+    }
+}
+
+impl TryFrom<pb::UpdateMappingRequest> for UpdateMappingRequest {
+    type Error = ProvisionError;
+
+    fn try_from(req: pb::UpdateMappingRequest) -> Result<Self> {
+        Ok(Self {
+            solana_pubkey: SolanaPubkey::parse(&req.solana_pubkey)?,
+            chain_id: ChainId::resolve(&req.chain_id)?,
+            expected_version: req.expected_version,
+            label: req.label,
+            idempotency_key: req.idempotency_key,
+            request_id: req.request_id,
+        })
+    }
+}
+
+impl From<UpdateMappingResponse> for pb::UpdateMappingResponse {
+    fn from(response: UpdateMappingResponse) -> Self {
+        Self {
+            new_evm_address: response.new_evm_address.to_string(),
+            new_key_id: response.new_key_id,
+            chain_id: response.chain_id.to_string(),
+            version: response.version,
+        }
+    }
+}
+
+impl TryFrom<pb::ProvisionBatchRequest> for ProvisionBatchRequest {
+    type Error = ProvisionError;
+
+    fn try_from(req: pb::ProvisionBatchRequest) -> Result<Self> {
+        Ok(Self {
+            requests: req.requests.into_iter().map(ProvisionRequest::try_from).collect::<Result<_>>()?,
+            request_id: req.request_id,
+        })
+    }
+}
+
+impl From<ProvisionError> for pb::Error {
+    fn from(e: ProvisionError) -> Self {
+        Self { code: e.code().to_string(), message: e.to_string(), retryable: e.is_retryable() }
+    }
+}
+
+impl From<ProvisionBatchItem> for pb::ProvisionBatchItem {
+    fn from(item: ProvisionBatchItem) -> Self {
+        let outcome = match (item.result, item.error) {
+            (Some(result), _) => Some(pb::provision_batch_item::Outcome::Result(result.into())),
+            (None, Some(error)) => Some(pb::provision_batch_item::Outcome::Error(error.into())),
+            (None, None) => None,
+        };
+        Self { solana_pubkey: item.solana_pubkey.to_string(), outcome }
+    }
+}
+
+impl From<ProvisionBatchResponse> for pb::ProvisionBatchResponse {
+    fn from(response: ProvisionBatchResponse) -> Self {
+        Self {
+            succeeded: response.succeeded as u64,
+            failed: response.failed as u64,
+            results: response.results.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+// =============================================================================
+// SERVICE
+// =============================================================================
+
+/// `Blocking` running tasks on tokio's blocking pool
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TokioBlocking;
+
+impl Blocking for TokioBlocking {
+    fn spawn_blocking(&self, task: Box<dyn FnOnce() + Send>) {
+        tokio::task::spawn_blocking(task);
+    }
+}
+
+/// The provisioning gRPC service over a `Provisioner`
+pub struct GrpcProvisioner<S, K> {
+    inner: AsyncProvisioner<S, K>,
+}
+
+impl<S, K> GrpcProvisioner<S, K>
+where
+    S: KvStore + Send + Sync + 'static,
+    K: KeyCreator + Send + Sync + 'static,
+{
+    pub fn new(provisioner: Provisioner<S, K>) -> Self {
+        Self { inner: AsyncProvisioner::new(provisioner).with_blocking(TokioBlocking) }
+    }
+
+    /// The service, to add to a `tonic::transport::Server`
+    pub fn into_server(self) -> pb::provisioner_server::ProvisionerServer<Self> {
+        pb::provisioner_server::ProvisionerServer::new(self)
+    }
+}
+
+/// The requester the authenticating proxy named in `request`'s metadata
+pub fn requester<T>(request: &Request<T>) -> Requester {
+    let value = |key: &str| request.metadata().get(key).and_then(|value| value.to_str().ok());
+    Requester::new(value(REQUESTER_IDENTITY_METADATA), value(REQUESTER_ORG_ROLE_METADATA))
+}
+
+fn respond<T, U: From<T>>(result: Result<T>) -> std::result::Result<Response<U>, Status> {
+    result.map(|response| Response::new(response.into())).map_err(|e| status(&e))
+}
+
+#[tonic::async_trait]
+impl<S, K> pb::provisioner_server::Provisioner for GrpcProvisioner<S, K>
+where
+    S: KvStore + Send + Sync + 'static,
+    K: KeyCreator + Send + Sync + 'static,
+{
+    async fn provision(&self, request: Request<pb::ProvisionRequest>) -> std::result::Result<Response<pb::ProvisionResponse>, Status> {
+        let req = ProvisionRequest::try_from(request.into_inner()).map_err(|e| status(&e))?;
+        respond(self.inner.handle(req).await)
+    }
+
+    async fn get(&self, request: Request<pb::GetMappingsRequest>) -> std::result::Result<Response<pb::GetMappingsResponse>, Status> {
+        let req = request.into_inner();
+        let solana_pubkey = SolanaPubkey::parse(&req.solana_pubkey).map_err(|e| status(&e))?;
+        let chain_ids = chain_ids(&req.chain_ids).map_err(|e| status(&e))?;
+        respond(self.inner.handle_get(solana_pubkey, chain_ids).await)
+    }
+
+    async fn update(&self, request: Request<pb::UpdateMappingRequest>) -> std::result::Result<Response<pb::UpdateMappingResponse>, Status> {
+        let requester = requester(&request);
+        let req = UpdateMappingRequest::try_from(request.into_inner()).map_err(|e| status(&e))?;
+        respond(self.inner.handle_update_mapping(requester, req).await)
+    }
+
+    async fn batch_provision(
+        &self,
+        request: Request<pb::ProvisionBatchRequest>,
+    ) -> std::result::Result<Response<pb::ProvisionBatchResponse>, Status> {
+        let req = ProvisionBatchRequest::try_from(request.into_inner()).map_err(|e| status(&e))?;
+        respond(self.inner.handle_batch(req).await)
+    }
+}
@@ -0,0 +1,57 @@
+//! Health and Version
+//!
+//! Operations checks for a deployed policy: `ping` writes a timestamp to the
+//! `metrics` bucket and reads it back, so a reply shows the KV store is
+//! reachable from the policy; `version` reports the build that answered.
+//!
+//! The git sha is baked in by `build.rs`: `CUBIST_GIT_SHA` when the build
+//! sets it, else `git rev-parse HEAD` of the checkout, else none (a source
+//! tarball).
+//!
+//! ## Key Schema (`metrics` bucket)
+//! ```text
+//! ping → Unix timestamp (seconds) of the last ping
+//! ```
+
+use crate::error::{ProvisionError, Result};
+use crate::kv::{KvStore, MAPPING_RECORD_VERSION};
+use serde::Serialize;
+
+/// Key the ping writes and reads back
+pub const PING_KEY: &str = "ping";
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct PingResponse {
+    /// Timestamp written and read back (Unix seconds)
+    pub checked_at: u64,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct VersionInfo {
+    /// Version of the `cubist-wallet-provisioner` crate
+    pub crate_version: String,
+    /// Commit the build was made from, if known
+    pub git_sha: Option<String>,
+    /// Mapping record version written (see `MAPPING_RECORD_VERSION`)
+    pub schema_version: u32,
+}
+
+/// Write `now` to `kv` and read it back
+pub fn ping(kv: &impl KvStore, now: u64) -> Result<PingResponse> {
+    let written = now.to_string();
+    kv.set(PING_KEY, &written)?;
+    // A concurrent ping may have overwritten it: any value read back will do
+    match kv.get(PING_KEY)? {
+        Some(_) => Ok(PingResponse { checked_at: now }),
+        None => Err(ProvisionError::Kv(format!("ping wrote {} but read back nothing", written))),
+    }
+}
+
+/// Version of this build
+pub fn version() -> VersionInfo {
+    VersionInfo {
+        crate_version: env!("CARGO_PKG_VERSION").to_string(),
+        git_sha: option_env!("CUBIST_GIT_SHA").filter(|sha| !sha.is_empty()).map(str::to_string),
+        schema_version: MAPPING_RECORD_VERSION,
+    }
+}
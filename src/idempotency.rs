@@ -0,0 +1,110 @@
+//! Idempotency Keys
+//!
+//! Callers may attach an `idempotency_key` to a mutating request. The first
+//! successful run stores its response under that key; a retry with the same
+//! key and the same request gets the stored response back instead of running
+//! again (and creating another key). Failed runs are not recorded, so a retry
+//! after an error executes normally.
+//!
+//! ## Key Schema (`idempotency` bucket)
+//! ```text
+//! {action}:{idempotency_key} → IdempotencyRecord
+//! ```
+//!
+//! Two requests racing with the same key are not deduplicated: both run, and
+//! the first to finish is the one recorded.
+
+use crate::error::{ProvisionError, Result};
+use crate::kv::KvStore;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// Bucket name for completed idempotent requests
+pub const IDEMPOTENCY_BUCKET: &str = "idempotency";
+
+/// Maximum length of an idempotency key
+pub const MAX_IDEMPOTENCY_KEY_LEN: usize = 128;
+
+/// Key of a completed request: `{action}:{idempotency_key}`
+pub fn record_key(action: &str, idempotency_key: &str) -> String {
+    format!("{}:{}", action, idempotency_key)
+}
+
+/// Value stored under `{action}:{idempotency_key}`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct IdempotencyRecord {
+    /// `request_hash` of the request that completed
+    pub request_hash: String,
+    /// Its response, JSON
+    pub response: String,
+    pub completed_at: u64,
+}
+
+/// SHA-256 of the request's JSON encoding, hex
+pub fn request_hash(request: &impl Serialize) -> String {
+    let encoded = serde_json::to_vec(request).expect("request serialization cannot fail");
+    Sha256::digest(&encoded).iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Idempotency keys end up in KV keys: 1-128 chars of `[A-Za-z0-9_-]`
+pub fn validate_key(idempotency_key: &str) -> Result<()> {
+    let valid = !idempotency_key.is_empty()
+        && idempotency_key.len() <= MAX_IDEMPOTENCY_KEY_LEN
+        && idempotency_key.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_');
+    if !valid {
+        return Err(ProvisionError::InvalidIdempotencyKey {
+            max_len: MAX_IDEMPOTENCY_KEY_LEN,
+        });
+    }
+    Ok(())
+}
+
+/// Run `f` once per `idempotency_key`: return the recorded response if this
+/// request already completed, otherwise run it and record a success. Reusing
+/// a key for a different request (`request_hash`) is an error.
+pub fn run<T: Serialize + DeserializeOwned>(
+    kv: &impl KvStore,
+    action: &str,
+    idempotency_key: &str,
+    request_hash: &str,
+    now: u64,
+    f: impl FnOnce() -> Result<T>,
+) -> Result<T> {
+    validate_key(idempotency_key)?;
+    let key = record_key(action, idempotency_key);
+
+    if let Some(recorded) = get_record(kv, &key)? {
+        return replay(recorded, idempotency_key, request_hash);
+    }
+
+    let response = f()?;
+    let record = IdempotencyRecord {
+        request_hash: request_hash.to_string(),
+        response: serde_json::to_string(&response).expect("response serialization cannot fail"),
+        completed_at: now,
+    };
+    let raw = serde_json::to_string(&record).expect("idempotency record serialization cannot fail");
+    if kv.set_if_absent(&key, &raw)? {
+        return Ok(response);
+    }
+
+    // A concurrent run with the same key finished first; answer like it did
+    match get_record(kv, &key)? {
+        Some(recorded) => replay(recorded, idempotency_key, request_hash),
+        None => Err(ProvisionError::KvConflict(format!("Key {} reported as existing but could not be read", key))),
+    }
+}
+
+fn get_record(kv: &impl KvStore, key: &str) -> Result<Option<IdempotencyRecord>> {
+    kv.get(key)?
+        .map(|raw| serde_json::from_str(&raw).map_err(|e| ProvisionError::corrupt("idempotency record", e)))
+        .transpose()
+}
+
+fn replay<T: DeserializeOwned>(recorded: IdempotencyRecord, idempotency_key: &str, request_hash: &str) -> Result<T> {
+    if recorded.request_hash != request_hash {
+        return Err(ProvisionError::IdempotencyKeyReused(idempotency_key.to_string()));
+    }
+    serde_json::from_str(&recorded.response).map_err(|e| ProvisionError::corrupt("idempotency record response", e))
+}
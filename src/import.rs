@@ -0,0 +1,118 @@
+//! Bucket Import
+//!
+//! Writes a batch of raw key/value entries, as returned by `export`, back into
+//! the mappings bucket: for restoring backups and seeding staging from
+//! production snapshots. A key that already holds a different value is a
+//! conflict, resolved by the request's `ImportStrategy`. Keys that already
+//! hold the imported value are left alone under every strategy.
+//!
+//! Mapping records are decoded before anything is written, so a batch with a
+//! malformed record writes nothing. Other values are imported as they are.
+//! With `dry_run` the report says what would change and nothing is written.
+//!
+//! Entries are written one by one, not as a transaction: a failing write
+//! leaves the entries before it imported. Re-running the batch with
+//! `skip_existing` is safe, since entries already written are `unchanged` the
+//! second time. Audit records are imported like any other key; the import's
+//! own audit record then continues the imported chain and moves its head, so
+//! a re-run reports the head as a conflict.
+
+use crate::anonymize;
+use crate::error::{ProvisionError, Result};
+use crate::export::ExportEntry;
+use crate::kv::{KvStore, MappingRecord};
+use crate::migrate::{self, MAX_MIGRATION_BATCH};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+/// Upper bound on entries per import (one export page)
+pub const MAX_IMPORT_BATCH: usize = MAX_MIGRATION_BATCH;
+
+/// What to do with keys that already hold a different value
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum ImportStrategy {
+    /// Keep the stored value
+    SkipExisting,
+    /// Replace the stored value
+    Overwrite,
+    /// Write nothing and fail with `IMPORT_CONFLICT`
+    #[default]
+    FailOnConflict,
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct ImportRequest {
+    pub entries: Vec<ExportEntry>,
+    #[serde(default)]
+    pub strategy: ImportStrategy,
+    /// Report what would change without writing
+    #[serde(default)]
+    pub dry_run: bool,
+    /// Correlation id carried into log events (see `logging`)
+    #[serde(default)]
+    pub request_id: Option<String>,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct ImportReport {
+    pub dry_run: bool,
+    /// Keys that did not exist (written unless `dry_run`)
+    pub created: Vec<String>,
+    /// Keys holding a different value: replaced with `overwrite`, kept with
+    /// `skip_existing`
+    pub conflicts: Vec<String>,
+    /// Keys that already held the imported value
+    pub unchanged: usize,
+}
+
+/// Import one batch of entries
+pub fn import_batch(kv: &impl KvStore, req: &ImportRequest) -> Result<ImportReport> {
+    if req.entries.len() > MAX_IMPORT_BATCH {
+        return Err(ProvisionError::BatchTooLarge { size: req.entries.len(), max: MAX_IMPORT_BATCH });
+    }
+    let mut seen = HashSet::new();
+    for entry in &req.entries {
+        if !seen.insert(entry.key.as_str()) {
+            return Err(ProvisionError::InvalidRequest(format!("duplicate key {}", entry.key)));
+        }
+        if migrate::is_mapping_key(&entry.key) && !anonymize::is_tombstone(&entry.value) {
+            MappingRecord::decode(&entry.value)?;
+        }
+    }
+
+    let keys: Vec<String> = req.entries.iter().map(|entry| entry.key.clone()).collect();
+    let stored = kv.get_many(&keys)?;
+    let mut report = ImportReport { dry_run: req.dry_run, created: Vec::new(), conflicts: Vec::new(), unchanged: 0 };
+    for (entry, stored) in req.entries.iter().zip(&stored) {
+        match stored {
+            None => report.created.push(entry.key.clone()),
+            Some(stored) if *stored == entry.value => report.unchanged += 1,
+            Some(_) => report.conflicts.push(entry.key.clone()),
+        }
+    }
+
+    if req.strategy == ImportStrategy::FailOnConflict && !req.dry_run {
+        if let Some(first) = report.conflicts.first() {
+            return Err(ProvisionError::ImportConflict { conflicts: report.conflicts.len(), first: first.clone() });
+        }
+    }
+    if req.dry_run {
+        return Ok(report);
+    }
+
+    for (entry, stored) in req.entries.iter().zip(&stored) {
+        match stored {
+            // Written by someone else since it was read: left to a re-run
+            None if !kv.set_if_absent(&entry.key, &entry.value)? => {
+                return Err(ProvisionError::KvConflict(format!("{} was written during the import", entry.key)));
+            }
+            Some(stored) if *stored != entry.value && req.strategy == ImportStrategy::Overwrite => {
+                kv.set(&entry.key, &entry.value)?;
+            }
+            _ => {}
+        }
+    }
+    Ok(report)
+}
@@ -0,0 +1,170 @@
+//! In-flight Keys
+//!
+//! A provision creates the user's key in CubeSigner, then writes the mapping.
+//! If the KV store fails in between, the key exists but nothing points to it,
+//! and a retry would create a second one. So the key is recorded here as soon
+//! as it is created; the next provision for the same user (or label) finds it
+//! and maps it instead of creating another.
+//!
+//! Recording is itself a KV write: a store failing at that very write still
+//! leaks the key (`reconcile` finds it). Records are not cleared; once the
+//! mapping is written, provisions no longer create keys for the user, so a
+//! leftover record is only read again by a store replacing the expired
+//! default it was the key of (see `expiry`), which skips it.
+//!
+//! ## Claims
+//!
+//! Two provisions for a user with no key would both find no record and both
+//! create one. So a provision first claims the key creation: it takes the
+//! next number in the user's (or label's) claim sequence with
+//! `set_if_absent`, holds it while creating and recording the key, then
+//! marks it released. A provision finding the latest claim held fails with
+//! `KvConflict` (retryable); its retry finds the recorded key. A claim held
+//! for `CLAIM_TTL_SECS` is taken to belong to a provision that died, and the
+//! next one claims past it.
+//!
+//! ## Key Schema
+//! ```text
+//! inflight:{solana_pubkey}                  → InflightKey # primary key
+//! inflight:{solana_pubkey}:{label}          → InflightKey # labeled key
+//! inflight:{solana_pubkey}[:{label}]:claim:{n} → KeyClaim # n-th claim, from 1
+//! ```
+
+use crate::address::SolanaPubkey;
+use crate::error::{ProvisionError, Result};
+use crate::keys::{CreatedKey, KeyClass, KeyType};
+use crate::kv::KvStore;
+use serde::{Deserialize, Serialize};
+
+/// How long a held claim blocks other provisions: well past the slowest key
+/// creation, so only a provision that died holds one this long
+pub const CLAIM_TTL_SECS: u64 = 120;
+
+/// A created key not yet known to be mapped
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct InflightKey {
+    pub address: String,
+    pub key_id: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub policies: Vec<String>,
+    /// What the key was created as; a retry asking for another kind does not reuse it
+    #[serde(default, skip_serializing_if = "KeyType::is_default")]
+    pub key_type: KeyType,
+    #[serde(default, skip_serializing_if = "KeyClass::is_default")]
+    pub key_class: KeyClass,
+    /// Unix timestamp (seconds)
+    pub created_at: u64,
+}
+
+/// Whether a claim's key creation is still under way
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ClaimStatus {
+    Held,
+    Released,
+}
+
+/// One claim on creating the user's (or label's) key
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct KeyClaim {
+    pub status: ClaimStatus,
+    /// Unix timestamp (seconds)
+    pub claimed_at: u64,
+}
+
+/// Key of the in-flight record: `inflight:{solana_pubkey}`, or
+/// `inflight:{solana_pubkey}:{label}` for a labeled key
+pub fn inflight_key(solana_pubkey: &SolanaPubkey, label: Option<&str>) -> String {
+    match label {
+        Some(label) => format!("inflight:{}:{}", solana_pubkey.as_str(), label),
+        None => format!("inflight:{}", solana_pubkey.as_str()),
+    }
+}
+
+/// Key of the `n`-th claim on creating the user's (or label's) key. Labels
+/// hold no `:`, so this never collides with a labeled record.
+pub fn claim_key(solana_pubkey: &SolanaPubkey, label: Option<&str>, n: u64) -> String {
+    format!("{}:claim:{}", inflight_key(solana_pubkey, label), n)
+}
+
+fn get_claim(kv: &impl KvStore, solana_pubkey: &SolanaPubkey, label: Option<&str>, n: u64) -> Result<Option<KeyClaim>> {
+    kv.get(&claim_key(solana_pubkey, label, n))?
+        .map(|raw| serde_json::from_str(&raw).map_err(|e| ProvisionError::corrupt("key claim", e)))
+        .transpose()
+}
+
+fn encode_claim(claim: &KeyClaim) -> String {
+    serde_json::to_string(claim).expect("key claim serialization cannot fail")
+}
+
+/// Claim creating the user's (or label's) key, returning the claim number to
+/// `release` once the key is recorded. Fails with `KvConflict` while another
+/// provision holds a live claim.
+pub fn claim(kv: &impl KvStore, solana_pubkey: &SolanaPubkey, label: Option<&str>, now: u64) -> Result<u64> {
+    let (mut last, mut latest) = (0, None);
+    while let Some(claim) = get_claim(kv, solana_pubkey, label, last + 1)? {
+        (last, latest) = (last + 1, Some(claim));
+    }
+    if let Some(KeyClaim { status: ClaimStatus::Held, claimed_at }) = latest {
+        if now < claimed_at.saturating_add(CLAIM_TTL_SECS) {
+            return Err(busy(solana_pubkey));
+        }
+    }
+    let claim = KeyClaim { status: ClaimStatus::Held, claimed_at: now };
+    if kv.set_if_absent(&claim_key(solana_pubkey, label, last + 1), &encode_claim(&claim))? {
+        Ok(last + 1)
+    } else {
+        // Another provision claimed it first
+        Err(busy(solana_pubkey))
+    }
+}
+
+fn busy(solana_pubkey: &SolanaPubkey) -> ProvisionError {
+    ProvisionError::KvConflict(format!("Another provision for {} is creating its key", solana_pubkey))
+}
+
+/// Release claim `n`, once its key is recorded (or its creation failed)
+pub fn release(kv: &impl KvStore, solana_pubkey: &SolanaPubkey, label: Option<&str>, n: u64, claimed_at: u64) -> Result<()> {
+    let claim = KeyClaim { status: ClaimStatus::Released, claimed_at };
+    kv.set(&claim_key(solana_pubkey, label, n), &encode_claim(&claim))
+}
+
+/// Key recorded for the user (or label), if it was created as `key_type`/`key_class`
+pub fn get(
+    kv: &impl KvStore,
+    solana_pubkey: &SolanaPubkey,
+    label: Option<&str>,
+    key_type: KeyType,
+    key_class: KeyClass,
+) -> Result<Option<CreatedKey>> {
+    let Some(raw) = kv.get(&inflight_key(solana_pubkey, label))? else {
+        return Ok(None);
+    };
+    let inflight: InflightKey = serde_json::from_str(&raw).map_err(|e| ProvisionError::corrupt("in-flight key", e))?;
+    if inflight.key_type != key_type || inflight.key_class != key_class {
+        return Ok(None);
+    }
+    Ok(Some(CreatedKey { address: inflight.address, key_id: inflight.key_id, policies: inflight.policies }))
+}
+
+/// Record a key just created for the user (or label)
+pub fn record(
+    kv: &impl KvStore,
+    solana_pubkey: &SolanaPubkey,
+    label: Option<&str>,
+    key: &CreatedKey,
+    key_type: KeyType,
+    key_class: KeyClass,
+    now: u64,
+) -> Result<()> {
+    let inflight = InflightKey {
+        address: key.address.clone(),
+        key_id: key.key_id.clone(),
+        policies: key.policies.clone(),
+        key_type,
+        key_class,
+        created_at: now,
+    };
+    let raw = serde_json::to_string(&inflight).expect("in-flight key serialization cannot fail");
+    kv.set(&inflight_key(solana_pubkey, label), &raw)
+}
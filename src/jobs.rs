@@ -0,0 +1,171 @@
+//! Provisioning Jobs
+//!
+//! Creating a key is the slow part of provisioning (several CubeSigner round
+//! trips for an MPC key), slow enough to run into a policy's execution
+//! timeout with the key made but never mapped. Jobs split provisioning into
+//! submit and poll: `submit` checks the request and records it as a `pending`
+//! job, a worker runs it (`Provisioner::handle_run_job`) and records each step,
+//! and the caller polls the job until it is `complete` or `failed`.
+//!
+//! ```text
+//! pending → key_created → stored → complete
+//!       ↘ failed (from any step)
+//! ```
+//!
+//! A worker that dies mid-job leaves it at its last recorded step; running it
+//! again resumes there, reusing a key already created instead of making a
+//! second one. A retryable error leaves the job where it was, with the error
+//! recorded, for the next run; any other error fails it.
+//!
+//! ## Key Schema
+//! ```text
+//! job:{solana_pubkey}:{id}  → ProvisionJob # Claimed with set_if_absent, ids from 1
+//! job:{solana_pubkey}:head  → {id}         # Hint for the latest job id
+//! ```
+
+use crate::address::{EvmAddress, SolanaPubkey};
+use crate::error::{ProvisionError, Result};
+use crate::kv::KvStore;
+use crate::{ProvisionRequest, ProvisionResponse};
+use serde::{Deserialize, Serialize};
+
+/// Attempts at claiming a job id before giving up
+const MAX_SUBMIT_ATTEMPTS: usize = 8;
+
+/// Key of a job: `job:{solana_pubkey}:{id}`
+pub fn job_key(solana_pubkey: &SolanaPubkey, id: u64) -> String {
+    format!("job:{}:{}", solana_pubkey.as_str(), id)
+}
+
+/// Key of the latest-job hint: `job:{solana_pubkey}:head`
+pub fn head_key(solana_pubkey: &SolanaPubkey) -> String {
+    format!("job:{}:head", solana_pubkey.as_str())
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum JobStatus {
+    /// Submitted, not yet run
+    Pending,
+    /// The user's key exists (created by the job, or already there)
+    KeyCreated,
+    /// The mappings are written; `response` is set
+    Stored,
+    Complete,
+    /// Stopped for good; `error` says why
+    Failed,
+}
+
+impl JobStatus {
+    /// Whether the job has finished, one way or the other
+    pub fn is_terminal(self) -> bool {
+        matches!(self, Self::Complete | Self::Failed)
+    }
+}
+
+/// Error a job ran into, as `ProvisionError` serializes it
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct JobError {
+    pub code: String,
+    pub message: String,
+    pub retryable: bool,
+}
+
+impl From<&ProvisionError> for JobError {
+    fn from(e: &ProvisionError) -> Self {
+        Self { code: e.code().to_string(), message: e.to_string(), retryable: e.is_retryable() }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct ProvisionJob {
+    /// Per-address job number, starting at 1
+    pub id: u64,
+    pub status: JobStatus,
+    /// The submitted request, with the chain ids it will provision
+    pub request: ProvisionRequest,
+    /// Key the job created (`None` if the user already had one)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub evm_address: Option<EvmAddress>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub policies: Vec<String>,
+    /// What a synchronous provision would have returned, once `stored`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub response: Option<ProvisionResponse>,
+    /// Why the job failed, or what the last run ran into if it is to be resumed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<JobError>,
+    /// Unix timestamps (seconds)
+    pub created_at: u64,
+    pub updated_at: u64,
+}
+
+/// Record `req` as a pending job. Callers check the ownership proof first
+/// (`mapping::authorize_store`), so a job that would fail on it is never
+/// queued, and running it later does not depend on the proof's expiry.
+pub fn submit(kv: &impl KvStore, req: ProvisionRequest, now: u64) -> Result<ProvisionJob> {
+    if req.chain_ids.is_empty() {
+        return Err(ProvisionError::InvalidRequest("chain_ids cannot be empty".to_string()));
+    }
+
+    let mut job = ProvisionJob {
+        id: 0,
+        status: JobStatus::Pending,
+        request: req,
+        evm_address: None,
+        key_id: None,
+        policies: Vec::new(),
+        response: None,
+        error: None,
+        created_at: now,
+        updated_at: now,
+    };
+    let solana_pubkey = job.request.solana_pubkey.clone();
+    for _ in 0..MAX_SUBMIT_ATTEMPTS {
+        job.id = last_id(kv, &solana_pubkey)? + 1;
+        if kv.set_if_absent(&job_key(&solana_pubkey, job.id), &encode(&job))? {
+            kv.set(&head_key(&solana_pubkey), &job.id.to_string())?;
+            return Ok(job);
+        }
+        // Another submit claimed this id - re-read the tail and retry
+    }
+
+    Err(ProvisionError::KvConflict(format!(
+        "Could not claim a job id for {} after {} attempts",
+        solana_pubkey, MAX_SUBMIT_ATTEMPTS
+    )))
+}
+
+pub fn get(kv: &impl KvStore, solana_pubkey: &SolanaPubkey, id: u64) -> Result<Option<ProvisionJob>> {
+    kv.get(&job_key(solana_pubkey, id))?
+        .map(|raw| serde_json::from_str(&raw).map_err(|e| ProvisionError::corrupt(format!("job {}", id), e)))
+        .transpose()
+}
+
+/// `get`, failing with `JobNotFound` if there is no such job
+pub fn require(kv: &impl KvStore, solana_pubkey: &SolanaPubkey, id: u64) -> Result<ProvisionJob> {
+    get(kv, solana_pubkey, id)?.ok_or_else(|| ProvisionError::JobNotFound { id, solana_pubkey: solana_pubkey.to_string() })
+}
+
+/// Record a job's progress
+pub fn save(kv: &impl KvStore, job: &ProvisionJob) -> Result<()> {
+    kv.set(&job_key(&job.request.solana_pubkey, job.id), &encode(job))
+}
+
+/// Latest job id, starting from the head hint and probing forward (0 if none)
+fn last_id(kv: &impl KvStore, solana_pubkey: &SolanaPubkey) -> Result<u64> {
+    let mut id = kv.get(&head_key(solana_pubkey))?.and_then(|raw| raw.parse::<u64>().ok()).unwrap_or(0);
+    while kv.get(&job_key(solana_pubkey, id + 1))?.is_some() {
+        id += 1;
+    }
+    Ok(id)
+}
+
+fn encode(job: &ProvisionJob) -> String {
+    serde_json::to_string(job).expect("job serialization cannot fail")
+}
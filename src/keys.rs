@@ -0,0 +1,197 @@
+//! Key Creation
+//!
+//! Key creation happens in CubeSigner, outside of the KV store. It is kept
+//! behind the `KeyCreator` (EVM keys) and `SolanaKeyCreator` (Solana keys)
+//! traits so the provisioning flows can be exercised without talking to
+//! CubeSigner.
+
+use crate::chain_id::ChainId;
+use crate::error::{ProvisionError, Result};
+use serde::{Deserialize, Serialize};
+
+/// CubeSigner type of a user's default key (`ProvisionRequest::key_type`).
+/// Serialized as the CubeSigner name; the short names are accepted too.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub enum KeyType {
+    /// Ethereum secp256k1 key; its address is the EVM address
+    #[default]
+    #[serde(alias = "Evm")]
+    SecpEthAddr,
+    /// Bitcoin-style secp256k1 key
+    #[serde(alias = "Secp256k1")]
+    SecpBtc,
+    /// Avalanche X/P-chain secp256k1 key
+    #[serde(alias = "Ava")]
+    SecpAvaAddr,
+    /// Stellar ed25519 key
+    #[serde(alias = "Stellar")]
+    Ed25519StellarAddr,
+}
+
+impl KeyType {
+    /// Key type name in the CubeSigner API
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::SecpEthAddr => "SecpEthAddr",
+            Self::SecpBtc => "SecpBtc",
+            Self::SecpAvaAddr => "SecpAvaAddr",
+            Self::Ed25519StellarAddr => "Ed25519StellarAddr",
+        }
+    }
+
+    /// Key type from its CubeSigner or short name
+    pub fn parse(name: &str) -> Result<Self> {
+        serde_json::from_value(serde_json::Value::from(name))
+            .map_err(|_| ProvisionError::InvalidRequest(format!("Unknown key type {}", name)))
+    }
+
+    pub fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Whether keys of this type have an EVM address (the address of their
+    /// secp256k1 public key), which is what mappings store
+    pub fn has_evm_address(&self) -> bool {
+        !matches!(self, Self::Ed25519StellarAddr)
+    }
+}
+
+/// Most signers an MPC key may be split between
+pub const MAX_MPC_PARTICIPANTS: u8 = 16;
+
+/// How a user's default key is held (`ProvisionRequest::key_class`)
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+#[serde(tag = "class", rename_all = "snake_case")]
+pub enum KeyClass {
+    /// One CubeSigner key
+    #[default]
+    Standard,
+    /// CubeSigner MPC key split between `participants` signers, of which
+    /// `threshold` must take part in every signature
+    Mpc { threshold: u8, participants: u8 },
+}
+
+impl KeyClass {
+    pub fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Check the quorum: 2 ≤ threshold ≤ participants ≤ `MAX_MPC_PARTICIPANTS`
+    pub fn validate(&self) -> Result<()> {
+        match *self {
+            Self::Standard => Ok(()),
+            Self::Mpc { threshold, participants } if 2 <= threshold && threshold <= participants && participants <= MAX_MPC_PARTICIPANTS => {
+                Ok(())
+            }
+            Self::Mpc { threshold, participants } => Err(ProvisionError::InvalidRequest(format!(
+                "Invalid MPC quorum {} of {} (need 2 <= threshold <= participants <= {})",
+                threshold, participants, MAX_MPC_PARTICIPANTS
+            ))),
+        }
+    }
+}
+
+/// A freshly created CubeSigner key
+#[derive(Debug, Clone, PartialEq)]
+pub struct CreatedKey {
+    /// Address (`material_id`): `0x…` for EVM keys, base58 for Solana keys
+    pub address: String,
+    /// CubeSigner key id, e.g. `Key#0x…`
+    pub key_id: String,
+    /// Signing policies attached to the key on creation, by id (none unless
+    /// the creator restricts new keys)
+    pub policies: Vec<String>,
+}
+
+/// An existing CubeSigner key, as listed for reconciliation (`reconcile`)
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct ListedKey {
+    pub key_id: String,
+    /// Address (`material_id`)
+    pub address: String,
+    /// Metadata name, empty if the key has none
+    #[serde(default)]
+    pub name: String,
+}
+
+/// Creates Secp256k1 EVM keys in CubeSigner
+pub trait KeyCreator {
+    /// Create the default EVM key for a Solana address (one per Solana address,
+    /// used across all chains). Metadata name: `EVM_{solana_pubkey}`
+    fn create_evm_key(&self, solana_pubkey: &str) -> Result<CreatedKey>;
+
+    /// Create a chain-specific EVM key (admin updates).
+    /// Metadata name: `EVM_{solana_pubkey}_chain{chain_id}` (`ChainId::key_segment`)
+    fn create_evm_key_for_chain(&self, solana_pubkey: &str, chain_id: &ChainId) -> Result<CreatedKey>;
+
+    /// Create the key of a labeled address (`labels`): the label's key when
+    /// `chain_id` is `None`, otherwise a chain-specific one (updates).
+    /// Metadata name: `EVM_{solana_pubkey}_label_{label}[_chain{chain_id}]`
+    fn create_labeled_evm_key(&self, solana_pubkey: &str, label: &str, chain_id: Option<&ChainId>) -> Result<CreatedKey>;
+
+    /// Create the default key for a Solana address as a `key_type` key of
+    /// `key_class`, with `CreatedKey::address` the key's EVM address. Metadata
+    /// name: `typed_key_name`. Creators that only make standard EVM keys
+    /// refuse anything else.
+    fn create_typed_key(&self, solana_pubkey: &str, key_type: KeyType, key_class: KeyClass) -> Result<CreatedKey> {
+        match (key_type, key_class) {
+            (KeyType::SecpEthAddr, KeyClass::Standard) => self.create_evm_key(solana_pubkey),
+            (KeyType::SecpEthAddr, _) => {
+                Err(ProvisionError::InvalidRequest("MPC keys are not supported by this key creator".to_string()))
+            }
+            (other, _) => Err(ProvisionError::InvalidRequest(format!("Key type {} is not supported by this key creator", other.as_str()))),
+        }
+    }
+}
+
+/// Creates Ed25519 Solana keys in CubeSigner (EVM → Solana provisioning)
+pub trait SolanaKeyCreator {
+    /// Create the Solana key for an EVM address (one per EVM address).
+    /// Metadata name: `SOL_{evm_address}`
+    fn create_solana_key(&self, evm_address: &str) -> Result<CreatedKey>;
+}
+
+/// Lists the EVM keys of the org (every Secp256k1 EVM key, whatever its name)
+pub trait KeyLister {
+    fn list_evm_keys(&self) -> Result<Vec<ListedKey>>;
+
+    /// The EVM key with metadata `name`, if any
+    fn find_evm_key(&self, name: &str) -> Result<Option<ListedKey>> {
+        Ok(self.list_evm_keys()?.into_iter().find(|key| key.name == name))
+    }
+}
+
+/// Metadata name of the default key for a Solana address
+pub fn default_key_name(solana_pubkey: &str) -> String {
+    format!("EVM_{}", solana_pubkey)
+}
+
+/// Metadata name of the default key of another type than `SecpEthAddr`:
+/// `EVM_{solana_pubkey}_{key_type}` (`default_key_name` for `SecpEthAddr`)
+pub fn typed_key_name(solana_pubkey: &str, key_type: KeyType) -> String {
+    match key_type {
+        KeyType::SecpEthAddr => default_key_name(solana_pubkey),
+        other => format!("EVM_{}_{}", solana_pubkey, other.as_str()),
+    }
+}
+
+/// Metadata name of a chain-specific key
+pub fn chain_key_name(solana_pubkey: &str, chain_id: &ChainId) -> String {
+    format!("EVM_{}_chain{}", solana_pubkey, chain_id.key_segment())
+}
+
+/// Metadata name of a labeled address's key
+pub fn labeled_key_name(solana_pubkey: &str, label: &str, chain_id: Option<&ChainId>) -> String {
+    match chain_id {
+        Some(chain_id) => format!("EVM_{}_label_{}_chain{}", solana_pubkey, label, chain_id.key_segment()),
+        None => format!("EVM_{}_label_{}", solana_pubkey, label),
+    }
+}
+
+/// Metadata name of the Solana key for an EVM address
+pub fn solana_key_name(evm_address: &str) -> String {
+    format!("SOL_{}", evm_address)
+}
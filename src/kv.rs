@@ -0,0 +1,599 @@
+//! KV Store Abstraction
+//!
+//! The provisioning flow only needs three primitives from the C2F key-value
+//! bucket: read, atomic insert (`IfExists::Deny`) and overwrite
+//! (`IfExists::Overwrite`). They are exposed here as the `KvStore` trait so
+//! the same flow runs against the real bucket and against test doubles.
+//! Schema migrations additionally list keys (`KvStore::list_keys`), lookups
+//! read many keys at once (`KvStore::get_many`), and sweeping expired
+//! mappings deletes keys (`KvStore::delete`).
+//!
+//! ## Key Schema
+//! ```text
+//! default:{solana_pubkey}     → MappingRecord   # Default address used across all chains
+//! {solana_pubkey}:{chain_id}  → MappingRecord   # Chain-specific mapping
+//! reverse:{evm_address}       → {solana_pubkey} # Reverse index (EVM → Solana)
+//! chains:{solana_pubkey}      → [chain_id, …]   # Chains the user has mappings for (legacy entries are numbers)
+//! history:{solana_pubkey}:{chain_id} → [MappingHistoryEntry, …] # Replaced values, oldest first
+//! nonce:{solana_pubkey}:{nonce} → {used_at}     # Consumed self-service update nonces
+//! nonce:{solana_pubkey}:head  → {nonce}         # Highest consumed nonce
+//! store_nonce:{solana_pubkey}:{nonce} → {used_at} # Consumed store authorization nonces
+//! revision:{solana_pubkey}:{chain_id}:{revision} → RevisionClaim # Claimed by the update that wrote `revision`
+//! revision:{solana_pubkey}:{chain_id}:{revision}:{n} → RevisionClaim # n-th claim, from 2, taking over a stale one
+//! renewal:{solana_pubkey}:{expired_at} → MappingRecord # Default replacing the one that expired at `expired_at`
+//! ```
+
+use crate::address::{EvmAddress, SolanaPubkey};
+use crate::anonymize;
+use crate::chain_id::ChainId;
+use crate::keys::{KeyClass, KeyType};
+use crate::spend_limits::SpendLimit;
+use crate::MappingHistoryEntry;
+use crate::error::{ProvisionError, Result};
+use serde::{Deserialize, Serialize};
+
+/// Bucket name for Solana to EVM mappings
+pub const BUCKET_NAME: &str = "solana_to_evm";
+
+/// Minimal interface over the C2F key-value bucket
+pub trait KvStore {
+    /// Read a value, `None` if the key does not exist
+    fn get(&self, key: &str) -> Result<Option<String>>;
+
+    /// Atomic write that only succeeds if the key does not exist yet
+    /// (`IfExists::Deny`). Returns `false` if another writer got there first.
+    fn set_if_absent(&self, key: &str, value: &str) -> Result<bool>;
+
+    /// Write allowing overwrite (`IfExists::Overwrite`), used for admin updates
+    fn set(&self, key: &str, value: &str) -> Result<()>;
+
+    /// Read several values in one round-trip, in the order of `keys`. The
+    /// default reads them one by one; stores with a batched read override it.
+    fn get_many(&self, keys: &[String]) -> Result<Vec<Option<String>>> {
+        keys.iter().map(|key| self.get(key)).collect()
+    }
+
+    /// Up to `limit` keys in ascending order, starting strictly after `after`.
+    /// Only needed for schema migrations (see `migrate`); stores that cannot
+    /// enumerate keys keep the default.
+    fn list_keys(&self, _after: Option<&str>, _limit: usize) -> Result<Vec<String>> {
+        Err(ProvisionError::Unsupported("Key listing"))
+    }
+
+    /// Remove a key; removing a missing key succeeds. Only needed to sweep
+    /// expired mappings (see `expiry`): the C2F bucket has no delete, so
+    /// stores keep the default and mappings stay immutable.
+    fn delete(&self, _key: &str) -> Result<()> {
+        Err(ProvisionError::Unsupported("Key deletion"))
+    }
+}
+
+impl<T: KvStore + ?Sized> KvStore for Box<T> {
+    fn get(&self, key: &str) -> Result<Option<String>> {
+        (**self).get(key)
+    }
+
+    fn set_if_absent(&self, key: &str, value: &str) -> Result<bool> {
+        (**self).set_if_absent(key, value)
+    }
+
+    fn set(&self, key: &str, value: &str) -> Result<()> {
+        (**self).set(key, value)
+    }
+
+    fn get_many(&self, keys: &[String]) -> Result<Vec<Option<String>>> {
+        (**self).get_many(keys)
+    }
+
+    fn list_keys(&self, after: Option<&str>, limit: usize) -> Result<Vec<String>> {
+        (**self).list_keys(after, limit)
+    }
+
+    fn delete(&self, key: &str) -> Result<()> {
+        (**self).delete(key)
+    }
+}
+
+/// A `KvStore` refusing every write (`UNSUPPORTED`), for builds that must
+/// only read
+pub struct ReadOnly<S>(pub S);
+
+impl<S: KvStore> KvStore for ReadOnly<S> {
+    fn get(&self, key: &str) -> Result<Option<String>> {
+        self.0.get(key)
+    }
+
+    fn set_if_absent(&self, _key: &str, _value: &str) -> Result<bool> {
+        Err(ProvisionError::Unsupported("Writing"))
+    }
+
+    fn set(&self, _key: &str, _value: &str) -> Result<()> {
+        Err(ProvisionError::Unsupported("Writing"))
+    }
+
+    fn get_many(&self, keys: &[String]) -> Result<Vec<Option<String>>> {
+        self.0.get_many(keys)
+    }
+
+    fn list_keys(&self, after: Option<&str>, limit: usize) -> Result<Vec<String>> {
+        self.0.list_keys(after, limit)
+    }
+
+    fn delete(&self, _key: &str) -> Result<()> {
+        Err(ProvisionError::Unsupported("Deletion"))
+    }
+}
+
+// =============================================================================
+// KEY FORMAT
+// =============================================================================
+
+/// Key of the chain-specific mapping: `{solana_pubkey}:{chain_id}` (see `ChainId::key_segment`)
+pub fn chain_key(solana_pubkey: &SolanaPubkey, chain_id: &ChainId) -> String {
+    format!("{}:{}", solana_pubkey.as_str(), chain_id.key_segment())
+}
+
+/// Key of the default (chain-agnostic) mapping: `default:{solana_pubkey}`
+pub fn default_key(solana_pubkey: &SolanaPubkey) -> String {
+    format!("default:{}", solana_pubkey.as_str())
+}
+
+/// Key of the reverse index entry: `reverse:{evm_address}`
+pub fn reverse_key(evm_address: &EvmAddress) -> String {
+    format!("reverse:{}", evm_address.as_str())
+}
+
+/// Key of the per-user chain index: `chains:{solana_pubkey}`
+pub fn chain_index_key(solana_pubkey: &SolanaPubkey) -> String {
+    format!("chains:{}", solana_pubkey.as_str())
+}
+
+/// Key of a chain mapping's history: `history:{solana_pubkey}:{chain_id}`
+pub fn history_key(solana_pubkey: &SolanaPubkey, chain_id: &ChainId) -> String {
+    format!("history:{}:{}", solana_pubkey.as_str(), chain_id.key_segment())
+}
+
+/// Key claimed by the update that writes a chain mapping's `revision`:
+/// `revision:{solana_pubkey}:{chain_id}:{revision}`
+pub fn revision_key(solana_pubkey: &SolanaPubkey, chain_id: &ChainId, revision: u64) -> String {
+    format!("revision:{}:{}:{}", solana_pubkey.as_str(), chain_id.key_segment(), revision)
+}
+
+/// Key of the `n`-th claim on a revision: `revision_key` for the first,
+/// `{revision_key}:{n}` for each takeover of a stale one
+pub fn revision_claim_key(solana_pubkey: &SolanaPubkey, chain_id: &ChainId, revision: u64, n: u64) -> String {
+    nth_claim_key(&revision_key(solana_pubkey, chain_id, revision), n)
+}
+
+/// Key of the `n`-th claim on the revision whose first claim is `first`
+fn nth_claim_key(first: &str, n: u64) -> String {
+    match n {
+        1 => first.to_string(),
+        n => format!("{}:{}", first, n),
+    }
+}
+
+/// Key of the default replacing one that expired at `expired_at`:
+/// `renewal:{solana_pubkey}:{expired_at}`
+pub fn renewal_key(solana_pubkey: &SolanaPubkey, expired_at: u64) -> String {
+    format!("renewal:{}:{}", solana_pubkey.as_str(), expired_at)
+}
+
+/// Key of a consumed self-service nonce: `nonce:{solana_pubkey}:{nonce}`
+pub fn nonce_key(solana_pubkey: &SolanaPubkey, nonce: u64) -> String {
+    format!("nonce:{}:{}", solana_pubkey.as_str(), nonce)
+}
+
+/// Key of the highest consumed nonce of a Solana address: `nonce:{solana_pubkey}:head`
+pub fn nonce_head_key(solana_pubkey: &SolanaPubkey) -> String {
+    format!("nonce:{}:head", solana_pubkey.as_str())
+}
+
+/// Key of a consumed store authorization nonce: `store_nonce:{solana_pubkey}:{nonce}`
+pub fn store_nonce_key(solana_pubkey: &SolanaPubkey, nonce: u64) -> String {
+    format!("store_nonce:{}:{}", solana_pubkey.as_str(), nonce)
+}
+
+// =============================================================================
+// VALUE FORMAT
+// =============================================================================
+
+/// Current `MappingRecord` schema version.
+///
+/// - 0: plain address string (no metadata)
+/// - 1: `{"address","key_id"}`
+/// - 2: adds `created_at`, `created_by` and `version`
+pub const MAPPING_RECORD_VERSION: u32 = 2;
+
+/// Value stored under mapping keys (`default:…` and `{pubkey}:{chain_id}`).
+///
+/// Encoded as JSON, address lowercase:
+/// `{"address":"0x…","key_id":"Key#0x…","created_at":…,"version":2,"created_by":"…"}`.
+/// Older values (plain strings, version-less JSON) still decode, with the
+/// fields they did not carry set to `None`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct MappingRecord {
+    #[serde(with = "crate::address::lowercase")]
+    pub address: EvmAddress,
+    /// CubeSigner key id of the key behind `address`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key_id: Option<String>,
+    /// Unix timestamp (seconds) the record was written
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created_at: Option<u64>,
+    /// Schema version (see `MAPPING_RECORD_VERSION`)
+    #[serde(default = "json_v1")]
+    pub version: u32,
+    /// Who wrote the record (Solana address for provisions, admin or user for updates)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created_by: Option<String>,
+    /// Updates applied to the chain mapping so far (0 until the first update)
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub revision: u64,
+    /// Address the user linked from a wallet of their own (`mapping::link_external`);
+    /// CubeSigner holds no key for it
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub external: bool,
+    /// CubeSigner type of the key behind `address` (see `ProvisionRequest::key_type`)
+    #[serde(default, skip_serializing_if = "KeyType::is_default")]
+    pub key_type: KeyType,
+    /// How the key behind `address` is held (see `ProvisionRequest::key_class`)
+    #[serde(default, skip_serializing_if = "KeyClass::is_default")]
+    pub key_class: KeyClass,
+    /// CubeSigner signing policies attached to the key when it was created, by id
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub policies: Vec<String>,
+    /// Limit on what the key may sign for (see `spend_limits`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub spend_limit: Option<SpendLimit>,
+    /// Unix timestamp (seconds) a temporary mapping stops being returned at (see `expiry`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<u64>,
+}
+
+fn json_v1() -> u32 {
+    1
+}
+
+fn is_zero(revision: &u64) -> bool {
+    *revision == 0
+}
+
+fn is_false(external: &bool) -> bool {
+    !external
+}
+
+impl MappingRecord {
+    pub fn new(address: &EvmAddress, key_id: Option<&str>, created_by: &str, created_at: u64) -> Self {
+        Self {
+            address: address.clone(),
+            key_id: key_id.map(str::to_string),
+            created_at: Some(created_at),
+            version: MAPPING_RECORD_VERSION,
+            created_by: Some(created_by.to_string()),
+            revision: 0,
+            external: false,
+            key_type: KeyType::default(),
+            key_class: KeyClass::default(),
+            policies: Vec::new(),
+            spend_limit: None,
+            expires_at: None,
+        }
+    }
+
+    /// Whether the mapping is temporary and expired at or before `now`
+    pub fn is_expired(&self, now: u64) -> bool {
+        self.expires_at.is_some_and(|expires_at| expires_at <= now)
+    }
+
+    /// Record of an externally owned address: no key id, `external: true`
+    pub fn external(address: &EvmAddress, created_by: &str, created_at: u64) -> Self {
+        Self {
+            external: true,
+            ..Self::new(address, None, created_by, created_at)
+        }
+    }
+
+    pub fn encode(&self) -> String {
+        serde_json::to_string(self).expect("MappingRecord serialization cannot fail")
+    }
+
+    pub fn decode(raw: &str) -> Result<Self> {
+        if !raw.starts_with('{') {
+            // Version 0: plain address string
+            return Ok(Self {
+                address: EvmAddress::parse(raw)?,
+                key_id: None,
+                created_at: None,
+                version: 0,
+                created_by: None,
+                revision: 0,
+                external: false,
+                key_type: KeyType::default(),
+                key_class: KeyClass::default(),
+                policies: Vec::new(),
+                spend_limit: None,
+                expires_at: None,
+            });
+        }
+
+        let record: Self = serde_json::from_str(raw)
+            .map_err(|e| anonymize::refuse_tombstone(raw).unwrap_or_else(|| ProvisionError::corrupt("mapping record", e)))?;
+        if record.version > MAPPING_RECORD_VERSION {
+            return Err(ProvisionError::UnsupportedRecordVersion(record.version));
+        }
+        Ok(record)
+    }
+}
+
+// =============================================================================
+// KV OPERATIONS
+// =============================================================================
+
+pub fn get_chain_mapping(kv: &impl KvStore, solana_pubkey: &SolanaPubkey, chain_id: &ChainId) -> Result<Option<MappingRecord>> {
+    get_value(kv, &chain_key(solana_pubkey, chain_id))
+}
+
+pub fn get_default_mapping(kv: &impl KvStore, solana_pubkey: &SolanaPubkey) -> Result<Option<MappingRecord>> {
+    get_value(kv, &default_key(solana_pubkey))
+}
+
+/// Mapping records under `keys` (`default_key`/`chain_key`), read with one `get_many`
+pub fn get_mappings(kv: &impl KvStore, keys: &[String]) -> Result<Vec<Option<MappingRecord>>> {
+    kv.get_many(keys)?
+        .into_iter()
+        .map(|raw| raw.map(|raw| MappingRecord::decode(&raw)).transpose())
+        .collect()
+}
+
+/// `get_mappings` for bucket scans: erased records (see `anonymize`) read as `None`
+pub fn get_live_mappings(kv: &impl KvStore, keys: &[String]) -> Result<Vec<Option<MappingRecord>>> {
+    kv.get_many(keys)?
+        .into_iter()
+        .map(|raw| raw.filter(|raw| !anonymize::is_tombstone(raw)).map(|raw| MappingRecord::decode(&raw)).transpose())
+        .collect()
+}
+
+pub fn get_existing_mapping(kv: &impl KvStore, solana_pubkey: &SolanaPubkey, chain_id: &ChainId) -> Result<Option<EvmAddress>> {
+    Ok(get_chain_mapping(kv, solana_pubkey, chain_id)?.map(|v| v.address))
+}
+
+pub fn get_default_evm_address(kv: &impl KvStore, solana_pubkey: &SolanaPubkey) -> Result<Option<EvmAddress>> {
+    Ok(get_default_mapping(kv, solana_pubkey)?.map(|v| v.address))
+}
+
+/// Store a chain mapping (first-writer-wins), returning the value that ended up stored
+pub fn store_mapping_once(
+    kv: &impl KvStore,
+    solana_pubkey: &SolanaPubkey,
+    chain_id: &ChainId,
+    value: &MappingRecord,
+) -> Result<MappingRecord> {
+    let stored = store_once(kv, &chain_key(solana_pubkey, chain_id), &value.encode())?;
+    MappingRecord::decode(&stored)
+}
+
+/// Store the default mapping (first-writer-wins), returning the value that ended up stored
+pub fn store_default_mapping(kv: &impl KvStore, solana_pubkey: &SolanaPubkey, value: &MappingRecord) -> Result<MappingRecord> {
+    let stored = store_once(kv, &default_key(solana_pubkey), &value.encode())?;
+    MappingRecord::decode(&stored)
+}
+
+/// Replace an expired default mapping (first-writer-wins), returning the
+/// replacement that ended up stored.
+///
+/// The replacement is claimed under the expired default's `renewal_key` with
+/// `set_if_absent`, so of two stores racing to replace the same default
+/// exactly one wins and both answer with its record; the claim is then
+/// copied over the default. Needs no delete. Every temporary default expires
+/// later than the one it replaced, so each expiry is claimed once.
+pub fn renew_default_mapping(
+    kv: &impl KvStore,
+    solana_pubkey: &SolanaPubkey,
+    expired: &MappingRecord,
+    value: &MappingRecord,
+) -> Result<MappingRecord> {
+    let expired_at = expired.expires_at.unwrap_or_default();
+    let stored = store_once(kv, &renewal_key(solana_pubkey, expired_at), &value.encode())?;
+    // Copied only over the expired default: a retry after the replacement
+    // itself expired and was renewed must not bring it back
+    if get_default_mapping(kv, solana_pubkey)?.is_some_and(|current| current.expires_at == expired.expires_at) {
+        kv.set(&default_key(solana_pubkey), &stored)?;
+    }
+    MappingRecord::decode(&stored)
+}
+
+pub fn update_mapping(kv: &impl KvStore, solana_pubkey: &SolanaPubkey, chain_id: &ChainId, value: &MappingRecord) -> Result<()> {
+    kv.set(&chain_key(solana_pubkey, chain_id), &value.encode())
+}
+
+/// Look up which Solana address owns an EVM address
+pub fn get_reverse_mapping(kv: &impl KvStore, evm_address: &EvmAddress) -> Result<Option<SolanaPubkey>> {
+    kv.get(&reverse_key(evm_address))?.map(|raw| reverse_owner(&raw)).transpose()
+}
+
+/// Record the owner of an EVM address (first-writer-wins), returning the stored owner
+pub fn store_reverse_mapping(
+    kv: &impl KvStore,
+    evm_address: &EvmAddress,
+    solana_pubkey: &SolanaPubkey,
+) -> Result<SolanaPubkey> {
+    reverse_owner(&store_once(kv, &reverse_key(evm_address), solana_pubkey.as_str())?)
+}
+
+/// Owner in a reverse entry; `Anonymized` if the owner was erased
+fn reverse_owner(raw: &str) -> Result<SolanaPubkey> {
+    if anonymize::is_pseudonym(raw) {
+        return Err(ProvisionError::Anonymized { pseudonym: raw.to_string() });
+    }
+    SolanaPubkey::parse(raw)
+}
+
+/// Chain ids the user has mappings for (sorted, empty if none recorded)
+pub fn get_chain_index(kv: &impl KvStore, solana_pubkey: &SolanaPubkey) -> Result<Vec<ChainId>> {
+    match kv.get(&chain_index_key(solana_pubkey))? {
+        Some(raw) => serde_json::from_str(&raw).map_err(|e| ProvisionError::corrupt("chain index", e)),
+        None => Ok(Vec::new()),
+    }
+}
+
+/// Add chain ids to the user's chain index. Returns whether any was missing.
+///
+/// Read-modify-write: the index only ever grows, so a concurrent writer can at
+/// worst drop a chain that the next store/update for it re-adds.
+pub fn add_to_chain_index(kv: &impl KvStore, solana_pubkey: &SolanaPubkey, chain_ids: &[ChainId]) -> Result<bool> {
+    let mut index = get_chain_index(kv, solana_pubkey)?;
+    let before = index.len();
+
+    index.extend_from_slice(chain_ids);
+    index.sort_unstable();
+    index.dedup();
+
+    if index.len() == before {
+        return Ok(false);
+    }
+    let raw = serde_json::to_string(&index).expect("chain index serialization cannot fail");
+    kv.set(&chain_index_key(solana_pubkey), &raw)?;
+    Ok(true)
+}
+
+/// Remove a chain from the user's chain index (a swept mapping, see `expiry`).
+/// Returns whether it was there.
+pub fn remove_from_chain_index(kv: &impl KvStore, solana_pubkey: &SolanaPubkey, chain_id: &ChainId) -> Result<bool> {
+    let mut index = get_chain_index(kv, solana_pubkey)?;
+    let before = index.len();
+    index.retain(|indexed| indexed != chain_id);
+
+    if index.len() == before {
+        return Ok(false);
+    }
+    let raw = serde_json::to_string(&index).expect("chain index serialization cannot fail");
+    kv.set(&chain_index_key(solana_pubkey), &raw)?;
+    Ok(true)
+}
+
+/// Past values of a chain mapping, oldest first
+pub fn get_history(kv: &impl KvStore, solana_pubkey: &SolanaPubkey, chain_id: &ChainId) -> Result<Vec<MappingHistoryEntry>> {
+    get_history_at(kv, &history_key(solana_pubkey, chain_id))
+}
+
+/// Append a replaced value to a chain mapping's history
+pub fn append_history(
+    kv: &impl KvStore,
+    solana_pubkey: &SolanaPubkey,
+    chain_id: &ChainId,
+    entry: MappingHistoryEntry,
+) -> Result<()> {
+    append_history_at(kv, &history_key(solana_pubkey, chain_id), entry)
+}
+
+/// The history stored under `key` (`history_key`, `labels::labeled_history_key`)
+pub fn get_history_at(kv: &impl KvStore, key: &str) -> Result<Vec<MappingHistoryEntry>> {
+    match kv.get(key)? {
+        Some(raw) => serde_json::from_str(&raw).map_err(|e| ProvisionError::corrupt("history", e)),
+        None => Ok(Vec::new()),
+    }
+}
+
+/// Append a replaced value to the history stored under `key`
+pub fn append_history_at(kv: &impl KvStore, key: &str, entry: MappingHistoryEntry) -> Result<()> {
+    let mut history = get_history_at(kv, key)?;
+    history.push(entry);
+
+    let raw = serde_json::to_string(&history).expect("history serialization cannot fail");
+    kv.set(key, &raw)
+}
+
+/// How long an update's revision claim blocks other updates: well past the
+/// slowest update, so only one that died before writing its record holds a
+/// claim this long
+pub const REVISION_CLAIM_TTL_SECS: u64 = 120;
+
+/// One update's claim on writing a revision of a chain mapping
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RevisionClaim {
+    pub actor: String,
+    /// Unix timestamp (seconds)
+    pub claimed_at: u64,
+}
+
+/// Claim `revision` of a chain mapping for an update (atomic). Returns `false`
+/// if a concurrent update holds it.
+///
+/// Callers claim the revision after the one they read, so a claim held past
+/// `REVISION_CLAIM_TTL_SECS` belongs to an update that died before writing
+/// its record; it is taken over with the next claim number instead of
+/// blocking every later update.
+pub fn claim_revision(
+    kv: &impl KvStore,
+    solana_pubkey: &SolanaPubkey,
+    chain_id: &ChainId,
+    revision: u64,
+    actor: &str,
+    now: u64,
+) -> Result<bool> {
+    claim_revision_at(kv, &revision_key(solana_pubkey, chain_id, revision), actor, now)
+}
+
+/// `claim_revision` for the revision whose first claim is `first`
+/// (`revision_key`, `labels::labeled_revision_key`)
+pub fn claim_revision_at(kv: &impl KvStore, first: &str, actor: &str, now: u64) -> Result<bool> {
+    let claim = RevisionClaim { actor: actor.to_string(), claimed_at: now };
+    let claim = serde_json::to_string(&claim).expect("revision claim serialization cannot fail");
+    let mut n = 1;
+    loop {
+        let key = nth_claim_key(first, n);
+        if kv.set_if_absent(&key, &claim)? {
+            return Ok(true);
+        }
+        // Claims from before they were timestamped hold just the actor
+        let claimed_at = kv.get(&key)?.and_then(|raw| serde_json::from_str::<RevisionClaim>(&raw).ok()).map_or(0, |held| held.claimed_at);
+        if now < claimed_at.saturating_add(REVISION_CLAIM_TTL_SECS) {
+            return Ok(false);
+        }
+        n += 1;
+    }
+}
+
+/// Highest nonce the Solana address consumed, `None` before its first
+pub fn get_nonce_head(kv: &impl KvStore, solana_pubkey: &SolanaPubkey) -> Result<Option<u64>> {
+    kv.get(&nonce_head_key(solana_pubkey))?
+        .map(|raw| raw.parse().map_err(|e| ProvisionError::corrupt("nonce head", e)))
+        .transpose()
+}
+
+/// Mark a nonce as used (atomic) and raise the nonce head to it. Returns
+/// `false` if it had already been used.
+///
+/// The head is read-modify-write: two requests consuming different nonces at
+/// once can leave it at the lower one. Each nonce is still claimed atomically,
+/// so neither can be replayed; only the older of the two stays above the head.
+pub fn consume_nonce(kv: &impl KvStore, solana_pubkey: &SolanaPubkey, nonce: u64, used_at: u64) -> Result<bool> {
+    if !kv.set_if_absent(&nonce_key(solana_pubkey, nonce), &used_at.to_string())? {
+        return Ok(false);
+    }
+    if get_nonce_head(kv, solana_pubkey)?.is_none_or(|head| head < nonce) {
+        kv.set(&nonce_head_key(solana_pubkey), &nonce.to_string())?;
+    }
+    Ok(true)
+}
+
+/// Mark a store authorization's nonce as used (atomic). Returns `false` if it
+/// had already been used. Store nonces only need to be unused, not growing:
+/// stores of one Solana address may race, and are idempotent.
+pub fn consume_store_nonce(kv: &impl KvStore, solana_pubkey: &SolanaPubkey, nonce: u64, used_at: u64) -> Result<bool> {
+    kv.set_if_absent(&store_nonce_key(solana_pubkey, nonce), &used_at.to_string())
+}
+
+fn get_value(kv: &impl KvStore, key: &str) -> Result<Option<MappingRecord>> {
+    kv.get(key)?.map(|raw| MappingRecord::decode(&raw)).transpose()
+}
+
+/// Atomic insert; if we lost the race, read back the winner's value
+fn store_once(kv: &impl KvStore, key: &str, value: &str) -> Result<String> {
+    if kv.set_if_absent(key, value)? {
+        return Ok(value.to_string());
+    }
+    kv.get(key)?
+        .ok_or_else(|| ProvisionError::KvConflict(format!("Key {} reported as existing but could not be read", key)))
+}
@@ -0,0 +1,174 @@
+//! Labeled Addresses
+//!
+//! A user can hold more than one EVM address per chain. The chain mapping
+//! (`{solana_pubkey}:{chain_id}`) stays the primary address: it is what `get`
+//! returns in `chain_mappings`, what chains inherit, and what an update without
+//! a label rotates. Further addresses live next to it under a label
+//! ("trading", "cold"); the label `primary` is reserved for the chain mapping.
+//!
+//! A label works like the default mapping: `store` with a label creates one
+//! key for it (`EVM_{solana_pubkey}_label_{label}`) and maps each requested
+//! chain to it, first writer wins. An update with a label rotates the label's
+//! address on one chain like an update of the chain mapping: it claims the
+//! next revision, keeps the replaced value in the label's history for the
+//! chain, and retires the replaced address (`retirement`).
+//!
+//! ## Key Schema
+//! ```text
+//! labeled:{solana_pubkey}:{label}              → MappingRecord   # The label's key, first-writer-wins
+//! labeled:{solana_pubkey}:{label}:{chain_id}   → MappingRecord   # First-writer-wins; overwritten by updates
+//! labeled_history:{solana_pubkey}:{label}:{chain_id}             → [MappingHistoryEntry, ...]
+//! labeled_revision:{solana_pubkey}:{label}:{chain_id}:{revision} → RevisionClaim  # Claimed by the update writing it
+//! labels:{solana_pubkey}                       → {label: [chain_id, ...]}  # Grow-only index
+//! ```
+
+use crate::address::{EvmAddress, SolanaPubkey};
+use crate::chain_id::ChainId;
+use crate::error::{ProvisionError, Result};
+use crate::kv::{self, KvStore, MappingRecord};
+use std::collections::{BTreeMap, HashMap};
+
+/// Label of the chain mapping itself; storing or updating with it is the same as without a label
+pub const PRIMARY_LABEL: &str = "primary";
+
+/// Longest accepted label
+pub const MAX_LABEL_LEN: usize = 32;
+
+/// Labels of a user and the chains each one is mapped on
+pub type LabelIndex = BTreeMap<String, Vec<ChainId>>;
+
+/// The label a request names, or `None` for the primary address (no label or
+/// `primary`). Labels are 1-32 chars of `[a-z0-9-]`.
+pub fn parse_label(label: Option<&str>) -> Result<Option<&str>> {
+    let Some(label) = label.filter(|label| *label != PRIMARY_LABEL) else {
+        return Ok(None);
+    };
+    let valid = !label.is_empty()
+        && label.len() <= MAX_LABEL_LEN
+        && label.bytes().all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'-');
+    if !valid {
+        return Err(ProvisionError::InvalidRequest(format!(
+            "label must be 1-{} characters of a-z, 0-9 and '-', got '{}'",
+            MAX_LABEL_LEN, label
+        )));
+    }
+    Ok(Some(label))
+}
+
+/// Key of a label's key record: `labeled:{solana_pubkey}:{label}`
+pub fn label_key(solana_pubkey: &SolanaPubkey, label: &str) -> String {
+    format!("labeled:{}:{}", solana_pubkey.as_str(), label)
+}
+
+/// Key of a labeled chain mapping: `labeled:{solana_pubkey}:{label}:{chain_id}`
+pub fn labeled_key(solana_pubkey: &SolanaPubkey, label: &str, chain_id: &ChainId) -> String {
+    format!("labeled:{}:{}:{}", solana_pubkey.as_str(), label, chain_id.key_segment())
+}
+
+/// Key of a labeled chain mapping's history:
+/// `labeled_history:{solana_pubkey}:{label}:{chain_id}`
+pub fn labeled_history_key(solana_pubkey: &SolanaPubkey, label: &str, chain_id: &ChainId) -> String {
+    format!("labeled_history:{}:{}:{}", solana_pubkey.as_str(), label, chain_id.key_segment())
+}
+
+/// Key claimed by the update that writes a labeled chain mapping's
+/// `revision` (see `kv::claim_revision`):
+/// `labeled_revision:{solana_pubkey}:{label}:{chain_id}:{revision}`
+pub fn labeled_revision_key(solana_pubkey: &SolanaPubkey, label: &str, chain_id: &ChainId, revision: u64) -> String {
+    format!("labeled_revision:{}:{}:{}:{}", solana_pubkey.as_str(), label, chain_id.key_segment(), revision)
+}
+
+/// Key of a user's label index: `labels:{solana_pubkey}`
+pub fn label_index_key(solana_pubkey: &SolanaPubkey) -> String {
+    format!("labels:{}", solana_pubkey.as_str())
+}
+
+pub fn get_label_mapping(kv: &impl KvStore, solana_pubkey: &SolanaPubkey, label: &str) -> Result<Option<MappingRecord>> {
+    kv.get(&label_key(solana_pubkey, label))?.map(|raw| MappingRecord::decode(&raw)).transpose()
+}
+
+/// Store a label's key record (first-writer-wins), returning the value that ended up stored
+pub fn store_label_mapping(kv: &impl KvStore, solana_pubkey: &SolanaPubkey, label: &str, value: &MappingRecord) -> Result<MappingRecord> {
+    let key = label_key(solana_pubkey, label);
+    kv.set_if_absent(&key, &value.encode())?;
+    get_label_mapping(kv, solana_pubkey, label)?
+        .ok_or_else(|| ProvisionError::KvConflict(format!("Key {} reported as existing but could not be read", key)))
+}
+
+pub fn get_labeled_mapping(
+    kv: &impl KvStore,
+    solana_pubkey: &SolanaPubkey,
+    label: &str,
+    chain_id: &ChainId,
+) -> Result<Option<MappingRecord>> {
+    kv.get(&labeled_key(solana_pubkey, label, chain_id))?
+        .map(|raw| MappingRecord::decode(&raw))
+        .transpose()
+}
+
+pub fn update_labeled_mapping(
+    kv: &impl KvStore,
+    solana_pubkey: &SolanaPubkey,
+    label: &str,
+    chain_id: &ChainId,
+    value: &MappingRecord,
+) -> Result<()> {
+    kv.set(&labeled_key(solana_pubkey, label, chain_id), &value.encode())
+}
+
+/// The user's labels (empty if none recorded)
+pub fn get_label_index(kv: &impl KvStore, solana_pubkey: &SolanaPubkey) -> Result<LabelIndex> {
+    match kv.get(&label_index_key(solana_pubkey))? {
+        Some(raw) => serde_json::from_str(&raw).map_err(|e| ProvisionError::corrupt("label index", e)),
+        None => Ok(LabelIndex::new()),
+    }
+}
+
+/// Add chain ids to a label in the user's label index. Returns whether any was
+/// missing. Read-modify-write like `kv::add_to_chain_index`, and for the same
+/// reason safe: the index only grows.
+pub fn add_to_label_index(kv: &impl KvStore, solana_pubkey: &SolanaPubkey, label: &str, chain_ids: &[ChainId]) -> Result<bool> {
+    let mut index = get_label_index(kv, solana_pubkey)?;
+    let chains = index.entry(label.to_string()).or_default();
+    let before = chains.len();
+
+    chains.extend_from_slice(chain_ids);
+    chains.sort_unstable();
+    chains.dedup();
+
+    if chains.len() == before {
+        return Ok(false);
+    }
+    let raw = serde_json::to_string(&index).expect("label index serialization cannot fail");
+    kv.set(&label_index_key(solana_pubkey), &raw)?;
+    Ok(true)
+}
+
+/// Labeled addresses of the user on `chain_ids` (every chain when empty), by chain and label
+pub fn get_labeled(
+    kv: &impl KvStore,
+    solana_pubkey: &SolanaPubkey,
+    chain_ids: &[ChainId],
+) -> Result<HashMap<ChainId, BTreeMap<String, EvmAddress>>> {
+    let mut wanted = Vec::new();
+    for (label, chains) in get_label_index(kv, solana_pubkey)? {
+        for chain_id in chains {
+            if chain_ids.is_empty() || chain_ids.contains(&chain_id) {
+                wanted.push((label.clone(), chain_id));
+            }
+        }
+    }
+
+    if wanted.is_empty() {
+        return Ok(HashMap::new());
+    }
+
+    let keys: Vec<String> = wanted.iter().map(|(label, chain_id)| labeled_key(solana_pubkey, label, chain_id)).collect();
+    let mut labeled: HashMap<ChainId, BTreeMap<String, EvmAddress>> = HashMap::new();
+    for ((label, chain_id), record) in wanted.into_iter().zip(kv::get_mappings(kv, &keys)?) {
+        if let Some(record) = record {
+            labeled.entry(chain_id).or_default().insert(label, record.address);
+        }
+    }
+    Ok(labeled)
+}
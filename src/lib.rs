@@ -1,52 +1,629 @@
 //! Wallet Provisioning Types
 //!
 //! This library exports types used for Solana→EVM wallet provisioning.
-//! The actual WASM policy that runs on CubeSigner is in `policy/src/main.rs`.
+//! The WASM policy that runs on CubeSigner (`policy/src/main.rs`) depends on
+//! it and runs the same flows (`mapping`) over its KV bucket.
 //! ## Flow
 //!
 //! ### Provision (batch creation):
-//! - Input: solana_address + chain_ids (e.g., [1, 137, 42161])
+//! - Input: solana_address + chain_ids (CAIP-2, e.g., ["eip155:1", "eip155:137"])
+//!   - every chain must be known and enabled in the chain registry (see `chains`)
+//!   + ed25519 signature by solana_address over `message` (ownership proof)
 //! - Backend creates ONE EVM wallet via `cs key create`
 //! - Policy stores mapping for ALL chains: solA -> { 1: 0xevmA, 137: 0xevmA, 42161: 0xevmA }
 //!
 //! ### Update (admin only, per-chain):
+//! - Actor must be in the admin allowlist (when configured, see `admin`)
 //! - Input: solana_address + single chain_id + new_evm_address
 //! - Backend creates NEW EVM wallet via `cs key create`
 //! - Policy updates ONLY that chain's mapping, others unchanged
+//! - With an admin allowlist, updates are two-phase (see `approval`): one
+//!   admin proposes, a different admin approves before the key is rotated
+//!
+//! ### Self-service update (per-chain):
+//! - Input: solana_address + chain_id + nonce + expiry, signed by solana_address
+//! - Signature, expiry and single-use nonce are checked before a new key is created
+//! - Same effect as an admin update; the history records the user as actor
+//!
+//! ### EVM → Solana provision:
+//! - Input: evm_address + EIP-191 signature by evm_address over `message`
+//! - Backend creates ONE Solana wallet (Ed25519 key) via CubeSigner
+//! - Stored in the `evm_to_solana` bucket (see `evm_to_solana`)
+//!
+//! ### Batch provision:
+//! - Input: list of provision requests (one per Solana address)
+//! - Each entry is provisioned independently; failures are reported per entry
+//!
+//! ## Modules
+//! - `kv`: `KvStore` trait over the C2F bucket, key format and KV helpers
+//! - `keys`: `KeyCreator`/`SolanaKeyCreator`/`KeyLister` traits over CubeSigner keys
+//! - `cubesigner_client`: CubeSigner management API client (implements `KeyCreator`)
+//! - `error`: `ProvisionError` with stable machine-readable codes
+//! - `address`: Solana/EVM address validation, EIP-55 checksums
+//! - `chain_id`: CAIP-2 chain ids (`eip155:137`), accepting legacy numeric ids
+//! - `chains`: registry of supported chains, enabled/disabled by admins
+//! - `wildcard`: the chain id `*`, every EVM chain using the default address
+//! - `config`: `config` bucket of admin-tunable parameters (default chains, rate limit, …)
+//! - `freeze`: admin freeze flags on EVM addresses suspected of compromise
+//! - `blocklist`: `blocklist` bucket of sanctioned addresses, screened on store/update
+//! - `admin`: admin allowlist (`admins` bucket) managed by org owners
+//! - `authz`: which requester roles may invoke which policy action
+//! - `policy_api`: `PolicyRequest`, the policy's actions and their JSON bodies
+//! - `approval`: pending-update queue for two-admin updates
+//! - `auth`: ownership proofs (ed25519 for Solana, EIP-191 for EVM addresses)
+//! - `attestation`: EIP-712 signed mapping statements partners can verify offline
+//! - `certificates`: short-lived JWTs asserting a mapping, and `verify_mapping_jwt`
+//! - `response_signing`: ed25519 signatures over policy replies, and `verify_response`
+//! - `request_auth`: HMAC of request bodies under a shared secret from `config`
+//! - `evm_to_solana`: KV helpers for the `evm_to_solana` bucket
+//! - `idempotency`: `idempotency` bucket replaying responses of retried requests
+//! - `rate_limit`: per-Solana-address sliding-window limit on stores and updates
+//! - `metrics`: `metrics` bucket counting provisions, updates and errors by code
+//! - `health`: KV round-trip (`ping`) and build version for operations checks
+//! - `audit`: hash-chained audit log of every mutating operation
+//! - `events`: ordered feed of mapping changes for downstream consumers
+//! - `merkle`: Merkle root over all mappings and per-mapping inclusion proofs
+//! - `migrate`: batched, resumable rewrite of old mapping records
+//! - `dry_run`: runs store/update flows with writes kept in memory, for previews
+//! - `export`: paged dump of the mappings bucket for backups
+//! - `expiry`: temporary mappings (`ttl_secs`) and the sweep that removes them
+//! - `anonymize`: right-to-erasure, replacing a user's records with a salted pseudonym
+//! - `privacy`: `HashedKeys`, storing Solana pubkeys in keys as peppered HMACs
+//! - `import`: writes exported entries back, resolving conflicts by strategy
+//! - `reconcile`: finds (and repairs) CubeSigner keys and mappings that lost each other
+//! - `mapping`: store/get/update flows shared by `Provisioner` and the policy
+//! - `jobs`: provisioning split into a submitted job and the steps a worker runs
+//! - `txn`: write journal that completes half-written multi-key stores
+//! - `inflight`: keys created but not yet mapped, reused by the next provision
+//! - `signing_gate`: allow signing only with keys mapped to the requesting user
+//! - `spend_limits`: per-user transaction value limits the signing gate enforces
+//! - `destinations`: per-user allowlists of addresses the signing gate lets transactions go to
+//! - `tenant`: per-tenant key namespaces (`Namespaced`) over shared buckets
+//! - `network`: mainnet/testnet mapping namespaces (`Networked`) and chain checks
+//! - `environment`: `prod:`/`staging:` key prefixes keeping deployments apart
+//! - `shadow`: shadow mode, a build running every action with its writes kept in memory
+//! - `read_cache`: per-invocation cache over KV reads, dropping keys as they are written
+//! - `memory_kv` (`mock-kv` feature): in-memory `KvStore` for local runs
+//! - `async_api` (`async` feature): `AsyncProvisioner`, the handlers as futures for async backends
+//! - `server` (`server` feature): REST routes for provision/update/get/attest/certificate over `std::net`
+//! - `cli` (`cli` feature): subcommands of the `provisioner-cli` binary, over a server or a local KV file
+//! - `curl` (`server`/`cli` features): `HttpTransport` running `curl`, for the binaries
+//! - `dev_keys` (`dev-keys` feature): `DevKeys`, EVM keys derived from the Solana address instead of created in CubeSigner
+//! - `grpc` (`grpc` feature): tonic service/client generated from `proto/provisioner.proto`
+//! - `openapi` (`openapi` feature): OpenAPI document derived from the request/response types
+//! - `onchain` (`onchain` feature): sync of mappings into the `SolanaToEvmRegistry` contract
+//! - `solana_sync` (`solana-sync` feature): mirror of mappings into PDAs of the Solana mapping program
+//! - `encryption` (`encryption` feature): `Encrypted`, values sealed with the org data key (wrapped under a secret key-encryption key)
+//! - `testing` (`testing` feature): the mock KV store, key creator and `TestContext` harness of the crate's tests
+//! - `Provisioner`: the provision/update flows on top of both traits
 
 use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+
+pub mod address;
+pub mod address_sanity;
+pub mod admin;
+pub mod anonymize;
+pub mod approval;
+pub mod attestation;
+#[cfg(feature = "async")]
+pub mod async_api;
+pub mod audit;
+pub mod auth;
+pub mod authz;
+pub mod blocklist;
+pub mod certificates;
+pub mod chain_id;
+pub mod chains;
+#[cfg(feature = "cli")]
+pub mod cli;
+pub mod config;
+pub mod cubesigner_client;
+#[cfg(feature = "dev-keys")]
+pub mod dev_keys;
+#[cfg(any(feature = "server", feature = "cli"))]
+pub mod curl;
+pub mod destinations;
+pub mod dry_run;
+#[cfg(feature = "encryption")]
+pub mod encryption;
+pub mod environment;
+pub mod error;
+pub mod events;
+pub mod expiry;
+pub mod evm_to_solana;
+pub mod export;
+pub mod freeze;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+pub mod health;
+pub mod idempotency;
+pub mod import;
+pub mod inflight;
+pub mod jobs;
+pub mod keys;
+pub mod kv;
+pub mod labels;
+pub mod logging;
+pub mod mapping;
+pub mod merkle;
+#[cfg(feature = "mock-kv")]
+pub mod memory_kv;
+pub mod metrics;
+pub mod migrate;
+pub mod network;
+pub mod privacy;
+#[cfg(feature = "onchain")]
+pub mod onchain;
+#[cfg(feature = "openapi")]
+pub mod openapi;
+pub mod policy_api;
+pub mod quota;
+pub mod rate_limit;
+pub mod read_cache;
+pub mod reconcile;
+pub mod repair;
+pub mod request_auth;
+pub mod response_signing;
+mod provisioner;
+pub mod retirement;
+#[cfg(feature = "server")]
+pub mod server;
+pub mod shadow;
+pub mod signing_gate;
+#[cfg(feature = "solana-sync")]
+pub mod solana_sync;
+pub mod spend_limits;
+pub mod tenant;
+#[cfg(feature = "testing")]
+pub mod testing;
+pub mod txn;
+pub mod usage;
+pub mod verify;
+pub mod wildcard;
+
+pub use address::{EvmAddress, SolanaPubkey};
+pub use chain_id::ChainId;
+pub use error::ProvisionError;
+pub use keys::{CreatedKey, KeyClass, KeyCreator, KeyLister, KeyType, ListedKey, SolanaKeyCreator};
+pub use provisioner::Clock;
+pub use kv::{KvStore, MappingRecord};
+pub use provisioner::Provisioner;
 
 /// Request to provision EVM wallets for a Solana address across multiple chains
-#[derive(Deserialize, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
 pub struct ProvisionRequest {
-    pub solana_pubkey: String,
-    /// List of chain IDs to provision (e.g., [1, 137, 42161])
-    pub chain_ids: Vec<u64>,
+    pub solana_pubkey: SolanaPubkey,
+    /// List of chain IDs to provision (e.g., ["eip155:1", "eip155:137"]; bare
+    /// numbers are read as `eip155` chain ids), `"*"` for every EVM chain (see
+    /// `wildcard`). Empty or absent: the default chain set (see `config`)
+    #[serde(default, deserialize_with = "wildcard::chain_ids")]
+    pub chain_ids: Vec<ChainId>,
+    /// The exact message signed by the Solana wallet: the store message of
+    /// this request (`auth::store_message`), with a single-use nonce
+    pub message: String,
+    /// Base64-encoded ed25519 signature of `message` by `solana_pubkey`
+    pub signature: String,
+    /// Store an additional address under this label instead of the primary
+    /// one (see `labels`); `None` or `"primary"` for the primary address
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    /// CubeSigner type of the key created for a new user (`SecpEthAddr` if
+    /// absent); ignored once the user has a key. Primary address only.
+    #[serde(default, skip_serializing_if = "KeyType::is_default")]
+    pub key_type: KeyType,
+    /// Create the new user's key as an MPC key (`standard` if absent); like
+    /// `key_type`, ignored once the user has a key. Primary address only.
+    #[serde(default, skip_serializing_if = "KeyClass::is_default")]
+    pub key_class: KeyClass,
+    /// Make the mappings this store writes temporary: they expire this many
+    /// seconds from now (see `expiry`). Primary address only.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ttl_secs: Option<u64>,
+    /// Retries with the same key return the first response (see `idempotency`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub idempotency_key: Option<String>,
+    /// Correlation id carried into log events (see `logging`)
+    #[serde(default, skip_serializing)]
+    pub request_id: Option<String>,
 }
 
-/// Request to update the EVM address for a specific chain (admin only)
+/// Maximum number of entries accepted in a single batch request
+pub const MAX_BATCH_SIZE: usize = 100;
+
+/// Request to provision many Solana addresses in one call
+#[derive(Deserialize, Clone)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct ProvisionBatchRequest {
+    pub requests: Vec<ProvisionRequest>,
+    /// Correlation id carried into log events (see `logging`)
+    #[serde(default)]
+    pub request_id: Option<String>,
+}
+
+/// Request to update many chain mappings in one call (admin only)
 #[derive(Deserialize, Clone)]
+pub struct UpdateBatchRequest {
+    pub updates: Vec<UpdateMappingRequest>,
+    /// Write nothing unless every update would succeed
+    #[serde(default)]
+    pub all_or_nothing: bool,
+    /// Correlation id carried into log events (see `logging`)
+    #[serde(default)]
+    pub request_id: Option<String>,
+}
+
+/// Request to update the EVM address for a specific chain (admin only)
+#[derive(Serialize, Deserialize, Clone)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
 pub struct UpdateMappingRequest {
-    pub solana_pubkey: String,
+    pub solana_pubkey: SolanaPubkey,
     /// The specific chain to update
-    pub chain_id: u64,
+    pub chain_id: ChainId,
+    /// Only update if the chain mapping is still at this revision
+    /// (`GetMappingsResponse::chain_versions`); fails with `VersionConflict` otherwise
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expected_version: Option<u64>,
+    /// Rotate the address stored under this label instead of the primary one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    /// Retries with the same key return the first response instead of
+    /// rotating the key again (see `idempotency`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub idempotency_key: Option<String>,
+    /// Correlation id carried into log events (see `logging`)
+    #[serde(default, skip_serializing)]
+    pub request_id: Option<String>,
 }
 
-/// Response containing the provisioned EVM address and all chain mappings
+/// Request to rotate a chain's key (admin operation): an update that records
+/// why the old address was retired
+#[derive(Serialize, Deserialize, Clone)]
+pub struct RotateRequest {
+    pub solana_pubkey: SolanaPubkey,
+    pub chain_id: ChainId,
+    /// Why the key is rotated (e.g. "suspected compromise"), kept in the
+    /// retirement record of the old address
+    pub reason: String,
+    /// Only rotate if the chain mapping is still at this revision
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expected_version: Option<u64>,
+    /// Retries with the same key return the first response instead of
+    /// rotating the key again (see `idempotency`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub idempotency_key: Option<String>,
+    /// Correlation id carried into log events (see `logging`)
+    #[serde(default, skip_serializing)]
+    pub request_id: Option<String>,
+}
+
+/// Request to enable or disable a chain in the registry (admin only)
+#[derive(Deserialize, Clone)]
+pub struct SetChainRequest {
+    pub chain_id: ChainId,
+    pub enabled: bool,
+    /// Required when registering a chain that is not built in
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub testnet: Option<bool>,
+    /// Correlation id carried into log events (see `logging`)
+    #[serde(default)]
+    pub request_id: Option<String>,
+}
+
+/// Request to freeze or unfreeze an EVM address (admin only)
+#[derive(Deserialize, Clone)]
+pub struct FreezeRequest {
+    pub evm_address: EvmAddress,
+    /// Why the address is frozen (ignored when unfreezing)
+    #[serde(default)]
+    pub reason: Option<String>,
+    /// Correlation id carried into log events (see `logging`)
+    #[serde(default)]
+    pub request_id: Option<String>,
+}
+
+/// Request to set or clear a spending limit (admin only, see `spend_limits`)
+#[derive(Deserialize, Clone)]
+pub struct SetSpendLimitRequest {
+    pub solana_pubkey: SolanaPubkey,
+    /// Limit the user's own mapping on this chain instead of the default
+    #[serde(default)]
+    pub chain_id: Option<ChainId>,
+    /// `None` clears the limit
+    #[serde(default)]
+    pub spend_limit: Option<spend_limits::SpendLimit>,
+    /// Correlation id carried into log events (see `logging`)
+    #[serde(default)]
+    pub request_id: Option<String>,
+}
+
+/// Request to add or remove an allowed destination (admin only, see `destinations`)
+#[derive(Deserialize, Clone)]
+pub struct AllowedDestinationRequest {
+    pub solana_pubkey: SolanaPubkey,
+    pub chain_id: ChainId,
+    /// Contract or EOA the user's wallet may send transactions to
+    pub destination: EvmAddress,
+    /// Correlation id carried into log events (see `logging`)
+    #[serde(default)]
+    pub request_id: Option<String>,
+}
+
+/// Request to block or unblock an address (admin only): `{"solana_pubkey": …}`
+/// or `{"evm_address": …}`
+#[derive(Deserialize, Clone)]
+pub struct BlockRequest {
+    #[serde(flatten)]
+    pub target: blocklist::BlockTarget,
+    /// Why the address is blocked (ignored when unblocking)
+    #[serde(default)]
+    pub reason: Option<String>,
+    /// Correlation id carried into log events (see `logging`)
+    #[serde(default)]
+    pub request_id: Option<String>,
+}
+
+/// Proposal to rotate one chain's EVM key, pending a second admin's approval
+#[derive(Deserialize, Clone)]
+pub struct ProposeUpdateRequest {
+    pub solana_pubkey: SolanaPubkey,
+    pub chain_id: ChainId,
+    /// Correlation id carried into log events (see `logging`)
+    #[serde(default)]
+    pub request_id: Option<String>,
+}
+
+/// Approval or rejection of a pending update
+#[derive(Deserialize, Clone)]
+pub struct ResolveUpdateRequest {
+    pub solana_pubkey: SolanaPubkey,
+    pub chain_id: ChainId,
+    /// `id` of the pending update being resolved
+    pub proposal_id: u64,
+    /// Correlation id carried into log events (see `logging`)
+    #[serde(default)]
+    pub request_id: Option<String>,
+}
+
+/// Request by the owner of a Solana address to rotate one chain's EVM key.
+///
+/// `signature` is the base64 ed25519 signature by `solana_pubkey` over
+/// `auth::update_self_message(...)` built from the other fields.
+#[derive(Deserialize, Clone)]
+pub struct UpdateSelfRequest {
+    pub solana_pubkey: SolanaPubkey,
+    pub chain_id: ChainId,
+    /// Decimal integer above the last nonce the user signed (see `auth::parse_nonce`)
+    pub nonce: String,
+    /// Unix timestamp (seconds) after which the signature is no longer accepted
+    pub expires_at: u64,
+    pub signature: String,
+    /// Correlation id carried into log events (see `logging`)
+    #[serde(default)]
+    pub request_id: Option<String>,
+}
+
+/// Request to link an EVM address the user already controls (e.g. MetaMask)
+/// as the mapping of some chains, instead of a CubeSigner key
+#[derive(Deserialize, Clone)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct LinkExternalRequest {
+    pub solana_pubkey: SolanaPubkey,
+    pub evm_address: EvmAddress,
+    pub chain_ids: Vec<ChainId>,
+    /// Decimal integer above the last nonce the user signed (see `auth::parse_nonce`)
+    pub nonce: String,
+    /// Unix timestamp (seconds) after which the signatures are no longer accepted
+    pub expires_at: u64,
+    /// Base64-encoded ed25519 signature of `auth::link_external_message` by `solana_pubkey`
+    pub signature: String,
+    /// `0x`-prefixed EIP-191 (`personal_sign`) signature of the same message by `evm_address`
+    pub evm_signature: String,
+    /// Make the links temporary: they expire this many seconds from now and
+    /// the chains fall back to the default address (see `expiry`)
+    #[serde(default)]
+    pub ttl_secs: Option<u64>,
+    /// Correlation id carried into log events (see `logging`)
+    #[serde(default)]
+    pub request_id: Option<String>,
+}
+
+/// Response for linking an external EVM address
 #[derive(Serialize, Debug)]
+pub struct LinkExternalResponse {
+    pub success: bool,
+    pub evm_address: EvmAddress,
+    /// Map of chain_id -> mapping revision after linking
+    pub chain_versions: HashMap<ChainId, u64>,
+}
+
+/// Request to provision a Solana wallet for an EVM address
+#[derive(Deserialize, Clone)]
+pub struct EvmToSolanaProvisionRequest {
+    pub evm_address: EvmAddress,
+    /// The exact message signed by the EVM wallet: `auth::evm_to_solana_message`
+    /// of `evm_address`, naming a nonce and an expiry
+    pub message: String,
+    /// `0x`-prefixed EIP-191 (`personal_sign`) signature of `message` by `evm_address`
+    pub signature: String,
+    /// Correlation id carried into log events (see `logging`)
+    #[serde(default)]
+    pub request_id: Option<String>,
+}
+
+/// Response containing the Solana wallet provisioned for an EVM address
+#[derive(Serialize, Debug)]
+pub struct EvmToSolanaProvisionResponse {
+    pub evm_address: EvmAddress,
+    pub solana_pubkey: SolanaPubkey,
+    /// CubeSigner key id of `solana_pubkey`
+    pub key_id: String,
+}
+
+/// Response containing the provisioned EVM address and all chain mappings
+#[derive(Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
 pub struct ProvisionResponse {
     /// The EVM address created (same for all chains)
-    pub evm_address: String,
+    pub evm_address: EvmAddress,
+    /// CubeSigner key id of `evm_address` (`None` for mappings stored before key ids were tracked)
+    pub key_id: Option<String>,
     /// Map of chain_id -> evm_address for all provisioned chains
-    pub chain_mappings: std::collections::HashMap<u64, String>,
+    pub chain_mappings: HashMap<ChainId, EvmAddress>,
+    /// Label the addresses were stored under; `None` for the primary address
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    /// Map of chain_id -> registry name of the chain (`Polygon`)
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub chain_names: HashMap<ChainId, String>,
+    /// Whether the request held the wildcard `*` (see `wildcard`): every EVM
+    /// chain without a mapping of its own uses `evm_address`
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub wildcard: bool,
 }
 
-/// Response for update mapping (admin operation)
+fn is_false(wildcard: &bool) -> bool {
+    !wildcard
+}
+
+/// Default mapping of a Solana address and its mappings on the requested chains
+#[derive(Serialize, Debug)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct GetMappingsResponse {
+    pub default_address: Option<EvmAddress>,
+    pub default_key_id: Option<String>,
+    /// Map of chain_id -> evm_address for the requested chains that have a mapping
+    pub chain_mappings: HashMap<ChainId, EvmAddress>,
+    /// Map of chain_id -> key id, for chains whose mapping has a known key id
+    pub chain_key_ids: HashMap<ChainId, String>,
+    /// Map of chain_id -> mapping revision, the `expected_version` for the next update
+    pub chain_versions: HashMap<ChainId, u64>,
+    /// Map of chain_id -> whether the chain has no mapping of its own and
+    /// inherits the default address
+    pub chain_inherited: HashMap<ChainId, bool>,
+    /// Map of chain_id -> label -> evm_address for the requested chains'
+    /// labeled addresses (see `labels`); `chain_mappings` is the primary one
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    pub labeled_mappings: HashMap<ChainId, BTreeMap<String, EvmAddress>>,
+    /// Returned addresses an admin froze; clients must not send deposits to them
+    pub frozen_addresses: Vec<EvmAddress>,
+    /// Returned addresses the user linked from their own wallet; CubeSigner
+    /// cannot sign for them
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub external_addresses: Vec<EvmAddress>,
+    /// Map of chain_id -> registry name (`Polygon`) of each requested chain
+    /// in the registry
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    pub chain_names: HashMap<ChainId, String>,
+    /// Whether the user has the wildcard (see `wildcard`): every requested
+    /// EVM chain without a mapping of its own inherits the default address
+    #[serde(skip_serializing_if = "is_false")]
+    pub wildcard: bool,
+}
+
+/// Every chain mapping recorded for a Solana address
+#[derive(Serialize, Debug)]
+pub struct ListMappingsResponse {
+    pub solana_pubkey: SolanaPubkey,
+    pub default_address: Option<EvmAddress>,
+    /// Map of chain_id -> evm_address for every chain in the user's chain index
+    pub chain_mappings: HashMap<ChainId, EvmAddress>,
+}
+
+/// Past value of a chain mapping, recorded when an update replaced it
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct MappingHistoryEntry {
+    /// The EVM address that was live before the update
+    pub address: EvmAddress,
+    pub key_id: Option<String>,
+    /// Unix timestamp (seconds) at which it was replaced
+    pub replaced_at: u64,
+    /// Who replaced it
+    pub replaced_by: String,
+}
+
+/// History of a chain mapping, oldest entry first
 #[derive(Serialize, Debug)]
+pub struct MappingHistoryResponse {
+    pub solana_pubkey: SolanaPubkey,
+    pub chain_id: ChainId,
+    /// The address currently live on this chain
+    pub current_address: Option<EvmAddress>,
+    pub entries: Vec<MappingHistoryEntry>,
+}
+
+/// Response for update mapping (admin operation)
+#[derive(Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
 pub struct UpdateMappingResponse {
     pub success: bool,
     /// The NEW EVM address created for this chain
-    pub new_evm_address: String,
+    pub new_evm_address: EvmAddress,
+    /// CubeSigner key id of `new_evm_address`
+    pub new_key_id: String,
     /// The chain that was updated
-    pub chain_id: u64,
+    pub chain_id: ChainId,
+    /// Revision of the chain mapping after the update
+    pub version: u64,
+}
+
+/// Response for a key rotation
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RotateResponse {
+    #[serde(flatten)]
+    pub update: UpdateMappingResponse,
+    /// Record linking the old address to `new_evm_address`; `None` if the
+    /// chain had no mapping of its own before
+    pub retired: Option<retirement::RetirementRecord>,
+}
+
+/// Outcome of one entry of a batch provision
+#[derive(Serialize, Debug)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct ProvisionBatchItem {
+    pub solana_pubkey: SolanaPubkey,
+    pub success: bool,
+    /// Set when the entry was provisioned
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result: Option<ProvisionResponse>,
+    /// Set when the entry failed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<ProvisionError>,
+}
+
+/// Response for batch provision, one item per request entry (same order)
+#[derive(Serialize, Debug)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct ProvisionBatchResponse {
+    pub succeeded: usize,
+    pub failed: usize,
+    pub results: Vec<ProvisionBatchItem>,
+}
+
+/// Outcome of one entry of a batch update
+#[derive(Serialize, Debug)]
+pub struct UpdateBatchItem<T> {
+    pub solana_pubkey: SolanaPubkey,
+    pub chain_id: ChainId,
+    pub success: bool,
+    /// Set when the entry was (or, in a batch not applied, would have been) updated
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result: Option<T>,
+    /// Set when the entry failed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<ProvisionError>,
+}
+
+/// Response for batch update, one item per entry (same order)
+#[derive(Serialize, Debug)]
+pub struct UpdateBatchResponse<T> {
+    /// `false` when an all-or-nothing batch had a failing entry: nothing was
+    /// written, and `results` are those of the dry run
+    pub applied: bool,
+    pub succeeded: usize,
+    pub failed: usize,
+    pub results: Vec<UpdateBatchItem<T>>,
 }
@@ -0,0 +1,91 @@
+//! Request Logging
+//!
+//! Every request may carry a `request_id` chosen by the caller (the backend's
+//! own correlation id). Handlers emit one `LogEvent` per request through a
+//! `Logger`, so a provisioning issue can be followed from the backend's logs
+//! into the policy's by that id instead of by guessing timestamps.
+//!
+//! Events name the Solana address only by `pubkey_hash`, so logs can be
+//! shipped to third-party tooling without listing user addresses. The hash is
+//! a pseudonym, not a secret: whoever has an address can compute its hash and
+//! find its events. The policy logs with `StderrLogger`; library users plug
+//! in their own (`Provisioner::with_logger`).
+
+use crate::error::Result;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::time::Instant;
+
+/// Longest `request_id` kept; longer ids are cut
+pub const MAX_REQUEST_ID_LEN: usize = 128;
+
+/// Hex digits of the SHA-256 kept in `pubkey_hash`
+const PUBKEY_HASH_LEN: usize = 16;
+
+/// One handled request
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct LogEvent {
+    /// The caller's correlation id, if it sent one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+    /// The action name, as in the audit log
+    pub action: String,
+    /// `pubkey_hash` of the Solana address the request is about, if any
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pubkey_hash: Option<String>,
+    pub duration_ms: u64,
+    /// `"ok"`, or the error's `code`
+    pub outcome: String,
+    /// Keys a build in shadow mode would have written (see `shadow`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub shadow_writes: Option<Vec<String>>,
+}
+
+/// Sink for log events
+pub trait Logger {
+    fn log(&self, event: &LogEvent);
+}
+
+impl<F: Fn(&LogEvent)> Logger for F {
+    fn log(&self, event: &LogEvent) {
+        self(event)
+    }
+}
+
+/// Writes each event as one JSON line to stderr, which the policy runtime collects
+pub struct StderrLogger;
+
+impl Logger for StderrLogger {
+    fn log(&self, event: &LogEvent) {
+        eprintln!("{}", serde_json::to_string(event).expect("log event serialization cannot fail"));
+    }
+}
+
+/// First 16 hex digits of the SHA-256 of a Solana address: enough to follow
+/// one user through the logs without printing the address
+pub fn pubkey_hash(solana_pubkey: &str) -> String {
+    let digest = Sha256::digest(solana_pubkey.as_bytes());
+    let hex: String = digest.iter().map(|b| format!("{:02x}", b)).collect();
+    hex[..PUBKEY_HASH_LEN].to_string()
+}
+
+/// Event for a request that started at `started` and ended with `result`
+pub fn event<T>(
+    request_id: Option<&str>,
+    action: &str,
+    solana_pubkey: Option<&str>,
+    started: Instant,
+    result: &Result<T>,
+) -> LogEvent {
+    LogEvent {
+        request_id: request_id.map(|id| id.chars().take(MAX_REQUEST_ID_LEN).collect()),
+        action: action.to_string(),
+        pubkey_hash: solana_pubkey.map(pubkey_hash),
+        duration_ms: u64::try_from(started.elapsed().as_millis()).unwrap_or(u64::MAX),
+        outcome: match result {
+            Ok(_) => "ok".to_string(),
+            Err(e) => e.code().to_string(),
+        },
+        shadow_writes: None,
+    }
+}
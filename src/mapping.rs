@@ -0,0 +1,949 @@
+//! Mapping Flows
+//!
+//! The store, get and update flows over a `KvStore`, shared by `Provisioner`
+//! and the WASM policy (`policy/src/main.rs`). The two only differ in where a
+//! new key comes from: `Provisioner` creates it through a `KeyCreator`, the
+//! policy is handed the address of a key the backend already created. Callers
+//! pass that step in as a closure; validation, key format, first-writer-wins
+//! writes, indexes and history live here once.
+//!
+//! Multi-key stores are journaled (`txn`); reads and updates first complete a
+//! store that an earlier call left half-written.
+
+use crate::address::{EvmAddress, SolanaPubkey};
+use crate::auth;
+use crate::chain_id::ChainId;
+use crate::chains::{self, ChainInfo};
+use crate::error::{ProvisionError, Result};
+use crate::events::{self, EventKind, MappingChange};
+use crate::expiry;
+use crate::evm_to_solana::{self, SolanaMappingValue};
+use crate::freeze;
+use crate::kv::{self, KvStore, MappingRecord};
+use crate::labels;
+use crate::retirement::{self, RetirementRecord};
+use crate::txn::{self, TxnWrite};
+use crate::wildcard::{self, WildcardRecord};
+use crate::{
+    EvmToSolanaProvisionRequest, EvmToSolanaProvisionResponse, GetMappingsResponse, LinkExternalRequest, LinkExternalResponse,
+    ListMappingsResponse, MappingHistoryEntry,
+    MappingHistoryResponse, ProvisionBatchItem, ProvisionBatchResponse, ProvisionRequest, ProvisionResponse, UpdateBatchItem,
+    UpdateBatchResponse, MAX_BATCH_SIZE,
+};
+use std::collections::HashMap;
+
+// =============================================================================
+// STORE
+// =============================================================================
+
+/// Provision flow: check the chains, then store the default mapping and one
+/// mapping per chain (all first-writer-wins). `new_default` is only called if
+/// the Solana address has no default yet. Callers check the ownership proof
+/// first, on the request as sent (`authorize_store`).
+///
+/// The default mapping is a single atomic write; everything after it goes
+/// through the write journal (`txn`), so a failure part-way is completed by
+/// the next call for the same Solana address. That includes the `provisioned`
+/// event (`events`), journaled only if the call maps new chains.
+///
+/// With a `label`, stores a labeled address instead (`store_labeled`), and
+/// `new_default` creates the label's key. With `ttl_secs`, the mappings it
+/// writes are temporary (see `expiry`); an expired default, and the expired
+/// chain mappings the request names, are replaced as if they were absent.
+/// With the chain id `*`, it also records the wildcard (see `wildcard`).
+pub fn store(
+    kv: &impl KvStore,
+    req: &ProvisionRequest,
+    now: u64,
+    new_default: impl FnOnce() -> Result<MappingRecord>,
+) -> Result<ProvisionResponse> {
+    if req.chain_ids.is_empty() {
+        return Err(ProvisionError::InvalidRequest("chain_ids cannot be empty".to_string()));
+    }
+    let label = labels::parse_label(req.label.as_deref())?;
+    if !req.key_type.has_evm_address() {
+        return Err(ProvisionError::InvalidRequest(format!("{} keys have no EVM address to map", req.key_type.as_str())));
+    }
+    req.key_class.validate()?;
+    if label.is_some() && !(req.key_type.is_default() && req.key_class.is_default()) {
+        return Err(ProvisionError::InvalidRequest("key_type and key_class apply to the primary address only".to_string()));
+    }
+    if label.is_some() && req.ttl_secs.is_some() {
+        return Err(ProvisionError::InvalidRequest("ttl_secs applies to the primary address only".to_string()));
+    }
+    let (wildcard, chain_ids) = wildcard::split(&req.chain_ids);
+    if wildcard && label.is_some() {
+        return Err(ProvisionError::InvalidRequest("the wildcard \"*\" applies to the primary address only".to_string()));
+    }
+    if wildcard && req.ttl_secs.is_some() {
+        return Err(ProvisionError::InvalidRequest("ttl_secs does not apply to the wildcard \"*\"".to_string()));
+    }
+    let expires_at = expiry::expires_at(req.ttl_secs, now)?;
+    let chain_names = chain_names(chains::require_enabled(kv, &chain_ids)?);
+
+    if let Some(label) = label {
+        return store_labeled(kv, req, label, now, new_default, chain_names);
+    }
+
+    let default = match kv::get_default_mapping(kv, &req.solana_pubkey)? {
+        Some(existing) if !existing.is_expired(now) => existing,
+        existing => {
+            let record = MappingRecord { key_type: req.key_type, key_class: req.key_class, expires_at, ..new_default()? };
+            claim_address(kv, &record.address, &req.solana_pubkey)?;
+            match existing {
+                None => kv::store_default_mapping(kv, &req.solana_pubkey, &record)?,
+                Some(expired) => kv::renew_default_mapping(kv, &req.solana_pubkey, &expired, &record)?,
+            }
+        }
+    };
+
+    // Reverse index for EVM → Solana lookups
+    let mut writes = vec![TxnWrite::Insert {
+        key: kv::reverse_key(&default.address),
+        value: req.solana_pubkey.to_string(),
+    }];
+    if wildcard {
+        writes.push(TxnWrite::Insert {
+            key: wildcard::wildcard_key(&req.solana_pubkey),
+            value: WildcardRecord { created_at: now }.encode(),
+        });
+    }
+    let record = MappingRecord {
+        key_type: default.key_type,
+        key_class: default.key_class,
+        policies: default.policies.clone(),
+        expires_at: expiry::earliest(expires_at, default.expires_at),
+        ..MappingRecord::new(&default.address, default.key_id.as_deref(), req.solana_pubkey.as_str(), now)
+    };
+    let mut inserted = Vec::new();
+    let mut expired = Vec::new();
+    for chain_id in &chain_ids {
+        match kv::get_chain_mapping(kv, &req.solana_pubkey, chain_id)? {
+            None => {
+                writes.push(TxnWrite::Insert {
+                    key: kv::chain_key(&req.solana_pubkey, chain_id),
+                    value: record.encode(),
+                });
+                inserted.push(chain_id.clone());
+            }
+            Some(existing) if existing.is_expired(now) => expired.push((chain_id, existing.revision)),
+            Some(_) => {}
+        }
+    }
+    writes.push(TxnWrite::AddToChainIndex {
+        chain_ids: chain_ids.clone(),
+    });
+    writes.extend(provisioned_event(&req.solana_pubkey, None, &default.address, inserted));
+    txn::run(kv, &req.solana_pubkey, writes, now)?;
+
+    // Expired chain mappings are replaced like an update from the revision
+    // read, so they keep their history; a concurrent store replacing the
+    // same one first wins (`VersionConflict`) and the read back shows it
+    for (chain_id, revision) in expired {
+        let actor = req.solana_pubkey.as_str();
+        match apply_update(kv, &req.solana_pubkey, chain_id, &record, Some(revision), Some(EXPIRED_REASON), actor, now) {
+            Ok(_) | Err(ProvisionError::VersionConflict { .. }) => {}
+            Err(e) => return Err(e),
+        }
+    }
+
+    // Read back: a concurrent store may have won some of the chain mappings
+    let mut chain_mappings = HashMap::new();
+    for chain_id in &chain_ids {
+        let key = kv::chain_key(&req.solana_pubkey, chain_id);
+        let value = kv::get_chain_mapping(kv, &req.solana_pubkey, chain_id)?
+            .ok_or_else(|| ProvisionError::KvConflict(format!("Key {} reported as existing but could not be read", key)))?;
+        if value.is_expired(now) {
+            return Err(ProvisionError::KvConflict(format!("Key {} is being replaced by a concurrent store", key)));
+        }
+        chain_mappings.insert(chain_id.clone(), value.address);
+    }
+
+    let response = ProvisionResponse {
+        evm_address: default.address,
+        key_id: default.key_id,
+        chain_mappings,
+        label: None,
+        chain_names,
+        wildcard,
+    };
+    require_not_frozen(kv, &response)?;
+    Ok(response)
+}
+
+/// Store flow for a labeled address: map each requested chain to the label's
+/// key (created by `new_key` on the label's first store), first writer wins.
+/// Only provisioned users get labeled addresses.
+fn store_labeled(
+    kv: &impl KvStore,
+    req: &ProvisionRequest,
+    label: &str,
+    now: u64,
+    new_key: impl FnOnce() -> Result<MappingRecord>,
+    chain_names: HashMap<ChainId, String>,
+) -> Result<ProvisionResponse> {
+    require_provisioned(kv, &req.solana_pubkey)?;
+    let key = match labels::get_label_mapping(kv, &req.solana_pubkey, label)? {
+        Some(existing) => existing,
+        None => {
+            let record = new_key()?;
+            claim_address(kv, &record.address, &req.solana_pubkey)?;
+            labels::store_label_mapping(kv, &req.solana_pubkey, label, &record)?
+        }
+    };
+
+    let mut writes = vec![TxnWrite::Insert {
+        key: kv::reverse_key(&key.address),
+        value: req.solana_pubkey.to_string(),
+    }];
+    let mut inserted = Vec::new();
+    for chain_id in &req.chain_ids {
+        if labels::get_labeled_mapping(kv, &req.solana_pubkey, label, chain_id)?.is_none() {
+            let record = MappingRecord {
+                policies: key.policies.clone(),
+                ..MappingRecord::new(&key.address, key.key_id.as_deref(), req.solana_pubkey.as_str(), now)
+            };
+            writes.push(TxnWrite::Insert {
+                key: labels::labeled_key(&req.solana_pubkey, label, chain_id),
+                value: record.encode(),
+            });
+            inserted.push(chain_id.clone());
+        }
+    }
+    writes.push(TxnWrite::AddToLabelIndex {
+        label: label.to_string(),
+        chain_ids: req.chain_ids.clone(),
+    });
+    writes.extend(provisioned_event(&req.solana_pubkey, Some(label), &key.address, inserted));
+    txn::run(kv, &req.solana_pubkey, writes, now)?;
+
+    // Read back: a concurrent store may have won some of the chain mappings
+    let mut chain_mappings = HashMap::new();
+    for chain_id in &req.chain_ids {
+        let key = labels::labeled_key(&req.solana_pubkey, label, chain_id);
+        let value = labels::get_labeled_mapping(kv, &req.solana_pubkey, label, chain_id)?
+            .ok_or_else(|| ProvisionError::KvConflict(format!("Key {} reported as existing but could not be read", key)))?;
+        chain_mappings.insert(chain_id.clone(), value.address);
+    }
+
+    let response = ProvisionResponse {
+        evm_address: key.address,
+        key_id: key.key_id,
+        chain_mappings,
+        label: Some(label.to_string()),
+        chain_names,
+        wildcard: false,
+    };
+    require_not_frozen(kv, &response)?;
+    Ok(response)
+}
+
+/// Registry name of each chain, for responses
+fn chain_names(chains: impl IntoIterator<Item = ChainInfo>) -> HashMap<ChainId, String> {
+    chains.into_iter().map(|chain| (chain.chain_id, chain.name)).collect()
+}
+
+/// Claim `evm_address` for `solana_pubkey` in the reverse index, or fail with
+/// `AddressOwned` if another user holds it: an address is mapped to one user
+/// only.
+///
+/// The reverse entry is the uniqueness constraint: it is written with
+/// `set_if_absent` before the mapping using the address, so of two users
+/// claiming one new address at the same time exactly one wins. A claim whose
+/// mapping then loses to a concurrent store of the same user is left pointing
+/// at that user, who holds no other claim on it.
+pub fn claim_address(kv: &impl KvStore, evm_address: &EvmAddress, solana_pubkey: &SolanaPubkey) -> Result<()> {
+    match kv::store_reverse_mapping(kv, evm_address, solana_pubkey) {
+        Ok(owner) if owner != *solana_pubkey => Err(ProvisionError::AddressOwned {
+            evm_address: evm_address.to_string(),
+            owner: owner.to_string(),
+        }),
+        // Held by an erased user, and so by no one else ever again
+        Err(ProvisionError::Anonymized { pseudonym }) => {
+            Err(ProvisionError::AddressOwned { evm_address: evm_address.to_string(), owner: pseudonym })
+        }
+        result => result.map(drop),
+    }
+}
+
+/// Fail with `AddressOwned` if the reverse index gives `evm_address` to a user
+/// other than `solana_pubkey`, writing nothing. Only an early refusal, for
+/// proposals that map nothing yet: what holds two users off one address is
+/// the `claim_address` of the write that maps it.
+pub fn require_address_free(kv: &impl KvStore, evm_address: &EvmAddress, solana_pubkey: &SolanaPubkey) -> Result<()> {
+    match kv::get_reverse_mapping(kv, evm_address) {
+        Ok(Some(owner)) if owner != *solana_pubkey => Err(ProvisionError::AddressOwned {
+            evm_address: evm_address.to_string(),
+            owner: owner.to_string(),
+        }),
+        Err(ProvisionError::Anonymized { pseudonym }) => {
+            Err(ProvisionError::AddressOwned { evm_address: evm_address.to_string(), owner: pseudonym })
+        }
+        result => result.map(drop),
+    }
+}
+
+/// Fail with `AddressFrozen` if `response` would hand out a frozen address
+pub fn require_not_frozen(kv: &impl KvStore, response: &ProvisionResponse) -> Result<()> {
+    let addresses: Vec<&EvmAddress> = std::iter::once(&response.evm_address).chain(response.chain_mappings.values()).collect();
+    freeze::require_not_frozen(kv, &addresses)
+}
+
+/// Journal write appending the `provisioned` event of a store that maps
+/// `inserted` chains to `address`; none if it maps nothing new
+fn provisioned_event(solana_pubkey: &SolanaPubkey, label: Option<&str>, address: &EvmAddress, inserted: Vec<ChainId>) -> Option<TxnWrite> {
+    if inserted.is_empty() {
+        return None;
+    }
+    let change = MappingChange {
+        kind: EventKind::Provisioned,
+        solana_pubkey: solana_pubkey.clone(),
+        label: label.map(str::to_string),
+        chain_mappings: inserted.into_iter().map(|chain_id| (chain_id, address.clone())).collect(),
+        version: None,
+    };
+    Some(TxnWrite::AppendEvent { change })
+}
+
+fn updated_event(solana_pubkey: &SolanaPubkey, label: Option<&str>, chain_id: &ChainId, record: &MappingRecord) -> MappingChange {
+    MappingChange {
+        kind: EventKind::Updated,
+        solana_pubkey: solana_pubkey.clone(),
+        label: label.map(str::to_string),
+        chain_mappings: [(chain_id.clone(), record.address.clone())].into(),
+        version: Some(record.revision),
+    }
+}
+
+/// Run `provision` on every entry; a failing entry does not abort the rest
+pub fn batch<E>(
+    entries: Vec<E>,
+    solana_pubkey: impl Fn(&E) -> SolanaPubkey,
+    mut provision: impl FnMut(E) -> Result<ProvisionResponse>,
+) -> Result<ProvisionBatchResponse> {
+    check_batch_size("requests", entries.len())?;
+
+    let mut results = Vec::with_capacity(entries.len());
+
+    for entry in entries {
+        let solana_pubkey = solana_pubkey(&entry);
+        let item = match provision(entry) {
+            Ok(result) => ProvisionBatchItem {
+                solana_pubkey,
+                success: true,
+                result: Some(result),
+                error: None,
+            },
+            Err(e) => ProvisionBatchItem {
+                solana_pubkey,
+                success: false,
+                result: None,
+                error: Some(e),
+            },
+        };
+        results.push(item);
+    }
+
+    let succeeded = results.iter().filter(|r| r.success).count();
+
+    Ok(ProvisionBatchResponse {
+        succeeded,
+        failed: results.len() - succeeded,
+        results,
+    })
+}
+
+/// Run `update` on every entry, each naming a chain mapping (`target`); a
+/// failing entry does not abort the rest
+pub fn update_batch<E, T>(
+    entries: Vec<E>,
+    target: impl Fn(&E) -> (SolanaPubkey, ChainId),
+    mut update: impl FnMut(E) -> Result<T>,
+) -> Result<UpdateBatchResponse<T>> {
+    check_batch_size("updates", entries.len())?;
+
+    let mut results = Vec::with_capacity(entries.len());
+    for entry in entries {
+        let (solana_pubkey, chain_id) = target(&entry);
+        let (result, error) = match update(entry) {
+            Ok(result) => (Some(result), None),
+            Err(e) => (None, Some(e)),
+        };
+        results.push(UpdateBatchItem { solana_pubkey, chain_id, success: error.is_none(), result, error });
+    }
+
+    let succeeded = results.iter().filter(|r| r.success).count();
+    Ok(UpdateBatchResponse {
+        applied: true,
+        succeeded,
+        failed: results.len() - succeeded,
+        results,
+    })
+}
+
+fn check_batch_size(field: &str, size: usize) -> Result<()> {
+    if size == 0 {
+        return Err(ProvisionError::InvalidRequest(format!("{} cannot be empty", field)));
+    }
+    if size > MAX_BATCH_SIZE {
+        return Err(ProvisionError::BatchTooLarge { size, max: MAX_BATCH_SIZE });
+    }
+    Ok(())
+}
+
+/// EVM → Solana provision flow: check the EIP-191 ownership proof and burn
+/// its nonce (`authorize_evm_to_solana`), then store the Solana wallet
+/// (first-writer-wins). `new_value` is only called if the EVM address has no
+/// Solana wallet yet.
+pub fn store_evm_to_solana(
+    kv: &impl KvStore,
+    req: &EvmToSolanaProvisionRequest,
+    now: u64,
+    new_value: impl FnOnce() -> Result<SolanaMappingValue>,
+) -> Result<EvmToSolanaProvisionResponse> {
+    authorize_evm_to_solana(kv, req, now)?;
+
+    let value = match evm_to_solana::get_mapping(kv, &req.evm_address)? {
+        Some(existing) => existing,
+        None => evm_to_solana::store_mapping_once(kv, &req.evm_address, &new_value()?)?,
+    };
+
+    evm_to_solana::store_reverse_mapping(kv, &value.address, &req.evm_address)?;
+
+    Ok(EvmToSolanaProvisionResponse {
+        evm_address: req.evm_address.clone(),
+        solana_pubkey: value.address,
+        key_id: value.key_id,
+    })
+}
+
+// =============================================================================
+// GET
+// =============================================================================
+
+/// Default mapping and the mappings of `chain_ids` (every chain the user has
+/// when empty), read in one `get_many` round-trip.
+///
+/// Only chains in the user's chain index are read. Users stored before the
+/// index existed have none until `migrate` backfills it, so for them the
+/// requested chains are probed instead.
+///
+/// A requested chain without its own mapping inherits the default address
+/// (`chain_inherited`) if the user has the wildcard and it is an EVM chain
+/// (see `wildcard`), or if the chain is enabled, since that is the mapping
+/// `store` would write for it. The registry entries of the requested chains
+/// take one more `get_many`, and so do the freeze flags of the returned
+/// addresses (`frozen_addresses`). Mappings expired by `now` are treated as
+/// absent (see `expiry`).
+pub fn get(kv: &impl KvStore, solana_pubkey: &SolanaPubkey, chain_ids: &[ChainId], now: u64) -> Result<GetMappingsResponse> {
+    txn::recover(kv, solana_pubkey)?;
+    let index = kv::get_chain_index(kv, solana_pubkey)?;
+    let requested = if chain_ids.is_empty() { index.clone() } else { chain_ids.to_vec() };
+    let stored: Vec<ChainId> = if index.is_empty() {
+        requested.clone()
+    } else {
+        requested.iter().filter(|chain_id| index.contains(chain_id)).cloned().collect()
+    };
+
+    // The wildcard is read with the mappings
+    let keys: Vec<String> = [kv::default_key(solana_pubkey), wildcard::wildcard_key(solana_pubkey)]
+        .into_iter()
+        .chain(stored.iter().map(|chain_id| kv::chain_key(solana_pubkey, chain_id)))
+        .collect();
+    let live = |raw: Option<String>| -> Result<Option<MappingRecord>> {
+        Ok(raw.map(|raw| MappingRecord::decode(&raw)).transpose()?.filter(|record| !record.is_expired(now)))
+    };
+    let mut values = kv.get_many(&keys)?.into_iter();
+    let default = live(values.next().flatten())?;
+    let wildcard = values.next().flatten().is_some();
+    let records = values.map(live).collect::<Result<Vec<_>>>()?;
+
+    let mut response = GetMappingsResponse {
+        default_address: default.as_ref().map(|value| value.address.clone()),
+        default_key_id: default.as_ref().and_then(|value| value.key_id.clone()),
+        chain_mappings: HashMap::new(),
+        chain_key_ids: HashMap::new(),
+        chain_versions: HashMap::new(),
+        chain_inherited: HashMap::new(),
+        labeled_mappings: labels::get_labeled(kv, solana_pubkey, chain_ids)?,
+        frozen_addresses: Vec::new(),
+        external_addresses: Vec::new(),
+        chain_names: HashMap::new(),
+        wildcard,
+    };
+    for (chain_id, record) in stored.iter().zip(records) {
+        if let Some(value) = record {
+            insert_chain(&mut response, chain_id, value, false);
+        }
+    }
+
+    // Registry entries of every requested chain: their names, and whether the
+    // ones without a mapping inherit the default. A chain's own mapping wins
+    // over the wildcard, which wins over the registry.
+    let registry: Vec<ChainInfo> = chains::get_chains(kv, &requested)?.into_iter().flatten().collect();
+    if let Some(default) = default {
+        for chain_id in &requested {
+            let enabled = registry.iter().any(|chain| chain.chain_id == *chain_id && chain.enabled);
+            let inherits = (wildcard && wildcard::covers(chain_id)) || enabled;
+            if inherits && !response.chain_mappings.contains_key(chain_id) {
+                insert_chain(&mut response, chain_id, default.clone(), true);
+            }
+        }
+    }
+    response.chain_names = chain_names(registry);
+
+    let addresses: Vec<&EvmAddress> = response
+        .default_address
+        .iter()
+        .chain(response.chain_mappings.values())
+        .chain(response.labeled_mappings.values().flat_map(|labeled| labeled.values()))
+        .collect();
+    response.frozen_addresses = freeze::frozen_among(kv, &addresses)?;
+    Ok(response)
+}
+
+/// `get`, then give every inherited chain its own mapping (as `store` would
+/// have written it) so later updates and lists see it. Only the first read of
+/// an inherited chain writes. Chains the user's wildcard covers are left to it.
+pub fn get_materialized(
+    kv: &impl KvStore,
+    solana_pubkey: &SolanaPubkey,
+    chain_ids: &[ChainId],
+    now: u64,
+) -> Result<GetMappingsResponse> {
+    let response = get(kv, solana_pubkey, chain_ids, now)?;
+    let inherited: Vec<ChainId> = response
+        .chain_inherited
+        .iter()
+        .filter(|(chain_id, &inherited)| inherited && !(response.wildcard && wildcard::covers(chain_id)))
+        .map(|(chain_id, _)| chain_id.clone())
+        .collect();
+    if inherited.is_empty() {
+        return Ok(response);
+    }
+    let Some(default_address) = &response.default_address else {
+        return Ok(response);
+    };
+
+    let (key_type, key_class, policies, expires_at) = kv::get_default_mapping(kv, solana_pubkey)?
+        .map(|default| (default.key_type, default.key_class, default.policies, default.expires_at))
+        .unwrap_or_default();
+    let record = MappingRecord {
+        key_type,
+        key_class,
+        policies,
+        expires_at,
+        ..MappingRecord::new(default_address, response.default_key_id.as_deref(), solana_pubkey.as_str(), now)
+    };
+    let mut writes: Vec<TxnWrite> = inherited
+        .iter()
+        .map(|chain_id| TxnWrite::Insert {
+            key: kv::chain_key(solana_pubkey, chain_id),
+            value: record.encode(),
+        })
+        .collect();
+    writes.push(TxnWrite::AddToChainIndex { chain_ids: inherited });
+    txn::run(kv, solana_pubkey, writes, now)?;
+
+    // Read back: a concurrent update may have written some of the chains first
+    get(kv, solana_pubkey, chain_ids, now)
+}
+
+fn insert_chain(response: &mut GetMappingsResponse, chain_id: &ChainId, value: MappingRecord, inherited: bool) {
+    if value.external && !response.external_addresses.contains(&value.address) {
+        response.external_addresses.push(value.address.clone());
+    }
+    if let Some(key_id) = value.key_id {
+        response.chain_key_ids.insert(chain_id.clone(), key_id);
+    }
+    response.chain_versions.insert(chain_id.clone(), if inherited { 0 } else { value.revision });
+    response.chain_inherited.insert(chain_id.clone(), inherited);
+    response.chain_mappings.insert(chain_id.clone(), value.address);
+}
+
+/// Every chain mapping of a Solana address, using its chain index
+pub fn list(kv: &impl KvStore, solana_pubkey: &SolanaPubkey) -> Result<ListMappingsResponse> {
+    txn::recover(kv, solana_pubkey)?;
+    let default_address = kv::get_default_evm_address(kv, solana_pubkey)?;
+
+    let chain_ids = kv::get_chain_index(kv, solana_pubkey)?;
+    let keys: Vec<String> = chain_ids.iter().map(|chain_id| kv::chain_key(solana_pubkey, chain_id)).collect();
+    let mut chain_mappings = HashMap::new();
+    for (chain_id, record) in chain_ids.into_iter().zip(kv::get_mappings(kv, &keys)?) {
+        if let Some(record) = record {
+            chain_mappings.insert(chain_id, record.address);
+        }
+    }
+
+    Ok(ListMappingsResponse {
+        solana_pubkey: solana_pubkey.clone(),
+        default_address,
+        chain_mappings,
+    })
+}
+
+/// Every address a chain mapping held before the current one
+pub fn history(kv: &impl KvStore, solana_pubkey: &SolanaPubkey, chain_id: &ChainId) -> Result<MappingHistoryResponse> {
+    Ok(MappingHistoryResponse {
+        solana_pubkey: solana_pubkey.clone(),
+        chain_id: chain_id.clone(),
+        current_address: kv::get_existing_mapping(kv, solana_pubkey, chain_id)?,
+        entries: kv::get_history(kv, solana_pubkey, chain_id)?,
+    })
+}
+
+/// `history` of the labeled address of `label` on one chain
+pub fn labeled_history(kv: &impl KvStore, solana_pubkey: &SolanaPubkey, label: &str, chain_id: &ChainId) -> Result<MappingHistoryResponse> {
+    Ok(MappingHistoryResponse {
+        solana_pubkey: solana_pubkey.clone(),
+        chain_id: chain_id.clone(),
+        current_address: labels::get_labeled_mapping(kv, solana_pubkey, label, chain_id)?.map(|record| record.address),
+        entries: kv::get_history_at(kv, &labels::labeled_history_key(solana_pubkey, label, chain_id))?,
+    })
+}
+
+// =============================================================================
+// UPDATE
+// =============================================================================
+
+/// Default mapping of a Solana address; updates require one
+pub fn require_provisioned(kv: &impl KvStore, solana_pubkey: &SolanaPubkey) -> Result<MappingRecord> {
+    txn::recover(kv, solana_pubkey)?;
+    kv::get_default_mapping(kv, solana_pubkey)?
+        .ok_or_else(|| ProvisionError::NotProvisioned(solana_pubkey.to_string()))
+}
+
+/// Check a store request's ownership proof and burn its nonce: `message` must
+/// be the `auth::store_message` of the request, signed
+/// by the address, unexpired and expiring within
+/// `auth::MAX_AUTHORIZATION_TTL_SECS`, with a nonce not used by an earlier
+/// store. Run it on the request as sent, before the default chains fill in an
+/// empty `chain_ids`, and before anything is created or written.
+pub fn authorize_store(kv: &impl KvStore, req: &ProvisionRequest, now: u64) -> Result<()> {
+    let nonce = verify_store(req, now)?;
+    // Only a correctly signed request can burn a nonce
+    if !kv::consume_store_nonce(kv, &req.solana_pubkey, nonce, now)? {
+        return Err(ProvisionError::NonceUsed(nonce.to_string()));
+    }
+    Ok(())
+}
+
+/// `authorize_store` without burning the nonce, which it returns. Writes
+/// nothing, so it can gate what must only count signed requests (rate limits).
+pub fn verify_store(req: &ProvisionRequest, now: u64) -> Result<u64> {
+    let (nonce, expires_at) = auth::store_message_terms(&req.message)
+        .filter(|&(nonce, expires_at)| req.message == auth::store_message(req, nonce, expires_at))
+        .ok_or_else(|| ProvisionError::InvalidRequest("message is not the store message of this request (see auth::store_message)".to_string()))?;
+    let nonce = auth::parse_nonce(nonce)?;
+    check_expiry(expires_at, now)?;
+
+    auth::verify_solana_signature(&req.solana_pubkey, &req.message, &req.signature)?;
+    Ok(nonce)
+}
+
+/// `authorize_store` for EVM → Solana provisioning: `message` must be the
+/// `auth::evm_to_solana_message` of the EVM address, signed by it (EIP-191),
+/// unexpired and expiring within `auth::MAX_AUTHORIZATION_TTL_SECS`, with a
+/// nonce the address has not used before. Nonces are burnt in `kv`, the
+/// `evm_to_solana` bucket.
+pub fn authorize_evm_to_solana(kv: &impl KvStore, req: &EvmToSolanaProvisionRequest, now: u64) -> Result<()> {
+    let (nonce, expires_at) = auth::store_message_terms(&req.message)
+        .filter(|&(nonce, expires_at)| req.message == auth::evm_to_solana_message(&req.evm_address, nonce, expires_at))
+        .ok_or_else(|| {
+            ProvisionError::InvalidRequest("message is not the provision message of this address (see auth::evm_to_solana_message)".to_string())
+        })?;
+    let nonce = auth::parse_nonce(nonce)?;
+    check_expiry(expires_at, now)?;
+
+    auth::verify_evm_signature(&req.evm_address, &req.message, &req.signature)?;
+
+    // Only a correctly signed request can burn a nonce
+    if !evm_to_solana::consume_store_nonce(kv, &req.evm_address, nonce, now)? {
+        return Err(ProvisionError::NonceUsed(nonce.to_string()));
+    }
+    Ok(())
+}
+
+/// Fail unless a signed authorization expiring at `expires_at` is still
+/// valid and expires within `auth::MAX_AUTHORIZATION_TTL_SECS`
+fn check_expiry(expires_at: u64, now: u64) -> Result<()> {
+    if now > expires_at {
+        return Err(ProvisionError::AuthorizationExpired { expires_at });
+    }
+    if expires_at > now.saturating_add(auth::MAX_AUTHORIZATION_TTL_SECS) {
+        return Err(ProvisionError::InvalidRequest(format!(
+            "expires_at must be at most {}s in the future",
+            auth::MAX_AUTHORIZATION_TTL_SECS
+        )));
+    }
+    Ok(())
+}
+
+/// Check a self-service update authorization (nonce format, expiry within
+/// `auth::MAX_AUTHORIZATION_TTL_SECS`, signature over `message`, nonce above
+/// the last one used) and burn its nonce
+pub fn authorize_update_self(
+    kv: &impl KvStore,
+    solana_pubkey: &SolanaPubkey,
+    message: &str,
+    nonce: &str,
+    expires_at: u64,
+    signature: &str,
+    now: u64,
+) -> Result<()> {
+    let nonce = verify_update_self(solana_pubkey, message, nonce, expires_at, signature, now)?;
+    if let Some(last) = kv::get_nonce_head(kv, solana_pubkey)?.filter(|last| nonce <= *last) {
+        return Err(ProvisionError::NonceTooLow { nonce, last });
+    }
+    // Only a correctly signed request can burn a nonce
+    if !kv::consume_nonce(kv, solana_pubkey, nonce, now)? {
+        return Err(ProvisionError::NonceUsed(nonce.to_string()));
+    }
+    Ok(())
+}
+
+/// `authorize_update_self` without the nonce checks or burning the nonce,
+/// which it returns. Writes nothing (see `verify_store`).
+pub fn verify_update_self(
+    solana_pubkey: &SolanaPubkey,
+    message: &str,
+    nonce: &str,
+    expires_at: u64,
+    signature: &str,
+    now: u64,
+) -> Result<u64> {
+    let nonce = auth::parse_nonce(nonce)?;
+    check_expiry(expires_at, now)?;
+
+    auth::verify_solana_signature(solana_pubkey, message, signature)?;
+    Ok(nonce)
+}
+
+/// Fail with `VersionConflict` unless the chain mapping is at `expected_version`
+/// (no check when `None`). Lets callers bail out before creating a key.
+pub fn check_version(
+    kv: &impl KvStore,
+    solana_pubkey: &SolanaPubkey,
+    chain_id: &ChainId,
+    expected_version: Option<u64>,
+) -> Result<()> {
+    let current = kv::get_chain_mapping(kv, solana_pubkey, chain_id)?;
+    ensure_version(solana_pubkey, chain_id, current.as_ref(), expected_version).map(|_| ())
+}
+
+/// Make `record` the chain's mapping (overwrite), keeping the replaced value
+/// in the chain's history and retiring its address with `reason` (see
+/// `retirement`). Returns the stored record.
+///
+/// Each update claims the next `revision` first, so of two updates racing
+/// from the same revision one fails with `VersionConflict` instead of
+/// silently overwriting the other. An update failing after its claim leaves
+/// the claim held until `kv::REVISION_CLAIM_TTL_SECS` pass; then the next
+/// update takes it over. With `expected_version`, the update also fails if
+/// the mapping moved on since the caller read it.
+#[allow(clippy::too_many_arguments)]
+pub fn apply_update(
+    kv: &impl KvStore,
+    solana_pubkey: &SolanaPubkey,
+    chain_id: &ChainId,
+    record: &MappingRecord,
+    expected_version: Option<u64>,
+    reason: Option<&str>,
+    actor: &str,
+    now: u64,
+) -> Result<MappingRecord> {
+    let previous = kv::get_chain_mapping(kv, solana_pubkey, chain_id)?;
+    let revision = ensure_version(solana_pubkey, chain_id, previous.as_ref(), expected_version)?;
+    // A spending limit stays with the chain, not the key
+    let spend_limit = previous.as_ref().and_then(|previous| previous.spend_limit.clone());
+
+    if !kv::claim_revision(kv, solana_pubkey, chain_id, revision + 1, actor, now)? {
+        let current = kv::get_chain_mapping(kv, solana_pubkey, chain_id)?;
+        return Err(version_conflict(solana_pubkey, chain_id, revision, current));
+    }
+
+    if let Some(previous) = previous {
+        if previous.address != record.address {
+            let retired = RetirementRecord {
+                address: previous.address.clone(),
+                key_id: previous.key_id.clone(),
+                solana_pubkey: solana_pubkey.clone(),
+                chain_id: chain_id.clone(),
+                label: None,
+                replaced_by: record.address.clone(),
+                reason: reason.map(str::to_string),
+                retired_by: actor.to_string(),
+                retired_at: now,
+            };
+            retirement::retire(kv, &retired)?;
+        }
+        let entry = MappingHistoryEntry {
+            address: previous.address,
+            key_id: previous.key_id,
+            replaced_at: now,
+            replaced_by: actor.to_string(),
+        };
+        kv::append_history(kv, solana_pubkey, chain_id, entry)?;
+    }
+
+    let record = MappingRecord {
+        revision: revision + 1,
+        spend_limit: record.spend_limit.clone().or(spend_limit),
+        ..record.clone()
+    };
+    kv::update_mapping(kv, solana_pubkey, chain_id, &record)?;
+    kv::store_reverse_mapping(kv, &record.address, solana_pubkey)?;
+    kv::add_to_chain_index(kv, solana_pubkey, std::slice::from_ref(chain_id))?;
+    events::append(kv, updated_event(solana_pubkey, None, chain_id, &record), now)?;
+    Ok(record)
+}
+
+/// `check_version` for a labeled address, which must exist
+pub fn check_labeled_version(
+    kv: &impl KvStore,
+    solana_pubkey: &SolanaPubkey,
+    label: &str,
+    chain_id: &ChainId,
+    expected_version: Option<u64>,
+) -> Result<()> {
+    let current = require_labeled(kv, solana_pubkey, label, chain_id)?;
+    ensure_version(solana_pubkey, chain_id, Some(&current), expected_version).map(|_| ())
+}
+
+fn require_labeled(kv: &impl KvStore, solana_pubkey: &SolanaPubkey, label: &str, chain_id: &ChainId) -> Result<MappingRecord> {
+    labels::get_labeled_mapping(kv, solana_pubkey, label, chain_id)?.ok_or_else(|| {
+        ProvisionError::InvalidRequest(format!("{} has no '{}' address on chain {}", solana_pubkey, label, chain_id))
+    })
+}
+
+/// `apply_update` for the labeled address of `label` on one chain, which
+/// must exist: claims the next revision (`labels::labeled_revision_key`),
+/// keeps the replaced value in the label's history for the chain and retires
+/// the replaced address. Returns the stored record.
+#[allow(clippy::too_many_arguments)]
+pub fn apply_labeled_update(
+    kv: &impl KvStore,
+    solana_pubkey: &SolanaPubkey,
+    label: &str,
+    chain_id: &ChainId,
+    record: &MappingRecord,
+    expected_version: Option<u64>,
+    actor: &str,
+    now: u64,
+) -> Result<MappingRecord> {
+    let previous = require_labeled(kv, solana_pubkey, label, chain_id)?;
+    let revision = ensure_version(solana_pubkey, chain_id, Some(&previous), expected_version)?;
+
+    let claim = labels::labeled_revision_key(solana_pubkey, label, chain_id, revision + 1);
+    if !kv::claim_revision_at(kv, &claim, actor, now)? {
+        let current = labels::get_labeled_mapping(kv, solana_pubkey, label, chain_id)?;
+        return Err(version_conflict(solana_pubkey, chain_id, revision, current));
+    }
+
+    if previous.address != record.address {
+        let retired = RetirementRecord {
+            address: previous.address.clone(),
+            key_id: previous.key_id.clone(),
+            solana_pubkey: solana_pubkey.clone(),
+            chain_id: chain_id.clone(),
+            label: Some(label.to_string()),
+            replaced_by: record.address.clone(),
+            reason: None,
+            retired_by: actor.to_string(),
+            retired_at: now,
+        };
+        retirement::retire(kv, &retired)?;
+    }
+    let entry = MappingHistoryEntry {
+        address: previous.address,
+        key_id: previous.key_id,
+        replaced_at: now,
+        replaced_by: actor.to_string(),
+    };
+    kv::append_history_at(kv, &labels::labeled_history_key(solana_pubkey, label, chain_id), entry)?;
+
+    let record = MappingRecord {
+        revision: revision + 1,
+        ..record.clone()
+    };
+    labels::update_labeled_mapping(kv, solana_pubkey, label, chain_id, &record)?;
+    kv::store_reverse_mapping(kv, &record.address, solana_pubkey)?;
+    events::append(kv, updated_event(solana_pubkey, Some(label), chain_id, &record), now)?;
+    Ok(record)
+}
+
+/// Retirement reason of expired addresses a store replaced (see `expiry`)
+pub const EXPIRED_REASON: &str = "expired";
+
+/// Retirement reason of addresses replaced by a linked external address
+pub const LINK_EXTERNAL_REASON: &str = "linked external address";
+
+/// Link flow: check that the user signed `auth::link_external_message` with
+/// both the Solana wallet and the EVM wallet and burn the nonce, then make the
+/// EVM address the mapping of each requested chain (`apply_update`, so a
+/// replaced address keeps its history and retirement record). Chains already
+/// linked to the address are left alone, so a retry after a partial failure
+/// completes the rest. With `ttl_secs`, the links are temporary (see `expiry`).
+pub fn link_external(kv: &impl KvStore, req: &LinkExternalRequest, now: u64) -> Result<LinkExternalResponse> {
+    if req.chain_ids.is_empty() {
+        return Err(ProvisionError::InvalidRequest("chain_ids cannot be empty".to_string()));
+    }
+    let expires_at = expiry::expires_at(req.ttl_secs, now)?;
+    chains::require_enabled(kv, &req.chain_ids)?;
+
+    let message = verify_link_external(req, now)?;
+    authorize_update_self(kv, &req.solana_pubkey, &message, &req.nonce, req.expires_at, &req.signature, now)?;
+    freeze::require_not_frozen(kv, &[&req.evm_address])?;
+    // Only once both wallets signed, so no one claims an address they do not hold
+    claim_address(kv, &req.evm_address, &req.solana_pubkey)?;
+
+    let actor = req.solana_pubkey.as_str();
+    let record = MappingRecord { expires_at, ..MappingRecord::external(&req.evm_address, actor, now) };
+    let mut chain_versions = HashMap::new();
+    for chain_id in &req.chain_ids {
+        let stored = match kv::get_chain_mapping(kv, &req.solana_pubkey, chain_id)? {
+            Some(current) if current.address == req.evm_address && !current.is_expired(now) => current,
+            _ => apply_update(kv, &req.solana_pubkey, chain_id, &record, None, Some(LINK_EXTERNAL_REASON), actor, now)?,
+        };
+        chain_versions.insert(chain_id.clone(), stored.revision);
+    }
+
+    Ok(LinkExternalResponse {
+        success: true,
+        evm_address: req.evm_address.clone(),
+        chain_versions,
+    })
+}
+
+/// Check both signatures of a link request without burning its nonce
+/// (see `verify_store`); returns the signed `auth::link_external_message`
+pub fn verify_link_external(req: &LinkExternalRequest, now: u64) -> Result<String> {
+    let message = auth::link_external_message(&req.solana_pubkey, &req.evm_address, &req.chain_ids, &req.nonce, req.expires_at);
+    auth::verify_evm_signature(&req.evm_address, &message, &req.evm_signature)?;
+    verify_update_self(&req.solana_pubkey, &message, &req.nonce, req.expires_at, &req.signature, now)?;
+    Ok(message)
+}
+
+/// Current revision of the chain mapping (0 if there is none), if it matches `expected_version`
+fn ensure_version(
+    solana_pubkey: &SolanaPubkey,
+    chain_id: &ChainId,
+    current: Option<&MappingRecord>,
+    expected_version: Option<u64>,
+) -> Result<u64> {
+    let revision = current.map_or(0, |record| record.revision);
+    match expected_version {
+        Some(expected) if expected != revision => Err(version_conflict(solana_pubkey, chain_id, expected, current.cloned())),
+        _ => Ok(revision),
+    }
+}
+
+fn version_conflict(solana_pubkey: &SolanaPubkey, chain_id: &ChainId, expected: u64, current: Option<MappingRecord>) -> ProvisionError {
+    ProvisionError::VersionConflict {
+        solana_pubkey: solana_pubkey.to_string(),
+        chain_id: chain_id.to_string(),
+        expected,
+        current: current.map(Box::new),
+    }
+}
+
@@ -0,0 +1,73 @@
+//! In-Memory KV Store (`mock-kv` feature)
+//!
+//! `KvStore` backed by a `BTreeMap`, for running the provisioning flow
+//! outside the C2F runtime (local integration tests, demos). Clones share the
+//! same data, so a `Provisioner` and the test inspecting it see one bucket.
+
+use crate::kv::KvStore;
+use crate::error::{ProvisionError, Result};
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
+#[derive(Debug, Clone, Default)]
+pub struct MemoryKvStore {
+    data: Arc<Mutex<BTreeMap<String, String>>>,
+}
+
+impl MemoryKvStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Copy of every key/value pair, in key order
+    pub fn snapshot(&self) -> BTreeMap<String, String> {
+        self.data.lock().map(|data| data.clone()).unwrap_or_default()
+    }
+
+    fn lock(&self) -> Result<std::sync::MutexGuard<'_, BTreeMap<String, String>>> {
+        self.data.lock().map_err(|_| ProvisionError::Kv("In-memory KV store lock poisoned".to_string()))
+    }
+}
+
+impl KvStore for MemoryKvStore {
+    fn get(&self, key: &str) -> Result<Option<String>> {
+        Ok(self.lock()?.get(key).cloned())
+    }
+
+    fn set_if_absent(&self, key: &str, value: &str) -> Result<bool> {
+        let mut data = self.lock()?;
+        if data.contains_key(key) {
+            return Ok(false);
+        }
+        data.insert(key.to_string(), value.to_string());
+        Ok(true)
+    }
+
+    fn set(&self, key: &str, value: &str) -> Result<()> {
+        self.lock()?.insert(key.to_string(), value.to_string());
+        Ok(())
+    }
+
+    fn get_many(&self, keys: &[String]) -> Result<Vec<Option<String>>> {
+        let data = self.lock()?;
+        Ok(keys.iter().map(|key| data.get(key).cloned()).collect())
+    }
+
+    fn list_keys(&self, after: Option<&str>, limit: usize) -> Result<Vec<String>> {
+        let data = self.lock()?;
+        let keys = match after {
+            Some(after) => data
+                .range::<str, _>((std::ops::Bound::Excluded(after), std::ops::Bound::Unbounded))
+                .map(|(key, _)| key.clone())
+                .take(limit)
+                .collect(),
+            None => data.keys().take(limit).cloned().collect(),
+        };
+        Ok(keys)
+    }
+
+    fn delete(&self, key: &str) -> Result<()> {
+        self.lock()?.remove(key);
+        Ok(())
+    }
+}
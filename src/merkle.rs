@@ -0,0 +1,229 @@
+//! Merkle Commitments
+//!
+//! A Merkle tree over every stored chain mapping, so the backend can anchor
+//! the mapping set on-chain (publish `root`) and users can check their own
+//! entry against it (`proof`, then `verify`).
+//!
+//! One leaf per chain mapping `{solana_pubkey}:{chain_id}` in the bucket.
+//! Chains a user only inherits from the default address have no leaf until
+//! they are stored; labeled addresses have none.
+//!
+//! Hashing is keccak256, so a Solidity verifier can recompute it:
+//! ```text
+//! leaf = keccak256(0x00 ‖ solana_pubkey (32 bytes) ‖ evm_address (20 bytes) ‖ chain_id (CAIP-2, UTF-8))
+//! node = keccak256(0x01 ‖ min(a, b) ‖ max(a, b))
+//! ```
+//! The prefixes keep a leaf from passing for a node. Pairs are hashed in
+//! sorted order, so a proof is just the sibling hashes from the leaf up.
+//! Leaves are sorted by hash, and a level with an odd count carries its last
+//! hash up unchanged. The root of an empty tree is 32 zero bytes.
+//!
+//! Building the tree reads the whole bucket (`KvStore::list_keys`), and a
+//! root is only comparable with proofs built from the same bucket state.
+
+use crate::address::{EvmAddress, SolanaPubkey};
+use crate::chain_id::ChainId;
+use crate::error::{ProvisionError, Result};
+use crate::kv::{self, KvStore};
+use crate::migrate::{self, MAX_MIGRATION_BATCH};
+use serde::{Deserialize, Serialize};
+use sha3::{Digest, Keccak256};
+
+type Hash = [u8; 32];
+
+const LEAF_PREFIX: u8 = 0x00;
+const NODE_PREFIX: u8 = 0x01;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct MerkleLeaf {
+    pub solana_pubkey: SolanaPubkey,
+    pub chain_id: ChainId,
+    pub evm_address: EvmAddress,
+}
+
+impl MerkleLeaf {
+    pub fn hash(&self) -> Hash {
+        let mut hasher = Keccak256::new();
+        hasher.update([LEAF_PREFIX]);
+        hasher.update(self.solana_pubkey.to_bytes());
+        hasher.update(evm_address_bytes(&self.evm_address));
+        hasher.update(self.chain_id.to_string().as_bytes());
+        hasher.finalize().into()
+    }
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct MerkleRoot {
+    /// 0x-prefixed hex
+    pub root: String,
+    pub leaf_count: usize,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct MerkleProof {
+    pub leaf: MerkleLeaf,
+    /// 0x-prefixed hex
+    pub leaf_hash: String,
+    /// Sibling hashes from the leaf up to the root, 0x-prefixed hex
+    pub proof: Vec<String>,
+    pub root: String,
+    pub leaf_count: usize,
+}
+
+/// Root of the tree over every stored chain mapping
+pub fn root(kv: &impl KvStore) -> Result<MerkleRoot> {
+    let hashes = leaf_hashes(&leaves(kv)?);
+    Ok(MerkleRoot {
+        root: to_hex(&compute_root(&hashes)),
+        leaf_count: hashes.len(),
+    })
+}
+
+/// Inclusion proof of the stored mapping of `solana_pubkey` on `chain_id`
+pub fn proof(kv: &impl KvStore, solana_pubkey: &SolanaPubkey, chain_id: &ChainId) -> Result<MerkleProof> {
+    let record = kv::get_chain_mapping(kv, solana_pubkey, chain_id)?.ok_or_else(|| {
+        ProvisionError::InvalidRequest(format!("{} has no stored mapping on chain {}", solana_pubkey, chain_id))
+    })?;
+    let leaf = MerkleLeaf {
+        solana_pubkey: solana_pubkey.clone(),
+        chain_id: chain_id.clone(),
+        evm_address: record.address,
+    };
+
+    let hashes = leaf_hashes(&leaves(kv)?);
+    let leaf_hash = leaf.hash();
+    let index = hashes
+        .binary_search(&leaf_hash)
+        .map_err(|_| ProvisionError::KvConflict(format!("Mapping of {} on chain {} changed while proving", solana_pubkey, chain_id)))?;
+
+    Ok(MerkleProof {
+        leaf,
+        leaf_hash: to_hex(&leaf_hash),
+        proof: siblings(&hashes, index).iter().map(to_hex).collect(),
+        root: to_hex(&compute_root(&hashes)),
+        leaf_count: hashes.len(),
+    })
+}
+
+/// Whether `proof` leads from its leaf to `root` (0x-prefixed hex)
+pub fn verify(proof: &MerkleProof, root: &str) -> bool {
+    let mut hash = proof.leaf.hash();
+    for sibling in &proof.proof {
+        let Some(sibling) = from_hex(sibling) else {
+            return false;
+        };
+        hash = hash_pair(&hash, &sibling);
+    }
+    from_hex(root) == Some(hash)
+}
+
+// =============================================================================
+// TREE
+// =============================================================================
+
+/// Every stored chain mapping of the bucket
+fn leaves(kv: &impl KvStore) -> Result<Vec<MerkleLeaf>> {
+    let mut leaves = Vec::new();
+    let mut cursor: Option<String> = None;
+    loop {
+        let keys = kv.list_keys(cursor.as_deref(), MAX_MIGRATION_BATCH)?;
+        let chain_keys: Vec<String> = keys
+            .iter()
+            .filter(|key| migrate::is_mapping_key(key) && !key.starts_with("default:"))
+            .cloned()
+            .collect();
+        for (key, record) in chain_keys.iter().zip(kv::get_live_mappings(kv, &chain_keys)?) {
+            let (Some(record), Some((solana_pubkey, chain_id))) = (record, key.split_once(':')) else {
+                continue;
+            };
+            leaves.push(MerkleLeaf {
+                solana_pubkey: SolanaPubkey::parse(solana_pubkey)?,
+                chain_id: ChainId::parse(chain_id)?,
+                evm_address: record.address,
+            });
+        }
+
+        if keys.len() < MAX_MIGRATION_BATCH {
+            return Ok(leaves);
+        }
+        cursor = keys.last().cloned();
+    }
+}
+
+/// Leaf hashes in tree order (sorted)
+fn leaf_hashes(leaves: &[MerkleLeaf]) -> Vec<Hash> {
+    let mut hashes: Vec<Hash> = leaves.iter().map(MerkleLeaf::hash).collect();
+    hashes.sort_unstable();
+    hashes
+}
+
+fn hash_pair(a: &Hash, b: &Hash) -> Hash {
+    let (low, high) = if a <= b { (a, b) } else { (b, a) };
+    let mut hasher = Keccak256::new();
+    hasher.update([NODE_PREFIX]);
+    hasher.update(low);
+    hasher.update(high);
+    hasher.finalize().into()
+}
+
+fn next_level(level: &[Hash]) -> Vec<Hash> {
+    level
+        .chunks(2)
+        .map(|pair| match pair {
+            [a, b] => hash_pair(a, b),
+            [last] => *last,
+            _ => unreachable!("chunks of two"),
+        })
+        .collect()
+}
+
+fn compute_root(hashes: &[Hash]) -> Hash {
+    let mut level = hashes.to_vec();
+    if level.is_empty() {
+        return [0; 32];
+    }
+    while level.len() > 1 {
+        level = next_level(&level);
+    }
+    level[0]
+}
+
+/// Sibling hashes on the path from leaf `index` to the root
+fn siblings(hashes: &[Hash], mut index: usize) -> Vec<Hash> {
+    let mut proof = Vec::new();
+    let mut level = hashes.to_vec();
+    while level.len() > 1 {
+        // The last hash of an odd level has no sibling and moves up as is
+        if let Some(sibling) = level.get(index ^ 1) {
+            proof.push(*sibling);
+        }
+        level = next_level(&level);
+        index /= 2;
+    }
+    proof
+}
+
+// =============================================================================
+// ENCODING
+// =============================================================================
+
+fn evm_address_bytes(evm_address: &EvmAddress) -> [u8; 20] {
+    let hex = &evm_address.as_str()[2..];
+    std::array::from_fn(|i| u8::from_str_radix(&hex[2 * i..2 * i + 2], 16).expect("EvmAddress is validated"))
+}
+
+fn to_hex(hash: &Hash) -> String {
+    format!("0x{}", hash.iter().map(|b| format!("{:02x}", b)).collect::<String>())
+}
+
+fn from_hex(hex: &str) -> Option<Hash> {
+    let hex = hex.strip_prefix("0x")?;
+    if hex.len() != 64 || !hex.is_ascii() {
+        return None;
+    }
+    let mut hash = [0u8; 32];
+    for (i, byte) in hash.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hex[2 * i..2 * i + 2], 16).ok()?;
+    }
+    Some(hash)
+}
@@ -0,0 +1,137 @@
+//! Provisioning Metrics
+//!
+//! Counters for operations: wallets provisioned (in total and per chain),
+//! external addresses linked, chain mapping updates, and failed requests by
+//! error code. They live in one document of their own bucket and are read
+//! back with the `stats` action, so adoption numbers never need an export.
+//!
+//! - `provisions` counts stores that created a user's default key;
+//!   `provisions_by_chain` counts chains mapped for the first time. Labeled
+//!   addresses are not counted
+//! - `external_by_chain` counts chains switched from a custodial key to an
+//!   address the user owns (`link_external`). Moving back to a custodial key
+//!   is not subtracted, so custodial mappings are at least
+//!   `provisions_by_chain - external_by_chain`
+//! - `updates` counts successful chain mapping updates (`UPDATE_ACTIONS`)
+//! - `errors_by_code` counts failed mutating requests, rate limited ones included
+//!
+//! ## Key Schema (`metrics` bucket)
+//! ```text
+//! counters → Stats
+//! ```
+//!
+//! Counters are updated with a plain read and write, like the rate limiter's:
+//! concurrent requests can lose increments, so the numbers are a trend, not a
+//! ledger. Callers ignore failures to count; metrics never fail a request.
+
+use crate::address::SolanaPubkey;
+use crate::chain_id::ChainId;
+use crate::error::{ProvisionError, Result};
+use crate::kv::{self, KvStore};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Bucket holding the counters
+pub const METRICS_BUCKET: &str = "metrics";
+
+/// Key of the counters document
+pub const COUNTERS_KEY: &str = "counters";
+
+/// Audited actions that update a chain mapping when they succeed
+pub const UPDATE_ACTIONS: &[&str] = &["update", "approve_update", "update_self", "rotate", "link_external"];
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Stats {
+    /// Users whose default key was created
+    #[serde(default)]
+    pub provisions: u64,
+    /// Users mapped on each chain
+    #[serde(default)]
+    pub provisions_by_chain: BTreeMap<ChainId, u64>,
+    /// Users who linked an external address on each chain
+    #[serde(default)]
+    pub external_by_chain: BTreeMap<ChainId, u64>,
+    /// Chain mapping updates applied
+    #[serde(default)]
+    pub updates: u64,
+    /// Failed requests by `ProvisionError::code`
+    #[serde(default)]
+    pub errors_by_code: BTreeMap<String, u64>,
+}
+
+/// Current counters (all zero before the first count)
+pub fn get_stats(kv: &impl KvStore) -> Result<Stats> {
+    match kv.get(COUNTERS_KEY)? {
+        Some(raw) => serde_json::from_str(&raw).map_err(|e| ProvisionError::corrupt("metrics counters", e)),
+        None => Ok(Stats::default()),
+    }
+}
+
+/// Chains of `chain_ids` (the wildcard aside) the user has no mapping on yet, going by the chain
+/// index in `mappings`. Read before a store to count what it adds.
+pub fn unmapped_chains(mappings: &impl KvStore, solana_pubkey: &SolanaPubkey, chain_ids: &[ChainId]) -> Result<Vec<ChainId>> {
+    let index = kv::get_chain_index(mappings, solana_pubkey)?;
+    Ok(chain_ids.iter().filter(|chain_id| !chain_id.is_wildcard() && !index.contains(chain_id)).cloned().collect())
+}
+
+/// Chains of `chain_ids` the user's mapping (own or inherited) is not an
+/// external address on. Read before a `link_external` to count what it switches.
+pub fn custodial_chains(mappings: &impl KvStore, solana_pubkey: &SolanaPubkey, chain_ids: &[ChainId]) -> Result<Vec<ChainId>> {
+    let default = kv::get_default_mapping(mappings, solana_pubkey)?;
+    let mut custodial = Vec::new();
+    for chain_id in chain_ids {
+        let current = kv::get_chain_mapping(mappings, solana_pubkey, chain_id)?;
+        if !current.as_ref().or(default.as_ref()).is_some_and(|record| record.external) {
+            custodial.push(chain_id.clone());
+        }
+    }
+    Ok(custodial)
+}
+
+/// Count chains switched to an external address
+pub fn record_external_links(kv: &impl KvStore, chain_ids: &[ChainId]) -> Result<()> {
+    if chain_ids.is_empty() {
+        return Ok(());
+    }
+    update(kv, |stats| {
+        for chain_id in chain_ids {
+            *stats.external_by_chain.entry(chain_id.clone()).or_default() += 1;
+        }
+    })
+}
+
+/// Count a store that created the user's default key (`new_wallet`) and/or
+/// mapped `new_chains` for the first time
+pub fn record_provision(kv: &impl KvStore, new_wallet: bool, new_chains: &[ChainId]) -> Result<()> {
+    if !new_wallet && new_chains.is_empty() {
+        return Ok(());
+    }
+    update(kv, |stats| {
+        stats.provisions += u64::from(new_wallet);
+        for chain_id in new_chains {
+            *stats.provisions_by_chain.entry(chain_id.clone()).or_default() += 1;
+        }
+    })
+}
+
+/// Count the outcome of an audited action: its error code if it failed, an
+/// update if it is one of `UPDATE_ACTIONS`
+pub fn record_outcome(kv: &impl KvStore, action: &str, outcome: std::result::Result<(), &ProvisionError>) -> Result<()> {
+    match outcome {
+        Err(e) => record_error(kv, e),
+        Ok(()) if UPDATE_ACTIONS.contains(&action) => update(kv, |stats| stats.updates += 1),
+        Ok(()) => Ok(()),
+    }
+}
+
+/// Count a failed request
+pub fn record_error(kv: &impl KvStore, error: &ProvisionError) -> Result<()> {
+    update(kv, |stats| *stats.errors_by_code.entry(error.code().to_string()).or_default() += 1)
+}
+
+fn update(kv: &impl KvStore, f: impl FnOnce(&mut Stats)) -> Result<()> {
+    let mut stats = get_stats(kv)?;
+    f(&mut stats);
+    let raw = serde_json::to_string(&stats).expect("metrics serialization cannot fail");
+    kv.set(COUNTERS_KEY, &raw)
+}
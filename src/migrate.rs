@@ -0,0 +1,135 @@
+//! Schema Migration
+//!
+//! Rewrites mapping records (`default:{solana_pubkey}` and
+//! `{solana_pubkey}:{chain_id}`) stored in an older format — plain address
+//! strings (v0) or version-less JSON (v1) — as current `MappingRecord`s.
+//!
+//! Runs in bounded batches over `KvStore::list_keys`: each call scans at most
+//! `limit` keys after `cursor` and returns the cursor to resume from, so a
+//! migration can be spread over many invocations while the service keeps
+//! running. Readers accept every version, so a half-migrated bucket is fine.
+//!
+//! Metadata the old formats did not carry (`created_at`, `created_by`) stays
+//! `None`; migrating does not invent it.
+//!
+//! Chain mappings written before the per-user chain index existed are also
+//! added to it (`chains:{solana_pubkey}`), so lookups that go by the index
+//! find them.
+
+use crate::address::SolanaPubkey;
+use crate::anonymize;
+use crate::chain_id::ChainId;
+use crate::kv::{self, KvStore, MappingRecord, MAPPING_RECORD_VERSION};
+use crate::error::{ProvisionError, Result};
+use serde::{Deserialize, Serialize};
+
+/// Keys scanned per batch when the request does not say
+pub const DEFAULT_MIGRATION_BATCH: usize = 100;
+
+/// Upper bound on keys scanned per batch
+pub const MAX_MIGRATION_BATCH: usize = 500;
+
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct MigrateRequest {
+    /// Resume after this key (`next_cursor` of the previous batch)
+    #[serde(default)]
+    pub cursor: Option<String>,
+    #[serde(default)]
+    pub limit: Option<usize>,
+    /// Correlation id carried into log events (see `logging`)
+    #[serde(default)]
+    pub request_id: Option<String>,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct MigrationFailure {
+    pub key: String,
+    pub error: ProvisionError,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct MigrationReport {
+    /// Keys looked at in this batch
+    pub scanned: usize,
+    /// Mapping records rewritten in the current format
+    pub migrated: usize,
+    /// Chain mappings added to their user's chain index
+    pub indexed: usize,
+    /// Mapping records that could not be decoded (left untouched)
+    pub failed: Vec<MigrationFailure>,
+    /// Pass as `cursor` to continue; null once every key has been scanned
+    pub next_cursor: Option<String>,
+}
+
+/// Whether `key` holds a mapping record
+pub fn is_mapping_key(key: &str) -> bool {
+    if let Some(solana_pubkey) = key.strip_prefix("default:") {
+        return SolanaPubkey::parse(solana_pubkey).is_ok();
+    }
+    // `{solana_pubkey}:{chain_id}`; other key families start with a word prefix
+    // (`reverse:`, `history:`, …) that is not a valid Solana pubkey
+    key.split_once(':')
+        .is_some_and(|(solana_pubkey, _)| SolanaPubkey::parse(solana_pubkey).is_ok())
+}
+
+/// Migrate one batch of keys after `cursor`
+pub fn migrate_batch(kv: &impl KvStore, cursor: Option<&str>, limit: Option<usize>) -> Result<MigrationReport> {
+    let limit = limit.unwrap_or(DEFAULT_MIGRATION_BATCH).clamp(1, MAX_MIGRATION_BATCH);
+    let keys = kv.list_keys(cursor, limit)?;
+
+    let mut report = MigrationReport {
+        scanned: keys.len(),
+        migrated: 0,
+        indexed: 0,
+        failed: Vec::new(),
+        next_cursor: if keys.len() < limit { None } else { keys.last().cloned() },
+    };
+
+    for key in keys.iter().filter(|key| is_mapping_key(key)) {
+        match migrate_key(kv, key).and_then(|migrated| Ok((migrated, index_key(kv, key)?))) {
+            Ok((migrated, indexed)) => {
+                report.migrated += migrated as usize;
+                report.indexed += indexed as usize;
+            }
+            Err(e) => report.failed.push(MigrationFailure {
+                key: key.clone(),
+                error: e,
+            }),
+        }
+    }
+
+    Ok(report)
+}
+
+/// Rewrite one record if it is outdated. Returns whether it was rewritten.
+fn migrate_key(kv: &impl KvStore, key: &str) -> Result<bool> {
+    let Some(raw) = kv.get(key)?.filter(|raw| !anonymize::is_tombstone(raw)) else {
+        return Ok(false);
+    };
+    let mut record = MappingRecord::decode(&raw)?;
+    if record.version >= MAPPING_RECORD_VERSION {
+        return Ok(false);
+    }
+    record.version = MAPPING_RECORD_VERSION;
+
+    // Narrow the window for clobbering a concurrent update: only write if the
+    // value is still the one we decoded
+    if kv.get(key)?.as_deref() != Some(raw.as_str()) {
+        return Ok(false);
+    }
+    kv.set(key, &record.encode())?;
+    Ok(true)
+}
+
+/// Add a chain mapping key to its user's chain index. Returns whether it was missing.
+fn index_key(kv: &impl KvStore, key: &str) -> Result<bool> {
+    if key.starts_with("default:") {
+        return Ok(false);
+    }
+    let Some((solana_pubkey, chain_id)) = key.split_once(':') else {
+        return Ok(false);
+    };
+    let solana_pubkey = SolanaPubkey::parse(solana_pubkey)?;
+    let chain_id = ChainId::parse(chain_id)?;
+    kv::add_to_chain_index(kv, &solana_pubkey, &[chain_id])
+}
@@ -0,0 +1,144 @@
+//! Mainnet / Testnet Segregation
+//!
+//! QA provisions the same Solana addresses as production, and must not get
+//! (or overwrite) their production wallets. Each request runs on a network:
+//! mainnet (the default) or testnet. A user has independent mappings (and
+//! so independent EVM wallets) on each, and a network only maps its own
+//! chains: a chain the registry marks `testnet` is refused on mainnet, and
+//! any other chain on testnet, with `WrongNetwork`.
+//!
+//! Like tenants (see `tenant`), `Networked` maps every key into the network's
+//! namespace, so the flows stay network-agnostic. Mainnet uses the unprefixed
+//! keys written before networks existed; the `testnet:` prefix is reserved
+//! there.
+//!
+//! ## Key Schema
+//! ```text
+//! testnet:{key}   # `key` as on mainnet
+//! ```
+
+use crate::chain_id::ChainId;
+use crate::chains;
+use crate::error::{ProvisionError, Result};
+use crate::kv::KvStore;
+use serde::{Deserialize, Serialize};
+
+/// Prefix reserved for testnet keys
+pub const TESTNET_PREFIX: &str = "testnet:";
+
+/// Sorts after every testnet key, so listing on mainnet skips them all in one step
+const TESTNET_KEYS_END: &str = "testnet:\u{7f}";
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum Network {
+    #[default]
+    Mainnet,
+    Testnet,
+}
+
+impl Network {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Mainnet => "mainnet",
+            Self::Testnet => "testnet",
+        }
+    }
+
+    pub fn is_default(&self) -> bool {
+        *self == Self::Mainnet
+    }
+}
+
+/// Fail with `WrongNetwork` if one of `chain_ids` is a known chain of the
+/// other network. Unknown chains are left to `chains::require_enabled`.
+pub fn require_chains(kv: &impl KvStore, network: Network, chain_ids: &[ChainId]) -> Result<()> {
+    for (chain_id, chain) in chain_ids.iter().zip(chains::get_chains(kv, chain_ids)?) {
+        if chain.is_some_and(|chain| chain.testnet != (network == Network::Testnet)) {
+            return Err(ProvisionError::WrongNetwork { chain_id: chain_id.to_string(), network: network.as_str() });
+        }
+    }
+    Ok(())
+}
+
+/// Whether `key` belongs to the testnet namespace
+pub fn is_testnet_key(key: &str) -> bool {
+    key.starts_with(TESTNET_PREFIX)
+}
+
+/// A `KvStore` restricted to one network's namespace
+pub struct Networked<S> {
+    inner: S,
+    network: Network,
+}
+
+impl<S: KvStore> Networked<S> {
+    pub fn new(inner: S, network: Network) -> Self {
+        Self { inner, network }
+    }
+
+    pub fn network(&self) -> Network {
+        self.network
+    }
+
+    /// The key `key` is stored under
+    fn key(&self, key: &str) -> Result<String> {
+        match self.network {
+            Network::Testnet => Ok(format!("{}{}", TESTNET_PREFIX, key)),
+            Network::Mainnet if is_testnet_key(key) => {
+                Err(ProvisionError::InvalidRequest(format!("key {} is reserved for the testnet namespace", key)))
+            }
+            Network::Mainnet => Ok(key.to_string()),
+        }
+    }
+}
+
+impl<S: KvStore> KvStore for Networked<S> {
+    fn get(&self, key: &str) -> Result<Option<String>> {
+        self.inner.get(&self.key(key)?)
+    }
+
+    fn set_if_absent(&self, key: &str, value: &str) -> Result<bool> {
+        self.inner.set_if_absent(&self.key(key)?, value)
+    }
+
+    fn set(&self, key: &str, value: &str) -> Result<()> {
+        self.inner.set(&self.key(key)?, value)
+    }
+
+    fn get_many(&self, keys: &[String]) -> Result<Vec<Option<String>>> {
+        let keys = keys.iter().map(|key| self.key(key)).collect::<Result<Vec<_>>>()?;
+        self.inner.get_many(&keys)
+    }
+
+    fn delete(&self, key: &str) -> Result<()> {
+        self.inner.delete(&self.key(key)?)
+    }
+
+    fn list_keys(&self, after: Option<&str>, limit: usize) -> Result<Vec<String>> {
+        match self.network {
+            Network::Testnet => {
+                let start = format!("{}{}", TESTNET_PREFIX, after.unwrap_or_default());
+                let keys = self.inner.list_keys(Some(&start), limit)?;
+                Ok(keys.into_iter().map_while(|key| key.strip_prefix(TESTNET_PREFIX).map(str::to_string)).collect())
+            }
+            Network::Mainnet => {
+                let mut keys = Vec::new();
+                let mut cursor = after.map(str::to_string);
+                loop {
+                    let wanted = limit - keys.len();
+                    let page = self.inner.list_keys(cursor.as_deref(), wanted)?;
+                    let exhausted = page.len() < wanted;
+                    let last = page.last().cloned();
+                    keys.extend(page.into_iter().filter(|key| !is_testnet_key(key)));
+                    if exhausted || keys.len() == limit {
+                        return Ok(keys);
+                    }
+                    // The page ran into the testnet keys: continue after all of them
+                    cursor = last.map(|last| if is_testnet_key(&last) { TESTNET_KEYS_END.to_string() } else { last });
+                }
+            }
+        }
+    }
+}
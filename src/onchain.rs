@@ -0,0 +1,255 @@
+//! On-Chain Registry Sync (`onchain` feature)
+//!
+//! Pushes mappings into a `SolanaToEvmRegistry` contract on each EVM chain, so
+//! contracts there can resolve a Solana identity to its EVM address without
+//! trusting the backend.
+//!
+//! Syncing a chain mapping:
+//! 1. `sync` builds the `setMapping` call for the mapping's current address
+//!    and revision, has the org's operator key sign it (`OperatorSigner`,
+//!    `CubeSignerOperator` for a CubeSigner key) and records the sync as
+//!    `signed`, with the raw transaction.
+//! 2. The caller broadcasts the transaction (`eth_sendRawTransaction`) and
+//!    reports progress with `record_status`: `submitted` with the tx hash,
+//!    then `confirmed` or `failed`.
+//!
+//! `sync` does nothing for a mapping whose current revision was already
+//! signed, unless that sync failed. Two syncs racing for the same revision
+//! both sign it and the later record wins; the contract applies a `version`
+//! only if it is not older than the one it holds, so duplicate or reordered
+//! transactions never roll a mapping back.
+//!
+//! ## Key Schema
+//! ```text
+//! onchain:{solana_pubkey}:{chain_id}  → SyncRecord  # Latest sync of the chain mapping
+//! ```
+
+use crate::address::{EvmAddress, SolanaPubkey};
+use crate::chain_id::ChainId;
+use crate::cubesigner_client::{CubeSignerClient, HttpTransport};
+use crate::error::{ProvisionError, Result};
+use crate::kv::KvStore;
+use crate::mapping;
+use alloy_primitives::{hex, Address};
+use alloy_sol_types::{sol, SolCall};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+sol! {
+    /// Registry of Solana → EVM mappings on one EVM chain
+    interface SolanaToEvmRegistry {
+        /// Map `solanaPubkey` to `evmAddress`; ignored if the registry holds a
+        /// newer `version` for it. Only the operator may call it.
+        function setMapping(bytes32 solanaPubkey, address evmAddress, uint64 version) external;
+    }
+}
+
+/// Key of a chain mapping's sync record: `onchain:{solana_pubkey}:{chain_id}`
+pub fn sync_key(solana_pubkey: &SolanaPubkey, chain_id: &ChainId) -> String {
+    format!("onchain:{}:{}", solana_pubkey.as_str(), chain_id.key_segment())
+}
+
+/// ABI-encoded `setMapping(solana_pubkey, evm_address, version)`
+pub fn set_mapping_calldata(solana_pubkey: &SolanaPubkey, evm_address: &EvmAddress, version: u64) -> Vec<u8> {
+    SolanaToEvmRegistry::setMappingCall {
+        solanaPubkey: solana_pubkey.to_bytes().into(),
+        evmAddress: to_address(evm_address),
+        version,
+    }
+    .abi_encode()
+}
+
+fn to_address(evm_address: &EvmAddress) -> Address {
+    evm_address.as_str().parse().expect("EvmAddress is validated")
+}
+
+// =============================================================================
+// TRANSACTIONS
+// =============================================================================
+
+/// Nonce and fees of a transaction, as quoted by the chain's RPC node
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TxParams {
+    /// Next nonce of the operator address
+    pub nonce: u64,
+    pub gas_limit: u64,
+    pub max_fee_per_gas: u128,
+    pub max_priority_fee_per_gas: u128,
+}
+
+/// Unsigned EIP-1559 call to a registry contract
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RegistryTransaction {
+    /// EVM chain id (the `eip155` reference)
+    pub chain_id: u64,
+    /// The registry contract
+    pub to: EvmAddress,
+    /// Calldata, 0x-prefixed hex
+    pub data: String,
+    pub params: TxParams,
+}
+
+impl RegistryTransaction {
+    /// The transaction with JSON-RPC field names and hex quantities
+    pub fn to_json(&self) -> serde_json::Value {
+        json!({
+            "type": "0x2",
+            "chainId": format!("{:#x}", self.chain_id),
+            "to": self.to.as_str(),
+            "value": "0x0",
+            "data": self.data,
+            "nonce": format!("{:#x}", self.params.nonce),
+            "gas": format!("{:#x}", self.params.gas_limit),
+            "maxFeePerGas": format!("{:#x}", self.params.max_fee_per_gas),
+            "maxPriorityFeePerGas": format!("{:#x}", self.params.max_priority_fee_per_gas),
+        })
+    }
+}
+
+/// Signs registry transactions with the org's operator key, the only key the
+/// registry contracts accept `setMapping` from
+pub trait OperatorSigner {
+    /// RLP-encoded signed transaction, 0x-prefixed hex
+    fn sign_transaction(&self, tx: &RegistryTransaction) -> Result<String>;
+}
+
+/// `OperatorSigner` over a CubeSigner EVM key
+pub struct CubeSignerOperator<T> {
+    client: CubeSignerClient<T>,
+    operator: EvmAddress,
+}
+
+impl<T: HttpTransport> CubeSignerOperator<T> {
+    /// Sign with the CubeSigner key at `operator`; the client's session needs
+    /// the `sign:evm:tx` scope
+    pub fn new(client: CubeSignerClient<T>, operator: EvmAddress) -> Self {
+        Self { client, operator }
+    }
+}
+
+impl<T: HttpTransport> OperatorSigner for CubeSignerOperator<T> {
+    fn sign_transaction(&self, tx: &RegistryTransaction) -> Result<String> {
+        self.client
+            .eth1_sign(self.operator.as_str(), tx.chain_id, tx.to_json())
+            .map_err(|e| ProvisionError::SigningFailed { message: e.to_string(), retryable: e.is_retryable() })
+    }
+}
+
+// =============================================================================
+// SYNC STATUS
+// =============================================================================
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum SyncStatus {
+    /// Signed, not yet reported as broadcast
+    Signed,
+    Submitted { tx_hash: String },
+    Confirmed { tx_hash: String },
+    /// Reverted, dropped or never broadcast; the next `sync` signs again
+    Failed { error: String },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SyncRecord {
+    #[serde(with = "crate::address::lowercase")]
+    pub address: EvmAddress,
+    /// Revision of the chain mapping the transaction sets
+    pub version: u64,
+    /// Registry contract the transaction calls
+    #[serde(with = "crate::address::lowercase")]
+    pub contract: EvmAddress,
+    /// The signed transaction, 0x-prefixed hex
+    pub signed_tx: String,
+    #[serde(flatten)]
+    pub status: SyncStatus,
+    /// Unix timestamp (seconds) of the last status change
+    pub updated_at: u64,
+}
+
+pub fn get_sync(kv: &impl KvStore, solana_pubkey: &SolanaPubkey, chain_id: &ChainId) -> Result<Option<SyncRecord>> {
+    kv.get(&sync_key(solana_pubkey, chain_id))?
+        .map(|raw| serde_json::from_str(&raw).map_err(|e| ProvisionError::corrupt(sync_key(solana_pubkey, chain_id), e)))
+        .transpose()
+}
+
+fn put_sync(kv: &impl KvStore, solana_pubkey: &SolanaPubkey, chain_id: &ChainId, record: &SyncRecord) -> Result<()> {
+    let raw = serde_json::to_string(record).expect("sync record serialization cannot fail");
+    kv.set(&sync_key(solana_pubkey, chain_id), &raw)
+}
+
+// =============================================================================
+// SYNC
+// =============================================================================
+
+/// Sign the `setMapping` transaction for the current mapping of
+/// `solana_pubkey` on `chain_id` (including a mapping inherited from the
+/// default address) and record it as `signed`. Returns `None` if that
+/// revision was already signed and its sync has not failed.
+pub fn sync(
+    kv: &impl KvStore,
+    signer: &impl OperatorSigner,
+    solana_pubkey: &SolanaPubkey,
+    chain_id: &ChainId,
+    contract: &EvmAddress,
+    params: TxParams,
+    now: u64,
+) -> Result<Option<SyncRecord>> {
+    let evm_chain_id = chain_id
+        .evm_chain_id()
+        .ok_or_else(|| ProvisionError::InvalidRequest(format!("{} is not an EVM chain", chain_id)))?;
+
+    let current = mapping::get(kv, solana_pubkey, std::slice::from_ref(chain_id), now)?;
+    let address = current
+        .chain_mappings
+        .get(chain_id)
+        .cloned()
+        .ok_or_else(|| ProvisionError::NotProvisioned(solana_pubkey.to_string()))?;
+    let version = current.chain_versions.get(chain_id).copied().unwrap_or(0);
+
+    if let Some(last) = get_sync(kv, solana_pubkey, chain_id)? {
+        let failed = matches!(last.status, SyncStatus::Failed { .. });
+        if last.address == address && last.version == version && !failed {
+            return Ok(None);
+        }
+    }
+
+    let tx = RegistryTransaction {
+        chain_id: evm_chain_id,
+        to: contract.clone(),
+        data: hex::encode_prefixed(set_mapping_calldata(solana_pubkey, &address, version)),
+        params,
+    };
+    let record = SyncRecord {
+        address,
+        version,
+        contract: contract.clone(),
+        signed_tx: signer.sign_transaction(&tx)?,
+        status: SyncStatus::Signed,
+        updated_at: now,
+    };
+    put_sync(kv, solana_pubkey, chain_id, &record)?;
+    Ok(Some(record))
+}
+
+/// Report what became of the transaction signed for `version` of the chain
+/// mapping. Fails if the latest sync is of another version, so a late report
+/// about a superseded transaction cannot overwrite the current one.
+pub fn record_status(
+    kv: &impl KvStore,
+    solana_pubkey: &SolanaPubkey,
+    chain_id: &ChainId,
+    version: u64,
+    status: SyncStatus,
+    now: u64,
+) -> Result<SyncRecord> {
+    let record = get_sync(kv, solana_pubkey, chain_id)?
+        .filter(|record| record.version == version)
+        .ok_or_else(|| {
+            ProvisionError::InvalidRequest(format!("No sync of version {} for {} on chain {}", version, solana_pubkey, chain_id))
+        })?;
+
+    let record = SyncRecord { status, updated_at: now, ..record };
+    put_sync(kv, solana_pubkey, chain_id, &record)?;
+    Ok(record)
+}
@@ -0,0 +1,112 @@
+//! OpenAPI Document (`openapi` feature)
+//!
+//! A machine-readable contract for integrators, derived from the request and
+//! response types themselves so it cannot drift from what the code accepts:
+//! - `paths`: the REST API of `server`
+//! - `components.schemas.PolicyRequest`: the body of a C2F policy invocation,
+//!   one variant per `"action"` (`policy_api`)
+//!
+//! The document is OpenAPI 3.1, whose schemas are JSON Schema 2020-12.
+//! Request types are described as they are read (`#[serde(default)]` fields
+//! optional) and response types as they are written. `cargo run --features
+//! openapi --bin openapi` prints it.
+
+use crate::policy_api::PolicyRequest;
+use crate::error::ProvisionError;
+use crate::{GetMappingsResponse, ProvisionRequest, ProvisionResponse, SolanaPubkey, UpdateMappingRequest, UpdateMappingResponse};
+use schemars::generate::SchemaSettings;
+use schemars::SchemaGenerator;
+use serde_json::{json, Map, Value};
+
+const DEFINITIONS_PATH: &str = "/components/schemas";
+
+fn generator(settings: SchemaSettings) -> SchemaGenerator {
+    settings
+        .with(|settings| {
+            settings.definitions_path = DEFINITIONS_PATH.into();
+            settings.meta_schema = None;
+        })
+        .into_generator()
+}
+
+fn content(schema: Value) -> Value {
+    json!({ "application/json": { "schema": schema } })
+}
+
+/// Responses of a route answering `ok` on success and `error` on failure
+fn responses(ok: Value, error: &Value) -> Value {
+    json!({
+        "200": { "description": "Success", "content": content(ok) },
+        "default": { "description": "Failure; the HTTP status follows `code` (see `server::status`)", "content": content(error.clone()) },
+    })
+}
+
+/// The OpenAPI document
+pub fn document() -> Value {
+    let mut reader = generator(SchemaSettings::draft2020_12().for_deserialize());
+    let mut writer = generator(SchemaSettings::draft2020_12().for_serialize());
+
+    let provision = reader.subschema_for::<ProvisionRequest>().to_value();
+    let update = reader.subschema_for::<UpdateMappingRequest>().to_value();
+    let solana_pubkey = reader.subschema_for::<SolanaPubkey>().to_value();
+    reader.subschema_for::<PolicyRequest>();
+    let provisioned = writer.subschema_for::<ProvisionResponse>().to_value();
+    let updated = writer.subschema_for::<UpdateMappingResponse>().to_value();
+    let mappings = writer.subschema_for::<GetMappingsResponse>().to_value();
+    let error = writer.subschema_for::<ProvisionError>().to_value();
+
+    let mut schemas = Map::new();
+    schemas.extend(writer.take_definitions(true));
+    schemas.extend(reader.take_definitions(true));
+
+    json!({
+        "openapi": "3.1.0",
+        "info": {
+            "title": "Cubist Wallet Provisioner",
+            "version": env!("CARGO_PKG_VERSION"),
+            "description": "Maps Solana addresses to CubeSigner-held EVM wallets per chain. `paths` is the REST API of the `server` feature; `components.schemas.PolicyRequest` is the JSON body of a C2F policy invocation.",
+        },
+        "paths": {
+            "/provision": {
+                "post": {
+                    "operationId": "provision",
+                    "summary": "Create one EVM key and map it on every requested chain (idempotent)",
+                    "requestBody": { "required": true, "content": content(provision) },
+                    "responses": responses(provisioned, &error),
+                },
+            },
+            "/update": {
+                "post": {
+                    "operationId": "updateMapping",
+                    "summary": "Rotate one chain's mapping to a new EVM key",
+                    "requestBody": { "required": true, "content": content(update) },
+                    "responses": responses(updated, &error),
+                },
+            },
+            "/mappings/{solana_pubkey}": {
+                "get": {
+                    "operationId": "getMappings",
+                    "summary": "Current mappings of a Solana address",
+                    "parameters": [
+                        {
+                            "name": "solana_pubkey",
+                            "in": "path",
+                            "required": true,
+                            "schema": solana_pubkey,
+                        },
+                        {
+                            "name": "chain_ids",
+                            "in": "query",
+                            "required": false,
+                            "description": "Comma-separated chain ids; omitted: every chain with a mapping",
+                            "schema": { "type": "string" },
+                            "example": "eip155:1,137",
+                        },
+                    ],
+                    "responses": responses(mappings, &error),
+                },
+            },
+        },
+        "components": { "schemas": schemas },
+    })
+}
@@ -0,0 +1,687 @@
+//! Policy Request Types
+//!
+//! The JSON body the C2F policy is invoked with: one variant per action,
+//! tagged by `"action"`. They live here rather than in the policy so that
+//! backends and the OpenAPI document (`openapi`) share the policy's own
+//! definition of the contract.
+
+use crate::blocklist::BlockTarget;
+use crate::config::ConfigUpdate;
+use crate::error::{ProvisionError, Result};
+use crate::export::ExportEntry;
+use crate::import::ImportStrategy;
+use crate::spend_limits::SpendLimit;
+use crate::wildcard;
+use crate::{ChainId, EvmAddress, KeyClass, KeyType, LinkExternalRequest, ListedKey, ProvisionRequest, SolanaPubkey};
+use serde::Deserialize;
+
+/// Body of a policy invocation, by `"action"`
+#[derive(Deserialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+#[serde(tag = "action")]
+pub enum PolicyRequest {
+    /// Store mappings for a Solana address (called after backend creates key)
+    /// `signature` is the user's ed25519 signature of `message` (ownership proof)
+    #[serde(rename = "store")]
+    Store {
+        solana_pubkey: SolanaPubkey,
+        /// Empty or absent: the configured `default_chain_ids`; `"*"`: every
+        /// EVM chain (see `wildcard`)
+        #[serde(default, deserialize_with = "wildcard::chain_ids")]
+        chain_ids: Vec<ChainId>,
+        evm_address: EvmAddress,
+        /// CubeSigner key id of `evm_address`
+        #[serde(default)]
+        key_id: Option<String>,
+        message: String,
+        signature: String,
+        /// Store an additional address under this label (see `labels`);
+        /// `evm_address` is then the label's key
+        #[serde(default)]
+        label: Option<String>,
+        /// CubeSigner type of `key_id`'s key (`SecpEthAddr` if absent)
+        #[serde(default)]
+        key_type: KeyType,
+        /// How `key_id`'s key is held (`standard` if absent)
+        #[serde(default)]
+        key_class: KeyClass,
+        /// Make the stored mappings expire this many seconds from now (see `expiry`)
+        #[serde(default)]
+        ttl_secs: Option<u64>,
+        /// Retries with the same key return the first response (see `idempotency`)
+        #[serde(default)]
+        idempotency_key: Option<String>,
+        /// Check and return the would-be response without writing (see `dry_run`)
+        #[serde(default)]
+        dry_run: bool,
+    },
+
+    /// Submit a provisioning job (see `jobs`): the backend's worker creates
+    /// the key and stores the mappings, and the caller polls `job_status`
+    #[serde(rename = "provision_async")]
+    ProvisionAsync {
+        solana_pubkey: SolanaPubkey,
+        /// Empty or absent: the configured `default_chain_ids`; `"*"`: every
+        /// EVM chain (see `wildcard`)
+        #[serde(default, deserialize_with = "wildcard::chain_ids")]
+        chain_ids: Vec<ChainId>,
+        message: String,
+        signature: String,
+        #[serde(default)]
+        label: Option<String>,
+        /// CubeSigner type of the key to create (`SecpEthAddr` if absent)
+        #[serde(default)]
+        key_type: KeyType,
+        /// How the key to create is held (`standard` if absent)
+        #[serde(default)]
+        key_class: KeyClass,
+    },
+
+    /// A provisioning job and how far it got
+    #[serde(rename = "job_status")]
+    JobStatus {
+        solana_pubkey: SolanaPubkey,
+        job_id: u64,
+    },
+    
+    /// Get existing mappings for a Solana address
+    #[serde(rename = "get")]
+    Get {
+        solana_pubkey: SolanaPubkey,
+        /// Empty or omitted: every chain the user has a mapping for
+        #[serde(default)]
+        chain_ids: Vec<ChainId>,
+    },
+    
+    /// Propose a new mapping for a specific chain (admin only, after backend
+    /// creates new key). Applied once a different admin approves it.
+    #[serde(rename = "propose_update")]
+    ProposeUpdate {
+        solana_pubkey: SolanaPubkey,
+        chain_id: ChainId,
+        new_evm_address: EvmAddress,
+        /// CubeSigner key id of `new_evm_address`
+        #[serde(default)]
+        new_key_id: Option<String>,
+        /// Map `new_evm_address` even if it belongs to another user
+        #[serde(default)]
+        allow_shared_address: bool,
+    },
+
+    /// Approve a pending update and overwrite the chain mapping (admin only,
+    /// not the proposer)
+    #[serde(rename = "approve_update")]
+    ApproveUpdate {
+        solana_pubkey: SolanaPubkey,
+        chain_id: ChainId,
+        proposal_id: u64,
+        /// Retries with the same key return the first response (see `idempotency`)
+        #[serde(default)]
+        idempotency_key: Option<String>,
+        /// Check and return the would-be response without writing (see `dry_run`)
+        #[serde(default)]
+        dry_run: bool,
+    },
+
+    /// Discard a pending update (admin only)
+    #[serde(rename = "reject_update")]
+    RejectUpdate {
+        solana_pubkey: SolanaPubkey,
+        chain_id: ChainId,
+        proposal_id: u64,
+    },
+
+    /// Propose or approve many chain updates in one call (admin only): an
+    /// entry without `proposal_id` is proposed, one with it approves that
+    /// proposal. With `all_or_nothing`, nothing is written unless every
+    /// entry would succeed.
+    #[serde(rename = "update_batch")]
+    UpdateBatch {
+        updates: Vec<UpdateBatchEntry>,
+        #[serde(default)]
+        all_or_nothing: bool,
+    },
+
+    /// Latest proposed update for a chain
+    #[serde(rename = "get_pending")]
+    GetPending {
+        solana_pubkey: SolanaPubkey,
+        chain_id: ChainId,
+    },
+
+    /// Add an identity to the admin allowlist (org owners only)
+    #[serde(rename = "add_admin")]
+    AddAdmin {
+        identity: String,
+    },
+
+    /// Remove an identity from the admin allowlist (org owners only)
+    #[serde(rename = "remove_admin")]
+    RemoveAdmin {
+        identity: String,
+    },
+
+    /// Update mapping for a specific chain, authorized by the user's own signature
+    /// over `update_self_message(...)` instead of by an admin
+    #[serde(rename = "update_self")]
+    UpdateSelf {
+        solana_pubkey: SolanaPubkey,
+        chain_id: ChainId,
+        /// Address of `new_key_id`
+        new_evm_address: EvmAddress,
+        /// CubeSigner id of the key the backend created (`Key#0x…`)
+        new_key_id: String,
+        /// Decimal integer above the last nonce the user signed (see `auth::parse_nonce`)
+        nonce: String,
+        /// Unix timestamp (seconds) after which the signature is rejected
+        expires_at: u64,
+        signature: String,
+        /// Retries with the same key return the first response (see `idempotency`)
+        #[serde(default)]
+        idempotency_key: Option<String>,
+    },
+
+    /// Link an EVM address the user already controls as the mapping of some
+    /// chains, proven by signatures from both wallets
+    #[serde(rename = "link_external")]
+    LinkExternal {
+        #[serde(flatten)]
+        request: LinkExternalRequest,
+    },
+
+    /// Past addresses of a chain mapping, oldest first
+    #[serde(rename = "history")]
+    History {
+        solana_pubkey: SolanaPubkey,
+        chain_id: ChainId,
+    },
+
+    /// Store mappings for many Solana addresses in one invocation
+    #[serde(rename = "store_batch")]
+    StoreBatch {
+        requests: Vec<StoreBatchEntry>,
+        /// Check and return the would-be responses without writing (see `dry_run`)
+        #[serde(default)]
+        dry_run: bool,
+    },
+
+    /// List every chain mapping for a Solana address (no chain_ids needed)
+    #[serde(rename = "list")]
+    List {
+        solana_pubkey: SolanaPubkey,
+    },
+
+    /// Store the Solana wallet of an EVM address (called after backend creates
+    /// the Ed25519 key). `signature` is the user's EIP-191 signature of `message`,
+    /// which must be the `auth::evm_to_solana_message` of `evm_address`.
+    #[serde(rename = "store_evm_to_solana")]
+    StoreEvmToSolana {
+        evm_address: EvmAddress,
+        solana_pubkey: SolanaPubkey,
+        /// CubeSigner key id of `solana_pubkey`
+        key_id: String,
+        message: String,
+        signature: String,
+    },
+
+    /// Get the Solana wallet of an EVM address
+    #[serde(rename = "get_evm_to_solana")]
+    GetEvmToSolana {
+        evm_address: EvmAddress,
+    },
+
+    /// Look up which Solana address owns an EVM address
+    #[serde(rename = "reverse_get")]
+    ReverseGet {
+        evm_address: EvmAddress,
+    },
+
+    /// Look up what replaced a retired EVM address, and why
+    #[serde(rename = "get_retirement")]
+    GetRetirement {
+        evm_address: EvmAddress,
+    },
+
+    /// Enable or disable a chain, or register a new one (admin only).
+    /// `name` is required when registering a chain that is not built in.
+    #[serde(rename = "set_chain")]
+    SetChain {
+        chain_id: ChainId,
+        enabled: bool,
+        #[serde(default)]
+        name: Option<String>,
+        #[serde(default)]
+        testnet: Option<bool>,
+    },
+
+    /// Every chain in the registry, enabled or not
+    #[serde(rename = "list_chains")]
+    ListChains,
+
+    /// Provisioning counters: wallets, updates and errors by code (see `metrics`)
+    #[serde(rename = "stats")]
+    Stats,
+
+    /// Billable counters of a calendar month, `YYYY-MM` (see `usage`)
+    #[serde(rename = "usage_report")]
+    UsageReport { month: String },
+
+    /// Write to the KV store and read it back (see `health`)
+    #[serde(rename = "ping")]
+    Ping,
+
+    /// Crate version, git sha and schema version of the deployed build
+    #[serde(rename = "version")]
+    Version,
+
+    /// Current runtime configuration (admin only, see `config`)
+    #[serde(rename = "get_config")]
+    GetConfig,
+
+    /// Change runtime configuration; fields left out keep their values (admin only)
+    #[serde(rename = "set_config")]
+    SetConfig {
+        config: ConfigUpdate,
+    },
+
+    /// Identities allowed to act in `tenant_id` (org owners only, see `tenant`)
+    #[serde(rename = "get_tenant_members")]
+    GetTenantMembers {
+        tenant_id: String,
+    },
+
+    /// Replace the identities allowed to act in `tenant_id` (org owners
+    /// only, see `tenant`)
+    #[serde(rename = "set_tenant_members")]
+    SetTenantMembers {
+        tenant_id: String,
+        members: Vec<String>,
+    },
+
+    /// Rewrite one batch of outdated mapping records as the current
+    /// `MappingRecord` version (admin only). Resume with `next_cursor`.
+    #[serde(rename = "migrate")]
+    Migrate {
+        #[serde(default)]
+        cursor: Option<String>,
+        #[serde(default)]
+        limit: Option<usize>,
+    },
+
+    /// Remove the expired temporary mappings among one page of the mappings
+    /// bucket (admin only, see `expiry`). Resume with `next_cursor`.
+    #[serde(rename = "sweep")]
+    Sweep {
+        #[serde(default)]
+        cursor: Option<String>,
+        #[serde(default)]
+        limit: Option<usize>,
+    },
+
+    /// Erase a user's mappings, leaving only a salted pseudonym (admin only,
+    /// see `anonymize`). Returns the deletion receipt.
+    #[serde(rename = "anonymize")]
+    Anonymize { solana_pubkey: SolanaPubkey, salt: String },
+
+    /// Export one page of the mappings bucket as raw key/value entries, for
+    /// backups (admin only). Resume with `next_cursor`.
+    #[serde(rename = "export")]
+    Export {
+        #[serde(default)]
+        cursor: Option<String>,
+        #[serde(default)]
+        limit: Option<usize>,
+    },
+
+    /// Check one page of the mappings bucket for inconsistent records (chain
+    /// mappings without a default, reverse index mismatches, ...) and report
+    /// them (admin only). Resume with `next_cursor`.
+    #[serde(rename = "verify")]
+    Verify {
+        #[serde(default)]
+        cursor: Option<String>,
+        #[serde(default)]
+        limit: Option<usize>,
+    },
+
+    /// Fix violations reported by `verify`, by id (admin only). With
+    /// `dry_run`, only report what would be fixed.
+    #[serde(rename = "repair")]
+    Repair {
+        ids: Vec<String>,
+        #[serde(default)]
+        dry_run: bool,
+    },
+
+    /// Write exported entries back into the mappings bucket, resolving keys
+    /// that hold other values by `strategy` (admin only). With `dry_run`,
+    /// only report what would change.
+    #[serde(rename = "import")]
+    Import {
+        entries: Vec<ExportEntry>,
+        #[serde(default)]
+        strategy: ImportStrategy,
+        #[serde(default)]
+        dry_run: bool,
+    },
+
+    /// Copy one batch of `bucket`'s unprefixed keys under this build's
+    /// environment prefix (org owners only, so it works before the admin
+    /// allowlist is copied). Resume with `next_cursor`.
+    #[serde(rename = "migrate_environment")]
+    MigrateEnvironment {
+        bucket: String,
+        #[serde(default)]
+        cursor: Option<String>,
+        #[serde(default)]
+        limit: Option<usize>,
+    },
+
+    /// Copy one batch of `bucket`'s keys naming a Solana pubkey in the clear
+    /// under their hashed form, for a build with a pepper (org owners only,
+    /// see `privacy`). Resume with `next_cursor`.
+    #[serde(rename = "migrate_hashed_keys")]
+    MigrateHashedKeys {
+        bucket: String,
+        #[serde(default)]
+        cursor: Option<String>,
+        #[serde(default)]
+        limit: Option<usize>,
+    },
+
+    /// Encrypt one batch of `bucket`'s plaintext values in place, for a
+    /// build with the `encryption` feature (org owners only, see
+    /// `encryption`). Resume with `next_cursor`.
+    #[serde(rename = "encrypt_values")]
+    EncryptValues {
+        bucket: String,
+        #[serde(default)]
+        cursor: Option<String>,
+        #[serde(default)]
+        limit: Option<usize>,
+    },
+
+    /// Compare the org's EVM keys with one batch of the bucket and report
+    /// (with `repair`, fix) keys and mappings that lost each other (admin
+    /// only). The policy cannot list keys itself: the backend passes all of
+    /// them in `keys`. Resume with `next_cursor`.
+    #[serde(rename = "reconcile")]
+    Reconcile {
+        keys: Vec<ListedKey>,
+        #[serde(default)]
+        cursor: Option<String>,
+        #[serde(default)]
+        limit: Option<usize>,
+        #[serde(default)]
+        repair: bool,
+    },
+
+    /// Freeze an EVM address suspected of compromise (admin only): `store`
+    /// stops handing it out and `get` lists it in `frozen_addresses`
+    #[serde(rename = "freeze")]
+    Freeze {
+        evm_address: EvmAddress,
+        #[serde(default)]
+        reason: Option<String>,
+    },
+
+    /// Lift a freeze (admin only)
+    #[serde(rename = "unfreeze")]
+    Unfreeze {
+        evm_address: EvmAddress,
+    },
+
+    /// Set a user's spending limit, on their default mapping or their own
+    /// mapping for `chain_id`; `null` clears it (admin only, see `spend_limits`)
+    #[serde(rename = "set_spend_limit")]
+    SetSpendLimit {
+        solana_pubkey: SolanaPubkey,
+        #[serde(default)]
+        chain_id: Option<ChainId>,
+        #[serde(default)]
+        spend_limit: Option<SpendLimit>,
+    },
+
+    /// Bind a CubeSigner identity to the user whose keys it signs with, as
+    /// the signing gate checks them; `null` unbinds it (admin only, see
+    /// `signing_gate`)
+    #[serde(rename = "set_signer")]
+    SetSigner {
+        identity: String,
+        #[serde(default)]
+        solana_pubkey: Option<SolanaPubkey>,
+    },
+
+    /// Spending limit in force on a chain, and what was signed there today
+    #[serde(rename = "get_spend_limit")]
+    GetSpendLimit {
+        solana_pubkey: SolanaPubkey,
+        #[serde(default)]
+        chain_id: Option<ChainId>,
+    },
+
+    /// Allow the user's wallet on `chain_id` to send transactions to
+    /// `destination`, restricting the chain to its allowlist (admin only,
+    /// see `destinations`)
+    #[serde(rename = "add_allowed_destination")]
+    AddAllowedDestination {
+        solana_pubkey: SolanaPubkey,
+        chain_id: ChainId,
+        destination: EvmAddress,
+    },
+
+    /// Take `destination` off the allowlist; removing the last one lifts the
+    /// restriction (admin only)
+    #[serde(rename = "remove_allowed_destination")]
+    RemoveAllowedDestination {
+        solana_pubkey: SolanaPubkey,
+        chain_id: ChainId,
+        destination: EvmAddress,
+    },
+
+    /// Put a Solana or EVM address on the blocklist (admin only):
+    /// `{"solana_pubkey": …}` or `{"evm_address": …}`
+    #[serde(rename = "block")]
+    Block {
+        #[serde(flatten)]
+        target: BlockTarget,
+        #[serde(default)]
+        reason: Option<String>,
+    },
+
+    /// Take an address off the blocklist (admin only)
+    #[serde(rename = "unblock")]
+    Unblock {
+        #[serde(flatten)]
+        target: BlockTarget,
+    },
+
+    /// Read audit records in a time range, paged by seq
+    #[serde(rename = "audit_query")]
+    AuditQuery {
+        #[serde(default)]
+        from: Option<u64>,
+        #[serde(default)]
+        to: Option<u64>,
+        #[serde(default)]
+        after_seq: Option<u64>,
+        #[serde(default)]
+        limit: Option<usize>,
+    },
+
+    /// Read mapping change events after `after_seq` (0: from the start)
+    #[serde(rename = "poll_events")]
+    PollEvents {
+        #[serde(default)]
+        after_seq: u64,
+        #[serde(default)]
+        limit: Option<usize>,
+    },
+
+    /// Merkle root over every stored chain mapping (admin only, see `merkle`)
+    #[serde(rename = "merkle_root")]
+    MerkleRoot,
+
+    /// Inclusion proof of one chain mapping against the Merkle root
+    #[serde(rename = "merkle_proof")]
+    MerkleProof {
+        solana_pubkey: SolanaPubkey,
+        chain_id: ChainId,
+    },
+}
+
+impl PolicyRequest {
+    /// Parse the policy's request body. A missing or malformed body is
+    /// `InvalidRequest`; no input makes this panic (fuzzed in `fuzz/`).
+    pub fn parse(body: Option<&str>) -> Result<Self> {
+        let body = body.ok_or_else(|| ProvisionError::InvalidRequest("missing request body".to_string()))?;
+        serde_json::from_str(body).map_err(|e| ProvisionError::InvalidRequest(e.to_string()))
+    }
+
+    /// The `"action"` name, as used in the authorization matrix (`authz::MATRIX`)
+    pub fn action(&self) -> &'static str {
+        match self {
+            Self::Store { .. } => "store",
+            Self::ProvisionAsync { .. } => "provision_async",
+            Self::JobStatus { .. } => "job_status",
+            Self::Get { .. } => "get",
+            Self::ProposeUpdate { .. } => "propose_update",
+            Self::ApproveUpdate { .. } => "approve_update",
+            Self::RejectUpdate { .. } => "reject_update",
+            Self::UpdateBatch { .. } => "update_batch",
+            Self::GetPending { .. } => "get_pending",
+            Self::AddAdmin { .. } => "add_admin",
+            Self::RemoveAdmin { .. } => "remove_admin",
+            Self::UpdateSelf { .. } => "update_self",
+            Self::LinkExternal { .. } => "link_external",
+            Self::History { .. } => "history",
+            Self::StoreBatch { .. } => "store_batch",
+            Self::List { .. } => "list",
+            Self::StoreEvmToSolana { .. } => "store_evm_to_solana",
+            Self::GetEvmToSolana { .. } => "get_evm_to_solana",
+            Self::ReverseGet { .. } => "reverse_get",
+            Self::GetRetirement { .. } => "get_retirement",
+            Self::SetChain { .. } => "set_chain",
+            Self::ListChains => "list_chains",
+            Self::Stats => "stats",
+            Self::UsageReport { .. } => "usage_report",
+            Self::Ping => "ping",
+            Self::Version => "version",
+            Self::GetConfig => "get_config",
+            Self::SetConfig { .. } => "set_config",
+            Self::GetTenantMembers { .. } => "get_tenant_members",
+            Self::SetTenantMembers { .. } => "set_tenant_members",
+            Self::Migrate { .. } => "migrate",
+            Self::Sweep { .. } => "sweep",
+            Self::Anonymize { .. } => "anonymize",
+            Self::Export { .. } => "export",
+            Self::Verify { .. } => "verify",
+            Self::Repair { .. } => "repair",
+            Self::Import { .. } => "import",
+            Self::MigrateEnvironment { .. } => "migrate_environment",
+            Self::MigrateHashedKeys { .. } => "migrate_hashed_keys",
+            Self::EncryptValues { .. } => "encrypt_values",
+            Self::Reconcile { .. } => "reconcile",
+            Self::Freeze { .. } => "freeze",
+            Self::Unfreeze { .. } => "unfreeze",
+            Self::SetSpendLimit { .. } => "set_spend_limit",
+            Self::SetSigner { .. } => "set_signer",
+            Self::GetSpendLimit { .. } => "get_spend_limit",
+            Self::AddAllowedDestination { .. } => "add_allowed_destination",
+            Self::RemoveAllowedDestination { .. } => "remove_allowed_destination",
+            Self::Block { .. } => "block",
+            Self::Unblock { .. } => "unblock",
+            Self::AuditQuery { .. } => "audit_query",
+            Self::PollEvents { .. } => "poll_events",
+            Self::MerkleRoot => "merkle_root",
+            Self::MerkleProof { .. } => "merkle_proof",
+        }
+    }
+
+    /// The Solana address the request is about, for the log (`logging::pubkey_hash`)
+    pub fn solana_pubkey(&self) -> Option<&SolanaPubkey> {
+        match self {
+            Self::Store { solana_pubkey, .. }
+            | Self::ProvisionAsync { solana_pubkey, .. }
+            | Self::JobStatus { solana_pubkey, .. }
+            | Self::Get { solana_pubkey, .. }
+            | Self::ProposeUpdate { solana_pubkey, .. }
+            | Self::ApproveUpdate { solana_pubkey, .. }
+            | Self::RejectUpdate { solana_pubkey, .. }
+            | Self::GetPending { solana_pubkey, .. }
+            | Self::UpdateSelf { solana_pubkey, .. }
+            | Self::History { solana_pubkey, .. }
+            | Self::MerkleProof { solana_pubkey, .. }
+            | Self::SetSpendLimit { solana_pubkey, .. }
+            | Self::GetSpendLimit { solana_pubkey, .. }
+            | Self::AddAllowedDestination { solana_pubkey, .. }
+            | Self::RemoveAllowedDestination { solana_pubkey, .. }
+            | Self::List { solana_pubkey }
+            | Self::StoreEvmToSolana { solana_pubkey, .. }
+            | Self::SetSigner { solana_pubkey: Some(solana_pubkey), .. } => Some(solana_pubkey),
+            Self::LinkExternal { request } => Some(&request.solana_pubkey),
+            Self::Block { target: BlockTarget::SolanaPubkey(solana_pubkey), .. }
+            | Self::Unblock { target: BlockTarget::SolanaPubkey(solana_pubkey) } => Some(solana_pubkey),
+            _ => None,
+        }
+    }
+}
+
+/// One entry of an `update_batch` request
+#[derive(Deserialize, Clone)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct UpdateBatchEntry {
+    pub solana_pubkey: SolanaPubkey,
+    pub chain_id: ChainId,
+    pub new_evm_address: EvmAddress,
+    /// CubeSigner key id of `new_evm_address`
+    #[serde(default)]
+    pub new_key_id: Option<String>,
+    /// Approve this proposal, which must be for `new_evm_address`, instead
+    /// of proposing the update
+    #[serde(default)]
+    pub proposal_id: Option<u64>,
+    /// When proposing: map `new_evm_address` even if it belongs to another user
+    #[serde(default)]
+    pub allow_shared_address: bool,
+}
+
+/// One entry of a `store_batch` request (same fields as `store`)
+#[derive(Deserialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct StoreBatchEntry {
+    pub solana_pubkey: SolanaPubkey,
+    #[serde(default, deserialize_with = "wildcard::chain_ids")]
+    pub chain_ids: Vec<ChainId>,
+    pub evm_address: EvmAddress,
+    #[serde(default)]
+    pub key_id: Option<String>,
+    pub message: String,
+    pub signature: String,
+    #[serde(default)]
+    pub label: Option<String>,
+    #[serde(default)]
+    pub key_type: KeyType,
+    #[serde(default)]
+    pub key_class: KeyClass,
+}
+
+impl StoreBatchEntry {
+    /// The entry as a store request, with the address and key id it stores
+    pub fn into_request(self) -> (ProvisionRequest, EvmAddress, Option<String>) {
+        let req = ProvisionRequest {
+            solana_pubkey: self.solana_pubkey,
+            chain_ids: self.chain_ids,
+            message: self.message,
+            signature: self.signature,
+            label: self.label,
+            key_type: self.key_type,
+            key_class: self.key_class,
+            ttl_secs: None,
+            idempotency_key: None,
+            request_id: None,
+        };
+        (req, self.evm_address, self.key_id)
+    }
+}
+
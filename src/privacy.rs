@@ -0,0 +1,236 @@
+//! Hashed Keys (Privacy Mode)
+//!
+//! Keys name their user in the clear (`default:{solana_pubkey}`,
+//! `{solana_pubkey}:{chain_id}`, …), so a dump of the bucket lists who has
+//! mappings. With a pepper, `HashedKeys` stores every Solana pubkey segment
+//! of a key as `h{HMAC-SHA256(pepper, solana_pubkey)}` (hex) instead. The
+//! flows keep passing plaintext keys; only the stored form changes.
+//!
+//! The policy reads the pepper from the configuration (`Config::kv_pepper`),
+//! which can set it once: changing it would orphan every key. The
+//! configuration document is the one value that carries it, encrypted like
+//! every other under the `encryption` feature. Without one, keys are stored
+//! as they are.
+//!
+//! Moving a deployment means copying its plaintext keys to their hashed form
+//! first: `migrate_plaintext_batch` does that in bounded batches, like
+//! `environment::migrate_legacy_batch`. Plaintext keys are copied, not moved,
+//! so the previous build keeps working until the new one is deployed.
+//!
+//! Values still name Solana addresses (reverse entries, `created_by`,
+//! journals, audit records), so hashing keys is only worth it over a store
+//! that seals values: wrap an `encryption::Encrypted` store, as the policy
+//! does (it refuses a pepper in builds without the `encryption` feature).
+//!
+//! Scans that parse users out of keys (`migrate`, `verify`, `repair`,
+//! `sweep`, the Merkle tree, `export`) need the pubkeys back. Each write of a
+//! key naming a user also records the user under `user:h{hmac}`, sealed like
+//! every value, and `list_keys` puts the pubkeys back in place of the hashes
+//! it finds there, so the scans see the keys the flows wrote.
+//!
+//! ## Key Schema
+//! ```text
+//! default:h{hmac}           # `default:{solana_pubkey}`; the same for every key family
+//! user:h{hmac} → {solana_pubkey}   # the user a hash stands for, for list_keys
+//! ```
+
+use crate::address::SolanaPubkey;
+use crate::error::{ProvisionError, Result};
+use crate::kv::KvStore;
+use crate::migrate::{DEFAULT_MIGRATION_BATCH, MAX_MIGRATION_BATCH};
+use hmac::{Hmac, Mac};
+use serde::Serialize;
+use sha2::Sha256;
+use std::collections::{BTreeSet, HashMap};
+
+/// Shortest accepted pepper
+pub const MIN_PEPPER_LEN: usize = 32;
+
+/// Prefix of a hashed pubkey segment. The 65 characters of a hashed segment
+/// never decode to a 32-byte pubkey, so it is never hashed again.
+pub const HASHED_SEGMENT_PREFIX: &str = "h";
+
+/// Prefix of the entries recording which user a hash stands for
+pub const USER_PREFIX: &str = "user:";
+
+/// Key of the entry recording the user `hashed` stands for: `user:h{hmac}`
+pub fn user_key(hashed: &str) -> String {
+    format!("{}{}", USER_PREFIX, hashed)
+}
+
+/// Whether a key segment is a hashed pubkey: `h` and 64 hex digits
+fn is_hashed_segment(segment: &str) -> bool {
+    segment
+        .strip_prefix(HASHED_SEGMENT_PREFIX)
+        .is_some_and(|hex| hex.len() == 64 && hex.bytes().all(|b| b.is_ascii_hexdigit()))
+}
+
+/// Secret key of the HMAC that hashes pubkeys
+#[derive(Clone)]
+pub struct Pepper(Hmac<Sha256>);
+
+impl Pepper {
+    pub fn new(secret: &[u8]) -> Result<Self> {
+        if secret.len() < MIN_PEPPER_LEN {
+            return Err(ProvisionError::InvalidRequest(format!("the pepper must be at least {} bytes", MIN_PEPPER_LEN)));
+        }
+        Ok(Self(Hmac::new_from_slice(secret).expect("HMAC accepts keys of any length")))
+    }
+
+    /// Stored form of a pubkey segment
+    pub fn hash(&self, solana_pubkey: &str) -> String {
+        let mut mac = self.0.clone();
+        mac.update(solana_pubkey.as_bytes());
+        let hex: String = mac.finalize().into_bytes().iter().map(|b| format!("{:02x}", b)).collect();
+        format!("{}{}", HASHED_SEGMENT_PREFIX, hex)
+    }
+
+    /// `key` with each Solana pubkey segment hashed; other segments (and
+    /// already hashed ones) are kept
+    pub fn hashed_key(&self, key: &str) -> String {
+        key.split(':')
+            .map(|segment| if SolanaPubkey::parse(segment).is_ok() { self.hash(segment) } else { segment.to_string() })
+            .collect::<Vec<_>>()
+            .join(":")
+    }
+
+    /// The `user_key` entries to record for the users `key` names in the clear
+    fn user_entries<'a>(&self, key: &'a str) -> Vec<(String, &'a str)> {
+        key.split(':')
+            .filter(|segment| SolanaPubkey::parse(segment).is_ok())
+            .map(|segment| (user_key(&self.hash(segment)), segment))
+            .collect()
+    }
+}
+
+/// A `KvStore` storing Solana pubkeys in keys hashed (`None`: as they are)
+pub struct HashedKeys<S> {
+    inner: S,
+    pepper: Option<Pepper>,
+}
+
+impl<S: KvStore> HashedKeys<S> {
+    pub fn new(inner: S, pepper: Option<Pepper>) -> Self {
+        Self { inner, pepper }
+    }
+
+    /// The key `key` is stored under
+    fn key(&self, key: &str) -> String {
+        match &self.pepper {
+            Some(pepper) => pepper.hashed_key(key),
+            None => key.to_string(),
+        }
+    }
+
+    /// Record the users `key` names, before anything is written under it, so
+    /// `list_keys` can give it back as written
+    fn record_users(&self, key: &str) -> Result<()> {
+        if let Some(pepper) = &self.pepper {
+            for (user_key, solana_pubkey) in pepper.user_entries(key) {
+                self.inner.set_if_absent(&user_key, solana_pubkey)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<S: KvStore> KvStore for HashedKeys<S> {
+    fn get(&self, key: &str) -> Result<Option<String>> {
+        self.inner.get(&self.key(key))
+    }
+
+    fn set_if_absent(&self, key: &str, value: &str) -> Result<bool> {
+        self.record_users(key)?;
+        self.inner.set_if_absent(&self.key(key), value)
+    }
+
+    fn set(&self, key: &str, value: &str) -> Result<()> {
+        self.record_users(key)?;
+        self.inner.set(&self.key(key), value)
+    }
+
+    fn get_many(&self, keys: &[String]) -> Result<Vec<Option<String>>> {
+        let keys: Vec<String> = keys.iter().map(|key| self.key(key)).collect();
+        self.inner.get_many(&keys)
+    }
+
+    fn delete(&self, key: &str) -> Result<()> {
+        self.inner.delete(&self.key(key))
+    }
+
+    /// Keys in stored order, with each hash recorded under `user_key` put
+    /// back as its pubkey. A hash with no (or a forged) record is kept: the
+    /// key still reads back as it is, since a hashed segment is not hashed again.
+    fn list_keys(&self, after: Option<&str>, limit: usize) -> Result<Vec<String>> {
+        let Some(pepper) = &self.pepper else {
+            return self.inner.list_keys(after, limit);
+        };
+        let keys = self.inner.list_keys(after.map(|after| pepper.hashed_key(after)).as_deref(), limit)?;
+
+        let hashed: Vec<&str> = keys
+            .iter()
+            .flat_map(|key| key.split(':'))
+            .filter(|segment| is_hashed_segment(segment))
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect();
+        let user_keys: Vec<String> = hashed.iter().map(|hashed| user_key(hashed)).collect();
+        let users: HashMap<&str, String> = hashed
+            .into_iter()
+            .zip(self.inner.get_many(&user_keys)?)
+            .filter_map(|(hashed, user)| user.filter(|user| pepper.hash(user) == hashed).map(|user| (hashed, user)))
+            .collect();
+
+        Ok(keys
+            .iter()
+            .map(|key| key.split(':').map(|segment| users.get(segment).map_or(segment, String::as_str)).collect::<Vec<_>>().join(":"))
+            .collect())
+    }
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct KeyHashingReport {
+    /// Keys looked at in this batch, hashed ones included
+    pub scanned: usize,
+    /// Plaintext keys copied under their hashed form
+    pub copied: usize,
+    /// Plaintext keys whose copy already held the same value
+    pub unchanged: usize,
+    /// Plaintext keys whose copy holds a different value (left as it is)
+    pub conflicts: Vec<String>,
+    /// Pass as `cursor` to continue; null once every key has been scanned
+    pub next_cursor: Option<String>,
+}
+
+/// Copy one batch of keys after `cursor` that name a Solana pubkey in the
+/// clear under their hashed form. `kv` is the bare bucket, not a
+/// `HashedKeys` view of it.
+pub fn migrate_plaintext_batch(kv: &impl KvStore, pepper: &Pepper, cursor: Option<&str>, limit: Option<usize>) -> Result<KeyHashingReport> {
+    let limit = limit.unwrap_or(DEFAULT_MIGRATION_BATCH).clamp(1, MAX_MIGRATION_BATCH);
+    let keys = kv.list_keys(cursor, limit)?;
+    let next_cursor = if keys.len() < limit { None } else { keys.last().cloned() };
+
+    let plaintext: Vec<(String, String)> = keys
+        .iter()
+        .map(|key| (key.clone(), pepper.hashed_key(key)))
+        .filter(|(key, target)| key != target)
+        .collect();
+    let sources: Vec<String> = plaintext.iter().map(|(key, _)| key.clone()).collect();
+    let values = kv.get_many(&sources)?;
+    let mut report = KeyHashingReport { scanned: keys.len(), copied: 0, unchanged: 0, conflicts: Vec::new(), next_cursor };
+    for ((key, target), value) in plaintext.into_iter().zip(values) {
+        // Deleted since it was listed
+        let Some(value) = value else { continue };
+        for (user_key, solana_pubkey) in pepper.user_entries(&key) {
+            kv.set_if_absent(&user_key, solana_pubkey)?;
+        }
+        if kv.set_if_absent(&target, &value)? {
+            report.copied += 1;
+        } else if kv.get(&target)?.as_deref() == Some(value.as_str()) {
+            report.unchanged += 1;
+        } else {
+            report.conflicts.push(key);
+        }
+    }
+    Ok(report)
+}
@@ -0,0 +1,1353 @@
+//! Provisioning Flow
+//!
+//! `Provisioner` ties a `KvStore` and a `KeyCreator` together and implements
+//! the provision (batch creation) and update (admin or self-service,
+//! per-chain) flows, plus EVM → Solana provisioning. The KV side of each flow
+//! is in `mapping`; this adds key creation, the admin checks and auditing.
+
+use crate::address::{EvmAddress, SolanaPubkey};
+use crate::address_sanity;
+use crate::admin::{self, Requester};
+use crate::anonymize::{self, AnonymizeRequest, DeletionReceipt};
+use crate::approval::{self, PendingStatus, PendingUpdate};
+use crate::attestation::{self, AttestationSigner, SignedAttestation};
+use crate::audit::{self, AuditEvent, AuditQuery, AuditQueryResponse};
+use crate::events::{self, EventPage};
+use crate::expiry::{self, SweepReport, SweepRequest};
+use crate::merkle::{self, MerkleProof, MerkleRoot};
+use crate::quota::{self, MappingQuota};
+use crate::auth;
+use crate::authz;
+use crate::blocklist::{self, BlockEntry, BlockTarget};
+use crate::certificates::{self, MappingCertificate};
+use crate::chain_id::ChainId;
+use crate::chains::{self, ChainInfo};
+use crate::destinations::{self, AllowedDestinations};
+use crate::dry_run::{self, DryRunKv, DryRunResponse, PlaceholderKeys};
+use crate::evm_to_solana::{self, SolanaMappingValue};
+use crate::export::{self, ExportPage, ExportRequest};
+use crate::freeze::{self, FreezeEntry};
+use crate::idempotency;
+use crate::import::{self, ImportReport, ImportRequest};
+use crate::inflight;
+use crate::jobs::{self, JobError, JobStatus, ProvisionJob};
+use crate::keys::{self, CreatedKey, KeyCreator, KeyLister, SolanaKeyCreator};
+use crate::kv::{self, KvStore, MappingRecord};
+use crate::labels;
+use crate::logging::{self, Logger};
+use crate::mapping;
+use crate::network::{self, Network};
+use crate::metrics::{self, Stats};
+use crate::usage::{self, UsageReport};
+use crate::migrate::{self, MigrateRequest, MigrationReport};
+use crate::rate_limit::{self, RateLimit};
+use crate::reconcile::{self, ReconcileReport, ReconcileRequest};
+use crate::repair::{self, RepairReport, RepairRequest};
+use crate::retirement::{self, RetirementRecord};
+use crate::signing_gate::{self, SigningRequest};
+use crate::spend_limits::{self, SpendLimitStatus};
+use crate::verify::{self, VerifyReport, VerifyRequest};
+use crate::{
+    AllowedDestinationRequest, BlockRequest, EvmToSolanaProvisionRequest, EvmToSolanaProvisionResponse, FreezeRequest, GetMappingsResponse, LinkExternalRequest,
+    LinkExternalResponse, ListMappingsResponse, MappingHistoryResponse,
+    ProposeUpdateRequest, ProvisionBatchRequest, ProvisionBatchResponse, ProvisionRequest, ProvisionResponse, ResolveUpdateRequest,
+    RotateRequest, RotateResponse, SetChainRequest, SetSpendLimitRequest, UpdateBatchRequest, UpdateBatchResponse, UpdateMappingRequest, UpdateMappingResponse, UpdateSelfRequest,
+};
+use crate::error::{ProvisionError, Result};
+use ed25519_dalek::SigningKey;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+/// Source of the current Unix time in seconds
+pub type Clock = Box<dyn Fn() -> u64 + Send + Sync>;
+
+fn system_clock() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// Actor recorded for default mappings restored from CubeSigner by `handle_get`
+const KEY_RECOVERY_ACTOR: &str = "key-recovery";
+
+pub struct Provisioner<S, K> {
+    kv: S,
+    keys: K,
+    clock: Clock,
+    /// `admins` bucket. Admin actions require a requester it lists, and fail
+    /// with `NotConfigured` while it is unset.
+    admins: Option<Box<dyn KvStore + Send + Sync>>,
+    /// Whether one admin may rotate a key (`handle_update_mapping`,
+    /// `handle_rotate`) instead of proposing it for a second one's approval
+    single_step_updates: bool,
+    /// `evm_to_solana` bucket, required for EVM → Solana provisioning
+    evm_to_solana: Option<Box<dyn KvStore + Send + Sync>>,
+    /// `idempotency` bucket, required for requests with an `idempotency_key`
+    idempotency: Option<Box<dyn KvStore + Send + Sync>>,
+    /// `blocklist` bucket; when set, stores and updates are screened against it
+    blocklist: Option<Box<dyn KvStore + Send + Sync>>,
+    /// `rate_limits` bucket and the limit on stores and updates per Solana address
+    rate_limit: Option<(Box<dyn KvStore + Send + Sync>, RateLimit)>,
+    /// Chains and labels allowed per Solana address; no quota when unset
+    mapping_quota: Option<MappingQuota>,
+    /// `metrics` bucket; when set, provisions, updates and errors are counted in it
+    metrics: Option<Box<dyn KvStore + Send + Sync>>,
+    /// `usage` bucket; when set, key creations and mapping operations are counted per month
+    usage: Option<Box<dyn KvStore + Send + Sync>>,
+    /// Receives one event per mutating request (see `logging`)
+    logger: Option<Box<dyn Logger + Send + Sync>>,
+    /// Where `handle_get` looks for the default key of a user whose default mapping is lost
+    key_recovery: Option<Box<dyn KeyLister + Send + Sync>>,
+    /// Whether `handle_get` writes a mapping for chains that inherit the default
+    materialize_inherited: bool,
+    /// Chains stored for requests without `chain_ids`; empty: such requests fail
+    default_chain_ids: Vec<ChainId>,
+    /// Addresses never mapped, on top of the built-in ones (see `address_sanity`)
+    denied_addresses: Vec<EvmAddress>,
+    /// Whether program ids and off-curve Solana addresses may be provisioned
+    allow_program_pubkeys: bool,
+    /// Network whose chains the provisioner maps (see `network`)
+    network: Network,
+    /// Signs mapping attestations (see `attestation`)
+    attester: Option<Box<dyn AttestationSigner + Send + Sync>>,
+    /// Signs mapping certificates (see `certificates`)
+    certificate_key: Option<SigningKey>,
+}
+
+impl<S: KvStore, K: KeyCreator> Provisioner<S, K> {
+    pub fn new(kv: S, keys: K) -> Self {
+        Self {
+            kv,
+            keys,
+            clock: Box::new(system_clock),
+            admins: None,
+            single_step_updates: false,
+            evm_to_solana: None,
+            idempotency: None,
+            blocklist: None,
+            rate_limit: None,
+            mapping_quota: None,
+            metrics: None,
+            usage: None,
+            logger: None,
+            key_recovery: None,
+            materialize_inherited: false,
+            default_chain_ids: Vec::new(),
+            denied_addresses: Vec::new(),
+            allow_program_pubkeys: false,
+            network: Network::default(),
+            attester: None,
+            certificate_key: None,
+        }
+    }
+
+    /// Enforce the admin allowlist stored in `admins` (the `admins` bucket).
+    /// Without one, every admin action fails.
+    pub fn with_admins(mut self, admins: impl KvStore + Send + Sync + 'static) -> Self {
+        self.admins = Some(Box::new(admins));
+        self
+    }
+
+    /// Let one admin rotate a key with `handle_update_mapping` or
+    /// `handle_rotate`. Without this, those fail with `ApprovalRequired` and
+    /// updates go through `handle_propose_update` + `handle_approve_update`,
+    /// as in the policy.
+    pub fn with_single_step_updates(mut self) -> Self {
+        self.single_step_updates = true;
+        self
+    }
+
+    /// Store EVM → Solana mappings in `kv` (the `evm_to_solana` bucket)
+    pub fn with_evm_to_solana(mut self, kv: impl KvStore + Send + Sync + 'static) -> Self {
+        self.evm_to_solana = Some(Box::new(kv));
+        self
+    }
+
+    /// Record completed requests in `kv` (the `idempotency` bucket) so retries
+    /// carrying the same `idempotency_key` get the original response
+    pub fn with_idempotency(mut self, kv: impl KvStore + Send + Sync + 'static) -> Self {
+        self.idempotency = Some(Box::new(kv));
+        self
+    }
+
+    /// Screen stores and updates against `kv` (the `blocklist` bucket)
+    pub fn with_blocklist(mut self, kv: impl KvStore + Send + Sync + 'static) -> Self {
+        self.blocklist = Some(Box::new(kv));
+        self
+    }
+
+    /// Limit stores and updates per Solana address, counting in `kv` (the
+    /// `rate_limits` bucket)
+    pub fn with_rate_limit(mut self, kv: impl KvStore + Send + Sync + 'static, limit: RateLimit) -> Self {
+        self.rate_limit = Some((Box::new(kv), limit));
+        self
+    }
+
+    /// Limit the chains and labels each Solana address is mapped on (see `quota`)
+    pub fn with_mapping_quota(mut self, quota: MappingQuota) -> Self {
+        self.mapping_quota = Some(quota);
+        self
+    }
+
+    /// Count provisions, updates and errors in `kv` (the `metrics` bucket)
+    pub fn with_metrics(mut self, kv: impl KvStore + Send + Sync + 'static) -> Self {
+        self.metrics = Some(Box::new(kv));
+        self
+    }
+
+    /// Meter key creations and mapping operations per month in `kv` (the
+    /// `usage` bucket, see `usage`)
+    pub fn with_usage(mut self, kv: impl KvStore + Send + Sync + 'static) -> Self {
+        self.usage = Some(Box::new(kv));
+        self
+    }
+
+    /// Log each mutating request to `logger`, with its `request_id`
+    pub fn with_logger(mut self, logger: impl Logger + Send + Sync + 'static) -> Self {
+        self.logger = Some(Box::new(logger));
+        self
+    }
+
+    /// When `handle_get` finds no default mapping, look for the user's default
+    /// key (`EVM_{solana_pubkey}`) in `keys` and restore the mapping from it,
+    /// so a lost KV record heals on the next read
+    pub fn with_key_recovery(mut self, keys: impl KeyLister + Send + Sync + 'static) -> Self {
+        self.key_recovery = Some(Box::new(keys));
+        self
+    }
+
+    /// Give chains that inherit the default address their own mapping the
+    /// first time `handle_get` returns them (see `mapping::get_materialized`)
+    pub fn with_materialized_inheritance(mut self) -> Self {
+        self.materialize_inherited = true;
+        self
+    }
+
+    /// Store `chain_ids` for requests that name no chains (the policy reads
+    /// them from the `config` bucket, see `config`)
+    pub fn with_default_chains(mut self, chain_ids: Vec<ChainId>) -> Self {
+        self.default_chain_ids = chain_ids;
+        self
+    }
+
+    /// Refuse to link `evm_addresses` besides the built-in unusable ones (the
+    /// policy reads them from the `config` bucket, see `config`). Keys the
+    /// library creates itself are not checked.
+    pub fn with_denied_addresses(mut self, evm_addresses: Vec<EvmAddress>) -> Self {
+        self.denied_addresses = evm_addresses;
+        self
+    }
+
+    /// Provision Solana addresses that look like programs (see
+    /// `address_sanity`); the policy reads this from the `config` bucket
+    pub fn with_program_pubkeys_allowed(mut self) -> Self {
+        self.allow_program_pubkeys = true;
+        self
+    }
+
+    /// Map only `network`'s chains. The stores should be wrapped in
+    /// `network::Networked` for the same network, so its mappings stay apart
+    /// from the other network's; the policy reads the network from each request.
+    pub fn with_network(mut self, network: Network) -> Self {
+        self.network = network;
+        self
+    }
+
+    /// Sign mapping attestations (`handle_attest`) with `signer`
+    pub fn with_attester(mut self, signer: impl AttestationSigner + Send + Sync + 'static) -> Self {
+        self.attester = Some(Box::new(signer));
+        self
+    }
+
+    /// Sign mapping certificates (`handle_certificate`) with `key`
+    pub fn with_certificate_key(mut self, key: SigningKey) -> Self {
+        self.certificate_key = Some(key);
+        self
+    }
+
+    /// Replace the system clock (tests, deterministic replays)
+    pub fn with_clock(mut self, clock: impl Fn() -> u64 + Send + Sync + 'static) -> Self {
+        self.clock = Box::new(clock);
+        self
+    }
+
+    fn now(&self) -> u64 {
+        (self.clock)()
+    }
+
+    /// The underlying KV store
+    pub fn kv(&self) -> &S {
+        &self.kv
+    }
+
+    /// The underlying key creator
+    pub fn keys(&self) -> &K {
+        &self.keys
+    }
+
+    /// Run `f` and record its outcome in the audit log. The action fails if
+    /// the audit record cannot be written.
+    fn audited<T>(&self, action: &str, actor: &str, subject: &str, f: impl FnOnce() -> Result<T>) -> Result<T> {
+        let result = f();
+        let event = AuditEvent {
+            action: action.to_string(),
+            actor: actor.to_string(),
+            subject: Some(subject.to_string()),
+            outcome: result.as_ref().map(|_| ()).map_err(|e| e.to_string()),
+        };
+        audit::append(&self.kv, event, self.now())?;
+        if let Some(metrics) = &self.metrics {
+            // Best effort: a lost count must not fail the action
+            let _ = metrics::record_outcome(metrics, action, result.as_ref().map(|_| ()));
+        }
+        if let (Some(usage), Ok(_)) = (&self.usage, &result) {
+            let _ = usage::record_operation(usage, action, self.now());
+        }
+        result
+    }
+
+    /// Run `f` and log how long it took and how it ended (see `logging`);
+    /// without a logger, just run it
+    fn traced<T>(&self, action: &str, request_id: Option<&str>, solana_pubkey: Option<&str>, f: impl FnOnce() -> Result<T>) -> Result<T> {
+        let Some(logger) = &self.logger else {
+            return f();
+        };
+        let started = Instant::now();
+        let result = f();
+        logger.log(&logging::event(request_id, action, solana_pubkey, started, &result));
+        result
+    }
+
+    /// Run `f` once per idempotency key (see `idempotency`); without a key, just run it
+    fn idempotent<T: Serialize + DeserializeOwned>(
+        &self,
+        action: &str,
+        idempotency_key: Option<&str>,
+        request_hash: &str,
+        f: impl FnOnce() -> Result<T>,
+    ) -> Result<T> {
+        let Some(key) = idempotency_key else {
+            return f();
+        };
+        let kv = self.idempotency.as_ref().ok_or(ProvisionError::NotConfigured("Idempotency bucket"))?;
+        idempotency::run(kv, action, key, request_hash, self.now(), f)
+    }
+
+    /// Main provision handler - batch creation for multiple chains
+    pub fn handle(&self, req: ProvisionRequest) -> Result<ProvisionResponse> {
+        let solana_pubkey = req.solana_pubkey.to_string();
+        let request_id = req.request_id.clone();
+        self.traced("provision", request_id.as_deref(), Some(&solana_pubkey), || {
+            let idempotency_key = req.idempotency_key.clone();
+            let request_hash = idempotency::request_hash(&req);
+            let response = self.idempotent("provision", idempotency_key.as_deref(), &request_hash, || {
+                self.rate_limited_if_proven("provision", &req.solana_pubkey, self.store_proof(&req))?;
+                self.audited("provision", &solana_pubkey, &solana_pubkey, || self.provision(req))
+            })?;
+            // A replayed response may hold an address frozen since it was recorded
+            mapping::require_not_frozen(&self.kv, &response)?;
+            Ok(response)
+        })
+    }
+
+    fn provision(&self, req: ProvisionRequest) -> Result<ProvisionResponse> {
+        let req = self.authorized(&self.kv, req)?;
+        self.provision_with(&req, || Self::create_key(&self.keys, &req))
+    }
+
+    /// `provision`, taking the key from `create_key` if one is needed
+    fn provision_with(&self, req: &ProvisionRequest, create_key: impl FnOnce() -> Result<CreatedKey>) -> Result<ProvisionResponse> {
+        let counted = match (&self.metrics, labels::parse_label(req.label.as_deref())?) {
+            (Some(_), None) => Some(metrics::unmapped_chains(&self.kv, &req.solana_pubkey, &req.chain_ids)?),
+            _ => None,
+        };
+        let (response, new_wallet) = self.store_with(&self.kv, req, create_key)?;
+
+        if let (Some(metrics), Some(new_chains)) = (&self.metrics, counted) {
+            let _ = metrics::record_provision(metrics, new_wallet, &new_chains);
+        }
+        if let (Some(usage), true) = (&self.usage, new_wallet) {
+            let _ = usage::record_key_creation(usage, self.now());
+        }
+        Ok(response)
+    }
+
+    /// The store flow over `kv`, creating the key with `keys` if the user (or
+    /// label) has none yet. Returns whether it did.
+    fn store(&self, kv: &impl KvStore, keys: &impl KeyCreator, req: &ProvisionRequest) -> Result<(ProvisionResponse, bool)> {
+        self.store_with(kv, req, || Self::create_key(keys, req))
+    }
+
+    /// Create the EVM key `req` asks for (one per Solana address, or per label)
+    fn create_key(keys: &impl KeyCreator, req: &ProvisionRequest) -> Result<CreatedKey> {
+        match labels::parse_label(req.label.as_deref())? {
+            Some(label) => keys.create_labeled_evm_key(req.solana_pubkey.as_str(), label, None),
+            None => keys.create_typed_key(req.solana_pubkey.as_str(), req.key_type, req.key_class),
+        }
+    }
+
+    /// The store flow over `kv`, taking the key from `create_key` if the user
+    /// (or label) has none yet and no earlier attempt left one in flight.
+    /// Returns whether the user (or label) got its first key.
+    fn store_with(
+        &self,
+        kv: &impl KvStore,
+        req: &ProvisionRequest,
+        create_key: impl FnOnce() -> Result<CreatedKey>,
+    ) -> Result<(ProvisionResponse, bool)> {
+        let now = self.now();
+        address_sanity::check_solana_pubkey(&req.solana_pubkey, self.allow_program_pubkeys)?;
+        self.screen(&req.solana_pubkey, &[])?;
+        let label = labels::parse_label(req.label.as_deref())?;
+        network::require_chains(kv, self.network, &req.chain_ids)?;
+        self.within_quota(kv, &req.solana_pubkey, label, &req.chain_ids)?;
+        let mut new_wallet = false;
+
+        let response = mapping::store(kv, req, now, || {
+            new_wallet = true;
+            // A key an earlier attempt created but failed to map (see `inflight`)
+            let (key, address) = match self.inflight_key(kv, req, label)? {
+                Some(key) => {
+                    let address = EvmAddress::parse(&key.address)?;
+                    self.screen(&req.solana_pubkey, &[&address])?;
+                    (key, address)
+                }
+                None => {
+                    // Held until the key is recorded, so a concurrent provision waits for it
+                    let claim = inflight::claim(kv, &req.solana_pubkey, label, now)?;
+                    let created = self.create_unless_inflight(kv, req, label, now, create_key);
+                    let released = inflight::release(kv, &req.solana_pubkey, label, claim, now);
+                    let created = created?;
+                    released?;
+                    created
+                }
+            };
+            Ok(MappingRecord {
+                policies: key.policies,
+                ..MappingRecord::new(&address, Some(&key.key_id), req.solana_pubkey.as_str(), now)
+            })
+        })?;
+        Ok((response, new_wallet))
+    }
+
+    /// Key recorded in flight for `req` (see `inflight`), unless it is the key
+    /// of the expired default a store is replacing (see `expiry`). Only read
+    /// when the user (or label) needs a new key, so a default found here is
+    /// an expired one.
+    fn inflight_key(&self, kv: &impl KvStore, req: &ProvisionRequest, label: Option<&str>) -> Result<Option<CreatedKey>> {
+        let Some(key) = inflight::get(kv, &req.solana_pubkey, label, req.key_type, req.key_class)? else {
+            return Ok(None);
+        };
+        if label.is_none() {
+            let expired = kv::get_default_mapping(kv, &req.solana_pubkey)?;
+            if expired.is_some_and(|expired| expired.address.as_str() == key.address.to_lowercase()) {
+                return Ok(None);
+            }
+        }
+        Ok(Some(key))
+    }
+
+    /// Under a claim: the key a provision that held the previous claim
+    /// recorded, or else one from `create_key`, recorded
+    fn create_unless_inflight(
+        &self,
+        kv: &impl KvStore,
+        req: &ProvisionRequest,
+        label: Option<&str>,
+        now: u64,
+        create_key: impl FnOnce() -> Result<CreatedKey>,
+    ) -> Result<(CreatedKey, EvmAddress)> {
+        if let Some(key) = self.inflight_key(kv, req, label)? {
+            let address = EvmAddress::parse(&key.address)?;
+            self.screen(&req.solana_pubkey, &[&address])?;
+            return Ok((key, address));
+        }
+        let key = create_key()?;
+        let address = EvmAddress::parse(&key.address)?;
+        // A blocked key is never mapped, so not worth keeping
+        self.screen(&req.solana_pubkey, &[&address])?;
+        inflight::record(kv, &req.solana_pubkey, label, &key, req.key_type, req.key_class, now)?;
+        Ok((key, address))
+    }
+
+    /// Run `handle` as a dry run (see `dry_run`): every check, nothing written,
+    /// no key created
+    pub fn handle_dry_run(&self, req: ProvisionRequest) -> Result<DryRunResponse<ProvisionResponse>> {
+        let keys = PlaceholderKeys::default();
+        let mut response = dry_run::run(&self.kv, |kv| Ok(self.store(kv, &keys, &self.authorized(kv, req)?)?.0))?;
+        response.creates_key = keys.used();
+        Ok(response)
+    }
+
+    /// Submit `req` as a provisioning job (see `jobs`), returned `pending`.
+    /// Rate limited like `handle`; the idempotency key is not used, as the
+    /// job id already identifies the request.
+    pub fn handle_provision_async(&self, req: ProvisionRequest) -> Result<ProvisionJob> {
+        let solana_pubkey = req.solana_pubkey.to_string();
+        let request_id = req.request_id.clone();
+        self.traced("provision_async", request_id.as_deref(), Some(&solana_pubkey), || {
+            self.rate_limited_if_proven("provision_async", &req.solana_pubkey, self.store_proof(&req))?;
+            let req = self.authorized(&self.kv, req)?;
+            jobs::submit(&self.kv, req, self.now())
+        })
+    }
+
+    /// A provisioning job and how far it got
+    pub fn handle_job_status(&self, solana_pubkey: &SolanaPubkey, id: u64) -> Result<ProvisionJob> {
+        jobs::require(&self.kv, solana_pubkey, id)
+    }
+
+    /// Run job `id` from its last recorded step until it completes, fails, or
+    /// hits a retryable error (recorded on the job, for a later run to resume
+    /// from). Returns the job as it was left. Run a job from one worker at a
+    /// time: two concurrent runs of a `pending` job both create a key.
+    pub fn handle_run_job(&self, solana_pubkey: &SolanaPubkey, id: u64) -> Result<ProvisionJob> {
+        self.traced("run_job", None, Some(solana_pubkey.as_str()), || {
+            let mut job = jobs::require(&self.kv, solana_pubkey, id)?;
+            while !job.status.is_terminal() {
+                let step = self.run_job_step(&mut job);
+                job.updated_at = self.now();
+                job.error = step.as_ref().err().map(JobError::from);
+                match step {
+                    Ok(status) => job.status = status,
+                    Err(e) if e.is_retryable() => {
+                        jobs::save(&self.kv, &job)?;
+                        return Ok(job);
+                    }
+                    Err(_) => job.status = JobStatus::Failed,
+                }
+                jobs::save(&self.kv, &job)?;
+            }
+            Ok(job)
+        })
+    }
+
+    /// Run the step after `job.status`, returning the status it reaches
+    fn run_job_step(&self, job: &mut ProvisionJob) -> Result<JobStatus> {
+        let req = &job.request;
+        match job.status {
+            JobStatus::Pending => {
+                let has_key = match labels::parse_label(req.label.as_deref())? {
+                    Some(label) => labels::get_label_mapping(&self.kv, &req.solana_pubkey, label)?.is_some(),
+                    None => kv::get_default_mapping(&self.kv, &req.solana_pubkey)?.is_some(),
+                };
+                if !has_key {
+                    let key = Self::create_key(&self.keys, req)?;
+                    job.evm_address = Some(EvmAddress::parse(&key.address)?);
+                    job.key_id = Some(key.key_id);
+                    job.policies = key.policies;
+                }
+                Ok(JobStatus::KeyCreated)
+            }
+            JobStatus::KeyCreated => {
+                // The job's key, or a new one if another request mapped the
+                // user's key away since (not possible without a delete)
+                let created = job.evm_address.as_ref().zip(job.key_id.as_ref()).map(|(address, key_id)| CreatedKey {
+                    address: address.to_string(),
+                    key_id: key_id.clone(),
+                    policies: job.policies.clone(),
+                });
+                let solana_pubkey = req.solana_pubkey.to_string();
+                let response = self.audited("provision", &solana_pubkey, &solana_pubkey, || {
+                    self.provision_with(req, || match created {
+                        Some(key) => Ok(key),
+                        None => Self::create_key(&self.keys, req),
+                    })
+                })?;
+                job.response = Some(response);
+                Ok(JobStatus::Stored)
+            }
+            JobStatus::Stored => {
+                if let Some(response) = &job.response {
+                    mapping::require_not_frozen(&self.kv, response)?;
+                }
+                Ok(JobStatus::Complete)
+            }
+            JobStatus::Complete | JobStatus::Failed => Ok(job.status),
+        }
+    }
+
+    /// `req` once its address is usable and its ownership proof checks out
+    /// (see `mapping::authorize_store`), with the default chains if it names none
+    fn authorized(&self, kv: &impl KvStore, mut req: ProvisionRequest) -> Result<ProvisionRequest> {
+        address_sanity::check_solana_pubkey(&req.solana_pubkey, self.allow_program_pubkeys)?;
+        mapping::authorize_store(kv, &req, self.now())?;
+        if req.chain_ids.is_empty() {
+            req.chain_ids = self.default_chain_ids.clone();
+        }
+        Ok(req)
+    }
+
+    /// Check `req`'s address and ownership proof without burning its nonce
+    /// (see `mapping::verify_store`)
+    fn store_proof(&self, req: &ProvisionRequest) -> Result<u64> {
+        address_sanity::check_solana_pubkey(&req.solana_pubkey, self.allow_program_pubkeys)?;
+        mapping::verify_store(req, self.now())
+    }
+
+    /// Fail with `Blocked` if the blocklist has `solana_pubkey` or one of
+    /// `evm_addresses`; no screening without a blocklist bucket
+    fn screen(&self, solana_pubkey: &SolanaPubkey, evm_addresses: &[&EvmAddress]) -> Result<()> {
+        match &self.blocklist {
+            Some(blocklist) => blocklist::screen(blocklist, solana_pubkey, evm_addresses),
+            None => Ok(()),
+        }
+    }
+
+    /// Fail with `QuotaExceeded` if mapping `solana_pubkey` on `chain_ids`
+    /// (under `label`) would take it past its quota; no quota unless set
+    fn within_quota(&self, kv: &impl KvStore, solana_pubkey: &SolanaPubkey, label: Option<&str>, chain_ids: &[ChainId]) -> Result<()> {
+        match &self.mapping_quota {
+            Some(mapping_quota) => quota::check(kv, mapping_quota, solana_pubkey, label, chain_ids),
+            None => Ok(()),
+        }
+    }
+
+    /// Count a request against `solana_pubkey`'s rate limit; no limit without
+    /// a `rate_limits` bucket. Refused requests are not audited, so a retry
+    /// loop does not flood the audit log either.
+    fn rate_limited(&self, solana_pubkey: &SolanaPubkey) -> Result<()> {
+        let result = match &self.rate_limit {
+            Some((kv, limit)) => rate_limit::check(kv, limit, solana_pubkey, self.now()),
+            None => Ok(()),
+        };
+        if let (Err(e), Some(metrics)) = (&result, &self.metrics) {
+            let _ = metrics::record_error(metrics, e);
+        }
+        result
+    }
+
+    /// Count a request against `solana_pubkey`'s rate limit once `proof`, its
+    /// ownership proof checked without burning the nonce (see
+    /// `mapping::verify_store`), holds. A failed proof is audited under
+    /// `action` and counts nothing, so no one can use up another address's
+    /// budget with requests they cannot sign.
+    fn rate_limited_if_proven<T>(&self, action: &str, solana_pubkey: &SolanaPubkey, proof: Result<T>) -> Result<()> {
+        if let Err(e) = proof {
+            return self.audited(action, solana_pubkey.as_str(), solana_pubkey.as_str(), || Err(e));
+        }
+        self.rate_limited(solana_pubkey)
+    }
+
+    /// Batch provision handler - provisions each entry independently,
+    /// a failing entry does not abort the rest of the batch. Entries without
+    /// a `request_id` are logged under the batch's.
+    pub fn handle_batch(&self, req: ProvisionBatchRequest) -> Result<ProvisionBatchResponse> {
+        let request_id = req.request_id;
+        mapping::batch(req.requests, |entry| entry.solana_pubkey.clone(), |mut entry| {
+            entry.request_id = entry.request_id.or_else(|| request_id.clone());
+            self.handle(entry)
+        })
+    }
+
+    /// Run `handle_batch` as a dry run; later entries see what earlier ones
+    /// would have written
+    pub fn handle_dry_run_batch(&self, req: ProvisionBatchRequest) -> Result<DryRunResponse<ProvisionBatchResponse>> {
+        let keys = PlaceholderKeys::default();
+        let mut response = dry_run::run(&self.kv, |kv| {
+            mapping::batch(req.requests, |entry| entry.solana_pubkey.clone(), |entry| {
+                Ok(self.store(kv, &keys, &self.authorized(kv, entry)?)?.0)
+            })
+        })?;
+        response.creates_key = keys.used();
+        Ok(response)
+    }
+
+    /// Admin-only update handler - creates NEW wallet for specific chain.
+    /// Single-step, so only available `with_single_step_updates`; otherwise
+    /// use `handle_propose_update` + `handle_approve_update`.
+    pub fn handle_update_mapping(&self, requester: &Requester, req: UpdateMappingRequest) -> Result<UpdateMappingResponse> {
+        let solana_pubkey = req.solana_pubkey.to_string();
+        let idempotency_key = req.idempotency_key.clone();
+        let request_id = req.request_id.clone();
+        let request_hash = idempotency::request_hash(&req);
+        self.traced("update", request_id.as_deref(), Some(&solana_pubkey), || {
+            self.authorize_audited("update", requester, &solana_pubkey)?;
+            self.idempotent("update", idempotency_key.as_deref(), &request_hash, || {
+                self.rate_limited(&req.solana_pubkey)?;
+                self.audited("update", requester.name(), &solana_pubkey, || self.update_mapping(&self.kv, &self.keys, requester, req))
+            })
+        })
+    }
+
+    /// Run `handle_update_mapping` on every entry. With `all_or_nothing` the
+    /// batch is dry-run first, and nothing is written (`applied: false`)
+    /// unless every entry would succeed; a KV failure during the real run can
+    /// still leave it half applied.
+    pub fn handle_update_batch(&self, requester: &Requester, req: UpdateBatchRequest) -> Result<UpdateBatchResponse<UpdateMappingResponse>> {
+        self.authorize_audited("update_batch", requester, "")?;
+        let target = |entry: &UpdateMappingRequest| (entry.solana_pubkey.clone(), entry.chain_id.clone());
+        if req.all_or_nothing {
+            let keys = PlaceholderKeys::default();
+            // The rate limit and idempotency keys are checked as in the real
+            // run, against dry runs of their buckets so the preview counts
+            // and records nothing
+            let rate_limit = self.rate_limit.as_ref().map(|(bucket, limit)| (DryRunKv::new(&**bucket), *limit));
+            let idempotency = self.idempotency.as_deref().map(DryRunKv::new);
+            let preview = dry_run::run(&self.kv, |kv| {
+                mapping::update_batch(req.updates.clone(), target, |entry| {
+                    let idempotency_key = entry.idempotency_key.clone();
+                    let request_hash = idempotency::request_hash(&entry);
+                    let update = || {
+                        if let Some((bucket, limit)) = &rate_limit {
+                            rate_limit::check(bucket, limit, &entry.solana_pubkey, self.now())?;
+                        }
+                        self.update_mapping(kv, &keys, requester, entry)
+                    };
+                    match (idempotency_key, &idempotency) {
+                        (None, _) => update(),
+                        (Some(key), Some(bucket)) => idempotency::run(bucket, "update", &key, &request_hash, self.now(), update),
+                        (Some(_), None) => Err(ProvisionError::NotConfigured("Idempotency bucket")),
+                    }
+                })
+            })?;
+            if preview.result.failed > 0 {
+                return Ok(UpdateBatchResponse { applied: false, ..preview.result });
+            }
+        }
+
+        let request_id = req.request_id;
+        mapping::update_batch(req.updates, target, |mut entry| {
+            entry.request_id = entry.request_id.or_else(|| request_id.clone());
+            self.handle_update_mapping(requester, entry)
+        })
+    }
+
+    /// Run `handle_update_mapping` as a dry run (see `dry_run`)
+    pub fn handle_dry_run_update(&self, requester: &Requester, req: UpdateMappingRequest) -> Result<DryRunResponse<UpdateMappingResponse>> {
+        let keys = PlaceholderKeys::default();
+        let mut response = dry_run::run(&self.kv, |kv| self.update_mapping(kv, &keys, requester, req))?;
+        response.creates_key = keys.used();
+        Ok(response)
+    }
+
+    fn update_mapping(&self, kv: &impl KvStore, keys: &impl KeyCreator, requester: &Requester, req: UpdateMappingRequest) -> Result<UpdateMappingResponse> {
+        self.authorize(requester, "update")?;
+        if !self.single_step_updates {
+            return Err(ProvisionError::ApprovalRequired);
+        }
+        let actor = requester.name();
+        if let Some(label) = labels::parse_label(req.label.as_deref())? {
+            return self.rotate_labeled_key(kv, keys, &req.solana_pubkey, label, &req.chain_id, req.expected_version, actor);
+        }
+        self.rotate_chain_key(kv, keys, &req.solana_pubkey, &req.chain_id, req.expected_version, None, actor)
+    }
+
+    /// Admin-only key rotation - like `handle_update_mapping`, but the old
+    /// address's retirement record says why it was retired. Returns it along
+    /// with the new mapping.
+    pub fn handle_rotate(&self, requester: &Requester, req: RotateRequest) -> Result<RotateResponse> {
+        let solana_pubkey = req.solana_pubkey.to_string();
+        let idempotency_key = req.idempotency_key.clone();
+        let request_id = req.request_id.clone();
+        let request_hash = idempotency::request_hash(&req);
+        self.traced("rotate", request_id.as_deref(), Some(&solana_pubkey), || {
+            self.authorize_audited("rotate", requester, &solana_pubkey)?;
+            self.idempotent("rotate", idempotency_key.as_deref(), &request_hash, || {
+                self.rate_limited(&req.solana_pubkey)?;
+                self.audited("rotate", requester.name(), &solana_pubkey, || self.rotate(req, requester.name()))
+            })
+        })
+    }
+
+    fn rotate(&self, req: RotateRequest, actor: &str) -> Result<RotateResponse> {
+        if !self.single_step_updates {
+            return Err(ProvisionError::ApprovalRequired);
+        }
+        let reason = req.reason.trim();
+        if reason.is_empty() {
+            return Err(ProvisionError::InvalidRequest("rotation reason must not be empty".to_string()));
+        }
+
+        let previous = kv::get_existing_mapping(&self.kv, &req.solana_pubkey, &req.chain_id)?;
+        let update = self.rotate_chain_key(&self.kv, &self.keys, &req.solana_pubkey, &req.chain_id, req.expected_version, Some(reason), actor)?;
+        let retired = match previous {
+            Some(previous) => retirement::get_retirement(&self.kv, &previous)?,
+            None => None,
+        };
+        Ok(RotateResponse { update, retired })
+    }
+
+    /// First phase of an admin update: record a pending update for the chain
+    pub fn handle_propose_update(&self, requester: &Requester, req: ProposeUpdateRequest) -> Result<PendingUpdate> {
+        let solana_pubkey = req.solana_pubkey.to_string();
+        self.traced("propose_update", req.request_id.as_deref(), Some(&solana_pubkey), || {
+            self.audited("propose_update", requester.name(), &solana_pubkey, || {
+                self.authorize(requester, "propose_update")?;
+                mapping::require_provisioned(&self.kv, &req.solana_pubkey)?;
+                approval::propose(&self.kv, &req.solana_pubkey, &req.chain_id, None, None, false, requester.name(), self.now())
+            })
+        })
+    }
+
+    /// Second phase of an admin update: a different admin approves, then the
+    /// chain's key is rotated
+    pub fn handle_approve_update(&self, requester: &Requester, req: ResolveUpdateRequest) -> Result<UpdateMappingResponse> {
+        let solana_pubkey = req.solana_pubkey.to_string();
+        self.traced("approve_update", req.request_id.as_deref(), Some(&solana_pubkey), || {
+            self.audited("approve_update", requester.name(), &solana_pubkey, || {
+                self.authorize(requester, "approve_update")?;
+                approval::resolve(
+                    &self.kv,
+                    &req.solana_pubkey,
+                    &req.chain_id,
+                    req.proposal_id,
+                    requester.name(),
+                    PendingStatus::Approved,
+                    self.now(),
+                )?;
+                self.rotate_chain_key(&self.kv, &self.keys, &req.solana_pubkey, &req.chain_id, None, None, requester.name())
+            })
+        })
+    }
+
+    /// Discard a pending update without touching the mapping
+    pub fn handle_reject_update(&self, requester: &Requester, req: ResolveUpdateRequest) -> Result<PendingUpdate> {
+        let solana_pubkey = req.solana_pubkey.to_string();
+        self.traced("reject_update", req.request_id.as_deref(), Some(&solana_pubkey), || {
+            self.audited("reject_update", requester.name(), &solana_pubkey, || {
+                self.authorize(requester, "reject_update")?;
+                approval::resolve(
+                    &self.kv,
+                    &req.solana_pubkey,
+                    &req.chain_id,
+                    req.proposal_id,
+                    requester.name(),
+                    PendingStatus::Rejected,
+                    self.now(),
+                )
+            })
+        })
+    }
+
+    /// Latest proposal for a chain (open, resolved or expired)
+    pub fn handle_pending(&self, solana_pubkey: &SolanaPubkey, chain_id: &ChainId) -> Result<Option<PendingUpdate>> {
+        approval::get_pending(&self.kv, solana_pubkey, chain_id)
+    }
+
+    /// Fail unless `requester` holds the role `action` requires
+    /// (`authz::MATRIX`), as the policy checks it. The admin allowlist
+    /// decides, so without one admin actions fail closed.
+    fn authorize(&self, requester: &Requester, action: &str) -> Result<()> {
+        let admins = self.admins.as_ref().ok_or(ProvisionError::NotConfigured("Admin allowlist"))?;
+        authz::authorize(admins, requester, action)
+    }
+
+    /// `authorize`, auditing a refusal. Checked before rate limits and
+    /// idempotency keys, so a refused requester uses up neither.
+    fn authorize_audited(&self, action: &str, requester: &Requester, subject: &str) -> Result<()> {
+        self.authorize(requester, action).or_else(|e| self.audited(action, requester.name(), subject, || Err(e)))
+    }
+
+    /// Self-service update handler - the owner of the Solana address rotates
+    /// one chain's key by signing `auth::update_self_message`
+    pub fn handle_update_self(&self, req: UpdateSelfRequest) -> Result<UpdateMappingResponse> {
+        let solana_pubkey = req.solana_pubkey.to_string();
+        let request_id = req.request_id.clone();
+        self.traced("update_self", request_id.as_deref(), Some(&solana_pubkey), || {
+            let message = auth::update_self_message(&req.solana_pubkey, &req.chain_id, &req.nonce, req.expires_at);
+            let proof = mapping::verify_update_self(&req.solana_pubkey, &message, &req.nonce, req.expires_at, &req.signature, self.now());
+            self.rate_limited_if_proven("update_self", &req.solana_pubkey, proof)?;
+            self.audited("update_self", &solana_pubkey, &solana_pubkey, || self.update_self(req))
+        })
+    }
+
+    /// Link an EVM address the user already controls (see `mapping::link_external`)
+    pub fn handle_link_external(&self, req: LinkExternalRequest) -> Result<LinkExternalResponse> {
+        let solana_pubkey = req.solana_pubkey.to_string();
+        self.traced("link_external", req.request_id.as_deref(), Some(&solana_pubkey), || {
+            self.rate_limited_if_proven("link_external", &req.solana_pubkey, address_sanity::check(&req.evm_address, &self.denied_addresses).and_then(|()| mapping::verify_link_external(&req, self.now())))?;
+            self.audited("link_external", &solana_pubkey, &solana_pubkey, || {
+                address_sanity::check(&req.evm_address, &self.denied_addresses)?;
+                self.screen(&req.solana_pubkey, &[&req.evm_address])?;
+                network::require_chains(&self.kv, self.network, &req.chain_ids)?;
+                self.within_quota(&self.kv, &req.solana_pubkey, None, &req.chain_ids)?;
+                let counted = match &self.metrics {
+                    Some(_) => Some(metrics::custodial_chains(&self.kv, &req.solana_pubkey, &req.chain_ids)?),
+                    None => None,
+                };
+                let response = mapping::link_external(&self.kv, &req, self.now())?;
+
+                if let (Some(metrics), Some(linked)) = (&self.metrics, counted) {
+                    let _ = metrics::record_external_links(metrics, &linked);
+                }
+                Ok(response)
+            })
+        })
+    }
+
+    fn update_self(&self, req: UpdateSelfRequest) -> Result<UpdateMappingResponse> {
+        let message = auth::update_self_message(&req.solana_pubkey, &req.chain_id, &req.nonce, req.expires_at);
+        mapping::authorize_update_self(
+            &self.kv,
+            &req.solana_pubkey,
+            &message,
+            &req.nonce,
+            req.expires_at,
+            &req.signature,
+            self.now(),
+        )?;
+
+        self.rotate_chain_key(&self.kv, &self.keys, &req.solana_pubkey, &req.chain_id, None, None, req.solana_pubkey.as_str())
+    }
+
+    /// Create a new chain-specific key and make it the chain's mapping,
+    /// keeping the replaced value in the chain's history and retiring it with `reason`
+    #[allow(clippy::too_many_arguments)]
+    fn rotate_chain_key(
+        &self,
+        kv: &impl KvStore,
+        keys: &impl KeyCreator,
+        solana_pubkey: &SolanaPubkey,
+        chain_id: &ChainId,
+        expected_version: Option<u64>,
+        reason: Option<&str>,
+        actor: &str,
+    ) -> Result<UpdateMappingResponse> {
+        // 1. Verify Solana address has been provisioned and the mapping is
+        //    at the expected version, before spending a key on it
+        mapping::require_provisioned(kv, solana_pubkey)?;
+        mapping::check_version(kv, solana_pubkey, chain_id, expected_version)?;
+        network::require_chains(kv, self.network, std::slice::from_ref(chain_id))?;
+        self.within_quota(kv, solana_pubkey, None, std::slice::from_ref(chain_id))?;
+        self.screen(solana_pubkey, &[])?;
+
+        // 2. Create NEW EVM key (chain-specific)
+        let key = keys.create_evm_key_for_chain(solana_pubkey.as_str(), chain_id)?;
+        let address = EvmAddress::parse(&key.address)?;
+        self.screen(solana_pubkey, &[&address])?;
+
+        // 3. Update the chain-specific mapping (allows overwrite)
+        let value = MappingRecord {
+            policies: key.policies,
+            ..MappingRecord::new(&address, Some(&key.key_id), actor, self.now())
+        };
+        let stored = mapping::apply_update(kv, solana_pubkey, chain_id, &value, expected_version, reason, actor, self.now())?;
+
+        Ok(UpdateMappingResponse {
+            success: true,
+            new_evm_address: address,
+            new_key_id: key.key_id,
+            chain_id: chain_id.clone(),
+            version: stored.revision,
+        })
+    }
+
+    /// Create a new key for a labeled address on one chain and point the
+    /// label's mapping for the chain at it
+    #[allow(clippy::too_many_arguments)]
+    fn rotate_labeled_key(
+        &self,
+        kv: &impl KvStore,
+        keys: &impl KeyCreator,
+        solana_pubkey: &SolanaPubkey,
+        label: &str,
+        chain_id: &ChainId,
+        expected_version: Option<u64>,
+        actor: &str,
+    ) -> Result<UpdateMappingResponse> {
+        mapping::require_provisioned(kv, solana_pubkey)?;
+        mapping::check_labeled_version(kv, solana_pubkey, label, chain_id, expected_version)?;
+        network::require_chains(kv, self.network, std::slice::from_ref(chain_id))?;
+        self.screen(solana_pubkey, &[])?;
+
+        let key = keys.create_labeled_evm_key(solana_pubkey.as_str(), label, Some(chain_id))?;
+        let address = EvmAddress::parse(&key.address)?;
+        self.screen(solana_pubkey, &[&address])?;
+
+        let value = MappingRecord {
+            policies: key.policies,
+            ..MappingRecord::new(&address, Some(&key.key_id), actor, self.now())
+        };
+        let stored = mapping::apply_labeled_update(kv, solana_pubkey, label, chain_id, &value, expected_version, actor, self.now())?;
+
+        Ok(UpdateMappingResponse {
+            success: true,
+            new_evm_address: address,
+            new_key_id: key.key_id,
+            chain_id: chain_id.clone(),
+            version: stored.revision,
+        })
+    }
+
+    /// Add (`active: true`) or remove an admin - org owners only
+    pub fn handle_set_admin(&self, requester: &Requester, identity: &str, active: bool) -> Result<()> {
+        let action = if active { "add_admin" } else { "remove_admin" };
+        self.traced(action, None, None, || {
+            self.audited(action, &requester.identity, identity, || {
+                self.authorize(requester, action)?;
+                let admins = self.admins.as_ref().ok_or(ProvisionError::NotConfigured("Admin allowlist"))?;
+                admin::set_admin(admins, requester, identity, active, self.now())
+            })
+        })
+    }
+
+    /// Enable or disable a chain, or register a new one - admin only
+    pub fn handle_set_chain(&self, requester: &Requester, req: SetChainRequest) -> Result<ChainInfo> {
+        let action = if req.enabled { "enable_chain" } else { "disable_chain" };
+        self.traced(action, req.request_id.as_deref(), None, || {
+            self.audited(action, requester.name(), req.chain_id.as_str(), || {
+                self.authorize(requester, "set_chain")?;
+                chains::set_chain(&self.kv, &req.chain_id, req.enabled, req.name.as_deref(), req.testnet, requester.name(), self.now())
+            })
+        })
+    }
+
+    /// Freeze an EVM address so it is no longer handed out - admin only
+    pub fn handle_freeze(&self, requester: &Requester, req: FreezeRequest) -> Result<FreezeEntry> {
+        self.set_frozen(requester, req, true)
+    }
+
+    /// Lift a freeze - admin only
+    pub fn handle_unfreeze(&self, requester: &Requester, req: FreezeRequest) -> Result<FreezeEntry> {
+        self.set_frozen(requester, req, false)
+    }
+
+    fn set_frozen(&self, requester: &Requester, req: FreezeRequest, frozen: bool) -> Result<FreezeEntry> {
+        let action = if frozen { "freeze" } else { "unfreeze" };
+        let reason = req.reason.filter(|_| frozen);
+        self.traced(action, req.request_id.as_deref(), None, || {
+            self.audited(action, requester.name(), req.evm_address.as_str(), || {
+                self.authorize(requester, action)?;
+                freeze::set_frozen(&self.kv, &req.evm_address, frozen, reason.as_deref(), requester.name(), self.now())
+            })
+        })
+    }
+
+    /// Set or clear a user's spending limit - admin only
+    pub fn handle_set_spend_limit(&self, requester: &Requester, req: SetSpendLimitRequest) -> Result<MappingRecord> {
+        self.traced("set_spend_limit", req.request_id.as_deref(), Some(req.solana_pubkey.as_str()), || {
+            self.audited("set_spend_limit", requester.name(), req.solana_pubkey.as_str(), || {
+                self.authorize(requester, "set_spend_limit")?;
+                spend_limits::set_spend_limit(&self.kv, &req.solana_pubkey, req.chain_id.as_ref(), req.spend_limit.clone())
+            })
+        })
+    }
+
+    /// Spending limit in force on a chain (the default's without one), and
+    /// what the user signed there today
+    pub fn handle_get_spend_limit(&self, solana_pubkey: &SolanaPubkey, chain_id: Option<&ChainId>) -> Result<SpendLimitStatus> {
+        spend_limits::status(&self.kv, solana_pubkey, chain_id, self.now())
+    }
+
+    /// Allow a user's wallet on a chain to send to a destination, restricting
+    /// the chain to its allowlist - admin only
+    pub fn handle_add_allowed_destination(&self, requester: &Requester, req: AllowedDestinationRequest) -> Result<AllowedDestinations> {
+        self.set_allowed_destination(requester, req, true)
+    }
+
+    /// Take a destination off a user's allowlist - admin only
+    pub fn handle_remove_allowed_destination(&self, requester: &Requester, req: AllowedDestinationRequest) -> Result<AllowedDestinations> {
+        self.set_allowed_destination(requester, req, false)
+    }
+
+    fn set_allowed_destination(&self, requester: &Requester, req: AllowedDestinationRequest, allowed: bool) -> Result<AllowedDestinations> {
+        let action = if allowed { "add_allowed_destination" } else { "remove_allowed_destination" };
+        self.traced(action, req.request_id.as_deref(), Some(req.solana_pubkey.as_str()), || {
+            self.audited(action, requester.name(), req.solana_pubkey.as_str(), || {
+                self.authorize(requester, action)?;
+                if allowed {
+                    destinations::add_allowed(&self.kv, &req.solana_pubkey, &req.chain_id, &req.destination)
+                } else {
+                    destinations::remove_allowed(&self.kv, &req.solana_pubkey, &req.chain_id, &req.destination)
+                }
+            })
+        })
+    }
+
+    /// Whether an EVM address is frozen, with who froze it and why
+    pub fn handle_get_freeze(&self, evm_address: &EvmAddress) -> Result<Option<FreezeEntry>> {
+        freeze::get_freeze(&self.kv, evm_address)
+    }
+
+    /// Provisioning counters (see `metrics`)
+    pub fn handle_stats(&self) -> Result<Stats> {
+        let kv = self.metrics.as_ref().ok_or(ProvisionError::NotConfigured("Metrics bucket"))?;
+        metrics::get_stats(kv)
+    }
+
+    /// Billable counters of `month`, `YYYY-MM` (see `usage`)
+    pub fn handle_usage_report(&self, month: &str) -> Result<UsageReport> {
+        let kv = self.usage.as_ref().ok_or(ProvisionError::NotConfigured("Usage bucket"))?;
+        usage::get_usage(kv, month)
+    }
+
+    /// Where a retired address went, and why
+    pub fn handle_get_retirement(&self, evm_address: &EvmAddress) -> Result<Option<RetirementRecord>> {
+        retirement::get_retirement(&self.kv, evm_address)
+    }
+
+    /// Put an address on the blocklist - org owners only
+    pub fn handle_block(&self, requester: &Requester, req: BlockRequest) -> Result<BlockEntry> {
+        self.set_blocked(requester, req, true)
+    }
+
+    /// Take an address off the blocklist - org owners only
+    pub fn handle_unblock(&self, requester: &Requester, req: BlockRequest) -> Result<BlockEntry> {
+        self.set_blocked(requester, req, false)
+    }
+
+    fn set_blocked(&self, requester: &Requester, req: BlockRequest, blocked: bool) -> Result<BlockEntry> {
+        let action = if blocked { "block" } else { "unblock" };
+        let reason = req.reason.filter(|_| blocked);
+        // Blocked Solana addresses are hashed like any other
+        let solana_pubkey = match &req.target {
+            BlockTarget::SolanaPubkey(solana_pubkey) => Some(solana_pubkey.as_str()),
+            BlockTarget::EvmAddress(_) => None,
+        };
+        self.traced(action, req.request_id.as_deref(), solana_pubkey, || {
+            self.audited(action, requester.name(), &req.target.key(), || {
+                self.authorize(requester, action)?;
+                let blocklist = self.blocklist.as_ref().ok_or(ProvisionError::NotConfigured("Blocklist"))?;
+                blocklist::set_blocked(blocklist, &req.target, blocked, reason.as_deref(), requester.name(), self.now())
+            })
+        })
+    }
+
+    /// Blocklist entry of an address, `None` if it was never blocked
+    pub fn handle_get_block(&self, target: &BlockTarget) -> Result<Option<BlockEntry>> {
+        let blocklist = self.blocklist.as_ref().ok_or(ProvisionError::NotConfigured("Blocklist"))?;
+        blocklist::get_block(blocklist, target)
+    }
+
+    /// Rewrite one batch of outdated mapping records - admin only.
+    /// Call again with `next_cursor` until it is `None`.
+    pub fn handle_migrate(&self, requester: &Requester, req: MigrateRequest) -> Result<MigrationReport> {
+        let subject = req.cursor.clone().unwrap_or_default();
+        self.traced("migrate", req.request_id.as_deref(), None, || {
+            self.audited("migrate", requester.name(), &subject, || {
+                self.authorize(requester, "migrate")?;
+                migrate::migrate_batch(&self.kv, req.cursor.as_deref(), req.limit)
+            })
+        })
+    }
+
+    /// Erase a user's mappings, leaving a salted pseudonym (see `anonymize`) -
+    /// admin only. Audited and logged under the pseudonym, never the address.
+    pub fn handle_anonymize(&self, requester: &Requester, req: AnonymizeRequest) -> Result<DeletionReceipt> {
+        let pseudonym = anonymize::pseudonym(&req.solana_pubkey, &req.salt);
+        self.traced("anonymize", req.request_id.as_deref(), None, || {
+            self.audited("anonymize", requester.name(), &pseudonym, || {
+                self.authorize(requester, "anonymize")?;
+                anonymize::anonymize(&self.kv, &req.solana_pubkey, &req.salt, requester.name(), self.now())
+            })
+        })
+    }
+
+    /// Remove the expired temporary mappings among one batch of keys - admin
+    /// only. Call again with `next_cursor` until it is `None`. Needs a store
+    /// that can delete keys (see `expiry`).
+    pub fn handle_sweep(&self, requester: &Requester, req: SweepRequest) -> Result<SweepReport> {
+        let subject = req.cursor.clone().unwrap_or_default();
+        self.traced("sweep", req.request_id.as_deref(), None, || {
+            self.audited("sweep", requester.name(), &subject, || {
+                self.authorize(requester, "sweep")?;
+                expiry::sweep_batch(&self.kv, req.cursor.as_deref(), req.limit, self.now())
+            })
+        })
+    }
+
+    /// Export one page of the mappings bucket - admin only. Call again with
+    /// `next_cursor` until it is `None`. A read: not audited, so exporting
+    /// does not write to the bucket being exported.
+    pub fn handle_export(&self, requester: &Requester, req: ExportRequest) -> Result<ExportPage> {
+        self.traced("export", req.request_id.as_deref(), None, || {
+            self.authorize(requester, "export")?;
+            export::export_page(&self.kv, req.cursor.as_deref(), req.limit)
+        })
+    }
+
+    /// Check one batch of the mappings bucket for inconsistent records -
+    /// admin only. Call again with `next_cursor` until it is `None`. Only
+    /// reports, so it is not audited.
+    pub fn handle_verify(&self, requester: &Requester, req: VerifyRequest) -> Result<VerifyReport> {
+        self.traced("verify", req.request_id.as_deref(), None, || {
+            self.authorize(requester, "verify")?;
+            verify::verify_batch(&self.kv, req.cursor.as_deref(), req.limit)
+        })
+    }
+
+    /// Import a batch of exported entries - admin only. Dry runs write
+    /// nothing and are not audited.
+    pub fn handle_import(&self, requester: &Requester, req: ImportRequest) -> Result<ImportReport> {
+        let subject = req.entries.first().map(|entry| entry.key.clone()).unwrap_or_default();
+        self.traced("import", req.request_id.as_deref(), None, || {
+            let run = || {
+                self.authorize(requester, "import")?;
+                import::import_batch(&self.kv, &req)
+            };
+            if req.dry_run {
+                run()
+            } else {
+                self.audited("import", requester.name(), &subject, run)
+            }
+        })
+    }
+
+    /// Fix violations found by `handle_verify`, by id - admin only. Dry runs
+    /// write nothing and are not audited.
+    pub fn handle_repair(&self, requester: &Requester, req: RepairRequest) -> Result<RepairReport> {
+        let subject = req.ids.first().cloned().unwrap_or_default();
+        self.traced("repair", req.request_id.as_deref(), None, || {
+            let run = || {
+                self.authorize(requester, "repair")?;
+                repair::repair_batch(&self.kv, &req)
+            };
+            if req.dry_run {
+                run()
+            } else {
+                self.audited("repair", requester.name(), &subject, run)
+            }
+        })
+    }
+
+    /// Every chain in the registry, enabled or not
+    pub fn handle_chains(&self) -> Result<Vec<ChainInfo>> {
+        chains::list_chains(&self.kv)
+    }
+
+    /// EIP-712 attestation of the current mapping of `solana_pubkey` on `chain_id`
+    pub fn handle_attest(&self, solana_pubkey: &SolanaPubkey, chain_id: &ChainId) -> Result<SignedAttestation> {
+        let signer = self.attester.as_ref().ok_or(ProvisionError::NotConfigured("Attestation signer"))?;
+        attestation::attest(&self.kv, signer.as_ref(), solana_pubkey, chain_id, self.now())
+    }
+
+    /// Short-lived JWT asserting the current mapping of `solana_pubkey` on `chain_id`
+    pub fn handle_certificate(&self, solana_pubkey: &SolanaPubkey, chain_id: &ChainId, ttl: Option<u64>) -> Result<MappingCertificate> {
+        let key = self.certificate_key.as_ref().ok_or(ProvisionError::NotConfigured("Certificate key"))?;
+        certificates::issue(&self.kv, key, solana_pubkey, chain_id, ttl, self.now())
+    }
+
+    /// Default mapping and the mappings of the requested chains, which
+    /// inherit the default when they have none of their own
+    pub fn handle_get(&self, solana_pubkey: &SolanaPubkey, chain_ids: &[ChainId]) -> Result<GetMappingsResponse> {
+        let response = self.get(solana_pubkey, chain_ids)?;
+        if response.default_address.is_none() && self.recover_default(solana_pubkey)? {
+            return self.get(solana_pubkey, chain_ids);
+        }
+        Ok(response)
+    }
+
+    fn get(&self, solana_pubkey: &SolanaPubkey, chain_ids: &[ChainId]) -> Result<GetMappingsResponse> {
+        if self.materialize_inherited {
+            return mapping::get_materialized(&self.kv, solana_pubkey, chain_ids, self.now());
+        }
+        mapping::get(&self.kv, solana_pubkey, chain_ids, self.now())
+    }
+
+    /// Restore the default mapping from the user's default key in CubeSigner,
+    /// if key recovery is on and the key exists. Returns whether it did.
+    fn recover_default(&self, solana_pubkey: &SolanaPubkey) -> Result<bool> {
+        let Some(lister) = &self.key_recovery else {
+            return Ok(false);
+        };
+        let Some(key) = lister.find_evm_key(&keys::default_key_name(solana_pubkey.as_str()))? else {
+            return Ok(false);
+        };
+        self.audited("recover_default", KEY_RECOVERY_ACTOR, solana_pubkey.as_str(), || {
+            let address = EvmAddress::parse(&key.address)?;
+            reconcile::restore_default(&self.kv, solana_pubkey, &address, &key.key_id, KEY_RECOVERY_ACTOR, self.now())
+        })
+    }
+
+    /// Fail with `AddressNotMapped` unless the signing key is a current
+    /// mapping of the user, or if the transaction breaks their spending limit
+    /// (see `signing_gate`)
+    pub fn handle_authorize_signing(&self, req: &SigningRequest) -> Result<()> {
+        signing_gate::authorize(&self.kv, req, self.now())
+    }
+
+    /// List every chain mapping for a Solana address, using its chain index
+    pub fn handle_list(&self, solana_pubkey: &SolanaPubkey) -> Result<ListMappingsResponse> {
+        mapping::list(&self.kv, solana_pubkey)
+    }
+
+    /// History of a chain mapping: every address it held before the current one
+    pub fn handle_history(&self, solana_pubkey: &SolanaPubkey, chain_id: &ChainId) -> Result<MappingHistoryResponse> {
+        mapping::history(&self.kv, solana_pubkey, chain_id)
+    }
+
+    /// History of a labeled address on one chain (see `handle_history`)
+    pub fn handle_labeled_history(&self, solana_pubkey: &SolanaPubkey, label: &str, chain_id: &ChainId) -> Result<MappingHistoryResponse> {
+        mapping::labeled_history(&self.kv, solana_pubkey, label, chain_id)
+    }
+
+    /// Reverse lookup - which Solana address owns this EVM address
+    pub fn handle_reverse_get(&self, evm_address: &EvmAddress) -> Result<Option<SolanaPubkey>> {
+        kv::get_reverse_mapping(&self.kv, evm_address)
+    }
+
+    /// Audit log records in a time range - admin only
+    pub fn handle_audit_query(&self, requester: &Requester, query: &AuditQuery) -> Result<AuditQueryResponse> {
+        self.authorize(requester, "audit_query")?;
+        audit::query(&self.kv, query)
+    }
+
+    /// Mapping change events after `after_seq`, oldest first
+    pub fn handle_poll_events(&self, after_seq: u64, limit: Option<usize>) -> Result<EventPage> {
+        events::poll(&self.kv, after_seq, limit)
+    }
+
+    /// Merkle root over every stored chain mapping - admin only
+    pub fn handle_merkle_root(&self, requester: &Requester) -> Result<MerkleRoot> {
+        self.authorize(requester, "merkle_root")?;
+        merkle::root(&self.kv)
+    }
+
+    /// Inclusion proof of one chain mapping against the Merkle root
+    pub fn handle_merkle_proof(&self, solana_pubkey: &SolanaPubkey, chain_id: &ChainId) -> Result<MerkleProof> {
+        merkle::proof(&self.kv, solana_pubkey, chain_id)
+    }
+}
+
+impl<S: KvStore, K: KeyCreator + SolanaKeyCreator> Provisioner<S, K> {
+    /// EVM → Solana provision handler - one Solana wallet per EVM address
+    pub fn handle_evm_to_solana(&self, req: EvmToSolanaProvisionRequest) -> Result<EvmToSolanaProvisionResponse> {
+        let evm_address = req.evm_address.to_string();
+        let request_id = req.request_id.clone();
+        self.traced("provision_evm_to_solana", request_id.as_deref(), None, || {
+            self.audited("provision_evm_to_solana", &evm_address, &evm_address, || self.provision_evm_to_solana(req))
+        })
+    }
+
+    fn provision_evm_to_solana(&self, req: EvmToSolanaProvisionRequest) -> Result<EvmToSolanaProvisionResponse> {
+        let kv = self
+            .evm_to_solana
+            .as_ref()
+            .ok_or(ProvisionError::NotConfigured("EVM to Solana bucket"))?;
+
+        mapping::store_evm_to_solana(kv, &req, self.now(), || {
+            let key = self.keys.create_solana_key(req.evm_address.as_str())?;
+            Ok(SolanaMappingValue {
+                address: SolanaPubkey::parse(&key.address)?,
+                key_id: key.key_id,
+            })
+        })
+    }
+
+    /// Solana wallet provisioned for an EVM address
+    pub fn handle_evm_to_solana_get(&self, evm_address: &EvmAddress) -> Result<Option<SolanaPubkey>> {
+        match &self.evm_to_solana {
+            Some(kv) => Ok(evm_to_solana::get_mapping(kv, evm_address)?.map(|v| v.address)),
+            None => Err(ProvisionError::NotConfigured("EVM to Solana bucket")),
+        }
+    }
+}
+
+impl<S: KvStore, K: KeyCreator + KeyLister> Provisioner<S, K> {
+    /// Compare the org's EVM keys with one batch of the bucket, repairing
+    /// what can be repaired with `req.repair` (see `reconcile`) - admin only.
+    /// Call again with `next_cursor` until it is `None`.
+    pub fn handle_reconcile(&self, requester: &Requester, req: ReconcileRequest) -> Result<ReconcileReport> {
+        let subject = req.cursor.clone().unwrap_or_default();
+        self.traced("reconcile", req.request_id.as_deref(), None, || {
+            self.audited("reconcile", requester.name(), &subject, || {
+                self.authorize(requester, "reconcile")?;
+                let keys = self.keys.list_evm_keys()?;
+                reconcile::reconcile_batch(&self.kv, &keys, &req, requester.name(), self.now())
+            })
+        })
+    }
+}
@@ -0,0 +1,66 @@
+//! Mapping Quotas
+//!
+//! Caps how many chains and labels one Solana address can be mapped on, so a
+//! script looping over chain ids cannot grow a user (and the bucket) without
+//! bound. Counted from the user's chain index and label index:
+//!
+//! - `max_chains`: chains with a primary mapping, and chains of each label
+//! - `max_labels`: labels besides the primary address
+//!
+//! Stores, updates and external links that would add past a limit fail with
+//! `QuotaExceeded` before writing. Requests adding nothing new always pass,
+//! so users already over a lowered limit keep their mappings. The indexes are
+//! read before the write, so concurrent stores can overshoot a limit slightly.
+
+use crate::address::SolanaPubkey;
+use crate::chain_id::ChainId;
+use crate::error::{ProvisionError, Result};
+use crate::kv::{self, KvStore};
+use crate::labels;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+
+/// Most chains and labels one Solana address can be mapped on
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct MappingQuota {
+    pub max_chains: usize,
+    pub max_labels: usize,
+}
+
+impl Default for MappingQuota {
+    fn default() -> Self {
+        Self { max_chains: 100, max_labels: 10 }
+    }
+}
+
+/// Fail with `QuotaExceeded` if mapping `solana_pubkey` on `chain_ids` (under
+/// `label`, or primary if `None`) would take it past `quota`
+pub fn check(
+    kv: &impl KvStore,
+    quota: &MappingQuota,
+    solana_pubkey: &SolanaPubkey,
+    label: Option<&str>,
+    chain_ids: &[ChainId],
+) -> Result<()> {
+    let exceeded = |what, limit| Err(ProvisionError::QuotaExceeded { solana_pubkey: solana_pubkey.to_string(), what, limit });
+
+    let mapped: Vec<ChainId> = match label {
+        None => kv::get_chain_index(kv, solana_pubkey)?,
+        Some(label) => {
+            let mut index = labels::get_label_index(kv, solana_pubkey)?;
+            if !index.contains_key(label) && index.len() >= quota.max_labels {
+                return exceeded("labels", quota.max_labels);
+            }
+            index.remove(label).unwrap_or_default()
+        }
+    };
+    let mut chains: BTreeSet<&ChainId> = mapped.iter().collect();
+    let before = chains.len();
+    // The wildcard is no chain of its own
+    chains.extend(chain_ids.iter().filter(|chain_id| !chain_id.is_wildcard()));
+    if chains.len() > before && chains.len() > quota.max_chains {
+        return exceeded("chains", quota.max_chains);
+    }
+    Ok(())
+}
@@ -0,0 +1,109 @@
+//! Rate Limiting
+//!
+//! Stores and self-service updates are limited per Solana address, so a
+//! client stuck in a retry loop is turned away before it reaches CubeSigner.
+//! The limiter is a sliding-window counter: requests are counted per fixed
+//! window, and the previous window's count is weighted by how much of it still
+//! overlaps the sliding window ending now.
+//!
+//! Only requests whose ownership proof verified are counted (callers check
+//! the proof first), so no one can use up another user's budget by sending
+//! unsigned requests in their name.
+//!
+//! ## Key Schema (`rate_limits` bucket)
+//! ```text
+//! rate:{solana_pubkey} → RateCounter   # counts of the latest window and the one before
+//! ```
+//!
+//! Each Solana address has one counter, rewritten in place as windows pass,
+//! so the bucket grows with the number of users, not with time. Counters are
+//! incremented with a plain read and write, so concurrent requests can
+//! undercount; the limit is a brake, not an exact quota.
+
+use crate::address::SolanaPubkey;
+use crate::error::{ProvisionError, Result};
+use crate::kv::KvStore;
+use serde::{Deserialize, Serialize};
+
+/// Bucket holding the request counters
+pub const RATE_LIMIT_BUCKET: &str = "rate_limits";
+
+/// Most requests a configured rate limit may allow per window
+pub const MAX_RATE_LIMIT_REQUESTS: u64 = 1_000_000;
+
+/// Longest window a configured rate limit may count over (a week)
+pub const MAX_RATE_LIMIT_WINDOW_SECS: u64 = 7 * 24 * 60 * 60;
+
+/// At most `max_requests` per Solana address in any `window_secs` seconds
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct RateLimit {
+    pub max_requests: u64,
+    pub window_secs: u64,
+}
+
+impl Default for RateLimit {
+    fn default() -> Self {
+        Self { max_requests: 10, window_secs: 60 }
+    }
+}
+
+/// Request counts of one Solana address. `window` is `now / window_secs` of
+/// the latest request counted.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RateCounter {
+    pub window: u64,
+    /// Requests counted in `window`
+    pub count: u64,
+    /// Requests counted in `window - 1`
+    #[serde(default)]
+    pub previous: u64,
+}
+
+impl RateCounter {
+    /// Counts of the current and the previous window as of `window`
+    fn at(&self, window: u64) -> (u64, u64) {
+        match window.checked_sub(self.window) {
+            Some(0) => (self.count, self.previous),
+            Some(1) => (0, self.count),
+            // Older (or, after a clock step back, newer): nothing overlaps
+            _ => (0, 0),
+        }
+    }
+}
+
+/// Key of a Solana address's counter: `rate:{solana_pubkey}`
+pub fn rate_key(solana_pubkey: &SolanaPubkey) -> String {
+    format!("rate:{}", solana_pubkey.as_str())
+}
+
+/// The counter of `solana_pubkey`, `None` before its first counted request
+pub fn get_counter(kv: &impl KvStore, solana_pubkey: &SolanaPubkey) -> Result<Option<RateCounter>> {
+    kv.get(&rate_key(solana_pubkey))?
+        .map(|raw| serde_json::from_str(&raw).map_err(|e| ProvisionError::corrupt("rate limit counter", e)))
+        .transpose()
+}
+
+/// Count one request for `solana_pubkey`, or fail with `RateLimited` if the
+/// limit is reached. Refused requests are not counted.
+pub fn check(kv: &impl KvStore, limit: &RateLimit, solana_pubkey: &SolanaPubkey, now: u64) -> Result<()> {
+    let window_secs = limit.window_secs.max(1);
+    let window = now / window_secs;
+    let elapsed = now % window_secs;
+
+    let (current, previous) = get_counter(kv, solana_pubkey)?.unwrap_or_default().at(window);
+
+    // previous * (window_secs - elapsed) / window_secs + current >= max_requests, without the division;
+    // in u128, where products of two u64 cannot overflow
+    let weighted = u128::from(previous) * u128::from(window_secs - elapsed) + u128::from(current) * u128::from(window_secs);
+    if weighted >= u128::from(limit.max_requests) * u128::from(window_secs) {
+        return Err(ProvisionError::RateLimited {
+            solana_pubkey: solana_pubkey.to_string(),
+            retry_after: window_secs - elapsed,
+        });
+    }
+
+    let counter = RateCounter { window, count: current.saturating_add(1), previous };
+    let raw = serde_json::to_string(&counter).expect("rate counter serialization cannot fail");
+    kv.set(&rate_key(solana_pubkey), &raw)
+}
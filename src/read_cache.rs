@@ -0,0 +1,125 @@
+//! Read Cache
+//!
+//! One action can read the same key many times: a batch store reads the
+//! user's default mapping once per chain, and each flow re-reads the records
+//! the previous one just checked. On C2F every read is a host call, so the
+//! policy puts its buckets behind `Cached`, which keeps what it read for the
+//! rest of the invocation.
+//!
+//! Every write through `Cached` (`set`, `set_if_absent`, `delete`) drops the
+//! key from the cache before it reaches the store, so the next read of it
+//! goes to the store, whether the write succeeded, failed, or lost the race
+//! of a conditional write. Writes the invocation does not make, by concurrent
+//! invocations, are not seen for keys already read: a cache lives for one
+//! invocation only. `list_keys` is not cached.
+//!
+//! Reads are shared across the buckets of one invocation, like
+//! `shadow::ShadowWrites`, and dropped with it.
+
+use crate::error::Result;
+use crate::kv::KvStore;
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::rc::Rc;
+
+/// Value of each key read (`None`: absent), by bucket and key
+type Reads = BTreeMap<(String, String), Option<String>>;
+
+/// Values read by one invocation
+#[derive(Clone, Default)]
+pub struct ReadCache(Rc<RefCell<Reads>>);
+
+impl ReadCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Keys currently cached, over all buckets
+    pub fn len(&self) -> usize {
+        self.0.borrow().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.borrow().is_empty()
+    }
+
+    fn value(&self, bucket: &str, key: &str) -> Option<Option<String>> {
+        self.0.borrow().get(&(bucket.to_string(), key.to_string())).cloned()
+    }
+
+    fn insert(&self, bucket: &str, key: &str, value: Option<String>) {
+        self.0.borrow_mut().insert((bucket.to_string(), key.to_string()), value);
+    }
+
+    fn invalidate(&self, bucket: &str, key: &str) {
+        self.0.borrow_mut().remove(&(bucket.to_string(), key.to_string()));
+    }
+}
+
+/// A `KvStore` answering repeated reads from a `ReadCache`
+pub struct Cached<S> {
+    inner: S,
+    bucket: String,
+    cache: ReadCache,
+}
+
+impl<S: KvStore> Cached<S> {
+    /// `bucket` tells this bucket's keys apart from the others' of the invocation
+    pub fn new(inner: S, bucket: &str, cache: ReadCache) -> Self {
+        Self { inner, bucket: bucket.to_string(), cache }
+    }
+}
+
+impl<S: KvStore> KvStore for Cached<S> {
+    fn get(&self, key: &str) -> Result<Option<String>> {
+        if let Some(value) = self.cache.value(&self.bucket, key) {
+            return Ok(value);
+        }
+        let value = self.inner.get(key)?;
+        self.cache.insert(&self.bucket, key, value.clone());
+        Ok(value)
+    }
+
+    fn set_if_absent(&self, key: &str, value: &str) -> Result<bool> {
+        self.cache.invalidate(&self.bucket, key);
+        self.inner.set_if_absent(key, value)
+    }
+
+    fn set(&self, key: &str, value: &str) -> Result<()> {
+        self.cache.invalidate(&self.bucket, key);
+        self.inner.set(key, value)
+    }
+
+    /// Cached keys from memory, the others in one `get_many` of the store
+    fn get_many(&self, keys: &[String]) -> Result<Vec<Option<String>>> {
+        let mut values: Vec<Option<Option<String>>> = keys.iter().map(|key| self.cache.value(&self.bucket, key)).collect();
+        let missing: Vec<String> = keys
+            .iter()
+            .zip(&values)
+            .filter(|(_, value)| value.is_none())
+            .map(|(key, _)| key.clone())
+            .collect();
+        if missing.is_empty() {
+            return Ok(values.into_iter().flatten().collect());
+        }
+
+        let mut read = self.inner.get_many(&missing)?.into_iter();
+        for (key, value) in keys.iter().zip(values.iter_mut()) {
+            if value.is_none() {
+                let stored = read.next().flatten();
+                self.cache.insert(&self.bucket, key, stored.clone());
+                *value = Some(stored);
+            }
+        }
+        Ok(values.into_iter().flatten().collect())
+    }
+
+    fn delete(&self, key: &str) -> Result<()> {
+        self.cache.invalidate(&self.bucket, key);
+        self.inner.delete(key)
+    }
+
+    fn list_keys(&self, after: Option<&str>, limit: usize) -> Result<Vec<String>> {
+        self.inner.list_keys(after, limit)
+    }
+}
@@ -0,0 +1,195 @@
+//! Key Reconciliation
+//!
+//! CubeSigner and the bucket can disagree after an incident: keys no mapping
+//! points at (the KV write was lost after the key was created) and mappings
+//! whose key no longer exists in CubeSigner. Reconciliation compares the org's
+//! EVM keys with the mapping records and reports both. With `repair` it also
+//! fixes what can be fixed without the user:
+//!
+//! - an orphan default key (`EVM_{solana_pubkey}`) of a user without a default
+//!   mapping becomes that user's default mapping
+//! - the address of a mapping whose key is gone is frozen (`freeze`), so it
+//!   is no longer handed out
+//!
+//! Other orphans (a second default key, chain keys nobody points at) are only
+//! reported. Keys a chain was rotated away from are not orphans: they are in
+//! the reverse index and the chain's history.
+//!
+//! Like `migrate`, the bucket is scanned in batches over `KvStore::list_keys`.
+//! The keys are checked once, in the first batch (no cursor).
+
+use crate::address::{EvmAddress, SolanaPubkey};
+use crate::chain_id::ChainId;
+use crate::error::{ProvisionError, Result};
+use crate::freeze;
+use crate::keys::ListedKey;
+use crate::kv::{self, KvStore, MappingRecord};
+use crate::migrate::{self, DEFAULT_MIGRATION_BATCH, MAX_MIGRATION_BATCH};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+/// Freeze reason of addresses whose key is gone
+pub const MISSING_KEY_REASON: &str = "key not found in CubeSigner";
+
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct ReconcileRequest {
+    /// Resume after this key (`next_cursor` of the previous batch)
+    #[serde(default)]
+    pub cursor: Option<String>,
+    #[serde(default)]
+    pub limit: Option<usize>,
+    /// Fix what can be fixed instead of only reporting
+    #[serde(default)]
+    pub repair: bool,
+    /// Correlation id carried into log events (see `logging`)
+    #[serde(default)]
+    pub request_id: Option<String>,
+}
+
+/// A CubeSigner key named like one of ours that no mapping refers to
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct OrphanKey {
+    pub key_id: String,
+    pub address: EvmAddress,
+    pub name: String,
+    /// Whether it was made its user's default mapping
+    pub adopted: bool,
+}
+
+/// A mapping record whose address has no CubeSigner key
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct DanglingMapping {
+    /// KV key of the mapping record
+    pub key: String,
+    pub address: EvmAddress,
+    pub key_id: Option<String>,
+    /// Whether its address was frozen
+    pub frozen: bool,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct ReconcileReport {
+    /// CubeSigner keys checked against the bucket (0 after the first batch)
+    pub keys_checked: usize,
+    /// Bucket keys looked at in this batch
+    pub scanned: usize,
+    pub orphan_keys: Vec<OrphanKey>,
+    pub dangling_mappings: Vec<DanglingMapping>,
+    /// Pass as `cursor` to continue; null once every key has been scanned
+    pub next_cursor: Option<String>,
+}
+
+/// Whose key a key name says it is: `EVM_{solana_pubkey}` (default key) or
+/// `EVM_{solana_pubkey}_chain{chain}` (see `keys::default_key_name`/`chain_key_name`)
+pub fn parse_key_name(name: &str) -> Option<(SolanaPubkey, Option<ChainId>)> {
+    let rest = name.strip_prefix("EVM_")?;
+    match rest.split_once("_chain") {
+        Some((solana_pubkey, chain)) => Some((SolanaPubkey::parse(solana_pubkey).ok()?, Some(ChainId::parse(chain).ok()?))),
+        None => Some((SolanaPubkey::parse(rest).ok()?, None)),
+    }
+}
+
+/// Reconcile one batch. `keys` are all EVM keys of the org, whatever their
+/// name: a mapping is only dangling if no key at all has its address.
+pub fn reconcile_batch(
+    kv: &impl KvStore,
+    keys: &[ListedKey],
+    req: &ReconcileRequest,
+    actor: &str,
+    now: u64,
+) -> Result<ReconcileReport> {
+    let limit = req.limit.unwrap_or(DEFAULT_MIGRATION_BATCH).clamp(1, MAX_MIGRATION_BATCH);
+    let scanned = kv.list_keys(req.cursor.as_deref(), limit)?;
+
+    let mut report = ReconcileReport {
+        keys_checked: 0,
+        scanned: scanned.len(),
+        orphan_keys: Vec::new(),
+        dangling_mappings: Vec::new(),
+        next_cursor: if scanned.len() < limit { None } else { scanned.last().cloned() },
+    };
+
+    if req.cursor.is_none() {
+        for key in keys {
+            let Some((solana_pubkey, chain_id)) = parse_key_name(&key.name) else {
+                continue;
+            };
+            let Ok(address) = EvmAddress::parse(&key.address) else {
+                continue;
+            };
+            report.keys_checked += 1;
+            if is_referenced(kv, &solana_pubkey, chain_id.as_ref(), &address)? {
+                continue;
+            }
+
+            let adopted = req.repair && chain_id.is_none() && restore_default(kv, &solana_pubkey, &address, &key.key_id, actor, now)?;
+            report.orphan_keys.push(OrphanKey {
+                key_id: key.key_id.clone(),
+                address,
+                name: key.name.clone(),
+                adopted,
+            });
+        }
+    }
+
+    let existing: HashSet<EvmAddress> = keys.iter().filter_map(|key| EvmAddress::parse(&key.address).ok()).collect();
+    let mapping_keys: Vec<String> = scanned.into_iter().filter(|key| migrate::is_mapping_key(key)).collect();
+    let mut frozen = HashSet::new();
+    for (key, record) in mapping_keys.iter().zip(kv::get_live_mappings(kv, &mapping_keys)?) {
+        let Some(record) = record else {
+            continue;
+        };
+        if existing.contains(&record.address) {
+            continue;
+        }
+
+        if req.repair && !frozen.contains(&record.address) {
+            freeze::set_frozen(kv, &record.address, true, Some(MISSING_KEY_REASON), actor, now)?;
+            frozen.insert(record.address.clone());
+        }
+        report.dangling_mappings.push(DanglingMapping {
+            key: key.clone(),
+            frozen: frozen.contains(&record.address),
+            address: record.address,
+            key_id: record.key_id,
+        });
+    }
+
+    Ok(report)
+}
+
+/// Whether a mapping of `solana_pubkey` uses (or used) `address`
+fn is_referenced(kv: &impl KvStore, solana_pubkey: &SolanaPubkey, chain_id: Option<&ChainId>, address: &EvmAddress) -> Result<bool> {
+    match kv::get_reverse_mapping(kv, address) {
+        Ok(owner) if owner.as_ref() == Some(solana_pubkey) => return Ok(true),
+        // The key of an erased user: its address stays reserved, not orphaned
+        Err(ProvisionError::Anonymized { .. }) => return Ok(true),
+        Ok(_) => {}
+        Err(e) => return Err(e),
+    }
+    // Mappings stored before the reverse index existed
+    match chain_id {
+        None => Ok(kv::get_default_evm_address(kv, solana_pubkey)?.as_ref() == Some(address)),
+        Some(chain_id) => Ok(kv::get_existing_mapping(kv, solana_pubkey, chain_id)?.as_ref() == Some(address)
+            || kv::get_history(kv, solana_pubkey, chain_id)?.iter().any(|entry| entry.address == *address)),
+    }
+}
+
+/// Make the user's default key (`address`, `key_id`) their default mapping
+/// again, if they have none. Returns whether it did.
+pub fn restore_default(
+    kv: &impl KvStore,
+    solana_pubkey: &SolanaPubkey,
+    address: &EvmAddress,
+    key_id: &str,
+    actor: &str,
+    now: u64,
+) -> Result<bool> {
+    let record = MappingRecord::new(address, Some(key_id), actor, now);
+    let stored = kv::store_default_mapping(kv, solana_pubkey, &record)?;
+    if stored.address != *address {
+        return Ok(false);
+    }
+    kv::store_reverse_mapping(kv, address, solana_pubkey)?;
+    Ok(true)
+}
@@ -0,0 +1,213 @@
+//! Consistency Repair
+//!
+//! Fixes violations found by `verify`, by id. Each violation is checked again
+//! first; one that is gone is reported `resolved` and left alone. What can be
+//! fixed from the bucket itself is:
+//!
+//! - `reverse_mismatch` on a mapping: the reverse entry is rewritten to the
+//!   mapping's user, unless another user's mapping uses the address
+//! - `missing_default`: the chain mapping is copied to the user's default
+//!   (first-writer-wins) and its chain indexed
+//! - `no_chains`: the user's chain mappings on known chains are indexed
+//! - `malformed_record`: a record that only fails on its address's case
+//!   (e.g. a bad checksum) is rewritten with the address lowercased
+//!
+//! Everything else (malformed keys, reverse entries of unknown users, a
+//! default without any chain mapping to index) is reported `unrepairable`
+//! and needs an admin to decide, e.g. with `import`.
+
+use crate::address::{EvmAddress, SolanaPubkey};
+use crate::chain_id::ChainId;
+use crate::chains;
+use crate::dry_run;
+use crate::error::{ProvisionError, Result};
+use crate::kv::{self, KvStore, MappingRecord};
+use crate::migrate::MAX_MIGRATION_BATCH;
+use crate::verify::{self, ViolationKind};
+use serde::{Deserialize, Serialize};
+
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct RepairRequest {
+    /// Ids of the violations to fix (`Violation::id`)
+    pub ids: Vec<String>,
+    /// Report what would be fixed without writing
+    #[serde(default)]
+    pub dry_run: bool,
+    /// Correlation id carried into log events (see `logging`)
+    #[serde(default)]
+    pub request_id: Option<String>,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RepairStatus {
+    Repaired,
+    /// The violation was no longer found
+    Resolved,
+    Unrepairable,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct RepairOutcome {
+    pub id: String,
+    pub status: RepairStatus,
+    /// What was done, or why nothing could be
+    pub detail: String,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct RepairReport {
+    /// Whether this is what the repair would do; nothing was written
+    pub dry_run: bool,
+    /// Violations fixed (or, in a dry run, that would be)
+    pub repaired: usize,
+    pub results: Vec<RepairOutcome>,
+}
+
+/// Fix the violations of `req`, one by one. A dry run makes the same fixes
+/// over a `dry_run` overlay, so later ids see what earlier ones would write.
+pub fn repair_batch(kv: &impl KvStore, req: &RepairRequest) -> Result<RepairReport> {
+    if req.dry_run {
+        return Ok(dry_run::run(kv, |kv| repair(kv, &req.ids, true))?.result);
+    }
+    repair(kv, &req.ids, false)
+}
+
+fn repair(kv: &impl KvStore, ids: &[String], dry_run: bool) -> Result<RepairReport> {
+    if ids.is_empty() {
+        return Err(ProvisionError::InvalidRequest("ids cannot be empty".to_string()));
+    }
+    if ids.len() > MAX_MIGRATION_BATCH {
+        return Err(ProvisionError::BatchTooLarge { size: ids.len(), max: MAX_MIGRATION_BATCH });
+    }
+
+    let mut results = Vec::with_capacity(ids.len());
+    for id in ids {
+        let (status, detail) = repair_one(kv, id)?;
+        results.push(RepairOutcome { id: id.clone(), status, detail });
+    }
+    let repaired = results.iter().filter(|outcome| outcome.status == RepairStatus::Repaired).count();
+    Ok(RepairReport { dry_run, repaired, results })
+}
+
+fn repair_one(kv: &impl KvStore, id: &str) -> Result<(RepairStatus, String)> {
+    let Some((kind, key)) = verify::parse_violation_id(id) else {
+        return Ok(unrepairable("not a violation id"));
+    };
+    if !verify::check_key(kv, key)?.iter().any(|violation| violation.id == id) {
+        return Ok((RepairStatus::Resolved, "no longer found".to_string()));
+    }
+
+    match kind {
+        ViolationKind::ReverseMismatch if key.starts_with("reverse:") => {
+            Ok(unrepairable("the owner has no mappings; re-import them or their reverse entry"))
+        }
+        ViolationKind::ReverseMismatch => rebuild_reverse(kv, key),
+        ViolationKind::MissingDefault => backfill_default(kv, key),
+        ViolationKind::NoChains => rebuild_chain_index(kv, key),
+        ViolationKind::MalformedRecord => normalize_record(kv, key),
+        ViolationKind::MalformedAddress => Ok(unrepairable("the key is not a valid address")),
+    }
+}
+
+/// Point the reverse entry of the mapping under `key` back at its user
+fn rebuild_reverse(kv: &impl KvStore, key: &str) -> Result<(RepairStatus, String)> {
+    let (solana_pubkey, _) = parse_mapping_key(key)?;
+    let record = decode(kv, key)?;
+    let reverse_key = kv::reverse_key(&record.address);
+    if let Some(owner) = kv::get_reverse_mapping(kv, &record.address)? {
+        if maps(kv, &owner, &record.address)? {
+            return Ok(unrepairable(&format!("{} is also mapped by {}", record.address, owner)));
+        }
+    }
+    kv.set(&reverse_key, solana_pubkey.as_str())?;
+    Ok((RepairStatus::Repaired, format!("{} now points at {}", reverse_key, solana_pubkey)))
+}
+
+/// Give the user of the chain mapping under `key` that mapping as their default
+fn backfill_default(kv: &impl KvStore, key: &str) -> Result<(RepairStatus, String)> {
+    let (solana_pubkey, chain_id) = parse_mapping_key(key)?;
+    let chain_id = chain_id.expect("missing_default is only found on chain mappings");
+    let record = decode(kv, key)?;
+    let default = MappingRecord { revision: 0, spend_limit: None, ..record };
+    let stored = kv::store_default_mapping(kv, &solana_pubkey, &default)?;
+    kv::add_to_chain_index(kv, &solana_pubkey, &[chain_id])?;
+    Ok((RepairStatus::Repaired, format!("default set to {}", stored.address)))
+}
+
+/// Index the chains the user of the default under `key` has mappings on
+fn rebuild_chain_index(kv: &impl KvStore, key: &str) -> Result<(RepairStatus, String)> {
+    let (solana_pubkey, _) = parse_mapping_key(key)?;
+    let mut found = Vec::new();
+    for chain in chains::list_chains(kv)? {
+        if kv::get_chain_mapping(kv, &solana_pubkey, &chain.chain_id)?.is_some() {
+            found.push(chain.chain_id);
+        }
+    }
+    if found.is_empty() {
+        return Ok(unrepairable("no chain mappings found on known chains"));
+    }
+    kv::add_to_chain_index(kv, &solana_pubkey, &found)?;
+    let found: Vec<String> = found.iter().map(ChainId::to_string).collect();
+    Ok((RepairStatus::Repaired, format!("indexed {}", found.join(", "))))
+}
+
+/// Rewrite the record under `key` with its address lowercased, if that makes it decode
+fn normalize_record(kv: &impl KvStore, key: &str) -> Result<(RepairStatus, String)> {
+    let Some(raw) = kv.get(key)? else {
+        return Ok((RepairStatus::Resolved, "no longer found".to_string()));
+    };
+    let normalized = match serde_json::from_str::<serde_json::Value>(&raw) {
+        Ok(serde_json::Value::Object(mut fields)) => match fields.get("address") {
+            Some(serde_json::Value::String(address)) => {
+                let address = address.to_ascii_lowercase();
+                fields.insert("address".to_string(), address.into());
+                serde_json::Value::Object(fields).to_string()
+            }
+            _ => return Ok(unrepairable("the record has no address")),
+        },
+        Ok(_) => return Ok(unrepairable("the record is not an object")),
+        // Version 0: a plain address
+        Err(_) => raw.trim().to_ascii_lowercase(),
+    };
+    match MappingRecord::decode(&normalized) {
+        Ok(record) => {
+            kv.set(key, &record.encode())?;
+            Ok((RepairStatus::Repaired, format!("address normalized to {}", record.address)))
+        }
+        Err(e) => Ok(unrepairable(&e.to_string())),
+    }
+}
+
+/// Whether a current mapping of `solana_pubkey` (default or indexed chain) is `evm_address`
+fn maps(kv: &impl KvStore, solana_pubkey: &SolanaPubkey, evm_address: &EvmAddress) -> Result<bool> {
+    if kv::get_default_evm_address(kv, solana_pubkey)?.as_ref() == Some(evm_address) {
+        return Ok(true);
+    }
+    for chain_id in kv::get_chain_index(kv, solana_pubkey)? {
+        if kv::get_existing_mapping(kv, solana_pubkey, &chain_id)?.as_ref() == Some(evm_address) {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+/// User (and chain, for chain mappings) of a mapping key
+fn parse_mapping_key(key: &str) -> Result<(SolanaPubkey, Option<ChainId>)> {
+    if let Some(solana_pubkey) = key.strip_prefix("default:") {
+        return Ok((SolanaPubkey::parse(solana_pubkey)?, None));
+    }
+    let (solana_pubkey, chain_id) = key
+        .split_once(':')
+        .ok_or_else(|| ProvisionError::InvalidRequest(format!("{} is not a mapping key", key)))?;
+    Ok((SolanaPubkey::parse(solana_pubkey)?, Some(ChainId::parse(chain_id)?)))
+}
+
+fn decode(kv: &impl KvStore, key: &str) -> Result<MappingRecord> {
+    let raw = kv.get(key)?.ok_or_else(|| ProvisionError::InvalidRequest(format!("{} holds no record", key)))?;
+    MappingRecord::decode(&raw)
+}
+
+fn unrepairable(detail: &str) -> (RepairStatus, String) {
+    (RepairStatus::Unrepairable, detail.to_string())
+}
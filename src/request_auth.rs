@@ -0,0 +1,63 @@
+//! Request Authentication
+//!
+//! CubeSigner authenticates the session invoking the policy; with a shared
+//! secret configured (`Config::request_auth_secret`), the policy also checks
+//! that each request body comes from a backend holding it. The request then
+//! carries `auth`: the hex HMAC-SHA256, under the secret, of the body without
+//! `auth` as canonical JSON (object keys sorted, no whitespace, see
+//! `response_signing::canonical_json`).
+//!
+//! The check runs before dispatch, so a request failing it has no effect. It
+//! does not stop a captured request from being sent again: actions that must
+//! not repeat already carry nonces or idempotency keys.
+//!
+//! Backends build requests with `sign_request`.
+
+use crate::error::{ProvisionError, Result};
+use crate::response_signing::canonical_json;
+use hmac::{Hmac, Mac};
+use serde_json::Value;
+use sha2::Sha256;
+
+/// Field of a request holding its HMAC
+pub const AUTH_FIELD: &str = "auth";
+
+/// Shortest accepted secret
+pub const MIN_SECRET_LEN: usize = 32;
+
+fn mac(secret: &str, request: &Value) -> Hmac<Sha256> {
+    let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(canonical_json(request).as_bytes());
+    mac
+}
+
+/// `request` with its `auth` field set
+pub fn sign_request(mut request: Value, secret: &str) -> Result<Value> {
+    let fields = request
+        .as_object_mut()
+        .ok_or_else(|| ProvisionError::InvalidRequest("a request is a JSON object".to_string()))?;
+    fields.remove(AUTH_FIELD);
+    let tag = mac(secret, &request).finalize().into_bytes();
+    let hex: String = tag.iter().map(|b| format!("{:02x}", b)).collect();
+    request[AUTH_FIELD] = Value::from(hex);
+    Ok(request)
+}
+
+/// Fail unless the `auth` field of request `body` is its HMAC under `secret`
+pub fn verify_request(body: &str, secret: &str) -> Result<()> {
+    let failed = |reason: &str| ProvisionError::RequestAuthFailed(reason.to_string());
+
+    let mut request: Value = serde_json::from_str(body).map_err(|_| failed("the body is not JSON"))?;
+    let auth = request
+        .as_object_mut()
+        .ok_or_else(|| failed("the body is not a JSON object"))?
+        .remove(AUTH_FIELD)
+        .ok_or_else(|| failed("missing auth"))?;
+    let tag = auth
+        .as_str()
+        .filter(|hex| hex.len() == 64 && hex.is_ascii())
+        .and_then(|hex| (0..hex.len()).step_by(2).map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok()).collect::<Option<Vec<u8>>>())
+        .ok_or_else(|| failed("auth is not a hex HMAC-SHA256"))?;
+    // Constant-time comparison
+    mac(secret, &request).verify_slice(&tag).map_err(|_| failed("auth does not match the body"))
+}
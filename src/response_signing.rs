@@ -0,0 +1,164 @@
+//! Signed Policy Responses
+//!
+//! Policy replies reach the backend through CubeSigner and whatever sits in
+//! between. A policy whose org designated a response signing key (its
+//! `response_signing_key` secret) signs every enveloped reply, so a backend
+//! pinning the key's public half can tell a reply the policy sent from one
+//! altered on the way (`verify_response`).
+//!
+//! The signature covers the request it answers, the envelope without its
+//! `signature` field, and when it was signed. Both are hashed as canonical
+//! JSON (object keys sorted, no whitespace):
+//! ```text
+//! cubist-policy-response:v2:{signed_at}:{hex SHA-256 of the request}:{hex SHA-256 of the envelope}
+//! ```
+//!
+//! The backend verifies a reply against the body it sent (`verify_response`),
+//! so a reply to one request cannot be passed off as the reply to another.
+//! `signed_at` bounds replays of a reply to the same request: `verify_response`
+//! refuses replies older than the caller's limit. A request carrying a
+//! `request_id` of its own is unique, which rules those out too.
+//!
+//! ## Threat Model
+//!
+//! The key is an ed25519 key the org designates for the policy and keeps in
+//! the policy's secrets at CubeSigner: the policy cannot reach CubeSigner to
+//! sign, but it reads the key for each reply, and the WASM holds none. So a
+//! signature shows that a reply came from a policy holding the org's key,
+//! unaltered, for the request the backend sent. It protects against whatever
+//! sits between CubeSigner and the backend (proxies, queues, logs replayed
+//! into the pipeline), and against anyone who can only read the policy WASM.
+//! It does not protect against those who can read the policy's secrets (the
+//! org owners who set them, CubeSigner itself). Rotation replaces the secret
+//! with no rebuild; a leaked key is revoked by pinning the next one.
+
+use crate::error::{ProvisionError, Result};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use ed25519_dalek::{Signature, Signer, VerifyingKey};
+pub use ed25519_dalek::SigningKey;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+/// Prefix of every signed message
+pub const RESPONSE_DOMAIN: &str = "cubist-policy-response:v2";
+
+/// Field of the envelope holding its signature
+pub const SIGNATURE_FIELD: &str = "signature";
+
+/// Signing key from the base64 of its 32-byte seed; anything else is a
+/// misconfigured key, not a bad request
+pub fn signing_key_from_base64(encoded: &str) -> Result<SigningKey> {
+    let seed: [u8; 32] = BASE64
+        .decode(encoded.trim())
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or(ProvisionError::NotConfigured("a response signing key of 32 base64 bytes"))?;
+    Ok(SigningKey::from_bytes(&seed))
+}
+
+/// The `signature` field of a signed envelope
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ResponseSignature {
+    /// Public key of the signer, base64
+    pub key: String,
+    /// Unix timestamp (seconds)
+    pub signed_at: u64,
+    /// Hex SHA-256 of the request's canonical JSON (see `request_sha256`)
+    pub request_sha256: String,
+    /// Hex SHA-256 of the envelope's canonical JSON, without this field
+    pub sha256: String,
+    /// ed25519 signature of the message, base64
+    pub signature: String,
+}
+
+/// `value` as JSON with object keys sorted and no whitespace
+pub fn canonical_json(value: &Value) -> String {
+    match value {
+        Value::Object(fields) => {
+            let mut fields: Vec<_> = fields.iter().collect();
+            fields.sort_by_key(|(name, _)| *name);
+            let fields: Vec<String> = fields
+                .into_iter()
+                .map(|(name, value)| format!("{}:{}", Value::from(name.as_str()), canonical_json(value)))
+                .collect();
+            format!("{{{}}}", fields.join(","))
+        }
+        Value::Array(items) => format!("[{}]", items.iter().map(canonical_json).collect::<Vec<_>>().join(",")),
+        scalar => scalar.to_string(),
+    }
+}
+
+fn message(signed_at: u64, request_sha256: &str, sha256: &str) -> String {
+    format!("{}:{}:{}:{}", RESPONSE_DOMAIN, signed_at, request_sha256, sha256)
+}
+
+fn hex_sha256(bytes: &[u8]) -> String {
+    Sha256::digest(bytes).iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn sha256_hex(envelope: &Value) -> String {
+    hex_sha256(canonical_json(envelope).as_bytes())
+}
+
+/// Hex SHA-256 of a request body as canonical JSON, so a reserialized body
+/// hashes the same; a body that is not JSON is hashed as it is
+pub fn request_sha256(body: &str) -> String {
+    match serde_json::from_str::<Value>(body) {
+        Ok(request) => sha256_hex(&request),
+        Err(_) => hex_sha256(body.as_bytes()),
+    }
+}
+
+/// Signature of `envelope` (serialized without a `signature` field), in
+/// answer to the request hashing to `request_sha256`, at `now`
+pub fn sign(envelope: &impl Serialize, request_sha256: &str, key: &SigningKey, now: u64) -> ResponseSignature {
+    let envelope = serde_json::to_value(envelope).expect("envelope serialization cannot fail");
+    let sha256 = sha256_hex(&envelope);
+    let signature = key.sign(message(now, request_sha256, &sha256).as_bytes());
+    ResponseSignature {
+        key: BASE64.encode(key.verifying_key().to_bytes()),
+        signed_at: now,
+        request_sha256: request_sha256.to_string(),
+        sha256,
+        signature: BASE64.encode(signature.to_bytes()),
+    }
+}
+
+/// The envelope in `body`, without its signature, if `key` signed it in
+/// answer to `request` (the body the backend sent) at most `max_age_secs`
+/// before `now`
+pub fn verify_response(body: &str, request: &str, key: &VerifyingKey, now: u64, max_age_secs: u64) -> Result<Value> {
+    let invalid = |reason: &str| ProvisionError::InvalidResponseSignature(reason.to_string());
+
+    let mut envelope: Value = serde_json::from_str(body).map_err(|_| invalid("not JSON"))?;
+    let signature = envelope
+        .as_object_mut()
+        .ok_or_else(|| invalid("not an envelope"))?
+        .remove(SIGNATURE_FIELD)
+        .ok_or_else(|| invalid("unsigned"))?;
+    let signature: ResponseSignature = serde_json::from_value(signature).map_err(|_| invalid("malformed signature"))?;
+
+    if signature.key != BASE64.encode(key.to_bytes()) {
+        return Err(invalid("signed by another key"));
+    }
+    let sha256 = sha256_hex(&envelope);
+    if signature.sha256 != sha256 {
+        return Err(invalid("the response was altered"));
+    }
+    let request_sha256 = request_sha256(request);
+    if signature.request_sha256 != request_sha256 {
+        return Err(invalid("the response answers another request"));
+    }
+    let bytes: [u8; 64] = BASE64
+        .decode(&signature.signature)
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or_else(|| invalid("malformed signature"))?;
+    key.verify_strict(message(signature.signed_at, &request_sha256, &sha256).as_bytes(), &Signature::from_bytes(&bytes))
+        .map_err(|_| invalid("signature does not match the response key"))?;
+    if now.saturating_sub(signature.signed_at) > max_age_secs {
+        return Err(invalid(&format!("signed at {}, too long ago", signature.signed_at)));
+    }
+    Ok(envelope)
+}
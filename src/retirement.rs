@@ -0,0 +1,64 @@
+//! Retired Addresses
+//!
+//! Rotating a chain's key replaces its mapping; the chain's history keeps the
+//! old address, but only under the user and chain. A retirement record links
+//! the old address to its replacement directly, so whoever holds an old
+//! address (a deposit that arrived late, a support ticket) can find where the
+//! user's funds go now and why the address was retired.
+//!
+//! Every update of a chain mapping writes one (`mapping::apply_update`). The
+//! `reason` is set by `Provisioner::handle_rotate`; other updates leave it empty.
+//!
+//! ## Key Schema
+//! ```text
+//! retired:{evm_address} → RetirementRecord   # Overwritten if the address is retired again
+//! ```
+
+use crate::address::{EvmAddress, SolanaPubkey};
+use crate::anonymize;
+use crate::chain_id::ChainId;
+use crate::error::{ProvisionError, Result};
+use crate::kv::KvStore;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RetirementRecord {
+    /// The retired address
+    pub address: EvmAddress,
+    /// CubeSigner key id of `address`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key_id: Option<String>,
+    pub solana_pubkey: SolanaPubkey,
+    pub chain_id: ChainId,
+    /// Label of the retired address (`labels`); `None` for the primary address
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    /// The address that took its place
+    pub replaced_by: EvmAddress,
+    /// Why the key was rotated
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    /// Who rotated it
+    pub retired_by: String,
+    /// Unix timestamp (seconds)
+    pub retired_at: u64,
+}
+
+/// Key of an address's retirement record: `retired:{evm_address}`
+pub fn retirement_key(evm_address: &EvmAddress) -> String {
+    format!("retired:{}", evm_address.as_str())
+}
+
+pub fn get_retirement(kv: &impl KvStore, evm_address: &EvmAddress) -> Result<Option<RetirementRecord>> {
+    kv.get(&retirement_key(evm_address))?
+        .map(|raw| {
+            serde_json::from_str(&raw)
+                .map_err(|e| anonymize::refuse_tombstone(&raw).unwrap_or_else(|| ProvisionError::corrupt("retirement record", e)))
+        })
+        .transpose()
+}
+
+pub fn retire(kv: &impl KvStore, record: &RetirementRecord) -> Result<()> {
+    let raw = serde_json::to_string(record).expect("retirement record serialization cannot fail");
+    kv.set(&retirement_key(&record.address), &raw)
+}
@@ -0,0 +1,335 @@
+//! HTTP Server (`server` feature)
+//!
+//! The provisioning API as a plain REST service, for deployments outside C2F.
+//! Requests go to the same `Provisioner` handlers as library calls, over
+//! whichever `KvStore` and `KeyCreator` the `Provisioner` was built with.
+//!
+//! ## Routes
+//! ```text
+//! POST /provision                              ProvisionRequest     → ProvisionResponse
+//! POST /update                                 UpdateMappingRequest → UpdateMappingResponse
+//! GET  /mappings/{solana_pubkey}?chain_ids=1,eip155:137            → GetMappingsResponse
+//! POST /attest                                 AttestRequest        → SignedAttestation
+//! POST /certificate                            CertificateRequest   → MappingCertificate
+//! ```
+//!
+//! Successful responses are the handler's response as JSON (200). Failures
+//! are the error object used for batch items (`{"code","message","retryable"}`)
+//! with a status derived from the error (`status`).
+//!
+//! The server is deliberately small: HTTP/1.1 over `std::net`, one thread and
+//! one request per connection, no TLS. At most `MAX_CONNECTIONS` are served
+//! at once, and request lines and headers are capped at `MAX_LINE_BYTES`, so
+//! neither many connections nor endless lines can exhaust the process. Put it behind a reverse proxy that
+//! terminates TLS and authenticates callers. The proxy names the caller in
+//! `X-Requester-Identity` and `X-Requester-Org-Role` (and must strip both
+//! from what clients send); admin routes (`/update`) check that requester
+//! against the admin allowlist, so without one they fail.
+
+use crate::address::SolanaPubkey;
+use crate::admin::Requester;
+use crate::attestation::AttestRequest;
+use crate::certificates::CertificateRequest;
+use crate::chain_id::ChainId;
+use crate::error::{ProvisionError, Result};
+use crate::keys::KeyCreator;
+use crate::kv::KvStore;
+use crate::{ProvisionRequest, Provisioner, UpdateMappingRequest};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+/// Largest request body accepted
+pub const MAX_BODY_BYTES: usize = 1 << 20;
+
+/// Most header lines accepted per request
+const MAX_HEADERS: usize = 64;
+
+/// Longest request line or header line accepted, line ending included
+pub const MAX_LINE_BYTES: usize = 8 * 1024;
+
+/// Most connections served at once; more are refused with 503
+pub const MAX_CONNECTIONS: usize = 64;
+
+/// How long a connection may take to send its request
+const READ_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Header the authenticating proxy puts the caller's identity in
+pub const REQUESTER_IDENTITY_HEADER: &str = "X-Requester-Identity";
+
+/// Header the authenticating proxy puts the caller's org role in
+pub const REQUESTER_ORG_ROLE_HEADER: &str = "X-Requester-Org-Role";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Response {
+    pub status: u16,
+    /// JSON
+    pub body: String,
+}
+
+impl Response {
+    fn json(status: u16, body: &impl Serialize) -> Self {
+        Self { status, body: serde_json::to_string(body).expect("response serialization cannot fail") }
+    }
+
+    fn error(e: &ProvisionError) -> Self {
+        Self::json(status(e), e)
+    }
+
+    /// Failure that is about the HTTP request, not provisioning
+    fn http_error(status: u16, code: &str, message: String) -> Self {
+        Self::json(status, &serde_json::json!({ "code": code, "message": message, "retryable": false }))
+    }
+}
+
+/// HTTP status of a failed request
+pub fn status(e: &ProvisionError) -> u16 {
+    use ProvisionError::*;
+    match e {
+        InvalidSolanaPubkey(_) | InvalidEvmAddress(_) | InvalidChecksum(_) | InvalidChainId(_)
+        | InvalidSignatureEncoding { .. } | InvalidRecoveryId(_) | InvalidNonce | InvalidIdempotencyKey { .. }
+        | InvalidRequest(_) | BatchTooLarge { .. } | UnknownChain(_) | ChainNameRequired(_) | UnusableAddress { .. }
+        | UnusablePubkey { .. } | WrongNetwork { .. } | AuthorizationExpired { .. } => 400,
+        SignatureMismatch(_) | InvalidCertificate(_) | InvalidResponseSignature(_) | RequestAuthFailed(_) => 401,
+        NotAdmin(_) | NotOrgOwner | Forbidden { .. } | NotTenantMember { .. } | UnknownSigner(_) | ApprovalRequired | SelfApproval { .. } | AddressFrozen(_)
+        | Blocked(_) | ChainDisabled { .. } | ExternalAddress(_) | SpendLimitExceeded { .. }
+        | DestinationNotAllowed { .. } | QuotaExceeded { .. } => 403,
+        NotProvisioned(_) | AddressNotMapped { .. } | ProposalNotFound { .. } | JobNotFound { .. } => 404,
+        Anonymized { .. } => 410,
+        NonceUsed(_) | NonceTooLow { .. } | AddressOwned { .. } | IdempotencyKeyReused(_) | ImportConflict { .. }
+        | VersionConflict { .. } | UpdatePending { .. } | ProposalResolved { .. } | ProposalExpired { .. } | KvConflict(_) => 409,
+        RateLimited { .. } => 429,
+        CorruptRecord { .. } | UnsupportedRecordVersion(_) | AuditChainBroken(_) => 500,
+        Unsupported(_) | NotConfigured(_) => 501,
+        KeyCreationFailed { .. } | SigningFailed { .. } => 502,
+        Kv(_) => 503,
+    }
+}
+
+/// Answer one request from `requester`
+pub fn route<S: KvStore, K: KeyCreator>(
+    provisioner: &Provisioner<S, K>,
+    requester: &Requester,
+    method: &str,
+    target: &str,
+    body: &str,
+) -> Response {
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let result = match (method, path) {
+        ("POST", "/provision") => {
+            parse::<ProvisionRequest>(body).and_then(|req| provisioner.handle(req)).map(|response| Response::json(200, &response))
+        }
+        ("POST", "/update") => parse::<UpdateMappingRequest>(body)
+            .and_then(|req| provisioner.handle_update_mapping(requester, req))
+            .map(|response| Response::json(200, &response)),
+        ("POST", "/attest") => parse::<AttestRequest>(body)
+            .and_then(|req| provisioner.handle_attest(&req.solana_pubkey, &req.chain_id))
+            .map(|response| Response::json(200, &response)),
+        ("POST", "/certificate") => parse::<CertificateRequest>(body)
+            .and_then(|req| provisioner.handle_certificate(&req.solana_pubkey, &req.chain_id, req.ttl))
+            .map(|response| Response::json(200, &response)),
+        ("GET", _) if path.starts_with("/mappings/") => get_mappings(provisioner, &path["/mappings/".len()..], query),
+        (_, "/provision" | "/update" | "/attest" | "/certificate") => {
+            return Response::http_error(405, "METHOD_NOT_ALLOWED", format!("{} {} is not supported", method, path));
+        }
+        _ if path.starts_with("/mappings/") => {
+            return Response::http_error(405, "METHOD_NOT_ALLOWED", format!("{} {} is not supported", method, path));
+        }
+        _ => return Response::http_error(404, "NOT_FOUND", format!("No route for {} {}", method, path)),
+    };
+    result.unwrap_or_else(|e| Response::error(&e))
+}
+
+fn get_mappings<S: KvStore, K: KeyCreator>(provisioner: &Provisioner<S, K>, solana_pubkey: &str, query: &str) -> Result<Response> {
+    let solana_pubkey = SolanaPubkey::parse(&percent_decode(solana_pubkey)?)?;
+    let mut chain_ids = Vec::new();
+    for (name, value) in query.split('&').filter_map(|pair| pair.split_once('=')) {
+        if name == "chain_ids" {
+            for chain_id in percent_decode(value)?.split(',').filter(|chain_id| !chain_id.is_empty()) {
+                chain_ids.push(ChainId::resolve(chain_id)?);
+            }
+        }
+    }
+    let response = provisioner.handle_get(&solana_pubkey, &chain_ids)?;
+    Ok(Response::json(200, &response))
+}
+
+fn parse<T: DeserializeOwned>(body: &str) -> Result<T> {
+    serde_json::from_str(body).map_err(|e| ProvisionError::InvalidRequest(e.to_string()))
+}
+
+/// Decode `%XX` escapes in a path segment or query value
+fn percent_decode(raw: &str) -> Result<String> {
+    let invalid = || ProvisionError::InvalidRequest(format!("invalid percent-encoding in {:?}", raw));
+    let bytes = raw.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = raw.get(i + 1..i + 3).ok_or_else(invalid)?;
+            decoded.push(u8::from_str_radix(hex, 16).map_err(|_| invalid())?);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(decoded).map_err(|_| invalid())
+}
+
+/// Serve requests from `listener` until accepting a connection fails
+pub fn serve<S, K>(listener: TcpListener, provisioner: Arc<Provisioner<S, K>>) -> io::Result<()>
+where
+    S: KvStore + Send + Sync + 'static,
+    K: KeyCreator + Send + Sync + 'static,
+{
+    let active = Arc::new(AtomicUsize::new(0));
+    for stream in listener.incoming() {
+        let stream = stream?;
+        let Some(slot) = ConnectionSlot::acquire(&active) else {
+            let overloaded = Response::http_error(503, "OVERLOADED", format!("more than {} connections", MAX_CONNECTIONS));
+            let _ = write_response(stream, &overloaded);
+            continue;
+        };
+        let provisioner = Arc::clone(&provisioner);
+        thread::spawn(move || {
+            let _slot = slot;
+            // The client is gone or sent garbage; nothing to report it to
+            let _ = handle_connection(stream, &provisioner);
+        });
+    }
+    Ok(())
+}
+
+/// One of the `MAX_CONNECTIONS` connections served at once, freed on drop
+struct ConnectionSlot(Arc<AtomicUsize>);
+
+impl ConnectionSlot {
+    fn acquire(active: &Arc<AtomicUsize>) -> Option<Self> {
+        active
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |count| (count < MAX_CONNECTIONS).then_some(count + 1))
+            .ok()
+            .map(|_| Self(Arc::clone(active)))
+    }
+}
+
+impl Drop for ConnectionSlot {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+fn handle_connection<S: KvStore, K: KeyCreator>(stream: TcpStream, provisioner: &Provisioner<S, K>) -> io::Result<()> {
+    stream.set_read_timeout(Some(READ_TIMEOUT))?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let response = match read_request(&mut reader)? {
+        Ok(request) => route(provisioner, &request.requester, &request.method, &request.target, &request.body),
+        Err(response) => response,
+    };
+    write_response(stream, &response)
+}
+
+/// A request as read off the connection
+struct Request {
+    method: String,
+    target: String,
+    requester: Requester,
+    body: String,
+}
+
+/// The request on `reader`, or the response refusing it
+fn read_request(reader: &mut impl BufRead) -> io::Result<std::result::Result<Request, Response>> {
+    let bad_request = |message: &str| Ok(Err(Response::http_error(400, "BAD_REQUEST", message.to_string())));
+
+    let mut line = String::new();
+    if !read_line(reader, &mut line)? {
+        return Ok(Err(Response::http_error(414, "URI_TOO_LONG", format!("request line exceeds {} bytes", MAX_LINE_BYTES))));
+    }
+    let mut parts = line.split_whitespace();
+    let (Some(method), Some(target), Some(_version)) = (parts.next(), parts.next(), parts.next()) else {
+        return bad_request("malformed request line");
+    };
+    let (method, target) = (method.to_string(), target.to_string());
+
+    let mut content_length = 0;
+    let (mut identity, mut org_role) = (None, None);
+    for _ in 0..=MAX_HEADERS {
+        line.clear();
+        if !read_line(reader, &mut line)? {
+            let message = format!("header exceeds {} bytes", MAX_LINE_BYTES);
+            return Ok(Err(Response::http_error(431, "HEADER_TOO_LARGE", message)));
+        }
+        let header = line.trim_end();
+        if header.is_empty() {
+            if content_length > MAX_BODY_BYTES {
+                return Ok(Err(Response::http_error(413, "PAYLOAD_TOO_LARGE", format!("body exceeds {} bytes", MAX_BODY_BYTES))));
+            }
+            let mut body = vec![0; content_length];
+            reader.read_exact(&mut body)?;
+            let requester = Requester::new(identity.as_deref(), org_role.as_deref());
+            return match String::from_utf8(body) {
+                Ok(body) => Ok(Ok(Request { method, target, requester, body })),
+                Err(_) => bad_request("body is not UTF-8"),
+            };
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            let (name, value) = (name.trim(), value.trim());
+            if name.eq_ignore_ascii_case("content-length") {
+                match value.parse() {
+                    Ok(length) => content_length = length,
+                    Err(_) => return bad_request("invalid Content-Length"),
+                }
+            } else if name.eq_ignore_ascii_case(REQUESTER_IDENTITY_HEADER) {
+                identity = Some(value.to_string());
+            } else if name.eq_ignore_ascii_case(REQUESTER_ORG_ROLE_HEADER) {
+                org_role = Some(value.to_string());
+            }
+        }
+    }
+    bad_request("too many headers")
+}
+
+/// Read a line of at most `MAX_LINE_BYTES` into `line`; `false` if it is
+/// longer, having read no more than that
+fn read_line(reader: &mut impl BufRead, line: &mut String) -> io::Result<bool> {
+    reader.by_ref().take(MAX_LINE_BYTES as u64).read_line(line)?;
+    Ok(line.len() < MAX_LINE_BYTES || line.ends_with('\n'))
+}
+
+fn write_response(mut stream: TcpStream, response: &Response) -> io::Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        response.status,
+        reason(response.status),
+        response.body.len(),
+        response.body
+    )?;
+    stream.flush()
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        409 => "Conflict",
+        410 => "Gone",
+        413 => "Payload Too Large",
+        414 => "URI Too Long",
+        429 => "Too Many Requests",
+        431 => "Request Header Fields Too Large",
+        501 => "Not Implemented",
+        502 => "Bad Gateway",
+        503 => "Service Unavailable",
+        _ => "Internal Server Error",
+    }
+}
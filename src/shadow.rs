@@ -0,0 +1,164 @@
+//! Shadow Mode
+//!
+//! A new policy build can run next to production before it is trusted with
+//! writes. The tenant's configuration names it by git sha
+//! (`Config::shadow_build`, see `health::version`); that build runs every
+//! action as usual, checks included, over buckets whose writes stay in
+//! memory (`Shadowed`), and logs which keys it would have written. Its
+//! replies are the ones it would have sent, so they can be compared with
+//! production's. Other builds ignore the setting.
+//!
+//! Unlike a dry run (`dry_run`), nothing is exempt: audit records, metrics,
+//! rate limits and idempotency records are kept in memory too. Writes are
+//! shared across the buckets of one request, and dropped with it.
+//!
+//! Logged keys name Solana addresses only by `logging::pubkey_hash`.
+
+use crate::address::SolanaPubkey;
+use crate::error::Result;
+use crate::kv::KvStore;
+use crate::logging::pubkey_hash;
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::rc::Rc;
+
+/// Shortest accepted `shadow_build`, like an abbreviated git sha
+pub const MIN_SHADOW_BUILD_LEN: usize = 7;
+
+/// Whether the build at `git_sha` is the one `shadow_build` names (a prefix of
+/// its sha). Builds without a sha never are.
+pub fn is_shadow_build(shadow_build: Option<&str>, git_sha: Option<&str>) -> bool {
+    match (shadow_build, git_sha) {
+        (Some(shadow_build), Some(git_sha)) => git_sha.starts_with(shadow_build),
+        _ => false,
+    }
+}
+
+#[derive(Default)]
+struct Writes {
+    /// Value written (`None`: deleted), by bucket and key
+    values: BTreeMap<(String, String), Option<String>>,
+    /// Bucket and key of each write, in the order of its first write
+    order: Vec<(String, String)>,
+}
+
+/// Writes of one request in shadow mode, shared by its buckets
+#[derive(Clone, Default)]
+pub struct ShadowWrites(Rc<RefCell<Writes>>);
+
+impl ShadowWrites {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Keys written so far as `{bucket}/{key}`, in the order of their first
+    /// write, with Solana addresses replaced by their `pubkey_hash`
+    pub fn logged_keys(&self) -> Vec<String> {
+        self.0
+            .borrow()
+            .order
+            .iter()
+            .map(|(bucket, key)| format!("{}/{}", bucket, redacted_key(key)))
+            .collect()
+    }
+
+    fn value(&self, bucket: &str, key: &str) -> Option<Option<String>> {
+        self.0.borrow().values.get(&(bucket.to_string(), key.to_string())).cloned()
+    }
+
+    fn write(&self, bucket: &str, key: &str, value: Option<&str>) {
+        let mut writes = self.0.borrow_mut();
+        let id = (bucket.to_string(), key.to_string());
+        if writes.values.insert(id.clone(), value.map(str::to_string)).is_none() {
+            writes.order.push(id);
+        }
+    }
+}
+
+/// `key` with each Solana pubkey segment replaced by its `pubkey_hash`
+fn redacted_key(key: &str) -> String {
+    key.split(':')
+        .map(|segment| if SolanaPubkey::parse(segment).is_ok() { pubkey_hash(segment) } else { segment.to_string() })
+        .collect::<Vec<_>>()
+        .join(":")
+}
+
+/// A `KvStore` whose writes go to `ShadowWrites` instead (`None`: to the store)
+pub struct Shadowed<S> {
+    inner: S,
+    bucket: String,
+    writes: Option<ShadowWrites>,
+}
+
+impl<S: KvStore> Shadowed<S> {
+    /// `bucket` tells this bucket's writes apart from the others' of the request
+    pub fn new(inner: S, bucket: &str, writes: Option<ShadowWrites>) -> Self {
+        Self { inner, bucket: bucket.to_string(), writes }
+    }
+}
+
+impl<S: KvStore> KvStore for Shadowed<S> {
+    fn get(&self, key: &str) -> Result<Option<String>> {
+        match self.writes.as_ref().and_then(|writes| writes.value(&self.bucket, key)) {
+            Some(value) => Ok(value),
+            None => self.inner.get(key),
+        }
+    }
+
+    fn set_if_absent(&self, key: &str, value: &str) -> Result<bool> {
+        let Some(writes) = &self.writes else {
+            return self.inner.set_if_absent(key, value);
+        };
+        if self.get(key)?.is_some() {
+            return Ok(false);
+        }
+        writes.write(&self.bucket, key, Some(value));
+        Ok(true)
+    }
+
+    fn set(&self, key: &str, value: &str) -> Result<()> {
+        match &self.writes {
+            Some(writes) => writes.write(&self.bucket, key, Some(value)),
+            None => return self.inner.set(key, value),
+        }
+        Ok(())
+    }
+
+    fn get_many(&self, keys: &[String]) -> Result<Vec<Option<String>>> {
+        if self.writes.is_none() {
+            return self.inner.get_many(keys);
+        }
+        keys.iter().map(|key| self.get(key)).collect()
+    }
+
+    fn delete(&self, key: &str) -> Result<()> {
+        match &self.writes {
+            Some(writes) => writes.write(&self.bucket, key, None),
+            None => return self.inner.delete(key),
+        }
+        Ok(())
+    }
+
+    fn list_keys(&self, after: Option<&str>, limit: usize) -> Result<Vec<String>> {
+        let Some(writes) = &self.writes else {
+            return self.inner.list_keys(after, limit);
+        };
+        // Like `DryRunKv`: the first `limit` keys of the union are among the
+        // first `limit` of each side. Deleted keys can leave a page short.
+        let mut keys = self.inner.list_keys(after, limit)?;
+        let shadowed = writes.0.borrow();
+        keys.extend(
+            shadowed
+                .values
+                .keys()
+                .filter(|(bucket, key)| *bucket == self.bucket && after.is_none_or(|after| key.as_str() > after))
+                .map(|(_, key)| key.clone())
+                .take(limit),
+        );
+        keys.sort();
+        keys.dedup();
+        keys.retain(|key| shadowed.values.get(&(self.bucket.clone(), key.clone())) != Some(&None));
+        keys.truncate(limit);
+        Ok(keys)
+    }
+}
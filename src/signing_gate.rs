@@ -0,0 +1,176 @@
+//! Signing Gate
+//!
+//! The mapping store records which EVM keys belong to which Solana user; the
+//! signing gate enforces it when a key is used. A signing request is allowed
+//! only if the key's EVM address is the user's current mapping: the mapping of
+//! the chain being signed for (own or inherited from the default), or, when the
+//! request names no chain, the default or any chain mapping. Labeled addresses
+//! (`labels`) count like the chain mapping they sit next to. Keys a chain was
+//! rotated away from are refused, and so are external addresses the user
+//! linked from their own wallet: CubeSigner has no key for them.
+//!
+//! EVM transactions carry their `value`, which must also fit the user's
+//! spending limit on the chain (`spend_limits`), and their `to`, which must be
+//! on the user's allowlist for the chain if it has one (`destinations`).
+//!
+//! Every field of a `SigningRequest` decides the answer, so it is built from
+//! what CubeSigner authenticates, never from what the party asking to sign
+//! claims (`signing_request`): the user is the one an admin bound the
+//! requester's identity to (`set_signer`), the address is that of the key
+//! CubeSigner signs with, and the chain, value and recipient come from the
+//! transaction being signed.
+//!
+//! ## Key Schema
+//! ```text
+//! signer:{identity} → {solana_pubkey}   # null once unbound
+//! ```
+
+use crate::address::{EvmAddress, SolanaPubkey};
+use crate::chain_id::ChainId;
+use crate::destinations;
+use crate::error::{ProvisionError, Result};
+use crate::kv::KvStore;
+use crate::mapping;
+use crate::spend_limits::{self, Wei};
+use serde::Deserialize;
+
+/// Prefix of the signer bindings: the user each CubeSigner identity signs for
+pub const SIGNER_PREFIX: &str = "signer:";
+
+/// Prefix of CubeSigner's ids for secp256k1 keys, followed by the key's address
+pub const EVM_KEY_ID_PREFIX: &str = "Key#";
+
+/// What a signing request needs checked
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct SigningRequest {
+    /// User the signature is made for
+    pub solana_pubkey: SolanaPubkey,
+    /// Address of the signing key
+    pub evm_address: EvmAddress,
+    /// Chain the signed payload is for, when known
+    #[serde(default)]
+    pub chain_id: Option<ChainId>,
+    /// Value of the EVM transaction being signed; requires `chain_id`
+    #[serde(default)]
+    pub value: Option<Wei>,
+    /// Recipient of the EVM transaction being signed; requires `chain_id`.
+    /// A transaction with a `value` but no `to` deploys a contract.
+    #[serde(default)]
+    pub to: Option<EvmAddress>,
+}
+
+pub fn signer_key(identity: &str) -> String {
+    format!("{}{}", SIGNER_PREFIX, identity)
+}
+
+/// The user `identity` signs for, if an admin bound it to one
+pub fn get_signer(kv: &impl KvStore, identity: &str) -> Result<Option<SolanaPubkey>> {
+    match kv.get(&signer_key(identity))? {
+        Some(raw) => serde_json::from_str(&raw).map_err(|e| ProvisionError::corrupt(format!("signer {}", identity), e)),
+        None => Ok(None),
+    }
+}
+
+/// Bind `identity` to the user it signs for, or unbind it (`None`)
+pub fn set_signer(kv: &impl KvStore, identity: &str, solana_pubkey: Option<&SolanaPubkey>) -> Result<()> {
+    if identity.trim().is_empty() {
+        return Err(ProvisionError::InvalidRequest("identity cannot be empty".to_string()));
+    }
+    let raw = serde_json::to_string(&solana_pubkey).expect("signer serialization cannot fail");
+    kv.set(&signer_key(identity), &raw)
+}
+
+/// The address of a CubeSigner secp256k1 key, from its id (`Key#0x…`)
+pub fn key_address(key_id: &str) -> Result<EvmAddress> {
+    key_id
+        .strip_prefix(EVM_KEY_ID_PREFIX)
+        .filter(|address| address.starts_with("0x"))
+        .ok_or_else(|| ProvisionError::InvalidRequest(format!("{} is not the id of an EVM key", key_id)))
+        .and_then(EvmAddress::parse)
+}
+
+/// Body of a CubeSigner EVM transaction signing request; other signing
+/// requests (messages, typed data, …) have no `tx`
+#[derive(Deserialize, Default)]
+struct EvmSignBody {
+    #[serde(default)]
+    chain_id: Option<u64>,
+    #[serde(default)]
+    tx: Option<EvmTransaction>,
+}
+
+#[derive(Deserialize)]
+struct EvmTransaction {
+    #[serde(default)]
+    to: Option<EvmAddress>,
+    #[serde(default)]
+    value: Option<Wei>,
+}
+
+/// The signing request of `identity`, an authenticated CubeSigner identity,
+/// signing `body` with the key `key_id`. Fails with `UnknownSigner` if no
+/// admin bound the identity to a user.
+pub fn signing_request(kv: &impl KvStore, identity: &str, key_id: &str, body: Option<&str>) -> Result<SigningRequest> {
+    let solana_pubkey = match identity {
+        "" => None,
+        identity => get_signer(kv, identity)?,
+    }
+    .ok_or_else(|| ProvisionError::UnknownSigner(identity.to_string()))?;
+    let evm_address = key_address(key_id)?;
+    let body: EvmSignBody = match body {
+        Some(body) => serde_json::from_str(body).map_err(|e| ProvisionError::InvalidRequest(e.to_string()))?,
+        None => EvmSignBody::default(),
+    };
+    let (to, value) = match body.tx {
+        Some(tx) => (tx.to, Some(tx.value.unwrap_or(Wei(0)))),
+        None => (None, None),
+    };
+    Ok(SigningRequest { solana_pubkey, evm_address, chain_id: body.chain_id.map(ChainId::eip155), value, to })
+}
+
+/// Fail with `AddressNotMapped` unless `req.evm_address` is a current mapping
+/// of `req.solana_pubkey`, with `ExternalAddress` if it is one CubeSigner
+/// cannot sign for, with `DestinationNotAllowed` if the transaction goes
+/// somewhere the user's allowlist does not permit, or with
+/// `SpendLimitExceeded` if `req.value` breaks the user's limit. An allowed
+/// value counts towards the day's total.
+pub fn authorize(kv: &impl KvStore, req: &SigningRequest, now: u64) -> Result<()> {
+    if (req.value.is_some() || req.to.is_some()) && req.chain_id.is_none() {
+        return Err(ProvisionError::InvalidRequest("a transaction needs the chain it is for".to_string()));
+    }
+
+    // Every chain when the request names none
+    let found = mapping::get(kv, &req.solana_pubkey, req.chain_id.as_slice(), now)?;
+    let mapped = match &req.chain_id {
+        Some(chain_id) => {
+            found.chain_mappings.get(chain_id) == Some(&req.evm_address)
+                || found.labeled_mappings.get(chain_id).is_some_and(|labeled| labeled.values().any(|address| *address == req.evm_address))
+        }
+        None => {
+            found.default_address.as_ref() == Some(&req.evm_address)
+                || found.chain_mappings.values().any(|address| *address == req.evm_address)
+                || found.labeled_mappings.values().flat_map(|labeled| labeled.values()).any(|address| *address == req.evm_address)
+        }
+    };
+
+    if !mapped {
+        return Err(ProvisionError::AddressNotMapped {
+            evm_address: req.evm_address.to_string(),
+            solana_pubkey: req.solana_pubkey.to_string(),
+        });
+    }
+    if found.external_addresses.contains(&req.evm_address) {
+        return Err(ProvisionError::ExternalAddress(req.evm_address.to_string()));
+    }
+    let Some(chain_id) = &req.chain_id else {
+        return Ok(());
+    };
+    // Anything else is not a transaction (a message, typed data, …)
+    if req.value.is_some() || req.to.is_some() {
+        destinations::check(kv, &req.solana_pubkey, chain_id, req.to.as_ref())?;
+    }
+    match req.value {
+        Some(value) => spend_limits::check_and_record(kv, &req.solana_pubkey, chain_id, value, now),
+        None => Ok(()),
+    }
+}
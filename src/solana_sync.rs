@@ -0,0 +1,251 @@
+//! Solana Program Sync (`solana-sync` feature)
+//!
+//! Mirrors mappings into the Solana mapping program, so Solana programs and
+//! dApps can read a user's EVM address on-chain. The program keeps one PDA per
+//! Solana address, at seeds `["mapping", solana_pubkey]`, holding the default
+//! EVM address (the one `store` maps on every chain). Per-chain rotations are
+//! not mirrored; `onchain` covers those on the EVM side.
+//!
+//! Syncing a user:
+//! 1. `sync` builds the program's `set_mapping` instruction, wraps it in a
+//!    transaction paid for by the org's operator key, has the operator sign it
+//!    (`OperatorSigner`, `CubeSignerOperator` for a CubeSigner key) and
+//!    records the sync as `signed`, with the transaction.
+//! 2. The caller sends the transaction (`sendTransaction`, base64) and reports
+//!    progress with `record_status`: `submitted` with the transaction
+//!    signature, then `confirmed` or `failed`. A transaction whose blockhash
+//!    expired before it landed is `failed`; the next `sync` signs it again
+//!    with the blockhash it is given.
+//!
+//! `set_mapping` creates the PDA or overwrites it with the same address, so
+//! sending a transaction twice is harmless.
+//!
+//! ## Key Schema
+//! ```text
+//! solana_sync:{solana_pubkey}  → SyncRecord  # Latest sync of the user's PDA
+//! ```
+
+use crate::address::{EvmAddress, SolanaPubkey};
+use crate::cubesigner_client::{CubeSignerClient, HttpTransport};
+use crate::error::{ProvisionError, Result};
+use crate::kv::KvStore;
+use crate::mapping;
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use solana_hash::Hash;
+use solana_instruction::{AccountMeta, Instruction};
+use solana_message::Message;
+use solana_pubkey::Pubkey;
+
+/// Seed prefix of the mapping PDAs
+pub const MAPPING_SEED: &[u8] = b"mapping";
+
+/// System program, which creates the PDA account on the first `set_mapping`
+const SYSTEM_PROGRAM_ID: Pubkey = Pubkey::new_from_array([0; 32]);
+
+/// Key of a user's sync record: `solana_sync:{solana_pubkey}`
+pub fn sync_key(solana_pubkey: &SolanaPubkey) -> String {
+    format!("solana_sync:{}", solana_pubkey.as_str())
+}
+
+fn to_pubkey(solana_pubkey: &SolanaPubkey) -> Pubkey {
+    Pubkey::new_from_array(solana_pubkey.to_bytes())
+}
+
+fn from_pubkey(pubkey: &Pubkey) -> SolanaPubkey {
+    SolanaPubkey::parse(&pubkey.to_string()).expect("Pubkey is 32 bytes")
+}
+
+/// PDA holding the mapping of `solana_pubkey`, and its bump seed
+pub fn mapping_address(program_id: &SolanaPubkey, solana_pubkey: &SolanaPubkey) -> (SolanaPubkey, u8) {
+    let (pda, bump) = Pubkey::find_program_address(&[MAPPING_SEED, &solana_pubkey.to_bytes()], &to_pubkey(program_id));
+    (from_pubkey(&pda), bump)
+}
+
+/// The program's `set_mapping(solana_pubkey, evm_address)` instruction:
+/// Anchor discriminator (`sha256("global:set_mapping")[..8]`), then the
+/// arguments. Accounts: the PDA (writable), the operator (signer, pays the
+/// rent) and the system program.
+pub fn set_mapping_instruction(
+    program_id: &SolanaPubkey,
+    operator: &SolanaPubkey,
+    solana_pubkey: &SolanaPubkey,
+    evm_address: &EvmAddress,
+) -> Instruction {
+    let mut data = Sha256::digest(b"global:set_mapping")[..8].to_vec();
+    data.extend_from_slice(&solana_pubkey.to_bytes());
+    data.extend_from_slice(&evm_address_bytes(evm_address));
+
+    let (pda, _) = mapping_address(program_id, solana_pubkey);
+    let accounts = vec![
+        AccountMeta::new(to_pubkey(&pda), false),
+        AccountMeta::new(to_pubkey(operator), true),
+        AccountMeta::new_readonly(SYSTEM_PROGRAM_ID, false),
+    ];
+    Instruction::new_with_bytes(to_pubkey(program_id), &data, accounts)
+}
+
+fn evm_address_bytes(evm_address: &EvmAddress) -> [u8; 20] {
+    let hex = &evm_address.as_str()[2..];
+    std::array::from_fn(|i| u8::from_str_radix(&hex[2 * i..2 * i + 2], 16).expect("EvmAddress is validated"))
+}
+
+// =============================================================================
+// SIGNING
+// =============================================================================
+
+/// Signs transactions with the org's operator key, the only key the mapping
+/// program accepts `set_mapping` from
+pub trait OperatorSigner {
+    /// The operator's address (fee payer and only signer)
+    fn operator(&self) -> &SolanaPubkey;
+
+    /// ed25519 signature of a serialized transaction message
+    fn sign_message(&self, message: &[u8]) -> Result<[u8; 64]>;
+}
+
+/// `OperatorSigner` over a CubeSigner Solana key
+pub struct CubeSignerOperator<T> {
+    client: CubeSignerClient<T>,
+    operator: SolanaPubkey,
+}
+
+impl<T: HttpTransport> CubeSignerOperator<T> {
+    /// Sign with the CubeSigner key at `operator`; the client's session needs
+    /// the `sign:solana` scope
+    pub fn new(client: CubeSignerClient<T>, operator: SolanaPubkey) -> Self {
+        Self { client, operator }
+    }
+}
+
+impl<T: HttpTransport> OperatorSigner for CubeSignerOperator<T> {
+    fn operator(&self) -> &SolanaPubkey {
+        &self.operator
+    }
+
+    fn sign_message(&self, message: &[u8]) -> Result<[u8; 64]> {
+        let signature = self
+            .client
+            .solana_sign(self.operator.as_str(), &BASE64.encode(message))
+            .map_err(|e| ProvisionError::SigningFailed { message: e.to_string(), retryable: e.is_retryable() })?;
+        decode_signature(&signature)
+            .ok_or_else(|| ProvisionError::SigningFailed { message: format!("Malformed signature {}", signature), retryable: false })
+    }
+}
+
+fn decode_signature(signature: &str) -> Option<[u8; 64]> {
+    let hex = signature.strip_prefix("0x").unwrap_or(signature);
+    if hex.len() != 128 || !hex.is_ascii() {
+        return None;
+    }
+    let mut bytes = [0u8; 64];
+    for (i, byte) in bytes.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hex[2 * i..2 * i + 2], 16).ok()?;
+    }
+    Some(bytes)
+}
+
+/// Wire form of a transaction with one signature: compact-u16 count (1),
+/// the signature, the message
+fn signed_transaction(signature: &[u8; 64], message: &[u8]) -> Vec<u8> {
+    let mut tx = Vec::with_capacity(1 + 64 + message.len());
+    tx.push(1);
+    tx.extend_from_slice(signature);
+    tx.extend_from_slice(message);
+    tx
+}
+
+// =============================================================================
+// SYNC STATUS
+// =============================================================================
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum SyncStatus {
+    /// Signed, not yet reported as sent
+    Signed,
+    Submitted { signature: String },
+    Confirmed { signature: String },
+    /// Failed, expired or never sent; the next `sync` signs again
+    Failed { error: String },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SyncRecord {
+    /// EVM address the transaction writes
+    #[serde(with = "crate::address::lowercase")]
+    pub address: EvmAddress,
+    /// The mapping program
+    pub program_id: SolanaPubkey,
+    /// The signed transaction, base64 (as `sendTransaction` takes it)
+    pub signed_tx: String,
+    #[serde(flatten)]
+    pub status: SyncStatus,
+    /// Unix timestamp (seconds) of the last status change
+    pub updated_at: u64,
+}
+
+pub fn get_sync(kv: &impl KvStore, solana_pubkey: &SolanaPubkey) -> Result<Option<SyncRecord>> {
+    kv.get(&sync_key(solana_pubkey))?
+        .map(|raw| serde_json::from_str(&raw).map_err(|e| ProvisionError::corrupt(sync_key(solana_pubkey), e)))
+        .transpose()
+}
+
+fn put_sync(kv: &impl KvStore, solana_pubkey: &SolanaPubkey, record: &SyncRecord) -> Result<()> {
+    let raw = serde_json::to_string(record).expect("sync record serialization cannot fail");
+    kv.set(&sync_key(solana_pubkey), &raw)
+}
+
+// =============================================================================
+// SYNC
+// =============================================================================
+
+/// Sign the `set_mapping` transaction writing the default address of
+/// `solana_pubkey` to its PDA, with `recent_blockhash` (base58, from the
+/// cluster's `getLatestBlockhash`), and record it as `signed`. Returns `None`
+/// if the address was already signed for and that sync has not failed.
+pub fn sync(
+    kv: &impl KvStore,
+    signer: &impl OperatorSigner,
+    program_id: &SolanaPubkey,
+    solana_pubkey: &SolanaPubkey,
+    recent_blockhash: &str,
+    now: u64,
+) -> Result<Option<SyncRecord>> {
+    let blockhash: Hash = recent_blockhash
+        .parse()
+        .map_err(|_| ProvisionError::InvalidRequest(format!("Invalid blockhash: {}", recent_blockhash)))?;
+    let address = mapping::require_provisioned(kv, solana_pubkey)?.address;
+
+    if let Some(last) = get_sync(kv, solana_pubkey)? {
+        let failed = matches!(last.status, SyncStatus::Failed { .. });
+        if last.address == address && last.program_id == *program_id && !failed {
+            return Ok(None);
+        }
+    }
+
+    let instruction = set_mapping_instruction(program_id, signer.operator(), solana_pubkey, &address);
+    let message = Message::new_with_blockhash(&[instruction], Some(&to_pubkey(signer.operator())), &blockhash).serialize();
+    let signature = signer.sign_message(&message)?;
+
+    let record = SyncRecord {
+        address,
+        program_id: program_id.clone(),
+        signed_tx: BASE64.encode(signed_transaction(&signature, &message)),
+        status: SyncStatus::Signed,
+        updated_at: now,
+    };
+    put_sync(kv, solana_pubkey, &record)?;
+    Ok(Some(record))
+}
+
+/// Report what became of the latest transaction signed for `solana_pubkey`
+pub fn record_status(kv: &impl KvStore, solana_pubkey: &SolanaPubkey, status: SyncStatus, now: u64) -> Result<SyncRecord> {
+    let record = get_sync(kv, solana_pubkey)?
+        .ok_or_else(|| ProvisionError::InvalidRequest(format!("No Solana sync for {}", solana_pubkey)))?;
+
+    let record = SyncRecord { status, updated_at: now, ..record };
+    put_sync(kv, solana_pubkey, &record)?;
+    Ok(record)
+}
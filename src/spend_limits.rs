@@ -0,0 +1,195 @@
+//! Spending Limits
+//!
+//! Risk caps what a user's keys may sign for. A limit sits on a mapping record
+//! (`MappingRecord::spend_limit`): a chain record's limit covers that chain,
+//! the default record's covers every chain without a limit of its own. The
+//! signing gate (`signing_gate`, the policy's `signing-gate` build) enforces
+//! it on EVM transactions, on the `value` of the transaction being signed:
+//!
+//! - `max_tx_value`: largest value of a single transaction
+//! - `daily_cap`: most value signed per UTC day on one chain
+//!
+//! Amounts are in the chain's smallest native unit (wei), so a limit is per
+//! chain: a default limit of 1 ETH is 1 MATIC on Polygon.
+//!
+//! The day's total is read, checked and written back without a lock, so two
+//! signatures racing on the same chain can both pass. A value is counted when
+//! the gate allows it, whether or not the signature is then made.
+//!
+//! ## Key Schema
+//! ```text
+//! spent:{solana_pubkey}:{chain_id}:{day} → decimal wei   # day = unix time / 86400
+//! ```
+
+use crate::address::SolanaPubkey;
+use crate::chain_id::ChainId;
+use crate::error::{ProvisionError, Result};
+use crate::kv::{self, KvStore, MappingRecord};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+const SECS_PER_DAY: u64 = 86_400;
+
+/// An amount in wei. Written as a decimal string; read from a decimal or
+/// `0x` hex string, or a JSON number (up to `u64::MAX`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub struct Wei(pub u128);
+
+impl Serialize for Wei {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.0.to_string())
+    }
+}
+
+impl<'de> Deserialize<'de> for Wei {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Raw {
+            Number(u64),
+            String(String),
+        }
+        let parsed = match Raw::deserialize(deserializer)? {
+            Raw::Number(value) => Some(value.into()),
+            Raw::String(value) => match value.strip_prefix("0x") {
+                Some(hex) => u128::from_str_radix(hex, 16).ok(),
+                None => value.parse().ok(),
+            },
+        };
+        parsed.map(Wei).ok_or_else(|| serde::de::Error::custom("expected an amount in wei"))
+    }
+}
+
+#[cfg(feature = "openapi")]
+impl schemars::JsonSchema for Wei {
+    fn schema_name() -> std::borrow::Cow<'static, str> {
+        "Wei".into()
+    }
+
+    fn json_schema(_: &mut schemars::SchemaGenerator) -> schemars::Schema {
+        schemars::json_schema!({
+            "type": "string",
+            "pattern": "^([0-9]+|0x[0-9a-fA-F]+)$",
+            "description": "Amount in wei, as a decimal or 0x-prefixed hex string",
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct SpendLimit {
+    /// Largest value of a single transaction
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_tx_value: Option<Wei>,
+    /// Most value signed per UTC day on one chain
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub daily_cap: Option<Wei>,
+}
+
+impl SpendLimit {
+    pub fn is_empty(&self) -> bool {
+        self.max_tx_value.is_none() && self.daily_cap.is_none()
+    }
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct SpendLimitStatus {
+    /// The limit in force: the chain's own, else the default's
+    pub spend_limit: Option<SpendLimit>,
+    /// Value signed on the chain today (only when a chain was asked for)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub spent_today: Option<Wei>,
+}
+
+/// Key of one day's total: `spent:{solana_pubkey}:{chain_id}:{day}`
+pub fn spent_key(solana_pubkey: &SolanaPubkey, chain_id: &ChainId, day: u64) -> String {
+    format!("spent:{}:{}:{}", solana_pubkey.as_str(), chain_id, day)
+}
+
+/// Set (or with `None`, clear) the limit of the user's default record, or of
+/// their own record for `chain_id`. Chains that inherit the default have no
+/// record of their own to limit. Returns the updated record.
+pub fn set_spend_limit(
+    kv: &impl KvStore,
+    solana_pubkey: &SolanaPubkey,
+    chain_id: Option<&ChainId>,
+    spend_limit: Option<SpendLimit>,
+) -> Result<MappingRecord> {
+    let (key, record) = match chain_id {
+        None => (kv::default_key(solana_pubkey), kv::get_default_mapping(kv, solana_pubkey)?),
+        Some(chain_id) => (kv::chain_key(solana_pubkey, chain_id), kv::get_chain_mapping(kv, solana_pubkey, chain_id)?),
+    };
+    let mut record = match (record, chain_id) {
+        (Some(record), _) => record,
+        (None, None) => return Err(ProvisionError::NotProvisioned(solana_pubkey.to_string())),
+        (None, Some(chain_id)) => {
+            return Err(ProvisionError::InvalidRequest(format!(
+                "{} has no mapping of its own on chain {}; limit the default instead",
+                solana_pubkey, chain_id
+            )))
+        }
+    };
+    record.spend_limit = spend_limit.filter(|limit| !limit.is_empty());
+    kv.set(&key, &record.encode())?;
+    Ok(record)
+}
+
+/// Limit in force on `chain_id`: the chain record's, else the default's
+pub fn effective_limit(kv: &impl KvStore, solana_pubkey: &SolanaPubkey, chain_id: &ChainId) -> Result<Option<SpendLimit>> {
+    if let Some(limit) = kv::get_chain_mapping(kv, solana_pubkey, chain_id)?.and_then(|record| record.spend_limit) {
+        return Ok(Some(limit));
+    }
+    Ok(kv::get_default_mapping(kv, solana_pubkey)?.and_then(|record| record.spend_limit))
+}
+
+/// The limit in force on `chain_id` (the default's without a chain), and what
+/// was signed there today
+pub fn status(kv: &impl KvStore, solana_pubkey: &SolanaPubkey, chain_id: Option<&ChainId>, now: u64) -> Result<SpendLimitStatus> {
+    match chain_id {
+        Some(chain_id) => Ok(SpendLimitStatus {
+            spend_limit: effective_limit(kv, solana_pubkey, chain_id)?,
+            spent_today: Some(spent(kv, solana_pubkey, chain_id, now / SECS_PER_DAY)?),
+        }),
+        None => Ok(SpendLimitStatus {
+            spend_limit: kv::get_default_mapping(kv, solana_pubkey)?.and_then(|record| record.spend_limit),
+            spent_today: None,
+        }),
+    }
+}
+
+/// Fail with `SpendLimitExceeded` if signing a transaction of `value` on
+/// `chain_id` breaks the limit in force; otherwise count it towards today's total
+pub fn check_and_record(kv: &impl KvStore, solana_pubkey: &SolanaPubkey, chain_id: &ChainId, value: Wei, now: u64) -> Result<()> {
+    let Some(limit) = effective_limit(kv, solana_pubkey, chain_id)? else {
+        return Ok(());
+    };
+    if let Some(max) = limit.max_tx_value.filter(|max| value > *max) {
+        return Err(ProvisionError::SpendLimitExceeded {
+            limit: "max_tx_value",
+            value: value.0.to_string(),
+            allowed: max.0.to_string(),
+        });
+    }
+    let Some(cap) = limit.daily_cap else {
+        return Ok(());
+    };
+
+    let day = now / SECS_PER_DAY;
+    let spent = spent(kv, solana_pubkey, chain_id, day)?;
+    let total = spent.0.saturating_add(value.0);
+    if total > cap.0 {
+        return Err(ProvisionError::SpendLimitExceeded {
+            limit: "daily_cap",
+            value: value.0.to_string(),
+            allowed: cap.0.saturating_sub(spent.0).to_string(),
+        });
+    }
+    kv.set(&spent_key(solana_pubkey, chain_id, day), &total.to_string())
+}
+
+fn spent(kv: &impl KvStore, solana_pubkey: &SolanaPubkey, chain_id: &ChainId, day: u64) -> Result<Wei> {
+    match kv.get(&spent_key(solana_pubkey, chain_id, day))? {
+        Some(raw) => raw.parse().map(Wei).map_err(|e| ProvisionError::corrupt("daily spend total", e)),
+        None => Ok(Wei::default()),
+    }
+}
@@ -0,0 +1,203 @@
+//! Tenant Namespaces
+//!
+//! One deployment can serve several products (tenants) from the same buckets.
+//! Each tenant's keys live under `tenant:{tenant_id}:`, and `Namespaced` maps
+//! every key a flow reads, writes or lists into its tenant's namespace, so
+//! the flows stay tenant-agnostic and a request for one tenant cannot reach
+//! another's keys.
+//!
+//! Requests without a tenant use the default namespace: the unprefixed keys
+//! written before tenants existed. The `tenant:` prefix is reserved there, so
+//! the default namespace cannot read, write or list tenants' keys either.
+//!
+//! Only a tenant's members may act in it (`require_member`): the identities
+//! org owners list for it in the `config` bucket of the default namespace,
+//! where no tenant's admins can reach. A tenant without a list has no members.
+//!
+//! ## Key Schema
+//! ```text
+//! tenant:{tenant_id}:{key}            # `key` as in the default namespace
+//! tenant_members:{tenant_id} → [identity]   # `config` bucket, default namespace
+//! ```
+
+use crate::error::{ProvisionError, Result};
+use crate::kv::KvStore;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Prefix reserved for tenants' keys
+pub const TENANT_PREFIX: &str = "tenant:";
+
+/// Prefix of the member lists, in the `config` bucket
+pub const MEMBERS_PREFIX: &str = "tenant_members:";
+
+/// Most identities one tenant may list
+pub const MAX_TENANT_MEMBERS: usize = 100;
+
+/// Longest tenant id accepted
+pub const MAX_TENANT_ID_LEN: usize = 32;
+
+/// Sorts after every tenant key (tenant ids are `[a-z0-9-]`), so listing in
+/// the default namespace skips them all in one step
+const TENANT_KEYS_END: &str = "tenant:\u{7f}";
+
+/// Tenant discriminator: 1-32 chars of `[a-z0-9-]`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+#[serde(try_from = "String", into = "String")]
+pub struct TenantId(String);
+
+impl TenantId {
+    pub fn parse(id: &str) -> Result<Self> {
+        let valid = !id.is_empty()
+            && id.len() <= MAX_TENANT_ID_LEN
+            && id.bytes().all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'-');
+        if !valid {
+            return Err(ProvisionError::InvalidRequest(format!(
+                "invalid tenant {:?} (expected 1-{} chars of [a-z0-9-])",
+                id, MAX_TENANT_ID_LEN
+            )));
+        }
+        Ok(Self(id.to_string()))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl TryFrom<String> for TenantId {
+    type Error = ProvisionError;
+
+    fn try_from(id: String) -> Result<Self> {
+        Self::parse(&id)
+    }
+}
+
+impl From<TenantId> for String {
+    fn from(id: TenantId) -> Self {
+        id.0
+    }
+}
+
+impl fmt::Display for TenantId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// Whether `key` belongs to a tenant's namespace
+pub fn is_tenant_key(key: &str) -> bool {
+    key.starts_with(TENANT_PREFIX)
+}
+
+pub fn members_key(tenant: &TenantId) -> String {
+    format!("{}{}", MEMBERS_PREFIX, tenant)
+}
+
+/// Identities allowed to act in `tenant` (none until an org owner lists them)
+pub fn get_members(kv: &impl KvStore, tenant: &TenantId) -> Result<Vec<String>> {
+    match kv.get(&members_key(tenant))? {
+        Some(raw) => serde_json::from_str(&raw).map_err(|e| ProvisionError::corrupt("tenant members", e)),
+        None => Ok(Vec::new()),
+    }
+}
+
+/// Replace `tenant`'s members; an empty list shuts everyone out
+pub fn set_members(kv: &impl KvStore, tenant: &TenantId, mut members: Vec<String>) -> Result<Vec<String>> {
+    if members.len() > MAX_TENANT_MEMBERS {
+        return Err(ProvisionError::InvalidRequest(format!("a tenant lists at most {} members", MAX_TENANT_MEMBERS)));
+    }
+    if members.iter().any(|identity| identity.trim().is_empty()) {
+        return Err(ProvisionError::InvalidRequest("members must be non-empty identities".to_string()));
+    }
+    members.sort_unstable();
+    members.dedup();
+    let raw = serde_json::to_string(&members).expect("member list serialization cannot fail");
+    kv.set(&members_key(tenant), &raw)?;
+    Ok(members)
+}
+
+/// Fail unless `identity` is one of `tenant`'s members. `kv` is the `config`
+/// bucket of the default namespace.
+pub fn require_member(kv: &impl KvStore, tenant: &TenantId, identity: &str) -> Result<()> {
+    if identity.is_empty() || !get_members(kv, tenant)?.iter().any(|member| member == identity) {
+        return Err(ProvisionError::NotTenantMember { identity: identity.to_string(), tenant: tenant.to_string() });
+    }
+    Ok(())
+}
+
+/// A `KvStore` restricted to one tenant's namespace (`None`: the default one)
+pub struct Namespaced<S> {
+    inner: S,
+    prefix: Option<String>,
+}
+
+impl<S: KvStore> Namespaced<S> {
+    pub fn new(inner: S, tenant: Option<&TenantId>) -> Self {
+        Self {
+            inner,
+            prefix: tenant.map(|tenant| format!("{}{}:", TENANT_PREFIX, tenant)),
+        }
+    }
+
+    /// The key `key` is stored under
+    fn key(&self, key: &str) -> Result<String> {
+        match &self.prefix {
+            Some(prefix) => Ok(format!("{}{}", prefix, key)),
+            None if is_tenant_key(key) => {
+                Err(ProvisionError::InvalidRequest(format!("key {} is reserved for tenant namespaces", key)))
+            }
+            None => Ok(key.to_string()),
+        }
+    }
+}
+
+impl<S: KvStore> KvStore for Namespaced<S> {
+    fn get(&self, key: &str) -> Result<Option<String>> {
+        self.inner.get(&self.key(key)?)
+    }
+
+    fn set_if_absent(&self, key: &str, value: &str) -> Result<bool> {
+        self.inner.set_if_absent(&self.key(key)?, value)
+    }
+
+    fn set(&self, key: &str, value: &str) -> Result<()> {
+        self.inner.set(&self.key(key)?, value)
+    }
+
+    fn get_many(&self, keys: &[String]) -> Result<Vec<Option<String>>> {
+        let keys = keys.iter().map(|key| self.key(key)).collect::<Result<Vec<_>>>()?;
+        self.inner.get_many(&keys)
+    }
+
+    fn delete(&self, key: &str) -> Result<()> {
+        self.inner.delete(&self.key(key)?)
+    }
+
+    fn list_keys(&self, after: Option<&str>, limit: usize) -> Result<Vec<String>> {
+        match &self.prefix {
+            Some(prefix) => {
+                // The namespace's keys sort together, right after the bare prefix
+                let start = format!("{}{}", prefix, after.unwrap_or_default());
+                let keys = self.inner.list_keys(Some(&start), limit)?;
+                Ok(keys.into_iter().map_while(|key| key.strip_prefix(prefix.as_str()).map(str::to_string)).collect())
+            }
+            None => {
+                let mut keys = Vec::new();
+                let mut cursor = after.map(str::to_string);
+                loop {
+                    let wanted = limit - keys.len();
+                    let page = self.inner.list_keys(cursor.as_deref(), wanted)?;
+                    let exhausted = page.len() < wanted;
+                    let last = page.last().cloned();
+                    keys.extend(page.into_iter().filter(|key| !is_tenant_key(key)));
+                    if exhausted || keys.len() == limit {
+                        return Ok(keys);
+                    }
+                    // The page ran into the tenants' keys: continue after all of them
+                    cursor = last.map(|last| if is_tenant_key(&last) { TENANT_KEYS_END.to_string() } else { last });
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,377 @@
+//! Test Doubles (`testing` feature)
+//!
+//! The mocks and harness the crate's own integration tests run on, for
+//! backends embedding the library to write theirs against the same ones:
+//! - `MockKvStore`: in-memory `KvStore` recording write and delete attempts,
+//!   whose inherent `delete` always fails (mappings are never deleted)
+//! - `MockKeyCreator`: CubeSigner stand-in handing out counter-based addresses
+//!   (`mock_key`): `0x…01`, `0x…02`, … for default keys and from `0x…03e9`
+//!   (1001) for chain-specific ones
+//! - `TestContext`: a `Provisioner` over both, plus helpers reading the store
+//! - `admins` / `admin`: an allowlist of one admin (`TEST_ADMIN`) and that
+//!   admin as a requester, for the admin handlers
+//! - Request builders signed by deterministic wallets (`wallet`, `provision_request`, …)
+//!
+//! ```ignore
+//! use cubist_wallet_provisioner::testing::{evm, provision_request, wallet, TestContext};
+//!
+//! let ctx = TestContext::new();
+//! let response = ctx.handle(provision_request(&wallet(1), vec![1, 137])).unwrap();
+//! assert_eq!(response.evm_address, evm("0x0000000000000000000000000000000000000001"));
+//! ```
+//!
+//! For addresses derived like real keys, use `dev_keys` (`dev-keys` feature).
+
+use crate::address::{EvmAddress, SolanaPubkey};
+use crate::admin::{self, Requester};
+use crate::auth;
+use crate::chain_id::ChainId;
+use crate::error::{ProvisionError, Result};
+use crate::keys::{CreatedKey, KeyClass, KeyCreator, KeyLister, KeyType, ListedKey, SolanaKeyCreator};
+use crate::kv::{self, KvStore, MappingRecord};
+use crate::{ProvisionRequest, ProvisionResponse, Provisioner, SetChainRequest, UpdateMappingRequest, UpdateMappingResponse, UpdateSelfRequest};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use ed25519_dalek::{Signer, SigningKey};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// Nonce of the next store request a builder signs: one counter for the
+/// process, so each wallet's nonces grow in the order its requests are built
+static NEXT_NONCE: AtomicU64 = AtomicU64::new(1);
+
+/// Mock KV store for testing
+#[derive(Clone, Default)]
+pub struct MockKvStore {
+    pub data: Arc<Mutex<HashMap<String, String>>>,
+    pub write_attempts: Arc<Mutex<Vec<String>>>,
+    pub delete_attempts: Arc<Mutex<Vec<String>>>,
+}
+
+impl MockKvStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Attempt to delete a key - should always fail for immutable storage
+    pub fn delete(&self, key: &str) -> Result<()> {
+        self.delete_attempts.lock().unwrap().push(key.to_string());
+        Err(ProvisionError::Unsupported("Delete"))
+    }
+}
+
+impl KvStore for MockKvStore {
+    fn get(&self, key: &str) -> Result<Option<String>> {
+        Ok(self.data.lock().unwrap().get(key).cloned())
+    }
+
+    /// Atomic write - only inserts if the key doesn't exist (IfExists::Deny)
+    fn set_if_absent(&self, key: &str, value: &str) -> Result<bool> {
+        self.write_attempts.lock().unwrap().push(key.to_string());
+
+        let mut data = self.data.lock().unwrap();
+        if data.contains_key(key) {
+            return Ok(false);
+        }
+        data.insert(key.to_string(), value.to_string());
+        Ok(true)
+    }
+
+    /// Set with overwrite allowed (for admin updates)
+    fn set(&self, key: &str, value: &str) -> Result<()> {
+        let mut data = self.data.lock().unwrap();
+        data.insert(key.to_string(), value.to_string());
+        Ok(())
+    }
+
+    fn list_keys(&self, after: Option<&str>, limit: usize) -> Result<Vec<String>> {
+        let data = self.data.lock().unwrap();
+        let mut keys: Vec<String> = data.keys().filter(|k| after.is_none_or(|a| k.as_str() > a)).cloned().collect();
+        keys.sort();
+        keys.truncate(limit);
+        Ok(keys)
+    }
+}
+
+/// Mock CubeSigner key creation with deterministic, counter-based addresses
+pub struct MockKeyCreator {
+    /// Counter for default keys (one per Solana address)
+    pub default_key_counter: Arc<Mutex<u32>>,
+    /// Counter for chain-specific keys (for admin updates)
+    pub chain_key_counter: Arc<Mutex<u32>>,
+}
+
+impl MockKeyCreator {
+    /// Counters at 0 (default keys) and 1000 (chain keys)
+    pub fn new() -> Self {
+        Self {
+            default_key_counter: Arc::new(Mutex::new(0)),
+            chain_key_counter: Arc::new(Mutex::new(1000)), // Start at 1000 to differentiate
+        }
+    }
+}
+
+impl Default for MockKeyCreator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl KeyCreator for MockKeyCreator {
+    /// Create default EVM key (one per Solana address, used across all chains)
+    fn create_evm_key(&self, _solana_pubkey: &str) -> Result<CreatedKey> {
+        let mut counter = self.default_key_counter.lock().unwrap();
+        *counter += 1;
+        Ok(mock_key(*counter))
+    }
+
+    /// Create chain-specific EVM key (for admin updates)
+    fn create_evm_key_for_chain(&self, _solana_pubkey: &str, _chain_id: &ChainId) -> Result<CreatedKey> {
+        let mut counter = self.chain_key_counter.lock().unwrap();
+        *counter += 1;
+        Ok(mock_key(*counter))
+    }
+
+    /// Labeled keys share the default and chain key counters
+    fn create_labeled_evm_key(&self, solana_pubkey: &str, _label: &str, chain_id: Option<&ChainId>) -> Result<CreatedKey> {
+        match chain_id {
+            Some(chain_id) => self.create_evm_key_for_chain(solana_pubkey, chain_id),
+            None => self.create_evm_key(solana_pubkey),
+        }
+    }
+
+    /// Keys of every type and class share the default key counter
+    fn create_typed_key(&self, solana_pubkey: &str, _key_type: KeyType, _key_class: KeyClass) -> Result<CreatedKey> {
+        self.create_evm_key(solana_pubkey)
+    }
+}
+
+impl SolanaKeyCreator for MockKeyCreator {
+    /// Create Solana key (one per EVM address); shares the default key counter
+    fn create_solana_key(&self, _evm_address: &str) -> Result<CreatedKey> {
+        let mut counter = self.default_key_counter.lock().unwrap();
+        *counter += 1;
+        let address = bs58::encode([*counter as u8; 32]).into_string();
+        Ok(CreatedKey {
+            key_id: format!("Key#Solana_{}", address),
+            address,
+            policies: Vec::new(),
+        })
+    }
+}
+
+/// Lists every key created so far, without names
+impl KeyLister for MockKeyCreator {
+    fn list_evm_keys(&self) -> Result<Vec<ListedKey>> {
+        let defaults = 1..=*self.default_key_counter.lock().unwrap();
+        let chains = 1001..=*self.chain_key_counter.lock().unwrap();
+        Ok(defaults
+            .chain(chains)
+            .map(mock_key)
+            .map(|key| ListedKey { key_id: key.key_id, address: key.address, name: String::new() })
+            .collect())
+    }
+}
+
+/// Key `MockKeyCreator` hands out for `counter`: address `0x{counter:040x}`
+pub fn mock_key(counter: u32) -> CreatedKey {
+    let address = format!("0x{:040x}", counter);
+    CreatedKey {
+        key_id: format!("Key#{}", address),
+        address,
+        policies: Vec::new(),
+    }
+}
+
+/// Runs the library `Provisioner` against the mock KV store and key creator
+pub struct TestContext {
+    pub provisioner: Provisioner<MockKvStore, MockKeyCreator>,
+    pub kv: MockKvStore,
+    /// Counter for default keys (one per Solana address)
+    pub default_key_counter: Arc<Mutex<u32>>,
+}
+
+impl Default for TestContext {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TestContext {
+    pub fn new() -> Self {
+        let kv = MockKvStore::new();
+        let keys = MockKeyCreator::new();
+        let default_key_counter = Arc::clone(&keys.default_key_counter);
+
+        Self {
+            provisioner: Provisioner::new(kv.clone(), keys).with_admins(admins()).with_single_step_updates(),
+            kv,
+            default_key_counter,
+        }
+    }
+
+    pub fn get_existing_mapping(&self, solana_pubkey: &SolanaPubkey, chain_id: u64) -> Result<Option<EvmAddress>> {
+        kv::get_existing_mapping(&self.kv, solana_pubkey, &chain(chain_id))
+    }
+
+    pub fn get_default_evm_address(&self, solana_pubkey: &SolanaPubkey) -> Result<Option<EvmAddress>> {
+        kv::get_default_evm_address(&self.kv, solana_pubkey)
+    }
+
+    pub fn store_mapping_once(&self, solana_pubkey: &SolanaPubkey, chain_id: u64, evm_address: &EvmAddress) -> Result<MappingRecord> {
+        kv::store_mapping_once(&self.kv, solana_pubkey, &chain(chain_id), &MappingRecord::new(evm_address, None, "test", 0))
+    }
+
+    pub fn store_default_evm_address(&self, solana_pubkey: &SolanaPubkey, evm_address: &EvmAddress) -> Result<MappingRecord> {
+        kv::store_default_mapping(&self.kv, solana_pubkey, &MappingRecord::new(evm_address, None, "test", 0))
+    }
+
+    pub fn handle(&self, req: ProvisionRequest) -> Result<ProvisionResponse> {
+        self.provisioner.handle(req)
+    }
+
+    pub fn handle_update_mapping(&self, req: UpdateMappingRequest) -> Result<UpdateMappingResponse> {
+        self.provisioner.handle_update_mapping(&admin(), req)
+    }
+}
+
+/// Identity of the admin `admins` lists
+pub const TEST_ADMIN: &str = "admin@test";
+
+/// `admins` bucket listing `TEST_ADMIN`, for `Provisioner::with_admins`
+pub fn admins() -> MockKvStore {
+    let admins = MockKvStore::new();
+    let owner = Requester::new(Some("owner@test"), Some(admin::ORG_OWNER_ROLE));
+    admin::set_admin(&admins, &owner, TEST_ADMIN, true, 0).unwrap();
+    admins
+}
+
+/// Requester authenticated as `TEST_ADMIN`
+pub fn admin() -> Requester {
+    Requester::new(Some(TEST_ADMIN), None)
+}
+
+/// Deterministic test wallet (ed25519 key from a fixed seed)
+pub fn wallet(seed: u8) -> SigningKey {
+    SigningKey::from_bytes(&[seed; 32])
+}
+
+/// Base58 Solana address of a test wallet
+pub fn pubkey(wallet: &SigningKey) -> SolanaPubkey {
+    SolanaPubkey::parse(&bs58::encode(wallet.verifying_key().as_bytes()).into_string()).unwrap()
+}
+
+pub fn evm(address: &str) -> EvmAddress {
+    EvmAddress::parse(address).unwrap()
+}
+
+pub fn chain(evm_chain_id: u64) -> ChainId {
+    ChainId::eip155(evm_chain_id)
+}
+
+/// Provision request carrying a valid ownership proof from `wallet`, which
+/// expires a minute from now by the system clock
+pub fn provision_request(wallet: &SigningKey, chain_ids: Vec<u64>) -> ProvisionRequest {
+    store_request(wallet, chain_ids.into_iter().map(chain).collect())
+}
+
+/// `provision_request` for a provisioner whose clock reads `now`
+pub fn provision_request_at(wallet: &SigningKey, chain_ids: Vec<u64>, now: u64) -> ProvisionRequest {
+    store_request_at(wallet, chain_ids.into_iter().map(chain).collect(), now)
+}
+
+/// `provision_request` for any `chain_ids` (the wildcard, non-EVM chains)
+pub fn store_request(wallet: &SigningKey, chain_ids: Vec<ChainId>) -> ProvisionRequest {
+    store_request_at(wallet, chain_ids, wall_clock())
+}
+
+/// `store_request` for a provisioner whose clock reads `now`
+pub fn store_request_at(wallet: &SigningKey, chain_ids: Vec<ChainId>, now: u64) -> ProvisionRequest {
+    signed_at(wallet, unsigned(&pubkey(wallet), chain_ids), now)
+}
+
+/// `req` signed by `wallet` over its terms as they are now (after changing
+/// the label, ttl or key), with a fresh nonce, expiring a minute from now by
+/// the system clock
+pub fn signed(wallet: &SigningKey, req: ProvisionRequest) -> ProvisionRequest {
+    signed_at(wallet, req, wall_clock())
+}
+
+/// `signed` for a provisioner whose clock reads `now`
+pub fn signed_at(wallet: &SigningKey, mut req: ProvisionRequest, now: u64) -> ProvisionRequest {
+    let nonce = NEXT_NONCE.fetch_add(1, Ordering::Relaxed).to_string();
+    req.message = auth::store_message(&req, &nonce, now + 60);
+    req.signature = BASE64.encode(wallet.sign(req.message.as_bytes()).to_bytes());
+    req
+}
+
+/// The message to sign for storing `chain_ids` with no label, ttl or key
+/// options (`auth::store_message`), with a fresh nonce and expiring a minute
+/// from now
+pub fn store_message(solana_pubkey: &SolanaPubkey, chain_ids: &[ChainId]) -> String {
+    store_message_at(solana_pubkey, chain_ids, wall_clock())
+}
+
+/// `store_message` for a provisioner whose clock reads `now`
+pub fn store_message_at(solana_pubkey: &SolanaPubkey, chain_ids: &[ChainId], now: u64) -> String {
+    let nonce = NEXT_NONCE.fetch_add(1, Ordering::Relaxed).to_string();
+    auth::store_message(&unsigned(solana_pubkey, chain_ids.to_vec()), &nonce, now + 60)
+}
+
+/// Store request with no label, ttl or key options and no proof yet
+fn unsigned(solana_pubkey: &SolanaPubkey, chain_ids: Vec<ChainId>) -> ProvisionRequest {
+    ProvisionRequest {
+        solana_pubkey: solana_pubkey.clone(),
+        chain_ids,
+        message: String::new(),
+        signature: String::new(),
+        label: None,
+        key_type: KeyType::default(),
+        key_class: KeyClass::default(),
+        idempotency_key: None,
+        ttl_secs: None,
+        request_id: None,
+    }
+}
+
+fn wall_clock() -> u64 {
+    std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_secs()
+}
+
+/// Admin update request for one chain
+pub fn update_request(solana_pubkey: &SolanaPubkey, chain_id: u64) -> UpdateMappingRequest {
+    UpdateMappingRequest {
+        solana_pubkey: solana_pubkey.clone(),
+        chain_id: chain(chain_id),
+        expected_version: None,
+        label: None,
+        idempotency_key: None,
+        request_id: None,
+    }
+}
+
+/// Admin request to enable/disable (or register) a chain
+pub fn set_chain_request(chain_id: &ChainId, enabled: bool, name: Option<&str>) -> SetChainRequest {
+    SetChainRequest {
+        chain_id: chain_id.clone(),
+        enabled,
+        name: name.map(str::to_string),
+        testnet: None,
+        request_id: None,
+    }
+}
+
+/// Self-service update request signed by `wallet`
+pub fn update_self_request(wallet: &SigningKey, chain_id: u64, nonce: &str, expires_at: u64) -> UpdateSelfRequest {
+    let solana_pubkey = pubkey(wallet);
+    let message = auth::update_self_message(&solana_pubkey, &chain(chain_id), nonce, expires_at);
+
+    UpdateSelfRequest {
+        solana_pubkey,
+        chain_id: chain(chain_id),
+        nonce: nonce.to_string(),
+        expires_at,
+        signature: BASE64.encode(wallet.sign(message.as_bytes()).to_bytes()),
+        request_id: None,
+    }
+}
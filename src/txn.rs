@@ -0,0 +1,169 @@
+//! Write Journal
+//!
+//! A store writes several keys one after the other (reverse index, N chain
+//! mappings, chain index). If one of them fails, the earlier ones are already
+//! in the bucket. To never leave that half-written state behind, the writes are
+//! recorded in a journal first and only then applied; the next invocation for
+//! the same Solana address finds the journal still pending and completes it.
+//!
+//! Recovery always rolls forward: every journaled write is first-writer-wins
+//! (`Insert`) or grow-only (`AddToChainIndex`, `AddToLabelIndex`,
+//! `AppendEvent`), so re-applying is safe even while the original writer is
+//! still running, and there is nothing to compensate. (`KvStore` has no
+//! delete, so undoing a write is not an option.) A re-applied `AppendEvent`
+//! appends the event again; events are delivered at least once (`events`).
+//!
+//! ## Key Schema
+//! ```text
+//! txn:{solana_pubkey}:{id}  → Journal # Claimed with set_if_absent, ids from 1
+//! txn:{solana_pubkey}:head  → {id}    # Hint for the latest journal id
+//! ```
+
+use crate::address::SolanaPubkey;
+use crate::chain_id::ChainId;
+use crate::error::{ProvisionError, Result};
+use crate::events::{self, MappingChange};
+use crate::kv::{self, KvStore};
+use crate::labels;
+use serde::{Deserialize, Serialize};
+
+/// Attempts at claiming a journal id before giving up
+const MAX_BEGIN_ATTEMPTS: usize = 8;
+
+/// Key of a journal: `txn:{solana_pubkey}:{id}`
+pub fn journal_key(solana_pubkey: &SolanaPubkey, id: u64) -> String {
+    format!("txn:{}:{}", solana_pubkey.as_str(), id)
+}
+
+/// Key of the latest-journal hint: `txn:{solana_pubkey}:head`
+pub fn head_key(solana_pubkey: &SolanaPubkey) -> String {
+    format!("txn:{}:head", solana_pubkey.as_str())
+}
+
+/// One intended write
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum TxnWrite {
+    /// `set_if_absent(key, value)`
+    Insert { key: String, value: String },
+    /// `kv::add_to_chain_index` for the journal's Solana address
+    AddToChainIndex { chain_ids: Vec<ChainId> },
+    /// `labels::add_to_label_index` for the journal's Solana address
+    AddToLabelIndex { label: String, chain_ids: Vec<ChainId> },
+    /// `events::append`, timestamped with the journal's start
+    AppendEvent { change: MappingChange },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum TxnStatus {
+    Pending,
+    Committed,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Journal {
+    /// Per-address journal number, starting at 1
+    pub id: u64,
+    pub status: TxnStatus,
+    pub writes: Vec<TxnWrite>,
+    /// Unix timestamp (seconds)
+    pub started_at: u64,
+}
+
+/// Journal `writes`, apply them, then mark the journal committed. A pending
+/// journal left by an earlier invocation is completed first.
+pub fn run(kv: &impl KvStore, solana_pubkey: &SolanaPubkey, writes: Vec<TxnWrite>, now: u64) -> Result<()> {
+    let journal = begin(kv, solana_pubkey, writes, now)?;
+    complete(kv, solana_pubkey, journal)
+}
+
+/// Complete the latest journal of `solana_pubkey` if it is still pending.
+/// Returns whether there was one.
+pub fn recover(kv: &impl KvStore, solana_pubkey: &SolanaPubkey) -> Result<bool> {
+    match find_last(kv, solana_pubkey)? {
+        Some(journal) if journal.status == TxnStatus::Pending => {
+            complete(kv, solana_pubkey, journal)?;
+            Ok(true)
+        }
+        _ => Ok(false),
+    }
+}
+
+pub fn get(kv: &impl KvStore, solana_pubkey: &SolanaPubkey, id: u64) -> Result<Option<Journal>> {
+    kv.get(&journal_key(solana_pubkey, id))?
+        .map(|raw| serde_json::from_str(&raw).map_err(|e| ProvisionError::corrupt(format!("journal {}", id), e)))
+        .transpose()
+}
+
+/// Claim the next journal id for `writes`, completing a pending predecessor first
+fn begin(kv: &impl KvStore, solana_pubkey: &SolanaPubkey, writes: Vec<TxnWrite>, now: u64) -> Result<Journal> {
+    for _ in 0..MAX_BEGIN_ATTEMPTS {
+        let last_id = match find_last(kv, solana_pubkey)? {
+            Some(last) if last.status == TxnStatus::Pending => {
+                let id = last.id;
+                complete(kv, solana_pubkey, last)?;
+                id
+            }
+            Some(last) => last.id,
+            None => 0,
+        };
+
+        let journal = Journal {
+            id: last_id + 1,
+            status: TxnStatus::Pending,
+            writes: writes.clone(),
+            started_at: now,
+        };
+        if kv.set_if_absent(&journal_key(solana_pubkey, journal.id), &encode(&journal))? {
+            kv.set(&head_key(solana_pubkey), &journal.id.to_string())?;
+            return Ok(journal);
+        }
+        // Another writer claimed this id - re-read the tail and retry
+    }
+
+    Err(ProvisionError::KvConflict(format!(
+        "Could not start a write journal for {} after {} attempts",
+        solana_pubkey, MAX_BEGIN_ATTEMPTS
+    )))
+}
+
+/// Apply every write of `journal` and mark it committed
+fn complete(kv: &impl KvStore, solana_pubkey: &SolanaPubkey, mut journal: Journal) -> Result<()> {
+    for write in &journal.writes {
+        match write {
+            TxnWrite::Insert { key, value } => {
+                kv.set_if_absent(key, value)?;
+            }
+            TxnWrite::AddToChainIndex { chain_ids } => {
+                kv::add_to_chain_index(kv, solana_pubkey, chain_ids)?;
+            }
+            TxnWrite::AddToLabelIndex { label, chain_ids } => {
+                labels::add_to_label_index(kv, solana_pubkey, label, chain_ids)?;
+            }
+            TxnWrite::AppendEvent { change } => {
+                events::append(kv, change.clone(), journal.started_at)?;
+            }
+        }
+    }
+
+    journal.status = TxnStatus::Committed;
+    kv.set(&journal_key(solana_pubkey, journal.id), &encode(&journal))
+}
+
+/// Latest journal, starting from the head hint and probing forward
+fn find_last(kv: &impl KvStore, solana_pubkey: &SolanaPubkey) -> Result<Option<Journal>> {
+    let hint = kv.get(&head_key(solana_pubkey))?.and_then(|raw| raw.parse::<u64>().ok()).unwrap_or(0);
+
+    let mut last = if hint > 0 { get(kv, solana_pubkey, hint)? } else { None };
+    let mut id = hint;
+    while let Some(next) = get(kv, solana_pubkey, id + 1)? {
+        id = next.id;
+        last = Some(next);
+    }
+    Ok(last)
+}
+
+fn encode(journal: &Journal) -> String {
+    serde_json::to_string(journal).expect("journal serialization cannot fail")
+}
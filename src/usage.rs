@@ -0,0 +1,95 @@
+//! Usage Metering
+//!
+//! Billable counters per calendar month (UTC), for charging white-label
+//! tenants. The bucket is namespaced per tenant like every other (see
+//! `tenant`), so each org has its own counters:
+//!
+//! - `key_creations`: keys mapped for the first time. A store creating a
+//!   user's or a label's key counts one, and so does every update mapping a
+//!   new key to a chain (`KEY_ACTIONS`); `link_external` creates none
+//! - `mapping_operations`: successful requests writing mappings (`MAPPING_ACTIONS`),
+//!   including stores that found everything already mapped
+//!
+//! ## Key Schema (`usage` bucket)
+//! ```text
+//! {YYYY-MM} → UsageReport
+//! ```
+//!
+//! Like `metrics`, counters are updated with a plain read and write and
+//! failures to count are ignored, so concurrent requests can lose increments.
+
+use crate::error::{ProvisionError, Result};
+use crate::kv::KvStore;
+use serde::{Deserialize, Serialize};
+
+/// Bucket holding the monthly counters
+pub const USAGE_BUCKET: &str = "usage";
+
+/// Audited actions that write mappings when they succeed
+pub const MAPPING_ACTIONS: &[&str] =
+    &["provision", "store", "update", "approve_update", "update_self", "rotate", "link_external"];
+
+/// Audited actions that map a new key when they succeed
+pub const KEY_ACTIONS: &[&str] = &["update", "approve_update", "update_self", "rotate"];
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct UsageReport {
+    /// `YYYY-MM`
+    pub month: String,
+    #[serde(default)]
+    pub key_creations: u64,
+    #[serde(default)]
+    pub mapping_operations: u64,
+}
+
+/// Calendar month (UTC) of a Unix timestamp, as `YYYY-MM`
+pub fn month_of(unix_secs: u64) -> String {
+    // Days since 1970-01-01 to a civil date, from Howard Hinnant's `civil_from_days`
+    let days = unix_secs / 86_400 + 719_468;
+    let era = days / 146_097;
+    let day_of_era = days % 146_097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let month = if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 };
+    let year = year_of_era + era * 400 + u64::from(month <= 2);
+    format!("{:04}-{:02}", year, month)
+}
+
+/// Counters of `month` (`YYYY-MM`; all zero if nothing was counted)
+pub fn get_usage(kv: &impl KvStore, month: &str) -> Result<UsageReport> {
+    let valid = month.len() == 7
+        && month.as_bytes()[4] == b'-'
+        && month[..4].bytes().chain(month[5..].bytes()).all(|b| b.is_ascii_digit())
+        && (1..=12).contains(&month[5..].parse::<u8>().unwrap_or(0));
+    if !valid {
+        return Err(ProvisionError::InvalidRequest(format!("month must be YYYY-MM, got '{}'", month)));
+    }
+    match kv.get(month)? {
+        Some(raw) => serde_json::from_str(&raw).map_err(|e| ProvisionError::corrupt("usage counters", e)),
+        None => Ok(UsageReport { month: month.to_string(), ..Default::default() }),
+    }
+}
+
+/// Count a successful audited action, if it is one of `MAPPING_ACTIONS`
+pub fn record_operation(kv: &impl KvStore, action: &str, now: u64) -> Result<()> {
+    if !MAPPING_ACTIONS.contains(&action) {
+        return Ok(());
+    }
+    update(kv, now, |usage| {
+        usage.mapping_operations += 1;
+        usage.key_creations += u64::from(KEY_ACTIONS.contains(&action));
+    })
+}
+
+/// Count a store that created a user's or a label's key
+pub fn record_key_creation(kv: &impl KvStore, now: u64) -> Result<()> {
+    update(kv, now, |usage| usage.key_creations += 1)
+}
+
+fn update(kv: &impl KvStore, now: u64, f: impl FnOnce(&mut UsageReport)) -> Result<()> {
+    let mut usage = get_usage(kv, &month_of(now))?;
+    f(&mut usage);
+    let raw = serde_json::to_string(&usage).expect("usage serialization cannot fail");
+    kv.set(&usage.month, &raw)
+}
@@ -0,0 +1,219 @@
+//! Consistency Check
+//!
+//! Scans the mappings bucket for records that contradict each other, the
+//! drift a lost write or a hand edit leaves behind:
+//!
+//! - a chain mapping whose user has no default mapping
+//! - a default mapping with no chains in its user's chain index
+//! - a mapping record that does not decode, or a key or value that is not a
+//!   valid address
+//! - a mapping whose address the reverse index gives to someone else (or to
+//!   nobody), and a reverse entry pointing at a user without mappings
+//!
+//! Only reports; `repair` fixes violations by id. Keys a chain was rotated
+//! away from stay in the reverse index, so a reverse entry only has to point
+//! at a provisioned user.
+//!
+//! Like `migrate`, the bucket is scanned in batches over `KvStore::list_keys`.
+
+use crate::address::{EvmAddress, SolanaPubkey};
+use crate::anonymize;
+use crate::chain_id::ChainId;
+use crate::error::Result;
+use crate::kv::{self, KvStore, MappingRecord};
+use crate::migrate::{self, DEFAULT_MIGRATION_BATCH, MAX_MIGRATION_BATCH};
+use serde::{Deserialize, Serialize};
+
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct VerifyRequest {
+    /// Resume after this key (`next_cursor` of the previous batch)
+    #[serde(default)]
+    pub cursor: Option<String>,
+    #[serde(default)]
+    pub limit: Option<usize>,
+    /// Correlation id carried into log events (see `logging`)
+    #[serde(default)]
+    pub request_id: Option<String>,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ViolationKind {
+    /// Chain mapping of a user without a default mapping
+    MissingDefault,
+    /// Default mapping of a user with no chains indexed
+    NoChains,
+    /// Mapping record that does not decode
+    MalformedRecord,
+    /// Key or value that is not a valid address
+    MalformedAddress,
+    /// Reverse index disagreeing with a mapping
+    ReverseMismatch,
+}
+
+impl ViolationKind {
+    const ALL: [Self; 5] = [Self::MissingDefault, Self::NoChains, Self::MalformedRecord, Self::MalformedAddress, Self::ReverseMismatch];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::MissingDefault => "missing_default",
+            Self::NoChains => "no_chains",
+            Self::MalformedRecord => "malformed_record",
+            Self::MalformedAddress => "malformed_address",
+            Self::ReverseMismatch => "reverse_mismatch",
+        }
+    }
+}
+
+/// Id of a violation: `{kind}:{key}`. Stable across scans, so a violation
+/// can be repaired by id for as long as it lasts.
+pub fn violation_id(kind: ViolationKind, key: &str) -> String {
+    format!("{}:{}", kind.as_str(), key)
+}
+
+/// Kind and key of a violation id, `None` if it is not one
+pub fn parse_violation_id(id: &str) -> Option<(ViolationKind, &str)> {
+    let (kind, key) = id.split_once(':')?;
+    let kind = ViolationKind::ALL.into_iter().find(|k| k.as_str() == kind)?;
+    Some((kind, key))
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Violation {
+    /// Pass to `repair` to fix it (see `violation_id`)
+    pub id: String,
+    pub kind: ViolationKind,
+    /// KV key of the offending entry
+    pub key: String,
+    pub detail: String,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct VerifyReport {
+    /// Keys looked at in this batch
+    pub scanned: usize,
+    pub violations: Vec<Violation>,
+    /// Pass as `cursor` to continue; null once every key has been scanned
+    pub next_cursor: Option<String>,
+}
+
+/// Check one batch of keys after `cursor`
+pub fn verify_batch(kv: &impl KvStore, cursor: Option<&str>, limit: Option<usize>) -> Result<VerifyReport> {
+    let limit = limit.unwrap_or(DEFAULT_MIGRATION_BATCH).clamp(1, MAX_MIGRATION_BATCH);
+    let keys = kv.list_keys(cursor, limit)?;
+
+    let mut report = VerifyReport {
+        scanned: keys.len(),
+        violations: Vec::new(),
+        next_cursor: if keys.len() < limit { None } else { keys.last().cloned() },
+    };
+
+    for key in &keys {
+        report.violations.extend(check_key(kv, key)?);
+    }
+
+    Ok(report)
+}
+
+/// Violations of the entry under `key` (none for keys that are not checked)
+pub fn check_key(kv: &impl KvStore, key: &str) -> Result<Vec<Violation>> {
+    let mut violations = Vec::new();
+    let mut violation = |kind, detail: String| {
+        violations.push(Violation { id: violation_id(kind, key), kind, key: key.to_string(), detail })
+    };
+    if let Some(evm_address) = key.strip_prefix("reverse:") {
+        check_reverse(kv, key, evm_address, &mut violation)?;
+    } else if let Some(solana_pubkey) = key.strip_prefix("default:") {
+        if migrate::is_mapping_key(key) {
+            check_default(kv, key, &SolanaPubkey::parse(solana_pubkey)?, &mut violation)?;
+        }
+    } else if migrate::is_mapping_key(key) {
+        check_chain(kv, key, &mut violation)?;
+    }
+    Ok(violations)
+}
+
+fn check_default(
+    kv: &impl KvStore,
+    key: &str,
+    solana_pubkey: &SolanaPubkey,
+    violation: &mut impl FnMut(ViolationKind, String),
+) -> Result<()> {
+    let Some(record) = decode(kv, key, violation)? else {
+        return Ok(());
+    };
+    if kv::get_chain_index(kv, solana_pubkey)?.is_empty() {
+        violation(ViolationKind::NoChains, format!("{} has a default mapping but no chains", solana_pubkey));
+    }
+    check_owner(kv, solana_pubkey, &record.address, violation)
+}
+
+fn check_chain(kv: &impl KvStore, key: &str, violation: &mut impl FnMut(ViolationKind, String)) -> Result<()> {
+    let Some((solana_pubkey, chain_id)) = key.split_once(':') else {
+        return Ok(());
+    };
+    let solana_pubkey = SolanaPubkey::parse(solana_pubkey)?;
+    if let Err(e) = ChainId::parse(chain_id) {
+        violation(ViolationKind::MalformedRecord, e.to_string());
+        return Ok(());
+    }
+    let Some(record) = decode(kv, key, violation)? else {
+        return Ok(());
+    };
+    if kv::get_default_mapping(kv, &solana_pubkey)?.is_none() {
+        violation(ViolationKind::MissingDefault, format!("{} has a chain mapping but no default mapping", solana_pubkey));
+    }
+    check_owner(kv, &solana_pubkey, &record.address, violation)
+}
+
+fn check_reverse(kv: &impl KvStore, key: &str, evm_address: &str, violation: &mut impl FnMut(ViolationKind, String)) -> Result<()> {
+    let evm_address = match EvmAddress::parse(evm_address) {
+        Ok(evm_address) => evm_address,
+        Err(e) => {
+            violation(ViolationKind::MalformedAddress, e.to_string());
+            return Ok(());
+        }
+    };
+    let Some(raw) = kv.get(key)?.filter(|raw| !anonymize::is_pseudonym(raw)) else {
+        return Ok(());
+    };
+    match SolanaPubkey::parse(&raw) {
+        Ok(owner) if kv::get_default_mapping(kv, &owner)?.is_none() => {
+            violation(ViolationKind::ReverseMismatch, format!("{} belongs to {}, who has no default mapping", evm_address, owner));
+        }
+        Ok(_) => {}
+        Err(e) => violation(ViolationKind::MalformedAddress, e.to_string()),
+    }
+    Ok(())
+}
+
+/// Report a mapping whose address the reverse index does not give to `solana_pubkey`
+fn check_owner(
+    kv: &impl KvStore,
+    solana_pubkey: &SolanaPubkey,
+    evm_address: &EvmAddress,
+    violation: &mut impl FnMut(ViolationKind, String),
+) -> Result<()> {
+    match kv.get(&kv::reverse_key(evm_address))?.map(|raw| SolanaPubkey::parse(&raw)) {
+        Some(Ok(owner)) if owner == *solana_pubkey => {}
+        Some(Ok(owner)) => violation(ViolationKind::ReverseMismatch, format!("{} is mapped but belongs to {}", evm_address, owner)),
+        None => violation(ViolationKind::ReverseMismatch, format!("{} is mapped but not in the reverse index", evm_address)),
+        // Reported when the reverse entry itself is scanned
+        Some(Err(_)) => {}
+    }
+    Ok(())
+}
+
+/// The record under `key`, or `None` (reported) if it does not decode
+fn decode(kv: &impl KvStore, key: &str, violation: &mut impl FnMut(ViolationKind, String)) -> Result<Option<MappingRecord>> {
+    let Some(raw) = kv.get(key)?.filter(|raw| !anonymize::is_tombstone(raw)) else {
+        return Ok(None);
+    };
+    match MappingRecord::decode(&raw) {
+        Ok(record) => Ok(Some(record)),
+        Err(e) => {
+            violation(ViolationKind::MalformedRecord, e.to_string());
+            Ok(None)
+        }
+    }
+}
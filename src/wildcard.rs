@@ -0,0 +1,74 @@
+//! Wildcard Mappings
+//!
+//! `store` with the chain id `"*"` records that every EVM chain, current or
+//! future, uses the user's default address. It writes one key, not one per
+//! chain: `get` resolves an `eip155` chain without a mapping of its own to the
+//! default address when the user has the wildcard, whether or not the chain is
+//! in the registry yet. A chain mapping of its own always wins over the
+//! wildcard.
+//!
+//! The wildcard only records intent; the address is always the current
+//! default. It is never written into per-chain keys, so it does not show up
+//! in the chain index, `list` or the chain history, and reads that
+//! materialize inherited chains (`mapping::get_materialized`) leave the
+//! chains it covers alone. Like other mappings it cannot be removed.
+//!
+//! Only `store` (and `store_batch`, `provision_async`) accept `"*"`, and only
+//! for the primary address; everywhere else a chain id names one chain.
+//!
+//! ## Key Schema
+//! ```text
+//! wildcard:{solana_pubkey} → WildcardRecord  # First-writer-wins
+//! ```
+
+use crate::address::SolanaPubkey;
+use crate::chain_id::{ChainId, RawChainId};
+use crate::error::{ProvisionError, Result};
+use serde::{Deserialize, Deserializer, Serialize};
+
+/// The chain id standing for every EVM chain
+pub const WILDCARD: &str = "*";
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct WildcardRecord {
+    /// Unix timestamp (seconds)
+    pub created_at: u64,
+}
+
+impl WildcardRecord {
+    pub fn encode(&self) -> String {
+        serde_json::to_string(self).expect("wildcard record serialization cannot fail")
+    }
+
+    pub fn decode(raw: &str) -> Result<Self> {
+        serde_json::from_str(raw).map_err(|e| ProvisionError::corrupt("wildcard record", e))
+    }
+}
+
+/// Key of a user's wildcard: `wildcard:{solana_pubkey}`
+pub fn wildcard_key(solana_pubkey: &SolanaPubkey) -> String {
+    format!("wildcard:{}", solana_pubkey.as_str())
+}
+
+/// Whether `chain_id` resolves through a wildcard: an `eip155` chain
+pub fn covers(chain_id: &ChainId) -> bool {
+    chain_id.evm_chain_id().is_some()
+}
+
+/// Whether a request's `chain_ids` hold the wildcard, and the chains besides it
+pub fn split(chain_ids: &[ChainId]) -> (bool, Vec<ChainId>) {
+    let wildcard = chain_ids.iter().any(ChainId::is_wildcard);
+    (wildcard, chain_ids.iter().filter(|chain_id| !chain_id.is_wildcard()).cloned().collect())
+}
+
+/// `chain_ids` of store requests: chain ids as `ChainId` reads them, or `"*"`
+pub fn chain_ids<'de, D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Vec<ChainId>, D::Error> {
+    Vec::<RawChainId>::deserialize(deserializer)?
+        .into_iter()
+        .map(|raw| match raw {
+            RawChainId::Str(chain_id) if chain_id == WILDCARD => Ok(ChainId::wildcard()),
+            raw => raw.resolve(),
+        })
+        .collect::<Result<_>>()
+        .map_err(serde::de::Error::custom)
+}
@@ -0,0 +1,264 @@
+mod common;
+
+use common::{evm_wallet, evm_wallet_address, fixed_clock_provisioner, labeled_request, link_external_request, AAVE_POOL};
+use cubist_wallet_provisioner::approval;
+use cubist_wallet_provisioner::address::{normalize_evm_address, to_checksum_address};
+use cubist_wallet_provisioner::address_sanity;
+use cubist_wallet_provisioner::auth;
+use cubist_wallet_provisioner::error::ProvisionError;
+use cubist_wallet_provisioner::dry_run::PLACEHOLDER_ADDRESS;
+use cubist_wallet_provisioner::kv;
+use cubist_wallet_provisioner::mapping;
+use cubist_wallet_provisioner::testing::{
+    chain, evm, provision_request, provision_request_at, pubkey, update_request, wallet, MockKvStore, TestContext,
+};
+use cubist_wallet_provisioner::{EvmAddress, MappingRecord, SolanaPubkey, UpdateMappingRequest};
+use std::sync::Arc;
+
+// =============================================================================
+// EVM ADDRESS TESTS
+// =============================================================================
+
+#[test]
+fn test_eip55_checksum() {
+    // Test vectors from EIP-55
+    for expected in [
+        "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed",
+        "0xfB6916095ca1df60bB79Ce92cE3Ea74c37c5d359",
+        "0xdbF03B407c01E7cD3CBea99509d93f8DDDC8C6FB",
+        "0xD1220A0cf47c7B9Be7A2E6BA89F429762e7b9aDb",
+    ] {
+        assert_eq!(to_checksum_address(&expected.to_lowercase()), expected);
+        assert_eq!(normalize_evm_address(expected).unwrap(), expected.to_lowercase());
+    }
+}
+
+#[test]
+fn test_evm_address_validation() {
+    let lower = "0x5aaeb6053f3e94c9b9a09f33669435e7ef1beaed";
+
+    // Single-case inputs carry no checksum and are accepted
+    assert_eq!(normalize_evm_address(lower).unwrap(), lower);
+    assert_eq!(normalize_evm_address("0x5AAEB6053F3E94C9B9A09F33669435E7EF1BEAED").unwrap(), lower);
+
+    // Mixed case with a wrong checksum is rejected
+    assert!(normalize_evm_address("0x5AAeb6053F3E94C9b9A09f33669435E7Ef1BeAed").is_err());
+
+    // Bad format
+    assert!(normalize_evm_address("5aaeb6053f3e94c9b9a09f33669435e7ef1beaed").is_err());
+    assert!(normalize_evm_address("0x5aaeb6053f3e94c9b9a09f33669435e7ef1bea").is_err());
+    assert!(normalize_evm_address("0xzaaeb6053f3e94c9b9a09f33669435e7ef1beaed").is_err());
+}
+
+#[test]
+fn test_reverse_get_accepts_any_case() {
+    let ctx = TestContext::new();
+    let alice = wallet(1);
+    ctx.handle(provision_request(&alice, vec![1])).unwrap();
+    let updated = ctx.handle_update_mapping(update_request(&pubkey(&alice), 1)).unwrap();
+
+    let checksummed = updated.new_evm_address.to_string();
+    let owner = Some(pubkey(&alice));
+    assert_eq!(ctx.provisioner.handle_reverse_get(&evm(&checksummed)).unwrap(), owner);
+    assert_eq!(ctx.provisioner.handle_reverse_get(&evm(&checksummed.to_lowercase())).unwrap(), owner);
+}
+
+#[test]
+fn test_evm_address_newtype() {
+    let address = evm("0x5aaeb6053f3e94c9b9a09f33669435e7ef1beaed");
+
+    // Held lowercase, displayed and serialized checksummed
+    assert_eq!(address.as_str(), "0x5aaeb6053f3e94c9b9a09f33669435e7ef1beaed");
+    assert_eq!(address.to_string(), "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed");
+    assert_eq!(serde_json::to_string(&address).unwrap(), "\"0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed\"");
+    assert_eq!(address, "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed".parse().unwrap());
+
+    // Mapping values keep the lowercase storage form
+    let encoded = MappingRecord::new(&address, None, "test", 7).encode();
+    assert_eq!(
+        encoded,
+        r#"{"address":"0x5aaeb6053f3e94c9b9a09f33669435e7ef1beaed","created_at":7,"version":2,"created_by":"test"}"#
+    );
+
+    assert!(serde_json::from_str::<EvmAddress>("\"0x5AAeb6053F3E94C9b9A09f33669435E7Ef1BeAed\"").is_err());
+}
+
+// =============================================================================
+// SOLANA PUBKEY VALIDATION TESTS
+// =============================================================================
+
+#[test]
+fn test_invalid_solana_pubkey_is_rejected() {
+    let alice = pubkey(&wallet(1));
+
+    // `:` would forge the `{solana_pubkey}:{chain_id}` key format
+    let forged = format!("{}:1", alice);
+    for invalid in [forged.as_str(), "0OIl", "TestUser123", ""] {
+        let err = SolanaPubkey::parse(invalid).unwrap_err();
+        assert!(err.to_string().contains("Invalid Solana public key"));
+    }
+
+    // Requests carrying one do not deserialize, so they never reach KV
+    let body = format!(r#"{{"solana_pubkey":"{}","chain_id":1}}"#, forged);
+    assert!(serde_json::from_str::<UpdateMappingRequest>(&body).is_err());
+
+    let body = format!(r#"{{"solana_pubkey":"{}","chain_id":1}}"#, alice);
+    let req: UpdateMappingRequest = serde_json::from_str(&body).unwrap();
+    assert_eq!(req.solana_pubkey, alice);
+}
+
+// =============================================================================
+// ADDRESS UNIQUENESS TESTS
+// =============================================================================
+
+#[test]
+fn test_store_refuses_address_of_another_user() {
+    let kv = MockKvStore::new();
+    let address = evm("0x1111111111111111111111111111111111111111");
+    let record = || Ok(MappingRecord::new(&address, Some("Key#backend"), "test", 0));
+    mapping::store(&kv, &provision_request(&wallet(1), vec![1]), 0, record).unwrap();
+
+    // The backend pasted Alice's address for Bob
+    let err = mapping::store(&kv, &provision_request(&wallet(2), vec![1]), 0, record).unwrap_err();
+    assert_eq!(err, ProvisionError::AddressOwned { evm_address: address.to_string(), owner: pubkey(&wallet(1)).to_string() });
+    assert_eq!(kv::get_default_mapping(&kv, &pubkey(&wallet(2))).unwrap(), None);
+
+    // Nor can it become one of Bob's labeled addresses
+    mapping::store(&kv, &provision_request(&wallet(2), vec![1]), 0, || Ok(MappingRecord::new(&evm(AAVE_POOL), None, "test", 0))).unwrap();
+    let err = mapping::store(&kv, &labeled_request(&wallet(2), vec![1], "cold"), 0, record).unwrap_err();
+    assert_eq!(err.code(), "ADDRESS_OWNED");
+
+    // Alice storing again is not a conflict with herself
+    mapping::store(&kv, &provision_request(&wallet(1), vec![1, 137]), 0, record).unwrap();
+}
+
+#[test]
+fn test_concurrent_claims_on_one_address_have_one_winner() {
+    use std::sync::Barrier;
+    use std::thread;
+
+    let kv = MockKvStore::new();
+    let address = evm("0x1111111111111111111111111111111111111111");
+    let barrier = Arc::new(Barrier::new(8));
+    let handles: Vec<_> = (1..=8)
+        .map(|seed| {
+            let (kv, address, barrier) = (kv.clone(), address.clone(), Arc::clone(&barrier));
+            thread::spawn(move || {
+                let record = || Ok(MappingRecord::new(&address, Some("Key#backend"), "test", 0));
+                barrier.wait();
+                mapping::store(&kv, &provision_request(&wallet(seed), vec![1]), 0, record).map(|_| pubkey(&wallet(seed)))
+            })
+        })
+        .collect();
+    let results: Vec<_> = handles.into_iter().map(|handle| handle.join().unwrap()).collect();
+
+    let winners: Vec<_> = results.iter().filter_map(|result| result.as_ref().ok()).collect();
+    assert_eq!(winners.len(), 1);
+    assert!(results.iter().filter_map(|result| result.as_ref().err()).all(|err| err.code() == "ADDRESS_OWNED"));
+    assert_eq!(kv::get_reverse_mapping(&kv, &address).unwrap().as_ref(), Some(winners[0]));
+    for seed in 1..=8 {
+        let mapped = kv::get_default_mapping(&kv, &pubkey(&wallet(seed))).unwrap().is_some();
+        assert_eq!(mapped, pubkey(&wallet(seed)) == *winners[0]);
+    }
+}
+
+#[test]
+fn test_shared_address_override_is_kept_on_the_proposal() {
+    let ctx = TestContext::new();
+    let alice = pubkey(&wallet(1));
+    let bob = pubkey(&wallet(2));
+    let address = ctx.handle(provision_request(&wallet(1), vec![1])).unwrap().evm_address;
+    ctx.handle(provision_request(&wallet(2), vec![1])).unwrap();
+
+    assert_eq!(mapping::claim_address(&ctx.kv, &address, &bob).unwrap_err().code(), "ADDRESS_OWNED");
+    mapping::claim_address(&ctx.kv, &address, &alice).unwrap();
+
+    let pending = approval::propose(&ctx.kv, &bob, &chain(1), Some(&address), None, true, "alice@test", 10).unwrap();
+    assert!(approval::get_pending(&ctx.kv, &bob, &chain(1)).unwrap().unwrap().allow_shared_address);
+    let json = serde_json::to_value(&pending).unwrap();
+    assert_eq!(json["allow_shared_address"], true);
+    // Not written when unset
+    let pending = approval::propose(&ctx.kv, &alice, &chain(1), Some(&evm(AAVE_POOL)), None, false, "alice@test", 10).unwrap();
+    assert!(serde_json::to_value(&pending).unwrap().get("allow_shared_address").is_none());
+}
+
+// =============================================================================
+// ADDRESS SANITY TESTS
+// =============================================================================
+
+#[test]
+fn test_address_sanity_rejects_unusable_addresses() {
+    let unusable = [
+        ("0x0000000000000000000000000000000000000000", "the zero address"),
+        ("0x0000000000000000000000000000000000000001", "a precompile"),
+        ("0x0000000000000000000000000000000000000011", "a precompile"),
+        ("0x0000000000000000000000000000000000000100", "a precompile"),
+        ("0x00000000000000000000000000000000000001ff", "a precompile"),
+        ("0x000000000000000000000000000000000000dEaD", "a burn address"),
+        ("0xdead000000000000000042069420694206942069", "a burn address"),
+    ];
+    for (address, reason) in unusable {
+        let err = address_sanity::check(&evm(address), &[]).unwrap_err();
+        assert_eq!(err, ProvisionError::UnusableAddress { evm_address: evm(address).to_string(), reason }, "{}", address);
+        assert_eq!(err.code(), "UNUSABLE_ADDRESS");
+    }
+
+    address_sanity::check(&evm("0x0000000000000000000000000000000000000200"), &[]).unwrap();
+    address_sanity::check(&evm("0x1000000000000000000000000000000000000001"), &[]).unwrap();
+    let denied = [evm(AAVE_POOL)];
+    address_sanity::check(&evm("0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed"), &denied).unwrap();
+    let err = address_sanity::check(&evm(AAVE_POOL), &denied).unwrap_err();
+    assert_eq!(err.to_string(), format!("EVM address {} cannot be mapped: it is on the deny list", evm(AAVE_POOL)));
+}
+
+#[test]
+fn test_link_external_refuses_unusable_addresses() {
+    let metamask = evm_wallet(9);
+    let provisioner = fixed_clock_provisioner().with_denied_addresses(vec![evm_wallet_address(&metamask)]);
+    let alice = wallet(1);
+    provisioner.handle(provision_request_at(&alice, vec![1], 1000)).unwrap();
+
+    let err = provisioner.handle_link_external(link_external_request(&alice, &metamask, vec![1], "1")).unwrap_err();
+    assert_eq!(err.code(), "UNUSABLE_ADDRESS");
+
+    // Refused before the signatures are looked at
+    let mut req = link_external_request(&alice, &evm_wallet(8), vec![1], "2");
+    req.evm_address = evm(PLACEHOLDER_ADDRESS);
+    assert_eq!(provisioner.handle_link_external(req).unwrap_err().code(), "UNUSABLE_ADDRESS");
+    assert!(provisioner.handle_get(&pubkey(&alice), &[chain(1)]).unwrap().external_addresses.is_empty());
+}
+
+/// A Solana address off the ed25519 curve, as program derived addresses are
+fn off_curve_pubkey() -> SolanaPubkey {
+    (0..=u8::MAX)
+        .map(|byte| [byte; 32])
+        .find(|bytes| ed25519_dalek::VerifyingKey::from_bytes(bytes).is_err())
+        .map(|bytes| SolanaPubkey::parse(&bs58::encode(bytes).into_string()).unwrap())
+        .unwrap()
+}
+
+#[test]
+fn test_program_pubkeys_are_not_provisioned() {
+    for program in address_sanity::PROGRAM_IDS {
+        let program = SolanaPubkey::parse(program).unwrap();
+        let err = address_sanity::check_solana_pubkey(&program, false).unwrap_err();
+        assert_eq!(err, ProvisionError::UnusablePubkey { solana_pubkey: program.to_string(), reason: "a program" });
+        address_sanity::check_solana_pubkey(&program, true).unwrap();
+    }
+    address_sanity::check_solana_pubkey(&pubkey(&wallet(1)), false).unwrap();
+
+    let provisioner = fixed_clock_provisioner();
+    let mut req = provision_request_at(&wallet(1), vec![1], 1000);
+    req.solana_pubkey = off_curve_pubkey();
+    let (nonce, expires_at) = auth::store_message_terms(&req.message).map(|(nonce, expires_at)| (nonce.to_string(), expires_at)).unwrap();
+    req.message = auth::store_message(&req, &nonce, expires_at);
+    let err = provisioner.handle(req.clone()).unwrap_err();
+    assert_eq!(err.code(), "UNUSABLE_SOLANA_PUBKEY");
+    assert!(err.to_string().ends_with("off the ed25519 curve (a program derived address)"));
+    assert_eq!(provisioner.handle_provision_async(req.clone()).unwrap_err().code(), "UNUSABLE_SOLANA_PUBKEY");
+    assert!(kv::get_default_mapping(provisioner.kv(), &req.solana_pubkey).unwrap().is_none());
+
+    // The override lifts the check, but nobody can prove owning an off-curve address
+    let provisioner = fixed_clock_provisioner().with_program_pubkeys_allowed();
+    assert_eq!(provisioner.handle(req).unwrap_err().code(), "INVALID_SOLANA_PUBKEY");
+}
@@ -0,0 +1,163 @@
+mod common;
+
+use common::{owner, propose_request, resolve_request};
+use cubist_wallet_provisioner::admin::{self, Requester};
+use cubist_wallet_provisioner::authz::{self, Role};
+use cubist_wallet_provisioner::error::ProvisionError;
+use cubist_wallet_provisioner::export::ExportRequest;
+use cubist_wallet_provisioner::kv;
+use cubist_wallet_provisioner::testing::{admin, chain, provision_request, pubkey, update_request, wallet, MockKeyCreator, MockKvStore, TestContext};
+use cubist_wallet_provisioner::Provisioner;
+use std::sync::{Arc, Mutex};
+
+// =============================================================================
+// ADMIN ALLOWLIST TESTS
+// =============================================================================
+
+#[test]
+fn test_update_requires_admin_when_allowlist_configured() {
+    let admins = MockKvStore::new();
+    let keys = MockKeyCreator {
+        default_key_counter: Arc::new(Mutex::new(0)),
+        chain_key_counter: Arc::new(Mutex::new(1000)),
+    };
+    let provisioner = Provisioner::new(MockKvStore::new(), keys).with_admins(admins.clone());
+    let alice = wallet(1);
+    let solana_pubkey = pubkey(&alice);
+    provisioner.handle(provision_request(&alice, vec![1])).unwrap();
+
+    let err = provisioner.handle_propose_update(&admin(), propose_request(&solana_pubkey, 1)).unwrap_err();
+    assert!(err.to_string().contains("admin@test is not an admin"));
+
+    provisioner.handle_set_admin(&owner(), "admin@test", true).unwrap();
+    provisioner.handle_propose_update(&admin(), propose_request(&solana_pubkey, 1)).unwrap();
+    assert!(admin::is_admin(&admins, "admin@test").unwrap());
+
+    // Removal is a tombstone, not a delete
+    provisioner.handle_set_admin(&owner(), "admin@test", false).unwrap();
+    assert!(provisioner.handle_reject_update(&admin(), resolve_request(&solana_pubkey, 1, 1)).is_err());
+    let entry = admin::get_admin(&admins, "admin@test").unwrap().unwrap();
+    assert!(!entry.active);
+    assert_eq!(entry.updated_by, "owner@test");
+
+    // Requesters without an identity are never admin
+    provisioner.handle_set_admin(&owner(), "admin@test", true).unwrap();
+    let err = provisioner.handle_propose_update(&Requester::new(None, None), propose_request(&solana_pubkey, 1)).unwrap_err();
+    assert_eq!(err, ProvisionError::Forbidden { identity: "anonymous".to_string(), action: "propose_update".to_string() });
+
+    // Single-step updates are off unless opted into
+    let err = provisioner.handle_update_mapping(&admin(), update_request(&solana_pubkey, 1)).unwrap_err();
+    assert!(err.to_string().contains("second admin"));
+}
+
+#[test]
+fn test_admin_actions_fail_closed_without_an_allowlist() {
+    let provisioner = Provisioner::new(MockKvStore::new(), MockKeyCreator::new()).with_single_step_updates();
+    let alice = wallet(1);
+    let solana_pubkey = pubkey(&alice);
+    provisioner.handle(provision_request(&alice, vec![1])).unwrap();
+
+    let err = provisioner.handle_update_mapping(&admin(), update_request(&solana_pubkey, 1)).unwrap_err();
+    assert_eq!(err, ProvisionError::NotConfigured("Admin allowlist"));
+    let err = provisioner.handle_export(&admin(), ExportRequest::default()).unwrap_err();
+    assert_eq!(err, ProvisionError::NotConfigured("Admin allowlist"));
+    assert_eq!(provisioner.handle_get(&solana_pubkey, &[chain(1)]).unwrap().chain_mappings.len(), 1);
+}
+
+#[test]
+fn test_only_org_owners_manage_admins() {
+    let keys = MockKeyCreator {
+        default_key_counter: Arc::new(Mutex::new(0)),
+        chain_key_counter: Arc::new(Mutex::new(1000)),
+    };
+    let provisioner = Provisioner::new(MockKvStore::new(), keys).with_admins(MockKvStore::new());
+
+    let admin_requester = Requester { identity: "admin@test".to_string(), is_org_owner: false, is_service_account: false };
+    provisioner.handle_set_admin(&owner(), "admin@test", true).unwrap();
+
+    let err = provisioner.handle_set_admin(&admin_requester, "mallory@test", true).unwrap_err();
+    assert!(err.to_string().contains("Only org owners"));
+
+    // Without an allowlist there is nothing to manage
+    let provisioner = Provisioner::new(MockKvStore::new(), MockKeyCreator::new());
+    assert!(provisioner.handle_set_admin(&owner(), "admin@test", true).is_err());
+}
+
+// =============================================================================
+// AUTHORIZATION MATRIX TESTS
+// =============================================================================
+
+fn requester(identity: &str, is_org_owner: bool, is_service_account: bool) -> Requester {
+    Requester { identity: identity.to_string(), is_org_owner, is_service_account }
+}
+
+#[test]
+fn test_authorization_matrix_by_role() {
+    let admins = MockKvStore::new();
+    admin::set_admin(&admins, &owner(), "admin@test", true, 1000).unwrap();
+    let reader = requester("User#reader", false, false);
+    let service = requester("Role#backend", false, true);
+    let admin = requester("admin@test", false, false);
+
+    authz::authorize(&admins, &reader, "get").unwrap();
+    let err = authz::authorize(&admins, &reader, "store").unwrap_err();
+    assert_eq!(
+        err,
+        ProvisionError::Forbidden { identity: "User#reader".to_string(), action: "store".to_string() }
+    );
+    assert_eq!(authz::authorize(&admins, &reader, "freeze").unwrap_err().code(), "NOT_ADMIN");
+
+    authz::authorize(&admins, &service, "store").unwrap();
+    authz::authorize(&admins, &service, "update_self").unwrap();
+    assert_eq!(authz::authorize(&admins, &service, "approve_update").unwrap_err().code(), "NOT_ADMIN");
+
+    // Admins can do what service accounts can, but not manage admins
+    authz::authorize(&admins, &admin, "store").unwrap();
+    authz::authorize(&admins, &admin, "approve_update").unwrap();
+    assert_eq!(authz::authorize(&admins, &admin, "add_admin").unwrap_err().code(), "NOT_ORG_OWNER");
+    // The blocklist is shared by every tenant, so no admin writes it
+    assert_eq!(authz::authorize(&admins, &admin, "block").unwrap_err().code(), "NOT_ORG_OWNER");
+    assert_eq!(authz::authorize(&admins, &admin, "unblock").unwrap_err().code(), "NOT_ORG_OWNER");
+
+    // Owners manage admins, but act as admins only when listed
+    authz::authorize(&admins, &owner(), "add_admin").unwrap();
+    authz::authorize(&admins, &owner(), "block").unwrap();
+    authz::authorize(&admins, &owner(), "store").unwrap();
+    assert_eq!(authz::authorize(&admins, &owner(), "freeze").unwrap_err().code(), "NOT_ADMIN");
+}
+
+#[test]
+fn test_authorization_refuses_anonymous_and_unknown_actions() {
+    let admins = MockKvStore::new();
+    let anonymous = requester("", true, true);
+    let err = authz::authorize(&admins, &anonymous, "get").unwrap_err();
+    assert_eq!(err.to_string(), "anonymous is not allowed to get");
+
+    assert_eq!(authz::required_role("rotate_everything"), Role::Owner);
+    assert!(authz::authorize(&admins, &requester("Role#backend", false, true), "rotate_everything").is_err());
+
+    // Every action is listed once
+    let mut actions: Vec<&str> = authz::MATRIX.iter().map(|(action, _)| *action).collect();
+    actions.sort_unstable();
+    actions.dedup();
+    assert_eq!(actions.len(), authz::MATRIX.len());
+}
+
+#[test]
+fn test_provisioner_checks_handlers_against_the_matrix() {
+    let ctx = TestContext::new();
+    let alice = wallet(1);
+    let provisioned = ctx.handle(provision_request(&alice, vec![1])).unwrap();
+    let reader = requester("User#reader", false, false);
+
+    // Readers may look mappings up, but neither change nor export them
+    let err = ctx.provisioner.handle_update_mapping(&reader, update_request(&pubkey(&alice), 1)).unwrap_err();
+    assert_eq!(err, ProvisionError::NotAdmin("User#reader".to_string()));
+    assert_eq!(ctx.provisioner.handle_export(&reader, ExportRequest::default()).unwrap_err().code(), "NOT_ADMIN");
+    assert_eq!(kv::get_existing_mapping(ctx.provisioner.kv(), &pubkey(&alice), &chain(1)).unwrap(), Some(provisioned.evm_address));
+
+    // Nor do service accounts, unless listed as admins
+    let service = requester("Role#backend", false, true);
+    assert_eq!(ctx.provisioner.handle_export(&service, ExportRequest::default()).unwrap_err().code(), "NOT_ADMIN");
+    assert!(ctx.provisioner.handle_export(&admin(), ExportRequest::default()).is_ok());
+}
@@ -0,0 +1,108 @@
+mod common;
+
+use common::{approval_provisioner, named, propose_request, resolve_request};
+use cubist_wallet_provisioner::approval::{PendingStatus, PENDING_UPDATE_TTL};
+use cubist_wallet_provisioner::kv;
+use cubist_wallet_provisioner::testing::{chain, provision_request_at, pubkey, wallet};
+use std::sync::atomic::Ordering;
+
+// =============================================================================
+// TWO-PHASE APPROVAL TESTS
+// =============================================================================
+
+#[test]
+fn test_approved_update_rotates_chain_key() {
+    let (provisioner, _) = approval_provisioner();
+    let user = wallet(1);
+    let solana_pubkey = pubkey(&user);
+    let provisioned = provisioner.handle(provision_request_at(&user, vec![1, 137], 1000)).unwrap();
+
+    let pending = provisioner.handle_propose_update(&named("alice@test"), propose_request(&solana_pubkey, 137)).unwrap();
+    assert_eq!(pending.id, 1);
+    assert_eq!(pending.status, PendingStatus::Pending);
+    assert_eq!(pending.expires_at, 1000 + PENDING_UPDATE_TTL);
+
+    // Nothing changes until the second admin approves
+    let current = kv::get_existing_mapping(provisioner.kv(), &solana_pubkey, &chain(137)).unwrap();
+    assert_eq!(current, Some(provisioned.evm_address.clone()));
+
+    let result = provisioner.handle_approve_update(&named("bob@test"), resolve_request(&solana_pubkey, 137, 1)).unwrap();
+    assert_ne!(result.new_evm_address, provisioned.evm_address);
+
+    let resolved = provisioner.handle_pending(&solana_pubkey, &chain(137)).unwrap().unwrap();
+    assert_eq!(resolved.status, PendingStatus::Approved);
+    assert_eq!(resolved.resolved_by.as_deref(), Some("bob@test"));
+
+    let history = provisioner.handle_history(&solana_pubkey, &chain(137)).unwrap();
+    assert_eq!(history.entries[0].replaced_by, "bob@test");
+
+    // A resolved proposal cannot be approved again
+    let err = provisioner.handle_approve_update(&named("bob@test"), resolve_request(&solana_pubkey, 137, 1)).unwrap_err();
+    assert!(err.to_string().contains("already approved"));
+}
+
+#[test]
+fn test_proposer_cannot_approve_own_update() {
+    let (provisioner, _) = approval_provisioner();
+    let user = wallet(1);
+    let solana_pubkey = pubkey(&user);
+    provisioner.handle(provision_request_at(&user, vec![1], 1000)).unwrap();
+
+    provisioner.handle_propose_update(&named("alice@test"), propose_request(&solana_pubkey, 1)).unwrap();
+    let err = provisioner.handle_approve_update(&named("alice@test"), resolve_request(&solana_pubkey, 1, 1)).unwrap_err();
+    assert!(err.to_string().contains("different admin"));
+
+    // Only one open proposal per chain
+    let err = provisioner.handle_propose_update(&named("bob@test"), propose_request(&solana_pubkey, 1)).unwrap_err();
+    assert!(err.to_string().contains("already pending"));
+}
+
+#[test]
+fn test_rejected_update_leaves_mapping_unchanged() {
+    let (provisioner, _) = approval_provisioner();
+    let user = wallet(1);
+    let solana_pubkey = pubkey(&user);
+    let provisioned = provisioner.handle(provision_request_at(&user, vec![1], 1000)).unwrap();
+
+    provisioner.handle_propose_update(&named("alice@test"), propose_request(&solana_pubkey, 1)).unwrap();
+    let rejected = provisioner.handle_reject_update(&named("bob@test"), resolve_request(&solana_pubkey, 1, 1)).unwrap();
+    assert_eq!(rejected.status, PendingStatus::Rejected);
+
+    assert!(provisioner.handle_approve_update(&named("bob@test"), resolve_request(&solana_pubkey, 1, 1)).is_err());
+    let current = kv::get_existing_mapping(provisioner.kv(), &solana_pubkey, &chain(1)).unwrap();
+    assert_eq!(current, Some(provisioned.evm_address));
+
+    // The chain is free for a new proposal
+    let next = provisioner.handle_propose_update(&named("alice@test"), propose_request(&solana_pubkey, 1)).unwrap();
+    assert_eq!(next.id, 2);
+}
+
+#[test]
+fn test_pending_update_expires() {
+    let (provisioner, now) = approval_provisioner();
+    let user = wallet(1);
+    let solana_pubkey = pubkey(&user);
+    provisioner.handle(provision_request_at(&user, vec![1], 1000)).unwrap();
+
+    provisioner.handle_propose_update(&named("alice@test"), propose_request(&solana_pubkey, 1)).unwrap();
+    now.store(1000 + PENDING_UPDATE_TTL + 1, Ordering::SeqCst);
+
+    let err = provisioner.handle_approve_update(&named("bob@test"), resolve_request(&solana_pubkey, 1, 1)).unwrap_err();
+    assert!(err.to_string().contains("expired"));
+
+    // An expired proposal no longer blocks new ones
+    let next = provisioner.handle_propose_update(&named("bob@test"), propose_request(&solana_pubkey, 1)).unwrap();
+    assert_eq!(next.id, 2);
+    assert!(provisioner.handle_approve_update(&named("alice@test"), resolve_request(&solana_pubkey, 1, 1)).is_err());
+    provisioner.handle_approve_update(&named("alice@test"), resolve_request(&solana_pubkey, 1, 2)).unwrap();
+}
+
+#[test]
+fn test_propose_requires_provisioned_address() {
+    let (provisioner, _) = approval_provisioner();
+    let solana_pubkey = pubkey(&wallet(9));
+
+    let err = provisioner.handle_propose_update(&named("alice@test"), propose_request(&solana_pubkey, 1)).unwrap_err();
+    assert!(err.to_string().contains("not been provisioned"));
+    assert!(provisioner.handle_pending(&solana_pubkey, &chain(1)).unwrap().is_none());
+}
@@ -0,0 +1,171 @@
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use cubist_wallet_provisioner::async_api::{AsyncProvisioner, Blocking, ThreadPerCall};
+use cubist_wallet_provisioner::error::Result;
+use cubist_wallet_provisioner::testing::{admin, admins, store_message};
+use cubist_wallet_provisioner::{
+    ChainId, CreatedKey, EvmAddress, KeyClass, KeyCreator, KeyType, KvStore, ProvisionRequest, Provisioner, SolanaPubkey, UpdateMappingRequest,
+};
+use ed25519_dalek::{Signer, SigningKey};
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Wake};
+use std::thread::{self, Thread, ThreadId};
+
+/// KV store over a shared map
+#[derive(Clone, Default)]
+struct MapKv(Arc<Mutex<HashMap<String, String>>>);
+
+impl KvStore for MapKv {
+    fn get(&self, key: &str) -> Result<Option<String>> {
+        Ok(self.0.lock().unwrap().get(key).cloned())
+    }
+
+    fn set_if_absent(&self, key: &str, value: &str) -> Result<bool> {
+        let mut data = self.0.lock().unwrap();
+        if data.contains_key(key) {
+            return Ok(false);
+        }
+        data.insert(key.to_string(), value.to_string());
+        Ok(true)
+    }
+
+    fn set(&self, key: &str, value: &str) -> Result<()> {
+        self.0.lock().unwrap().insert(key.to_string(), value.to_string());
+        Ok(())
+    }
+}
+
+/// Key creator handing out sequential addresses and recording the threads it runs on
+#[derive(Clone, Default)]
+struct ThreadKeys {
+    created: Arc<AtomicUsize>,
+    threads: Arc<Mutex<Vec<ThreadId>>>,
+}
+
+impl ThreadKeys {
+    fn next(&self) -> Result<CreatedKey> {
+        self.threads.lock().unwrap().push(thread::current().id());
+        let n = self.created.fetch_add(1, Ordering::SeqCst) + 1;
+        let address = format!("0x{:040x}", n);
+        Ok(CreatedKey { key_id: format!("Key#{}", address), address, policies: Vec::new() })
+    }
+}
+
+impl KeyCreator for ThreadKeys {
+    fn create_evm_key(&self, _solana_pubkey: &str) -> Result<CreatedKey> {
+        self.next()
+    }
+
+    fn create_evm_key_for_chain(&self, _solana_pubkey: &str, _chain_id: &ChainId) -> Result<CreatedKey> {
+        self.next()
+    }
+
+    fn create_labeled_evm_key(&self, _solana_pubkey: &str, _label: &str, _chain_id: Option<&ChainId>) -> Result<CreatedKey> {
+        self.next()
+    }
+}
+
+/// `Blocking` counting the tasks it runs
+#[derive(Clone, Default)]
+struct CountingBlocking(Arc<AtomicUsize>);
+
+impl Blocking for CountingBlocking {
+    fn spawn_blocking(&self, task: Box<dyn FnOnce() + Send>) {
+        self.0.fetch_add(1, Ordering::SeqCst);
+        ThreadPerCall.spawn_blocking(task);
+    }
+}
+
+/// Wakes the thread blocked in `block_on`
+struct Unpark(Thread);
+
+impl Wake for Unpark {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark();
+    }
+}
+
+/// Minimal executor: poll `future` on this thread until it completes
+fn block_on<F: Future>(future: F) -> F::Output {
+    let waker = Arc::new(Unpark(thread::current())).into();
+    let mut cx = Context::from_waker(&waker);
+    let mut future = pin!(future);
+    loop {
+        if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+            return output;
+        }
+        thread::park();
+    }
+}
+
+fn provision_request(seed: u8) -> ProvisionRequest {
+    let wallet = SigningKey::from_bytes(&[seed; 32]);
+    let solana_pubkey = SolanaPubkey::parse(&bs58::encode(wallet.verifying_key().to_bytes()).into_string()).unwrap();
+    let chain_ids = vec![ChainId::eip155(1), ChainId::eip155(137)];
+    let message = store_message(&solana_pubkey, &chain_ids);
+    ProvisionRequest {
+        solana_pubkey,
+        chain_ids,
+        signature: BASE64.encode(wallet.sign(message.as_bytes()).to_bytes()),
+        message,
+        label: None,
+        key_type: KeyType::default(),
+        key_class: KeyClass::default(),
+        idempotency_key: None,
+        ttl_secs: None,
+        request_id: None,
+    }
+}
+
+#[test]
+fn test_async_handlers_run_off_the_calling_thread() {
+    let keys = ThreadKeys::default();
+    let provisioner = Provisioner::new(MapKv::default(), keys.clone()).with_admins(admins()).with_single_step_updates();
+    let provisioner = AsyncProvisioner::new(provisioner);
+    let solana_pubkey = provision_request(1).solana_pubkey;
+
+    let stored = block_on(provisioner.handle(provision_request(1))).unwrap();
+    assert_eq!(block_on(provisioner.handle(provision_request(1))).unwrap().evm_address, stored.evm_address);
+    assert!(keys.threads.lock().unwrap().iter().all(|id| *id != thread::current().id()));
+
+    let update = UpdateMappingRequest {
+        solana_pubkey: solana_pubkey.clone(),
+        chain_id: ChainId::eip155(137),
+        expected_version: None,
+        label: None,
+        idempotency_key: None,
+        request_id: None,
+    };
+    let updated = block_on(provisioner.handle_update_mapping(admin(), update)).unwrap();
+    let mappings = block_on(provisioner.handle_get(solana_pubkey, vec![ChainId::eip155(1), ChainId::eip155(137)])).unwrap();
+    assert_eq!(mappings.chain_mappings[&ChainId::eip155(1)], stored.evm_address);
+    assert_eq!(mappings.chain_mappings[&ChainId::eip155(137)], updated.new_evm_address);
+    assert_eq!(keys.created.load(Ordering::SeqCst), 2);
+}
+
+#[test]
+fn test_async_provisioner_uses_the_given_executor() {
+    let blocking = CountingBlocking::default();
+    let provisioner = AsyncProvisioner::new(Provisioner::new(MapKv::default(), ThreadKeys::default())).with_blocking(blocking.clone());
+
+    let futures: Vec<_> = (1..=3).map(|seed| provisioner.handle(provision_request(seed))).collect();
+    let addresses: Vec<EvmAddress> = futures.into_iter().map(|future| block_on(future).unwrap().evm_address).collect();
+    assert_eq!(addresses.len(), 3);
+    assert_ne!(addresses[0], addresses[1]);
+
+    // Any other handler goes through `run`
+    let chains = block_on(provisioner.run(|provisioner| provisioner.handle_chains())).unwrap();
+    assert!(!chains.is_empty());
+    assert_eq!(blocking.0.load(Ordering::SeqCst), 4);
+}
+
+#[test]
+fn test_panicking_handler_panics_the_future() {
+    let provisioner = AsyncProvisioner::new(Provisioner::new(MapKv::default(), ThreadKeys::default()));
+    let completion = provisioner.run(|_| -> Result<()> { panic!("handler failed") });
+    let payload = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| block_on(completion))).unwrap_err();
+    assert_eq!(payload.downcast_ref::<&str>(), Some(&"handler failed"));
+}
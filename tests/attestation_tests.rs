@@ -0,0 +1,266 @@
+use cubist_wallet_provisioner::attestation::{self, AttestationSigner, MappingAttestation};
+use cubist_wallet_provisioner::auth;
+use cubist_wallet_provisioner::certificates::{self, MAX_CERTIFICATE_TTL_SECS};
+use cubist_wallet_provisioner::error::Result;
+use cubist_wallet_provisioner::response_signing;
+use cubist_wallet_provisioner::testing::{chain, provision_request, provision_request_at, pubkey, wallet, TestContext};
+use cubist_wallet_provisioner::{ChainId, EvmAddress};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+
+// =============================================================================
+// ATTESTATION TESTS
+// =============================================================================
+
+/// Attestation key held locally, standing in for CubeSigner
+struct LocalAttester {
+    key: k256::ecdsa::SigningKey,
+    address: EvmAddress,
+}
+
+impl LocalAttester {
+    fn new(seed: u8) -> Self {
+        let key = k256::ecdsa::SigningKey::from_bytes(&[seed; 32].into()).unwrap();
+        let address = EvmAddress::parse(&auth::evm_address_of(key.verifying_key())).unwrap();
+        Self { key, address }
+    }
+}
+
+impl AttestationSigner for LocalAttester {
+    fn attester(&self) -> &EvmAddress {
+        &self.address
+    }
+
+    fn sign(&self, attestation: &MappingAttestation) -> Result<String> {
+        let (signature, recovery_id) = self.key.sign_prehash_recoverable(&attestation.digest()?).unwrap();
+        let mut bytes = signature.to_bytes().to_vec();
+        bytes.push(27 + recovery_id.to_byte());
+        Ok(format!("0x{}", bytes.iter().map(|b| format!("{:02x}", b)).collect::<String>()))
+    }
+}
+
+#[test]
+fn test_attestation_verifies_against_attester() {
+    let ctx = TestContext::new();
+    let provisioner = ctx.provisioner.with_attester(LocalAttester::new(7)).with_clock(|| 1_700_000_000);
+    let alice = wallet(1);
+    let solana_pubkey = pubkey(&alice);
+    let stored = provisioner.handle(provision_request_at(&alice, vec![137], 1_700_000_000)).unwrap();
+
+    let signed = provisioner.handle_attest(&solana_pubkey, &chain(137)).unwrap();
+    assert_eq!(signed.attestation.evm_address, stored.evm_address);
+    assert_eq!(signed.attestation.version, 0);
+    assert_eq!(signed.attestation.issued_at, 1_700_000_000);
+    assert_eq!(signed.attester, LocalAttester::new(7).address);
+    attestation::verify(&signed, &LocalAttester::new(7).address).unwrap();
+
+    // Another attester, or any change to the statement, fails verification
+    let err = attestation::verify(&signed, &LocalAttester::new(8).address).unwrap_err();
+    assert_eq!(err.code(), "SIGNATURE_MISMATCH");
+    let mut forged = signed.clone();
+    forged.attestation.chain_id = chain(1);
+    assert!(attestation::verify(&forged, &signed.attester).is_err());
+    let mut forged = signed.clone();
+    forged.attestation.evm_address = EvmAddress::parse("0x000000000000000000000000000000000000dEaD").unwrap();
+    assert!(attestation::verify(&forged, &signed.attester).is_err());
+}
+
+#[test]
+fn test_attestation_digest_matches_eip712_typed_data() {
+    let attestation = MappingAttestation {
+        solana_pubkey: pubkey(&wallet(1)),
+        chain_id: chain(137),
+        evm_address: EvmAddress::parse("0xcb373e47d769b06dee02f05c86dd8790e0358aee").unwrap(),
+        version: 2,
+        issued_at: 1_700_000_000,
+    };
+    let typed_data = attestation.typed_data().unwrap();
+    assert_eq!(typed_data["primaryType"], "MappingAttestation");
+    assert_eq!(typed_data["domain"]["chainId"], 137);
+    assert_eq!(typed_data["message"]["chain"], "eip155:137");
+    assert_eq!(typed_data["message"]["version"], 2);
+
+    // Any attested field changes the digest
+    let digest = attestation.digest().unwrap();
+    let rotated = MappingAttestation { version: 3, ..attestation.clone() };
+    assert_ne!(rotated.digest().unwrap(), digest);
+    let other_chain = MappingAttestation { chain_id: chain(1), ..attestation };
+    assert_ne!(other_chain.digest().unwrap(), digest);
+}
+
+#[test]
+fn test_attest_rejects_unmapped_and_non_evm_chains() {
+    let ctx = TestContext::new();
+    let provisioner = ctx.provisioner.with_attester(LocalAttester::new(7));
+    let alice = wallet(1);
+    provisioner.handle(provision_request(&alice, vec![1])).unwrap();
+
+    let err = provisioner.handle_attest(&pubkey(&wallet(2)), &chain(1)).unwrap_err();
+    assert_eq!(err.code(), "NOT_PROVISIONED");
+    let solana = ChainId::parse("solana:5eykt4UsFv8P8NJdTREpY1vzqKqZKvdp").unwrap();
+    let err = provisioner.handle_attest(&pubkey(&alice), &solana).unwrap_err();
+    assert_eq!(err.code(), "INVALID_REQUEST");
+}
+
+// =============================================================================
+// CERTIFICATE TESTS
+// =============================================================================
+
+#[test]
+fn test_certificate_round_trips_through_verify() {
+    let ctx = TestContext::new();
+    let provisioner = ctx.provisioner.with_certificate_key(wallet(9)).with_clock(|| 1_700_000_000);
+    let alice = wallet(1);
+    let solana_pubkey = pubkey(&alice);
+    let stored = provisioner.handle(provision_request_at(&alice, vec![137], 1_700_000_000)).unwrap();
+
+    let certificate = provisioner.handle_certificate(&solana_pubkey, &chain(137), None).unwrap();
+    assert_eq!(certificate.expires_at, 1_700_000_300);
+    let claims = certificates::verify_mapping_jwt(&certificate.token, &wallet(9).verifying_key(), 1_700_000_299).unwrap();
+    assert_eq!(claims.solana_pubkey, solana_pubkey);
+    assert_eq!(claims.chain_id, chain(137));
+    assert_eq!(claims.evm_address, stored.evm_address);
+    assert_eq!(claims.iat, 1_700_000_000);
+    assert_eq!(claims.exp, 1_700_000_300);
+
+    // Expired, or signed by another key
+    let err = certificates::verify_mapping_jwt(&certificate.token, &wallet(9).verifying_key(), 1_700_000_300).unwrap_err();
+    assert_eq!(err.code(), "INVALID_CERTIFICATE");
+    let err = certificates::verify_mapping_jwt(&certificate.token, &wallet(8).verifying_key(), 1_700_000_000).unwrap_err();
+    assert_eq!(err.code(), "INVALID_CERTIFICATE");
+}
+
+#[test]
+fn test_tampered_certificates_are_rejected() {
+    let ctx = TestContext::new();
+    let provisioner = ctx.provisioner.with_certificate_key(wallet(9)).with_clock(|| 1_700_000_000);
+    let alice = wallet(1);
+    provisioner.handle(provision_request_at(&alice, vec![1], 1_700_000_000)).unwrap();
+    let token = provisioner.handle_certificate(&pubkey(&alice), &chain(1), Some(60)).unwrap().token;
+    let key = wallet(9).verifying_key();
+    let parts: Vec<&str> = token.split('.').collect();
+    let encode = |json: &str| base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(json);
+
+    // Claims swapped for another address under the original signature
+    let forged_claims = format!(
+        r#"{{"solana_pubkey":"{}","chain_id":"eip155:1","evm_address":"0x000000000000000000000000000000000000dead","iat":1700000000,"exp":1700000060}}"#,
+        pubkey(&alice)
+    );
+    let forged = format!("{}.{}.{}", parts[0], encode(&forged_claims), parts[2]);
+    // `alg: none` with the signature stripped
+    let unsigned = format!("{}.{}.", encode(r#"{"alg":"none","typ":"JWT"}"#), parts[1]);
+
+    for token in [forged, unsigned, "not-a-jwt".to_string(), format!("{}.extra", token)] {
+        let err = certificates::verify_mapping_jwt(&token, &key, 1_700_000_000).unwrap_err();
+        assert_eq!(err.code(), "INVALID_CERTIFICATE", "{}", token);
+    }
+}
+
+#[test]
+fn test_certificate_ttl_and_configuration_are_checked() {
+    let alice = wallet(1);
+    let unconfigured = TestContext::new();
+    unconfigured.handle(provision_request(&alice, vec![1])).unwrap();
+    let err = unconfigured.provisioner.handle_certificate(&pubkey(&alice), &chain(1), None).unwrap_err();
+    assert_eq!(err.code(), "NOT_CONFIGURED");
+
+    let provisioner = TestContext::new().provisioner.with_certificate_key(wallet(9));
+    provisioner.handle(provision_request(&alice, vec![1])).unwrap();
+    for ttl in [0, MAX_CERTIFICATE_TTL_SECS + 1] {
+        let err = provisioner.handle_certificate(&pubkey(&alice), &chain(1), Some(ttl)).unwrap_err();
+        assert_eq!(err.code(), "INVALID_REQUEST");
+    }
+    let err = provisioner.handle_certificate(&pubkey(&wallet(2)), &chain(1), None).unwrap_err();
+    assert_eq!(err.code(), "NOT_PROVISIONED");
+}
+
+// =============================================================================
+// RESPONSE SIGNING TESTS
+// =============================================================================
+
+/// Request the signed envelopes answer
+const SIGNED_REQUEST: &str = r#"{"action":"get","solana_pubkey":"11111111111111111111111111111112","envelope":true,"request_id":"req-1"}"#;
+
+/// An envelope as the policy sends it in answer to `SIGNED_REQUEST`, signed
+/// by `wallet(seed)` at `now`
+fn signed_envelope(seed: u8, now: u64) -> serde_json::Value {
+    let mut envelope = serde_json::json!({
+        "envelope": 1,
+        "outcome": "success",
+        "payload": { "evm_address": "0x00000000000000000000000000000000000000aa", "chain_mappings": { "eip155:137": "0x00000000000000000000000000000000000000aa" } },
+    });
+    let signature = response_signing::sign(&envelope, &response_signing::request_sha256(SIGNED_REQUEST), &wallet(seed), now);
+    envelope["signature"] = serde_json::to_value(signature).unwrap();
+    envelope
+}
+
+#[test]
+fn test_signed_response_verifies_however_its_keys_are_ordered() {
+    let envelope = signed_envelope(9, 1_700_000_000);
+    let body = serde_json::to_string(&envelope).unwrap();
+
+    let verified = response_signing::verify_response(&body, SIGNED_REQUEST, &wallet(9).verifying_key(), 1_700_000_030, 60).unwrap();
+    assert_eq!(verified["payload"]["evm_address"], "0x00000000000000000000000000000000000000aa");
+    assert!(verified.get("signature").is_none());
+
+    // Signed over canonical JSON, so reserializing in another order keeps it valid
+    let reordered = format!(
+        r#"{{"signature":{},"payload":{},"outcome":"success","envelope":1}}"#,
+        envelope["signature"], envelope["payload"]
+    );
+    response_signing::verify_response(&reordered, SIGNED_REQUEST, &wallet(9).verifying_key(), 1_700_000_030, 60).unwrap();
+
+    // So does the request the backend sent, however CubeSigner reserializes it
+    let request: serde_json::Value = serde_json::from_str(SIGNED_REQUEST).unwrap();
+    let pretty = serde_json::to_string_pretty(&request).unwrap();
+    response_signing::verify_response(&body, &pretty, &wallet(9).verifying_key(), 1_700_000_030, 60).unwrap();
+}
+
+#[test]
+fn test_altered_or_stale_responses_are_refused() {
+    let envelope = signed_envelope(9, 1_700_000_000);
+    let key = wallet(9).verifying_key();
+    let reason = |body: &str, key: &ed25519_dalek::VerifyingKey, now: u64| {
+        let err = response_signing::verify_response(body, SIGNED_REQUEST, key, now, 60).unwrap_err();
+        assert_eq!(err.code(), "INVALID_RESPONSE_SIGNATURE");
+        err.to_string()
+    };
+
+    let mut altered = envelope.clone();
+    altered["payload"]["evm_address"] = "0x00000000000000000000000000000000000000bb".into();
+    assert!(reason(&altered.to_string(), &key, 1_700_000_000).ends_with("the response was altered"));
+
+    // A matching hash does not help without the key
+    let mut forged = altered.clone();
+    let unsigned_altered = serde_json::json!({ "envelope": 1, "outcome": "success", "payload": altered["payload"] });
+    let rehashed = response_signing::sign(&unsigned_altered, &response_signing::request_sha256(SIGNED_REQUEST), &wallet(8), 1_700_000_000);
+    forged["signature"]["sha256"] = rehashed.sha256.into();
+    assert!(reason(&forged.to_string(), &key, 1_700_000_000).ends_with("signature does not match the response key"));
+
+    assert!(reason(&envelope.to_string(), &wallet(8).verifying_key(), 1_700_000_000).ends_with("signed by another key"));
+    assert!(reason(&envelope.to_string(), &key, 1_700_000_061).ends_with("too long ago"));
+    let mut unsigned = envelope.clone();
+    unsigned.as_object_mut().unwrap().remove("signature");
+    assert!(reason(&unsigned.to_string(), &key, 1_700_000_000).ends_with("unsigned"));
+
+    // A genuine reply to another request cannot stand in for this one's
+    let other = r#"{"action":"get","solana_pubkey":"11111111111111111111111111111113","envelope":true,"request_id":"req-2"}"#;
+    let err = response_signing::verify_response(&envelope.to_string(), other, &key, 1_700_000_000, 60).unwrap_err();
+    assert!(err.to_string().ends_with("the response answers another request"));
+    let mut rebound = envelope.clone();
+    rebound["signature"]["request_sha256"] = response_signing::request_sha256(other).into();
+    let err = response_signing::verify_response(&rebound.to_string(), other, &key, 1_700_000_000, 60).unwrap_err();
+    assert!(err.to_string().ends_with("signature does not match the response key"));
+}
+
+#[test]
+fn test_malformed_response_signing_key_is_not_configured() {
+    let seed = BASE64.encode([9; 32]);
+    let key = response_signing::signing_key_from_base64(&format!("{}\n", seed)).unwrap();
+    assert_eq!(key.verifying_key(), wallet(9).verifying_key());
+
+    // Too short, too long, or not base64: an error, never a panic
+    for malformed in [BASE64.encode([9; 31]), BASE64.encode([9; 33]), "not base64!".to_string(), String::new()] {
+        let err = response_signing::signing_key_from_base64(&malformed).unwrap_err();
+        assert_eq!(err.code(), "NOT_CONFIGURED");
+    }
+}
@@ -0,0 +1,191 @@
+use cubist_wallet_provisioner::audit::{self, AuditQuery};
+use cubist_wallet_provisioner::merkle;
+use cubist_wallet_provisioner::testing::{
+    admin, admins, chain, provision_request, provision_request_at, pubkey, update_request, wallet, MockKeyCreator, MockKvStore, TestContext,
+};
+use cubist_wallet_provisioner::{EvmAddress, KvStore, Provisioner};
+use std::sync::{Arc, Mutex};
+
+// =============================================================================
+// AUDIT LOG TESTS
+// =============================================================================
+
+#[test]
+fn test_audit_records_provision_and_update() {
+    let ctx = TestContext::new();
+    let alice = wallet(1);
+    let solana_pubkey = pubkey(&alice);
+
+    ctx.handle(provision_request(&alice, vec![1])).unwrap();
+    ctx.handle_update_mapping(update_request(&solana_pubkey, 1)).unwrap();
+
+    let page = ctx.provisioner.handle_audit_query(&admin(), &AuditQuery::default()).unwrap();
+    assert_eq!(page.records.len(), 2);
+    assert_eq!(page.next_seq, None);
+
+    assert_eq!(page.records[0].action, "provision");
+    assert_eq!(page.records[0].actor, solana_pubkey.as_str());
+    assert_eq!(page.records[1].action, "update");
+    assert_eq!(page.records[1].actor, "admin@test");
+    assert_eq!(page.records[1].subject.as_deref(), Some(solana_pubkey.as_str()));
+    assert!(page.records.iter().all(|r| r.success));
+
+    audit::verify_chain(None, &page.records).unwrap();
+}
+
+#[test]
+fn test_audit_records_failures() {
+    let ctx = TestContext::new();
+    let alice = wallet(1);
+
+    // Update before provision fails, and the failure is recorded
+    assert!(ctx.handle_update_mapping(update_request(&pubkey(&alice), 1)).is_err());
+
+    let mut forged = provision_request(&alice, vec![1]);
+    forged.signature = provision_request(&alice, vec![1]).signature;
+    assert!(ctx.handle(forged).is_err());
+
+    let records = ctx.provisioner.handle_audit_query(&admin(), &AuditQuery::default()).unwrap().records;
+    assert_eq!(records.len(), 2);
+    assert!(records.iter().all(|r| !r.success));
+    assert!(records[0].error.as_deref().unwrap().contains("has not been provisioned"));
+    assert!(records[1].error.as_deref().unwrap().contains("Signature verification failed"));
+}
+
+#[test]
+fn test_audit_query_filters_by_time_and_pages() {
+    let kv = MockKvStore::new();
+    let keys = MockKeyCreator {
+        default_key_counter: Arc::new(Mutex::new(0)),
+        chain_key_counter: Arc::new(Mutex::new(1000)),
+    };
+    let now = Arc::new(Mutex::new(100u64));
+    let clock = Arc::clone(&now);
+    let provisioner = Provisioner::new(kv, keys).with_admins(admins()).with_clock(move || *clock.lock().unwrap());
+
+    for seed in 1..=5 {
+        *now.lock().unwrap() = 100 * seed as u64;
+        provisioner.handle(provision_request_at(&wallet(seed), vec![1], 100 * seed as u64)).unwrap();
+    }
+
+    let in_range = AuditQuery { from: Some(200), to: Some(400), ..Default::default() };
+    let records = provisioner.handle_audit_query(&admin(), &in_range).unwrap().records;
+    assert_eq!(records.iter().map(|r| r.timestamp).collect::<Vec<_>>(), vec![200, 300, 400]);
+
+    // Page through two at a time
+    let first = provisioner.handle_audit_query(&admin(), &AuditQuery { limit: Some(2), ..Default::default() }).unwrap();
+    assert_eq!(first.records.len(), 2);
+    assert_eq!(first.next_seq, Some(2));
+
+    let rest = provisioner
+        .handle_audit_query(&admin(), &AuditQuery { after_seq: first.next_seq, ..Default::default() })
+        .unwrap();
+    assert_eq!(rest.records.len(), 3);
+    audit::verify_chain(first.records.last(), &rest.records).unwrap();
+}
+
+#[test]
+fn test_audit_chain_detects_tampering() {
+    let ctx = TestContext::new();
+    for seed in 1..=3 {
+        ctx.handle(provision_request(&wallet(seed), vec![1])).unwrap();
+    }
+
+    let mut records = ctx.provisioner.handle_audit_query(&admin(), &AuditQuery::default()).unwrap().records;
+    audit::verify_chain(None, &records).unwrap();
+
+    // Rewriting a record invalidates its hash
+    records[1].actor = "mallory".to_string();
+    assert!(audit::verify_chain(None, &records).is_err());
+
+    // Dropping a record breaks the chain
+    records.remove(1);
+    assert!(audit::verify_chain(None, &records).is_err());
+}
+
+#[test]
+fn test_audit_records_are_never_overwritten() {
+    let ctx = TestContext::new();
+    ctx.handle(provision_request(&wallet(1), vec![1])).unwrap();
+
+    // A stale head hint must not make the next append reuse seq 1
+    ctx.kv.set("audit:head", "0").unwrap();
+    ctx.handle(provision_request(&wallet(2), vec![1])).unwrap();
+
+    let records = ctx.provisioner.handle_audit_query(&admin(), &AuditQuery::default()).unwrap().records;
+    assert_eq!(records.len(), 2);
+    assert_eq!(records[0].actor, pubkey(&wallet(1)).as_str());
+    audit::verify_chain(None, &records).unwrap();
+}
+
+// =============================================================================
+// MERKLE TESTS
+// =============================================================================
+
+#[test]
+fn test_merkle_root_of_empty_bucket_is_zero() {
+    let ctx = TestContext::new();
+    let root = ctx.provisioner.handle_merkle_root(&admin()).unwrap();
+    assert_eq!(root.root, format!("0x{}", "0".repeat(64)));
+    assert_eq!(root.leaf_count, 0);
+}
+
+#[test]
+fn test_merkle_proofs_verify_against_root() {
+    let ctx = TestContext::new();
+    for seed in 1..=3 {
+        ctx.handle(provision_request(&wallet(seed), vec![1, 137])).unwrap();
+    }
+    let root = ctx.provisioner.handle_merkle_root(&admin()).unwrap();
+    // One leaf per stored chain mapping
+    assert_eq!(root.leaf_count, 6);
+
+    for seed in 1..=3 {
+        for chain_id in [chain(1), chain(137)] {
+            let proof = ctx.provisioner.handle_merkle_proof(&pubkey(&wallet(seed)), &chain_id).unwrap();
+            assert_eq!(proof.root, root.root);
+            assert!(merkle::verify(&proof, &root.root));
+        }
+    }
+
+    let proof = ctx.provisioner.handle_merkle_proof(&pubkey(&wallet(1)), &chain(1)).unwrap();
+    let mut tampered = proof.clone();
+    tampered.leaf.evm_address = EvmAddress::parse("0x000000000000000000000000000000000000dEaD").unwrap();
+    assert!(!merkle::verify(&tampered, &root.root));
+    assert!(!merkle::verify(&proof, &format!("0x{}", "0".repeat(64))));
+    assert!(!merkle::verify(&proof, "not hex"));
+}
+
+#[test]
+fn test_merkle_root_changes_with_mappings() {
+    let ctx = TestContext::new();
+    let alice = wallet(1);
+    let solana_pubkey = pubkey(&alice);
+    ctx.handle(provision_request(&alice, vec![1, 137])).unwrap();
+    let before = ctx.provisioner.handle_merkle_root(&admin()).unwrap();
+    let old_proof = ctx.provisioner.handle_merkle_proof(&solana_pubkey, &chain(137)).unwrap();
+
+    let updated = ctx.handle_update_mapping(update_request(&solana_pubkey, 137)).unwrap();
+    let after = ctx.provisioner.handle_merkle_root(&admin()).unwrap();
+    assert_ne!(after.root, before.root);
+    assert_eq!(after.leaf_count, 2);
+
+    // The old mapping is no longer in the tree; the new one is
+    assert!(!merkle::verify(&old_proof, &after.root));
+    let proof = ctx.provisioner.handle_merkle_proof(&solana_pubkey, &chain(137)).unwrap();
+    assert_eq!(proof.leaf.evm_address, updated.new_evm_address);
+    assert!(merkle::verify(&proof, &after.root));
+}
+
+#[test]
+fn test_merkle_proof_of_unstored_mapping_is_rejected() {
+    let ctx = TestContext::new();
+    let alice = wallet(1);
+    ctx.handle(provision_request(&alice, vec![1])).unwrap();
+
+    // Chain 10 only inherits the default address, so it has no leaf
+    let err = ctx.provisioner.handle_merkle_proof(&pubkey(&alice), &chain(10)).unwrap_err();
+    assert_eq!(err.code(), "INVALID_REQUEST");
+    let err = ctx.provisioner.handle_merkle_proof(&pubkey(&wallet(2)), &chain(1)).unwrap_err();
+    assert_eq!(err.code(), "INVALID_REQUEST");
+}
@@ -0,0 +1,401 @@
+mod common;
+
+use common::{approval_provisioner, fixed_clock_provisioner, named};
+use cubist_wallet_provisioner::config;
+use cubist_wallet_provisioner::kv::{self, default_key};
+use cubist_wallet_provisioner::network::{Network, Networked};
+use cubist_wallet_provisioner::quota::MappingQuota;
+use cubist_wallet_provisioner::testing::{
+    admin, admins, chain, evm, provision_request, pubkey, set_chain_request, signed, store_request, store_request_at, update_request, wallet,
+    MockKeyCreator, MockKvStore, TestContext,
+};
+use cubist_wallet_provisioner::wildcard;
+use cubist_wallet_provisioner::{ChainId, KvStore, ProvisionRequest, Provisioner, SetChainRequest, UpdateMappingRequest};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+// =============================================================================
+// CHAIN ID TESTS
+// =============================================================================
+
+#[test]
+fn test_chain_id_parse() {
+    assert_eq!(ChainId::parse("137").unwrap(), chain(137));
+    assert_eq!(ChainId::parse("eip155:137").unwrap(), chain(137));
+    assert_eq!(ChainId::parse("eip155:0137").unwrap(), chain(137));
+    assert_eq!(chain(137).as_str(), "eip155:137");
+    assert_eq!(chain(137).evm_chain_id(), Some(137));
+
+    let solana = ChainId::parse("solana:5eykt4UsFv8P8NJdTREpY1vzqKqZKvdp").unwrap();
+    assert_eq!(solana.namespace(), "solana");
+    assert_eq!(solana.reference(), "5eykt4UsFv8P8NJdTREpY1vzqKqZKvdp");
+    assert_eq!(solana.evm_chain_id(), None);
+
+    for invalid in ["", "eip155", "eip155:", "eip155:abc", "EIP155:1", "1abc:1", "ab:1", "cosmos:hub:4", "-1"] {
+        assert!(ChainId::parse(invalid).is_err(), "{} should be rejected", invalid);
+    }
+}
+
+#[test]
+fn test_chain_id_accepts_legacy_numeric_json() {
+    let alice = wallet(1);
+    let legacy = serde_json::json!({
+        "solana_pubkey": pubkey(&alice),
+        "chain_ids": [1, "137", "eip155:42161"],
+        "message": "m",
+        "signature": "s",
+    });
+    let req: ProvisionRequest = serde_json::from_value(legacy).unwrap();
+    assert_eq!(req.chain_ids, vec![chain(1), chain(137), chain(42161)]);
+
+    // Always serialized as CAIP-2
+    assert_eq!(serde_json::to_value(chain(137)).unwrap(), serde_json::json!("eip155:137"));
+
+    let bad = serde_json::json!({
+        "solana_pubkey": pubkey(&alice),
+        "chain_ids": ["not a chain"],
+        "message": "m",
+        "signature": "s",
+    });
+    assert!(serde_json::from_value::<ProvisionRequest>(bad).is_err());
+}
+
+#[test]
+fn test_provision_non_evm_chain() {
+    let ctx = TestContext::new();
+    let alice = wallet(1);
+    let solana_pubkey = pubkey(&alice);
+    let rollup = ChainId::parse("starknet:SN_MAIN").unwrap();
+    ctx.provisioner.handle_set_chain(&admin(), set_chain_request(&rollup, true, Some("Starknet"))).unwrap();
+
+    let result = ctx.handle(store_request(&alice, vec![chain(1), rollup.clone()])).unwrap();
+
+    assert_eq!(result.chain_mappings.get(&rollup), Some(&result.evm_address));
+    assert!(ctx.kv.get(&format!("{}:starknet:SN_MAIN", solana_pubkey)).unwrap().is_some());
+    assert_eq!(kv::get_chain_index(&ctx.kv, &solana_pubkey).unwrap(), vec![chain(1), rollup]);
+}
+
+#[test]
+fn test_existing_numeric_keys_and_index_still_read() {
+    let ctx = TestContext::new();
+    let solana_pubkey = pubkey(&wallet(1));
+    let evm_address = evm("0x1111111111111111111111111111111111111111");
+
+    // Data written before CAIP-2 ids: numeric key segment and numeric index
+    ctx.kv.set(&format!("{}:137", solana_pubkey), evm_address.as_str()).unwrap();
+    ctx.kv.set(&kv::chain_index_key(&solana_pubkey), "[1,137]").unwrap();
+
+    assert_eq!(ctx.get_existing_mapping(&solana_pubkey, 137).unwrap(), Some(evm_address));
+    assert_eq!(kv::get_chain_index(&ctx.kv, &solana_pubkey).unwrap(), vec![chain(1), chain(137)]);
+}
+
+#[test]
+fn test_requests_may_name_built_in_chains() {
+    assert_eq!(ChainId::resolve("polygon").unwrap(), chain(137));
+    assert_eq!(ChainId::resolve("Arbitrum One").unwrap(), chain(42161));
+    assert_eq!(ChainId::resolve("arbitrum").unwrap(), chain(42161));
+    assert_eq!(ChainId::resolve("base_sepolia").unwrap(), chain(84532));
+    assert_eq!(ChainId::resolve("eip155:10").unwrap(), chain(10));
+    assert_eq!(ChainId::resolve("polygn").unwrap_err().code(), "INVALID_CHAIN_ID");
+    // Names are for requests: ids read back from keys must be ids
+    assert!(ChainId::parse("polygon").is_err());
+
+    let alice = wallet(1);
+    let named = serde_json::json!({
+        "solana_pubkey": pubkey(&alice),
+        "chain_ids": ["ethereum", "polygon", 42161],
+        "message": "m",
+        "signature": "s",
+    });
+    let req: ProvisionRequest = serde_json::from_value(named).unwrap();
+    assert_eq!(req.chain_ids, vec![chain(1), chain(137), chain(42161)]);
+}
+
+#[test]
+fn test_responses_echo_chain_names() {
+    let ctx = TestContext::new();
+    let alice = wallet(1);
+    let solana_pubkey = pubkey(&alice);
+    ctx.provisioner.handle_set_chain(&admin(), set_chain_request(&chain(7777777), true, Some("Zora"))).unwrap();
+
+    let stored = ctx.handle(provision_request(&alice, vec![137, 7777777])).unwrap();
+    assert_eq!(
+        stored.chain_names,
+        HashMap::from([(chain(137), "Polygon".to_string()), (chain(7777777), "Zora".to_string())])
+    );
+
+    // Every requested chain in the registry is named, mapped or not; unknown ones are not
+    let found = ctx.provisioner.handle_get(&solana_pubkey, &[chain(137), chain(10), chain(1370)]).unwrap();
+    assert_eq!(
+        found.chain_names,
+        HashMap::from([(chain(137), "Polygon".to_string()), (chain(10), "OP Mainnet".to_string())])
+    );
+    let body = serde_json::to_value(&found).unwrap();
+    assert_eq!(body["chain_names"]["eip155:10"], "OP Mainnet");
+}
+
+// =============================================================================
+// CHAIN REGISTRY TESTS
+// =============================================================================
+
+#[test]
+fn test_provision_rejects_unknown_chain() {
+    let ctx = TestContext::new();
+    let alice = wallet(1);
+
+    // Typo of 137
+    let err = ctx.handle(provision_request(&alice, vec![1, 1370])).unwrap_err();
+    assert!(err.to_string().contains("Unknown chain id: eip155:1370"));
+
+    // Nothing was created or stored
+    assert_eq!(*ctx.default_key_counter.lock().unwrap(), 0);
+    assert!(ctx.get_default_evm_address(&pubkey(&alice)).unwrap().is_none());
+}
+
+#[test]
+fn test_disabled_chain_blocks_new_mappings() {
+    let ctx = TestContext::new();
+    let alice = wallet(1);
+
+    let info = ctx.provisioner.handle_set_chain(&admin(), set_chain_request(&chain(56), false, None)).unwrap();
+    assert_eq!((info.name.as_str(), info.enabled), ("BNB Smart Chain", false));
+
+    let err = ctx.handle(provision_request(&alice, vec![1, 56])).unwrap_err();
+    assert!(err.to_string().contains("Chain eip155:56 (BNB Smart Chain) is disabled"));
+
+    ctx.provisioner.handle_set_chain(&admin(), set_chain_request(&chain(56), true, None)).unwrap();
+    let result = ctx.handle(provision_request(&alice, vec![1, 56])).unwrap();
+    assert!(result.chain_mappings.contains_key(&chain(56)));
+}
+
+#[test]
+fn test_register_new_chain_requires_name() {
+    let ctx = TestContext::new();
+    let new_chain = chain(7777777);
+
+    let err = ctx.provisioner.handle_set_chain(&admin(), set_chain_request(&new_chain, true, None)).unwrap_err();
+    assert!(err.to_string().contains("a name is required"));
+
+    ctx.provisioner.handle_set_chain(&admin(), set_chain_request(&new_chain, true, Some("Zora"))).unwrap();
+    let chains = ctx.provisioner.handle_chains().unwrap();
+    assert_eq!(chains.last().map(|c| (&c.chain_id, c.name.as_str())), Some((&new_chain, "Zora")));
+    assert!(chains.iter().any(|c| c.chain_id == chain(11155111) && c.testnet));
+
+    ctx.handle(provision_request(&wallet(1), vec![7777777])).unwrap();
+}
+
+#[test]
+fn test_set_chain_requires_admin() {
+    let (provisioner, _) = approval_provisioner();
+
+    let req = set_chain_request(&chain(137), false, None);
+    let err = provisioner.handle_set_chain(&named("mallory@test"), req).unwrap_err();
+    assert!(err.to_string().contains("is not an admin"));
+
+    let chains = provisioner.handle_chains().unwrap();
+    assert!(chains.iter().find(|c| c.chain_id == chain(137)).unwrap().enabled);
+}
+
+// =============================================================================
+// DEFAULT CHAIN TESTS
+// =============================================================================
+
+#[test]
+fn test_store_without_chains_uses_default_chains() {
+    let ctx = TestContext::new();
+    let alice = wallet(1);
+    let solana_pubkey = pubkey(&alice);
+
+    // Without configured defaults, chains are required
+    let err = ctx.provisioner.handle(provision_request(&alice, vec![])).unwrap_err();
+    assert_eq!(err.code(), "INVALID_REQUEST");
+
+    let defaults = config::Config::default().default_chain_ids;
+    let provisioner = Provisioner::new(ctx.kv.clone(), MockKeyCreator {
+        default_key_counter: Arc::clone(&ctx.default_key_counter),
+        chain_key_counter: Arc::new(Mutex::new(1000)),
+    })
+    .with_default_chains(defaults.clone());
+
+    let preview = provisioner.handle_dry_run(provision_request(&alice, vec![])).unwrap();
+    assert_eq!(preview.result.chain_mappings.len(), defaults.len());
+
+    let response = provisioner.handle(provision_request(&alice, vec![])).unwrap();
+    for chain_id in &defaults {
+        assert_eq!(response.chain_mappings[chain_id], response.evm_address);
+        assert_eq!(ctx.get_existing_mapping(&solana_pubkey, chain_id.evm_chain_id().unwrap()).unwrap(), Some(response.evm_address.clone()));
+    }
+
+    // Chains named in the request replace the defaults
+    let bob = provisioner.handle(provision_request(&wallet(2), vec![10])).unwrap();
+    assert_eq!(bob.chain_mappings.keys().collect::<Vec<_>>(), vec![&chain(10)]);
+}
+
+#[test]
+fn test_chain_ids_may_be_omitted() {
+    let alice = wallet(1);
+    let json = format!(r#"{{"solana_pubkey": "{}", "message": "m", "signature": "s"}}"#, pubkey(&alice));
+    let req: ProvisionRequest = serde_json::from_str(&json).unwrap();
+    assert!(req.chain_ids.is_empty());
+}
+
+// =============================================================================
+// WILDCARD TESTS
+// =============================================================================
+
+#[test]
+fn test_wildcard_covers_every_evm_chain_without_writing_them() {
+    let ctx = TestContext::new();
+    let alice = wallet(1);
+    let solana_pubkey = pubkey(&alice);
+    let starknet = ChainId::parse("starknet:SN_MAIN").unwrap();
+    let requested = [chain(137), chain(7777777), starknet.clone()];
+
+    let stored = ctx.handle(provision_request(&alice, vec![1])).unwrap();
+    assert!(!stored.wildcard);
+    // Without the wildcard, a chain outside the registry has no mapping
+    let before = ctx.provisioner.handle_get(&solana_pubkey, &requested).unwrap();
+    assert_eq!(before.chain_mappings.keys().collect::<Vec<_>>(), vec![&chain(137)]);
+    assert!(!before.wildcard);
+
+    let response = ctx.handle(store_request(&alice, vec![ChainId::wildcard()])).unwrap();
+    assert!(response.wildcard);
+    assert!(response.chain_mappings.is_empty());
+    assert_eq!(response.evm_address, stored.evm_address);
+
+    // Every EVM chain, registered or not, inherits the default; others do not
+    let after = ctx.provisioner.handle_get(&solana_pubkey, &requested).unwrap();
+    assert!(after.wildcard);
+    assert_eq!(after.chain_mappings.len(), 2);
+    assert_eq!(after.chain_mappings.get(&chain(7777777)), Some(&stored.evm_address));
+    assert_eq!(after.chain_inherited.get(&chain(7777777)), Some(&true));
+    assert!(!after.chain_mappings.contains_key(&starknet));
+
+    // One key, no chain mappings or index entries
+    assert!(ctx.kv.get(&wildcard::wildcard_key(&solana_pubkey)).unwrap().is_some());
+    assert!(kv::get_chain_mapping(&ctx.kv, &solana_pubkey, &chain(7777777)).unwrap().is_none());
+    assert_eq!(kv::get_chain_index(&ctx.kv, &solana_pubkey).unwrap(), vec![chain(1)]);
+}
+
+#[test]
+fn test_chain_mapping_wins_over_wildcard() {
+    let kv = MockKvStore::new();
+    let keys = MockKeyCreator {
+        default_key_counter: Arc::new(Mutex::new(0)),
+        chain_key_counter: Arc::new(Mutex::new(1000)),
+    };
+    let provisioner = Provisioner::new(kv.clone(), keys).with_admins(admins()).with_single_step_updates().with_materialized_inheritance();
+    let alice = wallet(1);
+    let solana_pubkey = pubkey(&alice);
+    let default = provisioner.handle(store_request(&alice, vec![chain(1), ChainId::wildcard()])).unwrap().evm_address;
+
+    let updated = provisioner.handle_update_mapping(&admin(), update_request(&solana_pubkey, 137)).unwrap().new_evm_address;
+    let found = provisioner.handle_get(&solana_pubkey, &[chain(1), chain(137), chain(42161)]).unwrap();
+    assert_eq!(found.chain_mappings.get(&chain(137)), Some(&updated));
+    assert_eq!(found.chain_inherited.get(&chain(137)), Some(&false));
+    assert_eq!(found.chain_mappings.get(&chain(42161)), Some(&default));
+
+    // Chains the wildcard covers are not materialized
+    assert_eq!(found.chain_inherited.get(&chain(42161)), Some(&true));
+    assert!(kv::get_chain_mapping(&kv, &solana_pubkey, &chain(42161)).unwrap().is_none());
+}
+
+#[test]
+fn test_wildcard_only_in_store_requests_for_the_primary_address() {
+    let ctx = TestContext::new();
+    let alice = wallet(1);
+    let store = serde_json::json!({
+        "solana_pubkey": pubkey(&alice),
+        "chain_ids": ["*", "polygon"],
+        "message": "m",
+        "signature": "s",
+    });
+    let req: ProvisionRequest = serde_json::from_value(store).unwrap();
+    assert_eq!(req.chain_ids, vec![ChainId::wildcard(), chain(137)]);
+    // Serialized as sent, so a queued job reads it back
+    assert_eq!(serde_json::to_value(&req).unwrap()["chain_ids"], serde_json::json!(["*", "eip155:137"]));
+
+    // Not a chain id anywhere else
+    assert!(ChainId::resolve("*").is_err());
+    let update = serde_json::json!({ "solana_pubkey": pubkey(&alice), "chain_id": "*" });
+    assert!(serde_json::from_value::<UpdateMappingRequest>(update).is_err());
+
+    let labeled = signed(&alice, ProvisionRequest { label: Some("trading".to_string()), ..store_request(&alice, vec![chain(1), ChainId::wildcard()]) });
+    assert!(ctx.handle(labeled).unwrap_err().to_string().contains("primary address only"));
+    let temporary = signed(&alice, ProvisionRequest { ttl_secs: Some(3600), ..store_request(&alice, vec![chain(1), ChainId::wildcard()]) });
+    assert!(ctx.handle(temporary).unwrap_err().to_string().contains("does not apply to the wildcard"));
+    assert!(ctx.kv.get(&wildcard::wildcard_key(&pubkey(&alice))).unwrap().is_none());
+
+    // Not counted against the chain quota
+    let provisioner = fixed_clock_provisioner().with_mapping_quota(MappingQuota { max_chains: 2, max_labels: 1 });
+    let full = store_request_at(&alice, vec![chain(1), chain(137), ChainId::wildcard()], 1000);
+    assert!(provisioner.handle(full).unwrap().wildcard);
+}
+
+// =============================================================================
+// NETWORK TESTS
+// =============================================================================
+
+type NetworkProvisioner = Provisioner<Networked<MockKvStore>, MockKeyCreator>;
+
+/// Mainnet and testnet provisioners over `kv`, creating keys in one org
+fn network_provisioners(kv: &MockKvStore) -> (NetworkProvisioner, NetworkProvisioner) {
+    let (default_key_counter, chain_key_counter) = (Arc::new(Mutex::new(0)), Arc::new(Mutex::new(1000)));
+    let on = |network| {
+        let keys = MockKeyCreator { default_key_counter: default_key_counter.clone(), chain_key_counter: chain_key_counter.clone() };
+        Provisioner::new(Networked::new(kv.clone(), network), keys).with_admins(admins()).with_single_step_updates().with_network(network)
+    };
+    (on(Network::Mainnet), on(Network::Testnet))
+}
+
+#[test]
+fn test_networks_have_independent_wallets() {
+    let kv = MockKvStore::new();
+    let (mainnet, testnet) = network_provisioners(&kv);
+    let alice = wallet(1);
+    let solana_pubkey = pubkey(&alice);
+
+    let production = mainnet.handle(provision_request(&alice, vec![1, 137])).unwrap();
+    assert!(testnet.handle_get(&solana_pubkey, &[chain(11155111)]).unwrap().default_address.is_none());
+
+    let qa = testnet.handle(provision_request(&alice, vec![11155111, 84532])).unwrap();
+    assert_ne!(qa.evm_address, production.evm_address);
+    assert!(kv.get(&format!("testnet:{}", default_key(&solana_pubkey))).unwrap().unwrap().contains(qa.evm_address.as_str()));
+    assert_eq!(mainnet.handle_get(&solana_pubkey, &[]).unwrap().default_address, Some(production.evm_address));
+    assert_eq!(testnet.handle_get(&solana_pubkey, &[]).unwrap().default_address, Some(qa.evm_address));
+}
+
+#[test]
+fn test_networks_refuse_the_other_networks_chains() {
+    let kv = MockKvStore::new();
+    let (mainnet, testnet) = network_provisioners(&kv);
+
+    let err = mainnet.handle(provision_request(&wallet(1), vec![1, 11155111])).unwrap_err();
+    assert_eq!(err.code(), "WRONG_NETWORK");
+    assert_eq!(err.to_string(), "Chain eip155:11155111 is not a mainnet chain");
+    assert_eq!(testnet.handle(provision_request(&wallet(1), vec![137])).unwrap_err().code(), "WRONG_NETWORK");
+    assert!(mainnet.handle_get(&pubkey(&wallet(1)), &[]).unwrap().default_address.is_none());
+    assert!(testnet.handle_get(&pubkey(&wallet(1)), &[]).unwrap().default_address.is_none());
+
+    // A chain registered as a testnet by an admin follows its flag
+    let anvil = chain(31337);
+    testnet.handle_set_chain(&admin(), SetChainRequest { testnet: Some(true), ..set_chain_request(&anvil, true, Some("Anvil")) }).unwrap();
+    testnet.handle(provision_request(&wallet(1), vec![31337])).unwrap();
+}
+
+#[test]
+fn test_mainnet_does_not_see_testnet_keys() {
+    let kv = MockKvStore::new();
+    let mainnet = Networked::new(kv.clone(), Network::Mainnet);
+    let testnet = Networked::new(kv.clone(), Network::Testnet);
+    for key in ["a", "m", "z"] {
+        mainnet.set(key, "mainnet").unwrap();
+        testnet.set(key, "testnet").unwrap();
+    }
+
+    assert_eq!(mainnet.list_keys(None, 2).unwrap(), vec!["a", "m"]);
+    assert_eq!(mainnet.list_keys(Some("m"), 10).unwrap(), vec!["z"]);
+    assert_eq!(testnet.list_keys(None, 10).unwrap(), vec!["a", "m", "z"]);
+    assert_eq!(testnet.get("m").unwrap().as_deref(), Some("testnet"));
+    assert_eq!(mainnet.get("testnet:m").unwrap_err().code(), "INVALID_REQUEST");
+}
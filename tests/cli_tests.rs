@@ -0,0 +1,183 @@
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use cubist_wallet_provisioner::cli::{self, Backend, Command};
+use cubist_wallet_provisioner::cubesigner_client::{HttpMethod, HttpRequest, HttpResponse, HttpTransport};
+use cubist_wallet_provisioner::error::Result;
+use cubist_wallet_provisioner::testing::store_message;
+use cubist_wallet_provisioner::{ChainId, CreatedKey, KeyCreator, KeyLister, KvStore, ListedKey, SolanaPubkey};
+use ed25519_dalek::{Signer, SigningKey};
+use serde_json::{json, Value};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+/// Key creator handing out sequential addresses, and listing them (clones
+/// share their keys)
+#[derive(Clone, Default)]
+struct SequentialKeys(Arc<Mutex<Vec<ListedKey>>>);
+
+impl SequentialKeys {
+    fn next(&self, solana_pubkey: &str) -> Result<CreatedKey> {
+        let mut keys = self.0.lock().unwrap();
+        let address = format!("0x{:040x}", keys.len() + 1);
+        let key_id = format!("Key#{}", address);
+        keys.push(ListedKey { key_id: key_id.clone(), address: address.clone(), name: format!("EVM_{}", solana_pubkey) });
+        Ok(CreatedKey { key_id, address, policies: Vec::new() })
+    }
+}
+
+impl KeyCreator for SequentialKeys {
+    fn create_evm_key(&self, solana_pubkey: &str) -> Result<CreatedKey> {
+        self.next(solana_pubkey)
+    }
+
+    fn create_evm_key_for_chain(&self, solana_pubkey: &str, _chain_id: &ChainId) -> Result<CreatedKey> {
+        self.next(solana_pubkey)
+    }
+
+    fn create_labeled_evm_key(&self, solana_pubkey: &str, _label: &str, _chain_id: Option<&ChainId>) -> Result<CreatedKey> {
+        self.next(solana_pubkey)
+    }
+}
+
+impl KeyLister for SequentialKeys {
+    fn list_evm_keys(&self) -> Result<Vec<ListedKey>> {
+        Ok(self.0.lock().unwrap().clone())
+    }
+}
+
+/// Transport recording requests and answering each with `status` and `body`
+struct FixedTransport {
+    status: u16,
+    body: String,
+    sent: Mutex<Vec<HttpRequest>>,
+}
+
+impl FixedTransport {
+    fn new(status: u16, body: Value) -> Self {
+        Self { status, body: body.to_string(), sent: Mutex::new(Vec::new()) }
+    }
+}
+
+impl HttpTransport for FixedTransport {
+    fn send(&self, request: HttpRequest) -> std::result::Result<HttpResponse, String> {
+        self.sent.lock().unwrap().push(request);
+        Ok(HttpResponse { status: self.status, body: self.body.clone() })
+    }
+}
+
+fn args(line: &str) -> Vec<String> {
+    line.split_whitespace().map(str::to_string).collect()
+}
+
+fn wallet() -> (SigningKey, SolanaPubkey) {
+    let wallet = SigningKey::from_bytes(&[1; 32]);
+    let solana_pubkey = SolanaPubkey::parse(&bs58::encode(wallet.verifying_key().to_bytes()).into_string()).unwrap();
+    (wallet, solana_pubkey)
+}
+
+/// `provision` arguments for `solana_pubkey`, signed by `signer`
+fn provision_args(backend: &str, signer: &SigningKey, solana_pubkey: &SolanaPubkey) -> Vec<String> {
+    let message = store_message(solana_pubkey, &[ChainId::eip155(1), ChainId::eip155(137)]);
+    let mut args = args(&format!("{} provision --solana-pubkey {} --chain eip155:1 --chain 137", backend, solana_pubkey));
+    args.extend(["--message".to_string(), message.clone(), "--signature".to_string()]);
+    args.push(BASE64.encode(signer.sign(message.as_bytes()).to_bytes()));
+    args
+}
+
+fn kv_file(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("cli-tests-{}-{}.json", std::process::id(), name));
+    let _ = std::fs::remove_file(&path);
+    path
+}
+
+#[test]
+fn test_flags_become_requests() {
+    let (backend, command) = cli::parse(&args("--server http://localhost:8080/ update --solana-pubkey 7xKXtg2CW87d97TXJSDpbD5jBkheTqA83TZRuJosgAsU --chain 137 --expected-version 3")).unwrap();
+    assert_eq!(backend, Backend::Server("http://localhost:8080".to_string()));
+    let Command::Update(req) = command else { panic!("not an update") };
+    assert_eq!(req.chain_id, ChainId::eip155(137));
+    assert_eq!(req.expected_version, Some(3));
+
+    let (backend, command) = cli::parse(&args("--kv-file kv.json reconcile --repair --limit 50")).unwrap();
+    assert_eq!(backend, Backend::KvFile(PathBuf::from("kv.json")));
+    let Command::Reconcile(req) = command else { panic!("not a reconcile") };
+    assert!(req.repair);
+    assert_eq!(req.limit, Some(50));
+
+    for (line, reason) in [
+        ("get --solana-pubkey x", "expected --server URL or --kv-file PATH first"),
+        ("--kv-file kv.json delete", "unknown command delete"),
+        ("--kv-file kv.json get --repair", "unknown flag --repair for get"),
+        ("--kv-file kv.json export --limit", "--limit needs a value"),
+        ("--kv-file kv.json export --limit ten", "--limit needs a number"),
+        ("--kv-file kv.json get", "missing field `solana_pubkey`"),
+        ("--kv-file kv.json get --solana-pubkey nope", "Invalid Solana"),
+    ] {
+        let err = cli::parse(&args(line)).err().expect(line);
+        assert_eq!(err.code(), "INVALID_REQUEST");
+        assert!(err.to_string().contains(reason), "{}: {}", line, err);
+    }
+}
+
+#[test]
+fn test_commands_run_over_a_kv_file() {
+    let path = kv_file("flow");
+    let (signer, solana_pubkey) = wallet();
+    let backend = format!("--kv-file {}", path.display());
+    let keys = SequentialKeys::default();
+
+    let run = |args: &[String], keys: &SequentialKeys| {
+        let (Backend::KvFile(path), command) = cli::parse(args).unwrap() else { panic!("not a KV file") };
+        let kv = cli::load_kv(&path).unwrap();
+        let writes = command.writes();
+        let outcome = cli::run_on_kv(kv.clone(), keys.clone(), cli::DEFAULT_OPERATOR, command);
+        if writes {
+            cli::save_kv(&path, &kv).unwrap();
+        }
+        outcome.unwrap()
+    };
+
+    let provisioned = run(&provision_args(&backend, &signer, &solana_pubkey), &keys);
+    let evm_address = provisioned["evm_address"].as_str().unwrap().to_string();
+
+    // Each run starts from the saved file
+    let mappings = run(&args(&format!("{} get --solana-pubkey {}", backend, solana_pubkey)), &keys);
+    assert_eq!(mappings["chain_mappings"]["eip155:137"], json!(evm_address));
+    let updated = run(&args(&format!("{} update --solana-pubkey {} --chain 137", backend, solana_pubkey)), &keys);
+    assert_ne!(updated["new_evm_address"], json!(evm_address));
+
+    let page = run(&args(&format!("{} export", backend)), &keys);
+    assert!(!page["entries"].as_array().unwrap().is_empty());
+    assert_eq!(run(&args(&format!("{} verify", backend)), &keys)["violations"], json!([]));
+    assert_eq!(run(&args(&format!("{} reconcile", backend)), &keys)["orphan_keys"], json!([]));
+
+    // Saved as one JSON object of keys and values
+    let saved: Value = serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+    assert_eq!(saved.as_object().unwrap().len(), cli::load_kv(&path).unwrap().list_keys(None, 1000).unwrap().len());
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_server_commands_go_over_http() {
+    let (signer, solana_pubkey) = wallet();
+    let transport = FixedTransport::new(200, json!({ "evm_address": "0x0000000000000000000000000000000000000001" }));
+    let (_, command) = cli::parse(&provision_args("--server http://localhost:8080", &signer, &solana_pubkey)).unwrap();
+    let response = cli::run_on_server(&transport, "http://localhost:8080", &command).unwrap();
+    assert_eq!(response["evm_address"], json!("0x0000000000000000000000000000000000000001"));
+
+    let (_, command) = cli::parse(&args(&format!("--server http://localhost:8080 get --solana-pubkey {} --chain 1 --chain 137", solana_pubkey))).unwrap();
+    cli::run_on_server(&transport, "http://localhost:8080", &command).unwrap();
+    let sent = transport.sent.lock().unwrap();
+    assert!(matches!(sent[0].method, HttpMethod::Post));
+    assert_eq!(sent[0].url, "http://localhost:8080/provision");
+    assert_eq!(serde_json::from_str::<Value>(sent[0].body.as_deref().unwrap()).unwrap()["chain_ids"], json!(["eip155:1", "eip155:137"]));
+    assert_eq!(sent[1].url, format!("http://localhost:8080/mappings/{}?chain_ids=eip155:1,eip155:137", solana_pubkey));
+
+    // Server errors come back as they were sent
+    let error = json!({ "code": "NOT_PROVISIONED", "message": "Not provisioned", "retryable": false });
+    let failing = FixedTransport::new(404, error.clone());
+    assert_eq!(cli::run_on_server(&failing, "http://localhost:8080", &command).unwrap_err(), error);
+
+    // Operations the server does not serve
+    let (_, command) = cli::parse(&args("--server http://localhost:8080 export")).unwrap();
+    assert_eq!(cli::run_on_server(&transport, "http://localhost:8080", &command).unwrap_err()["code"], json!("INVALID_REQUEST"));
+}
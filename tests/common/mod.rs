@@ -0,0 +1,198 @@
+//! Helpers shared by the integration test suites
+
+// Each suite is its own crate and uses only some of them
+#![allow(dead_code)]
+
+use cubist_wallet_provisioner::admin::Requester;
+use cubist_wallet_provisioner::auth;
+use cubist_wallet_provisioner::error::{ProvisionError, Result};
+use cubist_wallet_provisioner::export::ExportEntry;
+use cubist_wallet_provisioner::import::{ImportRequest, ImportStrategy};
+use cubist_wallet_provisioner::mapping;
+use cubist_wallet_provisioner::rate_limit::RateLimit;
+use cubist_wallet_provisioner::testing::{
+    admins, chain, evm, mock_key, provision_request, provision_request_at, pubkey, signed, signed_at, MockKeyCreator, MockKvStore,
+};
+use cubist_wallet_provisioner::{
+    ChainId, EvmAddress, KvStore, LinkExternalRequest, ListedKey, MappingRecord, ProposeUpdateRequest, ProvisionRequest, ProvisionResponse,
+    Provisioner, ResolveUpdateRequest, SolanaPubkey,
+};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use ed25519_dalek::{Signer, SigningKey};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// Provisioner with a fixed clock at t=1000
+pub fn fixed_clock_provisioner() -> Provisioner<MockKvStore, MockKeyCreator> {
+    let keys = MockKeyCreator {
+        default_key_counter: Arc::new(Mutex::new(0)),
+        chain_key_counter: Arc::new(Mutex::new(1000)),
+    };
+    Provisioner::new(MockKvStore::new(), keys).with_admins(admins()).with_single_step_updates().with_clock(|| 1000)
+}
+
+pub fn owner() -> Requester {
+    Requester { identity: "owner@test".to_string(), is_org_owner: true, is_service_account: false }
+}
+
+/// Requester authenticated as `identity`, an admin if the allowlist says so
+pub fn named(identity: &str) -> Requester {
+    Requester::new(Some(identity), None)
+}
+
+pub fn propose_request(solana_pubkey: &SolanaPubkey, chain_id: u64) -> ProposeUpdateRequest {
+    ProposeUpdateRequest { solana_pubkey: solana_pubkey.clone(), chain_id: chain(chain_id), request_id: None }
+}
+
+pub fn resolve_request(solana_pubkey: &SolanaPubkey, chain_id: u64, proposal_id: u64) -> ResolveUpdateRequest {
+    ResolveUpdateRequest { solana_pubkey: solana_pubkey.clone(), chain_id: chain(chain_id), proposal_id, request_id: None }
+}
+
+/// Provisioner with two admins (`alice@test`, `bob@test`) and a settable clock
+pub fn approval_provisioner() -> (Provisioner<MockKvStore, MockKeyCreator>, Arc<AtomicU64>) {
+    let keys = MockKeyCreator {
+        default_key_counter: Arc::new(Mutex::new(0)),
+        chain_key_counter: Arc::new(Mutex::new(1000)),
+    };
+    let now = Arc::new(AtomicU64::new(1000));
+    let clock = Arc::clone(&now);
+    let provisioner = Provisioner::new(MockKvStore::new(), keys)
+        .with_admins(MockKvStore::new())
+        .with_clock(move || clock.load(Ordering::SeqCst));
+
+    provisioner.handle_set_admin(&owner(), "alice@test", true).unwrap();
+    provisioner.handle_set_admin(&owner(), "bob@test", true).unwrap();
+    (provisioner, now)
+}
+
+/// Deterministic test EVM wallet (secp256k1 key from a fixed seed)
+pub fn evm_wallet(seed: u8) -> k256::ecdsa::SigningKey {
+    k256::ecdsa::SigningKey::from_bytes(&[seed; 32].into()).unwrap()
+}
+
+pub fn evm_wallet_address(wallet: &k256::ecdsa::SigningKey) -> EvmAddress {
+    evm(&auth::evm_address_of(wallet.verifying_key()))
+}
+
+/// `personal_sign` signature (`0x` + r || s || v) over `message`
+pub fn personal_sign(wallet: &k256::ecdsa::SigningKey, message: &str) -> String {
+    use sha3::{Digest, Keccak256};
+    let prefixed = format!("\x19Ethereum Signed Message:\n{}{}", message.len(), message);
+    let digest = Keccak256::digest(prefixed.as_bytes());
+    let (signature, recovery_id) = wallet.sign_prehash_recoverable(&digest).unwrap();
+
+    let mut bytes = signature.to_bytes().to_vec();
+    bytes.push(27 + recovery_id.to_byte());
+    format!("0x{}", bytes.iter().map(|b| format!("{:02x}", b)).collect::<String>())
+}
+
+pub fn import_request(entries: Vec<ExportEntry>, strategy: ImportStrategy, dry_run: bool) -> ImportRequest {
+    ImportRequest { entries, strategy, dry_run, ..Default::default() }
+}
+
+pub fn entry(key: &str, value: &str) -> ExportEntry {
+    ExportEntry { key: key.to_string(), value: value.to_string() }
+}
+
+pub fn listed_key(counter: u32, name: String) -> ListedKey {
+    let key = mock_key(counter);
+    ListedKey { key_id: key.key_id, address: key.address, name }
+}
+
+pub fn labeled_request(wallet: &SigningKey, chain_ids: Vec<u64>, label: &str) -> ProvisionRequest {
+    signed(wallet, ProvisionRequest { label: Some(label.to_string()), ..provision_request(wallet, chain_ids) })
+}
+
+pub fn labeled_request_at(wallet: &SigningKey, chain_ids: Vec<u64>, label: &str, now: u64) -> ProvisionRequest {
+    signed_at(wallet, ProvisionRequest { label: Some(label.to_string()), ..provision_request_at(wallet, chain_ids, now) }, now)
+}
+
+/// Link request for `evm_wallet`'s address, signed by both wallets
+pub fn link_external_request(wallet: &SigningKey, evm_wallet: &k256::ecdsa::SigningKey, chain_ids: Vec<u64>, nonce: &str) -> LinkExternalRequest {
+    let solana_pubkey = pubkey(wallet);
+    let evm_address = evm_wallet_address(evm_wallet);
+    let chain_ids: Vec<ChainId> = chain_ids.into_iter().map(chain).collect();
+    let message = auth::link_external_message(&solana_pubkey, &evm_address, &chain_ids, nonce, 1300);
+
+    LinkExternalRequest {
+        solana_pubkey,
+        evm_address,
+        chain_ids,
+        nonce: nonce.to_string(),
+        expires_at: 1300,
+        signature: BASE64.encode(wallet.sign(message.as_bytes()).to_bytes()),
+        evm_signature: personal_sign(evm_wallet, &message),
+        ttl_secs: None,
+        request_id: None,
+    }
+}
+
+/// KV store whose writes start failing once `writes_left` is used up
+pub struct FlakyKvStore {
+    pub inner: MockKvStore,
+    pub writes_left: Mutex<usize>,
+}
+
+impl FlakyKvStore {
+    fn take_write(&self) -> Result<()> {
+        let mut writes_left = self.writes_left.lock().unwrap();
+        if *writes_left == 0 {
+            return Err(ProvisionError::Kv("KV write error: injected".to_string()));
+        }
+        *writes_left -= 1;
+        Ok(())
+    }
+}
+
+impl KvStore for FlakyKvStore {
+    fn get(&self, key: &str) -> Result<Option<String>> {
+        self.inner.get(key)
+    }
+
+    fn set_if_absent(&self, key: &str, value: &str) -> Result<bool> {
+        self.take_write()?;
+        self.inner.set_if_absent(key, value)
+    }
+
+    fn set(&self, key: &str, value: &str) -> Result<()> {
+        self.take_write()?;
+        self.inner.set(key, value)
+    }
+
+    fn list_keys(&self, after: Option<&str>, limit: usize) -> Result<Vec<String>> {
+        self.inner.list_keys(after, limit)
+    }
+}
+
+pub fn store_with_backend_key(kv: &impl KvStore, req: &ProvisionRequest) -> Result<ProvisionResponse> {
+    let address = evm("0x3333333333333333333333333333333333333333");
+    mapping::store(kv, req, 10, || Ok(MappingRecord::new(&address, Some("Key#3"), req.solana_pubkey.as_str(), 10)))
+}
+
+pub const AAVE_POOL: &str = "0x87870Bca3F3fD6335C3F4ce8392D69350B4fA4E2";
+
+pub fn idempotent_provisioner() -> (Provisioner<MockKvStore, MockKeyCreator>, MockKvStore) {
+    let keys = MockKeyCreator {
+        default_key_counter: Arc::new(Mutex::new(0)),
+        chain_key_counter: Arc::new(Mutex::new(1000)),
+    };
+    let bucket = MockKvStore::new();
+    let provisioner = Provisioner::new(MockKvStore::new(), keys).with_admins(admins()).with_single_step_updates().with_idempotency(bucket.clone());
+    (provisioner, bucket)
+}
+
+/// 3 requests per minute, starting at the beginning of a window (t = 6000)
+pub fn rate_limited_provisioner() -> (Provisioner<MockKvStore, MockKeyCreator>, MockKvStore, Arc<AtomicU64>) {
+    let keys = MockKeyCreator {
+        default_key_counter: Arc::new(Mutex::new(0)),
+        chain_key_counter: Arc::new(Mutex::new(1000)),
+    };
+    let bucket = MockKvStore::new();
+    let now = Arc::new(AtomicU64::new(6000));
+    let clock = Arc::clone(&now);
+    let provisioner = Provisioner::new(MockKvStore::new(), keys).with_admins(admins()).with_single_step_updates()
+        .with_idempotency(MockKvStore::new())
+        .with_rate_limit(bucket.clone(), RateLimit { max_requests: 3, window_secs: 60 })
+        .with_clock(move || clock.load(Ordering::SeqCst));
+    (provisioner, bucket, now)
+}
@@ -0,0 +1,65 @@
+use cubist_wallet_provisioner::auth;
+use cubist_wallet_provisioner::config::{self, ConfigUpdate};
+use cubist_wallet_provisioner::rate_limit::{RateLimit, MAX_RATE_LIMIT_REQUESTS, MAX_RATE_LIMIT_WINDOW_SECS};
+use cubist_wallet_provisioner::testing::{chain, MockKvStore};
+use cubist_wallet_provisioner::KvStore;
+
+// =============================================================================
+// CONFIG TESTS
+// =============================================================================
+
+#[test]
+fn test_config_defaults_until_set() {
+    let kv = MockKvStore::new();
+    let defaults = config::get_config(&kv).unwrap();
+    assert_eq!(defaults.default_chain_ids, vec![chain(1), chain(137), chain(42161)]);
+    assert_eq!(defaults.rate_limit, RateLimit::default());
+    assert_eq!(defaults.max_authorization_ttl_secs, auth::MAX_AUTHORIZATION_TTL_SECS);
+    assert!(!defaults.materialize_inherited);
+    assert!(defaults.denied_addresses.is_empty());
+    assert!(!defaults.allow_program_pubkeys);
+    assert_eq!(kv.get(config::CONFIG_KEY).unwrap(), None);
+
+    // Fields left out keep their values
+    let update = ConfigUpdate { rate_limit: Some(RateLimit { max_requests: 3, window_secs: 10 }), ..Default::default() };
+    let set = config::set_config(&kv, update).unwrap();
+    assert_eq!(set.rate_limit.max_requests, 3);
+    assert_eq!(set.default_chain_ids, defaults.default_chain_ids);
+    let update = ConfigUpdate { materialize_inherited: Some(true), ..Default::default() };
+    config::set_config(&kv, update).unwrap();
+    let stored = config::get_config(&kv).unwrap();
+    assert_eq!((stored.rate_limit.max_requests, stored.materialize_inherited), (3, true));
+}
+
+#[test]
+fn test_config_rejects_invalid_values() {
+    let kv = MockKvStore::new();
+    let invalid = [
+        ConfigUpdate { default_chain_ids: Some(vec![]), ..Default::default() },
+        ConfigUpdate { default_chain_ids: Some(vec![chain(1), chain(1)]), ..Default::default() },
+        ConfigUpdate { rate_limit: Some(RateLimit { max_requests: 0, window_secs: 60 }), ..Default::default() },
+        ConfigUpdate { rate_limit: Some(RateLimit { max_requests: MAX_RATE_LIMIT_REQUESTS + 1, window_secs: 60 }), ..Default::default() },
+        ConfigUpdate { rate_limit: Some(RateLimit { max_requests: 10, window_secs: MAX_RATE_LIMIT_WINDOW_SECS + 1 }), ..Default::default() },
+        ConfigUpdate { rate_limit: Some(RateLimit { max_requests: u64::MAX, window_secs: u64::MAX }), ..Default::default() },
+        ConfigUpdate { max_authorization_ttl_secs: Some(auth::MAX_AUTHORIZATION_TTL_SECS + 1), ..Default::default() },
+        ConfigUpdate { max_authorization_ttl_secs: Some(0), ..Default::default() },
+    ];
+    for update in invalid {
+        assert_eq!(config::set_config(&kv, update).unwrap_err().code(), "INVALID_REQUEST");
+    }
+    assert_eq!(kv.get(config::CONFIG_KEY).unwrap(), None);
+
+    // Misspelled settings are refused rather than ignored
+    assert!(serde_json::from_str::<ConfigUpdate>(r#"{"rate_limits": {"max_requests": 1, "window_secs": 1}}"#).is_err());
+}
+
+#[test]
+fn test_config_tightens_authorization_ttl() {
+    let kv = MockKvStore::new();
+    let config = config::get_config(&kv).unwrap();
+    config.check_authorization_ttl(1000 + auth::MAX_AUTHORIZATION_TTL_SECS, 1000).unwrap();
+
+    let config = config::set_config(&kv, ConfigUpdate { max_authorization_ttl_secs: Some(60), ..Default::default() }).unwrap();
+    config.check_authorization_ttl(1060, 1000).unwrap();
+    assert_eq!(config.check_authorization_ttl(1061, 1000).unwrap_err().code(), "INVALID_REQUEST");
+}
@@ -0,0 +1,401 @@
+use cubist_wallet_provisioner::cubesigner_client::{
+    CubeSignerClient, CubeSignerError, HttpMethod, KeyPolicies, HttpRequest, HttpResponse, HttpTransport, RetryPolicy,
+    DEFAULT_TIMEOUT,
+};
+use cubist_wallet_provisioner::auth;
+use cubist_wallet_provisioner::{KeyClass, KeyCreator, KeyLister, KeyType, ProvisionError, SolanaKeyCreator};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Transport that records requests and replays canned responses in order
+struct ScriptedTransport {
+    responses: Mutex<Vec<Result<HttpResponse, String>>>,
+    requests: Mutex<Vec<HttpRequest>>,
+}
+
+impl ScriptedTransport {
+    fn new(responses: Vec<Result<HttpResponse, String>>) -> Self {
+        Self {
+            responses: Mutex::new(responses.into_iter().rev().collect()),
+            requests: Mutex::new(Vec::new()),
+        }
+    }
+}
+
+impl HttpTransport for &ScriptedTransport {
+    fn send(&self, request: HttpRequest) -> Result<HttpResponse, String> {
+        self.requests.lock().unwrap().push(request);
+        self.responses.lock().unwrap().pop().expect("unexpected request")
+    }
+}
+
+fn ok(body: &str) -> Result<HttpResponse, String> {
+    Ok(HttpResponse { status: 200, body: body.to_string() })
+}
+
+fn status(status: u16, body: &str) -> Result<HttpResponse, String> {
+    Ok(HttpResponse { status, body: body.to_string() })
+}
+
+const KEY_JSON: &str = r#"{
+    "key_id": "Key#0xcb373e47d769b06dee02f05c86dd8790e0358aee",
+    "key_type": "SecpEthAddr",
+    "material_id": "0xcb373e47d769b06dee02f05c86dd8790e0358aee",
+    "metadata": { "name": "EVM_TestUser123" },
+    "purpose": "Evm"
+}"#;
+
+/// Client without retries, so each call maps to exactly one scripted response
+fn client(transport: &ScriptedTransport) -> CubeSignerClient<&ScriptedTransport> {
+    CubeSignerClient::new(transport, "https://signer.example/", "Org#123", "session-token").with_retry(RetryPolicy::none())
+}
+
+/// Client with the default retry policy, recording its backoffs instead of sleeping
+fn retrying_client(transport: &ScriptedTransport) -> (CubeSignerClient<&ScriptedTransport>, Arc<Mutex<Vec<Duration>>>) {
+    let sleeps = Arc::new(Mutex::new(Vec::new()));
+    let recorded = Arc::clone(&sleeps);
+    let client = CubeSignerClient::new(transport, "https://signer.example/", "Org#123", "session-token")
+        .with_sleep(move |delay| recorded.lock().unwrap().push(delay));
+    (client, sleeps)
+}
+
+#[test]
+fn test_create_evm_key_returns_material_id_and_key_id() {
+    let transport = ScriptedTransport::new(vec![ok(&format!(r#"{{"keys":[{}]}}"#, KEY_JSON))]);
+
+    let key = client(&transport).create_evm_key("TestUser123").unwrap();
+    assert_eq!(key.address, "0xcb373e47d769b06dee02f05c86dd8790e0358aee");
+    assert_eq!(key.key_id, "Key#0xcb373e47d769b06dee02f05c86dd8790e0358aee");
+
+    let requests = transport.requests.lock().unwrap();
+    assert_eq!(requests.len(), 1);
+    assert_eq!(requests[0].method, HttpMethod::Post);
+    assert_eq!(requests[0].url, "https://signer.example/v0/org/Org#123/keys");
+    assert!(requests[0].headers.contains(&("Authorization".to_string(), "session-token".to_string())));
+
+    let body: serde_json::Value = serde_json::from_str(requests[0].body.as_ref().unwrap()).unwrap();
+    assert_eq!(body["key_type"], "SecpEthAddr");
+    assert_eq!(body["metadata"]["name"], "EVM_TestUser123");
+    assert!(body.get("mpc").is_none());
+}
+
+#[test]
+fn test_get_and_list_keys() {
+    let transport = ScriptedTransport::new(vec![
+        ok(KEY_JSON),
+        ok(&format!(r#"{{"keys":[{}],"last_evaluated_key":"next"}}"#, KEY_JSON)),
+        ok(r#"{"keys":[]}"#),
+    ]);
+    let client = client(&transport);
+
+    let key = client.get_key("Key#0xcb373e47d769b06dee02f05c86dd8790e0358aee").unwrap();
+    assert_eq!(key.metadata.unwrap().name, "EVM_TestUser123");
+
+    let page = client.list_keys(None).unwrap();
+    assert_eq!(page.keys.len(), 1);
+    assert_eq!(page.last_evaluated_key.as_deref(), Some("next"));
+
+    let last = client.list_keys(Some("next")).unwrap();
+    assert!(last.keys.is_empty());
+    assert_eq!(last.last_evaluated_key, None);
+
+    let requests = transport.requests.lock().unwrap();
+    assert_eq!(requests[2].url, "https://signer.example/v0/org/Org#123/keys?page.start=next");
+}
+
+#[test]
+fn test_error_statuses_are_mapped() {
+    let transport = ScriptedTransport::new(vec![
+        status(401, r#"{"message":"session expired"}"#),
+        status(404, r#"{"message":"no such key"}"#),
+        status(409, r#"{"message":"duplicate"}"#),
+        status(429, "slow down"),
+        status(503, "unavailable"),
+        Err("connection reset".to_string()),
+        ok("not json"),
+    ]);
+    let client = client(&transport);
+
+    assert_eq!(client.get_key("k").unwrap_err(), CubeSignerError::Unauthorized("session expired".into()));
+    assert_eq!(client.get_key("k").unwrap_err(), CubeSignerError::NotFound("no such key".into()));
+    assert_eq!(client.get_key("k").unwrap_err(), CubeSignerError::Conflict("duplicate".into()));
+    assert_eq!(client.get_key("k").unwrap_err(), CubeSignerError::RateLimited("slow down".into()));
+    assert_eq!(
+        client.get_key("k").unwrap_err(),
+        CubeSignerError::Api { status: 503, message: "unavailable".into() }
+    );
+    assert_eq!(client.get_key("k").unwrap_err(), CubeSignerError::Transport("connection reset".into()));
+    assert!(matches!(client.get_key("k").unwrap_err(), CubeSignerError::InvalidResponse(_)));
+}
+
+#[test]
+fn test_key_creation_errors_are_retryable_only_for_transient_failures() {
+    let transport = ScriptedTransport::new(vec![
+        status(401, r#"{"message":"session expired"}"#),
+        status(503, "unavailable"),
+        status(429, "slow down"),
+        Err("connection reset".to_string()),
+    ]);
+    let client = client(&transport);
+
+    let retryable: Vec<bool> = (0..4)
+        .map(|_| {
+            let err = client.create_evm_key("7xKXtg2CW87d97TXJSDpbD5jBkheTqA83TZRuJosgAsU").unwrap_err();
+            assert!(matches!(err, ProvisionError::KeyCreationFailed { .. }));
+            assert_eq!(err.code(), "KEY_CREATION_FAILED");
+            err.is_retryable()
+        })
+        .collect();
+    assert_eq!(retryable, vec![false, true, true, true]);
+}
+
+#[test]
+fn test_create_solana_key_uses_solana_key_type() {
+    let key_json = r#"{
+        "key_id": "Key#Solana_7xKXtg2CW87d97TXJSDpbD5jBkheTqA83TZRuJosgAsU",
+        "key_type": "Ed25519SolanaAddr",
+        "material_id": "7xKXtg2CW87d97TXJSDpbD5jBkheTqA83TZRuJosgAsU"
+    }"#;
+    let transport = ScriptedTransport::new(vec![ok(&format!(r#"{{"keys":[{}]}}"#, key_json))]);
+
+    let key = client(&transport).create_solana_key("0xcb373e47d769b06dee02f05c86dd8790e0358aee").unwrap();
+    assert_eq!(key.address, "7xKXtg2CW87d97TXJSDpbD5jBkheTqA83TZRuJosgAsU");
+
+    let requests = transport.requests.lock().unwrap();
+    let body: serde_json::Value = serde_json::from_str(requests[0].body.as_ref().unwrap()).unwrap();
+    assert_eq!(body["key_type"], "Ed25519SolanaAddr");
+    assert_eq!(body["metadata"]["name"], "SOL_0xcb373e47d769b06dee02f05c86dd8790e0358aee");
+}
+
+#[test]
+fn test_transient_failures_are_retried_with_backoff() {
+    let transport = ScriptedTransport::new(vec![
+        status(503, "unavailable"),
+        Err("timed out".to_string()),
+        ok(&format!(r#"{{"keys":[{}]}}"#, KEY_JSON)),
+    ]);
+    let (client, sleeps) = retrying_client(&transport);
+
+    let key = client.create_evm_key("TestUser123").unwrap();
+    assert_eq!(key.address, "0xcb373e47d769b06dee02f05c86dd8790e0358aee");
+
+    let requests = transport.requests.lock().unwrap();
+    assert_eq!(requests.len(), 3);
+    assert!(requests.iter().all(|request| request.timeout == DEFAULT_TIMEOUT));
+
+    // 200ms doubling, each with its upper half jittered
+    let sleeps = sleeps.lock().unwrap();
+    assert_eq!(sleeps.len(), 2);
+    assert!(sleeps[0] >= Duration::from_millis(100) && sleeps[0] <= Duration::from_millis(200));
+    assert!(sleeps[1] >= Duration::from_millis(200) && sleeps[1] <= Duration::from_millis(400));
+}
+
+#[test]
+fn test_permanent_failures_and_exhausted_retries_are_returned() {
+    let transport = ScriptedTransport::new(vec![
+        status(401, r#"{"message":"session expired"}"#),
+        status(503, "unavailable"),
+        status(502, "bad gateway"),
+        status(500, "still broken"),
+    ]);
+    let (client, sleeps) = retrying_client(&transport);
+
+    assert_eq!(client.get_key("k").unwrap_err(), CubeSignerError::Unauthorized("session expired".into()));
+    assert!(sleeps.lock().unwrap().is_empty());
+
+    let err = client.create_evm_key("TestUser123").unwrap_err();
+    assert!(err.is_retryable());
+    assert!(err.to_string().contains("still broken"));
+    assert_eq!(transport.requests.lock().unwrap().len(), 4);
+}
+
+#[test]
+fn test_backoff_is_capped_and_timeout_configurable() {
+    let policy = RetryPolicy {
+        max_attempts: 10,
+        base_delay: Duration::from_secs(1),
+        max_delay: Duration::from_secs(2),
+    };
+    for retry in [3, 9, 40] {
+        let delay = policy.backoff(retry);
+        assert!(delay >= Duration::from_secs(1) && delay <= Duration::from_secs(2));
+    }
+
+    let transport = ScriptedTransport::new(vec![ok(KEY_JSON)]);
+    client(&transport).with_timeout(Duration::from_secs(3)).get_key("k").unwrap();
+    assert_eq!(transport.requests.lock().unwrap()[0].timeout, Duration::from_secs(3));
+}
+
+#[test]
+fn test_create_returns_existing_key_when_name_is_taken() {
+    let other = KEY_JSON.replace("EVM_TestUser123", "EVM_SomeoneElse").replace("cb373e47", "00000000");
+    let transport = ScriptedTransport::new(vec![
+        status(409, r#"{"message":"key with this name exists"}"#),
+        ok(&format!(r#"{{"keys":[{}],"last_evaluated_key":"next"}}"#, other)),
+        ok(&format!(r#"{{"keys":[{}]}}"#, KEY_JSON)),
+    ]);
+
+    let key = client(&transport).create_evm_key("TestUser123").unwrap();
+    assert_eq!(key.address, "0xcb373e47d769b06dee02f05c86dd8790e0358aee");
+    assert_eq!(key.key_id, "Key#0xcb373e47d769b06dee02f05c86dd8790e0358aee");
+
+    let requests = transport.requests.lock().unwrap();
+    assert_eq!(requests[1].url, "https://signer.example/v0/org/Org#123/keys");
+    assert_eq!(requests[2].url, "https://signer.example/v0/org/Org#123/keys?page.start=next");
+}
+
+#[test]
+fn test_conflict_without_matching_key_is_returned() {
+    // A Solana key does not count as the EVM key of the same name
+    let solana = KEY_JSON.replace("SecpEthAddr", "Ed25519SolanaAddr");
+    let transport = ScriptedTransport::new(vec![
+        status(409, r#"{"message":"duplicate"}"#),
+        ok(&format!(r#"{{"keys":[{}]}}"#, solana)),
+    ]);
+
+    let err = client(&transport).create_evm_key("TestUser123").unwrap_err();
+    assert_eq!(err.code(), "KEY_CREATION_FAILED");
+    assert!(!err.is_retryable());
+    assert!(err.to_string().contains("duplicate"));
+}
+
+#[test]
+fn test_list_evm_keys_pages_and_skips_other_key_types() {
+    let solana = KEY_JSON.replace("SecpEthAddr", "Ed25519SolanaAddr");
+    let unnamed = r#"{"key_id":"Key#0x01","key_type":"SecpEthAddr","material_id":"0x01"}"#;
+    let transport = ScriptedTransport::new(vec![
+        ok(&format!(r#"{{"keys":[{},{}],"last_evaluated_key":"next"}}"#, KEY_JSON, solana)),
+        ok(&format!(r#"{{"keys":[{}]}}"#, unnamed)),
+    ]);
+
+    let keys = client(&transport).list_evm_keys().unwrap();
+    let listed: Vec<(&str, &str)> = keys.iter().map(|key| (key.address.as_str(), key.name.as_str())).collect();
+    assert_eq!(listed, vec![("0xcb373e47d769b06dee02f05c86dd8790e0358aee", "EVM_TestUser123"), ("0x01", "")]);
+}
+
+#[test]
+fn test_find_evm_key_stops_at_the_page_holding_it() {
+    let transport = ScriptedTransport::new(vec![ok(&format!(r#"{{"keys":[{}],"last_evaluated_key":"next"}}"#, KEY_JSON))]);
+
+    let key = client(&transport).find_evm_key("EVM_TestUser123").unwrap().unwrap();
+    assert_eq!(key.key_id, "Key#0xcb373e47d769b06dee02f05c86dd8790e0358aee");
+    assert_eq!(transport.requests.lock().unwrap().len(), 1);
+}
+
+#[test]
+fn test_eth1_sign_posts_transaction_and_returns_signed_rlp() {
+    let transport = ScriptedTransport::new(vec![ok(r#"{"rlp_signed_tx":"0x02f8aa"}"#)]);
+    let tx = serde_json::json!({ "type": "0x2", "to": "0x1111111111111111111111111111111111111111", "nonce": "0x0" });
+
+    let signed = client(&transport).eth1_sign("0xcb373e47d769b06dee02f05c86dd8790e0358aee", 137, tx.clone()).unwrap();
+    assert_eq!(signed, "0x02f8aa");
+
+    let requests = transport.requests.lock().unwrap();
+    assert_eq!(requests[0].method, HttpMethod::Post);
+    assert_eq!(requests[0].url, "https://signer.example/v1/org/Org#123/eth1/sign/0xcb373e47d769b06dee02f05c86dd8790e0358aee");
+    let body: serde_json::Value = serde_json::from_str(requests[0].body.as_ref().unwrap()).unwrap();
+    assert_eq!(body["chain_id"], 137);
+    assert_eq!(body["tx"], tx);
+}
+
+#[test]
+fn test_create_typed_key_derives_evm_address_from_public_key() {
+    let signing_key = k256::ecdsa::SigningKey::from_bytes(&[1u8; 32].into()).unwrap();
+    let public_key = signing_key.verifying_key().to_encoded_point(false);
+    let public_key: String = public_key.as_bytes().iter().map(|b| format!("{:02x}", b)).collect();
+    let key_json = format!(
+        r#"{{"keys":[{{"key_id":"Key#SecpAva_1","key_type":"SecpAvaAddr","material_id":"avax1qqqq","public_key":"0x{}"}}]}}"#,
+        public_key
+    );
+    let transport = ScriptedTransport::new(vec![ok(&key_json)]);
+
+    let key = client(&transport).create_typed_key("TestUser123", KeyType::SecpAvaAddr, KeyClass::Standard).unwrap();
+    assert_eq!(key.key_id, "Key#SecpAva_1");
+    assert_eq!(key.address, auth::evm_address_of(signing_key.verifying_key()));
+
+    let requests = transport.requests.lock().unwrap();
+    let body: serde_json::Value = serde_json::from_str(requests[0].body.as_ref().unwrap()).unwrap();
+    assert_eq!(body["key_type"], "SecpAvaAddr");
+    assert_eq!(body["metadata"]["name"], "EVM_TestUser123_SecpAvaAddr");
+}
+
+#[test]
+fn test_create_typed_key_sends_mpc_quorum() {
+    let transport = ScriptedTransport::new(vec![ok(&format!(r#"{{"keys":[{}]}}"#, KEY_JSON))]);
+
+    let key = client(&transport)
+        .create_typed_key("TestUser123", KeyType::SecpEthAddr, KeyClass::Mpc { threshold: 2, participants: 3 })
+        .unwrap();
+    assert_eq!(key.address, "0xcb373e47d769b06dee02f05c86dd8790e0358aee");
+
+    let requests = transport.requests.lock().unwrap();
+    let body: serde_json::Value = serde_json::from_str(requests[0].body.as_ref().unwrap()).unwrap();
+    assert_eq!(body["metadata"]["name"], "EVM_TestUser123");
+    assert_eq!(body["mpc"], serde_json::json!({ "threshold": 2, "participants": 3 }));
+}
+
+#[test]
+fn test_new_keys_get_configured_policies_and_role() {
+    let transport = ScriptedTransport::new(vec![ok(&format!(r#"{{"keys":[{}]}}"#, KEY_JSON)), ok(KEY_JSON), ok("{}")]);
+    let policies = KeyPolicies {
+        policy_ids: vec!["NamedPolicy#allowlist".to_string(), "NamedPolicy#max-value".to_string()],
+        role_id: Some("Role#signers".to_string()),
+    };
+
+    let key = client(&transport).with_key_policies(policies.clone()).create_evm_key("TestUser123").unwrap();
+    assert_eq!(key.policies, policies.policy_ids);
+
+    let requests = transport.requests.lock().unwrap();
+    assert_eq!(requests.len(), 3);
+    assert_eq!(requests[1].method, HttpMethod::Patch);
+    assert_eq!(requests[1].url, "https://signer.example/v0/org/Org#123/keys/Key#0xcb373e47d769b06dee02f05c86dd8790e0358aee");
+    let body: serde_json::Value = serde_json::from_str(requests[1].body.as_ref().unwrap()).unwrap();
+    assert_eq!(body["policy"], serde_json::json!(["NamedPolicy#allowlist", "NamedPolicy#max-value"]));
+    assert_eq!(requests[2].method, HttpMethod::Put);
+    assert_eq!(requests[2].url, "https://signer.example/v0/org/Org#123/roles/Role#signers/add_keys");
+    let body: serde_json::Value = serde_json::from_str(requests[2].body.as_ref().unwrap()).unwrap();
+    assert_eq!(body["key_ids"], serde_json::json!(["Key#0xcb373e47d769b06dee02f05c86dd8790e0358aee"]));
+}
+
+#[test]
+fn test_existing_key_is_restricted_again_and_role_conflict_is_ignored() {
+    let transport = ScriptedTransport::new(vec![
+        status(409, r#"{"message":"name taken"}"#),
+        ok(&format!(r#"{{"keys":[{}]}}"#, KEY_JSON)),
+        status(409, r#"{"message":"key already in role"}"#),
+    ]);
+    let policies = KeyPolicies { policy_ids: Vec::new(), role_id: Some("Role#signers".to_string()) };
+
+    let key = client(&transport).with_key_policies(policies).create_evm_key("TestUser123").unwrap();
+    assert_eq!(key.key_id, "Key#0xcb373e47d769b06dee02f05c86dd8790e0358aee");
+    assert!(key.policies.is_empty());
+    assert_eq!(transport.requests.lock().unwrap()[2].method, HttpMethod::Put);
+}
+
+#[test]
+fn test_eip712_sign_posts_typed_data_and_returns_signature() {
+    let transport = ScriptedTransport::new(vec![ok(r#"{"signature":"0x1b2c"}"#)]);
+    let typed_data = serde_json::json!({ "primaryType": "MappingAttestation", "domain": { "chainId": 137 } });
+
+    let signature = client(&transport).eip712_sign("0xcb373e47d769b06dee02f05c86dd8790e0358aee", 137, &typed_data).unwrap();
+    assert_eq!(signature, "0x1b2c");
+
+    let requests = transport.requests.lock().unwrap();
+    assert_eq!(requests[0].url, "https://signer.example/v1/org/Org#123/evm/eip712/sign/0xcb373e47d769b06dee02f05c86dd8790e0358aee");
+    let body: serde_json::Value = serde_json::from_str(requests[0].body.as_ref().unwrap()).unwrap();
+    assert_eq!(body["chain_id"], 137);
+    assert_eq!(body["typed_data"], typed_data);
+}
+
+#[test]
+fn test_solana_sign_posts_message_and_returns_signature() {
+    let transport = ScriptedTransport::new(vec![ok(r#"{"signature":"0xabcd"}"#)]);
+
+    let signature = client(&transport).solana_sign("So1anaOperator", "AQID").unwrap();
+    assert_eq!(signature, "0xabcd");
+
+    let requests = transport.requests.lock().unwrap();
+    assert_eq!(requests[0].url, "https://signer.example/v0/org/Org#123/solana/sign/So1anaOperator");
+    let body: serde_json::Value = serde_json::from_str(requests[0].body.as_ref().unwrap()).unwrap();
+    assert_eq!(body["message_base64"], "AQID");
+}
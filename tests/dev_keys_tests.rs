@@ -0,0 +1,103 @@
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use cubist_wallet_provisioner::auth::{evm_address_of, verify_evm_signature};
+use cubist_wallet_provisioner::dev_keys::DevKeys;
+use cubist_wallet_provisioner::keys::{chain_key_name, default_key_name};
+use cubist_wallet_provisioner::memory_kv::MemoryKvStore;
+use cubist_wallet_provisioner::testing::{admin, admins, store_message};
+use cubist_wallet_provisioner::{
+    ChainId, EvmAddress, KeyClass, KeyCreator, KeyType, ProvisionRequest, Provisioner, SolanaKeyCreator, SolanaPubkey, UpdateMappingRequest,
+};
+use ed25519_dalek::{Signer, SigningKey};
+use sha3::{Digest, Keccak256};
+
+fn wallet() -> (SigningKey, SolanaPubkey) {
+    let wallet = SigningKey::from_bytes(&[1; 32]);
+    let solana_pubkey = SolanaPubkey::parse(&bs58::encode(wallet.verifying_key().to_bytes()).into_string()).unwrap();
+    (wallet, solana_pubkey)
+}
+
+#[test]
+fn test_dev_keys_are_derived_from_the_key_name() {
+    let (_, solana_pubkey) = wallet();
+    let solana_pubkey = solana_pubkey.as_str();
+    let keys = DevKeys::default();
+    let default_key = keys.create_evm_key(solana_pubkey).unwrap();
+
+    // Pinned, so local stacks keep their addresses across releases
+    assert_eq!(default_key.address, "0xe5772324b62d6f689d34c90e734333a20633e41b");
+    assert_eq!(default_key.key_id, format!("Key#{}", default_key.address));
+    assert_eq!(DevKeys::default().create_evm_key(solana_pubkey).unwrap(), default_key);
+    assert_ne!(DevKeys::new(b"another seed").create_evm_key(solana_pubkey).unwrap().address, default_key.address);
+
+    // Each key name is its own key
+    let other = bs58::encode([2; 32]).into_string();
+    let addresses = [
+        keys.create_evm_key(&other).unwrap().address,
+        keys.create_evm_key_for_chain(solana_pubkey, &ChainId::eip155(137)).unwrap().address,
+        keys.create_labeled_evm_key(solana_pubkey, "trading", None).unwrap().address,
+        keys.create_typed_key(solana_pubkey, KeyType::SecpBtc, KeyClass::Standard).unwrap().address,
+    ];
+    for (i, address) in addresses.iter().enumerate() {
+        assert_ne!(*address, default_key.address);
+        assert!(!addresses[..i].contains(address));
+    }
+    assert_eq!(keys.create_typed_key(solana_pubkey, KeyType::SecpEthAddr, KeyClass::Standard).unwrap(), default_key);
+    assert!(keys.create_typed_key(solana_pubkey, KeyType::Ed25519StellarAddr, KeyClass::Standard).is_err());
+    assert!(keys.create_typed_key(solana_pubkey, KeyType::SecpEthAddr, KeyClass::Mpc { threshold: 2, participants: 3 }).is_err());
+
+    let solana_key = keys.create_solana_key(&default_key.address).unwrap();
+    assert!(SolanaPubkey::parse(&solana_key.address).is_ok());
+}
+
+#[test]
+fn test_dev_key_addresses_sign_for_themselves() {
+    let (_, solana_pubkey) = wallet();
+    let keys = DevKeys::default();
+    let address = EvmAddress::parse(&keys.create_evm_key(solana_pubkey.as_str()).unwrap().address).unwrap();
+
+    // EIP-191 signature by the derived key, recovered to the derived address
+    let message = "dev keys are real keys";
+    let digest = Keccak256::digest(format!("\x19Ethereum Signed Message:\n{}{}", message.len(), message).as_bytes());
+    let (signature, recovery_id) = keys.evm_signing_key(&default_key_name(solana_pubkey.as_str())).sign_prehash_recoverable(&digest).unwrap();
+    let mut bytes = signature.to_bytes().to_vec();
+    bytes.push(27 + recovery_id.to_byte());
+    let signature = format!("0x{}", bytes.iter().map(|b| format!("{:02x}", b)).collect::<String>());
+    verify_evm_signature(&address, message, &signature).unwrap();
+}
+
+#[test]
+fn test_provisioning_runs_offline_with_dev_keys() {
+    let (wallet, solana_pubkey) = wallet();
+    let keys = DevKeys::default();
+    let provisioner = Provisioner::new(MemoryKvStore::new(), keys.clone()).with_admins(admins()).with_single_step_updates();
+    let chain_ids = vec![ChainId::eip155(1), ChainId::eip155(137)];
+    let message = store_message(&solana_pubkey, &chain_ids);
+    let provisioned = provisioner
+        .handle(ProvisionRequest {
+            solana_pubkey: solana_pubkey.clone(),
+            chain_ids,
+            signature: BASE64.encode(wallet.sign(message.as_bytes()).to_bytes()),
+            message,
+            label: None,
+            key_type: KeyType::default(),
+            key_class: KeyClass::default(),
+            idempotency_key: None,
+            ttl_secs: None,
+            request_id: None,
+        })
+        .unwrap();
+    assert_eq!(provisioned.evm_address.as_str(), keys.create_evm_key(solana_pubkey.as_str()).unwrap().address);
+
+    let updated = provisioner
+        .handle_update_mapping(&admin(), UpdateMappingRequest {
+            solana_pubkey: solana_pubkey.clone(),
+            chain_id: ChainId::eip155(137),
+            expected_version: None,
+            label: None,
+            idempotency_key: None,
+            request_id: None,
+        })
+        .unwrap();
+    let chain_key = keys.evm_signing_key(&chain_key_name(solana_pubkey.as_str(), &ChainId::eip155(137)));
+    assert_eq!(updated.new_evm_address.as_str(), evm_address_of(chain_key.verifying_key()));
+}
@@ -0,0 +1,80 @@
+use cubist_wallet_provisioner::dry_run::{PLACEHOLDER_ADDRESS, PLACEHOLDER_KEY_ID};
+use cubist_wallet_provisioner::kv::{chain_key, default_key};
+use cubist_wallet_provisioner::testing::{admin, chain, evm, provision_request, pubkey, update_request, wallet, MockKvStore, TestContext};
+use cubist_wallet_provisioner::{KvStore, ProvisionBatchRequest};
+
+// =============================================================================
+// DRY RUN TESTS
+// =============================================================================
+
+/// Every key of the bucket with its value
+fn bucket_snapshot(kv: &MockKvStore) -> Vec<(String, Option<String>)> {
+    let keys = kv.list_keys(None, 10_000).unwrap();
+    let values = kv.get_many(&keys).unwrap();
+    keys.into_iter().zip(values).collect()
+}
+
+#[test]
+fn test_dry_run_store_previews_without_writing() {
+    let ctx = TestContext::new();
+    let alice = wallet(1);
+    let solana_pubkey = pubkey(&alice);
+
+    let preview = ctx.provisioner.handle_dry_run(provision_request(&alice, vec![1, 137])).unwrap();
+    assert!(preview.dry_run && preview.creates_key);
+    assert_eq!(preview.result.evm_address, evm(PLACEHOLDER_ADDRESS));
+    assert_eq!(preview.result.chain_mappings.len(), 2);
+    assert!(preview.writes.contains(&default_key(&solana_pubkey)));
+    assert!(preview.writes.contains(&chain_key(&solana_pubkey, &chain(137))));
+    assert!(bucket_snapshot(&ctx.kv).is_empty());
+    assert_eq!(*ctx.default_key_counter.lock().unwrap(), 0);
+
+    // Checks still run
+    let mut forged = provision_request(&alice, vec![1]);
+    forged.signature = provision_request(&alice, vec![1]).signature;
+    assert_eq!(ctx.provisioner.handle_dry_run(forged).unwrap_err().code(), "SIGNATURE_MISMATCH");
+
+    // Once provisioned, the preview shows the real address and no new key
+    let stored = ctx.provisioner.handle(provision_request(&alice, vec![1])).unwrap();
+    let before = bucket_snapshot(&ctx.kv);
+    let preview = ctx.provisioner.handle_dry_run(provision_request(&alice, vec![1, 137])).unwrap();
+    assert!(!preview.creates_key);
+    assert_eq!(preview.result.chain_mappings[&chain(137)], stored.evm_address);
+    assert_eq!(bucket_snapshot(&ctx.kv), before);
+}
+
+#[test]
+fn test_dry_run_update_previews_without_writing() {
+    let ctx = TestContext::new();
+    let solana_pubkey = pubkey(&wallet(1));
+    assert_eq!(ctx.provisioner.handle_dry_run_update(&admin(), update_request(&solana_pubkey, 137)).unwrap_err().code(), "NOT_PROVISIONED");
+
+    ctx.provisioner.handle(provision_request(&wallet(1), vec![1, 137])).unwrap();
+    let before = bucket_snapshot(&ctx.kv);
+    let preview = ctx.provisioner.handle_dry_run_update(&admin(), update_request(&solana_pubkey, 137)).unwrap();
+    assert!(preview.creates_key);
+    assert_eq!(preview.result.new_evm_address, evm(PLACEHOLDER_ADDRESS));
+    assert_eq!(preview.result.new_key_id, PLACEHOLDER_KEY_ID);
+    assert_eq!(preview.result.version, 1);
+    assert!(preview.writes.contains(&chain_key(&solana_pubkey, &chain(137))));
+    assert_eq!(bucket_snapshot(&ctx.kv), before);
+    assert_eq!(*ctx.provisioner.keys().chain_key_counter.lock().unwrap(), 1000);
+
+    let mut stale = update_request(&solana_pubkey, 137);
+    stale.expected_version = Some(3);
+    assert_eq!(ctx.provisioner.handle_dry_run_update(&admin(), stale).unwrap_err().code(), "VERSION_CONFLICT");
+}
+
+#[test]
+fn test_dry_run_batch_reports_each_entry() {
+    let ctx = TestContext::new();
+    let batch = ProvisionBatchRequest {
+        requests: vec![provision_request(&wallet(1), vec![1]), provision_request(&wallet(2), vec![])],
+        request_id: None,
+    };
+    let preview = ctx.provisioner.handle_dry_run_batch(batch).unwrap();
+    assert!(preview.creates_key);
+    assert_eq!((preview.result.succeeded, preview.result.failed), (1, 1));
+    assert_eq!(preview.result.results[1].error.as_ref().unwrap().code(), "INVALID_REQUEST");
+    assert!(bucket_snapshot(&ctx.kv).is_empty());
+}
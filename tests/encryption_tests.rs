@@ -0,0 +1,190 @@
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use cubist_wallet_provisioner::encryption::{self, DataKey, Encrypted, SecretWrapper, ENCRYPTED_PREFIX};
+use cubist_wallet_provisioner::environment::{self, EnvPrefixed, Environment};
+use cubist_wallet_provisioner::error::Result;
+use cubist_wallet_provisioner::kv::{self, chain_index_key, default_key, reverse_key};
+use cubist_wallet_provisioner::export::ExportRequest;
+use cubist_wallet_provisioner::memory_kv::MemoryKvStore;
+use cubist_wallet_provisioner::migrate::MigrateRequest;
+use cubist_wallet_provisioner::privacy::{HashedKeys, Pepper};
+use cubist_wallet_provisioner::verify::VerifyRequest;
+use cubist_wallet_provisioner::testing::{admin, admins, store_message};
+use cubist_wallet_provisioner::{ChainId, CreatedKey, KeyClass, KeyCreator, KeyType, KvStore, ProvisionRequest, Provisioner, SolanaPubkey};
+use ed25519_dalek::{Signer, SigningKey};
+
+const EVM_ADDRESS: &str = "0x00000000000000000000000000000000000000aa";
+
+/// Key creator returning the same key for every call
+struct FixedKeys;
+
+impl KeyCreator for FixedKeys {
+    fn create_evm_key(&self, _solana_pubkey: &str) -> Result<CreatedKey> {
+        Ok(CreatedKey { key_id: format!("Key#{}", EVM_ADDRESS), address: EVM_ADDRESS.to_string(), policies: Vec::new() })
+    }
+
+    fn create_evm_key_for_chain(&self, solana_pubkey: &str, _chain_id: &ChainId) -> Result<CreatedKey> {
+        self.create_evm_key(solana_pubkey)
+    }
+
+    fn create_labeled_evm_key(&self, solana_pubkey: &str, _label: &str, _chain_id: Option<&ChainId>) -> Result<CreatedKey> {
+        self.create_evm_key(solana_pubkey)
+    }
+}
+
+fn data_key(seed: u8) -> DataKey {
+    DataKey::new(&[seed; 32]).unwrap()
+}
+
+fn provision(provisioner: &Provisioner<impl KvStore, FixedKeys>) -> SolanaPubkey {
+    let wallet = SigningKey::from_bytes(&[1; 32]);
+    let solana_pubkey = SolanaPubkey::parse(&bs58::encode(wallet.verifying_key().to_bytes()).into_string()).unwrap();
+    let chain_ids = vec![ChainId::eip155(1), ChainId::eip155(137)];
+    let message = store_message(&solana_pubkey, &chain_ids);
+    provisioner
+        .handle(ProvisionRequest {
+            solana_pubkey: solana_pubkey.clone(),
+            chain_ids,
+            signature: BASE64.encode(wallet.sign(message.as_bytes()).to_bytes()),
+            message,
+            label: None,
+            key_type: KeyType::default(),
+            key_class: KeyClass::default(),
+            idempotency_key: None,
+            ttl_secs: None,
+            request_id: None,
+        })
+        .unwrap();
+    solana_pubkey
+}
+
+#[test]
+fn test_encrypted_store_keeps_no_plaintext_values() {
+    let kv = MemoryKvStore::new();
+    let provisioner = Provisioner::new(Encrypted::new(kv.clone(), data_key(7)), FixedKeys);
+    let solana_pubkey = provision(&provisioner);
+
+    let keys = kv.list_keys(None, 100).unwrap();
+    assert!(!keys.is_empty());
+    for (key, stored) in keys.iter().zip(kv.get_many(&keys).unwrap()) {
+        let stored = stored.unwrap();
+        assert!(stored.starts_with(ENCRYPTED_PREFIX), "{} is stored as {}", key, stored);
+        assert!(!stored.contains(&EVM_ADDRESS[2..]) && !stored.contains(solana_pubkey.as_str()));
+    }
+
+    let evm_address = provisioner.handle_get(&solana_pubkey, &[]).unwrap().default_address.unwrap();
+    assert_eq!(evm_address.as_str(), EVM_ADDRESS);
+    assert_eq!(provisioner.handle_reverse_get(&evm_address).unwrap(), Some(solana_pubkey.clone()));
+    assert!(encryption::is_encrypted(&kv.get(&reverse_key(&evm_address)).unwrap().unwrap()));
+
+    // Another key cannot open the values
+    let stolen = Encrypted::new(kv.clone(), data_key(8));
+    assert_eq!(stolen.get(&default_key(&solana_pubkey)).unwrap_err().code(), "CORRUPT_RECORD");
+}
+
+#[test]
+fn test_hashed_keys_over_sealed_values_keep_scans_working() {
+    let pepper = Pepper::new(b"test-pepper-0123456789abcdef-0123456789").unwrap();
+    let (plain_kv, hashed_kv) = (MemoryKvStore::new(), MemoryKvStore::new());
+    let plain = Provisioner::new(Encrypted::new(plain_kv.clone(), data_key(7)), FixedKeys).with_admins(admins());
+    let hashed = Provisioner::new(HashedKeys::new(Encrypted::new(hashed_kv.clone(), data_key(7)), Some(pepper.clone())), FixedKeys)
+        .with_admins(admins());
+    let solana_pubkey = provision(&plain);
+    provision(&hashed);
+
+    // Neither keys nor values of the dump name the user
+    let keys = hashed_kv.list_keys(None, 100).unwrap();
+    for (key, stored) in keys.iter().zip(hashed_kv.get_many(&keys).unwrap()) {
+        assert!(!key.contains(solana_pubkey.as_str()), "{}", key);
+        assert!(!stored.unwrap().contains(solana_pubkey.as_str()), "{}", key);
+    }
+
+    // Scans find the user behind the hashes, as they do over plaintext keys
+    let root = hashed.handle_merkle_root(&admin()).unwrap();
+    assert!(root.leaf_count > 0);
+    assert_eq!(root, plain.handle_merkle_root(&admin()).unwrap());
+    let verified = hashed.handle_verify(&admin(), VerifyRequest::default()).unwrap();
+    assert_eq!(verified.scanned, plain.handle_verify(&admin(), VerifyRequest::default()).unwrap().scanned + 1);
+    assert!(verified.violations.is_empty());
+    // A lost chain index is rebuilt from the hashed keys
+    let index = HashedKeys::new(Encrypted::new(hashed_kv.clone(), data_key(7)), Some(pepper));
+    index.delete(&chain_index_key(&solana_pubkey)).unwrap();
+    assert_eq!(hashed.handle_migrate(&admin(), MigrateRequest::default()).unwrap().indexed, 2);
+    assert_eq!(kv::get_chain_index(&index, &solana_pubkey).unwrap().len(), 2);
+    let exported = hashed.handle_export(&admin(), ExportRequest::default()).unwrap();
+    assert!(exported.entries.iter().any(|entry| entry.key == default_key(&solana_pubkey)));
+}
+
+#[test]
+fn test_ciphertext_moved_to_another_key_fails_to_decrypt() {
+    let kv = MemoryKvStore::new();
+    let encrypted = Encrypted::new(kv.clone(), data_key(7));
+    let solana_pubkey = provision(&Provisioner::new(Encrypted::new(kv.clone(), data_key(7)), FixedKeys));
+    let victim = SolanaPubkey::parse(&bs58::encode([2; 32]).into_string()).unwrap();
+
+    // Copying alice's sealed record under another user's key does not give them her mapping
+    let sealed = kv.get(&default_key(&solana_pubkey)).unwrap().unwrap();
+    kv.set(&default_key(&victim), &sealed).unwrap();
+    let err = encrypted.get(&default_key(&victim)).unwrap_err();
+    assert_eq!(err.code(), "CORRUPT_RECORD");
+    assert!(encrypted.get_many(&[default_key(&victim)]).is_err());
+    assert!(encrypted.get(&default_key(&solana_pubkey)).unwrap().is_some());
+
+    // The same value under two keys is sealed differently
+    encrypted.set("a", "value").unwrap();
+    encrypted.set("b", "value").unwrap();
+    assert_ne!(kv.get("a").unwrap(), kv.get("b").unwrap());
+
+    // Copying migrations go through the `Encrypted` view, which seals each value again under its new key
+    kv.delete(&default_key(&victim)).unwrap();
+    let report = environment::migrate_legacy_batch(&encrypted, Environment::Prod, None, None).unwrap();
+    assert!(report.copied > 0 && report.conflicts.is_empty());
+    let migrated = EnvPrefixed::new(Encrypted::new(kv.clone(), data_key(7)), Some(Environment::Prod));
+    assert_eq!(migrated.get("a").unwrap().as_deref(), Some("value"));
+    assert!(migrated.get(&default_key(&solana_pubkey)).unwrap().is_some());
+}
+
+#[test]
+fn test_plaintext_values_are_read_and_encrypted_in_place() {
+    let kv = MemoryKvStore::new();
+    let solana_pubkey = provision(&Provisioner::new(kv.clone(), FixedKeys));
+    let plaintext = kv.get(&default_key(&solana_pubkey)).unwrap().unwrap();
+
+    // Values written before encryption was turned on still read
+    let provisioner = Provisioner::new(Encrypted::new(kv.clone(), data_key(7)), FixedKeys);
+    assert_eq!(provisioner.handle_get(&solana_pubkey, &[]).unwrap().default_address.unwrap().as_str(), EVM_ADDRESS);
+
+    let mut cursor = None;
+    let mut encrypted = 0;
+    loop {
+        let report = encryption::encrypt_plaintext_batch(&kv, &data_key(7), cursor.as_deref(), Some(2)).unwrap();
+        encrypted += report.encrypted;
+        cursor = report.next_cursor;
+        if cursor.is_none() {
+            break;
+        }
+    }
+    assert_eq!(encrypted, kv.list_keys(None, 100).unwrap().len());
+    assert!(encryption::is_encrypted(&kv.get(&default_key(&solana_pubkey)).unwrap().unwrap()));
+    assert_eq!(Encrypted::new(kv.clone(), data_key(7)).get(&default_key(&solana_pubkey)).unwrap(), Some(plaintext));
+    assert_eq!(encryption::encrypt_plaintext_batch(&kv, &data_key(7), None, None).unwrap().encrypted, 0);
+}
+
+#[test]
+fn test_data_key_is_wrapped_by_secret_key() {
+    let kek = BASE64.encode([7; 32]);
+    let secret = [5; 32];
+    let wrapped = encryption::wrap_data_key(&secret, &SecretWrapper(&kek)).unwrap();
+    assert!(!wrapped.contains(&BASE64.encode(secret)));
+
+    let unwrapped = encryption::unwrap_data_key(&wrapped, &SecretWrapper(&format!("{}\n", kek))).unwrap();
+    assert_eq!(unwrapped, BASE64.encode(secret));
+    let stored = DataKey::from_base64(&unwrapped).unwrap().encrypt("key", "value");
+    assert_eq!(DataKey::new(&secret).unwrap().decrypt("key", &stored).unwrap(), "value");
+
+    // Another key-encryption key cannot unwrap it, and a malformed one is no key
+    let other = BASE64.encode([8; 32]);
+    assert_eq!(encryption::unwrap_data_key(&wrapped, &SecretWrapper(&other)).unwrap_err().code(), "CORRUPT_RECORD");
+    assert_eq!(encryption::unwrap_data_key(&wrapped, &SecretWrapper(&BASE64.encode([7; 16]))).unwrap_err().code(), "NOT_CONFIGURED");
+    assert_eq!(encryption::unwrap_data_key(&wrapped, &SecretWrapper("not base64!")).unwrap_err().code(), "NOT_CONFIGURED");
+    assert_eq!(DataKey::new(&[5; 16]).err().unwrap().code(), "INVALID_REQUEST");
+}
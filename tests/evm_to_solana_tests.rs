@@ -0,0 +1,135 @@
+mod common;
+
+use common::{evm_wallet, evm_wallet_address, personal_sign};
+use cubist_wallet_provisioner::auth;
+use cubist_wallet_provisioner::evm_to_solana;
+use cubist_wallet_provisioner::testing::{MockKeyCreator, MockKvStore, TestContext};
+use cubist_wallet_provisioner::{EvmToSolanaProvisionRequest, KvStore, Provisioner};
+use std::sync::{Arc, Mutex};
+
+// =============================================================================
+// EVM → SOLANA PROVISION TESTS
+// =============================================================================
+
+/// Clock of `evm_to_solana_provisioner`
+const EVM_TO_SOLANA_NOW: u64 = 1_700_000_000;
+
+/// EVM → Solana request signed by `wallet`, expiring a minute after `EVM_TO_SOLANA_NOW`
+fn evm_to_solana_request(wallet: &k256::ecdsa::SigningKey, nonce: &str) -> EvmToSolanaProvisionRequest {
+    let evm_address = evm_wallet_address(wallet);
+    let message = auth::evm_to_solana_message(&evm_address, nonce, EVM_TO_SOLANA_NOW + 60);
+    EvmToSolanaProvisionRequest {
+        signature: personal_sign(wallet, &message),
+        evm_address,
+        message,
+        request_id: None,
+    }
+}
+
+fn evm_to_solana_provisioner() -> (Provisioner<MockKvStore, MockKeyCreator>, MockKvStore) {
+    let keys = MockKeyCreator {
+        default_key_counter: Arc::new(Mutex::new(0)),
+        chain_key_counter: Arc::new(Mutex::new(1000)),
+    };
+    let bucket = MockKvStore::new();
+    let provisioner = Provisioner::new(MockKvStore::new(), keys)
+        .with_evm_to_solana(bucket.clone())
+        .with_clock(|| EVM_TO_SOLANA_NOW);
+    (provisioner, bucket)
+}
+
+#[test]
+fn test_evm_to_solana_provision_is_idempotent() {
+    let (provisioner, bucket) = evm_to_solana_provisioner();
+    let user = evm_wallet(7);
+
+    let first = provisioner.handle_evm_to_solana(evm_to_solana_request(&user, "1")).unwrap();
+    let second = provisioner.handle_evm_to_solana(evm_to_solana_request(&user, "2")).unwrap();
+    assert_eq!(first.solana_pubkey, second.solana_pubkey);
+    assert_eq!(first.key_id, second.key_id);
+
+    let evm_address = evm_wallet_address(&user);
+    assert_eq!(provisioner.handle_evm_to_solana_get(&evm_address).unwrap(), Some(first.solana_pubkey.clone()));
+    assert_eq!(
+        evm_to_solana::get_reverse_mapping(&bucket, &first.solana_pubkey).unwrap(),
+        Some(evm_address.clone())
+    );
+
+    // Stored lowercase in its own bucket, nothing in solana_to_evm
+    assert!(bucket.get(&format!("default:{}", evm_address.as_str())).unwrap().is_some());
+    assert!(provisioner.kv().get(&format!("default:{}", evm_address.as_str())).unwrap().is_none());
+}
+
+#[test]
+fn test_evm_to_solana_requires_signature_by_evm_address() {
+    let (provisioner, bucket) = evm_to_solana_provisioner();
+    let user = evm_wallet(7);
+
+    let mut forged = evm_to_solana_request(&user, "1");
+    forged.signature = personal_sign(&evm_wallet(8), &forged.message);
+    let err = provisioner.handle_evm_to_solana(forged).unwrap_err();
+    assert!(err.to_string().contains("Signature verification failed"));
+
+    let mut tampered = evm_to_solana_request(&user, "1");
+    tampered.message.push('!');
+    assert!(provisioner.handle_evm_to_solana(tampered).is_err());
+
+    let mut malformed = evm_to_solana_request(&user, "1");
+    malformed.signature = "0x1234".to_string();
+    let err = provisioner.handle_evm_to_solana(malformed).unwrap_err();
+    assert!(err.to_string().contains("Invalid signature encoding"));
+
+    assert!(bucket.data.lock().unwrap().is_empty());
+}
+
+#[test]
+fn test_evm_to_solana_rejects_signatures_over_other_messages() {
+    let (provisioner, bucket) = evm_to_solana_provisioner();
+    let user = evm_wallet(7);
+
+    // A signature the address made elsewhere, e.g. to log in to another dapp
+    let message = "Sign in to example.com\nnonce: 1".to_string();
+    let login = EvmToSolanaProvisionRequest {
+        evm_address: evm_wallet_address(&user),
+        signature: personal_sign(&user, &message),
+        message,
+        request_id: None,
+    };
+    let err = provisioner.handle_evm_to_solana(login).unwrap_err();
+    assert_eq!(err.code(), "INVALID_REQUEST");
+
+    // The provision message of another address
+    let mut other = evm_to_solana_request(&evm_wallet(8), "1");
+    other.evm_address = evm_wallet_address(&user);
+    other.signature = personal_sign(&user, &other.message);
+    assert_eq!(provisioner.handle_evm_to_solana(other).unwrap_err().code(), "INVALID_REQUEST");
+
+    // Expired
+    let mut expired = evm_to_solana_request(&user, "1");
+    expired.message = auth::evm_to_solana_message(&evm_wallet_address(&user), "1", EVM_TO_SOLANA_NOW - 1);
+    expired.signature = personal_sign(&user, &expired.message);
+    assert_eq!(provisioner.handle_evm_to_solana(expired).unwrap_err().code(), "AUTHORIZATION_EXPIRED");
+
+    assert!(bucket.data.lock().unwrap().is_empty());
+}
+
+#[test]
+fn test_evm_to_solana_nonce_is_single_use() {
+    let (provisioner, _bucket) = evm_to_solana_provisioner();
+    let user = evm_wallet(7);
+
+    let request = evm_to_solana_request(&user, "5");
+    provisioner.handle_evm_to_solana(request.clone()).unwrap();
+    let err = provisioner.handle_evm_to_solana(request).unwrap_err();
+    assert_eq!(err.code(), "NONCE_USED");
+
+    // Nonces are per address
+    provisioner.handle_evm_to_solana(evm_to_solana_request(&evm_wallet(8), "5")).unwrap();
+}
+
+#[test]
+fn test_evm_to_solana_requires_bucket() {
+    let ctx = TestContext::new();
+    let err = ctx.provisioner.handle_evm_to_solana(evm_to_solana_request(&evm_wallet(7), "1")).unwrap_err();
+    assert!(err.to_string().contains("not configured"));
+}
@@ -0,0 +1,128 @@
+mod common;
+
+use common::{approval_provisioner, evm_wallet, fixed_clock_provisioner, link_external_request, named};
+use cubist_wallet_provisioner::expiry::{self, SweepRequest};
+use cubist_wallet_provisioner::kv;
+use cubist_wallet_provisioner::mapping;
+use cubist_wallet_provisioner::signing_gate::SigningRequest;
+use cubist_wallet_provisioner::testing::{chain, evm, provision_request_at, pubkey, signed_at, wallet, MockKvStore};
+use cubist_wallet_provisioner::{LinkExternalRequest, MappingRecord, ProvisionRequest};
+use std::sync::atomic::Ordering;
+
+// =============================================================================
+// TEMPORARY MAPPING TESTS
+// =============================================================================
+
+#[test]
+fn test_temporary_mappings_disappear_when_they_expire() {
+    let (provisioner, now) = approval_provisioner();
+    let alice = wallet(1);
+    let solana_pubkey = pubkey(&alice);
+
+    let result = provisioner.handle(signed_at(&alice, ProvisionRequest { ttl_secs: Some(600), ..provision_request_at(&alice, vec![1], 1000) }, 1000)).unwrap();
+    let found = provisioner.handle_get(&solana_pubkey, &[chain(1)]).unwrap();
+    assert_eq!(found.default_address, Some(result.evm_address.clone()));
+    assert!(found.chain_mappings.contains_key(&chain(1)));
+
+    now.store(1600, Ordering::SeqCst);
+    let found = provisioner.handle_get(&solana_pubkey, &[chain(1)]).unwrap();
+    assert!(found.default_address.is_none());
+    assert!(found.chain_mappings.is_empty());
+    let request = SigningRequest {
+        solana_pubkey: solana_pubkey.clone(),
+        evm_address: result.evm_address.clone(),
+        chain_id: Some(chain(1)),
+        value: None,
+        to: None,
+    };
+    assert_eq!(provisioner.handle_authorize_signing(&request).unwrap_err().code(), "ADDRESS_NOT_MAPPED");
+
+    // This store cannot delete, so nothing can be swept
+    assert_eq!(provisioner.handle_sweep(&named("alice@test"), SweepRequest::default()).unwrap_err().code(), "UNSUPPORTED");
+    assert_eq!(provisioner.handle_sweep(&named("mallory@test"), SweepRequest::default()).unwrap_err().code(), "NOT_ADMIN");
+}
+
+#[test]
+fn test_store_replaces_expired_mappings_without_delete() {
+    let (provisioner, now) = approval_provisioner();
+    let alice = wallet(1);
+    let solana_pubkey = pubkey(&alice);
+    let first = provisioner.handle(signed_at(&alice, ProvisionRequest { ttl_secs: Some(600), ..provision_request_at(&alice, vec![1], 1000) }, 1000)).unwrap();
+
+    // Past the TTL the expired records are still stored (`MockKvStore` cannot
+    // delete), and the next store provisions the user afresh
+    now.store(1600, Ordering::SeqCst);
+    let second = provisioner.handle(provision_request_at(&alice, vec![1, 137], 1600)).unwrap();
+    assert_ne!(second.evm_address, first.evm_address);
+    assert_eq!(second.chain_mappings[&chain(1)], second.evm_address);
+    assert_eq!(second.chain_mappings[&chain(137)], second.evm_address);
+    let found = provisioner.handle_get(&solana_pubkey, &[chain(1), chain(137)]).unwrap();
+    assert_eq!(found.default_address, Some(second.evm_address.clone()));
+    assert_eq!(found.chain_mappings[&chain(1)], second.evm_address);
+
+    // The expired chain mapping was replaced like an update, keeping its history
+    let replaced = kv::get_chain_mapping(provisioner.kv(), &solana_pubkey, &chain(1)).unwrap().unwrap();
+    assert_eq!((replaced.revision, replaced.expires_at), (1, None));
+    let history = provisioner.handle_history(&solana_pubkey, &chain(1)).unwrap();
+    assert_eq!(history.entries[0].address, first.evm_address);
+}
+
+#[test]
+fn test_racing_stores_replace_an_expired_default_once() {
+    let kv = MockKvStore::new();
+    let alice = wallet(1);
+    let solana_pubkey = pubkey(&alice);
+    let temporary = signed_at(&alice, ProvisionRequest { ttl_secs: Some(600), ..provision_request_at(&alice, vec![1], 1000) }, 1000);
+    let expired = evm("0x3333333333333333333333333333333333333333");
+    mapping::store(&kv, &temporary, 1000, || Ok(MappingRecord::new(&expired, Some("Key#3"), solana_pubkey.as_str(), 1000))).unwrap();
+
+    // Both stores read the expired default before either replaces it: the
+    // second loses the renewal claim and answers with the first's address
+    let expired_default = kv::get_default_mapping(&kv, &solana_pubkey).unwrap().unwrap();
+    let winner = evm("0x4444444444444444444444444444444444444444");
+    let loser = evm("0x5555555555555555555555555555555555555555");
+    let stored = kv::renew_default_mapping(&kv, &solana_pubkey, &expired_default, &MappingRecord::new(&winner, None, solana_pubkey.as_str(), 1600)).unwrap();
+    assert_eq!(stored.address, winner);
+    let stored = kv::renew_default_mapping(&kv, &solana_pubkey, &expired_default, &MappingRecord::new(&loser, None, solana_pubkey.as_str(), 1600)).unwrap();
+    assert_eq!(stored.address, winner);
+    assert_eq!(kv::get_default_mapping(&kv, &solana_pubkey).unwrap().unwrap().address, winner);
+
+    let req = provision_request_at(&alice, vec![1], 1600);
+    let response = mapping::store(&kv, &req, 1600, || Ok(MappingRecord::new(&loser, None, solana_pubkey.as_str(), 1600))).unwrap();
+    assert_eq!(response.evm_address, winner);
+    assert_eq!(response.chain_mappings[&chain(1)], winner);
+}
+
+#[test]
+fn test_temporary_external_link_falls_back_to_default() {
+    let (provisioner, now) = approval_provisioner();
+    let alice = wallet(1);
+    let solana_pubkey = pubkey(&alice);
+    let custodial = provisioner.handle(provision_request_at(&alice, vec![1], 1000)).unwrap();
+
+    let req = LinkExternalRequest { ttl_secs: Some(100), ..link_external_request(&alice, &evm_wallet(9), vec![137], "1") };
+    let linked = provisioner.handle_link_external(req).unwrap();
+    assert_eq!(provisioner.handle_get(&solana_pubkey, &[chain(137)]).unwrap().chain_mappings[&chain(137)], linked.evm_address);
+
+    now.store(1100, Ordering::SeqCst);
+    let found = provisioner.handle_get(&solana_pubkey, &[chain(1), chain(137)]).unwrap();
+    assert_eq!(found.chain_mappings[&chain(137)], custodial.evm_address);
+    assert!(found.chain_inherited[&chain(137)]);
+    assert!(found.external_addresses.is_empty());
+}
+
+#[test]
+fn test_temporary_mapping_ttl_is_checked() {
+    let provisioner = fixed_clock_provisioner();
+    let alice = wallet(1);
+
+    let err = provisioner.handle(signed_at(&alice, ProvisionRequest { ttl_secs: Some(0), ..provision_request_at(&alice, vec![1], 1000) }, 1000)).unwrap_err();
+    assert_eq!(err.code(), "INVALID_REQUEST");
+    let ttl_secs = Some(expiry::MAX_MAPPING_TTL_SECS + 1);
+    assert_eq!(provisioner.handle(signed_at(&alice, ProvisionRequest { ttl_secs, ..provision_request_at(&alice, vec![1], 1000) }, 1000)).unwrap_err().code(), "INVALID_REQUEST");
+
+    // Only the primary address can be temporary
+    let req = signed_at(&alice, ProvisionRequest { label: Some("trading".to_string()), ttl_secs: Some(600), ..provision_request_at(&alice, vec![1], 1000) }, 1000);
+    assert_eq!(provisioner.handle(req).unwrap_err().to_string(), "Invalid request: ttl_secs applies to the primary address only");
+    assert!(provisioner.handle_get(&pubkey(&alice), &[]).unwrap().default_address.is_none());
+}
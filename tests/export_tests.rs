@@ -0,0 +1,152 @@
+mod common;
+
+use common::{approval_provisioner, entry, import_request, labeled_request, named};
+use cubist_wallet_provisioner::export::{ExportEntry, ExportRequest};
+use cubist_wallet_provisioner::import::ImportStrategy;
+use cubist_wallet_provisioner::kv::default_key;
+use cubist_wallet_provisioner::testing::{
+    admin, chain, provision_request, provision_request_at, pubkey, update_request, wallet, MockKeyCreator, MockKvStore, TestContext,
+};
+use cubist_wallet_provisioner::{KvStore, Provisioner};
+use std::sync::{Arc, Mutex};
+
+// =============================================================================
+// EXPORT TESTS
+// =============================================================================
+
+#[test]
+fn test_export_pages_restore_the_bucket() {
+    let ctx = TestContext::new();
+    for seed in 1..=3 {
+        ctx.provisioner.handle(provision_request(&wallet(seed), vec![1, 137])).unwrap();
+    }
+    ctx.provisioner.handle(labeled_request(&wallet(1), vec![1], "cold")).unwrap();
+    ctx.provisioner.handle_update_mapping(&admin(), update_request(&pubkey(&wallet(2)), 137)).unwrap();
+
+    let mut entries: Vec<ExportEntry> = Vec::new();
+    let mut cursor = None;
+    loop {
+        let req = ExportRequest { cursor, limit: Some(5), request_id: None };
+        let page = ctx.provisioner.handle_export(&admin(), req).unwrap();
+        assert!(page.entries.len() <= 5);
+        entries.extend(page.entries);
+        cursor = page.next_cursor;
+        if cursor.is_none() {
+            break;
+        }
+    }
+
+    // Every key once, in order, with its stored value
+    let keys: Vec<&str> = entries.iter().map(|entry| entry.key.as_str()).collect();
+    assert!(keys.windows(2).all(|pair| pair[0] < pair[1]));
+    for entry in &entries {
+        assert_eq!(ctx.kv.get(&entry.key).unwrap().as_deref(), Some(entry.value.as_str()), "{}", entry.key);
+    }
+    assert!(keys.contains(&default_key(&pubkey(&wallet(3))).as_str()));
+
+    let keys = MockKeyCreator {
+        default_key_counter: Arc::new(Mutex::new(0)),
+        chain_key_counter: Arc::new(Mutex::new(1000)),
+    };
+    let restored = Provisioner::new(MockKvStore::new(), keys);
+    for entry in &entries {
+        restored.kv().set(&entry.key, &entry.value).unwrap();
+    }
+    for seed in 1..=3 {
+        let solana_pubkey = pubkey(&wallet(seed));
+        let chains = [chain(1), chain(137)];
+        assert_eq!(
+            serde_json::to_value(restored.handle_get(&solana_pubkey, &chains).unwrap()).unwrap(),
+            serde_json::to_value(ctx.provisioner.handle_get(&solana_pubkey, &chains).unwrap()).unwrap()
+        );
+    }
+}
+
+#[test]
+fn test_export_requires_admin() {
+    let (provisioner, _) = approval_provisioner();
+    provisioner.handle(provision_request_at(&wallet(1), vec![1], 1000)).unwrap();
+
+    let err = provisioner
+        .handle_export(&named("mallory@test"), ExportRequest::default())
+        .unwrap_err();
+    assert_eq!(err.code(), "NOT_ADMIN");
+
+    let page = provisioner
+        .handle_export(&named("alice@test"), ExportRequest::default())
+        .unwrap();
+    assert!(page.entries.iter().any(|entry| entry.key == default_key(&pubkey(&wallet(1)))));
+    assert_eq!(page.next_cursor, None);
+}
+
+// =============================================================================
+// IMPORT TESTS
+// =============================================================================
+
+#[test]
+fn test_import_restores_an_export() {
+    let source = TestContext::new();
+    source.provisioner.handle(provision_request(&wallet(1), vec![1, 137])).unwrap();
+    let entries = source.provisioner.handle_export(&admin(), ExportRequest::default()).unwrap().entries;
+
+    let target = TestContext::new();
+    let dry = target.provisioner.handle_import(&admin(), import_request(entries.clone(), ImportStrategy::FailOnConflict, true)).unwrap();
+    assert_eq!(dry.created.len(), entries.len());
+    assert!(target.kv.list_keys(None, 10).unwrap().is_empty());
+
+    let report = target.provisioner.handle_import(&admin(), import_request(entries.clone(), ImportStrategy::FailOnConflict, false)).unwrap();
+    assert_eq!(report.created, dry.created);
+    let solana_pubkey = pubkey(&wallet(1));
+    assert_eq!(
+        serde_json::to_value(target.provisioner.handle_get(&solana_pubkey, &[chain(1), chain(137)]).unwrap()).unwrap(),
+        serde_json::to_value(source.provisioner.handle_get(&solana_pubkey, &[chain(1), chain(137)]).unwrap()).unwrap()
+    );
+
+    // Re-running writes nothing; only the audit head moved (by the import's own record)
+    let rerun = target.provisioner.handle_import(&admin(), import_request(entries.clone(), ImportStrategy::SkipExisting, false)).unwrap();
+    assert!(rerun.created.is_empty());
+    assert_eq!(rerun.conflicts, vec!["audit:head".to_string()]);
+    assert_eq!(rerun.unchanged, entries.len() - 1);
+}
+
+#[test]
+fn test_import_strategies_resolve_conflicts() {
+    let ctx = TestContext::new();
+    ctx.kv.set("chain:eip155:5", "stored").unwrap();
+    let entries = vec![entry("chain:eip155:5", "imported"), entry("chain:eip155:10", "new")];
+
+    let err = ctx.provisioner.handle_import(&admin(), import_request(entries.clone(), ImportStrategy::FailOnConflict, false)).unwrap_err();
+    assert_eq!(err.code(), "IMPORT_CONFLICT");
+    assert_eq!(ctx.kv.get("chain:eip155:10").unwrap(), None);
+
+    // A dry run lists the conflicts the real run would fail on
+    let dry = ctx.provisioner.handle_import(&admin(), import_request(entries.clone(), ImportStrategy::FailOnConflict, true)).unwrap();
+    assert_eq!(dry.conflicts, vec!["chain:eip155:5".to_string()]);
+
+    let skipped = ctx.provisioner.handle_import(&admin(), import_request(entries.clone(), ImportStrategy::SkipExisting, false)).unwrap();
+    assert_eq!((skipped.created.len(), skipped.conflicts.len()), (1, 1));
+    assert_eq!(ctx.kv.get("chain:eip155:5").unwrap().as_deref(), Some("stored"));
+    assert_eq!(ctx.kv.get("chain:eip155:10").unwrap().as_deref(), Some("new"));
+
+    let overwritten = ctx.provisioner.handle_import(&admin(), import_request(entries, ImportStrategy::Overwrite, false)).unwrap();
+    assert_eq!((overwritten.unchanged, overwritten.conflicts.len()), (1, 1));
+    assert_eq!(ctx.kv.get("chain:eip155:5").unwrap().as_deref(), Some("imported"));
+}
+
+#[test]
+fn test_import_rejects_malformed_batches() {
+    let ctx = TestContext::new();
+    let solana_pubkey = pubkey(&wallet(1));
+    let malformed = vec![entry("other", "ok"), entry(&default_key(&solana_pubkey), "not an address")];
+    let err = ctx.provisioner.handle_import(&admin(), import_request(malformed, ImportStrategy::Overwrite, false)).unwrap_err();
+    assert_eq!(err.code(), "INVALID_EVM_ADDRESS");
+    assert_eq!(ctx.kv.get("other").unwrap(), None);
+
+    let duplicated = vec![entry("other", "a"), entry("other", "b")];
+    let err = ctx.provisioner.handle_import(&admin(), import_request(duplicated, ImportStrategy::Overwrite, false)).unwrap_err();
+    assert!(err.to_string().contains("duplicate key other"));
+
+    let (provisioner, _) = approval_provisioner();
+    let req = import_request(vec![], ImportStrategy::Overwrite, false);
+    assert_eq!(provisioner.handle_import(&named("mallory@test"), req).unwrap_err().code(), "NOT_ADMIN");
+}
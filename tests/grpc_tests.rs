@@ -0,0 +1,170 @@
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use cubist_wallet_provisioner::error::Result;
+use cubist_wallet_provisioner::grpc::pb::provisioner_client::ProvisionerClient;
+use cubist_wallet_provisioner::grpc::pb::{self, provision_batch_item::Outcome};
+use cubist_wallet_provisioner::grpc::{GrpcProvisioner, ERROR_CODE_METADATA, REQUESTER_IDENTITY_METADATA, RETRYABLE_METADATA};
+use cubist_wallet_provisioner::testing::{admins, store_message, TEST_ADMIN};
+use cubist_wallet_provisioner::{ChainId, CreatedKey, KeyCreator, KvStore, Provisioner, SolanaPubkey};
+use ed25519_dalek::{Signer, SigningKey};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use tonic::transport::server::TcpIncoming;
+use tonic::transport::{Channel, Server};
+use tonic::{Code, Request};
+
+/// KV store over a shared map
+#[derive(Clone, Default)]
+struct MapKv(Arc<Mutex<HashMap<String, String>>>);
+
+impl KvStore for MapKv {
+    fn get(&self, key: &str) -> Result<Option<String>> {
+        Ok(self.0.lock().unwrap().get(key).cloned())
+    }
+
+    fn set_if_absent(&self, key: &str, value: &str) -> Result<bool> {
+        let mut data = self.0.lock().unwrap();
+        if data.contains_key(key) {
+            return Ok(false);
+        }
+        data.insert(key.to_string(), value.to_string());
+        Ok(true)
+    }
+
+    fn set(&self, key: &str, value: &str) -> Result<()> {
+        self.0.lock().unwrap().insert(key.to_string(), value.to_string());
+        Ok(())
+    }
+}
+
+/// Key creator handing out sequential addresses
+#[derive(Default)]
+struct SequentialKeys(AtomicUsize);
+
+impl SequentialKeys {
+    fn next(&self) -> Result<CreatedKey> {
+        let address = format!("0x{:040x}", self.0.fetch_add(1, Ordering::SeqCst) + 1);
+        Ok(CreatedKey { key_id: format!("Key#{}", address), address, policies: Vec::new() })
+    }
+}
+
+impl KeyCreator for SequentialKeys {
+    fn create_evm_key(&self, _solana_pubkey: &str) -> Result<CreatedKey> {
+        self.next()
+    }
+
+    fn create_evm_key_for_chain(&self, _solana_pubkey: &str, _chain_id: &ChainId) -> Result<CreatedKey> {
+        self.next()
+    }
+
+    fn create_labeled_evm_key(&self, _solana_pubkey: &str, _label: &str, _chain_id: Option<&ChainId>) -> Result<CreatedKey> {
+        self.next()
+    }
+}
+
+/// Signed provision request for the wallet with `seed`
+fn provision_request(seed: u8, chain_ids: &[&str]) -> pb::ProvisionRequest {
+    let wallet = SigningKey::from_bytes(&[seed; 32]);
+    let solana_pubkey = bs58::encode(wallet.verifying_key().to_bytes()).into_string();
+    // Signed over the chain ids as resolved; an unknown one is left out for the server to refuse
+    let resolved: Vec<ChainId> = chain_ids.iter().filter_map(|chain_id| ChainId::resolve(chain_id).ok()).collect();
+    let message = store_message(&SolanaPubkey::parse(&solana_pubkey).unwrap(), &resolved);
+    pb::ProvisionRequest {
+        solana_pubkey,
+        chain_ids: chain_ids.iter().map(|chain_id| chain_id.to_string()).collect(),
+        signature: BASE64.encode(wallet.sign(message.as_bytes()).to_bytes()),
+        message,
+        ..Default::default()
+    }
+}
+
+/// `message` as the authenticating proxy forwards it for the test admin
+fn as_admin<T>(message: T) -> Request<T> {
+    let mut request = Request::new(message);
+    request.metadata_mut().insert(REQUESTER_IDENTITY_METADATA, TEST_ADMIN.parse().unwrap());
+    request
+}
+
+/// Serve a fresh provisioner on an ephemeral port and run `test` with a client of it
+fn with_client<F: std::future::Future<Output = ()>>(test: impl FnOnce(ProvisionerClient<Channel>) -> F) {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    runtime.block_on(async {
+        let incoming = TcpIncoming::bind("127.0.0.1:0".parse().unwrap()).unwrap();
+        let addr = incoming.local_addr().unwrap();
+        let provisioner = Provisioner::new(MapKv::default(), SequentialKeys::default()).with_admins(admins()).with_single_step_updates();
+        let service = GrpcProvisioner::new(provisioner).into_server();
+        tokio::spawn(Server::builder().add_service(service).serve_with_incoming(incoming));
+
+        let client = ProvisionerClient::connect(format!("http://{}", addr)).await.unwrap();
+        test(client).await;
+    });
+}
+
+#[test]
+fn test_grpc_provision_update_and_get() {
+    with_client(|mut client| async move {
+        let req = provision_request(1, &["eip155:1", "137"]);
+        let solana_pubkey = req.solana_pubkey.clone();
+
+        let stored = client.provision(req).await.unwrap().into_inner();
+        assert_eq!(stored.chain_mappings["eip155:1"], stored.evm_address);
+        assert_eq!(stored.chain_mappings["eip155:137"], stored.evm_address);
+        assert_eq!(client.provision(provision_request(1, &["eip155:1", "137"])).await.unwrap().into_inner(), stored);
+
+        let update = pb::UpdateMappingRequest {
+            solana_pubkey: solana_pubkey.clone(),
+            chain_id: "eip155:137".to_string(),
+            ..Default::default()
+        };
+        let updated = client.update(as_admin(update)).await.unwrap().into_inner();
+        assert_ne!(updated.new_evm_address, stored.evm_address);
+        assert_eq!(updated.chain_id, "eip155:137");
+
+        let get = pb::GetMappingsRequest { solana_pubkey, chain_ids: vec!["eip155:1".to_string(), "eip155:137".to_string()] };
+        let mappings = client.get(get).await.unwrap().into_inner();
+        assert_eq!(mappings.default_address.as_deref(), Some(stored.evm_address.as_str()));
+        assert_eq!(mappings.chain_mappings["eip155:1"], stored.evm_address);
+        assert_eq!(mappings.chain_mappings["eip155:137"], updated.new_evm_address);
+        assert_eq!(mappings.chain_versions["eip155:137"], updated.version);
+    });
+}
+
+#[test]
+fn test_grpc_batch_reports_failures_per_entry() {
+    with_client(|mut client| async move {
+        let mut forged = provision_request(3, &["eip155:1"]);
+        forged.signature = provision_request(4, &["eip155:1"]).signature;
+        let batch = pb::ProvisionBatchRequest { requests: vec![provision_request(2, &["eip155:1"]), forged], request_id: None };
+
+        let response = client.batch_provision(batch).await.unwrap().into_inner();
+        assert_eq!((response.succeeded, response.failed), (1, 1));
+        assert!(matches!(response.results[0].outcome, Some(Outcome::Result(_))));
+        let Some(Outcome::Error(error)) = &response.results[1].outcome else { panic!("expected an error") };
+        assert_eq!(error.code, "SIGNATURE_MISMATCH");
+        assert!(!error.retryable);
+    });
+}
+
+#[test]
+fn test_grpc_errors_carry_status_and_code() {
+    with_client(|mut client| async move {
+        let invalid = client.provision(provision_request(5, &["not-a-chain"])).await.unwrap_err();
+        assert_eq!(invalid.code(), Code::InvalidArgument);
+        assert_eq!(invalid.metadata().get(ERROR_CODE_METADATA).unwrap(), "INVALID_CHAIN_ID");
+
+        let update = pb::UpdateMappingRequest {
+            solana_pubkey: provision_request(6, &[]).solana_pubkey,
+            chain_id: "eip155:1".to_string(),
+            ..Default::default()
+        };
+        let missing = client.update(as_admin(update.clone())).await.unwrap_err();
+        assert_eq!(missing.code(), Code::NotFound);
+        assert_eq!(missing.metadata().get(ERROR_CODE_METADATA).unwrap(), "NOT_PROVISIONED");
+        assert_eq!(missing.metadata().get(RETRYABLE_METADATA).unwrap(), "false");
+
+        // Without a requester from the proxy, updates are refused
+        let anonymous = client.update(update).await.unwrap_err();
+        assert_eq!(anonymous.code(), Code::PermissionDenied);
+        assert_eq!(anonymous.metadata().get(ERROR_CODE_METADATA).unwrap(), "NOT_ADMIN");
+    });
+}
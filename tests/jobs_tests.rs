@@ -0,0 +1,131 @@
+mod common;
+
+use common::FlakyKvStore;
+use cubist_wallet_provisioner::error::{ProvisionError, Result};
+use cubist_wallet_provisioner::jobs::{self, JobStatus};
+use cubist_wallet_provisioner::testing::{
+    admin, chain, provision_request, pubkey, set_chain_request, wallet, MockKeyCreator, MockKvStore, TestContext,
+};
+use cubist_wallet_provisioner::{ChainId, CreatedKey, KeyCreator, ProvisionRequest, Provisioner};
+use std::sync::{Arc, Mutex};
+
+// =============================================================================
+// PROVISIONING JOB TESTS
+// =============================================================================
+
+/// Key creator whose CubeSigner is down
+struct UnavailableKeys;
+
+impl UnavailableKeys {
+    fn fail() -> Result<CreatedKey> {
+        Err(ProvisionError::KeyCreationFailed { message: "503 Service Unavailable".to_string(), retryable: true })
+    }
+}
+
+impl KeyCreator for UnavailableKeys {
+    fn create_evm_key(&self, _solana_pubkey: &str) -> Result<CreatedKey> {
+        Self::fail()
+    }
+
+    fn create_evm_key_for_chain(&self, _solana_pubkey: &str, _chain_id: &ChainId) -> Result<CreatedKey> {
+        Self::fail()
+    }
+
+    fn create_labeled_evm_key(&self, _solana_pubkey: &str, _label: &str, _chain_id: Option<&ChainId>) -> Result<CreatedKey> {
+        Self::fail()
+    }
+}
+
+#[test]
+fn test_provision_job_runs_to_complete() {
+    let ctx = TestContext::new();
+    let alice = wallet(1);
+    let solana_pubkey = pubkey(&alice);
+
+    let job = ctx.provisioner.handle_provision_async(provision_request(&alice, vec![1, 137])).unwrap();
+    assert_eq!((job.id, job.status), (1, JobStatus::Pending));
+    assert!(ctx.get_default_evm_address(&solana_pubkey).unwrap().is_none());
+
+    let job = ctx.provisioner.handle_run_job(&solana_pubkey, 1).unwrap();
+    assert_eq!(job.status, JobStatus::Complete);
+    assert!(job.error.is_none());
+    let response = job.response.unwrap();
+    assert_eq!(Some(&response.evm_address), job.evm_address.as_ref());
+    assert_eq!(response.chain_mappings.len(), 2);
+    assert_eq!(ctx.get_existing_mapping(&solana_pubkey, 137).unwrap(), Some(response.evm_address));
+    assert_eq!(ctx.provisioner.handle_job_status(&solana_pubkey, 1).unwrap().status, JobStatus::Complete);
+
+    // A user who already has a key gets none from the job
+    let job = ctx.provisioner.handle_provision_async(provision_request(&alice, vec![42161])).unwrap();
+    assert_eq!(job.id, 2);
+    let job = ctx.provisioner.handle_run_job(&solana_pubkey, 2).unwrap();
+    assert_eq!(job.status, JobStatus::Complete);
+    assert!(job.evm_address.is_none());
+    assert_eq!(*ctx.default_key_counter.lock().unwrap(), 1);
+}
+
+#[test]
+fn test_provision_job_resumes_with_the_key_it_created() {
+    let kv = MockKvStore::new();
+    let default_key_counter = Arc::new(Mutex::new(0));
+    let keys = || MockKeyCreator {
+        default_key_counter: Arc::clone(&default_key_counter),
+        chain_key_counter: Arc::new(Mutex::new(1000)),
+    };
+    // Store nonce, job claim, job head and the `key_created` step; then the KV fails
+    let flaky = FlakyKvStore { inner: kv.clone(), writes_left: Mutex::new(4) };
+    let alice = wallet(1);
+    let solana_pubkey = pubkey(&alice);
+    let provisioner = Provisioner::new(flaky, keys());
+    provisioner.handle_provision_async(provision_request(&alice, vec![1])).unwrap();
+    assert_eq!(provisioner.handle_run_job(&solana_pubkey, 1).unwrap_err().code(), "KV_ERROR");
+    let job = jobs::get(&kv, &solana_pubkey, 1).unwrap().unwrap();
+    assert_eq!(job.status, JobStatus::KeyCreated);
+
+    let job = Provisioner::new(kv.clone(), keys()).handle_run_job(&solana_pubkey, 1).unwrap();
+    assert_eq!(job.status, JobStatus::Complete);
+    assert_eq!(job.response.unwrap().evm_address, job.evm_address.unwrap());
+    assert_eq!(*default_key_counter.lock().unwrap(), 1);
+}
+
+#[test]
+fn test_provision_job_records_retryable_errors_and_fails_on_others() {
+    let kv = MockKvStore::new();
+    let alice = wallet(1);
+    let solana_pubkey = pubkey(&alice);
+    let provisioner = Provisioner::new(kv.clone(), UnavailableKeys);
+    provisioner.handle_provision_async(provision_request(&alice, vec![1])).unwrap();
+
+    let job = provisioner.handle_run_job(&solana_pubkey, 1).unwrap();
+    assert_eq!(job.status, JobStatus::Pending);
+    let error = job.error.unwrap();
+    assert_eq!((error.code.as_str(), error.retryable), ("KEY_CREATION_FAILED", true));
+
+    let ctx = TestContext::new();
+    let job = Provisioner::new(kv.clone(), MockKeyCreator {
+        default_key_counter: Arc::clone(&ctx.default_key_counter),
+        chain_key_counter: Arc::new(Mutex::new(1000)),
+    })
+    .handle_run_job(&solana_pubkey, 1)
+    .unwrap();
+    assert_eq!(job.status, JobStatus::Complete);
+    assert!(job.error.is_none());
+
+    // A chain disabled since submitting fails the job for good
+    let req = provision_request(&alice, vec![137]);
+    ctx.provisioner.handle_provision_async(req).unwrap();
+    ctx.provisioner.handle_set_chain(&admin(), set_chain_request(&chain(137), false, None)).unwrap();
+    let job = ctx.provisioner.handle_run_job(&solana_pubkey, 1).unwrap();
+    assert_eq!(job.status, JobStatus::Failed);
+    assert_eq!(job.error.unwrap().code, "CHAIN_DISABLED");
+}
+
+#[test]
+fn test_provision_job_submit_checks_ownership() {
+    let ctx = TestContext::new();
+    let alice = wallet(1);
+    let forged = ProvisionRequest { signature: provision_request(&wallet(2), vec![1]).signature, ..provision_request(&alice, vec![1]) };
+    assert_eq!(ctx.provisioner.handle_provision_async(forged).unwrap_err().code(), "SIGNATURE_MISMATCH");
+    let err = ctx.provisioner.handle_job_status(&pubkey(&wallet(2)), 1).unwrap_err();
+    assert_eq!(err, ProvisionError::JobNotFound { id: 1, solana_pubkey: pubkey(&wallet(2)).to_string() });
+}
@@ -0,0 +1,195 @@
+mod common;
+
+use common::labeled_request;
+use cubist_wallet_provisioner::error::Result;
+use cubist_wallet_provisioner::kv;
+use cubist_wallet_provisioner::testing::{
+    admin, admins, chain, provision_request, pubkey, signed, update_request, wallet, MockKeyCreator, MockKvStore, TestContext,
+};
+use cubist_wallet_provisioner::{ChainId, CreatedKey, KeyClass, KeyCreator, KeyType, ProvisionRequest, Provisioner};
+use std::sync::{Arc, Mutex};
+
+// =============================================================================
+// KEY TYPE TESTS
+// =============================================================================
+
+#[test]
+fn test_key_type_is_recorded_on_new_mappings() {
+    let ctx = TestContext::new();
+    let alice = wallet(1);
+    let solana_pubkey = pubkey(&alice);
+    let req = signed(&alice, ProvisionRequest { key_type: KeyType::SecpAvaAddr, ..provision_request(&alice, vec![1, 137]) });
+    ctx.handle(req).unwrap();
+
+    let default = kv::get_default_mapping(&ctx.kv, &solana_pubkey).unwrap().unwrap();
+    assert_eq!(default.key_type, KeyType::SecpAvaAddr);
+    assert!(default.encode().contains(r#""key_type":"SecpAvaAddr""#));
+    for chain_id in [chain(1), chain(137)] {
+        assert_eq!(kv::get_chain_mapping(&ctx.kv, &solana_pubkey, &chain_id).unwrap().unwrap().key_type, KeyType::SecpAvaAddr);
+    }
+
+    // The key exists now; a later request's key type does not change it
+    ctx.handle(provision_request(&alice, vec![10])).unwrap();
+    assert_eq!(kv::get_chain_mapping(&ctx.kv, &solana_pubkey, &chain(10)).unwrap().unwrap().key_type, KeyType::SecpAvaAddr);
+
+    // Records of EVM keys are unchanged
+    let bob = wallet(2);
+    ctx.handle(provision_request(&bob, vec![1])).unwrap();
+    let record = kv::get_default_mapping(&ctx.kv, &pubkey(&bob)).unwrap().unwrap();
+    assert_eq!(record.key_type, KeyType::SecpEthAddr);
+    assert!(!record.encode().contains("key_type"));
+}
+
+#[test]
+fn test_unmappable_key_types_are_rejected_before_key_creation() {
+    let ctx = TestContext::new();
+    let alice = wallet(1);
+
+    let stellar = signed(&alice, ProvisionRequest { key_type: KeyType::Ed25519StellarAddr, ..provision_request(&alice, vec![1]) });
+    assert_eq!(ctx.handle(stellar).unwrap_err().code(), "INVALID_REQUEST");
+    let labeled = signed(&alice, ProvisionRequest { key_type: KeyType::SecpBtc, ..labeled_request(&alice, vec![1], "trading") });
+    assert_eq!(ctx.handle(labeled).unwrap_err().code(), "INVALID_REQUEST");
+    assert_eq!(*ctx.default_key_counter.lock().unwrap(), 0);
+}
+
+#[test]
+fn test_key_type_accepts_cubesigner_and_short_names() {
+    assert_eq!(KeyType::parse("SecpAvaAddr").unwrap(), KeyType::SecpAvaAddr);
+    assert_eq!(KeyType::parse("Ava").unwrap(), KeyType::SecpAvaAddr);
+    assert_eq!(KeyType::parse("Secp256k1").unwrap(), KeyType::SecpBtc);
+    assert_eq!(KeyType::parse("Stellar").unwrap(), KeyType::Ed25519StellarAddr);
+    assert_eq!(KeyType::parse("Rsa").unwrap_err().code(), "INVALID_REQUEST");
+
+    let json = format!(r#"{{"solana_pubkey": "{}", "message": "m", "signature": "s", "key_type": "Ava"}}"#, pubkey(&wallet(1)));
+    let req: ProvisionRequest = serde_json::from_str(&json).unwrap();
+    assert_eq!(req.key_type, KeyType::SecpAvaAddr);
+}
+
+// =============================================================================
+// KEY CLASS TESTS
+// =============================================================================
+
+#[test]
+fn test_key_class_is_recorded_on_new_mappings() {
+    let ctx = TestContext::new();
+    let alice = wallet(1);
+    let solana_pubkey = pubkey(&alice);
+    let mpc = KeyClass::Mpc { threshold: 2, participants: 3 };
+    ctx.handle(signed(&alice, ProvisionRequest { key_class: mpc, ..provision_request(&alice, vec![1]) })).unwrap();
+
+    let default = kv::get_default_mapping(&ctx.kv, &solana_pubkey).unwrap().unwrap();
+    assert_eq!(default.key_class, mpc);
+    assert!(default.encode().contains(r#""key_class":{"class":"mpc","threshold":2,"participants":3}"#));
+    assert_eq!(kv::get_chain_mapping(&ctx.kv, &solana_pubkey, &chain(1)).unwrap().unwrap().key_class, mpc);
+
+    // Chains added later inherit the class of the existing key
+    ctx.handle(provision_request(&alice, vec![137])).unwrap();
+    assert_eq!(kv::get_chain_mapping(&ctx.kv, &solana_pubkey, &chain(137)).unwrap().unwrap().key_class, mpc);
+
+    // Records of standard keys are unchanged
+    let bob = wallet(2);
+    ctx.handle(provision_request(&bob, vec![1])).unwrap();
+    let record = kv::get_default_mapping(&ctx.kv, &pubkey(&bob)).unwrap().unwrap();
+    assert_eq!(record.key_class, KeyClass::Standard);
+    assert!(!record.encode().contains("key_class"));
+}
+
+#[test]
+fn test_invalid_mpc_quorums_are_rejected_before_key_creation() {
+    let ctx = TestContext::new();
+    let alice = wallet(1);
+
+    for (threshold, participants) in [(1, 3), (4, 3), (2, 17)] {
+        let req = signed(&alice, ProvisionRequest { key_class: KeyClass::Mpc { threshold, participants }, ..provision_request(&alice, vec![1]) });
+        assert_eq!(ctx.handle(req).unwrap_err().code(), "INVALID_REQUEST");
+    }
+    let labeled = signed(&alice, ProvisionRequest {
+        key_class: KeyClass::Mpc { threshold: 2, participants: 3 },
+        ..labeled_request(&alice, vec![1], "trading")
+    });
+    assert_eq!(ctx.handle(labeled).unwrap_err().code(), "INVALID_REQUEST");
+    assert_eq!(*ctx.default_key_counter.lock().unwrap(), 0);
+}
+
+#[test]
+fn test_key_class_deserializes_from_tagged_json() {
+    let json = format!(
+        r#"{{"solana_pubkey": "{}", "message": "m", "signature": "s", "key_class": {{"class": "mpc", "threshold": 3, "participants": 5}}}}"#,
+        pubkey(&wallet(1))
+    );
+    let req: ProvisionRequest = serde_json::from_str(&json).unwrap();
+    assert_eq!(req.key_class, KeyClass::Mpc { threshold: 3, participants: 5 });
+
+    let json = format!(r#"{{"solana_pubkey": "{}", "message": "m", "signature": "s"}}"#, pubkey(&wallet(1)));
+    let req: ProvisionRequest = serde_json::from_str(&json).unwrap();
+    assert_eq!(req.key_class, KeyClass::Standard);
+}
+
+// =============================================================================
+// KEY POLICY TESTS
+// =============================================================================
+
+/// Key creator that reports `policies` as attached to every key it creates
+struct RestrictedKeys {
+    keys: MockKeyCreator,
+    policies: Vec<String>,
+}
+
+impl RestrictedKeys {
+    fn restrict(&self, key: Result<CreatedKey>) -> Result<CreatedKey> {
+        Ok(CreatedKey { policies: self.policies.clone(), ..key? })
+    }
+}
+
+impl KeyCreator for RestrictedKeys {
+    fn create_evm_key(&self, solana_pubkey: &str) -> Result<CreatedKey> {
+        self.restrict(self.keys.create_evm_key(solana_pubkey))
+    }
+
+    fn create_evm_key_for_chain(&self, solana_pubkey: &str, chain_id: &ChainId) -> Result<CreatedKey> {
+        self.restrict(self.keys.create_evm_key_for_chain(solana_pubkey, chain_id))
+    }
+
+    fn create_labeled_evm_key(&self, solana_pubkey: &str, label: &str, chain_id: Option<&ChainId>) -> Result<CreatedKey> {
+        self.restrict(self.keys.create_labeled_evm_key(solana_pubkey, label, chain_id))
+    }
+}
+
+#[test]
+fn test_attached_policies_are_recorded_on_mappings() {
+    let kv = MockKvStore::new();
+    let keys = RestrictedKeys {
+        keys: MockKeyCreator {
+            default_key_counter: Arc::new(Mutex::new(0)),
+            chain_key_counter: Arc::new(Mutex::new(1000)),
+        },
+        policies: vec!["NamedPolicy#allowlist".to_string(), "NamedPolicy#max-value".to_string()],
+    };
+    let provisioner = Provisioner::new(kv.clone(), keys).with_admins(admins()).with_single_step_updates();
+    let alice = wallet(1);
+    let solana_pubkey = pubkey(&alice);
+    let policies = vec!["NamedPolicy#allowlist".to_string(), "NamedPolicy#max-value".to_string()];
+
+    provisioner.handle(provision_request(&alice, vec![1])).unwrap();
+    let default = kv::get_default_mapping(&kv, &solana_pubkey).unwrap().unwrap();
+    assert_eq!(default.policies, policies);
+    assert!(default.encode().contains(r#""policies":["NamedPolicy#allowlist","NamedPolicy#max-value"]"#));
+    assert_eq!(kv::get_chain_mapping(&kv, &solana_pubkey, &chain(1)).unwrap().unwrap().policies, policies);
+
+    // Chains added later inherit them with the key; rotated keys carry their own
+    provisioner.handle(provision_request(&alice, vec![137])).unwrap();
+    assert_eq!(kv::get_chain_mapping(&kv, &solana_pubkey, &chain(137)).unwrap().unwrap().policies, policies);
+    provisioner.handle_update_mapping(&admin(), update_request(&solana_pubkey, 10)).unwrap();
+    assert_eq!(kv::get_chain_mapping(&kv, &solana_pubkey, &chain(10)).unwrap().unwrap().policies, policies);
+}
+
+#[test]
+fn test_unrestricted_keys_record_no_policies() {
+    let ctx = TestContext::new();
+    let alice = wallet(1);
+    ctx.handle(provision_request(&alice, vec![1])).unwrap();
+
+    let record = kv::get_default_mapping(&ctx.kv, &pubkey(&alice)).unwrap().unwrap();
+    assert!(record.policies.is_empty());
+    assert!(!record.encode().contains("policies"));
+}
@@ -0,0 +1,212 @@
+mod common;
+
+use common::{evm_wallet, fixed_clock_provisioner, labeled_request, labeled_request_at, link_external_request};
+use cubist_wallet_provisioner::kv;
+use cubist_wallet_provisioner::labels;
+use cubist_wallet_provisioner::mapping;
+use cubist_wallet_provisioner::retirement;
+use cubist_wallet_provisioner::signing_gate::SigningRequest;
+use cubist_wallet_provisioner::testing::{admin, chain, evm, provision_request, provision_request_at, pubkey, update_request, wallet, TestContext};
+use cubist_wallet_provisioner::{ChainId, MappingRecord, UpdateMappingRequest};
+
+// =============================================================================
+// LABELED ADDRESS TESTS
+// =============================================================================
+
+#[test]
+fn test_labeled_store_adds_second_address_per_chain() {
+    let ctx = TestContext::new();
+    let alice = wallet(1);
+    let solana_pubkey = pubkey(&alice);
+    let primary = ctx.handle(provision_request(&alice, vec![1, 137])).unwrap();
+
+    let trading = ctx.handle(labeled_request(&alice, vec![1, 137], "trading")).unwrap();
+    assert_ne!(trading.evm_address, primary.evm_address);
+    assert_eq!(trading.label.as_deref(), Some("trading"));
+    assert_eq!(trading.chain_mappings.get(&chain(137)), Some(&trading.evm_address));
+
+    // The label's key is reused for more chains; the primary mapping is untouched
+    let again = ctx.handle(labeled_request(&alice, vec![42161], "trading")).unwrap();
+    assert_eq!(again.evm_address, trading.evm_address);
+    let found = ctx.provisioner.handle_get(&solana_pubkey, &[chain(1), chain(42161)]).unwrap();
+    assert_eq!(found.chain_mappings.get(&chain(1)), Some(&primary.evm_address));
+    assert_eq!(found.labeled_mappings[&chain(1)]["trading"], trading.evm_address);
+    assert_eq!(found.labeled_mappings[&chain(42161)]["trading"], trading.evm_address);
+    assert!(!found.labeled_mappings.contains_key(&chain(137)));
+
+    // Every chain when none are requested
+    let found = ctx.provisioner.handle_get(&solana_pubkey, &[]).unwrap();
+    assert_eq!(found.labeled_mappings.len(), 3);
+    assert_eq!(kv::get_reverse_mapping(&ctx.kv, &trading.evm_address).unwrap(), Some(solana_pubkey.clone()));
+
+    // `primary` is the chain mapping itself
+    let primary_again = ctx.handle(labeled_request(&alice, vec![1], "primary")).unwrap();
+    assert_eq!((primary_again.evm_address, primary_again.label), (primary.evm_address, None));
+}
+
+#[test]
+fn test_labeled_store_validates_label_and_requires_provisioned_user() {
+    let ctx = TestContext::new();
+    let alice = wallet(1);
+    let err = ctx.handle(labeled_request(&alice, vec![1], "cold")).unwrap_err();
+    assert_eq!(err.code(), "NOT_PROVISIONED");
+
+    ctx.handle(provision_request(&alice, vec![1])).unwrap();
+    for label in ["", "Cold", "cold:1", "a_b", &"x".repeat(33)] {
+        let err = ctx.handle(labeled_request(&alice, vec![1], label)).unwrap_err();
+        assert_eq!(err.code(), "INVALID_REQUEST", "{:?}", label);
+    }
+}
+
+#[test]
+fn test_labeled_update_rotates_only_the_label() {
+    let provisioner = fixed_clock_provisioner();
+    let alice = wallet(1);
+    let solana_pubkey = pubkey(&alice);
+    let primary = provisioner.handle(provision_request_at(&alice, vec![137], 1000)).unwrap();
+    let cold = provisioner.handle(labeled_request_at(&alice, vec![137], "cold", 1000)).unwrap();
+
+    let labeled_update = |expected_version| UpdateMappingRequest {
+        label: Some("cold".to_string()),
+        expected_version,
+        ..update_request(&solana_pubkey, 137)
+    };
+    let rotated = provisioner.handle_update_mapping(&admin(), labeled_update(Some(0))).unwrap();
+    assert_eq!(rotated.version, 1);
+    let err = provisioner.handle_update_mapping(&admin(), labeled_update(Some(0))).unwrap_err();
+    assert_eq!(err.code(), "VERSION_CONFLICT");
+
+    let found = provisioner.handle_get(&solana_pubkey, &[chain(137)]).unwrap();
+    assert_eq!(found.chain_mappings.get(&chain(137)), Some(&primary.evm_address));
+    assert_eq!(found.labeled_mappings[&chain(137)]["cold"], rotated.new_evm_address);
+
+    let retired = provisioner.handle_get_retirement(&cold.evm_address).unwrap().unwrap();
+    assert_eq!((retired.label.as_deref(), &retired.replaced_by), (Some("cold"), &rotated.new_evm_address));
+    let history = provisioner.handle_labeled_history(&solana_pubkey, "cold", &chain(137)).unwrap();
+    assert_eq!(history.current_address, Some(rotated.new_evm_address.clone()));
+    assert_eq!(history.entries.iter().map(|entry| &entry.address).collect::<Vec<_>>(), vec![&cold.evm_address]);
+    assert!(provisioner.handle_history(&solana_pubkey, &chain(137)).unwrap().entries.is_empty());
+
+    // Only labels mapped on the chain can be rotated
+    let err = provisioner
+        .handle_update_mapping(&admin(), UpdateMappingRequest { chain_id: chain(1), ..labeled_update(None) })
+        .unwrap_err();
+    assert_eq!(err.code(), "INVALID_REQUEST");
+}
+
+#[test]
+fn test_concurrent_labeled_updates_from_same_version_conflict() {
+    let ctx = TestContext::new();
+    let alice = wallet(1);
+    let solana_pubkey = pubkey(&alice);
+    ctx.handle(provision_request(&alice, vec![137])).unwrap();
+    let cold = ctx.handle(labeled_request(&alice, vec![137], "cold")).unwrap();
+    let bob = MappingRecord::new(&evm("0x3333333333333333333333333333333333333333"), None, "bob@test", 10);
+    let carol = MappingRecord::new(&evm("0x4444444444444444444444444444444444444444"), None, "carol@test", 10);
+
+    // Both read revision 0; bob claims revision 1 first and has not written it yet
+    assert!(kv::claim_revision_at(&ctx.kv, &labels::labeled_revision_key(&solana_pubkey, "cold", &chain(137), 1), "bob@test", 10).unwrap());
+    let err = mapping::apply_labeled_update(&ctx.kv, &solana_pubkey, "cold", &chain(137), &carol, Some(0), "carol@test", 10).unwrap_err();
+    assert_eq!(err.code(), "VERSION_CONFLICT");
+    let history = mapping::labeled_history(&ctx.kv, &solana_pubkey, "cold", &chain(137)).unwrap();
+    assert_eq!(history.current_address, Some(cold.evm_address.clone()));
+    assert!(history.entries.is_empty());
+    assert!(retirement::get_retirement(&ctx.kv, &cold.evm_address).unwrap().is_none());
+
+    // Once bob's claim is stale, his retry takes it over; carol's still conflicts
+    let now = 10 + kv::REVISION_CLAIM_TTL_SECS;
+    let stored = mapping::apply_labeled_update(&ctx.kv, &solana_pubkey, "cold", &chain(137), &bob, Some(0), "bob@test", now).unwrap();
+    assert_eq!(stored.revision, 1);
+    let err = mapping::apply_labeled_update(&ctx.kv, &solana_pubkey, "cold", &chain(137), &carol, Some(0), "carol@test", now).unwrap_err();
+    assert_eq!(err.code(), "VERSION_CONFLICT");
+    let history = mapping::labeled_history(&ctx.kv, &solana_pubkey, "cold", &chain(137)).unwrap();
+    assert_eq!(history.current_address, Some(bob.address.clone()));
+    assert_eq!(history.entries.len(), 1);
+    assert_eq!((&history.entries[0].address, history.entries[0].replaced_by.as_str()), (&cold.evm_address, "bob@test"));
+}
+
+#[test]
+fn test_signing_gate_accepts_labeled_addresses() {
+    let ctx = TestContext::new();
+    let alice = wallet(1);
+    let solana_pubkey = pubkey(&alice);
+    ctx.handle(provision_request(&alice, vec![1])).unwrap();
+    let trading = ctx.handle(labeled_request(&alice, vec![1], "trading")).unwrap();
+
+    let request = |chain_id: Option<ChainId>| SigningRequest {
+        solana_pubkey: solana_pubkey.clone(),
+        evm_address: trading.evm_address.clone(),
+        chain_id,
+        value: None,
+        to: None,
+    };
+    ctx.provisioner.handle_authorize_signing(&request(Some(chain(1)))).unwrap();
+    ctx.provisioner.handle_authorize_signing(&request(None)).unwrap();
+    let err = ctx.provisioner.handle_authorize_signing(&request(Some(chain(137)))).unwrap_err();
+    assert_eq!(err.code(), "ADDRESS_NOT_MAPPED");
+}
+
+// =============================================================================
+// EXTERNAL ADDRESS TESTS
+// =============================================================================
+
+#[test]
+fn test_link_external_replaces_custodial_mapping() {
+    let provisioner = fixed_clock_provisioner();
+    let alice = wallet(1);
+    let solana_pubkey = pubkey(&alice);
+    let metamask = evm_wallet(9);
+    let custodial = provisioner.handle(provision_request_at(&alice, vec![1], 1000)).unwrap();
+
+    let linked = provisioner.handle_link_external(link_external_request(&alice, &metamask, vec![1, 137], "1")).unwrap();
+    assert_eq!(linked.chain_versions[&chain(1)], 1);
+    assert_eq!(linked.chain_versions[&chain(137)], 1);
+
+    let found = provisioner.handle_get(&solana_pubkey, &[chain(1), chain(137)]).unwrap();
+    assert_eq!(found.chain_mappings[&chain(1)], linked.evm_address);
+    assert_eq!(found.default_address, Some(custodial.evm_address.clone()));
+    assert_eq!(found.external_addresses, vec![linked.evm_address.clone()]);
+    assert!(!found.chain_key_ids.contains_key(&chain(1)));
+
+    let retired = provisioner.handle_get_retirement(&custodial.evm_address).unwrap().unwrap();
+    assert_eq!(retired.reason.as_deref(), Some(mapping::LINK_EXTERNAL_REASON));
+    assert_eq!(provisioner.handle_reverse_get(&linked.evm_address).unwrap(), Some(solana_pubkey.clone()));
+
+    // CubeSigner has no key to sign with
+    let request = SigningRequest {
+        solana_pubkey: solana_pubkey.clone(),
+        evm_address: linked.evm_address.clone(),
+        chain_id: Some(chain(1)),
+        value: None,
+        to: None,
+    };
+    assert_eq!(provisioner.handle_authorize_signing(&request).unwrap_err().code(), "EXTERNAL_ADDRESS");
+
+    // Linking again (new nonce) leaves already linked chains alone
+    let again = provisioner.handle_link_external(link_external_request(&alice, &metamask, vec![1], "2")).unwrap();
+    assert_eq!(again.chain_versions[&chain(1)], 1);
+}
+
+#[test]
+fn test_link_external_requires_both_wallets() {
+    let provisioner = fixed_clock_provisioner();
+    let alice = wallet(1);
+    let metamask = evm_wallet(9);
+
+    // Signed by a different EVM wallet: the nonce is not burned
+    let mut req = link_external_request(&alice, &metamask, vec![1], "1");
+    req.evm_signature = link_external_request(&alice, &evm_wallet(8), vec![1], "1").evm_signature;
+    assert_eq!(provisioner.handle_link_external(req).unwrap_err().code(), "SIGNATURE_MISMATCH");
+
+    let mut req = link_external_request(&alice, &metamask, vec![1], "1");
+    req.signature = link_external_request(&wallet(2), &metamask, vec![1], "1").signature;
+    assert_eq!(provisioner.handle_link_external(req).unwrap_err().code(), "SIGNATURE_MISMATCH");
+
+    provisioner.handle_link_external(link_external_request(&alice, &metamask, vec![1], "1")).unwrap();
+    let err = provisioner.handle_link_external(link_external_request(&alice, &metamask, vec![137], "1")).unwrap_err();
+    assert_eq!(err.code(), "NONCE_TOO_LOW");
+
+    // Another user cannot claim the same address
+    let err = provisioner.handle_link_external(link_external_request(&wallet(2), &metamask, vec![1], "1")).unwrap_err();
+    assert_eq!(err.code(), "ADDRESS_OWNED");
+}
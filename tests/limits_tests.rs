@@ -0,0 +1,215 @@
+mod common;
+
+use common::{fixed_clock_provisioner, idempotent_provisioner, labeled_request_at, rate_limited_provisioner};
+use cubist_wallet_provisioner::audit::AuditQuery;
+use cubist_wallet_provisioner::config::{self, ConfigUpdate};
+use cubist_wallet_provisioner::error::ProvisionError;
+use cubist_wallet_provisioner::idempotency;
+use cubist_wallet_provisioner::kv;
+use cubist_wallet_provisioner::mapping;
+use cubist_wallet_provisioner::quota::{self, MappingQuota};
+use cubist_wallet_provisioner::rate_limit::{self, rate_key, RateCounter, RateLimit};
+use cubist_wallet_provisioner::testing::{
+    admin, chain, evm, mock_key, provision_request, provision_request_at, pubkey, update_request, wallet, MockKvStore, TestContext,
+};
+use cubist_wallet_provisioner::KvStore;
+use std::sync::atomic::Ordering;
+
+// =============================================================================
+// MAPPING QUOTA TESTS
+// =============================================================================
+
+#[test]
+fn test_quota_limits_chains_and_labels_per_user() {
+    let provisioner = fixed_clock_provisioner().with_mapping_quota(MappingQuota { max_chains: 2, max_labels: 1 });
+    let alice = wallet(1);
+    provisioner.handle(provision_request_at(&alice, vec![1, 137], 1000)).unwrap();
+
+    let err = provisioner.handle(provision_request_at(&alice, vec![1, 42161], 1000)).unwrap_err();
+    assert_eq!(err, ProvisionError::QuotaExceeded { solana_pubkey: pubkey(&alice).to_string(), what: "chains", limit: 2 });
+    assert_eq!(kv::get_chain_mapping(provisioner.kv(), &pubkey(&alice), &chain(42161)).unwrap(), None);
+    // Nothing new: not counted against the quota
+    provisioner.handle(provision_request_at(&alice, vec![137], 1000)).unwrap();
+
+    provisioner.handle(labeled_request_at(&alice, vec![1, 137], "cold", 1000)).unwrap();
+    assert_eq!(provisioner.handle(labeled_request_at(&alice, vec![1], "hot", 1000)).unwrap_err().code(), "QUOTA_EXCEEDED");
+    let err = provisioner.handle(labeled_request_at(&alice, vec![42161], "cold", 1000)).unwrap_err();
+    assert_eq!(err.to_string(), format!("{} is already mapped on the most chains allowed (2)", pubkey(&alice)));
+}
+
+#[test]
+fn test_lowered_quota_keeps_existing_mappings() {
+    let ctx = TestContext::new();
+    let alice = pubkey(&wallet(1));
+    ctx.handle(provision_request(&wallet(1), vec![1, 137, 42161])).unwrap();
+
+    let quota = MappingQuota { max_chains: 1, max_labels: 0 };
+    quota::check(&ctx.kv, &quota, &alice, None, &[chain(1), chain(137)]).unwrap();
+    assert_eq!(quota::check(&ctx.kv, &quota, &alice, None, &[chain(10)]).unwrap_err().code(), "QUOTA_EXCEEDED");
+    assert_eq!(quota::check(&ctx.kv, &quota, &alice, Some("cold"), &[chain(1)]).unwrap_err().code(), "QUOTA_EXCEEDED");
+
+    assert_eq!(config::Config::default().mapping_quota, MappingQuota { max_chains: 100, max_labels: 10 });
+    let update = ConfigUpdate { mapping_quota: Some(MappingQuota { max_chains: 0, max_labels: 0 }), ..Default::default() };
+    assert_eq!(config::set_config(&ctx.kv, update).unwrap_err().code(), "INVALID_REQUEST");
+}
+
+// =============================================================================
+// IDEMPOTENCY KEY TESTS
+// =============================================================================
+
+#[test]
+fn test_retried_update_with_idempotency_key_rotates_once() {
+    let (provisioner, bucket) = idempotent_provisioner();
+    let alice = wallet(1);
+    let solana_pubkey = pubkey(&alice);
+    provisioner.handle(provision_request(&alice, vec![1, 137])).unwrap();
+
+    let mut req = update_request(&solana_pubkey, 137);
+    req.idempotency_key = Some("update-1".to_string());
+    let first = provisioner.handle_update_mapping(&admin(), req.clone()).unwrap();
+    let retried = provisioner.handle_update_mapping(&admin(), req).unwrap();
+
+    assert_eq!(retried.new_evm_address, first.new_evm_address);
+    assert_eq!(retried.new_key_id, first.new_key_id);
+    assert_eq!(mapping::history(provisioner.kv(), &solana_pubkey, &chain(137)).unwrap().entries.len(), 1);
+    assert!(bucket.get(&idempotency::record_key("update", "update-1")).unwrap().is_some());
+
+    // A new key is a new request
+    let mut req = update_request(&solana_pubkey, 137);
+    req.idempotency_key = Some("update-2".to_string());
+    assert_ne!(provisioner.handle_update_mapping(&admin(), req).unwrap().new_evm_address, first.new_evm_address);
+}
+
+#[test]
+fn test_idempotency_key_reused_for_different_request_is_rejected() {
+    let (provisioner, _) = idempotent_provisioner();
+    let alice = wallet(1);
+    let solana_pubkey = pubkey(&alice);
+    provisioner.handle(provision_request(&alice, vec![1, 137])).unwrap();
+
+    let mut req = update_request(&solana_pubkey, 137);
+    req.idempotency_key = Some("update-1".to_string());
+    provisioner.handle_update_mapping(&admin(), req).unwrap();
+
+    let mut other = update_request(&solana_pubkey, 1);
+    other.idempotency_key = Some("update-1".to_string());
+    let err = provisioner.handle_update_mapping(&admin(), other).unwrap_err();
+    assert_eq!(err.code(), "IDEMPOTENCY_KEY_REUSED");
+    assert_eq!(kv::get_existing_mapping(provisioner.kv(), &solana_pubkey, &chain(1)).unwrap(), Some(evm(&mock_key(1).address)));
+}
+
+#[test]
+fn test_failed_request_is_not_recorded() {
+    let (provisioner, bucket) = idempotent_provisioner();
+    let alice = wallet(1);
+    let solana_pubkey = pubkey(&alice);
+
+    let mut req = update_request(&solana_pubkey, 137);
+    req.idempotency_key = Some("update-1".to_string());
+    assert!(matches!(provisioner.handle_update_mapping(&admin(), req.clone()), Err(ProvisionError::NotProvisioned(_))));
+    assert!(bucket.get(&idempotency::record_key("update", "update-1")).unwrap().is_none());
+
+    // The retry runs again and now succeeds
+    provisioner.handle(provision_request(&alice, vec![137])).unwrap();
+    provisioner.handle_update_mapping(&admin(), req).unwrap();
+}
+
+#[test]
+fn test_idempotency_key_requires_bucket_and_valid_format() {
+    let ctx = TestContext::new();
+    let mut req = provision_request(&wallet(1), vec![1]);
+    req.idempotency_key = Some("provision-1".to_string());
+    assert_eq!(ctx.handle(req).unwrap_err(), ProvisionError::NotConfigured("Idempotency bucket"));
+
+    let (provisioner, _) = idempotent_provisioner();
+    let mut req = provision_request(&wallet(1), vec![1]);
+    req.idempotency_key = Some("has spaces".to_string());
+    assert_eq!(provisioner.handle(req).unwrap_err().code(), "INVALID_IDEMPOTENCY_KEY");
+}
+
+// =============================================================================
+// RATE LIMIT TESTS
+// =============================================================================
+
+#[test]
+fn test_rate_limit_refuses_retry_loops_per_solana_address() {
+    let (provisioner, bucket, now) = rate_limited_provisioner();
+    let alice = wallet(1);
+    for _ in 0..3 {
+        provisioner.handle(provision_request_at(&alice, vec![1], 6000)).unwrap();
+    }
+
+    let err = provisioner.handle(provision_request_at(&alice, vec![1], 6000)).unwrap_err();
+    assert_eq!(err, ProvisionError::RateLimited { solana_pubkey: pubkey(&alice).to_string(), retry_after: 60 });
+    assert!(err.is_retryable());
+    assert_eq!(rate_limit::get_counter(&bucket, &pubkey(&alice)).unwrap(), Some(RateCounter { window: 100, count: 3, previous: 0 }));
+    // Refused requests are neither counted nor audited
+    let audited = provisioner.handle_audit_query(&admin(), &AuditQuery::default()).unwrap().records;
+    assert_eq!(audited.len(), 3);
+
+    // Other addresses have their own limit
+    provisioner.handle(provision_request_at(&wallet(2), vec![1], 6000)).unwrap();
+
+    // Half a window later, the previous window counts half: 3 * 30/60 + 2 < 3
+    now.store(6090, Ordering::SeqCst);
+    provisioner.handle(provision_request_at(&alice, vec![1], 6090)).unwrap();
+    provisioner.handle(provision_request_at(&alice, vec![1], 6090)).unwrap();
+    assert_eq!(provisioner.handle(provision_request_at(&alice, vec![1], 6090)).unwrap_err().code(), "RATE_LIMITED");
+}
+
+#[test]
+fn test_rate_limit_counts_only_requests_whose_proof_verifies() {
+    let (provisioner, bucket, _) = rate_limited_provisioner();
+    let (alice, mallory) = (wallet(1), wallet(2));
+
+    // Requests in alice's name that mallory signed: refused, audited, not counted
+    for _ in 0..5 {
+        let mut forged = provision_request_at(&alice, vec![1], 6000);
+        forged.signature = provision_request_at(&mallory, vec![1], 6000).signature;
+        assert_eq!(provisioner.handle(forged).unwrap_err().code(), "SIGNATURE_MISMATCH");
+    }
+    assert_eq!(rate_limit::get_counter(&bucket, &pubkey(&alice)).unwrap(), None);
+    assert_eq!(provisioner.handle_audit_query(&admin(), &AuditQuery::default()).unwrap().records.len(), 5);
+
+    // alice still has her whole budget
+    for _ in 0..3 {
+        provisioner.handle(provision_request_at(&alice, vec![1], 6000)).unwrap();
+    }
+
+    // A refused request does not burn its nonce, so it can be sent again later
+    let req = provision_request_at(&alice, vec![1], 6000);
+    assert_eq!(provisioner.handle(req.clone()).unwrap_err().code(), "RATE_LIMITED");
+    assert!(bucket.get(&rate_key(&pubkey(&alice))).unwrap().is_some());
+}
+
+#[test]
+fn test_rate_limit_skips_idempotent_replays() {
+    let (provisioner, bucket, _) = rate_limited_provisioner();
+    let alice = wallet(1);
+    let mut req = provision_request_at(&alice, vec![1], 6000);
+    req.idempotency_key = Some("order-1".to_string());
+    for _ in 0..5 {
+        provisioner.handle(req.clone()).unwrap();
+    }
+    assert_eq!(rate_limit::get_counter(&bucket, &pubkey(&alice)).unwrap().unwrap().count, 1);
+}
+
+#[test]
+fn test_rate_limit_does_not_overflow_on_huge_limits_or_counts() {
+    let bucket = MockKvStore::new();
+    let solana_pubkey = pubkey(&wallet(1));
+    let huge = RateLimit { max_requests: u64::MAX, window_secs: u64::MAX };
+    rate_limit::check(&bucket, &huge, &solana_pubkey, u64::MAX - 1).unwrap();
+
+    // A counter at the top of the range refuses, and is never wrapped to zero
+    let limit = RateLimit { max_requests: u64::MAX, window_secs: 60 };
+    let set_counter = |count: u64, previous: u64| {
+        let counter = RateCounter { window: 100, count, previous };
+        bucket.set(&rate_key(&solana_pubkey), &serde_json::to_string(&counter).unwrap()).unwrap();
+    };
+    set_counter(u64::MAX, u64::MAX);
+    assert_eq!(rate_limit::check(&bucket, &limit, &solana_pubkey, 6030).unwrap_err().code(), "RATE_LIMITED");
+    set_counter(u64::MAX - 1, 0);
+    rate_limit::check(&bucket, &limit, &solana_pubkey, 6030).unwrap();
+    assert_eq!(rate_limit::get_counter(&bucket, &solana_pubkey).unwrap().unwrap().count, u64::MAX);
+}
@@ -0,0 +1,142 @@
+use cubist_wallet_provisioner::error::{ProvisionError, Result};
+use cubist_wallet_provisioner::kv;
+use cubist_wallet_provisioner::testing::{mock_key, provision_request_at, pubkey, wallet};
+use cubist_wallet_provisioner::{ChainId, CreatedKey, KeyCreator, KvStore, ProvisionRequest, ProvisionResponse, Provisioner};
+use loom::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use loom::sync::{Arc, Mutex};
+use loom::thread;
+use std::collections::BTreeMap;
+
+/// Most preemptions loom tries per execution. Every interleaving of two
+/// provisions up to this bound is explored.
+const PREEMPTION_BOUND: usize = 2;
+
+/// Attempts a client makes before giving up, retrying retryable errors
+const ATTEMPTS: usize = 2;
+
+/// Stack of each client thread: the provision flow runs deeper than loom's
+/// default, and loom's main thread cannot be given more
+const STACK_SIZE: usize = 8 << 20;
+
+/// `KvStore` behind a loom mutex: each operation is a point where loom may
+/// switch to the other thread. With `lose_default_write`, the first write of
+/// the default mapping is applied but reported as failed, like a response
+/// lost on the way back from the KV store.
+#[derive(Clone)]
+struct LoomKv {
+    data: Arc<Mutex<BTreeMap<String, String>>>,
+    lose_default_write: Option<(String, Arc<AtomicBool>)>,
+}
+
+impl LoomKv {
+    fn new() -> Self {
+        Self { data: Arc::new(Mutex::new(BTreeMap::new())), lose_default_write: None }
+    }
+
+    fn losing_default_write(default_key: String) -> Self {
+        Self { lose_default_write: Some((default_key, Arc::new(AtomicBool::new(false)))), ..Self::new() }
+    }
+}
+
+impl KvStore for LoomKv {
+    fn get(&self, key: &str) -> Result<Option<String>> {
+        Ok(self.data.lock().unwrap().get(key).cloned())
+    }
+
+    fn set_if_absent(&self, key: &str, value: &str) -> Result<bool> {
+        let inserted = {
+            let mut data = self.data.lock().unwrap();
+            !data.contains_key(key) && data.insert(key.to_string(), value.to_string()).is_none()
+        };
+        match &self.lose_default_write {
+            Some((default_key, lost)) if inserted && key == default_key && !lost.swap(true, Ordering::SeqCst) => {
+                Err(ProvisionError::Kv("response lost".to_string()))
+            }
+            _ => Ok(inserted),
+        }
+    }
+
+    fn set(&self, key: &str, value: &str) -> Result<()> {
+        self.data.lock().unwrap().insert(key.to_string(), value.to_string());
+        Ok(())
+    }
+
+    fn list_keys(&self, after: Option<&str>, limit: usize) -> Result<Vec<String>> {
+        let data = self.data.lock().unwrap();
+        Ok(data.keys().filter(|key| after.is_none_or(|after| key.as_str() > after)).take(limit).cloned().collect())
+    }
+}
+
+/// Key creator counting the keys it creates
+#[derive(Clone)]
+struct CountingKeys(Arc<AtomicU32>);
+
+impl KeyCreator for CountingKeys {
+    fn create_evm_key(&self, _solana_pubkey: &str) -> Result<CreatedKey> {
+        Ok(mock_key(self.0.fetch_add(1, Ordering::SeqCst) + 1))
+    }
+
+    fn create_evm_key_for_chain(&self, solana_pubkey: &str, _chain_id: &ChainId) -> Result<CreatedKey> {
+        self.create_evm_key(solana_pubkey)
+    }
+
+    fn create_labeled_evm_key(&self, solana_pubkey: &str, _label: &str, _chain_id: Option<&ChainId>) -> Result<CreatedKey> {
+        self.create_evm_key(solana_pubkey)
+    }
+}
+
+/// `handle`, retried on retryable errors like a client would: each attempt
+/// signs `request()` afresh, as a used nonce cannot be sent again
+fn provision_with_retries(provisioner: &Provisioner<LoomKv, CountingKeys>, request: impl Fn() -> ProvisionRequest) -> Result<ProvisionResponse> {
+    let mut result = provisioner.handle(request());
+    for _ in 1..ATTEMPTS {
+        match &result {
+            Err(e) if e.is_retryable() => result = provisioner.handle(request()),
+            _ => break,
+        }
+    }
+    result
+}
+
+/// Run two clients provisioning the same user over `kv` under every
+/// interleaving, and check that at most one key is created and everyone who
+/// got an answer got the same one
+fn check_two_provisions(kv: impl Fn() -> LoomKv + Sync + Send + 'static) {
+    let mut builder = loom::model::Builder::new();
+    builder.preemption_bound = Some(PREEMPTION_BOUND);
+    builder.check(move || {
+        let created = Arc::new(AtomicU32::new(0));
+        let kv = kv();
+        let provisioner = Arc::new(Provisioner::new(kv.clone(), CountingKeys(Arc::clone(&created))).with_clock(|| 1000));
+        let alice = wallet(1);
+
+        let clients = [1, 137].map(|evm_chain_id| {
+            let provisioner = Arc::clone(&provisioner);
+            let alice = alice.clone();
+            let request = move || provision_request_at(&alice, vec![evm_chain_id], 1000);
+            thread::Builder::new().stack_size(STACK_SIZE).spawn(move || provision_with_retries(&provisioner, request)).unwrap()
+        });
+        let [first, second] = clients.map(|client| client.join().unwrap());
+
+        assert!(created.load(Ordering::SeqCst) <= 1, "{} keys created", created.load(Ordering::SeqCst));
+        let stored = kv::get_default_evm_address(&kv, &pubkey(&alice)).unwrap();
+        for response in [&first, &second].into_iter().flatten() {
+            assert_eq!(Some(&response.evm_address), stored.as_ref());
+        }
+        assert!(first.is_ok() || second.is_ok(), "{:?} / {:?}", first.err(), second.err());
+    });
+}
+
+// =============================================================================
+// PROVISION RACE MODEL
+// =============================================================================
+
+#[test]
+fn test_concurrent_provisions_create_at_most_one_key() {
+    check_two_provisions(LoomKv::new);
+}
+
+#[test]
+fn test_retry_after_lost_store_response_creates_at_most_one_key() {
+    check_two_provisions(|| LoomKv::losing_default_write(kv::default_key(&pubkey(&wallet(1)))));
+}
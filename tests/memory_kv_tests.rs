@@ -0,0 +1,98 @@
+use cubist_wallet_provisioner::error::Result;
+use cubist_wallet_provisioner::expiry::SweepRequest;
+use cubist_wallet_provisioner::kv::{self, chain_key, default_key, reverse_key};
+use cubist_wallet_provisioner::memory_kv::MemoryKvStore;
+use cubist_wallet_provisioner::testing::{admin, admins, provision_request, provision_request_at, pubkey, signed_at, wallet};
+use cubist_wallet_provisioner::{ChainId, CreatedKey, KeyCreator, KvStore, ProvisionRequest, Provisioner};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+/// Key creator returning one fixed address per call kind
+struct FixedKeys;
+
+impl KeyCreator for FixedKeys {
+    fn create_evm_key(&self, _solana_pubkey: &str) -> Result<CreatedKey> {
+        Ok(CreatedKey {
+            key_id: "Key#0x0000000000000000000000000000000000000001".to_string(),
+            address: "0x0000000000000000000000000000000000000001".to_string(),
+            policies: Vec::new(),
+        })
+    }
+
+    fn create_evm_key_for_chain(&self, _solana_pubkey: &str, _chain_id: &ChainId) -> Result<CreatedKey> {
+        Ok(CreatedKey {
+            key_id: "Key#0x0000000000000000000000000000000000000002".to_string(),
+            address: "0x0000000000000000000000000000000000000002".to_string(),
+            policies: Vec::new(),
+        })
+    }
+
+    fn create_labeled_evm_key(&self, _solana_pubkey: &str, _label: &str, _chain_id: Option<&ChainId>) -> Result<CreatedKey> {
+        Ok(CreatedKey {
+            key_id: "Key#0x0000000000000000000000000000000000000003".to_string(),
+            address: "0x0000000000000000000000000000000000000003".to_string(),
+            policies: Vec::new(),
+        })
+    }
+}
+
+#[test]
+fn test_provision_runs_against_memory_kv() {
+    let kv = MemoryKvStore::new();
+    let provisioner = Provisioner::new(kv.clone(), FixedKeys);
+
+    let solana_pubkey = pubkey(&wallet(1));
+    let result = provisioner.handle(provision_request(&wallet(1), vec![1, 137])).unwrap();
+    assert_eq!(provisioner.handle(provision_request(&wallet(1), vec![1, 137])).unwrap().evm_address, result.evm_address);
+
+    // The clone held by the test sees the provisioner's writes
+    assert!(kv.get(&default_key(&solana_pubkey)).unwrap().is_some());
+    assert_eq!(kv::get_chain_index(&kv, &solana_pubkey).unwrap().len(), 2);
+}
+
+#[test]
+fn test_memory_kv_primitives() {
+    let kv = MemoryKvStore::new();
+
+    assert!(kv.set_if_absent("b", "1").unwrap());
+    assert!(!kv.set_if_absent("b", "2").unwrap());
+    assert_eq!(kv.get("b").unwrap().as_deref(), Some("1"));
+
+    kv.set("b", "3").unwrap();
+    kv.set("a", "0").unwrap();
+    kv.set("c", "0").unwrap();
+    assert_eq!(kv.get("b").unwrap().as_deref(), Some("3"));
+
+    assert_eq!(kv.list_keys(None, 2).unwrap(), vec!["a", "b"]);
+    assert_eq!(kv.list_keys(Some("b"), 10).unwrap(), vec!["c"]);
+    assert_eq!(kv.snapshot().len(), 3);
+}
+
+#[test]
+fn test_sweep_removes_expired_mappings() {
+    let kv = MemoryKvStore::new();
+    let now = Arc::new(AtomicU64::new(1000));
+    let clock = Arc::clone(&now);
+    let provisioner = Provisioner::new(kv.clone(), FixedKeys).with_admins(admins()).with_clock(move || clock.load(Ordering::SeqCst));
+
+    let solana_pubkey = pubkey(&wallet(1));
+    let req = signed_at(&wallet(1), ProvisionRequest { ttl_secs: Some(600), ..provision_request_at(&wallet(1), vec![1], 1000) }, 1000);
+    let result = provisioner.handle(req).unwrap();
+
+    // Nothing has expired yet
+    let report = provisioner.handle_sweep(&admin(), SweepRequest::default()).unwrap();
+    assert!(report.swept.is_empty());
+    assert_eq!(report.next_cursor, None);
+
+    now.store(1600, Ordering::SeqCst);
+    let report = provisioner.handle_sweep(&admin(), SweepRequest::default()).unwrap();
+    assert_eq!(report.swept.len(), 2);
+    assert!(kv.get(&default_key(&solana_pubkey)).unwrap().is_none());
+    assert!(kv.get(&chain_key(&solana_pubkey, &ChainId::eip155(1))).unwrap().is_none());
+    assert!(kv.get(&reverse_key(&result.evm_address)).unwrap().is_none());
+    assert!(kv::get_chain_index(&kv, &solana_pubkey).unwrap().is_empty());
+
+    // The address can be provisioned again
+    let again = provisioner.handle(provision_request_at(&wallet(1), vec![1], 1600)).unwrap();
+    assert_eq!(provisioner.handle_get(&solana_pubkey, &[ChainId::eip155(1)]).unwrap().default_address, Some(again.evm_address));
+}
@@ -0,0 +1,77 @@
+mod common;
+
+use common::{approval_provisioner, named};
+use cubist_wallet_provisioner::kv::{self, chain_key, default_key, reverse_key};
+use cubist_wallet_provisioner::migrate::MigrateRequest;
+use cubist_wallet_provisioner::testing::{admin, chain, evm, pubkey, wallet, TestContext};
+use cubist_wallet_provisioner::KvStore;
+
+// =============================================================================
+// MIGRATION TESTS
+// =============================================================================
+
+#[test]
+fn test_migrate_rewrites_legacy_records_in_batches() {
+    let ctx = TestContext::new();
+    let legacy = "0xcb373e47d769b06dee02f05c86dd8790e0358aee";
+    let v1 = r#"{"address":"0xcb373e47d769b06dee02f05c86dd8790e0358aee","key_id":"Key#1"}"#;
+
+    for seed in 1..=3 {
+        let solana_pubkey = pubkey(&wallet(seed));
+        ctx.kv.set(&default_key(&solana_pubkey), legacy).unwrap();
+        ctx.kv.set(&chain_key(&solana_pubkey, &chain(1)), legacy).unwrap();
+        ctx.kv.set(&chain_key(&solana_pubkey, &chain(137)), v1).unwrap();
+    }
+    ctx.kv.set(&reverse_key(&evm(legacy)), pubkey(&wallet(1)).as_str()).unwrap();
+
+    let mut cursor = None;
+    let mut batches = 0;
+    let mut migrated = 0;
+    loop {
+        let req = MigrateRequest { cursor, limit: Some(4), request_id: None };
+        let report = ctx.provisioner.handle_migrate(&admin(), req).unwrap();
+        assert!(report.scanned <= 4 && report.failed.is_empty());
+        migrated += report.migrated;
+        batches += 1;
+        cursor = report.next_cursor;
+        if cursor.is_none() {
+            break;
+        }
+    }
+    assert_eq!(migrated, 9);
+    assert!(batches >= 3);
+
+    for seed in 1..=3 {
+        let solana_pubkey = pubkey(&wallet(seed));
+        let chain_137 = kv::get_chain_mapping(&ctx.kv, &solana_pubkey, &chain(137)).unwrap().unwrap();
+        assert_eq!(chain_137.version, kv::MAPPING_RECORD_VERSION);
+        assert_eq!(chain_137.key_id.as_deref(), Some("Key#1"));
+        assert_eq!(chain_137.created_at, None);
+        assert_eq!(kv::get_default_mapping(&ctx.kv, &solana_pubkey).unwrap().unwrap().address, evm(legacy));
+    }
+
+    // Non-mapping keys are untouched; a second run has nothing to do
+    assert_eq!(ctx.kv.get(&reverse_key(&evm(legacy))).unwrap().as_deref(), Some(pubkey(&wallet(1)).as_str()));
+    let rerun = ctx.provisioner.handle_migrate(&admin(), MigrateRequest { limit: Some(500), ..Default::default() }).unwrap();
+    assert_eq!((rerun.migrated, rerun.next_cursor), (0, None));
+}
+
+#[test]
+fn test_migrate_reports_malformed_records_and_requires_admin() {
+    let (provisioner, _) = approval_provisioner();
+    let solana_pubkey = pubkey(&wallet(1));
+    provisioner.kv().set(&chain_key(&solana_pubkey, &chain(1)), "not an address").unwrap();
+
+    let err = provisioner
+        .handle_migrate(&named("mallory@test"), MigrateRequest::default())
+        .unwrap_err();
+    assert!(err.to_string().contains("is not an admin"));
+
+    let report = provisioner
+        .handle_migrate(&named("alice@test"), MigrateRequest::default())
+        .unwrap();
+    assert_eq!(report.migrated, 0);
+    assert_eq!(report.failed.len(), 1);
+    assert_eq!(report.failed[0].key, chain_key(&solana_pubkey, &chain(1)));
+    assert_eq!(provisioner.kv().get(&chain_key(&solana_pubkey, &chain(1))).unwrap().as_deref(), Some("not an address"));
+}
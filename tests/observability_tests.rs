@@ -0,0 +1,305 @@
+mod common;
+
+use common::{
+    evm_wallet, fixed_clock_provisioner, idempotent_provisioner, labeled_request_at, link_external_request, store_with_backend_key, FlakyKvStore,
+};
+use cubist_wallet_provisioner::events::{self, EventKind};
+use cubist_wallet_provisioner::health;
+use cubist_wallet_provisioner::kv;
+use cubist_wallet_provisioner::logging::{self, LogEvent};
+use cubist_wallet_provisioner::mapping;
+use cubist_wallet_provisioner::metrics::{self, Stats};
+use cubist_wallet_provisioner::testing::{
+    admin, chain, provision_request, provision_request_at, pubkey, update_request, wallet, MockKeyCreator, MockKvStore, TestContext,
+};
+use cubist_wallet_provisioner::usage::{self, UsageReport};
+use cubist_wallet_provisioner::{KvStore, ProvisionBatchRequest, Provisioner};
+use std::sync::{Arc, Mutex};
+
+// =============================================================================
+// EVENT TESTS
+// =============================================================================
+
+#[test]
+fn test_provisions_and_updates_emit_events() {
+    let ctx = TestContext::new();
+    let alice = wallet(1);
+    let solana_pubkey = pubkey(&alice);
+
+    let stored = ctx.handle(provision_request(&alice, vec![1, 137])).unwrap();
+    // A retry maps nothing new and emits nothing
+    ctx.handle(provision_request(&alice, vec![1, 137])).unwrap();
+    ctx.handle(provision_request(&alice, vec![1, 10])).unwrap();
+    let updated = ctx.handle_update_mapping(update_request(&solana_pubkey, 137)).unwrap();
+
+    let page = ctx.provisioner.handle_poll_events(0, None).unwrap();
+    assert_eq!(page.last_seq, 3);
+    assert_eq!(page.events.iter().map(|e| e.seq).collect::<Vec<_>>(), vec![1, 2, 3]);
+    assert!(page.events.iter().all(|e| e.change.solana_pubkey == solana_pubkey));
+
+    let provisioned = &page.events[0].change;
+    assert_eq!(provisioned.kind, EventKind::Provisioned);
+    assert_eq!(provisioned.chain_mappings, [(chain(1), stored.evm_address.clone()), (chain(137), stored.evm_address.clone())].into());
+    assert_eq!(provisioned.version, None);
+
+    assert_eq!(page.events[1].change.chain_mappings, [(chain(10), stored.evm_address.clone())].into());
+
+    let update = &page.events[2].change;
+    assert_eq!(update.kind, EventKind::Updated);
+    assert_eq!(update.chain_mappings, [(chain(137), updated.new_evm_address.clone())].into());
+    assert_eq!(update.version, Some(updated.version));
+}
+
+#[test]
+fn test_poll_events_pages_by_seq() {
+    let ctx = TestContext::new();
+    for seed in 1..=5 {
+        ctx.handle(provision_request(&wallet(seed), vec![1])).unwrap();
+    }
+
+    let first = ctx.provisioner.handle_poll_events(0, Some(2)).unwrap();
+    assert_eq!(first.events.len(), 2);
+    assert_eq!(first.last_seq, 2);
+    assert_eq!(first.events[0].change.solana_pubkey, pubkey(&wallet(1)));
+
+    let rest = ctx.provisioner.handle_poll_events(first.last_seq, None).unwrap();
+    assert_eq!(rest.events.iter().map(|e| e.seq).collect::<Vec<_>>(), vec![3, 4, 5]);
+
+    // Caught up: nothing new, and the cursor stays put
+    let idle = ctx.provisioner.handle_poll_events(rest.last_seq, None).unwrap();
+    assert!(idle.events.is_empty());
+    assert_eq!(idle.last_seq, 5);
+}
+
+#[test]
+fn test_half_written_store_still_emits_its_event() {
+    // address claim, default, journal, journal head, reverse index, first chain; then the KV fails
+    let flaky = FlakyKvStore { inner: MockKvStore::new(), writes_left: Mutex::new(6) };
+    let alice = wallet(1);
+    let solana_pubkey = pubkey(&alice);
+    assert!(store_with_backend_key(&flaky, &provision_request(&alice, vec![1, 137])).is_err());
+
+    let kv = flaky.inner;
+    assert!(events::poll(&kv, 0, None).unwrap().events.is_empty());
+
+    // Completing the journal appends the event with the rest of the writes
+    mapping::get(&kv, &solana_pubkey, &[chain(1)], 1000).unwrap();
+    let page = events::poll(&kv, 0, None).unwrap();
+    assert_eq!(page.events.len(), 1);
+    assert_eq!(page.events[0].change.chain_mappings.keys().collect::<Vec<_>>(), vec![&chain(1), &chain(137)]);
+}
+
+// =============================================================================
+// METRICS TESTS
+// =============================================================================
+
+fn metered_provisioner() -> (Provisioner<MockKvStore, MockKeyCreator>, MockKvStore) {
+    let bucket = MockKvStore::new();
+    (fixed_clock_provisioner().with_metrics(bucket.clone()), bucket)
+}
+
+#[test]
+fn test_metrics_count_new_wallets_and_chains() {
+    let (provisioner, _) = metered_provisioner();
+    let alice = wallet(1);
+    let solana_pubkey = pubkey(&alice);
+
+    provisioner.handle(provision_request_at(&alice, vec![1, 137], 1000)).unwrap();
+    // Retries and chains already mapped count nothing; a new chain counts once
+    provisioner.handle(provision_request_at(&alice, vec![1, 137], 1000)).unwrap();
+    provisioner.handle(provision_request_at(&alice, vec![137, 42161], 1000)).unwrap();
+    provisioner.handle(provision_request_at(&wallet(2), vec![1], 1000)).unwrap();
+    // Labeled addresses are not wallets of their own
+    provisioner.handle(labeled_request_at(&alice, vec![1], "cold", 1000)).unwrap();
+
+    provisioner.handle_update_mapping(&admin(), update_request(&solana_pubkey, 137)).unwrap();
+
+    let stats = provisioner.handle_stats().unwrap();
+    assert_eq!(stats.provisions, 2);
+    assert_eq!(stats.provisions_by_chain[&chain(1)], 2);
+    assert_eq!(stats.provisions_by_chain[&chain(137)], 1);
+    assert_eq!(stats.provisions_by_chain[&chain(42161)], 1);
+    assert_eq!(stats.updates, 1);
+    assert!(stats.errors_by_code.is_empty());
+}
+
+#[test]
+fn test_metrics_count_errors_by_code() {
+    let (provisioner, bucket) = metered_provisioner();
+    let alice = wallet(1);
+
+    let mut forged = provision_request_at(&alice, vec![1], 1000);
+    forged.signature = provision_request_at(&alice, vec![1], 1000).signature;
+    provisioner.handle(forged).unwrap_err();
+    provisioner.handle_update_mapping(&admin(), update_request(&pubkey(&alice), 137)).unwrap_err();
+    provisioner.handle_update_mapping(&admin(), update_request(&pubkey(&wallet(2)), 137)).unwrap_err();
+
+    let stats = metrics::get_stats(&bucket).unwrap();
+    assert_eq!(stats.errors_by_code["SIGNATURE_MISMATCH"], 1);
+    assert_eq!(stats.errors_by_code["NOT_PROVISIONED"], 2);
+    assert_eq!((stats.provisions, stats.updates), (0, 0));
+}
+
+#[test]
+fn test_metrics_count_external_links_per_chain() {
+    let (provisioner, _) = metered_provisioner();
+    let alice = wallet(1);
+    let metamask = evm_wallet(9);
+    provisioner.handle(provision_request_at(&alice, vec![1, 137], 1000)).unwrap();
+
+    provisioner.handle_link_external(link_external_request(&alice, &metamask, vec![1], "1")).unwrap();
+    // Chain 1 is already external; only chain 137 switches
+    provisioner.handle_link_external(link_external_request(&alice, &metamask, vec![1, 137], "2")).unwrap();
+    provisioner.handle_link_external(link_external_request(&wallet(2), &metamask, vec![1], "1")).unwrap_err();
+
+    let stats = provisioner.handle_stats().unwrap();
+    assert_eq!(stats.external_by_chain, [(chain(1), 1), (chain(137), 1)].into_iter().collect());
+    assert_eq!(stats.provisions_by_chain[&chain(1)], 1);
+    assert_eq!(stats.updates, 2);
+}
+
+#[test]
+fn test_metrics_are_optional() {
+    let provisioner = fixed_clock_provisioner();
+    assert_eq!(provisioner.handle_stats().unwrap_err().code(), "NOT_CONFIGURED");
+    assert_eq!(metrics::get_stats(&MockKvStore::new()).unwrap(), Stats::default());
+}
+
+// =============================================================================
+// USAGE TESTS
+// =============================================================================
+
+#[test]
+fn test_usage_months_are_utc_calendar_months() {
+    assert_eq!(usage::month_of(0), "1970-01");
+    assert_eq!(usage::month_of(951_782_400), "2000-02"); // 2000-02-29
+    assert_eq!(usage::month_of(1_700_000_000), "2023-11");
+    assert_eq!(usage::month_of(1_798_761_599), "2026-12");
+    assert_eq!(usage::month_of(1_798_761_600), "2027-01");
+
+    let kv = MockKvStore::new();
+    assert_eq!(usage::get_usage(&kv, "2026-10").unwrap(), UsageReport { month: "2026-10".to_string(), ..Default::default() });
+    for month in ["2026-13", "2026-00", "2026-1", "26-10", "2026/10"] {
+        assert_eq!(usage::get_usage(&kv, month).unwrap_err().code(), "INVALID_REQUEST", "{}", month);
+    }
+}
+
+#[test]
+fn test_usage_counts_key_creations_and_mapping_operations() {
+    let bucket = MockKvStore::new();
+    let provisioner = fixed_clock_provisioner().with_usage(bucket.clone());
+    let alice = wallet(1);
+    assert_eq!(provisioner.handle_usage_report("1970-01").unwrap().mapping_operations, 0);
+
+    provisioner.handle(provision_request_at(&alice, vec![1, 137], 1000)).unwrap();
+    // A retry maps nothing new but is still an operation
+    provisioner.handle(provision_request_at(&alice, vec![1, 137], 1000)).unwrap();
+    provisioner.handle(labeled_request_at(&alice, vec![1], "cold", 1000)).unwrap();
+    provisioner.handle_update_mapping(&admin(), update_request(&pubkey(&alice), 137)).unwrap();
+    // Failures and reads are not billed
+    provisioner.handle_update_mapping(&admin(), update_request(&pubkey(&wallet(2)), 137)).unwrap_err();
+    provisioner.handle_get(&pubkey(&alice), &[chain(1)]).unwrap();
+
+    let report = provisioner.handle_usage_report("1970-01").unwrap();
+    assert_eq!((report.key_creations, report.mapping_operations), (3, 4));
+    assert_eq!(usage::get_usage(&bucket, "1970-02").unwrap().mapping_operations, 0);
+
+    let unmetered = fixed_clock_provisioner();
+    assert_eq!(unmetered.handle_usage_report("1970-01").unwrap_err().code(), "NOT_CONFIGURED");
+}
+
+// =============================================================================
+// HEALTH TESTS
+// =============================================================================
+
+#[test]
+fn test_ping_round_trips_kv_and_version_names_the_build() {
+    let kv = MockKvStore::new();
+    assert_eq!(health::ping(&kv, 1_700_000_000).unwrap().checked_at, 1_700_000_000);
+    assert_eq!(kv.get(health::PING_KEY).unwrap().as_deref(), Some("1700000000"));
+
+    let unreachable = FlakyKvStore { inner: MockKvStore::new(), writes_left: Mutex::new(0) };
+    assert_eq!(health::ping(&unreachable, 1_700_000_000).unwrap_err().code(), "KV_ERROR");
+
+    let version = health::version();
+    assert_eq!(version.crate_version, env!("CARGO_PKG_VERSION"));
+    assert_eq!(version.schema_version, kv::MAPPING_RECORD_VERSION);
+    // None when built outside a checkout
+    assert!(version.git_sha.is_none_or(|sha| sha.len() == 40));
+}
+
+// =============================================================================
+// LOGGING TESTS
+// =============================================================================
+
+fn logged_provisioner() -> (Provisioner<MockKvStore, MockKeyCreator>, Arc<Mutex<Vec<LogEvent>>>) {
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let sink = events.clone();
+    let provisioner = fixed_clock_provisioner().with_logger(move |event: &LogEvent| sink.lock().unwrap().push(event.clone()));
+    (provisioner, events)
+}
+
+#[test]
+fn test_log_events_carry_request_id_and_outcome() {
+    let (provisioner, events) = logged_provisioner();
+    let alice = wallet(1);
+    let solana_pubkey = pubkey(&alice);
+
+    let mut req = provision_request_at(&alice, vec![1], 1000);
+    req.request_id = Some("req-1".to_string());
+    provisioner.handle(req).unwrap();
+
+    let mut update = update_request(&pubkey(&wallet(2)), 137);
+    update.request_id = Some("req-2".to_string());
+    provisioner.handle_update_mapping(&admin(), update).unwrap_err();
+
+    let events = events.lock().unwrap();
+    assert_eq!(events.len(), 2);
+    assert_eq!(events[0].request_id.as_deref(), Some("req-1"));
+    assert_eq!(events[0].action, "provision");
+    assert_eq!(events[0].outcome, "ok");
+    // The address is logged only by its hash
+    assert_eq!(events[0].pubkey_hash.as_deref(), Some(logging::pubkey_hash(solana_pubkey.as_str()).as_str()));
+    assert!(!serde_json::to_string(&events[0]).unwrap().contains(solana_pubkey.as_str()));
+    assert_eq!(events[1].request_id.as_deref(), Some("req-2"));
+    assert_eq!(events[1].outcome, "NOT_PROVISIONED");
+}
+
+#[test]
+fn test_batch_entries_are_logged_under_batch_request_id() {
+    let (provisioner, events) = logged_provisioner();
+    let mut own = provision_request(&wallet(2), vec![1]);
+    own.request_id = Some("entry".to_string());
+    let batch = ProvisionBatchRequest {
+        requests: vec![provision_request(&wallet(1), vec![1]), own],
+        request_id: Some("batch".to_string()),
+    };
+    provisioner.handle_batch(batch).unwrap();
+
+    let ids: Vec<_> = events.lock().unwrap().iter().map(|event| event.request_id.clone()).collect();
+    assert_eq!(ids, vec![Some("batch".to_string()), Some("entry".to_string())]);
+}
+
+#[test]
+fn test_request_id_does_not_change_idempotency_hash() {
+    let (provisioner, _) = idempotent_provisioner();
+    let alice = wallet(1);
+    let solana_pubkey = pubkey(&alice);
+    provisioner.handle(provision_request(&alice, vec![1, 137])).unwrap();
+
+    // A retry is logged under a new request_id but is the same request
+    let mut req = update_request(&solana_pubkey, 137);
+    req.idempotency_key = Some("update-1".to_string());
+    req.request_id = Some("attempt-1".to_string());
+    let first = provisioner.handle_update_mapping(&admin(), req.clone()).unwrap();
+    req.request_id = Some("attempt-2".to_string());
+    assert_eq!(provisioner.handle_update_mapping(&admin(), req).unwrap().new_evm_address, first.new_evm_address);
+}
+
+#[test]
+fn test_request_id_is_truncated() {
+    let id = "x".repeat(logging::MAX_REQUEST_ID_LEN + 10);
+    let event = logging::event::<()>(Some(&id), "provision", None, std::time::Instant::now(), &Ok(()));
+    assert_eq!(event.request_id.unwrap().len(), logging::MAX_REQUEST_ID_LEN);
+    assert!(event.pubkey_hash.is_none());
+}
@@ -0,0 +1,148 @@
+use alloy_sol_types::SolCall;
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use cubist_wallet_provisioner::error::Result;
+use cubist_wallet_provisioner::mapping;
+use cubist_wallet_provisioner::onchain::{
+    self, set_mapping_calldata, OperatorSigner, RegistryTransaction, SolanaToEvmRegistry, SyncStatus, TxParams,
+};
+use cubist_wallet_provisioner::{ChainId, EvmAddress, KeyClass, KeyType, KvStore, MappingRecord, ProvisionRequest, SolanaPubkey};
+use ed25519_dalek::{Signer, SigningKey};
+use sha3::{Digest, Keccak256};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+/// KV store over a shared map
+#[derive(Clone, Default)]
+struct MapKv(Arc<Mutex<HashMap<String, String>>>);
+
+impl KvStore for MapKv {
+    fn get(&self, key: &str) -> Result<Option<String>> {
+        Ok(self.0.lock().unwrap().get(key).cloned())
+    }
+
+    fn set_if_absent(&self, key: &str, value: &str) -> Result<bool> {
+        let mut data = self.0.lock().unwrap();
+        if data.contains_key(key) {
+            return Ok(false);
+        }
+        data.insert(key.to_string(), value.to_string());
+        Ok(true)
+    }
+
+    fn set(&self, key: &str, value: &str) -> Result<()> {
+        self.0.lock().unwrap().insert(key.to_string(), value.to_string());
+        Ok(())
+    }
+}
+
+/// Signer recording the transactions it signs
+#[derive(Default)]
+struct RecordingSigner(Mutex<Vec<RegistryTransaction>>);
+
+impl OperatorSigner for RecordingSigner {
+    fn sign_transaction(&self, tx: &RegistryTransaction) -> Result<String> {
+        let mut signed = self.0.lock().unwrap();
+        signed.push(tx.clone());
+        Ok(format!("0xsigned{}", signed.len()))
+    }
+}
+
+const PARAMS: TxParams = TxParams { nonce: 7, gas_limit: 100_000, max_fee_per_gas: 30_000_000_000, max_priority_fee_per_gas: 1_000_000_000 };
+
+fn evm(address: &str) -> EvmAddress {
+    EvmAddress::parse(address).unwrap()
+}
+
+fn registry() -> EvmAddress {
+    evm("0x9999999999999999999999999999999999999999")
+}
+
+/// Provision `seed`'s wallet on `chain_ids` with the backend key 0x11…11
+fn provision(kv: &MapKv, seed: u8, chain_ids: &[u64]) -> SolanaPubkey {
+    let wallet = SigningKey::from_bytes(&[seed; 32]);
+    let solana_pubkey = SolanaPubkey::parse(&bs58::encode(wallet.verifying_key().to_bytes()).into_string()).unwrap();
+    let message = format!("Provision EVM wallet for {}", solana_pubkey);
+    let req = ProvisionRequest {
+        solana_pubkey: solana_pubkey.clone(),
+        chain_ids: chain_ids.iter().map(|&chain_id| ChainId::eip155(chain_id)).collect(),
+        signature: BASE64.encode(wallet.sign(message.as_bytes()).to_bytes()),
+        message,
+        label: None,
+        key_type: KeyType::default(),
+        key_class: KeyClass::default(),
+        idempotency_key: None,
+        ttl_secs: None,
+        request_id: None,
+    };
+    let address = evm("0x1111111111111111111111111111111111111111");
+    mapping::store(kv, &req, 10, || Ok(MappingRecord::new(&address, Some("Key#1"), solana_pubkey.as_str(), 10))).unwrap();
+    solana_pubkey
+}
+
+#[test]
+fn test_set_mapping_calldata_matches_the_registry_abi() {
+    let solana_pubkey = SolanaPubkey::parse(&bs58::encode([7u8; 32]).into_string()).unwrap();
+    let address = evm("0x1111111111111111111111111111111111111111");
+    let calldata = set_mapping_calldata(&solana_pubkey, &address, 3);
+
+    let selector = Keccak256::digest(b"setMapping(bytes32,address,uint64)");
+    assert_eq!(calldata[..4], selector[..4]);
+    assert_eq!(calldata.len(), 4 + 3 * 32);
+
+    let call = SolanaToEvmRegistry::setMappingCall::abi_decode(&calldata).unwrap();
+    assert_eq!(call.solanaPubkey.0, [7u8; 32]);
+    assert_eq!(call.evmAddress.to_string().to_lowercase(), address.as_str());
+    assert_eq!(call.version, 3);
+}
+
+#[test]
+fn test_sync_signs_each_revision_once_and_tracks_status() {
+    let kv = MapKv::default();
+    let signer = RecordingSigner::default();
+    let solana_pubkey = provision(&kv, 1, &[137]);
+    let chain_id = ChainId::eip155(137);
+
+    let record = onchain::sync(&kv, &signer, &solana_pubkey, &chain_id, &registry(), PARAMS, 20).unwrap().unwrap();
+    assert_eq!((record.version, record.status.clone(), record.signed_tx.as_str()), (0, SyncStatus::Signed, "0xsigned1"));
+    let tx = signer.0.lock().unwrap()[0].clone();
+    assert_eq!((tx.chain_id, &tx.to, tx.params), (137, &registry(), PARAMS));
+    assert_eq!(tx.to_json()["nonce"], "0x7");
+
+    // The revision is signed: nothing to do until it changes or fails
+    assert!(onchain::sync(&kv, &signer, &solana_pubkey, &chain_id, &registry(), PARAMS, 21).unwrap().is_none());
+
+    let submitted = SyncStatus::Submitted { tx_hash: "0xabc".to_string() };
+    onchain::record_status(&kv, &solana_pubkey, &chain_id, 0, submitted, 22).unwrap();
+    let failed = SyncStatus::Failed { error: "dropped".to_string() };
+    onchain::record_status(&kv, &solana_pubkey, &chain_id, 0, failed, 23).unwrap();
+    let retried = onchain::sync(&kv, &signer, &solana_pubkey, &chain_id, &registry(), PARAMS, 24).unwrap().unwrap();
+    assert_eq!(retried.signed_tx, "0xsigned2");
+
+    // An update moves the mapping to a new revision, which needs a new sync
+    let rotated = MappingRecord::new(&evm("0x2222222222222222222222222222222222222222"), Some("Key#2"), "admin@test", 30);
+    mapping::apply_update(&kv, &solana_pubkey, &chain_id, &rotated, None, None, "admin@test", 30).unwrap();
+    let record = onchain::sync(&kv, &signer, &solana_pubkey, &chain_id, &registry(), PARAMS, 31).unwrap().unwrap();
+    assert_eq!((record.version, &record.address), (1, &rotated.address));
+
+    // Reports about the superseded transaction are refused
+    let confirmed = SyncStatus::Confirmed { tx_hash: "0xabc".to_string() };
+    assert_eq!(onchain::record_status(&kv, &solana_pubkey, &chain_id, 0, confirmed.clone(), 32).unwrap_err().code(), "INVALID_REQUEST");
+    onchain::record_status(&kv, &solana_pubkey, &chain_id, 1, confirmed.clone(), 32).unwrap();
+    assert_eq!(onchain::get_sync(&kv, &solana_pubkey, &chain_id).unwrap().unwrap().status, confirmed);
+}
+
+#[test]
+fn test_sync_requires_an_evm_chain_mapping() {
+    let kv = MapKv::default();
+    let signer = RecordingSigner::default();
+    let solana_pubkey = provision(&kv, 1, &[1]);
+
+    let solana_chain = ChainId::parse("solana:5eykt4UsFv8P8NJdTREpY1vzqKqZKvdp").unwrap();
+    let err = onchain::sync(&kv, &signer, &solana_pubkey, &solana_chain, &registry(), PARAMS, 20).unwrap_err();
+    assert_eq!(err.code(), "INVALID_REQUEST");
+
+    let unknown = SolanaPubkey::parse(&bs58::encode([9u8; 32]).into_string()).unwrap();
+    let err = onchain::sync(&kv, &signer, &unknown, &ChainId::eip155(1), &registry(), PARAMS, 20).unwrap_err();
+    assert_eq!(err.code(), "NOT_PROVISIONED");
+    assert!(signer.0.lock().unwrap().is_empty());
+}
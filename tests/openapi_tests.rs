@@ -0,0 +1,65 @@
+use cubist_wallet_provisioner::{authz, openapi};
+use serde_json::Value;
+use std::collections::BTreeSet;
+
+/// Every `$ref` in `value`
+fn refs<'a>(value: &'a Value, found: &mut Vec<&'a str>) {
+    match value {
+        Value::Object(map) => {
+            if let Some(Value::String(reference)) = map.get("$ref") {
+                found.push(reference);
+            }
+            map.values().for_each(|v| refs(v, found));
+        }
+        Value::Array(items) => items.iter().for_each(|v| refs(v, found)),
+        _ => {}
+    }
+}
+
+#[test]
+fn test_document_covers_rest_routes() {
+    let document = openapi::document();
+    assert_eq!(document["openapi"], "3.1.0");
+
+    let provision = &document["paths"]["/provision"]["post"];
+    assert_eq!(provision["requestBody"]["content"]["application/json"]["schema"]["$ref"], "#/components/schemas/ProvisionRequest");
+    assert_eq!(provision["responses"]["200"]["content"]["application/json"]["schema"]["$ref"], "#/components/schemas/ProvisionResponse");
+    assert!(document["paths"]["/update"]["post"].is_object());
+    assert!(document["paths"]["/mappings/{solana_pubkey}"]["get"].is_object());
+
+    // Request fields with a serde default are optional
+    let request = &document["components"]["schemas"]["ProvisionRequest"];
+    let required: BTreeSet<&str> = request["required"].as_array().unwrap().iter().map(|v| v.as_str().unwrap()).collect();
+    assert_eq!(required, BTreeSet::from(["solana_pubkey", "message", "signature"]));
+}
+
+#[test]
+fn test_policy_request_lists_every_action() {
+    let document = openapi::document();
+    let actions: BTreeSet<&str> = document["components"]["schemas"]["PolicyRequest"]["oneOf"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|variant| variant["properties"]["action"]["const"].as_str().unwrap())
+        .collect();
+    assert_eq!(actions.len(), 54);
+    for action in ["store", "get", "update_self", "link_external", "store_batch", "set_config", "block", "audit_query"] {
+        assert!(actions.contains(action), "{} missing", action);
+    }
+    // An action left out of the matrix would silently require Owner
+    for action in &actions {
+        assert!(authz::MATRIX.iter().any(|(name, _)| name == action), "{} is not in authz::MATRIX", action);
+    }
+}
+
+#[test]
+fn test_every_reference_resolves() {
+    let document = openapi::document();
+    let mut found = Vec::new();
+    refs(&document, &mut found);
+    assert!(!found.is_empty());
+    for reference in found {
+        let pointer = reference.strip_prefix('#').unwrap();
+        assert!(document.pointer(pointer).is_some(), "{} does not resolve", reference);
+    }
+}
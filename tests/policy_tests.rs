@@ -0,0 +1,273 @@
+mod common;
+
+use common::fixed_clock_provisioner;
+use cubist_wallet_provisioner::approval::{self, PendingStatus};
+use cubist_wallet_provisioner::auth;
+use cubist_wallet_provisioner::config::{self, ConfigUpdate};
+use cubist_wallet_provisioner::kv;
+use cubist_wallet_provisioner::logging;
+use cubist_wallet_provisioner::mapping;
+use cubist_wallet_provisioner::policy_api::PolicyRequest;
+use cubist_wallet_provisioner::request_auth;
+use cubist_wallet_provisioner::shadow::{self, ShadowWrites, Shadowed};
+use cubist_wallet_provisioner::signing_gate;
+use cubist_wallet_provisioner::testing::{
+    chain, evm, provision_request, provision_request_at, pubkey, update_self_request, wallet, MockKeyCreator, MockKvStore, TestContext,
+};
+use cubist_wallet_provisioner::{EvmAddress, KvStore, MappingRecord, Provisioner};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use ed25519_dalek::Signer;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+// =============================================================================
+// REQUEST AUTH TESTS
+// =============================================================================
+
+const REQUEST_SECRET: &str = "backend-secret-0123456789abcdef-0123";
+
+#[test]
+fn test_request_auth_accepts_only_the_signed_body() {
+    let request = serde_json::json!({ "action": "get", "solana_pubkey": pubkey(&wallet(1)).to_string(), "chain_ids": ["eip155:137"] });
+    let signed = request_auth::sign_request(request, REQUEST_SECRET).unwrap();
+    request_auth::verify_request(&signed.to_string(), REQUEST_SECRET).unwrap();
+
+    // Canonicalized, so the order of the fields does not matter
+    let reordered = format!(r#"{{"auth":{},"chain_ids":["eip155:137"],"solana_pubkey":{},"action":"get"}}"#, signed["auth"], signed["solana_pubkey"]);
+    request_auth::verify_request(&reordered, REQUEST_SECRET).unwrap();
+
+    let reason = |body: &str, secret: &str| {
+        let err = request_auth::verify_request(body, secret).unwrap_err();
+        assert_eq!(err.code(), "REQUEST_AUTH_FAILED");
+        err.to_string()
+    };
+    let mut altered = signed.clone();
+    altered["chain_ids"] = serde_json::json!(["eip155:1"]);
+    assert!(reason(&altered.to_string(), REQUEST_SECRET).ends_with("auth does not match the body"));
+    assert!(reason(&signed.to_string(), "another-secret-0123456789abcdef-0123").ends_with("auth does not match the body"));
+    altered["auth"] = "not hex".into();
+    assert!(reason(&altered.to_string(), REQUEST_SECRET).ends_with("auth is not a hex HMAC-SHA256"));
+    assert!(reason(r#"{"action":"get"}"#, REQUEST_SECRET).ends_with("missing auth"));
+}
+
+#[test]
+fn test_request_auth_secret_is_configured_but_never_shown() {
+    let kv = MockKvStore::new();
+    let short = ConfigUpdate { request_auth_secret: Some("short".to_string()), ..Default::default() };
+    assert_eq!(config::set_config(&kv, short).unwrap_err().code(), "INVALID_REQUEST");
+
+    let update = ConfigUpdate { request_auth_secret: Some(REQUEST_SECRET.to_string()), ..Default::default() };
+    let set = config::set_config(&kv, update).unwrap();
+    assert_eq!(set.request_auth_secret.as_deref(), Some(REQUEST_SECRET));
+    assert_eq!(set.redacted().request_auth_secret.as_deref(), Some(config::REDACTED));
+
+    // Sending the redacted value back cannot replace the secret
+    let echoed = ConfigUpdate { request_auth_secret: Some(config::REDACTED.to_string()), ..Default::default() };
+    assert!(config::set_config(&kv, echoed).is_err());
+    let off = ConfigUpdate { request_auth_secret: Some(String::new()), ..Default::default() };
+    assert_eq!(config::set_config(&kv, off).unwrap().request_auth_secret, None);
+}
+
+// =============================================================================
+// SHADOW MODE TESTS
+// =============================================================================
+
+#[test]
+fn test_shadow_mode_runs_flows_without_writing() {
+    let kv = MockKvStore::new();
+    let writes = ShadowWrites::new();
+    let keys = MockKeyCreator {
+        default_key_counter: Arc::new(Mutex::new(0)),
+        chain_key_counter: Arc::new(Mutex::new(1000)),
+    };
+    let provisioner = Provisioner::new(Shadowed::new(kv.clone(), "solana_to_evm", Some(writes.clone())), keys);
+    let alice = wallet(1);
+    let response = provisioner.handle(provision_request(&alice, vec![1, 137])).unwrap();
+
+    // The flow reads its own writes, but none reached the bucket
+    let mappings = provisioner.handle_get(&pubkey(&alice), &[]).unwrap();
+    assert_eq!(mappings.default_address, Some(response.evm_address.clone()));
+    assert!(kv.list_keys(None, 100).unwrap().is_empty());
+    // A repeated provision finds the mapping the first one kept in memory
+    assert_eq!(provisioner.handle(provision_request(&alice, vec![1])).unwrap().evm_address, response.evm_address);
+
+    let logged = writes.logged_keys();
+    let default = format!("solana_to_evm/default:{}", logging::pubkey_hash(pubkey(&alice).as_str()));
+    assert!(logged.contains(&default), "{:?}", logged);
+    assert!(logged.iter().all(|key| !key.contains(pubkey(&alice).as_str())));
+
+    // Without writes to keep, the store is written through
+    let live = Shadowed::new(kv.clone(), "solana_to_evm", None);
+    live.set("k", "v").unwrap();
+    assert_eq!(kv.get("k").unwrap().as_deref(), Some("v"));
+}
+
+#[test]
+fn test_shadow_build_is_named_by_git_sha() {
+    let sha = "8a05668e0c6a2151fb538e5b7b8a9f042f42f4ee";
+    assert!(shadow::is_shadow_build(Some("8a05668"), Some(sha)));
+    assert!(!shadow::is_shadow_build(Some("0c6a215"), Some(sha)));
+    assert!(!shadow::is_shadow_build(Some("8a05668"), None));
+    assert!(!shadow::is_shadow_build(None, Some(sha)));
+
+    let kv = MockKvStore::new();
+    for invalid in ["8a056", "not-a-sha"] {
+        let update = ConfigUpdate { shadow_build: Some(invalid.to_string()), ..Default::default() };
+        assert_eq!(config::set_config(&kv, update).unwrap_err().code(), "INVALID_REQUEST");
+    }
+    let update = ConfigUpdate { shadow_build: Some("8A05668".to_string()), ..Default::default() };
+    assert_eq!(config::set_config(&kv, update).unwrap().shadow_build.as_deref(), Some("8a05668"));
+}
+
+// =============================================================================
+// POLICY REQUEST TESTS
+// =============================================================================
+
+#[test]
+fn test_policy_request_parse_fails_with_invalid_request() {
+    let alice = pubkey(&wallet(1));
+    let req = PolicyRequest::parse(Some(&format!(r#"{{"action":"get","solana_pubkey":"{}"}}"#, alice))).unwrap();
+    assert_eq!(req.action(), "get");
+    assert_eq!(req.solana_pubkey(), Some(&alice));
+
+    for body in [
+        None,
+        Some(""),
+        Some("\u{0}"),
+        Some("[]"),
+        Some(r#"{"action":"nope"}"#),
+        Some(r#"{"action":"get"}"#),
+        Some(r#"{"action":"get","solana_pubkey":"0x0"}"#),
+        Some(r#"{"action":"get","solana_pubkey":[[[[[[[[[[[[[[[[[[[[[[[[[[[[[[[[]]]]]]]]]]]]]]]]]]]]]]]]]]]]]]]]}"#),
+    ] {
+        let err = PolicyRequest::parse(body).err().unwrap_or_else(|| panic!("{:?} parsed", body));
+        assert_eq!(err.code(), "INVALID_REQUEST", "{:?}", body);
+    }
+}
+
+// =============================================================================
+// SHARED FLOW TESTS (as run by the policy, with backend-created keys)
+// =============================================================================
+
+#[test]
+fn test_get_returns_requested_chains_with_key_ids() {
+    let ctx = TestContext::new();
+    let alice = wallet(1);
+    let solana_pubkey = pubkey(&alice);
+    let provisioned = ctx.handle(provision_request(&alice, vec![1, 137])).unwrap();
+
+    let result = ctx.provisioner.handle_get(&solana_pubkey, &[chain(1), chain(42161)]).unwrap();
+    assert_eq!(result.default_address, Some(provisioned.evm_address.clone()));
+    assert_eq!(result.default_key_id, provisioned.key_id);
+    assert_eq!(result.chain_mappings.len(), 2);
+    assert_eq!(result.chain_key_ids.get(&chain(1)), Some(&format!("Key#{}", provisioned.evm_address.as_str())));
+    assert_eq!(result.chain_inherited, HashMap::from([(chain(1), false), (chain(42161), true)]));
+
+    let unknown = ctx.provisioner.handle_get(&pubkey(&wallet(2)), &[chain(1)]).unwrap();
+    assert!(unknown.default_address.is_none() && unknown.chain_mappings.is_empty());
+}
+
+#[test]
+fn test_store_with_backend_created_key_keeps_first_address() {
+    let kv = MockKvStore::new();
+    let alice = wallet(1);
+    let first = evm("0x1111111111111111111111111111111111111111");
+    let second = evm("0x2222222222222222222222222222222222222222");
+
+    let record = |address: &EvmAddress| MappingRecord::new(address, Some("Key#backend"), "test", 0);
+    let stored = mapping::store(&kv, &provision_request(&alice, vec![1]), 0, || Ok(record(&first))).unwrap();
+    assert_eq!(stored.evm_address, first);
+
+    // A later store with another backend key adds the chain, mapped to the stored default
+    let again = mapping::store(&kv, &provision_request(&alice, vec![1, 137]), 0, || Ok(record(&second))).unwrap();
+    assert_eq!(again.evm_address, first);
+    assert_eq!(again.chain_mappings.get(&chain(137)), Some(&first));
+    assert_eq!(kv::get_reverse_mapping(&kv, &second).unwrap(), None);
+}
+
+#[test]
+fn test_approved_proposal_applies_proposed_address() {
+    let ctx = TestContext::new();
+    let alice = wallet(1);
+    let solana_pubkey = pubkey(&alice);
+    let old = ctx.handle(provision_request(&alice, vec![137])).unwrap().evm_address;
+    let new = evm("0x3333333333333333333333333333333333333333");
+
+    let pending = approval::propose(&ctx.kv, &solana_pubkey, &chain(137), Some(&new), Some("Key#new"), false, "alice@test", 10).unwrap();
+    let json = serde_json::to_value(&pending).unwrap();
+    assert_eq!(json["new_evm_address"], serde_json::json!(new.to_string()));
+
+    let approved = approval::resolve(&ctx.kv, &solana_pubkey, &chain(137), pending.id, "bob@test", PendingStatus::Approved, 20).unwrap();
+    let proposed = approved.new_evm_address.unwrap();
+    let record = MappingRecord::new(&proposed, approved.new_key_id.as_deref(), "bob@test", 20);
+    mapping::apply_update(&ctx.kv, &solana_pubkey, &chain(137), &record, None, None, "bob@test", 20).unwrap();
+
+    assert_eq!(ctx.get_existing_mapping(&solana_pubkey, 137).unwrap(), Some(new.clone()));
+    let history = mapping::history(&ctx.kv, &solana_pubkey, &chain(137)).unwrap();
+    assert_eq!(history.entries.len(), 1);
+    assert_eq!((&history.entries[0].address, history.entries[0].replaced_by.as_str()), (&old, "bob@test"));
+    assert_eq!(kv::get_reverse_mapping(&ctx.kv, &new).unwrap(), Some(solana_pubkey));
+}
+
+#[test]
+fn test_update_self_address_authorization_is_single_use() {
+    let kv = MockKvStore::new();
+    let alice = wallet(1);
+    let solana_pubkey = pubkey(&alice);
+    let new = evm("0x3333333333333333333333333333333333333333");
+    let new_key_id = format!("Key#{}", new);
+    assert_eq!(signing_gate::key_address(&new_key_id).unwrap(), new);
+
+    let message = auth::update_self_address_message(&solana_pubkey, &chain(137), &new, &new_key_id, "1", 100);
+    let signature = BASE64.encode(alice.sign(message.as_bytes()).to_bytes());
+
+    // Signed for another address or key
+    let other = evm("0x4444444444444444444444444444444444444444");
+    let other_address = auth::update_self_address_message(&solana_pubkey, &chain(137), &other, &new_key_id, "1", 100);
+    assert!(mapping::authorize_update_self(&kv, &solana_pubkey, &other_address, "1", 100, &signature, 50).is_err());
+    let other_key = auth::update_self_address_message(&solana_pubkey, &chain(137), &new, &format!("Key#{}", other), "1", 100);
+    assert!(mapping::authorize_update_self(&kv, &solana_pubkey, &other_key, "1", 100, &signature, 50).is_err());
+
+    mapping::authorize_update_self(&kv, &solana_pubkey, &message, "1", 100, &signature, 50).unwrap();
+    let err = mapping::authorize_update_self(&kv, &solana_pubkey, &message, "1", 100, &signature, 50).unwrap_err();
+    assert_eq!(err.code(), "NONCE_TOO_LOW");
+
+    // A nonce claimed without raising the head (a concurrent request) is still single-use
+    kv.set(&kv::nonce_head_key(&solana_pubkey), "0").unwrap();
+    let err = mapping::authorize_update_self(&kv, &solana_pubkey, &message, "1", 100, &signature, 50).unwrap_err();
+    assert_eq!(err.code(), "NONCE_USED");
+}
+
+#[test]
+fn test_update_self_nonces_must_increase() {
+    let provisioner = fixed_clock_provisioner();
+    let alice = wallet(1);
+    provisioner.handle(provision_request_at(&alice, vec![1, 137], 1000)).unwrap();
+
+    // Signed earlier but never sent: a later request invalidates it
+    let held_back = update_self_request(&alice, 137, "5", 1300);
+    provisioner.handle_update_self(update_self_request(&alice, 1, "7", 1300)).unwrap();
+    let err = provisioner.handle_update_self(held_back).unwrap_err();
+    assert_eq!(err.code(), "NONCE_TOO_LOW");
+    assert_eq!(kv::get_nonce_head(provisioner.kv(), &pubkey(&alice)).unwrap(), Some(7));
+
+    // Gaps are fine
+    provisioner.handle_update_self(update_self_request(&alice, 137, "1700000000000", 1300)).unwrap();
+}
+
+#[test]
+fn test_update_self_rejects_long_lived_authorization() {
+    let provisioner = fixed_clock_provisioner();
+    let alice = wallet(1);
+    provisioner.handle(provision_request_at(&alice, vec![1], 1000)).unwrap();
+
+    let far = 1000 + auth::MAX_AUTHORIZATION_TTL_SECS + 1;
+    let err = provisioner.handle_update_self(update_self_request(&alice, 1, "1", far)).unwrap_err();
+    assert_eq!(err.code(), "INVALID_REQUEST");
+
+    let err = provisioner.handle_update_self(update_self_request(&alice, 1, "nonce-1", 1300)).unwrap_err();
+    assert_eq!(err.code(), "INVALID_NONCE");
+
+    // Neither burned the nonce
+    provisioner.handle_update_self(update_self_request(&alice, 1, "1", far - 1)).unwrap();
+}
@@ -0,0 +1,176 @@
+mod common;
+
+use common::{approval_provisioner, evm_wallet, link_external_request, named, propose_request, resolve_request};
+use cubist_wallet_provisioner::anonymize::{self, AnonymizeRequest};
+use cubist_wallet_provisioner::audit::AuditQuery;
+use cubist_wallet_provisioner::config::{self, ConfigUpdate};
+use cubist_wallet_provisioner::kv::{self, chain_key, default_key, reverse_key};
+use cubist_wallet_provisioner::privacy::{self, HashedKeys, Pepper};
+use cubist_wallet_provisioner::testing::{chain, provision_request_at, pubkey, wallet, MockKeyCreator, MockKvStore};
+use cubist_wallet_provisioner::txn;
+use cubist_wallet_provisioner::verify::VerifyRequest;
+use cubist_wallet_provisioner::{KvStore, Provisioner, SolanaPubkey};
+use std::sync::{Arc, Mutex};
+
+// =============================================================================
+// ANONYMIZE TESTS
+// =============================================================================
+
+const ERASURE_SALT: &str = "case-2026-0142-secret";
+
+fn anonymize_request(solana_pubkey: &SolanaPubkey) -> AnonymizeRequest {
+    AnonymizeRequest { solana_pubkey: solana_pubkey.clone(), salt: ERASURE_SALT.to_string(), request_id: None }
+}
+
+#[test]
+fn test_anonymize_replaces_records_with_pseudonym() {
+    let (provisioner, _) = approval_provisioner();
+    let alice = wallet(1);
+    let solana_pubkey = pubkey(&alice);
+    let provisioned = provisioner.handle(provision_request_at(&alice, vec![1, 137], 1000)).unwrap();
+    provisioner.handle_propose_update(&named("alice@test"), propose_request(&solana_pubkey, 137)).unwrap();
+    let rotated = provisioner.handle_approve_update(&named("bob@test"), resolve_request(&solana_pubkey, 137, 1)).unwrap();
+
+    let receipt = provisioner.handle_anonymize(&named("alice@test"), anonymize_request(&solana_pubkey)).unwrap();
+    assert_eq!(receipt.pseudonym, anonymize::pseudonym(&solana_pubkey, ERASURE_SALT));
+    assert_eq!(receipt.anonymized_by, "alice@test");
+    assert_eq!(receipt.reserved_addresses, 2);
+
+    // Nothing left links the user to their addresses
+    let kv = provisioner.kv();
+    for key in [default_key(&solana_pubkey), chain_key(&solana_pubkey, &chain(137)), reverse_key(&provisioned.evm_address)] {
+        let raw = kv.get(&key).unwrap().unwrap();
+        assert!(raw.contains(&receipt.pseudonym));
+        assert!(!raw.contains(provisioned.evm_address.as_str()) && !raw.contains(solana_pubkey.as_str()), "{} still holds {}", key, raw);
+    }
+    assert_eq!(kv.get(&kv::history_key(&solana_pubkey, &chain(137))).unwrap().as_deref(), Some("[]"));
+    assert!(txn::get(kv, &solana_pubkey, 1).unwrap().unwrap().writes.is_empty());
+    assert_eq!(provisioner.handle_history(&solana_pubkey, &chain(137)).unwrap_err().code(), "ANONYMIZED");
+    assert_eq!(provisioner.handle_get(&solana_pubkey, &[]).unwrap_err().code(), "ANONYMIZED");
+    assert_eq!(provisioner.handle_reverse_get(&rotated.new_evm_address).unwrap_err().code(), "ANONYMIZED");
+    assert_eq!(provisioner.handle_get_retirement(&provisioned.evm_address).unwrap_err().code(), "ANONYMIZED");
+
+    // The user cannot be provisioned again, and the scans skip the tombstones
+    assert_eq!(provisioner.handle(provision_request_at(&alice, vec![1], 1000)).unwrap_err().code(), "ANONYMIZED");
+    let report = provisioner.handle_verify(&named("alice@test"), VerifyRequest::default()).unwrap();
+    assert!(report.violations.is_empty(), "{:?}", report.violations);
+
+    // Audited under the pseudonym; a retry returns the first receipt
+    let records = provisioner.handle_audit_query(&named("alice@test"), &AuditQuery::default()).unwrap().records;
+    let audited = records.iter().find(|record| record.action == "anonymize").unwrap();
+    assert_eq!(audited.subject.as_deref(), Some(receipt.pseudonym.as_str()));
+    let retry = AnonymizeRequest { salt: "another-salt-entirely".to_string(), ..anonymize_request(&solana_pubkey) };
+    assert_eq!(provisioner.handle_anonymize(&named("bob@test"), retry).unwrap(), receipt);
+}
+
+#[test]
+fn test_anonymized_addresses_stay_reserved() {
+    let (provisioner, _) = approval_provisioner();
+    let alice = wallet(1);
+    let solana_pubkey = pubkey(&alice);
+    let metamask = evm_wallet(9);
+    provisioner.handle(provision_request_at(&alice, vec![1], 1000)).unwrap();
+    provisioner.handle_link_external(link_external_request(&alice, &metamask, vec![137], "1")).unwrap();
+
+    let err = provisioner.handle_anonymize(&named("mallory@test"), anonymize_request(&solana_pubkey)).unwrap_err();
+    assert_eq!(err.code(), "NOT_ADMIN");
+    let short_salt = AnonymizeRequest { salt: "short".to_string(), ..anonymize_request(&solana_pubkey) };
+    assert_eq!(provisioner.handle_anonymize(&named("alice@test"), short_salt).unwrap_err().code(), "INVALID_REQUEST");
+    let err = provisioner.handle_anonymize(&named("alice@test"), anonymize_request(&pubkey(&wallet(2)))).unwrap_err();
+    assert_eq!(err.code(), "NOT_PROVISIONED");
+
+    let receipt = provisioner.handle_anonymize(&named("alice@test"), anonymize_request(&solana_pubkey)).unwrap();
+    assert_eq!(receipt.reserved_addresses, 2);
+
+    // Another user cannot claim the erased user's wallet
+    let err = provisioner.handle_link_external(link_external_request(&wallet(2), &metamask, vec![1], "1")).unwrap_err();
+    assert_eq!(err.code(), "ADDRESS_OWNED");
+    assert!(err.to_string().ends_with(&receipt.pseudonym));
+}
+
+// =============================================================================
+// PRIVACY MODE TESTS
+// =============================================================================
+
+const KV_PEPPER: &[u8] = b"test-pepper-0123456789abcdef-0123456789";
+
+fn hashed_provisioner(kv: &MockKvStore) -> Provisioner<HashedKeys<MockKvStore>, MockKeyCreator> {
+    let keys = MockKeyCreator {
+        default_key_counter: Arc::new(Mutex::new(0)),
+        chain_key_counter: Arc::new(Mutex::new(1000)),
+    };
+    let pepper = Pepper::new(KV_PEPPER).unwrap();
+    Provisioner::new(HashedKeys::new(kv.clone(), Some(pepper)), keys).with_clock(|| 1000)
+}
+
+#[test]
+fn test_hashed_keys_do_not_name_users() {
+    let kv = MockKvStore::new();
+    let provisioner = hashed_provisioner(&kv);
+    let alice = wallet(1);
+    let solana_pubkey = pubkey(&alice);
+
+    let provisioned = provisioner.handle(provision_request_at(&alice, vec![1, 137], 1000)).unwrap();
+    let stored = kv.list_keys(None, 100).unwrap();
+    assert!(stored.iter().all(|key| !key.contains(solana_pubkey.as_str())), "{:?}", stored);
+    let pepper = Pepper::new(KV_PEPPER).unwrap();
+    assert!(stored.contains(&format!("default:{}", pepper.hash(solana_pubkey.as_str()))));
+
+    // Reads go through the same hashing; values are unchanged
+    let mapping = provisioner.handle_get(&solana_pubkey, &[chain(137)]).unwrap();
+    assert_eq!(mapping.default_address, Some(provisioned.evm_address.clone()));
+    assert_eq!(provisioner.handle_reverse_get(&provisioned.evm_address).unwrap(), Some(solana_pubkey));
+    assert_eq!(Pepper::new(b"too short").err().unwrap().code(), "INVALID_REQUEST");
+}
+
+#[test]
+fn test_kv_pepper_is_configured_once_and_never_shown() {
+    let kv = MockKvStore::new();
+    let short = ConfigUpdate { kv_pepper: Some("too short".to_string()), ..Default::default() };
+    assert_eq!(config::set_config(&kv, short).unwrap_err().code(), "INVALID_REQUEST");
+
+    let pepper = String::from_utf8(KV_PEPPER.to_vec()).unwrap();
+    let set = config::set_config(&kv, ConfigUpdate { kv_pepper: Some(pepper.clone()), ..Default::default() }).unwrap();
+    assert_eq!(config::get_config(&kv).unwrap().kv_pepper, Some(pepper.clone()));
+    assert_eq!(set.redacted().kv_pepper.as_deref(), Some(config::REDACTED));
+
+    // Changing it would orphan every key, so it stays
+    for update in [pepper.clone(), config::REDACTED.to_string(), String::new()] {
+        let update = ConfigUpdate { kv_pepper: Some(update), ..Default::default() };
+        assert_eq!(config::set_config(&kv, update).unwrap_err().to_string(), "Invalid request: kv_pepper cannot be changed once set");
+    }
+    let other = ConfigUpdate { materialize_inherited: Some(true), ..Default::default() };
+    assert_eq!(config::set_config(&kv, other).unwrap().kv_pepper, Some(pepper));
+}
+
+#[test]
+fn test_plaintext_keys_migrate_to_hashed_keys() {
+    let kv = MockKvStore::new();
+    let plain = Provisioner::new(kv.clone(), MockKeyCreator { default_key_counter: Arc::new(Mutex::new(0)), chain_key_counter: Arc::new(Mutex::new(1000)) })
+        .with_clock(|| 1000);
+    let alice = wallet(1);
+    let solana_pubkey = pubkey(&alice);
+    let provisioned = plain.handle(provision_request_at(&alice, vec![1, 137], 1000)).unwrap();
+
+    let pepper = Pepper::new(KV_PEPPER).unwrap();
+    let mut cursor = None;
+    let mut copied = 0;
+    loop {
+        let report = privacy::migrate_plaintext_batch(&kv, &pepper, cursor.as_deref(), Some(2)).unwrap();
+        assert!(report.conflicts.is_empty());
+        copied += report.copied;
+        cursor = report.next_cursor;
+        if cursor.is_none() {
+            break;
+        }
+    }
+    assert!(copied > 0);
+    let rerun = privacy::migrate_plaintext_batch(&kv, &pepper, None, Some(1000)).unwrap();
+    assert_eq!((rerun.copied, rerun.conflicts.len(), rerun.unchanged), (0, 0, copied));
+
+    // The hashed build sees the same mappings; the plaintext keys are left for the old build
+    let hashed = hashed_provisioner(&kv);
+    assert_eq!(hashed.handle_get(&solana_pubkey, &[]).unwrap().default_address, Some(provisioned.evm_address.clone()));
+    assert_eq!(hashed.handle(provision_request_at(&alice, vec![1, 137], 1000)).unwrap().evm_address, provisioned.evm_address);
+    assert!(kv.get(&default_key(&solana_pubkey)).unwrap().is_some());
+}
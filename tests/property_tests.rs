@@ -0,0 +1,195 @@
+use cubist_wallet_provisioner::error::Result;
+use cubist_wallet_provisioner::kv::{self, MappingRecord};
+use cubist_wallet_provisioner::testing::{admin, admins, chain, provision_request, pubkey, update_request, wallet, MockKeyCreator, MockKvStore};
+use cubist_wallet_provisioner::{ChainId, CreatedKey, EvmAddress, KeyClass, KeyCreator, KeyType, Provisioner, RotateRequest, SolanaPubkey};
+use proptest::prelude::*;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+const CHAINS: [u64; 3] = [1, 137, 42161];
+
+/// Keys created so far: default keys by Solana address, and chain keys
+#[derive(Clone, Default)]
+struct KeyCounts {
+    default_keys: Arc<Mutex<HashMap<String, u32>>>,
+    chain_keys: Arc<Mutex<u32>>,
+}
+
+/// `MockKeyCreator` counting the keys it creates
+struct CountingKeys {
+    inner: MockKeyCreator,
+    counts: KeyCounts,
+}
+
+impl KeyCreator for CountingKeys {
+    fn create_evm_key(&self, solana_pubkey: &str) -> Result<CreatedKey> {
+        *self.counts.default_keys.lock().unwrap().entry(solana_pubkey.to_string()).or_default() += 1;
+        self.inner.create_evm_key(solana_pubkey)
+    }
+
+    fn create_evm_key_for_chain(&self, solana_pubkey: &str, chain_id: &ChainId) -> Result<CreatedKey> {
+        *self.counts.chain_keys.lock().unwrap() += 1;
+        self.inner.create_evm_key_for_chain(solana_pubkey, chain_id)
+    }
+
+    fn create_labeled_evm_key(&self, solana_pubkey: &str, label: &str, chain_id: Option<&ChainId>) -> Result<CreatedKey> {
+        self.inner.create_labeled_evm_key(solana_pubkey, label, chain_id)
+    }
+
+    fn create_typed_key(&self, solana_pubkey: &str, _key_type: KeyType, _key_class: KeyClass) -> Result<CreatedKey> {
+        self.create_evm_key(solana_pubkey)
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Op {
+    Provision { user: u8, chains: Vec<u64> },
+    /// Another writer storing a chain mapping first, with its reverse and
+    /// index entries
+    Store { user: u8, chain: u64 },
+    Get { user: u8 },
+    /// `retry`: sent twice under one idempotency key
+    Update { user: u8, chain: u64, retry: bool },
+    Rotate { user: u8, chain: u64 },
+}
+
+fn op() -> impl Strategy<Value = Op> {
+    let user = 1u8..=3;
+    let chain = prop::sample::select(CHAINS.to_vec());
+    prop_oneof![
+        3 => (user.clone(), prop::sample::subsequence(CHAINS.to_vec(), 1..=CHAINS.len())).prop_map(|(user, chains)| Op::Provision { user, chains }),
+        1 => (user.clone(), chain.clone()).prop_map(|(user, chain)| Op::Store { user, chain }),
+        2 => user.clone().prop_map(|user| Op::Get { user }),
+        2 => (user.clone(), chain.clone(), any::<bool>()).prop_map(|(user, chain, retry)| Op::Update { user, chain, retry }),
+        1 => (user, chain).prop_map(|(user, chain)| Op::Rotate { user, chain }),
+    ]
+}
+
+/// What the store should hold, from the responses seen so far
+#[derive(Default)]
+struct Model {
+    defaults: HashMap<u8, EvmAddress>,
+    chains: HashMap<(u8, u64), EvmAddress>,
+    chain_keys: u32,
+}
+
+impl Model {
+    /// Record `address` for (`user`, `chain`) unless it already has one, which
+    /// it must then be
+    fn first_write(&mut self, user: u8, chain: u64, address: &EvmAddress) -> std::result::Result<(), TestCaseError> {
+        let stored = self.chains.entry((user, chain)).or_insert_with(|| address.clone());
+        prop_assert_eq!(&*stored, address, "user {} chain {} was overwritten", user, chain);
+        Ok(())
+    }
+}
+
+fn user(user: u8) -> SolanaPubkey {
+    pubkey(&wallet(user))
+}
+
+/// Run `op`, checking its response against `model` and updating it
+fn apply(provisioner: &Provisioner<MockKvStore, CountingKeys>, kv: &MockKvStore, model: &mut Model, op: &Op, step: usize) -> std::result::Result<(), TestCaseError> {
+    match op {
+        Op::Provision { user, chains } => {
+            let response = provisioner.handle(provision_request(&wallet(*user), chains.clone())).unwrap();
+            let default = model.defaults.entry(*user).or_insert_with(|| response.evm_address.clone());
+            prop_assert_eq!(&response.evm_address, &*default, "default of user {} changed", user);
+            for evm_chain_id in chains {
+                model.first_write(*user, *evm_chain_id, &response.chain_mappings[&chain(*evm_chain_id)])?;
+            }
+        }
+        Op::Store { user: owner, chain: evm_chain_id } => {
+            let address = EvmAddress::parse(&format!("0x{:040x}", 0xf000_0000u64 + step as u64)).unwrap();
+            let record = MappingRecord::new(&address, None, "proptest", 0);
+            let stored = kv::store_mapping_once(kv, &user(*owner), &chain(*evm_chain_id), &record).unwrap();
+            if stored.address == address {
+                kv::store_reverse_mapping(kv, &address, &user(*owner)).unwrap();
+                kv::add_to_chain_index(kv, &user(*owner), &[chain(*evm_chain_id)]).unwrap();
+            }
+            model.first_write(*owner, *evm_chain_id, &stored.address)?;
+        }
+        Op::Get { user: owner } => {
+            let chain_ids: Vec<ChainId> = CHAINS.iter().copied().map(chain).collect();
+            let response = provisioner.handle_get(&user(*owner), &chain_ids).unwrap();
+            prop_assert_eq!(response.default_address.as_ref(), model.defaults.get(owner));
+            for evm_chain_id in CHAINS {
+                if let Some(expected) = model.chains.get(&(*owner, evm_chain_id)) {
+                    prop_assert_eq!(&response.chain_mappings[&chain(evm_chain_id)], expected);
+                }
+            }
+        }
+        Op::Update { user: owner, chain: evm_chain_id, retry } => {
+            let mut req = update_request(&user(*owner), *evm_chain_id);
+            if *retry {
+                req.idempotency_key = Some(format!("update-{}", step));
+            }
+            let result = provisioner.handle_update_mapping(&admin(), req.clone());
+            prop_assert_eq!(result.is_ok(), model.defaults.contains_key(owner), "update of user {}: {:?}", owner, result.as_ref().err());
+            if let Ok(response) = result {
+                model.chain_keys += 1;
+                if *retry {
+                    let replayed = provisioner.handle_update_mapping(&admin(), req).unwrap();
+                    prop_assert_eq!(&replayed.new_evm_address, &response.new_evm_address, "retry rotated again");
+                }
+                model.chains.insert((*owner, *evm_chain_id), response.new_evm_address);
+            }
+        }
+        Op::Rotate { user: owner, chain: evm_chain_id } => {
+            let result = provisioner.handle_rotate(&admin(), RotateRequest {
+                solana_pubkey: user(*owner),
+                chain_id: chain(*evm_chain_id),
+                reason: "proptest".to_string(),
+                expected_version: None,
+                idempotency_key: None,
+                request_id: None,
+            });
+            prop_assert_eq!(result.is_ok(), model.defaults.contains_key(owner), "rotation of user {}: {:?}", owner, result.as_ref().err());
+            if let Ok(response) = result {
+                model.chain_keys += 1;
+                model.chains.insert((*owner, *evm_chain_id), response.update.new_evm_address);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Invariants over the whole store after every operation
+fn check(kv: &MockKvStore, keys: &KeyCounts, model: &Model) -> std::result::Result<(), TestCaseError> {
+    for (owner, default) in &model.defaults {
+        prop_assert_eq!(kv::get_default_evm_address(kv, &user(*owner)).unwrap(), Some(default.clone()), "default of user {} changed", owner);
+        prop_assert_eq!(kv::get_reverse_mapping(kv, default).unwrap(), Some(user(*owner)));
+    }
+    for ((owner, evm_chain_id), address) in &model.chains {
+        prop_assert_eq!(kv::get_existing_mapping(kv, &user(*owner), &chain(*evm_chain_id)).unwrap(), Some(address.clone()));
+        prop_assert_eq!(kv::get_reverse_mapping(kv, address).unwrap(), Some(user(*owner)), "reverse entry of {}", address);
+    }
+    for (solana_pubkey, created) in keys.default_keys.lock().unwrap().iter() {
+        prop_assert_eq!(*created, 1, "default key of {} created {} times", solana_pubkey, created);
+    }
+    prop_assert_eq!(*keys.chain_keys.lock().unwrap(), model.chain_keys, "chain keys created per successful update");
+    Ok(())
+}
+
+// =============================================================================
+// IDEMPOTENCY INVARIANTS
+// =============================================================================
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(64))]
+
+    #[test]
+    fn test_operation_sequences_keep_mapping_invariants(ops in prop::collection::vec(op(), 1..24)) {
+        let kv = MockKvStore::new();
+        let counts = KeyCounts::default();
+        let provisioner = Provisioner::new(kv.clone(), CountingKeys { inner: MockKeyCreator::new(), counts: counts.clone() })
+            .with_admins(admins())
+            .with_single_step_updates()
+            .with_idempotency(MockKvStore::new());
+        let mut model = Model::default();
+
+        for (step, op) in ops.iter().enumerate() {
+            apply(&provisioner, &kv, &mut model, op, step)?;
+            check(&kv, &counts, &model)?;
+        }
+    }
+}
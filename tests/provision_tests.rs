@@ -0,0 +1,496 @@
+use cubist_wallet_provisioner::kv::{self, chain_key, default_key, reverse_key};
+use cubist_wallet_provisioner::testing::{
+    admin, admins, chain, evm, provision_request, provision_request_at, pubkey, signed, update_request, wallet, MockKeyCreator, MockKvStore,
+    TestContext,
+};
+use cubist_wallet_provisioner::{
+    ChainId, KeyClass, KvStore, MappingRecord, ProvisionBatchRequest, ProvisionRequest, Provisioner, SolanaPubkey, MAX_BATCH_SIZE,
+};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use ed25519_dalek::Signer;
+use std::sync::{Arc, Mutex};
+
+// =============================================================================
+// PROVISION TESTS (Batch Creation)
+// =============================================================================
+
+#[test]
+fn test_provision_creates_wallet_for_all_chains() {
+    let ctx = TestContext::new();
+    let alice = wallet(1);
+    let req = provision_request(&alice, vec![1, 137, 42161]);
+
+    let result = ctx.handle(req).unwrap();
+    
+    // Should create ONE address
+    assert_eq!(result.evm_address, evm("0x0000000000000000000000000000000000000001"));
+    
+    // Should have mappings for all 3 chains
+    assert_eq!(result.chain_mappings.len(), 3);
+    
+    // All chains should have the SAME address
+    assert_eq!(result.chain_mappings.get(&chain(1)), Some(&evm("0x0000000000000000000000000000000000000001")));
+    assert_eq!(result.chain_mappings.get(&chain(137)), Some(&evm("0x0000000000000000000000000000000000000001")));
+    assert_eq!(result.chain_mappings.get(&chain(42161)), Some(&evm("0x0000000000000000000000000000000000000001")));
+    
+    // Should have only created one key
+    assert_eq!(*ctx.default_key_counter.lock().unwrap(), 1);
+}
+
+#[test]
+fn test_provision_is_idempotent() {
+    let ctx = TestContext::new();
+    let alice = wallet(1);
+    // First provision
+    let result1 = ctx.handle(provision_request(&alice, vec![1, 137, 42161])).unwrap();
+    
+    // Second provision (same chains, a fresh proof)
+    let result2 = ctx.handle(provision_request(&alice, vec![1, 137, 42161])).unwrap();
+    
+    // Should return the same address
+    assert_eq!(result1.evm_address, result2.evm_address);
+    assert_eq!(result1.chain_mappings, result2.chain_mappings);
+    
+    // Should only have created one key (not two)
+    assert_eq!(*ctx.default_key_counter.lock().unwrap(), 1);
+}
+
+#[test]
+fn test_provision_can_add_new_chains_later() {
+    let ctx = TestContext::new();
+    let alice = wallet(1);
+    
+    // First provision with chains 1, 137
+    let req1 = provision_request(&alice, vec![1, 137]);
+    let result1 = ctx.handle(req1).unwrap();
+    
+    // Later provision with chain 42161 added
+    let req2 = provision_request(&alice, vec![1, 137, 42161]);
+    let result2 = ctx.handle(req2).unwrap();
+    
+    // All should have the same address (including new chain)
+    assert_eq!(result1.evm_address, result2.evm_address);
+    assert_eq!(result2.chain_mappings.len(), 3);
+    assert_eq!(result2.chain_mappings.get(&chain(42161)), Some(&result1.evm_address));
+    
+    // Still only one key created
+    assert_eq!(*ctx.default_key_counter.lock().unwrap(), 1);
+}
+
+#[test]
+fn test_provision_fails_with_empty_chain_ids() {
+    let ctx = TestContext::new();
+    let alice = wallet(1);
+    let req = provision_request(&alice, vec![]);
+
+    let result = ctx.handle(req);
+    assert!(result.is_err());
+    assert!(result.unwrap_err().to_string().contains("chain_ids cannot be empty"));
+}
+
+#[test]
+fn test_different_solana_addresses_get_different_wallets() {
+    let ctx = TestContext::new();
+    let alice = wallet(1);
+    let bob = wallet(2);
+    
+    let req1 = provision_request(&alice, vec![1, 137, 42161]);
+    
+    let req2 = provision_request(&bob, vec![1, 137, 42161]);
+
+    let result1 = ctx.handle(req1).unwrap();
+    let result2 = ctx.handle(req2).unwrap();
+    
+    // Different Solana addresses → different EVM wallets
+    assert_ne!(result1.evm_address, result2.evm_address);
+    
+    // Two keys created (one per Solana address)
+    assert_eq!(*ctx.default_key_counter.lock().unwrap(), 2);
+}
+
+// =============================================================================
+// OWNERSHIP PROOF TESTS
+// =============================================================================
+
+#[test]
+fn test_provision_rejects_signature_from_other_wallet() {
+    let ctx = TestContext::new();
+    let alice = wallet(1);
+    let mallory = wallet(3);
+
+    // Mallory signs a request for Alice's address
+    let mut req = provision_request(&alice, vec![1, 137]);
+    req.signature = BASE64.encode(mallory.sign(req.message.as_bytes()).to_bytes());
+
+    let result = ctx.handle(req);
+    assert!(result.unwrap_err().to_string().contains("Signature verification failed"));
+
+    // Nothing was created or written
+    assert_eq!(*ctx.default_key_counter.lock().unwrap(), 0);
+    assert_eq!(ctx.get_default_evm_address(&pubkey(&alice)).unwrap(), None);
+}
+
+#[test]
+fn test_provision_rejects_tampered_message() {
+    let ctx = TestContext::new();
+    let alice = wallet(1);
+
+    let mut req = provision_request(&alice, vec![1]);
+    req.message.push_str(" (tampered)");
+
+    assert!(ctx.handle(req).is_err());
+    assert_eq!(*ctx.default_key_counter.lock().unwrap(), 0);
+}
+
+#[test]
+fn test_provision_rejects_replayed_or_unrelated_proofs() {
+    let ctx = TestContext::new();
+    let alice = wallet(1);
+
+    // The same signed request cannot be sent twice
+    let req = provision_request(&alice, vec![1]);
+    ctx.handle(req.clone()).unwrap();
+    assert_eq!(ctx.handle(req).unwrap_err().code(), "NONCE_USED");
+
+    // Alice's signature over anything but this request's store message proves nothing
+    let mut unrelated = provision_request(&alice, vec![1]);
+    unrelated.message = "Sign in to example.com".to_string();
+    unrelated.signature = BASE64.encode(alice.sign(unrelated.message.as_bytes()).to_bytes());
+    assert_eq!(ctx.handle(unrelated).unwrap_err().code(), "INVALID_REQUEST");
+    let mut other_chains = provision_request(&alice, vec![1]);
+    other_chains.chain_ids = vec![chain(137)];
+    assert_eq!(ctx.handle(other_chains).unwrap_err().code(), "INVALID_REQUEST");
+    assert!(ctx.get_existing_mapping(&pubkey(&alice), 137).unwrap().is_none());
+
+    // The label, lifetime and key are signed too, so none can be swapped
+    let mut relabeled = signed(&alice, ProvisionRequest { label: Some("trading".to_string()), ..provision_request(&alice, vec![1]) });
+    relabeled.label = Some("savings".to_string());
+    assert_eq!(ctx.handle(relabeled).unwrap_err().code(), "INVALID_REQUEST");
+    let mut shortened = signed(&alice, ProvisionRequest { ttl_secs: Some(3600), ..provision_request(&alice, vec![1]) });
+    shortened.ttl_secs = Some(60);
+    assert_eq!(ctx.handle(shortened).unwrap_err().code(), "INVALID_REQUEST");
+    let mut made_permanent = signed(&alice, ProvisionRequest { ttl_secs: Some(3600), ..provision_request(&alice, vec![1]) });
+    made_permanent.ttl_secs = None;
+    assert_eq!(ctx.handle(made_permanent).unwrap_err().code(), "INVALID_REQUEST");
+    let mut other_key = provision_request(&alice, vec![1]);
+    other_key.key_class = KeyClass::Mpc { threshold: 2, participants: 3 };
+    assert_eq!(ctx.handle(other_key).unwrap_err().code(), "INVALID_REQUEST");
+
+    // Nor does an expired one
+    let stale = provision_request_at(&alice, vec![1], 1000);
+    assert_eq!(ctx.handle(stale).unwrap_err().code(), "AUTHORIZATION_EXPIRED");
+    assert_eq!(*ctx.default_key_counter.lock().unwrap(), 1);
+}
+
+#[test]
+fn test_provision_rejects_malformed_proof() {
+    let ctx = TestContext::new();
+    let alice = wallet(1);
+
+    let mut bad_signature = provision_request(&alice, vec![1]);
+    bad_signature.signature = "not base64!".to_string();
+    let result = ctx.handle(bad_signature);
+    assert!(result.unwrap_err().to_string().contains("Invalid signature encoding"));
+
+    let mut bad_signer = provision_request(&alice, vec![1]);
+    bad_signer.signature = BASE64.encode(wallet(2).sign(bad_signer.message.as_bytes()).to_bytes());
+    let result = ctx.handle(bad_signer);
+    assert!(result.unwrap_err().to_string().contains("Signature verification failed"));
+}
+
+// =============================================================================
+// KV KEY FORMAT TESTS
+// =============================================================================
+
+#[test]
+fn test_chain_key_format() {
+    let solana_pubkey = SolanaPubkey::parse("7xKXtg2CW87d97TXJSDpbD5jBkheTqA83TZRuJosgAsU").unwrap();
+    assert_eq!(chain_key(&solana_pubkey, &chain(1)), "7xKXtg2CW87d97TXJSDpbD5jBkheTqA83TZRuJosgAsU:1");
+    assert_eq!(chain_key(&solana_pubkey, &chain(137)), 
+               "7xKXtg2CW87d97TXJSDpbD5jBkheTqA83TZRuJosgAsU:137");
+}
+
+#[test]
+fn test_chain_key_format_for_non_evm_chain() {
+    let solana_pubkey = SolanaPubkey::parse("7xKXtg2CW87d97TXJSDpbD5jBkheTqA83TZRuJosgAsU").unwrap();
+    let chain_id = ChainId::parse("solana:mainnet").unwrap();
+    assert_eq!(chain_key(&solana_pubkey, &chain_id),
+               "7xKXtg2CW87d97TXJSDpbD5jBkheTqA83TZRuJosgAsU:solana:mainnet");
+}
+
+#[test]
+fn test_default_key_format() {
+    let solana_pubkey = SolanaPubkey::parse("7xKXtg2CW87d97TXJSDpbD5jBkheTqA83TZRuJosgAsU").unwrap();
+    assert_eq!(default_key(&solana_pubkey), 
+               "default:7xKXtg2CW87d97TXJSDpbD5jBkheTqA83TZRuJosgAsU");
+}
+
+// =============================================================================
+// INTEGRATION SCENARIO TESTS
+// =============================================================================
+
+#[test]
+fn test_full_user_journey() {
+    let ctx = TestContext::new();
+    
+    // User A comes with Solana wallet
+    let alice = wallet(1);
+    let sol_a = &pubkey(&alice);
+    
+    // Step 1: Provision wallet for all chains
+    let provision_req = provision_request(&alice, vec![1, 137, 42161]);
+    let provision_result = ctx.handle(provision_req).unwrap();
+    
+    println!("Provisioned wallet: {}", provision_result.evm_address);
+    println!("Chain mappings: {:?}", provision_result.chain_mappings);
+    
+    // Verify all chains have same address
+    let default_addr = provision_result.evm_address.clone();
+    assert_eq!(provision_result.chain_mappings.get(&chain(1)), Some(&default_addr));
+    assert_eq!(provision_result.chain_mappings.get(&chain(137)), Some(&default_addr));
+    assert_eq!(provision_result.chain_mappings.get(&chain(42161)), Some(&default_addr));
+    
+    // Step 2: Later, admin decides to update chain 137 to new address
+    let update_req = update_request(sol_a, 137);
+    let update_result = ctx.handle_update_mapping(update_req).unwrap();
+    
+    println!("Updated chain 137 to new wallet: {}", update_result.new_evm_address);
+    
+    // Step 3: Verify final state
+    // Chain 1 and 42161 still have default address
+    assert_eq!(ctx.get_existing_mapping(sol_a, 1).unwrap(), Some(default_addr.clone()));
+    assert_eq!(ctx.get_existing_mapping(sol_a, 42161).unwrap(), Some(default_addr.clone()));
+    
+    // Chain 137 has new address
+    assert_eq!(ctx.get_existing_mapping(sol_a, 137).unwrap(), Some(update_result.new_evm_address.clone()));
+    assert_ne!(ctx.get_existing_mapping(sol_a, 137).unwrap(), Some(default_addr));
+}
+
+#[test]
+fn test_multiple_users_independent() {
+    let ctx = TestContext::new();
+    
+    let alice = wallet(1);
+    let sol_a = &pubkey(&alice);
+    let bob = wallet(2);
+    let sol_b = &pubkey(&bob);
+    
+    // Provision both users
+    let req_a = provision_request(&alice, vec![1, 137]);
+    let req_b = provision_request(&bob, vec![1, 137]);
+    
+    let result_a = ctx.handle(req_a).unwrap();
+    let result_b = ctx.handle(req_b).unwrap();
+    
+    // Different users have different wallets
+    assert_ne!(result_a.evm_address, result_b.evm_address);
+    
+    // Update user A's chain 137
+    let update_a = update_request(sol_a, 137);
+    let update_result_a = ctx.handle_update_mapping(update_a).unwrap();
+    
+    // User B should be unaffected
+    let b_chain_137 = ctx.get_existing_mapping(sol_b, 137).unwrap();
+    assert_eq!(b_chain_137, Some(result_b.evm_address.clone()));
+    
+    // User A's chain 137 should be updated
+    let a_chain_137 = ctx.get_existing_mapping(sol_a, 137).unwrap();
+    assert_eq!(a_chain_137, Some(update_result_a.new_evm_address));
+}
+
+// =============================================================================
+// KEY ID TESTS
+// =============================================================================
+
+#[test]
+fn test_provision_returns_key_id() {
+    let ctx = TestContext::new();
+    let alice = wallet(1);
+
+    let result = ctx.handle(provision_request(&alice, vec![1, 137])).unwrap();
+    assert_eq!(result.key_id, Some(format!("Key#{}", result.evm_address)));
+
+    // Key id is persisted and returned on repeat provisions
+    let again = ctx.handle(provision_request(&alice, vec![1, 137])).unwrap();
+    assert_eq!(again.key_id, result.key_id);
+
+    let stored = kv::get_chain_mapping(&ctx.kv, &pubkey(&alice), &chain(137)).unwrap().unwrap();
+    assert_eq!(stored.key_id, result.key_id);
+}
+
+#[test]
+fn test_update_returns_new_key_id() {
+    let ctx = TestContext::new();
+    let alice = wallet(1);
+    ctx.handle(provision_request(&alice, vec![1, 137])).unwrap();
+
+    let update_req = update_request(&pubkey(&alice), 137);
+    let result = ctx.handle_update_mapping(update_req).unwrap();
+    assert_eq!(result.new_key_id, format!("Key#{}", result.new_evm_address));
+
+    let stored = kv::get_chain_mapping(&ctx.kv, &pubkey(&alice), &chain(137)).unwrap().unwrap();
+    assert_eq!(stored.key_id, Some(result.new_key_id));
+}
+
+#[test]
+fn test_legacy_plain_string_values_still_decode() {
+    let ctx = TestContext::new();
+    let alice = wallet(1);
+    let solana_pubkey = pubkey(&alice);
+    let legacy = "0xcb373e47d769b06dee02f05c86dd8790e0358aee";
+
+    // Values written before key ids were tracked are bare addresses
+    ctx.kv.set(&default_key(&solana_pubkey), legacy).unwrap();
+    ctx.kv.set(&chain_key(&solana_pubkey, &chain(1)), legacy).unwrap();
+
+    let result = ctx.handle(provision_request(&alice, vec![1, 137])).unwrap();
+    assert_eq!(result.evm_address, evm(legacy));
+    assert_eq!(result.key_id, None);
+    assert_eq!(result.chain_mappings.get(&chain(1)), Some(&evm(legacy)));
+    assert_eq!(result.chain_mappings.get(&chain(137)), Some(&evm(legacy)));
+    assert_eq!(*ctx.default_key_counter.lock().unwrap(), 0);
+}
+
+#[test]
+fn test_mapping_record_versions_decode() {
+    let address = evm("0xcb373e47d769b06dee02f05c86dd8790e0358aee");
+
+    let v0 = MappingRecord::decode("0xcb373e47d769b06dee02f05c86dd8790e0358aee").unwrap();
+    assert_eq!((v0.version, v0.created_at, v0.created_by), (0, None, None));
+
+    let v1 = MappingRecord::decode(r#"{"address":"0xcb373e47d769b06dee02f05c86dd8790e0358aee","key_id":"Key#1"}"#).unwrap();
+    assert_eq!((v1.version, v1.key_id.as_deref(), v1.created_at), (1, Some("Key#1"), None));
+
+    let v2 = MappingRecord::new(&address, Some("Key#1"), "admin@test", 42);
+    assert_eq!(v2.version, kv::MAPPING_RECORD_VERSION);
+    assert_eq!(MappingRecord::decode(&v2.encode()).unwrap(), v2);
+
+    let future = r#"{"address":"0xcb373e47d769b06dee02f05c86dd8790e0358aee","version":99}"#;
+    let err = MappingRecord::decode(future).unwrap_err();
+    assert!(err.to_string().contains("Unsupported mapping record version 99"));
+}
+
+#[test]
+fn test_records_carry_creation_metadata() {
+    let kv = MockKvStore::new();
+    let keys = MockKeyCreator {
+        default_key_counter: Arc::new(Mutex::new(0)),
+        chain_key_counter: Arc::new(Mutex::new(1000)),
+    };
+    let provisioner = Provisioner::new(kv.clone(), keys).with_admins(admins()).with_single_step_updates().with_clock(|| 1_700_000_000);
+    let alice = wallet(1);
+    let solana_pubkey = pubkey(&alice);
+
+    provisioner.handle(provision_request_at(&alice, vec![1], 1_700_000_000)).unwrap();
+    let record = kv::get_chain_mapping(&kv, &solana_pubkey, &chain(1)).unwrap().unwrap();
+    assert_eq!(record.created_at, Some(1_700_000_000));
+    assert_eq!(record.created_by.as_deref(), Some(solana_pubkey.as_str()));
+
+    provisioner.handle_update_mapping(&admin(), update_request(&solana_pubkey, 1)).unwrap();
+    let record = kv::get_chain_mapping(&kv, &solana_pubkey, &chain(1)).unwrap().unwrap();
+    assert_eq!(record.created_by.as_deref(), Some("admin@test"));
+    assert_eq!(record.version, kv::MAPPING_RECORD_VERSION);
+}
+
+// =============================================================================
+// REVERSE INDEX TESTS
+// =============================================================================
+
+#[test]
+fn test_reverse_lookup_after_provision() {
+    let ctx = TestContext::new();
+    let alice = wallet(1);
+    let solana_pubkey = &pubkey(&alice);
+
+    let req = provision_request(&alice, vec![1, 137]);
+    let result = ctx.handle(req).unwrap();
+
+    let owner = ctx.provisioner.handle_reverse_get(&result.evm_address).unwrap();
+    assert_eq!(owner, Some(solana_pubkey.clone()));
+
+    // Unknown addresses resolve to nothing
+    let unknown = ctx.provisioner.handle_reverse_get(&evm("0x00000000000000000000000000000000deadbeef")).unwrap();
+    assert_eq!(unknown, None);
+}
+
+#[test]
+fn test_reverse_lookup_covers_updated_addresses() {
+    let ctx = TestContext::new();
+    let alice = wallet(1);
+    let solana_pubkey = &pubkey(&alice);
+
+    let req = provision_request(&alice, vec![1, 137]);
+    let result = ctx.handle(req).unwrap();
+
+    let update_req = update_request(solana_pubkey, 137);
+    let update_result = ctx.handle_update_mapping(update_req).unwrap();
+
+    // Both the default and the chain-specific address resolve to the owner
+    assert_eq!(
+        ctx.provisioner.handle_reverse_get(&result.evm_address).unwrap(),
+        Some(solana_pubkey.clone())
+    );
+    assert_eq!(
+        ctx.provisioner.handle_reverse_get(&update_result.new_evm_address).unwrap(),
+        Some(solana_pubkey.clone())
+    );
+}
+
+#[test]
+fn test_reverse_key_format() {
+    assert_eq!(
+        reverse_key(&evm("0xCB373E47D769B06DEE02F05C86DD8790E0358AEE")),
+        "reverse:0xcb373e47d769b06dee02f05c86dd8790e0358aee"
+    );
+}
+
+// =============================================================================
+// BATCH PROVISION TESTS
+// =============================================================================
+
+#[test]
+fn test_batch_provision_reports_partial_failures() {
+    let ctx = TestContext::new();
+    let alice = wallet(1);
+    let bob = wallet(2);
+
+    let batch = ProvisionBatchRequest {
+        requests: vec![
+            provision_request(&alice, vec![1, 137]),
+            provision_request(&bob, vec![]),
+        ],
+        request_id: None,
+    };
+
+    let result = ctx.provisioner.handle_batch(batch).unwrap();
+    assert_eq!(result.succeeded, 1);
+    assert_eq!(result.failed, 1);
+
+    // Results come back in request order
+    assert!(result.results[0].success);
+    assert_eq!(result.results[0].result.as_ref().unwrap().chain_mappings.len(), 2);
+    assert!(!result.results[1].success);
+    assert_eq!(result.results[1].error.as_ref().unwrap().code(), "INVALID_REQUEST");
+    assert!(result.results[1].error.as_ref().unwrap().to_string().contains("chain_ids cannot be empty"));
+
+    // Only the successful entry created a key
+    assert_eq!(*ctx.default_key_counter.lock().unwrap(), 1);
+}
+
+#[test]
+fn test_batch_provision_rejects_empty_and_oversized_batches() {
+    let ctx = TestContext::new();
+    let alice = wallet(1);
+
+    let empty = ProvisionBatchRequest { requests: vec![], request_id: None };
+    assert!(ctx.provisioner.handle_batch(empty).is_err());
+
+    let entry = provision_request(&alice, vec![1]);
+    let oversized = ProvisionBatchRequest {
+        requests: vec![entry; MAX_BATCH_SIZE + 1],
+        request_id: None,
+    };
+    let result = ctx.provisioner.handle_batch(oversized);
+    assert!(result.unwrap_err().to_string().contains("Batch too large"));
+    assert_eq!(*ctx.default_key_counter.lock().unwrap(), 0);
+}
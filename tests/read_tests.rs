@@ -0,0 +1,386 @@
+use cubist_wallet_provisioner::error::Result;
+use cubist_wallet_provisioner::kv::{self, chain_key, default_key, ReadOnly};
+use cubist_wallet_provisioner::mapping;
+use cubist_wallet_provisioner::migrate::MigrateRequest;
+use cubist_wallet_provisioner::read_cache::{Cached, ReadCache};
+use cubist_wallet_provisioner::testing::{
+    admin, admins, chain, evm, provision_request, provision_request_at, pubkey, set_chain_request, update_request, wallet, MockKeyCreator,
+    MockKvStore, TestContext,
+};
+use cubist_wallet_provisioner::{ChainId, KvStore, MappingRecord, ProvisionBatchRequest, Provisioner};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+// =============================================================================
+// LIST TESTS
+// =============================================================================
+
+#[test]
+fn test_list_returns_all_indexed_chains() {
+    let ctx = TestContext::new();
+    let alice = wallet(1);
+    let solana_pubkey = pubkey(&alice);
+
+    let result = ctx.handle(provision_request(&alice, vec![137, 1])).unwrap();
+    ctx.handle(provision_request(&alice, vec![42161, 1])).unwrap();
+
+    // Index is sorted and de-duplicated
+    assert_eq!(kv::get_chain_index(&ctx.kv, &solana_pubkey).unwrap(), vec![chain(1), chain(137), chain(42161)]);
+
+    let list = ctx.provisioner.handle_list(&solana_pubkey).unwrap();
+    assert_eq!(list.default_address, Some(result.evm_address.clone()));
+    assert_eq!(list.chain_mappings.len(), 3);
+    assert_eq!(list.chain_mappings.get(&chain(42161)), Some(&result.evm_address));
+}
+
+#[test]
+fn test_list_includes_updated_chains() {
+    let ctx = TestContext::new();
+    let alice = wallet(1);
+    let solana_pubkey = pubkey(&alice);
+    ctx.handle(provision_request(&alice, vec![1])).unwrap();
+
+    // Admin update on a chain that was never provisioned still shows up
+    let update_req = update_request(&solana_pubkey, 10);
+    let update_result = ctx.handle_update_mapping(update_req).unwrap();
+
+    let list = ctx.provisioner.handle_list(&solana_pubkey).unwrap();
+    assert_eq!(list.chain_mappings.len(), 2);
+    assert_eq!(list.chain_mappings.get(&chain(10)), Some(&update_result.new_evm_address));
+}
+
+#[test]
+fn test_list_unknown_user_is_empty() {
+    let ctx = TestContext::new();
+
+    let list = ctx.provisioner.handle_list(&pubkey(&wallet(9))).unwrap();
+    assert_eq!(list.default_address, None);
+    assert!(list.chain_mappings.is_empty());
+}
+
+// =============================================================================
+// HISTORY TESTS
+// =============================================================================
+
+#[test]
+fn test_update_records_replaced_address_in_history() {
+    let kv = MockKvStore::new();
+    let keys = MockKeyCreator {
+        default_key_counter: Arc::new(Mutex::new(0)),
+        chain_key_counter: Arc::new(Mutex::new(1000)),
+    };
+    let provisioner = Provisioner::new(kv.clone(), keys).with_admins(admins()).with_single_step_updates().with_clock(|| 1_700_000_000);
+    let alice = wallet(1);
+    let solana_pubkey = pubkey(&alice);
+
+    let provisioned = provisioner.handle(provision_request_at(&alice, vec![1, 137], 1_700_000_000)).unwrap();
+    let first = provisioner.handle_update_mapping(&admin(), update_request(&solana_pubkey, 137)).unwrap();
+    let second = provisioner.handle_update_mapping(&admin(), update_request(&solana_pubkey, 137)).unwrap();
+
+    let history = provisioner.handle_history(&solana_pubkey, &chain(137)).unwrap();
+    assert_eq!(history.current_address, Some(second.new_evm_address));
+    assert_eq!(history.entries.len(), 2);
+
+    // Oldest first: the default address, then the first rotated key
+    assert_eq!(history.entries[0].address, provisioned.evm_address);
+    assert_eq!(history.entries[0].key_id, provisioned.key_id);
+    assert_eq!(history.entries[1].address, first.new_evm_address);
+    assert_eq!(history.entries[1].replaced_at, 1_700_000_000);
+    assert_eq!(history.entries[1].replaced_by, "admin@test");
+
+    // Untouched chains have no history
+    assert!(provisioner.handle_history(&solana_pubkey, &chain(1)).unwrap().entries.is_empty());
+}
+
+#[test]
+fn test_update_of_unmapped_chain_has_no_history() {
+    let ctx = TestContext::new();
+    let alice = wallet(1);
+    let solana_pubkey = pubkey(&alice);
+    ctx.handle(provision_request(&alice, vec![1])).unwrap();
+
+    // Nothing was replaced on chain 10, so nothing is recorded
+    ctx.handle_update_mapping(update_request(&solana_pubkey, 10)).unwrap();
+    assert!(ctx.provisioner.handle_history(&solana_pubkey, &chain(10)).unwrap().entries.is_empty());
+}
+
+// =============================================================================
+// BATCHED READ TESTS
+// =============================================================================
+
+/// KV store that counts read round-trips
+struct CountingKvStore {
+    inner: MockKvStore,
+    gets: AtomicU64,
+    get_manys: AtomicU64,
+}
+
+impl KvStore for CountingKvStore {
+    fn get(&self, key: &str) -> Result<Option<String>> {
+        self.gets.fetch_add(1, Ordering::SeqCst);
+        self.inner.get(key)
+    }
+
+    fn set_if_absent(&self, key: &str, value: &str) -> Result<bool> {
+        self.inner.set_if_absent(key, value)
+    }
+
+    fn set(&self, key: &str, value: &str) -> Result<()> {
+        self.inner.set(key, value)
+    }
+
+    fn get_many(&self, keys: &[String]) -> Result<Vec<Option<String>>> {
+        self.get_manys.fetch_add(1, Ordering::SeqCst);
+        self.inner.get_many(keys)
+    }
+}
+
+#[test]
+fn test_get_reads_all_chains_in_one_round_trip() {
+    let kv = CountingKvStore { inner: MockKvStore::new(), gets: AtomicU64::new(0), get_manys: AtomicU64::new(0) };
+    let alice = wallet(1);
+    let solana_pubkey = pubkey(&alice);
+    let address = evm("0x4444444444444444444444444444444444444444");
+    let record = MappingRecord::new(&address, Some("Key#4"), solana_pubkey.as_str(), 10);
+    kv::store_default_mapping(&kv, &solana_pubkey, &record).unwrap();
+    // Every other chain is mapped
+    let chain_ids: Vec<ChainId> = (1..=20).map(chain).collect();
+    for chain_id in chain_ids.iter().step_by(2) {
+        kv::store_mapping_once(&kv, &solana_pubkey, chain_id, &record).unwrap();
+    }
+
+    let before = kv.gets.load(Ordering::SeqCst);
+    let found = mapping::get(&kv, &solana_pubkey, &chain_ids, 1000).unwrap();
+
+    // Mappings, the registry entries of the chains that might inherit the default, freeze flags
+    assert_eq!(kv.get_manys.load(Ordering::SeqCst), 3);
+    // The rest are the chain index, the label index and the write-journal check (head hint and first journal)
+    assert_eq!(kv.gets.load(Ordering::SeqCst) - before, 4);
+    assert_eq!(found.default_address, Some(address.clone()));
+    // Chain 10 (OP Mainnet) is known, so it inherits the default; the others are unknown
+    assert_eq!(found.chain_mappings.len(), 11);
+    assert_eq!(found.chain_mappings.get(&chain(1)), Some(&address));
+    assert_eq!(found.chain_inherited.get(&chain(10)), Some(&true));
+    assert!(!found.chain_mappings.contains_key(&chain(2)));
+}
+
+#[test]
+fn test_get_without_chain_ids_returns_every_indexed_chain() {
+    let ctx = TestContext::new();
+    let alice = wallet(1);
+    let solana_pubkey = pubkey(&alice);
+    ctx.provisioner.handle(provision_request(&alice, vec![1, 137])).unwrap();
+    ctx.provisioner.handle(provision_request(&alice, vec![42161])).unwrap();
+
+    let all = ctx.provisioner.handle_get(&solana_pubkey, &[]).unwrap();
+    assert_eq!(all.chain_mappings.len(), 3);
+    assert_eq!(all.chain_versions.len(), 3);
+
+    // Requested chains outside the index are not read; they inherit the default
+    let some = ctx.provisioner.handle_get(&solana_pubkey, &[chain(137), chain(10)]).unwrap();
+    assert_eq!(some.chain_inherited, HashMap::from([(chain(137), false), (chain(10), true)]));
+}
+
+#[test]
+fn test_migrate_backfills_chain_index_of_legacy_users() {
+    let ctx = TestContext::new();
+    let solana_pubkey = pubkey(&wallet(1));
+    let legacy = "0xcb373e47d769b06dee02f05c86dd8790e0358aee";
+    ctx.kv.set(&default_key(&solana_pubkey), legacy).unwrap();
+    ctx.kv.set(&chain_key(&solana_pubkey, &chain(1)), legacy).unwrap();
+    ctx.kv.set(&chain_key(&solana_pubkey, &chain(137)), legacy).unwrap();
+
+    // Without an index the requested chains are probed
+    assert_eq!(ctx.provisioner.handle_get(&solana_pubkey, &[chain(1)]).unwrap().chain_mappings.len(), 1);
+    assert!(ctx.provisioner.handle_get(&solana_pubkey, &[]).unwrap().chain_mappings.is_empty());
+
+    let report = ctx.provisioner.handle_migrate(&admin(), MigrateRequest::default()).unwrap();
+    assert_eq!(report.indexed, 2);
+    assert_eq!(kv::get_chain_index(&ctx.kv, &solana_pubkey).unwrap(), vec![chain(1), chain(137)]);
+    assert_eq!(ctx.provisioner.handle_get(&solana_pubkey, &[]).unwrap().chain_mappings.len(), 2);
+
+    let rerun = ctx.provisioner.handle_migrate(&admin(), MigrateRequest::default()).unwrap();
+    assert_eq!(rerun.indexed, 0);
+}
+
+// =============================================================================
+// INHERITED MAPPING TESTS
+// =============================================================================
+
+#[test]
+fn test_get_inherits_default_on_enabled_chains_only() {
+    let ctx = TestContext::new();
+    let alice = wallet(1);
+    let solana_pubkey = pubkey(&alice);
+    let provisioned = ctx.handle(provision_request(&alice, vec![1])).unwrap();
+    ctx.provisioner.handle_set_chain(&admin(), set_chain_request(&chain(137), false, None)).unwrap();
+
+    let result = ctx.provisioner.handle_get(&solana_pubkey, &[chain(1), chain(42161), chain(137), chain(999_999)]).unwrap();
+    assert_eq!(result.chain_mappings.get(&chain(42161)), Some(&provisioned.evm_address));
+    assert_eq!(result.chain_key_ids.get(&chain(42161)), provisioned.key_id.as_ref());
+    assert_eq!(result.chain_versions.get(&chain(42161)), Some(&0));
+    assert_eq!(result.chain_inherited, HashMap::from([(chain(1), false), (chain(42161), true)]));
+
+    // Reading does not write by default
+    assert!(kv::get_chain_mapping(&ctx.kv, &solana_pubkey, &chain(42161)).unwrap().is_none());
+}
+
+#[test]
+fn test_materialized_inheritance_writes_chain_on_first_read() {
+    let kv = MockKvStore::new();
+    let keys = MockKeyCreator {
+        default_key_counter: Arc::new(Mutex::new(0)),
+        chain_key_counter: Arc::new(Mutex::new(1000)),
+    };
+    let provisioner = Provisioner::new(kv.clone(), keys).with_materialized_inheritance();
+    let alice = wallet(1);
+    let solana_pubkey = pubkey(&alice);
+    let provisioned = provisioner.handle(provision_request(&alice, vec![1])).unwrap();
+
+    let first = provisioner.handle_get(&solana_pubkey, &[chain(42161)]).unwrap();
+    assert_eq!(first.chain_mappings.get(&chain(42161)), Some(&provisioned.evm_address));
+    assert_eq!(first.chain_inherited.get(&chain(42161)), Some(&false));
+
+    let stored = kv::get_chain_mapping(&kv, &solana_pubkey, &chain(42161)).unwrap().unwrap();
+    assert_eq!(stored.address, provisioned.evm_address);
+    assert_eq!(kv::get_chain_index(&kv, &solana_pubkey).unwrap(), vec![chain(1), chain(42161)]);
+    assert_eq!(provisioner.handle_list(&solana_pubkey).unwrap().chain_mappings.len(), 2);
+}
+
+// =============================================================================
+// READ-ONLY TESTS
+// =============================================================================
+
+#[test]
+fn test_read_only_store_serves_reads_and_refuses_writes() {
+    let kv = MockKvStore::new();
+    let keys = || MockKeyCreator {
+        default_key_counter: Arc::new(Mutex::new(0)),
+        chain_key_counter: Arc::new(Mutex::new(1000)),
+    };
+    let alice = wallet(1);
+    let evm_address = Provisioner::new(kv.clone(), keys()).handle(provision_request(&alice, vec![1, 137])).unwrap().evm_address;
+
+    let read_only = ReadOnly(kv.clone());
+    let mappings = mapping::get(&read_only, &pubkey(&alice), &[], 1000).unwrap();
+    assert_eq!(mappings.default_address, Some(evm_address.clone()));
+    assert_eq!(mapping::list(&read_only, &pubkey(&alice)).unwrap().solana_pubkey, pubkey(&alice));
+    assert_eq!(kv::get_reverse_mapping(&read_only, &evm_address).unwrap(), Some(pubkey(&alice)));
+
+    let written = kv.list_keys(None, 100).unwrap();
+    let err = Provisioner::new(ReadOnly(kv.clone()), keys()).handle(provision_request(&wallet(2), vec![1])).unwrap_err();
+    assert_eq!(err.code(), "UNSUPPORTED");
+    assert_eq!(read_only.delete(&default_key(&pubkey(&alice))).unwrap_err().code(), "UNSUPPORTED");
+    assert_eq!(kv.list_keys(None, 100).unwrap(), written);
+}
+
+// =============================================================================
+// READ CACHE TESTS
+// =============================================================================
+
+/// KV store logging every key it reads, shared with the test
+#[derive(Clone, Default)]
+struct ReadLog {
+    inner: MockKvStore,
+    reads: Arc<Mutex<Vec<String>>>,
+}
+
+impl ReadLog {
+    fn reads_of(&self, key: &str) -> usize {
+        self.reads.lock().unwrap().iter().filter(|read| *read == key).count()
+    }
+}
+
+impl KvStore for ReadLog {
+    fn get(&self, key: &str) -> Result<Option<String>> {
+        self.reads.lock().unwrap().push(key.to_string());
+        self.inner.get(key)
+    }
+
+    fn set_if_absent(&self, key: &str, value: &str) -> Result<bool> {
+        self.inner.set_if_absent(key, value)
+    }
+
+    fn set(&self, key: &str, value: &str) -> Result<()> {
+        self.inner.set(key, value)
+    }
+
+    fn get_many(&self, keys: &[String]) -> Result<Vec<Option<String>>> {
+        self.reads.lock().unwrap().extend(keys.iter().cloned());
+        self.inner.get_many(keys)
+    }
+
+    fn delete(&self, key: &str) -> Result<()> {
+        self.inner.delete(key)
+    }
+
+    fn list_keys(&self, after: Option<&str>, limit: usize) -> Result<Vec<String>> {
+        self.inner.list_keys(after, limit)
+    }
+}
+
+#[test]
+fn test_read_cache_serves_repeated_reads_until_written() {
+    let log = ReadLog::default();
+    let cache = ReadCache::new();
+    let cached = Cached::new(log.clone(), "solana_to_evm", cache.clone());
+    log.inner.set("a", "1").unwrap();
+
+    assert_eq!(cached.get("a").unwrap().as_deref(), Some("1"));
+    assert_eq!(cached.get("a").unwrap().as_deref(), Some("1"));
+    // Absence is cached too
+    assert_eq!(cached.get("b").unwrap(), None);
+    assert_eq!(cached.get("b").unwrap(), None);
+    assert_eq!((log.reads_of("a"), log.reads_of("b")), (1, 1));
+
+    // Only the keys not read yet reach the store, in one round-trip
+    let keys = ["a", "c", "b", "d"].map(str::to_string);
+    log.inner.set("d", "4").unwrap();
+    assert_eq!(cached.get_many(&keys).unwrap(), vec![Some("1".to_string()), None, None, Some("4".to_string())]);
+    assert_eq!(*log.reads.lock().unwrap(), ["a", "b", "c", "d"]);
+    assert_eq!(cached.get_many(&keys).unwrap().len(), 4);
+    assert_eq!(log.reads.lock().unwrap().len(), 4);
+
+    // A write drops the key: the next read sees the store
+    cached.set("a", "2").unwrap();
+    assert_eq!(cached.get("a").unwrap().as_deref(), Some("2"));
+    assert_eq!(log.reads_of("a"), 2);
+    // Even one that fails (the mock store refuses deletes)
+    assert_eq!(cached.delete("d").unwrap_err().code(), "UNSUPPORTED");
+    assert_eq!(cached.get("d").unwrap().as_deref(), Some("4"));
+    assert_eq!(log.reads_of("d"), 2);
+
+    // So does losing a conditional write, so a retry reads the winner's value
+    assert_eq!(cached.get("e").unwrap(), None);
+    log.inner.set("e", "theirs").unwrap();
+    assert!(!cached.set_if_absent("e", "ours").unwrap());
+    assert_eq!(cached.get("e").unwrap().as_deref(), Some("theirs"));
+
+    // Buckets of one invocation share the cache without sharing keys
+    let other = Cached::new(log.clone(), "config", cache.clone());
+    assert_eq!(other.get("a").unwrap().as_deref(), Some("2"));
+    assert_eq!(log.reads_of("a"), 3);
+    assert_eq!(cache.len(), 6);
+}
+
+#[test]
+fn test_batch_store_reads_default_once_after_writing_it() {
+    let alice = wallet(1);
+    let batch = || ProvisionBatchRequest {
+        requests: [1, 10, 56, 137, 8453, 42161, 43114].into_iter().map(|chain_id| provision_request(&alice, vec![chain_id])).collect(),
+        request_id: None,
+    };
+    let default = default_key(&pubkey(&alice));
+
+    let uncached = ReadLog::default();
+    Provisioner::new(uncached.clone(), MockKeyCreator::new()).handle_batch(batch()).unwrap();
+    let log = ReadLog::default();
+    let provisioner = Provisioner::new(Cached::new(log.clone(), "solana_to_evm", ReadCache::new()), MockKeyCreator::new());
+    assert_eq!(provisioner.handle_batch(batch()).unwrap().succeeded, 7);
+    // Uncached, every entry reads the default; cached, the first entry reads
+    // it before creating it and the second after writing it
+    assert_eq!(uncached.reads_of(&default), 7);
+    assert_eq!(log.reads_of(&default), 2);
+    assert!(log.reads.lock().unwrap().len() < uncached.reads.lock().unwrap().len());
+}
@@ -0,0 +1,198 @@
+mod common;
+
+use common::{listed_key, store_with_backend_key, FlakyKvStore};
+use cubist_wallet_provisioner::error::Result;
+use cubist_wallet_provisioner::inflight;
+use cubist_wallet_provisioner::kv;
+use cubist_wallet_provisioner::mapping;
+use cubist_wallet_provisioner::testing::{
+    chain, evm, mock_key, provision_request, provision_request_at, pubkey, signed, wallet, MockKeyCreator, MockKvStore, TestContext,
+};
+use cubist_wallet_provisioner::txn::{self, TxnStatus};
+use cubist_wallet_provisioner::{KeyClass, KeyLister, KeyType, KvStore, ListedKey, ProvisionRequest, Provisioner};
+use std::sync::{Arc, Mutex};
+
+// =============================================================================
+// KEY RECOVERY TESTS
+// =============================================================================
+
+/// Fixed set of CubeSigner keys
+struct ListedKeys(Vec<ListedKey>);
+
+impl KeyLister for ListedKeys {
+    fn list_evm_keys(&self) -> Result<Vec<ListedKey>> {
+        Ok(self.0.clone())
+    }
+}
+
+fn recovering_provisioner(kv: &MockKvStore, keys: Vec<ListedKey>) -> Provisioner<MockKvStore, MockKeyCreator> {
+    let creator = MockKeyCreator {
+        default_key_counter: Arc::new(Mutex::new(0)),
+        chain_key_counter: Arc::new(Mutex::new(1000)),
+    };
+    Provisioner::new(kv.clone(), creator).with_clock(|| 1000).with_key_recovery(ListedKeys(keys))
+}
+
+#[test]
+fn test_get_restores_lost_default_mapping_from_cubesigner() {
+    let kv = MockKvStore::new();
+    let alice = pubkey(&wallet(1));
+    let bob = pubkey(&wallet(2));
+    let provisioner = recovering_provisioner(&kv, vec![listed_key(7, format!("EVM_{}", alice)), listed_key(8, "unrelated".to_string())]);
+    let address = evm(&mock_key(7).address);
+
+    let found = provisioner.handle_get(&alice, &[chain(1)]).unwrap();
+    assert_eq!(found.default_address, Some(address.clone()));
+    assert_eq!(found.chain_mappings.get(&chain(1)), Some(&address));
+
+    let restored = kv::get_default_mapping(&kv, &alice).unwrap().unwrap();
+    assert_eq!(restored.key_id, Some(mock_key(7).key_id));
+    assert_eq!(restored.created_by.as_deref(), Some("key-recovery"));
+    assert_eq!(kv::get_reverse_mapping(&kv, &address).unwrap(), Some(alice.clone()));
+
+    // No key in CubeSigner either: nothing to restore
+    assert_eq!(provisioner.handle_get(&bob, &[]).unwrap().default_address, None);
+    assert!(kv::get_default_mapping(&kv, &bob).unwrap().is_none());
+}
+
+#[test]
+fn test_key_recovery_only_fills_a_missing_default() {
+    let ctx = TestContext::new();
+    let alice = pubkey(&wallet(1));
+    assert_eq!(ctx.provisioner.handle_get(&alice, &[]).unwrap().default_address, None);
+
+    // With recovery on, an existing mapping is returned as is even if CubeSigner has another default key
+    let kv = MockKvStore::new();
+    let provisioner = recovering_provisioner(&kv, vec![listed_key(7, format!("EVM_{}", alice))]);
+    let stored = provisioner.handle(provision_request_at(&wallet(1), vec![1], 1000)).unwrap();
+    assert_eq!(provisioner.handle_get(&alice, &[]).unwrap().default_address, Some(stored.evm_address));
+}
+
+// =============================================================================
+// WRITE JOURNAL TESTS
+// =============================================================================
+
+#[test]
+fn test_half_written_store_is_completed_by_next_call() {
+    // address claim, default, journal, journal head, reverse index, first chain; then the KV fails
+    let flaky = FlakyKvStore { inner: MockKvStore::new(), writes_left: Mutex::new(6) };
+    let alice = wallet(1);
+    let solana_pubkey = pubkey(&alice);
+    let req = provision_request(&alice, vec![1, 137, 42161]);
+
+    let err = store_with_backend_key(&flaky, &req).unwrap_err();
+    assert_eq!(err.code(), "KV_ERROR");
+    let kv = flaky.inner;
+    assert!(kv::get_chain_mapping(&kv, &solana_pubkey, &chain(1)).unwrap().is_some());
+    assert!(kv::get_chain_mapping(&kv, &solana_pubkey, &chain(137)).unwrap().is_none());
+    assert_eq!(txn::get(&kv, &solana_pubkey, 1).unwrap().unwrap().status, TxnStatus::Pending);
+
+    // Any later call for the address completes the journal before reading
+    let found = mapping::get(&kv, &solana_pubkey, &[chain(1), chain(137), chain(42161)], 1000).unwrap();
+    assert_eq!(found.chain_mappings.len(), 3);
+    assert_eq!(kv::get_chain_index(&kv, &solana_pubkey).unwrap(), vec![chain(1), chain(137), chain(42161)]);
+    assert_eq!(txn::get(&kv, &solana_pubkey, 1).unwrap().unwrap().status, TxnStatus::Committed);
+    assert!(!txn::recover(&kv, &solana_pubkey).unwrap());
+}
+
+#[test]
+fn test_store_completes_pending_journal_before_its_own() {
+    let flaky = FlakyKvStore { inner: MockKvStore::new(), writes_left: Mutex::new(4) };
+    let alice = wallet(1);
+    let solana_pubkey = pubkey(&alice);
+    assert!(store_with_backend_key(&flaky, &provision_request(&alice, vec![1])).is_err());
+
+    let kv = flaky.inner;
+    let response = store_with_backend_key(&kv, &provision_request(&alice, vec![137])).unwrap();
+    assert_eq!(response.chain_mappings.len(), 1);
+    assert_eq!(kv::get_chain_index(&kv, &solana_pubkey).unwrap(), vec![chain(1), chain(137)]);
+    for id in [1, 2] {
+        assert_eq!(txn::get(&kv, &solana_pubkey, id).unwrap().unwrap().status, TxnStatus::Committed);
+    }
+}
+
+// =============================================================================
+// IN-FLIGHT KEY TESTS
+// =============================================================================
+
+#[test]
+fn test_retry_maps_key_left_in_flight_by_failed_store() {
+    let kv = MockKvStore::new();
+    let default_key_counter = Arc::new(Mutex::new(0));
+    let keys = || MockKeyCreator {
+        default_key_counter: Arc::clone(&default_key_counter),
+        chain_key_counter: Arc::new(Mutex::new(1000)),
+    };
+    // The claim, the in-flight record and its release; then the KV fails
+    // before the default mapping
+    let flaky = FlakyKvStore { inner: kv.clone(), writes_left: Mutex::new(3) };
+    let alice = wallet(1);
+    let solana_pubkey = pubkey(&alice);
+    let err = Provisioner::new(flaky, keys()).handle(provision_request(&alice, vec![1])).unwrap_err();
+    assert_eq!(err.code(), "KV_ERROR");
+    assert!(kv::get_default_mapping(&kv, &solana_pubkey).unwrap().is_none());
+    assert!(inflight::get(&kv, &solana_pubkey, None, KeyType::default(), KeyClass::default()).unwrap().is_some());
+
+    let response = Provisioner::new(kv.clone(), keys()).handle(provision_request(&alice, vec![1])).unwrap();
+    assert_eq!(*default_key_counter.lock().unwrap(), 1);
+    let inflight = inflight::get(&kv, &solana_pubkey, None, KeyType::default(), KeyClass::default()).unwrap().unwrap();
+    assert_eq!(response.evm_address, evm(&inflight.address));
+    assert_eq!(response.key_id.as_deref(), Some(inflight.key_id.as_str()));
+}
+
+#[test]
+fn test_key_in_flight_is_not_reused_for_another_key_type() {
+    let kv = MockKvStore::new();
+    let default_key_counter = Arc::new(Mutex::new(0));
+    let keys = || MockKeyCreator {
+        default_key_counter: Arc::clone(&default_key_counter),
+        chain_key_counter: Arc::new(Mutex::new(1000)),
+    };
+    let flaky = FlakyKvStore { inner: kv.clone(), writes_left: Mutex::new(4) };
+    let alice = wallet(1);
+    let solana_pubkey = pubkey(&alice);
+    assert!(Provisioner::new(flaky, keys()).handle(provision_request(&alice, vec![1])).is_err());
+
+    let req = signed(&alice, ProvisionRequest { key_type: KeyType::SecpAvaAddr, ..provision_request(&alice, vec![1]) });
+    Provisioner::new(kv.clone(), keys()).handle(req).unwrap();
+    assert_eq!(*default_key_counter.lock().unwrap(), 2);
+    assert_eq!(kv::get_default_mapping(&kv, &solana_pubkey).unwrap().unwrap().key_type, KeyType::SecpAvaAddr);
+}
+
+#[test]
+fn test_held_claim_blocks_key_creation_until_it_expires() {
+    let kv = MockKvStore::new();
+    let default_key_counter = Arc::new(Mutex::new(0));
+    let keys = || MockKeyCreator {
+        default_key_counter: Arc::clone(&default_key_counter),
+        chain_key_counter: Arc::new(Mutex::new(1000)),
+    };
+    let clock = Arc::new(Mutex::new(1_700_000_000));
+    let provisioner = |kv: MockKvStore| {
+        let clock = Arc::clone(&clock);
+        Provisioner::new(kv, keys()).with_clock(move || *clock.lock().unwrap())
+    };
+    // The store nonce and the claim; then the KV fails at the in-flight record, leaving the claim held
+    let flaky = FlakyKvStore { inner: kv.clone(), writes_left: Mutex::new(2) };
+    let alice = wallet(1);
+    let solana_pubkey = pubkey(&alice);
+    let clocked = Provisioner::new(flaky, keys()).with_clock(|| 1_700_000_000);
+    assert_eq!(clocked.handle(provision_request_at(&alice, vec![1], 1_700_000_000)).unwrap_err().code(), "KV_ERROR");
+    let claim: inflight::KeyClaim = serde_json::from_str(&kv.get(&inflight::claim_key(&solana_pubkey, None, 1)).unwrap().unwrap()).unwrap();
+    assert_eq!(claim.status, inflight::ClaimStatus::Held);
+
+    let err = provisioner(kv.clone()).handle(provision_request_at(&alice, vec![1], 1_700_000_000)).unwrap_err();
+    assert_eq!(err.code(), "KV_CONFLICT");
+    assert!(err.is_retryable());
+
+    assert_eq!(*default_key_counter.lock().unwrap(), 1);
+
+    // Past the TTL the claim is taken over; the unrecorded key is left to `reconcile`
+    *clock.lock().unwrap() += inflight::CLAIM_TTL_SECS;
+    let response = provisioner(kv.clone()).handle(provision_request_at(&alice, vec![1], 1_700_000_000 + inflight::CLAIM_TTL_SECS)).unwrap();
+    assert_eq!(*default_key_counter.lock().unwrap(), 2);
+    let inflight = inflight::get(&kv, &solana_pubkey, None, KeyType::default(), KeyClass::default()).unwrap().unwrap();
+    assert_eq!(response.evm_address, evm(&inflight.address));
+    let claim: inflight::KeyClaim = serde_json::from_str(&kv.get(&inflight::claim_key(&solana_pubkey, None, 2)).unwrap().unwrap()).unwrap();
+    assert_eq!(claim.status, inflight::ClaimStatus::Released);
+}
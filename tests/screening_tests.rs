@@ -0,0 +1,125 @@
+mod common;
+
+use common::{approval_provisioner, idempotent_provisioner, named, owner};
+use cubist_wallet_provisioner::blocklist::BlockTarget;
+use cubist_wallet_provisioner::error::ProvisionError;
+use cubist_wallet_provisioner::kv;
+use cubist_wallet_provisioner::testing::{
+    admin, admins, chain, evm, mock_key, provision_request, provision_request_at, pubkey, update_request, wallet, MockKeyCreator, MockKvStore,
+};
+use cubist_wallet_provisioner::{BlockRequest, EvmAddress, FreezeRequest, KvStore, Provisioner};
+use std::sync::{Arc, Mutex};
+
+// =============================================================================
+// FREEZE TESTS
+// =============================================================================
+
+fn freeze_request(evm_address: &EvmAddress) -> FreezeRequest {
+    FreezeRequest { evm_address: evm_address.clone(), reason: Some("suspected compromise".to_string()), request_id: None }
+}
+
+#[test]
+fn test_frozen_address_is_reported_and_not_handed_out() {
+    let (provisioner, _) = approval_provisioner();
+    let alice = wallet(1);
+    let solana_pubkey = pubkey(&alice);
+    let provisioned = provisioner.handle(provision_request_at(&alice, vec![1], 1000)).unwrap();
+    let address = provisioned.evm_address;
+
+    let entry = provisioner.handle_freeze(&named("alice@test"), freeze_request(&address)).unwrap();
+    assert!(entry.frozen);
+    assert_eq!(entry.reason.as_deref(), Some("suspected compromise"));
+
+    let err = provisioner.handle(provision_request_at(&alice, vec![1, 137], 1000)).unwrap_err();
+    assert_eq!(err, ProvisionError::AddressFrozen(address.to_string()));
+    let found = provisioner.handle_get(&solana_pubkey, &[chain(1)]).unwrap();
+    assert_eq!(found.frozen_addresses, vec![address.clone()]);
+    // The mapping itself is untouched
+    assert_eq!(found.chain_mappings.get(&chain(1)), Some(&address));
+
+    let entry = provisioner.handle_unfreeze(&named("bob@test"), freeze_request(&address)).unwrap();
+    assert_eq!((entry.frozen, entry.reason, entry.updated_by.as_str()), (false, None, "bob@test"));
+    assert_eq!(provisioner.handle(provision_request_at(&alice, vec![1, 137], 1000)).unwrap().evm_address, address);
+    assert!(provisioner.handle_get(&solana_pubkey, &[chain(1)]).unwrap().frozen_addresses.is_empty());
+}
+
+#[test]
+fn test_freeze_requires_admin_and_covers_idempotent_replays() {
+    let (provisioner, bucket) = idempotent_provisioner();
+    let alice = wallet(1);
+    let mut req = provision_request(&alice, vec![1]);
+    req.idempotency_key = Some("order-7".to_string());
+    let address = provisioner.handle(req.clone()).unwrap().evm_address;
+    assert!(bucket.get("provision:order-7").unwrap().is_some());
+
+    provisioner.handle_freeze(&named("admin@test"), freeze_request(&address)).unwrap();
+    assert_eq!(provisioner.handle(req).unwrap_err().code(), "ADDRESS_FROZEN");
+
+    let (provisioner, _) = approval_provisioner();
+    let err = provisioner.handle_freeze(&named("mallory@test"), freeze_request(&address)).unwrap_err();
+    assert_eq!(err.code(), "NOT_ADMIN");
+    assert!(provisioner.handle_get_freeze(&address).unwrap().is_none());
+}
+
+// =============================================================================
+// BLOCKLIST TESTS
+// =============================================================================
+
+fn blocklist_provisioner() -> Provisioner<MockKvStore, MockKeyCreator> {
+    let keys = MockKeyCreator {
+        default_key_counter: Arc::new(Mutex::new(0)),
+        chain_key_counter: Arc::new(Mutex::new(1000)),
+    };
+    Provisioner::new(MockKvStore::new(), keys).with_admins(admins()).with_single_step_updates().with_blocklist(MockKvStore::new())
+}
+
+fn block_request(json: &str) -> BlockRequest {
+    serde_json::from_str(json).unwrap()
+}
+
+#[test]
+fn test_blocked_addresses_are_never_mapped() {
+    let provisioner = blocklist_provisioner();
+    let (alice, bob) = (wallet(1), wallet(2));
+
+    let req = block_request(&format!(r#"{{"solana_pubkey": "{}", "reason": "OFAC SDN", "actor": "admin@test"}}"#, pubkey(&alice)));
+    assert_eq!(req.target, BlockTarget::SolanaPubkey(pubkey(&alice)));
+    assert_eq!(provisioner.handle_block(&owner(), req).unwrap().reason.as_deref(), Some("OFAC SDN"));
+    let err = provisioner.handle(provision_request(&alice, vec![1])).unwrap_err();
+    assert_eq!(err, ProvisionError::Blocked(pubkey(&alice).to_string()));
+    assert!(kv::get_default_mapping(provisioner.kv(), &pubkey(&alice)).unwrap().is_none());
+
+    // The next default key the backend would hand out is blocked
+    let next_default = evm(&mock_key(1).address);
+    provisioner.handle_block(&owner(), block_request(&format!(r#"{{"evm_address": "{}"}}"#, next_default))).unwrap();
+    assert_eq!(provisioner.handle(provision_request(&bob, vec![1])).unwrap_err().code(), "BLOCKED");
+    assert!(kv::get_default_mapping(provisioner.kv(), &pubkey(&bob)).unwrap().is_none());
+
+    // So is the next chain key; the update fails and the chain keeps its mapping
+    let provisioned = provisioner.handle(provision_request(&bob, vec![137])).unwrap();
+    let next_chain_key = evm(&mock_key(1001).address);
+    provisioner.handle_block(&owner(), block_request(&format!(r#"{{"evm_address": "{}"}}"#, next_chain_key))).unwrap();
+    assert_eq!(provisioner.handle_update_mapping(&admin(), update_request(&pubkey(&bob), 137)).unwrap_err().code(), "BLOCKED");
+    assert_eq!(kv::get_existing_mapping(provisioner.kv(), &pubkey(&bob), &chain(137)).unwrap(), Some(provisioned.evm_address));
+
+    // Unblocking lifts the screen
+    provisioner.handle_unblock(&owner(), block_request(&format!(r#"{{"solana_pubkey": "{}"}}"#, pubkey(&alice)))).unwrap();
+    assert!(provisioner.handle(provision_request(&alice, vec![1])).is_ok());
+}
+
+#[test]
+fn test_block_requires_org_owner_and_blocklist_bucket() {
+    let target = BlockTarget::EvmAddress(evm("0x5555555555555555555555555555555555555555"));
+    let req = || BlockRequest { target: target.clone(), reason: None, request_id: None };
+
+    let (provisioner, _) = approval_provisioner();
+    assert_eq!(provisioner.handle_block(&owner(), req()).unwrap_err().code(), "NOT_CONFIGURED");
+
+    // Admins cannot write the blocklist; it is shared by every tenant
+    let provisioner = provisioner.with_blocklist(MockKvStore::new());
+    assert_eq!(provisioner.handle_block(&named("mallory@test"), req()).unwrap_err().code(), "NOT_ORG_OWNER");
+    assert_eq!(provisioner.handle_block(&named("alice@test"), req()).unwrap_err().code(), "NOT_ORG_OWNER");
+    assert!(provisioner.handle_get_block(&target).unwrap().is_none());
+    assert!(provisioner.handle_block(&owner(), req()).unwrap().blocked);
+    assert!(provisioner.handle_get_block(&target).unwrap().unwrap().blocked);
+}
@@ -0,0 +1,247 @@
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use cubist_wallet_provisioner::error::Result;
+use cubist_wallet_provisioner::server::{self, route};
+use cubist_wallet_provisioner::admin::Requester;
+use cubist_wallet_provisioner::testing::{admin, admins, store_message, TEST_ADMIN};
+use cubist_wallet_provisioner::{ChainId, CreatedKey, KeyCreator, KvStore, Provisioner, SolanaPubkey};
+use ed25519_dalek::{Signer, SigningKey};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+/// KV store over a shared map
+#[derive(Clone, Default)]
+struct MapKv(Arc<Mutex<HashMap<String, String>>>);
+
+impl KvStore for MapKv {
+    fn get(&self, key: &str) -> Result<Option<String>> {
+        Ok(self.0.lock().unwrap().get(key).cloned())
+    }
+
+    fn set_if_absent(&self, key: &str, value: &str) -> Result<bool> {
+        let mut data = self.0.lock().unwrap();
+        if data.contains_key(key) {
+            return Ok(false);
+        }
+        data.insert(key.to_string(), value.to_string());
+        Ok(true)
+    }
+
+    fn set(&self, key: &str, value: &str) -> Result<()> {
+        self.0.lock().unwrap().insert(key.to_string(), value.to_string());
+        Ok(())
+    }
+}
+
+/// Key creator handing out sequential addresses
+#[derive(Default)]
+struct SequentialKeys(AtomicUsize);
+
+impl SequentialKeys {
+    fn next(&self) -> Result<CreatedKey> {
+        let address = format!("0x{:040x}", self.0.fetch_add(1, Ordering::SeqCst) + 1);
+        Ok(CreatedKey { key_id: format!("Key#{}", address), address, policies: Vec::new() })
+    }
+}
+
+impl KeyCreator for SequentialKeys {
+    fn create_evm_key(&self, _solana_pubkey: &str) -> Result<CreatedKey> {
+        self.next()
+    }
+
+    fn create_evm_key_for_chain(&self, _solana_pubkey: &str, _chain_id: &ChainId) -> Result<CreatedKey> {
+        self.next()
+    }
+
+    fn create_labeled_evm_key(&self, _solana_pubkey: &str, _label: &str, _chain_id: Option<&ChainId>) -> Result<CreatedKey> {
+        self.next()
+    }
+}
+
+fn provisioner() -> Provisioner<MapKv, SequentialKeys> {
+    Provisioner::new(MapKv::default(), SequentialKeys::default()).with_admins(admins()).with_single_step_updates()
+}
+
+/// A caller the proxy did not name
+fn anonymous() -> Requester {
+    Requester::new(None, None)
+}
+
+fn wallet(seed: u8) -> (SigningKey, SolanaPubkey) {
+    let wallet = SigningKey::from_bytes(&[seed; 32]);
+    let solana_pubkey = SolanaPubkey::parse(&bs58::encode(wallet.verifying_key().to_bytes()).into_string()).unwrap();
+    (wallet, solana_pubkey)
+}
+
+/// `POST /provision` body for `solana_pubkey`, signed by `signer`
+fn signed_body(solana_pubkey: &SolanaPubkey, signer: &SigningKey, chain_ids: &[&str]) -> String {
+    let resolved: Vec<ChainId> = chain_ids.iter().filter_map(|chain_id| ChainId::resolve(chain_id).ok()).collect();
+    let message = store_message(solana_pubkey, &resolved);
+    json!({
+        "solana_pubkey": solana_pubkey.as_str(),
+        "chain_ids": chain_ids,
+        "signature": BASE64.encode(signer.sign(message.as_bytes()).to_bytes()),
+        "message": message,
+    })
+    .to_string()
+}
+
+/// Signed `POST /provision` body for the wallet with `seed`
+fn provision_body(seed: u8, chain_ids: &[&str]) -> (SolanaPubkey, String) {
+    let (signer, solana_pubkey) = wallet(seed);
+    let body = signed_body(&solana_pubkey, &signer, chain_ids);
+    (solana_pubkey, body)
+}
+
+fn body(response: &server::Response) -> Value {
+    serde_json::from_str(&response.body).unwrap()
+}
+
+#[test]
+fn test_routes_provision_update_and_get() {
+    let provisioner = provisioner();
+    let (solana_pubkey, request) = provision_body(1, &["eip155:1", "eip155:137"]);
+
+    let stored = route(&provisioner, &anonymous(), "POST", "/provision", &request);
+    assert_eq!(stored.status, 200);
+    let evm_address = body(&stored)["evm_address"].as_str().unwrap().to_string();
+
+    let update = json!({ "solana_pubkey": solana_pubkey.as_str(), "chain_id": "eip155:137" });
+    let updated = route(&provisioner, &admin(), "POST", "/update", &update.to_string());
+    assert_eq!(updated.status, 200);
+    let new_evm_address = body(&updated)["new_evm_address"].as_str().unwrap().to_string();
+    assert_ne!(new_evm_address, evm_address);
+
+    // Chain ids may be percent-encoded
+    let target = format!("/mappings/{}?chain_ids=eip155%3A1,137", solana_pubkey);
+    let mappings = route(&provisioner, &anonymous(), "GET", &target, "");
+    assert_eq!(mappings.status, 200);
+    let mappings = body(&mappings);
+    assert_eq!(mappings["default_address"], evm_address);
+    assert_eq!(mappings["chain_mappings"]["eip155:1"], evm_address);
+    assert_eq!(mappings["chain_mappings"]["eip155:137"], new_evm_address);
+
+    // ... or named
+    let named = route(&provisioner, &anonymous(), "GET", &format!("/mappings/{}?chain_ids=polygon", solana_pubkey), "");
+    assert_eq!(body(&named)["chain_mappings"]["eip155:137"], new_evm_address);
+    assert_eq!(body(&named)["chain_names"]["eip155:137"], "Polygon");
+}
+
+#[test]
+fn test_errors_map_to_statuses() {
+    let provisioner = provisioner();
+    let (_, solana_pubkey) = wallet(2);
+
+    let malformed = route(&provisioner, &anonymous(), "POST", "/provision", "{");
+    assert_eq!(malformed.status, 400);
+    assert_eq!(body(&malformed)["code"], "INVALID_REQUEST");
+    assert_eq!(body(&malformed)["retryable"], false);
+
+    let (other, _) = wallet(3);
+    let forged = route(&provisioner, &anonymous(), "POST", "/provision", &signed_body(&solana_pubkey, &other, &["eip155:1"]));
+    assert_eq!(forged.status, 401);
+    assert_eq!(body(&forged)["code"], "SIGNATURE_MISMATCH");
+
+    let update = json!({ "solana_pubkey": solana_pubkey.as_str(), "chain_id": "eip155:1" });
+    let missing = route(&provisioner, &admin(), "POST", "/update", &update.to_string());
+    assert_eq!(missing.status, 404);
+    assert_eq!(body(&missing)["code"], "NOT_PROVISIONED");
+    let refused = route(&provisioner, &anonymous(), "POST", "/update", &update.to_string());
+    assert_eq!(refused.status, 403);
+    assert_eq!(body(&refused)["code"], "FORBIDDEN");
+
+    assert_eq!(route(&provisioner, &anonymous(), "GET", "/mappings/not-a-pubkey", "").status, 400);
+    // No attestation signer configured
+    let attest = json!({ "solana_pubkey": solana_pubkey.as_str(), "chain_id": "eip155:1" });
+    let unconfigured = route(&provisioner, &anonymous(), "POST", "/attest", &attest.to_string());
+    assert_eq!(unconfigured.status, 501);
+    assert_eq!(body(&unconfigured)["code"], "NOT_CONFIGURED");
+    assert_eq!(route(&provisioner, &anonymous(), "GET", "/provision", "").status, 405);
+    assert_eq!(route(&provisioner, &anonymous(), "DELETE", &format!("/mappings/{}", solana_pubkey), "").status, 405);
+    let unknown = route(&provisioner, &anonymous(), "GET", "/health", "");
+    assert_eq!(unknown.status, 404);
+    assert_eq!(body(&unknown)["code"], "NOT_FOUND");
+}
+
+/// Send `request` and return the status and body of the response
+fn exchange(addr: std::net::SocketAddr, request: &str) -> (u16, String) {
+    let mut stream = TcpStream::connect(addr).unwrap();
+    stream.write_all(request.as_bytes()).unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    let (head, body) = response.split_once("\r\n\r\n").unwrap();
+    let status = head.split_whitespace().nth(1).unwrap().parse().unwrap();
+    (status, body.to_string())
+}
+
+#[test]
+fn test_serve_over_tcp() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let provisioner = Arc::new(provisioner());
+    thread::spawn(move || server::serve(listener, provisioner));
+
+    let (solana_pubkey, request) = provision_body(4, &["eip155:1"]);
+    let (status, response) = exchange(
+        addr,
+        &format!("POST /provision HTTP/1.1\r\nHost: test\r\nContent-Length: {}\r\n\r\n{}", request.len(), request),
+    );
+    assert_eq!(status, 200);
+    let evm_address = serde_json::from_str::<Value>(&response).unwrap()["evm_address"].clone();
+
+    let (status, response) = exchange(addr, &format!("GET /mappings/{}?chain_ids=1 HTTP/1.1\r\nHost: test\r\n\r\n", solana_pubkey));
+    assert_eq!(status, 200);
+    assert_eq!(serde_json::from_str::<Value>(&response).unwrap()["chain_mappings"]["eip155:1"], evm_address);
+
+    // The requester comes from the headers the proxy sets
+    let update = json!({ "solana_pubkey": solana_pubkey.as_str(), "chain_id": "eip155:1" }).to_string();
+    let post_update = |headers: &str| {
+        exchange(addr, &format!("POST /update HTTP/1.1\r\n{}Content-Length: {}\r\n\r\n{}", headers, update.len(), update))
+    };
+    assert_eq!(post_update("").0, 403);
+    let (status, response) = post_update(&format!("x-requester-identity: {}\r\n", TEST_ADMIN));
+    assert_eq!(status, 200);
+    assert_ne!(serde_json::from_str::<Value>(&response).unwrap()["new_evm_address"], evm_address);
+
+    let oversized = server::MAX_BODY_BYTES + 1;
+    let (status, _) = exchange(addr, &format!("POST /provision HTTP/1.1\r\nContent-Length: {}\r\n\r\n", oversized));
+    assert_eq!(status, 413);
+}
+
+#[test]
+fn test_serve_refuses_overlong_lines_and_too_many_connections() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let provisioner = Arc::new(provisioner());
+    thread::spawn(move || server::serve(listener, provisioner));
+
+    // Lines are cut off at the limit, with or without a line ending after it
+    let target = "a".repeat(server::MAX_LINE_BYTES);
+    let (status, response) = exchange(addr, &format!("GET /{}", &target[5..]));
+    assert_eq!(status, 414);
+    assert_eq!(serde_json::from_str::<Value>(&response).unwrap()["code"], "URI_TOO_LONG");
+    let request_line = "GET /mappings/x HTTP/1.1\r\n";
+    let (status, _) = exchange(addr, &format!("{}X-Long: {}", request_line, &target[8..]));
+    assert_eq!(status, 431);
+
+    // Connections past the limit are refused, unread, until one is freed
+    let held: Vec<TcpStream> = (0..server::MAX_CONNECTIONS).map(|_| TcpStream::connect(addr).unwrap()).collect();
+    let mut refused = String::new();
+    TcpStream::connect(addr).unwrap().read_to_string(&mut refused).unwrap();
+    assert!(refused.starts_with("HTTP/1.1 503 Service Unavailable\r\n"));
+    assert!(refused.ends_with(r#""code":"OVERLOADED","message":"more than 64 connections","retryable":false}"#));
+    drop(held);
+    let served = (0..100).any(|_| {
+        thread::sleep(std::time::Duration::from_millis(20));
+        let mut stream = TcpStream::connect(addr).unwrap();
+        let mut response = String::new();
+        stream.write_all(b"GET /health HTTP/1.1\r\n\r\n").is_ok()
+            && stream.read_to_string(&mut response).is_ok()
+            && response.starts_with("HTTP/1.1 404 Not Found\r\n")
+    });
+    assert!(served);
+}
@@ -0,0 +1,367 @@
+mod common;
+
+use common::{approval_provisioner, named, AAVE_POOL};
+use cubist_wallet_provisioner::error::ProvisionError;
+use cubist_wallet_provisioner::destinations;
+use cubist_wallet_provisioner::kv;
+use cubist_wallet_provisioner::signing_gate::{self, SigningRequest};
+use cubist_wallet_provisioner::spend_limits::{SpendLimit, Wei};
+use cubist_wallet_provisioner::testing::{
+    admin, admins, chain, evm, provision_request, provision_request_at, pubkey, update_request, wallet, MockKeyCreator, MockKvStore, TestContext,
+};
+use cubist_wallet_provisioner::{AllowedDestinationRequest, EvmAddress, Provisioner, SetSpendLimitRequest, SolanaPubkey};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+// =============================================================================
+// SIGNING GATE TESTS
+// =============================================================================
+
+fn signing_request(solana_pubkey: &SolanaPubkey, evm_address: &EvmAddress, chain_id: Option<u64>) -> SigningRequest {
+    SigningRequest {
+        solana_pubkey: solana_pubkey.clone(),
+        evm_address: evm_address.clone(),
+        chain_id: chain_id.map(chain),
+        value: None,
+        to: None,
+    }
+}
+
+#[test]
+fn test_signing_gate_allows_current_mappings_only() {
+    let ctx = TestContext::new();
+    let alice = wallet(1);
+    let solana_pubkey = pubkey(&alice);
+    let default_address = ctx.handle(provision_request(&alice, vec![1])).unwrap().evm_address;
+
+    // Default address: without a chain, on its own chain, and on a chain inheriting it
+    for chain_id in [None, Some(1), Some(42161)] {
+        ctx.provisioner.handle_authorize_signing(&signing_request(&solana_pubkey, &default_address, chain_id)).unwrap();
+    }
+
+    let rotated = ctx.handle_update_mapping(update_request(&solana_pubkey, 1)).unwrap().new_evm_address;
+    ctx.provisioner.handle_authorize_signing(&signing_request(&solana_pubkey, &rotated, Some(1))).unwrap();
+    ctx.provisioner.handle_authorize_signing(&signing_request(&solana_pubkey, &rotated, None)).unwrap();
+
+    // Chain 1 was rotated away from the default key
+    let err = ctx.provisioner
+        .handle_authorize_signing(&signing_request(&solana_pubkey, &default_address, Some(1)))
+        .unwrap_err();
+    assert_eq!(err.code(), "ADDRESS_NOT_MAPPED");
+    // ...and the rotated key is not mapped on other chains
+    let err = ctx.provisioner
+        .handle_authorize_signing(&signing_request(&solana_pubkey, &rotated, Some(42161)))
+        .unwrap_err();
+    assert_eq!(err.code(), "ADDRESS_NOT_MAPPED");
+}
+
+#[test]
+fn test_signing_gate_refuses_other_users_keys() {
+    let ctx = TestContext::new();
+    let alice = wallet(1);
+    let bob = wallet(2);
+    let alice_address = ctx.handle(provision_request(&alice, vec![1])).unwrap().evm_address;
+    ctx.handle(provision_request(&bob, vec![1])).unwrap();
+
+    let err = ctx.provisioner
+        .handle_authorize_signing(&signing_request(&pubkey(&bob), &alice_address, None))
+        .unwrap_err();
+    assert_eq!(
+        err,
+        ProvisionError::AddressNotMapped {
+            evm_address: alice_address.to_string(),
+            solana_pubkey: pubkey(&bob).to_string(),
+        }
+    );
+    // Unprovisioned users have nothing to sign with
+    let err = ctx.provisioner
+        .handle_authorize_signing(&signing_request(&pubkey(&wallet(3)), &alice_address, Some(1)))
+        .unwrap_err();
+    assert_eq!(err.code(), "ADDRESS_NOT_MAPPED");
+}
+
+#[test]
+fn test_signing_request_comes_from_authenticated_fields_only() {
+    let ctx = TestContext::new();
+    let alice = wallet(1);
+    let solana_pubkey = pubkey(&alice);
+    let address = ctx.handle(provision_request(&alice, vec![1])).unwrap().evm_address;
+    let key_id = format!("Key#{}", address.as_str());
+    let body = r#"{"chain_id":1,"tx":{"to":"0x68b3465833fb72a70ecdf485e0e4c7bd8665fc45","value":"0x64"}}"#;
+
+    let err = signing_gate::signing_request(&ctx.kv, "User#alice", &key_id, Some(body)).unwrap_err();
+    assert_eq!(err.code(), "UNKNOWN_SIGNER");
+    assert_eq!(signing_gate::signing_request(&ctx.kv, "", &key_id, Some(body)).unwrap_err().code(), "UNKNOWN_SIGNER");
+
+    signing_gate::set_signer(&ctx.kv, "User#alice", Some(&solana_pubkey)).unwrap();
+    let req = signing_gate::signing_request(&ctx.kv, "User#alice", &key_id, Some(body)).unwrap();
+    assert_eq!(req, SigningRequest {
+        solana_pubkey: solana_pubkey.clone(),
+        evm_address: address.clone(),
+        chain_id: Some(chain(1)),
+        value: Some(Wei(100)),
+        to: Some(evm("0x68b3465833fb72a70ecdf485e0e4c7bd8665fc45")),
+    });
+    ctx.provisioner.handle_authorize_signing(&req).unwrap();
+
+    // A body naming another user or key changes nothing
+    let claimed = format!(r#"{{"solana_pubkey":"{}","evm_address":"{}"}}"#, pubkey(&wallet(2)), evm("0x1111111111111111111111111111111111111111"));
+    let req = signing_gate::signing_request(&ctx.kv, "User#alice", &key_id, Some(&claimed)).unwrap();
+    assert_eq!((req.solana_pubkey, req.evm_address, req.value), (solana_pubkey.clone(), address, None));
+
+    // Messages have no transaction; other keys are not EVM keys
+    assert_eq!(signing_gate::signing_request(&ctx.kv, "User#alice", &key_id, None).unwrap().chain_id, None);
+    assert_eq!(signing_gate::signing_request(&ctx.kv, "User#alice", "Key#ed25519", None).unwrap_err().code(), "INVALID_REQUEST");
+
+    signing_gate::set_signer(&ctx.kv, "User#alice", None).unwrap();
+    let err = signing_gate::signing_request(&ctx.kv, "User#alice", &key_id, Some(body)).unwrap_err();
+    assert_eq!(err.code(), "UNKNOWN_SIGNER");
+}
+
+// =============================================================================
+// SPEND LIMIT TESTS
+// =============================================================================
+
+fn spend_limit_request(solana_pubkey: &SolanaPubkey, chain_id: Option<u64>, max_tx_value: u128, daily_cap: u128) -> SetSpendLimitRequest {
+    SetSpendLimitRequest {
+        solana_pubkey: solana_pubkey.clone(),
+        chain_id: chain_id.map(chain),
+        spend_limit: Some(SpendLimit { max_tx_value: Some(Wei(max_tx_value)), daily_cap: Some(Wei(daily_cap)) }),
+        request_id: None,
+    }
+}
+
+fn transaction(solana_pubkey: &SolanaPubkey, evm_address: &EvmAddress, chain_id: u64, value: u128) -> SigningRequest {
+    SigningRequest { value: Some(Wei(value)), ..signing_request(solana_pubkey, evm_address, Some(chain_id)) }
+}
+
+#[test]
+fn test_signing_gate_enforces_max_tx_value_and_daily_cap() {
+    let ctx = TestContext::new();
+    let alice = wallet(1);
+    let solana_pubkey = pubkey(&alice);
+    let address = ctx.handle(provision_request(&alice, vec![1])).unwrap().evm_address;
+    let record = ctx.provisioner.handle_set_spend_limit(&admin(), spend_limit_request(&solana_pubkey, None, 100, 250)).unwrap();
+    assert!(record.encode().contains(r#""spend_limit":{"max_tx_value":"100","daily_cap":"250"}"#));
+
+    let err = ctx.provisioner.handle_authorize_signing(&transaction(&solana_pubkey, &address, 1, 101)).unwrap_err();
+    assert_eq!(err, ProvisionError::SpendLimitExceeded { limit: "max_tx_value", value: "101".to_string(), allowed: "100".to_string() });
+
+    ctx.provisioner.handle_authorize_signing(&transaction(&solana_pubkey, &address, 1, 100)).unwrap();
+    ctx.provisioner.handle_authorize_signing(&transaction(&solana_pubkey, &address, 1, 100)).unwrap();
+    let err = ctx.provisioner.handle_authorize_signing(&transaction(&solana_pubkey, &address, 1, 60)).unwrap_err();
+    assert_eq!(err, ProvisionError::SpendLimitExceeded { limit: "daily_cap", value: "60".to_string(), allowed: "50".to_string() });
+
+    // Refused values are not counted; other chains have their own total
+    let status = ctx.provisioner.handle_get_spend_limit(&solana_pubkey, Some(&chain(1))).unwrap();
+    assert_eq!(status.spent_today, Some(Wei(200)));
+    ctx.provisioner.handle_authorize_signing(&transaction(&solana_pubkey, &address, 42161, 100)).unwrap();
+
+    // Signing requests without a value are not limited
+    ctx.provisioner.handle_authorize_signing(&signing_request(&solana_pubkey, &address, Some(1))).unwrap();
+    let err = ctx.provisioner
+        .handle_authorize_signing(&SigningRequest { value: Some(Wei(1)), ..signing_request(&solana_pubkey, &address, None) })
+        .unwrap_err();
+    assert_eq!(err.code(), "INVALID_REQUEST");
+}
+
+#[test]
+fn test_signing_gate_limits_the_value_of_the_signed_transaction() {
+    let ctx = TestContext::new();
+    let alice = wallet(1);
+    let solana_pubkey = pubkey(&alice);
+    let address = ctx.handle(provision_request(&alice, vec![1])).unwrap().evm_address;
+    ctx.provisioner.handle_set_spend_limit(&admin(), spend_limit_request(&solana_pubkey, None, 100, 150)).unwrap();
+    signing_gate::set_signer(&ctx.kv, "User#alice", Some(&solana_pubkey)).unwrap();
+    let key_id = format!("Key#{}", address.as_str());
+    let gate = |body: &str| {
+        let req = signing_gate::signing_request(&ctx.kv, "User#alice", &key_id, Some(body))?;
+        ctx.provisioner.handle_authorize_signing(&req)
+    };
+
+    let err = gate(r#"{"chain_id":1,"tx":{"to":"0x68b3465833fb72a70ecdf485e0e4c7bd8665fc45","value":"0x65"}}"#).unwrap_err();
+    assert_eq!(err, ProvisionError::SpendLimitExceeded { limit: "max_tx_value", value: "101".to_string(), allowed: "100".to_string() });
+    gate(r#"{"chain_id":1,"tx":{"to":"0x68b3465833fb72a70ecdf485e0e4c7bd8665fc45","value":"0x64"}}"#).unwrap();
+    let err = gate(r#"{"chain_id":1,"tx":{"to":"0x68b3465833fb72a70ecdf485e0e4c7bd8665fc45","value":"0x64"}}"#).unwrap_err();
+    assert_eq!(err.code(), "SPEND_LIMIT_EXCEEDED");
+
+    // A transaction without a value sends nothing; messages are not transactions
+    gate(r#"{"chain_id":1,"tx":{"to":"0x68b3465833fb72a70ecdf485e0e4c7bd8665fc45"}}"#).unwrap();
+    gate(r#"{"data":"0x68656c6c6f"}"#).unwrap();
+    let status = ctx.provisioner.handle_get_spend_limit(&solana_pubkey, Some(&chain(1))).unwrap();
+    assert_eq!(status.spent_today, Some(Wei(100)));
+}
+
+#[test]
+fn test_daily_cap_resets_at_utc_midnight() {
+    let now = Arc::new(AtomicU64::new(86_400 * 19_000 + 86_399));
+    let clock = Arc::clone(&now);
+    let provisioner = Provisioner::new(MockKvStore::new(), MockKeyCreator {
+        default_key_counter: Arc::new(Mutex::new(0)),
+        chain_key_counter: Arc::new(Mutex::new(1000)),
+    }).with_admins(admins()).with_single_step_updates()
+    .with_clock(move || clock.load(Ordering::SeqCst));
+    let alice = wallet(1);
+    let solana_pubkey = pubkey(&alice);
+    let address = provisioner.handle(provision_request_at(&alice, vec![1], 86_400 * 19_000 + 86_399)).unwrap().evm_address;
+    provisioner.handle_set_spend_limit(&admin(), spend_limit_request(&solana_pubkey, None, 100, 100)).unwrap();
+
+    provisioner.handle_authorize_signing(&transaction(&solana_pubkey, &address, 1, 100)).unwrap();
+    assert_eq!(provisioner.handle_authorize_signing(&transaction(&solana_pubkey, &address, 1, 1)).unwrap_err().code(), "SPEND_LIMIT_EXCEEDED");
+    now.fetch_add(1, Ordering::SeqCst);
+    provisioner.handle_authorize_signing(&transaction(&solana_pubkey, &address, 1, 100)).unwrap();
+}
+
+#[test]
+fn test_chain_limit_overrides_default_and_survives_rotation() {
+    let ctx = TestContext::new();
+    let alice = wallet(1);
+    let solana_pubkey = pubkey(&alice);
+    let address = ctx.handle(provision_request(&alice, vec![1, 137])).unwrap().evm_address;
+    ctx.provisioner.handle_set_spend_limit(&admin(), spend_limit_request(&solana_pubkey, None, 100, 1000)).unwrap();
+    ctx.provisioner.handle_set_spend_limit(&admin(), spend_limit_request(&solana_pubkey, Some(137), 5000, 10_000)).unwrap();
+
+    ctx.provisioner.handle_authorize_signing(&transaction(&solana_pubkey, &address, 137, 5000)).unwrap();
+    assert_eq!(ctx.provisioner.handle_authorize_signing(&transaction(&solana_pubkey, &address, 1, 5000)).unwrap_err().code(), "SPEND_LIMIT_EXCEEDED");
+
+    let rotated = ctx.handle_update_mapping(update_request(&solana_pubkey, 137)).unwrap().new_evm_address;
+    let record = kv::get_chain_mapping(&ctx.kv, &solana_pubkey, &chain(137)).unwrap().unwrap();
+    assert_eq!(record.spend_limit.and_then(|limit| limit.max_tx_value), Some(Wei(5000)));
+    ctx.provisioner.handle_authorize_signing(&transaction(&solana_pubkey, &rotated, 137, 5000)).unwrap();
+
+    // Inherited chains have no record of their own; clearing lifts the limit
+    let err = ctx.provisioner.handle_set_spend_limit(&admin(), spend_limit_request(&solana_pubkey, Some(10), 1, 1)).unwrap_err();
+    assert_eq!(err.code(), "INVALID_REQUEST");
+    ctx.provisioner
+        .handle_set_spend_limit(&admin(), SetSpendLimitRequest { spend_limit: None, ..spend_limit_request(&solana_pubkey, None, 0, 0) })
+        .unwrap();
+    assert_eq!(ctx.provisioner.handle_get_spend_limit(&solana_pubkey, None).unwrap().spend_limit, None);
+    ctx.provisioner.handle_authorize_signing(&transaction(&solana_pubkey, &address, 1, 5000)).unwrap();
+}
+
+#[test]
+fn test_set_spend_limit_requires_admin() {
+    let (provisioner, _) = approval_provisioner();
+    let alice = wallet(1);
+    provisioner.handle(provision_request_at(&alice, vec![1], 1000)).unwrap();
+
+    let req = spend_limit_request(&pubkey(&alice), None, 1, 1);
+    assert_eq!(provisioner.handle_set_spend_limit(&named("mallory@test"), req).unwrap_err().code(), "NOT_ADMIN");
+    let req = spend_limit_request(&pubkey(&wallet(2)), None, 1, 1);
+    assert_eq!(provisioner.handle_set_spend_limit(&named("alice@test"), req).unwrap_err().code(), "NOT_PROVISIONED");
+}
+
+#[test]
+fn test_wei_accepts_decimal_hex_and_numbers() {
+    let limit: SpendLimit = serde_json::from_str(r#"{"max_tx_value": "0xde0b6b3a7640000", "daily_cap": 5}"#).unwrap();
+    assert_eq!(limit.max_tx_value, Some(Wei(1_000_000_000_000_000_000)));
+    assert_eq!(limit.daily_cap, Some(Wei(5)));
+    assert_eq!(serde_json::to_string(&limit).unwrap(), r#"{"max_tx_value":"1000000000000000000","daily_cap":"5"}"#);
+    assert!(serde_json::from_str::<SpendLimit>(r#"{"max_tx_value": "1 ETH"}"#).is_err());
+}
+
+// =============================================================================
+// DESTINATION ALLOWLIST TESTS
+// =============================================================================
+
+const UNISWAP_ROUTER: &str = "0x68b3465833fb72A70ecDF485E0e4C7bD8665Fc45";
+
+fn destination_request(solana_pubkey: &SolanaPubkey, chain_id: u64, destination: &str) -> AllowedDestinationRequest {
+    AllowedDestinationRequest {
+        solana_pubkey: solana_pubkey.clone(),
+        chain_id: chain(chain_id),
+        destination: evm(destination),
+        request_id: None,
+    }
+}
+
+fn transaction_to(solana_pubkey: &SolanaPubkey, evm_address: &EvmAddress, chain_id: u64, to: &str) -> SigningRequest {
+    SigningRequest { to: Some(evm(to)), ..transaction(solana_pubkey, evm_address, chain_id, 0) }
+}
+
+#[test]
+fn test_signing_gate_enforces_destination_allowlist() {
+    let ctx = TestContext::new();
+    let alice = wallet(1);
+    let solana_pubkey = pubkey(&alice);
+    let address = ctx.handle(provision_request(&alice, vec![1, 137])).unwrap().evm_address;
+
+    // Unrestricted until a destination is added
+    ctx.provisioner.handle_authorize_signing(&transaction_to(&solana_pubkey, &address, 1, AAVE_POOL)).unwrap();
+    let allowed = ctx.provisioner.handle_add_allowed_destination(&admin(), destination_request(&solana_pubkey, 1, UNISWAP_ROUTER)).unwrap();
+    assert_eq!(allowed.destinations, vec![evm(UNISWAP_ROUTER)]);
+
+    ctx.provisioner.handle_authorize_signing(&transaction_to(&solana_pubkey, &address, 1, UNISWAP_ROUTER)).unwrap();
+    let err = ctx.provisioner.handle_authorize_signing(&transaction_to(&solana_pubkey, &address, 1, AAVE_POOL)).unwrap_err();
+    assert_eq!(err, ProvisionError::DestinationNotAllowed { to: Some(evm(AAVE_POOL).to_string()), chain_id: "eip155:1".to_string() });
+    // A transaction without `to` deploys a contract
+    let err = ctx.provisioner.handle_authorize_signing(&transaction(&solana_pubkey, &address, 1, 0)).unwrap_err();
+    assert_eq!(err, ProvisionError::DestinationNotAllowed { to: None, chain_id: "eip155:1".to_string() });
+
+    // Other chains and non-transaction signatures are not restricted
+    ctx.provisioner.handle_authorize_signing(&transaction_to(&solana_pubkey, &address, 137, AAVE_POOL)).unwrap();
+    ctx.provisioner.handle_authorize_signing(&signing_request(&solana_pubkey, &address, Some(1))).unwrap();
+    let err = ctx.provisioner
+        .handle_authorize_signing(&SigningRequest { to: Some(evm(UNISWAP_ROUTER)), ..signing_request(&solana_pubkey, &address, None) })
+        .unwrap_err();
+    assert_eq!(err.code(), "INVALID_REQUEST");
+}
+
+#[test]
+fn test_signing_gate_checks_the_recipient_of_the_signed_transaction() {
+    let ctx = TestContext::new();
+    let alice = wallet(1);
+    let solana_pubkey = pubkey(&alice);
+    let address = ctx.handle(provision_request(&alice, vec![1])).unwrap().evm_address;
+    ctx.provisioner.handle_add_allowed_destination(&admin(), destination_request(&solana_pubkey, 1, UNISWAP_ROUTER)).unwrap();
+    signing_gate::set_signer(&ctx.kv, "User#alice", Some(&solana_pubkey)).unwrap();
+    let key_id = format!("Key#{}", address.as_str());
+    let gate = |body: String| {
+        let req = signing_gate::signing_request(&ctx.kv, "User#alice", &key_id, Some(&body))?;
+        ctx.provisioner.handle_authorize_signing(&req)
+    };
+
+    gate(format!(r#"{{"chain_id":1,"tx":{{"to":"{}","data":"0x"}}}}"#, UNISWAP_ROUTER)).unwrap();
+    let err = gate(format!(r#"{{"chain_id":1,"tx":{{"to":"{}","data":"0x"}}}}"#, AAVE_POOL)).unwrap_err();
+    assert_eq!(err, ProvisionError::DestinationNotAllowed { to: Some(evm(AAVE_POOL).to_string()), chain_id: "eip155:1".to_string() });
+    // Deployments have no `to`, even with no value
+    let err = gate(r#"{"chain_id":1,"tx":{"data":"0x6080"}}"#.to_string()).unwrap_err();
+    assert_eq!(err, ProvisionError::DestinationNotAllowed { to: None, chain_id: "eip155:1".to_string() });
+}
+
+#[test]
+fn test_removing_last_destination_lifts_restriction() {
+    let ctx = TestContext::new();
+    let alice = wallet(1);
+    let solana_pubkey = pubkey(&alice);
+    let address = ctx.handle(provision_request(&alice, vec![1])).unwrap().evm_address;
+    ctx.provisioner.handle_add_allowed_destination(&admin(), destination_request(&solana_pubkey, 1, UNISWAP_ROUTER)).unwrap();
+    ctx.provisioner.handle_add_allowed_destination(&admin(), destination_request(&solana_pubkey, 1, AAVE_POOL)).unwrap();
+    // Adding twice keeps one entry
+    let allowed = ctx.provisioner.handle_add_allowed_destination(&admin(), destination_request(&solana_pubkey, 1, AAVE_POOL)).unwrap();
+    assert_eq!(allowed.destinations.len(), 2);
+
+    let allowed = ctx.provisioner.handle_remove_allowed_destination(&admin(), destination_request(&solana_pubkey, 1, UNISWAP_ROUTER)).unwrap();
+    assert_eq!(allowed.destinations, vec![evm(AAVE_POOL)]);
+    assert_eq!(
+        ctx.provisioner.handle_authorize_signing(&transaction_to(&solana_pubkey, &address, 1, UNISWAP_ROUTER)).unwrap_err().code(),
+        "DESTINATION_NOT_ALLOWED"
+    );
+
+    ctx.provisioner.handle_remove_allowed_destination(&admin(), destination_request(&solana_pubkey, 1, AAVE_POOL)).unwrap();
+    assert!(destinations::get_allowed(&ctx.kv, &solana_pubkey, &chain(1)).unwrap().is_empty());
+    ctx.provisioner.handle_authorize_signing(&transaction_to(&solana_pubkey, &address, 1, UNISWAP_ROUTER)).unwrap();
+}
+
+#[test]
+fn test_allowed_destinations_require_admin() {
+    let (provisioner, _) = approval_provisioner();
+    let alice = wallet(1);
+    provisioner.handle(provision_request_at(&alice, vec![1], 1000)).unwrap();
+
+    let req = destination_request(&pubkey(&alice), 1, AAVE_POOL);
+    assert_eq!(provisioner.handle_add_allowed_destination(&named("mallory@test"), req.clone()).unwrap_err().code(), "NOT_ADMIN");
+    assert_eq!(provisioner.handle_remove_allowed_destination(&named("mallory@test"), req).unwrap_err().code(), "NOT_ADMIN");
+    let req = destination_request(&pubkey(&wallet(2)), 1, AAVE_POOL);
+    assert_eq!(provisioner.handle_add_allowed_destination(&named("alice@test"), req).unwrap_err().code(), "NOT_PROVISIONED");
+}
@@ -0,0 +1,178 @@
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use cubist_wallet_provisioner::cubesigner_client::{CubeSignerClient, HttpRequest, HttpResponse, HttpTransport, RetryPolicy};
+use cubist_wallet_provisioner::error::Result;
+use cubist_wallet_provisioner::mapping;
+use cubist_wallet_provisioner::solana_sync::{self, CubeSignerOperator, OperatorSigner, SyncStatus, MAPPING_SEED};
+use cubist_wallet_provisioner::{ChainId, EvmAddress, KeyClass, KeyType, KvStore, MappingRecord, ProvisionRequest, SolanaPubkey};
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier};
+use sha2::{Digest, Sha256};
+use solana_pubkey::Pubkey;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+/// KV store over a shared map
+#[derive(Clone, Default)]
+struct MapKv(Arc<Mutex<HashMap<String, String>>>);
+
+impl KvStore for MapKv {
+    fn get(&self, key: &str) -> Result<Option<String>> {
+        Ok(self.0.lock().unwrap().get(key).cloned())
+    }
+
+    fn set_if_absent(&self, key: &str, value: &str) -> Result<bool> {
+        let mut data = self.0.lock().unwrap();
+        if data.contains_key(key) {
+            return Ok(false);
+        }
+        data.insert(key.to_string(), value.to_string());
+        Ok(true)
+    }
+
+    fn set(&self, key: &str, value: &str) -> Result<()> {
+        self.0.lock().unwrap().insert(key.to_string(), value.to_string());
+        Ok(())
+    }
+}
+
+/// Operator signing with a local ed25519 key
+struct LocalOperator {
+    key: SigningKey,
+    address: SolanaPubkey,
+}
+
+impl LocalOperator {
+    fn new(seed: u8) -> Self {
+        let key = SigningKey::from_bytes(&[seed; 32]);
+        Self { address: solana_pubkey(&key), key }
+    }
+}
+
+impl OperatorSigner for LocalOperator {
+    fn operator(&self) -> &SolanaPubkey {
+        &self.address
+    }
+
+    fn sign_message(&self, message: &[u8]) -> Result<[u8; 64]> {
+        Ok(self.key.sign(message).to_bytes())
+    }
+}
+
+/// Transport answering every request with `body`
+struct FixedTransport(String);
+
+impl HttpTransport for FixedTransport {
+    fn send(&self, _request: HttpRequest) -> std::result::Result<HttpResponse, String> {
+        Ok(HttpResponse { status: 200, body: self.0.clone() })
+    }
+}
+
+const BLOCKHASH: &str = "EkSnNWid2cvwEVnVx9aBqawnmiCNiDgp3gUdkDPTKN1N";
+
+fn solana_pubkey(key: &SigningKey) -> SolanaPubkey {
+    SolanaPubkey::parse(&bs58::encode(key.verifying_key().to_bytes()).into_string()).unwrap()
+}
+
+fn program_id() -> SolanaPubkey {
+    SolanaPubkey::parse(&bs58::encode([42u8; 32]).into_string()).unwrap()
+}
+
+fn evm_address() -> EvmAddress {
+    EvmAddress::parse("0x1111111111111111111111111111111111111111").unwrap()
+}
+
+/// Provision `seed`'s wallet with the backend key 0x11…11
+fn provision(kv: &MapKv, seed: u8) -> SolanaPubkey {
+    let wallet = SigningKey::from_bytes(&[seed; 32]);
+    let solana_pubkey = solana_pubkey(&wallet);
+    let message = format!("Provision EVM wallet for {}", solana_pubkey);
+    let req = ProvisionRequest {
+        solana_pubkey: solana_pubkey.clone(),
+        chain_ids: vec![ChainId::eip155(1)],
+        signature: BASE64.encode(wallet.sign(message.as_bytes()).to_bytes()),
+        message,
+        label: None,
+        key_type: KeyType::default(),
+        key_class: KeyClass::default(),
+        idempotency_key: None,
+        ttl_secs: None,
+        request_id: None,
+    };
+    mapping::store(kv, &req, 10, || Ok(MappingRecord::new(&evm_address(), Some("Key#1"), solana_pubkey.as_str(), 10))).unwrap();
+    solana_pubkey
+}
+
+#[test]
+fn test_set_mapping_instruction_targets_the_user_pda() {
+    let operator = LocalOperator::new(9);
+    let user = solana_pubkey(&SigningKey::from_bytes(&[1; 32]));
+    let instruction = solana_sync::set_mapping_instruction(&program_id(), &operator.address, &user, &evm_address());
+
+    let program = Pubkey::new_from_array(program_id().to_bytes());
+    let (pda, bump) = Pubkey::find_program_address(&[MAPPING_SEED, &user.to_bytes()], &program);
+    assert_eq!(solana_sync::mapping_address(&program_id(), &user), (SolanaPubkey::parse(&pda.to_string()).unwrap(), bump));
+    assert_eq!(instruction.program_id, program);
+
+    let accounts: Vec<(Pubkey, bool, bool)> = instruction.accounts.iter().map(|a| (a.pubkey, a.is_signer, a.is_writable)).collect();
+    let operator_key = Pubkey::new_from_array(operator.address.to_bytes());
+    assert_eq!(accounts, vec![(pda, false, true), (operator_key, true, true), (Pubkey::default(), false, false)]);
+
+    assert_eq!(instruction.data[..8], Sha256::digest(b"global:set_mapping")[..8]);
+    assert_eq!(instruction.data[8..40], user.to_bytes());
+    assert_eq!(instruction.data[40..], [0x11; 20]);
+}
+
+#[test]
+fn test_sync_signs_the_transaction_once_and_tracks_status() {
+    let kv = MapKv::default();
+    let operator = LocalOperator::new(9);
+    let user = provision(&kv, 1);
+
+    let record = solana_sync::sync(&kv, &operator, &program_id(), &user, BLOCKHASH, 20).unwrap().unwrap();
+    assert_eq!((&record.address, &record.status), (&evm_address(), &SyncStatus::Signed));
+
+    // One signature by the operator over the rest of the transaction
+    let tx = BASE64.decode(&record.signed_tx).unwrap();
+    assert_eq!(tx[0], 1);
+    let signature = Signature::from_bytes(tx[1..65].try_into().unwrap());
+    operator.key.verifying_key().verify(&tx[65..], &signature).unwrap();
+    let blockhash = bs58::decode(BLOCKHASH).into_vec().unwrap();
+    assert!(tx[65..].windows(32).any(|window| window == blockhash));
+
+    // Already signed: nothing to do until the sync fails
+    assert!(solana_sync::sync(&kv, &operator, &program_id(), &user, BLOCKHASH, 21).unwrap().is_none());
+    let submitted = SyncStatus::Submitted { signature: "5VERv8".to_string() };
+    solana_sync::record_status(&kv, &user, submitted, 22).unwrap();
+    solana_sync::record_status(&kv, &user, SyncStatus::Failed { error: "blockhash expired".to_string() }, 23).unwrap();
+    let resigned = solana_sync::sync(&kv, &operator, &program_id(), &user, BLOCKHASH, 24).unwrap().unwrap();
+    assert_eq!(resigned.status, SyncStatus::Signed);
+    assert_eq!(solana_sync::get_sync(&kv, &user).unwrap().unwrap().updated_at, 24);
+}
+
+#[test]
+fn test_sync_rejects_unprovisioned_users_and_bad_blockhashes() {
+    let kv = MapKv::default();
+    let operator = LocalOperator::new(9);
+    let user = provision(&kv, 1);
+
+    let err = solana_sync::sync(&kv, &operator, &program_id(), &user, "not-a-blockhash", 20).unwrap_err();
+    assert_eq!(err.code(), "INVALID_REQUEST");
+
+    let unknown = solana_pubkey(&SigningKey::from_bytes(&[2; 32]));
+    assert_eq!(solana_sync::sync(&kv, &operator, &program_id(), &unknown, BLOCKHASH, 20).unwrap_err().code(), "NOT_PROVISIONED");
+    assert_eq!(solana_sync::record_status(&kv, &unknown, SyncStatus::Signed, 20).unwrap_err().code(), "INVALID_REQUEST");
+}
+
+#[test]
+fn test_cubesigner_operator_decodes_hex_signatures() {
+    let client = |body: &str| {
+        CubeSignerClient::new(FixedTransport(body.to_string()), "https://signer.example", "Org#123", "token").with_retry(RetryPolicy::none())
+    };
+    let operator = program_id();
+
+    let signature = format!(r#"{{"signature":"0x{}"}}"#, "ab".repeat(64));
+    let signed = CubeSignerOperator::new(client(&signature), operator.clone()).sign_message(b"message").unwrap();
+    assert_eq!(signed, [0xab; 64]);
+
+    let err = CubeSignerOperator::new(client(r#"{"signature":"0x1234"}"#), operator).sign_message(b"message").unwrap_err();
+    assert_eq!(err.code(), "SIGNING_FAILED");
+}
@@ -0,0 +1,225 @@
+mod common;
+
+use common::{entry, import_request};
+use cubist_wallet_provisioner::environment::{self, is_environment_key, EnvPrefixed, Environment};
+use cubist_wallet_provisioner::export::ExportRequest;
+use cubist_wallet_provisioner::import::ImportStrategy;
+use cubist_wallet_provisioner::kv::default_key;
+use cubist_wallet_provisioner::tenant::{self, Namespaced, TenantId};
+use cubist_wallet_provisioner::testing::{admin, admins, chain, provision_request, pubkey, wallet, MockKeyCreator, MockKvStore};
+use cubist_wallet_provisioner::{KvStore, Provisioner};
+use std::sync::{Arc, Mutex};
+
+// =============================================================================
+// TENANT TESTS
+// =============================================================================
+
+fn tenant(id: &str) -> TenantId {
+    TenantId::parse(id).unwrap()
+}
+
+/// Provisioner over `kv`, restricted to `tenant`'s namespace
+fn tenant_provisioner(kv: &MockKvStore, tenant: Option<&TenantId>) -> Provisioner<Namespaced<MockKvStore>, MockKeyCreator> {
+    let keys = MockKeyCreator {
+        default_key_counter: Arc::new(Mutex::new(0)),
+        chain_key_counter: Arc::new(Mutex::new(1000)),
+    };
+    Provisioner::new(Namespaced::new(kv.clone(), tenant), keys).with_admins(admins()).with_single_step_updates()
+}
+
+#[test]
+fn test_tenants_do_not_see_each_others_mappings() {
+    let kv = MockKvStore::new();
+    let (a, b) = (tenant("a"), tenant("product-b"));
+    let in_a = tenant_provisioner(&kv, Some(&a));
+    let in_b = tenant_provisioner(&kv, Some(&b));
+    let default = tenant_provisioner(&kv, None);
+    let alice = wallet(1);
+    let solana_pubkey = pubkey(&alice);
+
+    let stored = in_a.handle(provision_request(&alice, vec![1, 137])).unwrap();
+    assert!(kv.get(&format!("tenant:a:{}", default_key(&solana_pubkey))).unwrap().unwrap().contains(stored.evm_address.as_str()));
+    assert_eq!(kv.get(&default_key(&solana_pubkey)).unwrap(), None);
+    assert!(in_b.handle_get(&solana_pubkey, &[chain(137)]).unwrap().default_address.is_none());
+    assert!(default.handle_get(&solana_pubkey, &[chain(137)]).unwrap().default_address.is_none());
+
+    // The same user provisions independently in each namespace
+    in_b.handle(provision_request(&alice, vec![1])).unwrap();
+    let unscoped = default.handle(provision_request(&alice, vec![1])).unwrap();
+    assert_eq!(in_a.handle_get(&solana_pubkey, &[]).unwrap().default_address, Some(stored.evm_address));
+    assert_eq!(default.handle_get(&solana_pubkey, &[]).unwrap().default_address, Some(unscoped.evm_address));
+}
+
+#[test]
+fn test_export_stays_within_the_namespace() {
+    let kv = MockKvStore::new();
+    let a = tenant("a");
+    let in_a = tenant_provisioner(&kv, Some(&a));
+    let default = tenant_provisioner(&kv, None);
+    in_a.handle(provision_request(&wallet(1), vec![1, 137])).unwrap();
+    default.handle(provision_request(&wallet(2), vec![1])).unwrap();
+    default.handle(provision_request(&wallet(3), vec![1])).unwrap();
+
+    let export_all = |provisioner: &Provisioner<Namespaced<MockKvStore>, MockKeyCreator>| {
+        let mut entries = Vec::new();
+        let mut cursor = None;
+        loop {
+            let req = ExportRequest { cursor, limit: Some(2), ..Default::default() };
+            let page = provisioner.handle_export(&admin(), req).unwrap();
+            entries.extend(page.entries);
+            cursor = page.next_cursor;
+            if cursor.is_none() {
+                return entries;
+            }
+        }
+    };
+
+    // Tenant keys come back without their prefix
+    let exported = export_all(&in_a);
+    assert!(exported.iter().any(|entry| entry.key == default_key(&pubkey(&wallet(1)))));
+    let stored = kv.list_keys(None, 10_000).unwrap();
+    assert_eq!(exported.len(), stored.iter().filter(|key| key.starts_with("tenant:a:")).count());
+
+    // The default namespace pages past every tenant key
+    let exported = export_all(&default);
+    assert!(exported.iter().all(|entry| !entry.key.starts_with("tenant:")));
+    assert_eq!(exported.len(), stored.iter().filter(|key| !key.starts_with("tenant:")).count());
+}
+
+#[test]
+fn test_default_namespace_rejects_tenant_keys() {
+    let kv = MockKvStore::new();
+    let a = tenant("a");
+    tenant_provisioner(&kv, Some(&a)).handle(provision_request(&wallet(1), vec![1])).unwrap();
+    let default = tenant_provisioner(&kv, None);
+
+    let key = format!("tenant:a:{}", default_key(&pubkey(&wallet(1))));
+    let err = default
+        .handle_import(&admin(), import_request(vec![entry(&key, "0x0000000000000000000000000000000000000001")], ImportStrategy::Overwrite, false))
+        .unwrap_err();
+    assert_eq!(err.code(), "INVALID_REQUEST");
+    assert_eq!(default.kv().get(&key).unwrap_err().code(), "INVALID_REQUEST");
+}
+
+#[test]
+fn test_tenant_members_are_refused_on_other_tenants() {
+    let config = MockKvStore::new();
+    let (a, b) = (tenant("a"), tenant("product-b"));
+    // A tenant nobody was listed for has no members
+    assert_eq!(tenant::require_member(&config, &a, "Role#backend-a").unwrap_err().code(), "NOT_TENANT_MEMBER");
+
+    let members = vec!["Role#backend-a".to_string(), "Role#backend-a".to_string()];
+    assert_eq!(tenant::set_members(&config, &a, members).unwrap(), vec!["Role#backend-a"]);
+    tenant::set_members(&config, &b, vec!["Role#backend-b".to_string()]).unwrap();
+    tenant::require_member(&config, &a, "Role#backend-a").unwrap();
+    tenant::require_member(&config, &b, "Role#backend-b").unwrap();
+
+    // Tenant A's backend cannot act in tenant B by naming it
+    let err = tenant::require_member(&config, &b, "Role#backend-a").unwrap_err();
+    assert_eq!(err.code(), "NOT_TENANT_MEMBER");
+    assert_eq!(err.to_string(), "\"Role#backend-a\" is not a member of tenant product-b");
+    assert_eq!(tenant::require_member(&config, &b, "").unwrap_err().code(), "NOT_TENANT_MEMBER");
+
+    assert_eq!(tenant::set_members(&config, &b, vec![" ".to_string()]).unwrap_err().code(), "INVALID_REQUEST");
+    tenant::set_members(&config, &b, Vec::new()).unwrap();
+    assert_eq!(tenant::require_member(&config, &b, "Role#backend-b").unwrap_err().code(), "NOT_TENANT_MEMBER");
+    assert_eq!(tenant::get_members(&config, &a).unwrap(), vec!["Role#backend-a"]);
+}
+
+#[test]
+fn test_tenant_ids_are_validated() {
+    for id in ["a", "product-b", "0", &"x".repeat(32)] {
+        assert_eq!(TenantId::parse(id).unwrap().as_str(), id);
+    }
+    for id in ["", "Upper", "a:b", "a_b", "é", &"x".repeat(33)] {
+        assert_eq!(TenantId::parse(id).unwrap_err().code(), "INVALID_REQUEST");
+    }
+    assert!(serde_json::from_str::<TenantId>("\"a:b\"").is_err());
+}
+
+// =============================================================================
+// ENVIRONMENT TESTS
+// =============================================================================
+
+/// Provisioner over `kv`, restricted to `env`'s keys
+fn env_provisioner(kv: &MockKvStore, env: Option<Environment>) -> Provisioner<EnvPrefixed<MockKvStore>, MockKeyCreator> {
+    let keys = MockKeyCreator {
+        default_key_counter: Arc::new(Mutex::new(0)),
+        chain_key_counter: Arc::new(Mutex::new(1000)),
+    };
+    Provisioner::new(EnvPrefixed::new(kv.clone(), env), keys).with_admins(admins()).with_single_step_updates()
+}
+
+#[test]
+fn test_environments_do_not_share_keys() {
+    let kv = MockKvStore::new();
+    let prod = env_provisioner(&kv, Some(Environment::Prod));
+    let staging = env_provisioner(&kv, Some(Environment::Staging));
+    let alice = wallet(1);
+    let solana_pubkey = pubkey(&alice);
+
+    prod.handle(provision_request(&alice, vec![1, 137])).unwrap();
+    assert!(kv.get(&format!("prod:{}", default_key(&solana_pubkey))).unwrap().is_some());
+    assert!(kv.list_keys(None, 10_000).unwrap().iter().all(|key| key.starts_with("prod:")));
+    assert!(staging.handle_get(&solana_pubkey, &[chain(137)]).unwrap().default_address.is_none());
+    assert!(staging.handle_export(&admin(), ExportRequest::default()).unwrap().entries.is_empty());
+
+    // Tenants nest inside the environment
+    let a = tenant("a");
+    let in_a = Provisioner::new(Namespaced::new(EnvPrefixed::new(kv.clone(), Some(Environment::Prod)), Some(&a)), MockKeyCreator {
+        default_key_counter: Arc::new(Mutex::new(0)),
+        chain_key_counter: Arc::new(Mutex::new(1000)),
+    });
+    in_a.handle(provision_request(&alice, vec![1])).unwrap();
+    assert!(kv.get(&format!("prod:tenant:a:{}", default_key(&solana_pubkey))).unwrap().is_some());
+}
+
+#[test]
+fn test_legacy_keys_migrate_into_an_environment() {
+    let kv = MockKvStore::new();
+    let legacy = env_provisioner(&kv, None);
+    for seed in 1..=3 {
+        legacy.handle(provision_request(&wallet(seed), vec![1, 137])).unwrap();
+    }
+    env_provisioner(&kv, Some(Environment::Staging)).handle(provision_request(&wallet(4), vec![1])).unwrap();
+    let legacy_keys: Vec<String> = kv.list_keys(None, 10_000).unwrap().into_iter().filter(|key| !is_environment_key(key)).collect();
+
+    let migrate_all = || {
+        let (mut copied, mut unchanged, mut conflicts) = (0, 0, Vec::new());
+        let mut cursor = None;
+        loop {
+            let report = environment::migrate_legacy_batch(&kv, Environment::Prod, cursor.as_deref(), Some(4)).unwrap();
+            copied += report.copied;
+            unchanged += report.unchanged;
+            conflicts.extend(report.conflicts);
+            cursor = report.next_cursor;
+            if cursor.is_none() {
+                return (copied, unchanged, conflicts);
+            }
+        }
+    };
+    assert_eq!(migrate_all(), (legacy_keys.len(), 0, vec![]));
+
+    // Prod now sees the legacy mappings; staging's keys were not copied
+    let prod = env_provisioner(&kv, Some(Environment::Prod));
+    let before = legacy.handle_get(&pubkey(&wallet(2)), &[chain(137)]).unwrap();
+    let after = prod.handle_get(&pubkey(&wallet(2)), &[chain(137)]).unwrap();
+    assert_eq!((after.default_address, after.chain_mappings), (before.default_address, before.chain_mappings));
+    assert!(prod.handle_get(&pubkey(&wallet(4)), &[]).unwrap().default_address.is_none());
+    assert!(kv.get(&default_key(&pubkey(&wallet(1)))).unwrap().is_some());
+
+    // Re-running is safe; copies changed since are reported, not overwritten
+    let changed = format!("prod:{}", default_key(&pubkey(&wallet(3))));
+    kv.set(&changed, "0x0000000000000000000000000000000000000003").unwrap();
+    assert_eq!(migrate_all(), (0, legacy_keys.len() - 1, vec![default_key(&pubkey(&wallet(3)))]));
+    assert_eq!(kv.get(&changed).unwrap().as_deref(), Some("0x0000000000000000000000000000000000000003"));
+}
+
+#[test]
+fn test_environment_names_are_validated() {
+    assert_eq!(Environment::from_build(None), None);
+    assert_eq!(Environment::from_build(Some("staging")), Some(Environment::Staging));
+    assert_eq!(Environment::parse("prod").unwrap(), Environment::Prod);
+    assert_eq!(Environment::parse("production").unwrap_err().code(), "INVALID_REQUEST");
+    assert!(std::panic::catch_unwind(|| Environment::from_build(Some("Prod"))).is_err());
+}
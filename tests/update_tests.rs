@@ -0,0 +1,414 @@
+mod common;
+
+use common::{approval_provisioner, fixed_clock_provisioner, named, rate_limited_provisioner, FlakyKvStore};
+use cubist_wallet_provisioner::error::ProvisionError;
+use cubist_wallet_provisioner::dry_run::PLACEHOLDER_ADDRESS;
+use cubist_wallet_provisioner::kv;
+use cubist_wallet_provisioner::mapping;
+use cubist_wallet_provisioner::rate_limit;
+use cubist_wallet_provisioner::testing::{
+    admin, chain, evm, mock_key, provision_request, provision_request_at, pubkey, update_request, update_self_request, wallet, TestContext,
+};
+use cubist_wallet_provisioner::{
+    EvmAddress, KvStore, MappingRecord, RotateRequest, SolanaPubkey, UpdateBatchRequest, UpdateMappingRequest, MAX_BATCH_SIZE,
+};
+use std::sync::Mutex;
+
+// =============================================================================
+// UPDATE TESTS (Admin Only)
+// =============================================================================
+
+#[test]
+fn test_update_creates_new_wallet_for_specific_chain() {
+    let ctx = TestContext::new();
+    let alice = wallet(1);
+    let solana_pubkey = &pubkey(&alice);
+
+    // First provision all chains with same default address
+    let provision_req = provision_request(&alice, vec![1, 137, 42161]);
+    let provision_result = ctx.handle(provision_req).unwrap();
+    let default_address = provision_result.evm_address.clone();
+    
+    // Admin updates chain 137 to a NEW wallet
+    let update_req = update_request(solana_pubkey, 137);
+    let update_result = ctx.handle_update_mapping(update_req).unwrap();
+    
+    // Update should succeed
+    assert!(update_result.success);
+    assert_eq!(update_result.chain_id, chain(137));
+    
+    // New address should be different from default
+    assert_ne!(update_result.new_evm_address, default_address);
+    
+    // Chain 137 should now have new address
+    let chain_137 = ctx.get_existing_mapping(solana_pubkey, 137).unwrap();
+    assert_eq!(chain_137, Some(update_result.new_evm_address.clone()));
+    
+    // Other chains should still have default address
+    let chain_1 = ctx.get_existing_mapping(solana_pubkey, 1).unwrap();
+    let chain_42161 = ctx.get_existing_mapping(solana_pubkey, 42161).unwrap();
+    assert_eq!(chain_1, Some(default_address.clone()));
+    assert_eq!(chain_42161, Some(default_address.clone()));
+}
+
+#[test]
+fn test_update_fails_if_not_provisioned() {
+    let ctx = TestContext::new();
+    
+    // Try to update without provisioning first
+    let update_req = update_request(&pubkey(&wallet(1)), 137);
+    
+    let err = ctx.handle_update_mapping(update_req).unwrap_err();
+    assert!(matches!(err, ProvisionError::NotProvisioned(_)));
+    assert!(err.to_string().contains("has not been provisioned yet"));
+}
+
+#[test]
+fn test_errors_serialize_with_stable_codes() {
+    let err = ProvisionError::NotProvisioned("7xKXtg2CW87d97TXJSDpbD5jBkheTqA83TZRuJosgAsU".to_string());
+    let json = serde_json::to_value(&err).unwrap();
+    assert_eq!(json["code"], "NOT_PROVISIONED");
+    assert_eq!(json["message"], err.to_string());
+    assert_eq!(json["retryable"], false);
+
+    // Input errors carry their own code, only conflicts and backend failures are retryable
+    assert_eq!(EvmAddress::parse("0x123").unwrap_err().code(), "INVALID_EVM_ADDRESS");
+    assert!(ProvisionError::KvConflict("raced".to_string()).is_retryable());
+    assert!(!ProvisionError::InvalidNonce.is_retryable());
+}
+
+#[test]
+fn test_update_can_be_called_multiple_times() {
+    let ctx = TestContext::new();
+    let alice = wallet(1);
+    let solana_pubkey = &pubkey(&alice);
+
+    // Provision
+    let provision_req = provision_request(&alice, vec![1, 137, 42161]);
+    ctx.handle(provision_req).unwrap();
+    
+    // First update for chain 137
+    let update_req1 = update_request(solana_pubkey, 137);
+    let result1 = ctx.handle_update_mapping(update_req1).unwrap();
+    
+    // Second update for chain 137 (e.g., key rotation)
+    let update_req2 = update_request(solana_pubkey, 137);
+    let result2 = ctx.handle_update_mapping(update_req2).unwrap();
+    
+    // Each update creates a new wallet
+    assert_ne!(result1.new_evm_address, result2.new_evm_address);
+    
+    // Latest address should be stored
+    let current = ctx.get_existing_mapping(solana_pubkey, 137).unwrap();
+    assert_eq!(current, Some(result2.new_evm_address));
+}
+
+// =============================================================================
+// SELF-SERVICE UPDATE TESTS
+// =============================================================================
+
+#[test]
+fn test_update_self_rotates_chain_key() {
+    let provisioner = fixed_clock_provisioner();
+    let alice = wallet(1);
+    let provisioned = provisioner.handle(provision_request_at(&alice, vec![1, 137], 1000)).unwrap();
+
+    let result = provisioner.handle_update_self(update_self_request(&alice, 137, "1", 1300)).unwrap();
+    assert_ne!(result.new_evm_address, provisioned.evm_address);
+
+    let list = provisioner.handle_list(&pubkey(&alice)).unwrap();
+    assert_eq!(list.chain_mappings.get(&chain(137)), Some(&result.new_evm_address));
+    assert_eq!(list.chain_mappings.get(&chain(1)), Some(&provisioned.evm_address));
+
+    // The user is recorded as the actor
+    let history = provisioner.handle_history(&pubkey(&alice), &chain(137)).unwrap();
+    assert_eq!(history.entries[0].replaced_by, pubkey(&alice).as_str());
+}
+
+#[test]
+fn test_update_self_rejects_replayed_nonce() {
+    let provisioner = fixed_clock_provisioner();
+    let alice = wallet(1);
+    provisioner.handle(provision_request_at(&alice, vec![1], 1000)).unwrap();
+
+    let req = update_self_request(&alice, 1, "1", 1300);
+    provisioner.handle_update_self(req.clone()).unwrap();
+
+    let err = provisioner.handle_update_self(req).unwrap_err();
+    assert_eq!(err.code(), "NONCE_TOO_LOW");
+
+    // Nonces are per user
+    let bob = wallet(2);
+    provisioner.handle(provision_request_at(&bob, vec![1], 1000)).unwrap();
+    provisioner.handle_update_self(update_self_request(&bob, 1, "1", 1300)).unwrap();
+}
+
+#[test]
+fn test_update_self_rejects_expired_and_forged_requests() {
+    let provisioner = fixed_clock_provisioner();
+    let alice = wallet(1);
+    let bob = wallet(2);
+    let provisioned = provisioner.handle(provision_request_at(&alice, vec![1], 1000)).unwrap();
+
+    let err = provisioner.handle_update_self(update_self_request(&alice, 1, "1", 999)).unwrap_err();
+    assert!(err.to_string().contains("expired"));
+
+    // Signed for another chain
+    let mut req = update_self_request(&alice, 137, "2", 1300);
+    req.chain_id = chain(1);
+    assert!(provisioner.handle_update_self(req).is_err());
+
+    // Signed by someone else
+    let mut req = update_self_request(&bob, 1, "3", 1300);
+    req.solana_pubkey = pubkey(&alice);
+    assert!(provisioner.handle_update_self(req).is_err());
+
+    // Bad nonce format
+    let err = provisioner.handle_update_self(update_self_request(&alice, 1, "a:b", 1300)).unwrap_err();
+    assert!(err.to_string().contains("Invalid nonce"));
+
+    // None of the failures touched the mapping or burned the nonce
+    let current = kv::get_existing_mapping(provisioner.kv(), &pubkey(&alice), &chain(1)).unwrap();
+    assert_eq!(current, Some(provisioned.evm_address));
+    provisioner.handle_update_self(update_self_request(&alice, 1, "3", 1300)).unwrap();
+}
+
+// =============================================================================
+// KEY ROTATION TESTS
+// =============================================================================
+
+fn rotate_request(solana_pubkey: &SolanaPubkey, chain_id: u64, reason: &str) -> RotateRequest {
+    RotateRequest {
+        solana_pubkey: solana_pubkey.clone(),
+        chain_id: chain(chain_id),
+        reason: reason.to_string(),
+        expected_version: None,
+        idempotency_key: None,
+        request_id: None,
+    }
+}
+
+#[test]
+fn test_rotate_retires_old_address_with_reason() {
+    let provisioner = fixed_clock_provisioner();
+    let alice = wallet(1);
+    let solana_pubkey = pubkey(&alice);
+    let provisioned = provisioner.handle(provision_request_at(&alice, vec![137], 1000)).unwrap();
+
+    let rotated = provisioner.handle_rotate(&admin(), rotate_request(&solana_pubkey, 137, "suspected compromise")).unwrap();
+    let retired = rotated.retired.clone().unwrap();
+    assert_eq!(retired.address, provisioned.evm_address);
+    assert_eq!(retired.replaced_by, rotated.update.new_evm_address);
+    assert_eq!(retired.chain_id, chain(137));
+    assert_eq!(retired.reason.as_deref(), Some("suspected compromise"));
+    assert_eq!((retired.retired_by.as_str(), retired.retired_at), ("admin@test", 1000));
+    assert_eq!(provisioner.handle_get_retirement(&provisioned.evm_address).unwrap(), Some(retired));
+
+    // The response flattens the update
+    let json = serde_json::to_value(&rotated).unwrap();
+    assert_eq!(json["version"], 1);
+    assert_eq!(json["retired"]["reason"], "suspected compromise");
+}
+
+#[test]
+fn test_plain_update_also_links_retired_address() {
+    let provisioner = fixed_clock_provisioner();
+    let alice = wallet(1);
+    let solana_pubkey = pubkey(&alice);
+    provisioner.handle(provision_request_at(&alice, vec![137], 1000)).unwrap();
+    let first = provisioner.handle_update_mapping(&admin(), update_request(&solana_pubkey, 137)).unwrap();
+    let second = provisioner.handle_update_mapping(&admin(), update_request(&solana_pubkey, 137)).unwrap();
+
+    let retired = provisioner.handle_get_retirement(&first.new_evm_address).unwrap().unwrap();
+    assert_eq!(retired.replaced_by, second.new_evm_address);
+    assert_eq!(retired.reason, None);
+    assert_eq!(provisioner.handle_get_retirement(&second.new_evm_address).unwrap(), None);
+}
+
+#[test]
+fn test_rotate_requires_reason_and_single_step_updates() {
+    let provisioner = fixed_clock_provisioner();
+    let alice = wallet(1);
+    let solana_pubkey = pubkey(&alice);
+    provisioner.handle(provision_request_at(&alice, vec![137], 1000)).unwrap();
+    let err = provisioner.handle_rotate(&admin(), rotate_request(&solana_pubkey, 137, "  ")).unwrap_err();
+    assert_eq!(err.code(), "INVALID_REQUEST");
+
+    let (provisioner, _) = approval_provisioner();
+    provisioner.handle(provision_request_at(&alice, vec![137], 1000)).unwrap();
+    let err = provisioner.handle_rotate(&named("alice@test"), rotate_request(&solana_pubkey, 137, "lost device")).unwrap_err();
+    assert_eq!(err.code(), "APPROVAL_REQUIRED");
+}
+
+// =============================================================================
+// BATCH UPDATE TESTS
+// =============================================================================
+
+fn update_batch_request(updates: Vec<UpdateMappingRequest>, all_or_nothing: bool) -> UpdateBatchRequest {
+    UpdateBatchRequest { updates, all_or_nothing, request_id: None }
+}
+
+#[test]
+fn test_update_batch_reports_each_entry() {
+    let ctx = TestContext::new();
+    let alice = pubkey(&wallet(1));
+    let default_address = ctx.handle(provision_request(&wallet(1), vec![1, 137])).unwrap().evm_address;
+
+    let updates = vec![update_request(&alice, 137), update_request(&pubkey(&wallet(2)), 137), update_request(&alice, 1)];
+    let batch = ctx.provisioner.handle_update_batch(&admin(), update_batch_request(updates, false)).unwrap();
+    assert!(batch.applied);
+    assert_eq!((batch.succeeded, batch.failed), (2, 1));
+    assert_eq!(batch.results[1].error.as_ref().unwrap().code(), "NOT_PROVISIONED");
+
+    // Entries before and after the failing one are applied
+    let updated = batch.results[0].result.as_ref().unwrap();
+    assert_ne!(updated.new_evm_address, default_address);
+    assert_eq!(ctx.get_existing_mapping(&alice, 137).unwrap(), Some(updated.new_evm_address.clone()));
+    assert_eq!(ctx.get_existing_mapping(&alice, 1).unwrap(), Some(batch.results[2].result.as_ref().unwrap().new_evm_address.clone()));
+}
+
+#[test]
+fn test_all_or_nothing_update_batch_writes_nothing_on_failure() {
+    let ctx = TestContext::new();
+    let alice = pubkey(&wallet(1));
+    let default_address = ctx.handle(provision_request(&wallet(1), vec![1, 137])).unwrap().evm_address;
+
+    let updates = vec![update_request(&alice, 137), update_request(&pubkey(&wallet(2)), 137)];
+    let batch = ctx.provisioner.handle_update_batch(&admin(), update_batch_request(updates, true)).unwrap();
+    assert!(!batch.applied);
+    assert_eq!((batch.succeeded, batch.failed), (1, 1));
+    assert_eq!(batch.results[0].result.as_ref().unwrap().new_evm_address.as_str(), PLACEHOLDER_ADDRESS);
+    assert_eq!(ctx.get_existing_mapping(&alice, 137).unwrap(), Some(default_address.clone()));
+
+    let batch = ctx.provisioner.handle_update_batch(&admin(), update_batch_request(vec![update_request(&alice, 137)], true)).unwrap();
+    assert!(batch.applied && batch.failed == 0);
+    let updated = batch.results[0].result.as_ref().unwrap();
+    assert_ne!(updated.new_evm_address.as_str(), PLACEHOLDER_ADDRESS);
+    assert_eq!(ctx.get_existing_mapping(&alice, 137).unwrap(), Some(updated.new_evm_address.clone()));
+}
+
+#[test]
+fn test_all_or_nothing_update_batch_checks_rate_limit_and_idempotency() {
+    let (provisioner, bucket, _) = rate_limited_provisioner();
+    let alice = pubkey(&wallet(1));
+    provisioner.handle(provision_request_at(&wallet(1), vec![1, 137, 42161], 6000)).unwrap();
+
+    // With the provision, the third update goes past the limit of three a minute
+    let updates = vec![update_request(&alice, 1), update_request(&alice, 137), update_request(&alice, 42161)];
+    let batch = provisioner.handle_update_batch(&admin(), update_batch_request(updates, true)).unwrap();
+    assert!(!batch.applied);
+    assert_eq!((batch.succeeded, batch.failed), (2, 1));
+    assert_eq!(batch.results[2].error.as_ref().unwrap().code(), "RATE_LIMITED");
+    // The preview counted nothing and wrote nothing
+    assert_eq!(rate_limit::get_counter(&bucket, &alice).unwrap().unwrap().count, 1);
+    assert!(provisioner.handle_history(&alice, &chain(1)).unwrap().entries.is_empty());
+
+    // An idempotency key already used for another request fails the preview too
+    let used = UpdateMappingRequest { idempotency_key: Some("rotate-1".to_string()), ..update_request(&alice, 1) };
+    provisioner.handle_update_mapping(&admin(), used).unwrap();
+    let reused = UpdateMappingRequest { idempotency_key: Some("rotate-1".to_string()), ..update_request(&alice, 137) };
+    let batch = provisioner.handle_update_batch(&admin(), update_batch_request(vec![reused], true)).unwrap();
+    assert!(!batch.applied);
+    assert_eq!(batch.results[0].error.as_ref().unwrap().code(), "IDEMPOTENCY_KEY_REUSED");
+    assert!(provisioner.handle_history(&alice, &chain(137)).unwrap().entries.is_empty());
+}
+
+#[test]
+fn test_update_batch_respects_approval_and_size() {
+    let (provisioner, _) = approval_provisioner();
+    let alice = wallet(1);
+    provisioner.handle(provision_request_at(&alice, vec![137], 1000)).unwrap();
+
+    // With an admin list configured, updates go through propose/approve one by one
+    let batch = provisioner.handle_update_batch(&named("alice@test"), update_batch_request(vec![update_request(&pubkey(&alice), 137)], false)).unwrap();
+    assert_eq!(batch.results[0].error, Some(ProvisionError::ApprovalRequired));
+
+    let err = provisioner.handle_update_batch(&named("alice@test"), update_batch_request(Vec::new(), false)).unwrap_err();
+    assert_eq!(err.code(), "INVALID_REQUEST");
+    let updates = vec![update_request(&pubkey(&alice), 137); MAX_BATCH_SIZE + 1];
+    let err = provisioner.handle_update_batch(&named("alice@test"), update_batch_request(updates, true)).unwrap_err();
+    assert!(matches!(err, ProvisionError::BatchTooLarge { .. }));
+}
+
+// =============================================================================
+// VERSIONED UPDATE TESTS
+// =============================================================================
+
+#[test]
+fn test_update_with_stale_expected_version_is_rejected() {
+    let ctx = TestContext::new();
+    let alice = wallet(1);
+    let solana_pubkey = pubkey(&alice);
+    ctx.handle(provision_request(&alice, vec![1, 137])).unwrap();
+    assert_eq!(ctx.provisioner.handle_get(&solana_pubkey, &[chain(137)]).unwrap().chain_versions[&chain(137)], 0);
+
+    let mut req = update_request(&solana_pubkey, 137);
+    req.expected_version = Some(0);
+    let first = ctx.handle_update_mapping(req.clone()).unwrap();
+    assert_eq!(first.version, 1);
+
+    // Second admin still working from version 0
+    let err = ctx.handle_update_mapping(req).unwrap_err();
+    match &err {
+        ProvisionError::VersionConflict { expected, current, .. } => {
+            assert_eq!(*expected, 0);
+            let current = current.as_ref().unwrap();
+            assert_eq!((current.revision, &current.address), (1, &first.new_evm_address));
+        }
+        other => panic!("expected a version conflict, got {:?}", other),
+    }
+    assert_eq!(serde_json::to_value(&err).unwrap()["current"]["revision"], 1);
+
+    // No key was spent on the rejected update, and the mapping is untouched
+    assert_eq!(ctx.get_existing_mapping(&solana_pubkey, 137).unwrap(), Some(first.new_evm_address.clone()));
+    let mut req = update_request(&solana_pubkey, 137);
+    req.expected_version = Some(1);
+    let second = ctx.handle_update_mapping(req).unwrap();
+    assert_eq!(second.new_evm_address, evm(&mock_key(1002).address));
+    assert_eq!(second.version, 2);
+}
+
+#[test]
+fn test_concurrent_update_from_same_version_conflicts() {
+    let ctx = TestContext::new();
+    let alice = wallet(1);
+    let solana_pubkey = pubkey(&alice);
+    ctx.handle(provision_request(&alice, vec![137])).unwrap();
+
+    // Another writer already claimed revision 1 but has not written it yet
+    ctx.kv.set_if_absent(&kv::revision_key(&solana_pubkey, &chain(137), 1), "bob@test").unwrap();
+
+    let record = MappingRecord::new(&evm("0x3333333333333333333333333333333333333333"), None, "alice@test", 10);
+    let err = mapping::apply_update(&ctx.kv, &solana_pubkey, &chain(137), &record, None, None, "alice@test", 10).unwrap_err();
+    assert_eq!(err.code(), "VERSION_CONFLICT");
+    assert_eq!(ctx.get_existing_mapping(&solana_pubkey, 137).unwrap(), Some(evm(&mock_key(1).address)));
+    assert!(kv::get_history(&ctx.kv, &solana_pubkey, &chain(137)).unwrap().is_empty());
+}
+
+#[test]
+fn test_update_failing_after_its_claim_is_taken_over() {
+    let ctx = TestContext::new();
+    let alice = wallet(1);
+    let solana_pubkey = pubkey(&alice);
+    ctx.handle(provision_request(&alice, vec![137])).unwrap();
+    let record = MappingRecord::new(&evm("0x3333333333333333333333333333333333333333"), None, "alice@test", 10);
+
+    // The claim on revision 1 is written, then the KV fails
+    let flaky = FlakyKvStore { inner: ctx.kv.clone(), writes_left: Mutex::new(1) };
+    let err = mapping::apply_update(&flaky, &solana_pubkey, &chain(137), &record, None, None, "alice@test", 10).unwrap_err();
+    assert_eq!(err.code(), "KV_ERROR");
+    assert!(ctx.kv.get(&kv::revision_key(&solana_pubkey, &chain(137), 1)).unwrap().is_some());
+
+    // Held while the failed update could still be running
+    let err = mapping::apply_update(&ctx.kv, &solana_pubkey, &chain(137), &record, Some(0), None, "alice@test", 20).unwrap_err();
+    assert_eq!(err.code(), "VERSION_CONFLICT");
+
+    // Then the retry takes the claim over, and later updates claim as usual
+    let now = 10 + kv::REVISION_CLAIM_TTL_SECS;
+    let stored = mapping::apply_update(&ctx.kv, &solana_pubkey, &chain(137), &record, Some(0), None, "alice@test", now).unwrap();
+    assert_eq!(stored.revision, 1);
+    assert_eq!(ctx.get_existing_mapping(&solana_pubkey, 137).unwrap(), Some(record.address.clone()));
+    assert!(ctx.kv.get(&kv::revision_claim_key(&solana_pubkey, &chain(137), 1, 2)).unwrap().is_some());
+    let next = MappingRecord::new(&evm("0x4444444444444444444444444444444444444444"), None, "alice@test", now);
+    assert_eq!(mapping::apply_update(&ctx.kv, &solana_pubkey, &chain(137), &next, Some(1), None, "alice@test", now).unwrap().revision, 2);
+}
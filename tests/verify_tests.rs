@@ -0,0 +1,241 @@
+mod common;
+
+use common::{approval_provisioner, evm_wallet, fixed_clock_provisioner, labeled_request_at, link_external_request, listed_key, named, AAVE_POOL};
+use cubist_wallet_provisioner::kv::{self, chain_key, default_key, reverse_key};
+use cubist_wallet_provisioner::reconcile::{self, ReconcileRequest};
+use cubist_wallet_provisioner::repair::{RepairRequest, RepairStatus};
+use cubist_wallet_provisioner::testing::{
+    admin, chain, evm, mock_key, provision_request, provision_request_at, pubkey, update_request, wallet, MockKeyCreator, MockKvStore, TestContext,
+};
+use cubist_wallet_provisioner::verify::{VerifyRequest, Violation, ViolationKind};
+use cubist_wallet_provisioner::{KvStore, MappingRecord, Provisioner};
+
+// =============================================================================
+// RECONCILIATION TESTS
+// =============================================================================
+
+#[test]
+fn test_reconcile_reports_and_repairs_both_directions() {
+    let ctx = TestContext::new();
+    let alice = pubkey(&wallet(1));
+    let bob = pubkey(&wallet(2));
+    let carol = pubkey(&wallet(3));
+    ctx.handle(provision_request(&wallet(1), vec![1])).unwrap(); // key 1
+    ctx.handle(provision_request(&wallet(3), vec![1])).unwrap(); // key 2, deleted in CubeSigner
+    ctx.handle_update_mapping(update_request(&alice, 137)).unwrap(); // key 1001, rotated away below
+    ctx.handle_update_mapping(update_request(&alice, 137)).unwrap(); // key 1002
+
+    let keys = vec![
+        listed_key(1, format!("EVM_{}", alice)),
+        listed_key(1001, format!("EVM_{}_chain137", alice)),
+        listed_key(1002, format!("EVM_{}_chain137", alice)),
+        listed_key(50, format!("EVM_{}", bob)),          // store never happened
+        listed_key(51, format!("EVM_{}_chain10", alice)), // rotation never happened
+        listed_key(52, "unrelated".to_string()),
+    ];
+    let req = ReconcileRequest { limit: Some(500), ..Default::default() };
+    let report = reconcile::reconcile_batch(&ctx.kv, &keys, &req, "admin@test", 1000).unwrap();
+    assert_eq!(report.keys_checked, 5);
+    let orphans: Vec<&str> = report.orphan_keys.iter().map(|orphan| orphan.key_id.as_str()).collect();
+    assert_eq!(orphans, vec![mock_key(50).key_id.as_str(), mock_key(51).key_id.as_str()]);
+    let dangling: Vec<&str> = report.dangling_mappings.iter().map(|mapping| mapping.key.as_str()).collect();
+    assert_eq!(dangling, vec![chain_key(&carol, &chain(1)), default_key(&carol)]);
+    assert!(report.orphan_keys.iter().all(|orphan| !orphan.adopted));
+    assert!(report.dangling_mappings.iter().all(|mapping| !mapping.frozen));
+    assert_eq!(report.next_cursor, None);
+
+    // Reporting alone changes nothing
+    assert!(kv::get_default_mapping(&ctx.kv, &bob).unwrap().is_none());
+
+    let req = ReconcileRequest { repair: true, ..req };
+    let report = reconcile::reconcile_batch(&ctx.kv, &keys, &req, "admin@test", 1000).unwrap();
+    assert_eq!(report.orphan_keys.iter().map(|orphan| orphan.adopted).collect::<Vec<_>>(), vec![true, false]);
+    assert!(report.dangling_mappings.iter().all(|mapping| mapping.frozen));
+
+    let adopted = kv::get_default_mapping(&ctx.kv, &bob).unwrap().unwrap();
+    assert_eq!(adopted.address, evm(&mock_key(50).address));
+    assert_eq!(kv::get_reverse_mapping(&ctx.kv, &adopted.address).unwrap(), Some(bob.clone()));
+    let carol_address = kv::get_default_evm_address(&ctx.kv, &carol).unwrap().unwrap();
+    assert_eq!(ctx.provisioner.handle_get_freeze(&carol_address).unwrap().unwrap().reason.as_deref(), Some(reconcile::MISSING_KEY_REASON));
+
+    // Once repaired, only the unused chain key is left to report
+    let report = reconcile::reconcile_batch(&ctx.kv, &keys, &req, "admin@test", 1000).unwrap();
+    assert_eq!(report.orphan_keys.len(), 1);
+}
+
+#[test]
+fn test_reconcile_lists_keys_and_requires_admin() {
+    let (provisioner, _) = approval_provisioner();
+    provisioner.handle(provision_request_at(&wallet(1), vec![1], 1000)).unwrap();
+    let req = ReconcileRequest::default;
+
+    let report = provisioner.handle_reconcile(&named("alice@test"), req()).unwrap();
+    assert!(report.orphan_keys.is_empty() && report.dangling_mappings.is_empty());
+    assert!(report.scanned > 0);
+
+    assert_eq!(provisioner.handle_reconcile(&named("mallory@test"), req()).unwrap_err().code(), "NOT_ADMIN");
+}
+
+// =============================================================================
+// VERIFY TESTS
+// =============================================================================
+
+/// Violations of every page, scanning `limit` keys at a time
+fn verify_all(provisioner: &Provisioner<MockKvStore, MockKeyCreator>, limit: usize) -> Vec<Violation> {
+    let mut violations = Vec::new();
+    let mut cursor = None;
+    loop {
+        let req = VerifyRequest { cursor, limit: Some(limit), request_id: None };
+        let report = provisioner.handle_verify(&admin(), req).unwrap();
+        assert!(report.scanned <= limit);
+        violations.extend(report.violations);
+        cursor = report.next_cursor;
+        if cursor.is_none() {
+            return violations;
+        }
+    }
+}
+
+#[test]
+fn test_verify_finds_nothing_in_a_consistent_bucket() {
+    let provisioner = fixed_clock_provisioner();
+    for seed in 1..=3 {
+        provisioner.handle(provision_request_at(&wallet(seed), vec![1, 137], 1000)).unwrap();
+    }
+    provisioner.handle(labeled_request_at(&wallet(1), vec![1], "cold", 1000)).unwrap();
+    provisioner.handle_update_mapping(&admin(), update_request(&pubkey(&wallet(2)), 137)).unwrap();
+    provisioner.handle_link_external(link_external_request(&wallet(3), &evm_wallet(9), vec![1], "1")).unwrap();
+
+    assert_eq!(verify_all(&provisioner, 4), Vec::new());
+}
+
+#[test]
+fn test_verify_reports_drift() {
+    let ctx = TestContext::new();
+    let alice = pubkey(&wallet(1));
+    let bob = pubkey(&wallet(2));
+    let carol = pubkey(&wallet(3));
+    let address = ctx.handle(provision_request(&wallet(1), vec![1])).unwrap().evm_address;
+    let stray = evm("0x3333333333333333333333333333333333333333");
+
+    // A chain mapping without a default, and not in the reverse index
+    ctx.kv.set(&chain_key(&bob, &chain(137)), &MappingRecord::new(&stray, None, "test", 0).encode()).unwrap();
+    // A default without chains
+    ctx.kv.set(&default_key(&carol), &MappingRecord::new(&evm(AAVE_POOL), None, "test", 0).encode()).unwrap();
+    ctx.kv.set(&reverse_key(&evm(AAVE_POOL)), carol.as_str()).unwrap();
+    // Alice's address handed to Bob, an unreadable record and a bad reverse key
+    ctx.kv.set(&reverse_key(&address), bob.as_str()).unwrap();
+    ctx.kv.set(&chain_key(&alice, &chain(137)), "{not json").unwrap();
+    ctx.kv.set("reverse:0x1234", alice.as_str()).unwrap();
+
+    let found: Vec<(ViolationKind, String)> = verify_all(&ctx.provisioner, 3).into_iter().map(|v| (v.kind, v.key)).collect();
+    let expected = [
+        (ViolationKind::MissingDefault, chain_key(&bob, &chain(137))),
+        (ViolationKind::ReverseMismatch, chain_key(&bob, &chain(137))),
+        (ViolationKind::NoChains, default_key(&carol)),
+        (ViolationKind::ReverseMismatch, default_key(&alice)),
+        (ViolationKind::ReverseMismatch, chain_key(&alice, &chain(1))),
+        (ViolationKind::ReverseMismatch, reverse_key(&address)),
+        (ViolationKind::MalformedRecord, chain_key(&alice, &chain(137))),
+        (ViolationKind::MalformedAddress, "reverse:0x1234".to_string()),
+    ];
+    assert_eq!(found.len(), expected.len(), "{:?}", found);
+    for violation in &expected {
+        assert!(found.contains(violation), "{:?} missing from {:?}", violation, found);
+    }
+}
+
+#[test]
+fn test_verify_requires_admin() {
+    let (provisioner, _) = approval_provisioner();
+    let err = provisioner.handle_verify(&named("mallory@test"), VerifyRequest::default()).unwrap_err();
+    assert_eq!(err.code(), "NOT_ADMIN");
+}
+
+// =============================================================================
+// REPAIR TESTS
+// =============================================================================
+
+fn repair_request(ids: &[&Violation], dry_run: bool) -> RepairRequest {
+    RepairRequest {
+        ids: ids.iter().map(|violation| violation.id.clone()).collect(),
+        dry_run,
+        request_id: None,
+    }
+}
+
+#[test]
+fn test_repair_fixes_violations_by_id() {
+    let ctx = TestContext::new();
+    let alice = pubkey(&wallet(1));
+    let bob = pubkey(&wallet(2));
+    let carol = pubkey(&wallet(3));
+    let address = ctx.handle(provision_request(&wallet(1), vec![1])).unwrap().evm_address;
+    let stray = evm("0x3333333333333333333333333333333333333333");
+
+    // Bob: a chain mapping with no default or reverse entry
+    ctx.kv.set(&chain_key(&bob, &chain(137)), &MappingRecord::new(&stray, None, "test", 0).encode()).unwrap();
+    // Alice's address handed to Carol, who does not map it
+    ctx.kv.set(&reverse_key(&address), carol.as_str()).unwrap();
+    // A hand-edited record whose address fails its checksum
+    let miscased = r#"{"address":"0xAAAAaaaaAAAAaaaaAAAAaaaaAAAAaaaaAAAAaaaa","version":2}"#;
+    ctx.kv.set(&chain_key(&alice, &chain(10)), miscased).unwrap();
+
+    let violations = verify_all(&ctx.provisioner, 100);
+    assert_eq!(violations.len(), 6, "{:?}", violations);
+    let all: Vec<&Violation> = violations.iter().collect();
+
+    // A dry run reports the fixes and writes nothing
+    let preview = ctx.provisioner.handle_repair(&admin(), repair_request(&all, true)).unwrap();
+    assert!(preview.dry_run);
+    assert_eq!(verify_all(&ctx.provisioner, 100), violations);
+
+    let report = ctx.provisioner.handle_repair(&admin(), repair_request(&all, false)).unwrap();
+    assert_eq!(report.results.iter().map(|r| r.status).collect::<Vec<_>>(), preview.results.iter().map(|r| r.status).collect::<Vec<_>>());
+    // Bob's reverse entry and default, Alice's reverse entry and record; fixing
+    // the reverse entry on Alice's first key resolves the other two reports of it
+    assert_eq!(report.repaired, 4, "{:?}", report.results);
+    assert_eq!(report.results.iter().filter(|r| r.status == RepairStatus::Resolved).count(), 2);
+
+    // The normalized record can be checked now: its reverse entry is missing
+    let revealed = verify_all(&ctx.provisioner, 100);
+    assert_eq!(revealed.iter().map(|v| (v.kind, v.key.as_str())).collect::<Vec<_>>(), vec![(ViolationKind::ReverseMismatch, chain_key(&alice, &chain(10)).as_str())]);
+    ctx.provisioner.handle_repair(&admin(), repair_request(&[&revealed[0]], false)).unwrap();
+    assert_eq!(verify_all(&ctx.provisioner, 100), Vec::new());
+
+    assert_eq!(kv::get_default_evm_address(&ctx.kv, &bob).unwrap(), Some(stray.clone()));
+    assert_eq!(kv::get_reverse_mapping(&ctx.kv, &stray).unwrap(), Some(bob.clone()));
+    assert_eq!(kv::get_reverse_mapping(&ctx.kv, &address).unwrap(), Some(alice.clone()));
+    assert_eq!(ctx.get_existing_mapping(&alice, 10).unwrap(), Some(evm("0xaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa")));
+
+    // Fixed violations are resolved on a second pass
+    let again = ctx.provisioner.handle_repair(&admin(), repair_request(&all, false)).unwrap();
+    assert_eq!(again.repaired, 0);
+}
+
+#[test]
+fn test_repair_leaves_what_it_cannot_fix() {
+    let ctx = TestContext::new();
+    let alice = pubkey(&wallet(1));
+    let bob = pubkey(&wallet(2));
+    let address = ctx.handle(provision_request(&wallet(1), vec![1])).unwrap().evm_address;
+    // Bob's mappings use Alice's address (his missing chain index can be rebuilt)
+    ctx.kv.set(&chain_key(&bob, &chain(1)), &MappingRecord::new(&address, None, "test", 0).encode()).unwrap();
+    ctx.kv.set(&default_key(&bob), &MappingRecord::new(&address, None, "test", 0).encode()).unwrap();
+    ctx.kv.set("reverse:0x1234", alice.as_str()).unwrap();
+
+    let violations = verify_all(&ctx.provisioner, 100);
+    let all: Vec<&Violation> = violations.iter().collect();
+    let mut req = repair_request(&all, false);
+    req.ids.push("not_a_kind:default:x".to_string());
+    let report = ctx.provisioner.handle_repair(&admin(), req).unwrap();
+    assert_eq!(report.repaired, 1, "{:?}", report.results);
+    assert_eq!(report.results.iter().filter(|r| r.status == RepairStatus::Unrepairable).count(), 4);
+    assert_eq!(kv::get_reverse_mapping(&ctx.kv, &address).unwrap(), Some(alice));
+
+    let err = ctx.provisioner.handle_repair(&admin(), RepairRequest { ids: Vec::new(), ..repair_request(&[], false) }).unwrap_err();
+    assert_eq!(err.code(), "INVALID_REQUEST");
+    let (provisioner, _) = approval_provisioner();
+    let err = provisioner.handle_repair(&named("mallory@test"), RepairRequest { ..repair_request(&all, false) }).unwrap_err();
+    assert_eq!(err.code(), "NOT_ADMIN");
+}